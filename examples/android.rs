@@ -0,0 +1,8 @@
+// Native entry point of the Android build, everything else lives in `reverie::android`. Elsewhere this builds to an empty
+// library.
+
+#[cfg_attr(target_os = "android", ndk_glue::main(backtrace = "on"))]
+pub fn main() {
+    #[cfg(target_os = "android")]
+    reverie::android::run();
+}
@@ -0,0 +1,7 @@
+// Renders the meshes under assets/ on an OpenXR headset: cargo run --example xr --features xr
+use reverie::vulkan::renderer::RendererSettings;
+
+fn main() -> anyhow::Result<()> {
+    reverie::logging::init();
+    reverie::xr::run(&RendererSettings::default())
+}
@@ -1,13 +1,96 @@
 #version 450
 
+layout (location = 0) in vec3 in_normal;
+layout (location = 1) in vec3 in_world_position;
+layout (location = 2) in vec3 in_world_normal;
+layout (location = 3) in float in_view_depth;
+layout (location = 4) in vec4 in_clip_position;
+layout (location = 5) in vec4 in_previous_clip_position;
+layout (location = 7) flat in uint in_object_index;
+layout (location = 8) flat in uint in_material_index;
+layout (location = 9) in vec2 in_tex_coord;
+layout (location = 10) in vec4 in_world_tangent;
+
 layout (location = 0) out vec4 color;
+layout (location = 1) out vec4 normal_roughness;
+layout (location = 2) out vec2 motion;
+layout (location = 3) out uint object_id;
+
+#include "include/camera.glsl"
+#include "include/lighting.glsl"
+#include "include/cluster_lighting.glsl"
+#include "include/light_probes.glsl"
+#include "include/material.glsl"
+#include "include/reflection_probes.glsl"
+#include "include/motion.glsl"
+#include "include/parallax.glsl"
+#ifdef VIRTUAL
+#include "include/virtual_texture.glsl"
+#endif
 
-layout(push_constant) uniform Push {
-    mat2 transform;
-    vec2 offset;
-    vec3 color;
-} push;
+// Bends the interpolated normal by the material's normal map, after shifting `tex_coord` by the height in its alpha for
+// materials with a parallax depth. Materials without a normal map skip the texture and tangent frame entirely.
+vec3 surface_normal(MaterialData material, vec3 view_direction, inout vec2 tex_coord) {
+    vec3 normal = normalize(in_world_normal);
+    if (material.params.y == 0.0) {
+        return normal;
+    }
+
+    vec3 tangent = normalize(in_world_tangent.xyz - normal * dot(normal, in_world_tangent.xyz));
+    vec3 bitangent = cross(normal, tangent) * in_world_tangent.w;
+    mat3 tangent_frame = mat3(tangent, bitangent, normal);
+    tex_coord = parallax_tex_coord(normal_map, tex_coord, transpose(tangent_frame) * view_direction, material.params.z);
+    // z is rebuilt, BC5 normal maps only have x and y
+    vec2 mapped_xy = texture(normal_map, tex_coord).xy * 2.0 - 1.0;
+    vec3 mapped = vec3(mapped_xy, sqrt(max(1.0 - dot(mapped_xy, mapped_xy), 0.0)));
+    return normalize(tangent_frame * mapped);
+}
 
 void main() {
-    color = vec4(push.color, 1.0);
+    MaterialData material = materials[in_material_index];
+    vec3 view_direction = normalize(camera.position.xyz - in_world_position);
+    vec2 tex_coord = in_tex_coord;
+    vec3 normal = surface_normal(material, view_direction, tex_coord);
+
+    // Color textures hold sRGB color in a UNORM image. Lightmapped objects drawn with this shader (reflections,
+    // captures) have their lightmap bound instead, which isn't a color.
+    vec4 base_color = material.color;
+#ifdef VIRTUAL
+    // Virtual textures hold sRGB color too, paged in for what the feedback pass saw
+    vec4 texel = sample_virtual(tex_coord);
+    base_color *= vec4(pow(texel.rgb, vec3(2.2)), texel.a);
+#else
+    if (material.texture_params.x != 0.0) {
+        vec4 texel = texture(material_texture, tex_coord);
+        base_color *= vec4(pow(texel.rgb, vec3(2.2)), texel.a);
+    }
+#endif
+#ifdef CUTOUT
+    if (base_color.a < material.params.w) {
+        discard;
+    }
+#endif
+
+    vec3 lighting = probe_ambient(in_world_position, normal, ambient.rgb);
+
+    lighting += direct_lighting(in_world_position, normal, in_view_depth);
+
+    // Schlick fresnel of a dielectric, probes only add to what's inside their volumes
+    float roughness = material.params.x;
+    vec4 reflection = reflection_probe_radiance(in_world_position, reflect(-view_direction, normal), roughness);
+    float fresnel = 0.04 + 0.96 * pow(1.0 - max(dot(normal, view_direction), 0.0), 5.0);
+
+    color = vec4(base_color.rgb * lighting + reflection.rgb * reflection.a * fresnel * (1.0 - roughness), 1.0);
+#if defined(TRANSPARENT)
+    // Blended into the scene color only, the transparent pipelines mask the other targets
+    color.a = base_color.a;
+#elif defined(ALPHA_TO_COVERAGE)
+    // Sharpened to about a pixel wide ramp around the cutoff, the covered samples do the smoothing
+    color.a = clamp((base_color.a - material.params.w) / max(fwidth(base_color.a), 0.0001) + 0.5, 0.0, 1.0);
+#endif
+    // The view matrix only rotates and translates, so it carries the mapped normal into view space as it is
+    vec3 view_normal = material.params.y == 0.0 ? normalize(in_normal) : normalize(mat3(camera.view) * normal);
+    normal_roughness = vec4(view_normal, roughness);
+    motion = motion_vector(in_clip_position, in_previous_clip_position, camera.jitter.xy);
+    object_id = in_object_index + 1;
 }
\ No newline at end of file
@@ -1,21 +1,143 @@
 #version 450
 
-layout(location = 0) in vec2 in_position;
+#ifdef VERTEX_PULLING
+// Tightly packed `Vertex`s of the merged scene geometry, fetched by gl_VertexIndex instead of vertex input
+layout(std430, set = 2, binding = 1) readonly buffer Vertices {
+    float vertices[];
+};
+const uint VERTEX_STRIDE = 17;
+#else
+layout(location = 0) in vec3 in_position;
 layout(location = 1) in vec3 in_color;
+layout(location = 2) in vec3 in_normal;
+layout(location = 3) in vec2 in_lightmap_uv;
+layout(location = 4) in vec2 in_tex_coord;
+layout(location = 5) in vec4 in_tangent;
+#endif
 
+layout(location = 0) out vec3 out_normal;
+layout(location = 1) out vec3 out_world_position;
+layout(location = 2) out vec3 out_world_normal;
+layout(location = 3) out float out_view_depth;
+layout(location = 4) out vec4 out_clip_position;
+layout(location = 5) out vec4 out_previous_clip_position;
+layout(location = 6) out vec2 out_lightmap_uv;
+layout(location = 7) flat out uint out_object_index;
+layout(location = 8) flat out uint out_material_index;
+layout(location = 9) out vec2 out_tex_coord;
+layout(location = 10) out vec4 out_world_tangent;
 
+#include "include/camera.glsl"
+#include "include/material.glsl"
+#include "include/wind.glsl"
+#ifdef WATER
+#include "include/water.glsl"
+#endif
+
+// Must match ObjectData in object_buffer.rs
+struct ObjectData {
+    mat4 model;
+    mat4 previous_model;
+    uint material;
+};
+
+layout(std430, set = 2, binding = 0) readonly buffer Objects {
+    ObjectData objects[];
+};
+
+#ifdef SCATTER
+// Must match ScatterInstance in scatter.rs
+struct ScatterInstance {
+    mat4 model;
+    vec4 sphere;
+};
+
+// SCATTER_SET in scatter.rs, every instance of the layer and the ones scatter_cull.comp kept for this view
+layout(std430, set = 4, binding = 0) readonly buffer ScatterInstances {
+    ScatterInstance instances[];
+};
+
+layout(std430, set = 4, binding = 1) readonly buffer VisibleInstances {
+    uint visible[];
+};
+
+// Must match ScatterPushConstants in scatter.rs
 layout(push_constant) uniform Push {
-    mat2 transform;
-    vec2 offset;
-    vec3 color;
-} push;
+    uint material;
+    float fade_start;
+    float fade_end;
+} scatter;
+#endif
 
-// out gl_PerVertex {
-//     vec2 gl_Position;
-// };
+out gl_PerVertex {
+    vec4 gl_Position;
+    float gl_ClipDistance[1];
+};
 
 void main() {
-    gl_Position = vec4(push.transform * in_position + push.offset.xy, 0.0, 1.0);
+#ifdef VERTEX_PULLING
+    // Indexed draws add their vertex offset to gl_VertexIndex, so it indexes the merged buffer directly
+    uint base = gl_VertexIndex * VERTEX_STRIDE;
+    vec3 in_position = vec3(vertices[base], vertices[base + 1], vertices[base + 2]);
+    vec3 in_color = vec3(vertices[base + 3], vertices[base + 4], vertices[base + 5]);
+    vec3 in_normal = vec3(vertices[base + 6], vertices[base + 7], vertices[base + 8]);
+    vec2 in_lightmap_uv = vec2(vertices[base + 9], vertices[base + 10]);
+    vec2 in_tex_coord = vec2(vertices[base + 11], vertices[base + 12]);
+    vec4 in_tangent = vec4(vertices[base + 13], vertices[base + 14], vertices[base + 15], vertices[base + 16]);
+#endif
+
+#ifdef SCATTER
+    // Instances shrink away between the fade distances, the culling drops them past the end. They don't move, and
+    // ids are written one past the object index so they read as no object at all.
+    ScatterInstance instance = instances[visible[gl_InstanceIndex]];
+    float fade = 1.0 - smoothstep(scatter.fade_start, scatter.fade_end, distance(instance.sphere.xyz, camera.position.xyz));
+    mat4 model = instance.model;
+    model[0] *= max(fade, 0.01);
+    model[1] *= max(fade, 0.01);
+    model[2] *= max(fade, 0.01);
+    mat4 previous_model = model;
+    uint material_index = scatter.material;
+    uint object_index = 0xFFFFFFFFu;
+#else
+    // Draws pass the object's index as their first instance, direct and indirect alike
+    uint object_index = gl_InstanceIndex;
+    mat4 model = objects[object_index].model;
+    mat4 previous_model = objects[object_index].previous_model;
+    uint material_index = objects[object_index].material;
+#endif
+
+    vec4 world_position = model * vec4(in_position, 1.0);
+    // The red vertex color is the stiffness, the higher above the object's origin the further the wind bends
+    float sway = materials[material_index].texture_params.y * (1.0 - in_color.r) * max(in_position.y, 0.0);
+    world_position.xyz += wind_offset(world_position.xyz, sway);
+#ifdef WATER
+    // The waves move the surface in world space and replace its normal
+    vec3 wave_normal;
+    world_position.xyz += water_waves(materials[material_index], world_position.xz, camera.time.x, wave_normal);
+#endif
+    vec4 view_position = camera.view * world_position;
+    gl_Position = camera.projection * view_position;
+
+    // View space normals are what the screen space passes work with
+    mat3 normal_matrix = transpose(inverse(mat3(camera.view * model)));
+    out_normal = normal_matrix * in_normal;
+    out_world_normal = transpose(inverse(mat3(model))) * in_normal;
+#ifdef WATER
+    out_normal = mat3(camera.view) * wave_normal;
+    out_world_normal = wave_normal;
+#endif
+    out_world_position = world_position.xyz;
+    out_view_depth = -view_position.z;
+    out_lightmap_uv = in_lightmap_uv;
+    out_tex_coord = in_tex_coord;
+    // Tangents follow the surface like positions do, the handedness is passed on as it is
+    out_world_tangent = vec4(mat3(model) * in_tangent.xyz, in_tangent.w);
+    out_object_index = object_index;
+    out_material_index = material_index;
+
+    out_clip_position = gl_Position;
+    out_previous_clip_position = camera.previous_view_projection * previous_model * vec4(in_position, 1.0);
 
-    //out_color = in_color;
+    // Only the reflection pass sets a plane, a zero plane never clips
+    gl_ClipDistance[0] = dot(world_position, camera.clip_plane);
 }
\ No newline at end of file
@@ -0,0 +1,149 @@
+#version 450
+
+layout(location = 0) in vec2 in_uv;
+
+layout(location = 0) out vec4 out_clouds;
+
+#include "include/camera.glsl"
+
+// Last frame's clouds, reprojected and blended with this frame's
+layout(set = 1, binding = 0) uniform sampler2D history;
+
+// Must match CloudPushConstants in clouds.rs
+layout(push_constant) uniform Push {
+    // Direction towards the sun, weight of the history (0.0 right after a reset)
+    vec4 sun;
+    // Sunlight reaching the clouds, frame counter
+    vec4 sun_color;
+    // Sky light from above, radius of the planet at the scene's origin
+    vec4 ambient;
+    // Coverage, density per meter, bottom and top height of the layer above the scene's origin
+    vec4 layer;
+    // Size of the noise features, drift per second along x and z, raymarch steps
+    vec4 shape;
+} push;
+
+// Must match CLOUD_DOWNSCALE in clouds.rs
+const float CLOUD_DOWNSCALE = 2.0;
+const uint CLOUD_LIGHT_STEPS = 6;
+const float CLOUD_LIGHT_STEP = 150.0;
+// Clouds fade out towards this distance, the layer goes on for hundreds of kilometers near the horizon
+const float CLOUD_FADE_DISTANCE = 40000.0;
+const float CLOUD_PI = 3.14159265;
+
+float hash(vec3 p) {
+    p = fract(p * 0.3183099 + 0.1) * 17.0;
+    return fract(p.x * p.y * p.z * (p.x + p.y + p.z));
+}
+
+float value_noise(vec3 p) {
+    vec3 i = floor(p);
+    vec3 f = fract(p);
+    f = f * f * (3.0 - 2.0 * f);
+    return mix(
+        mix(mix(hash(i), hash(i + vec3(1, 0, 0)), f.x), mix(hash(i + vec3(0, 1, 0)), hash(i + vec3(1, 1, 0)), f.x), f.y),
+        mix(mix(hash(i + vec3(0, 0, 1)), hash(i + vec3(1, 0, 1)), f.x), mix(hash(i + vec3(0, 1, 1)), hash(i + vec3(1, 1, 1)), f.x), f.y),
+        f.z);
+}
+
+float fbm(vec3 p) {
+    float sum = 0.0;
+    float amplitude = 0.5;
+    for (uint i = 0; i < 4; i++) {
+        sum += value_noise(p) * amplitude;
+        p *= 2.03;
+        amplitude *= 0.5;
+    }
+    return sum / 0.9375;
+}
+
+// Extinction per meter at `position`, zero outside the layer
+float cloud_density(vec3 position) {
+    float height = length(position) - push.ambient.w;
+    float layer_height = (height - push.layer.z) / (push.layer.w - push.layer.z);
+    if (layer_height < 0.0 || layer_height > 1.0) {
+        return 0.0;
+    }
+
+    // Rounded bottoms, thinning out towards the top
+    float profile = clamp(layer_height * 5.0, 0.0, 1.0) * clamp((1.0 - layer_height) * 2.0, 0.0, 1.0);
+    vec3 drift = vec3(push.shape.y, 0.0, push.shape.z) * camera.time.x;
+    float noise = fbm((position + drift) / push.shape.x) * profile;
+    float coverage = push.layer.x;
+    return clamp((noise - (1.0 - coverage)) / max(coverage, 0.001), 0.0, 1.0) * push.layer.y;
+}
+
+float henyey_greenstein(float mu, float g) {
+    return (1.0 - g * g) / (4.0 * CLOUD_PI * pow(1.0 + g * g - 2.0 * g * mu, 1.5));
+}
+
+// Distance along the ray to where it leaves the sphere around the planet's center, the camera is always inside
+float sphere_exit(vec3 origin, vec3 direction, float radius) {
+    float b = dot(origin, direction);
+    float c = dot(origin, origin) - radius * radius;
+    return -b + sqrt(max(b * b - c, 0.0));
+}
+
+// Scattered light and transmittance of the layer along `direction`
+vec4 march_clouds(vec3 direction) {
+    vec3 origin = vec3(camera.position.x, push.ambient.w + max(camera.position.y, 0.0), camera.position.z);
+    float start = sphere_exit(origin, direction, push.ambient.w + push.layer.z);
+    float end = sphere_exit(origin, direction, push.ambient.w + push.layer.w);
+    // Below the horizon the ground hides the clouds
+    float fade = 1.0 - clamp(start / CLOUD_FADE_DISTANCE, 0.0, 1.0);
+    if (direction.y < 0.0 || fade <= 0.0) {
+        return vec4(0.0, 0.0, 0.0, 1.0);
+    }
+
+    uint steps = max(uint(push.shape.w), 1);
+    float step_length = (end - start) / float(steps);
+    // Shifted per pixel and frame, the banding of few steps turns into noise the history averages out
+    float offset = fract(52.9829189 * fract(dot(gl_FragCoord.xy, vec2(0.06711056, 0.00583715))) + push.sun_color.w * 0.618034);
+
+    // Mostly forward scattering with a little back scattering, for the silver lining towards the sun. Relative to
+    // scattering evenly in every direction, as the ambient light is assumed to.
+    float mu = dot(direction, push.sun.xyz);
+    float phase = mix(henyey_greenstein(mu, 0.6), henyey_greenstein(mu, -0.3), 0.3) * 4.0 * CLOUD_PI;
+
+    vec3 scattered = vec3(0.0);
+    float transmittance = 1.0;
+    for (uint i = 0; i < steps && transmittance > 0.01; i++) {
+        vec3 position = origin + direction * (start + (float(i) + offset) * step_length);
+        float density = cloud_density(position);
+        if (density <= 0.0) {
+            continue;
+        }
+
+        float light_depth = 0.0;
+        for (uint j = 0; j < CLOUD_LIGHT_STEPS; j++) {
+            light_depth += cloud_density(position + push.sun.xyz * (float(j) + 0.5) * CLOUD_LIGHT_STEP) * CLOUD_LIGHT_STEP;
+        }
+        // Beer's law towards the sun, the powder term darkens the thin edges facing away from it
+        float powder = 1.0 - exp(-2.0 * light_depth);
+        vec3 light = push.sun_color.rgb * phase * exp(-light_depth) * mix(1.0, powder, 0.5) + push.ambient.rgb;
+
+        float sample_transmittance = exp(-density * step_length);
+        scattered += transmittance * light * (1.0 - sample_transmittance);
+        transmittance *= sample_transmittance;
+    }
+
+    return vec4(scattered * fade, mix(1.0, transmittance, fade));
+}
+
+void main() {
+    vec4 view_position = camera.inverse_projection * vec4(in_uv * 2.0 - 1.0, 1.0, 1.0);
+    vec3 direction = normalize(transpose(mat3(camera.view)) * (view_position.xyz / view_position.w));
+    vec4 clouds = march_clouds(direction);
+
+    // Clouds are far enough away that only the camera's rotation moves them on screen
+    vec4 previous_clip_position = camera.previous_view_projection * vec4(direction, 0.0);
+    vec2 previous_uv = previous_clip_position.xy / previous_clip_position.w * 0.5 + 0.5;
+    bool on_screen = previous_clip_position.w > 0.0 && all(greaterThanEqual(previous_uv, vec2(0.0)))
+        && all(lessThanEqual(previous_uv, vec2(1.0)));
+    if (push.sun.w > 0.0 && on_screen) {
+        vec2 history_uv = (camera.viewport_offset.xy + previous_uv * camera.viewport.xy) / (CLOUD_DOWNSCALE * vec2(textureSize(history, 0)));
+        clouds = mix(clouds, texture(history, history_uv), push.sun.w);
+    }
+
+    out_clouds = clouds;
+}
\ No newline at end of file
@@ -0,0 +1,55 @@
+#version 450
+
+layout(location = 0) in vec2 in_uv;
+
+layout(location = 0) out vec4 out_color;
+
+layout(set = 0, binding = 0) uniform sampler2D scene_color;
+
+layout(set = 2, binding = 0) uniform sampler3D grading_lut;
+
+// Adapted by the auto exposure passes, stays at 1.0 while those are disabled
+layout(set = 2, binding = 1) readonly buffer Exposure {
+    float exposure;
+    float luminance;
+} adapted;
+
+// The scene color before any effect, which all write an alpha of one
+layout(set = 2, binding = 2) uniform sampler2D scene_coverage;
+
+layout(push_constant) uniform Push {
+    vec4 lift;
+    vec4 gamma;
+    vec4 gain;
+    float exposure;
+    float lut_strength;
+    // 0 opaque, 1 premultiplied, 2 straight
+    uint alpha_mode;
+} push;
+
+// Narkowicz's fit of the ACES filmic curve
+vec3 tonemap(vec3 color) {
+    return clamp((color * (2.51 * color + 0.03)) / (color * (2.43 * color + 0.59) + 0.14), 0.0, 1.0);
+}
+
+vec3 linear_to_srgb(vec3 color) {
+    return mix(color * 12.92, 1.055 * pow(color, vec3(1.0 / 2.4)) - 0.055, step(vec3(0.0031308), color));
+}
+
+void main() {
+    vec3 color = tonemap(texture(scene_color, in_uv).rgb * push.exposure * adapted.exposure);
+
+    color = push.gain.rgb * (color + push.lift.rgb * (1.0 - color));
+    color = pow(max(color, 0.0), 1.0 / max(push.gamma.rgb, vec3(0.001)));
+
+    // LUTs are authored against display encoded values, and the swapchain is written as plain UNORM
+    color = linear_to_srgb(clamp(color, 0.0, 1.0));
+
+    float lut_size = float(textureSize(grading_lut, 0).x);
+    vec3 lut_uvw = color * (lut_size - 1.0) / lut_size + 0.5 / lut_size;
+    color = mix(color, texture(grading_lut, lut_uvw).rgb, push.lut_strength);
+
+    // The camera's clear color decides the alpha where nothing was drawn
+    float alpha = push.alpha_mode == 0u ? 1.0 : clamp(texture(scene_coverage, in_uv).a, 0.0, 1.0);
+    out_color = vec4(push.alpha_mode == 1u ? color * alpha : color, alpha);
+}
\ No newline at end of file
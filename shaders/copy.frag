@@ -0,0 +1,11 @@
+#version 450
+
+layout(location = 0) in vec2 in_uv;
+
+layout(location = 0) out vec4 out_color;
+
+layout(set = 0, binding = 0) uniform sampler2D scene_color;
+
+void main() {
+    out_color = vec4(texture(scene_color, in_uv).rgb, 1.0);
+}
\ No newline at end of file
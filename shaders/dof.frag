@@ -0,0 +1,64 @@
+#version 450
+
+layout(location = 0) in vec2 in_uv;
+
+layout(location = 0) out vec4 out_color;
+
+layout(set = 0, binding = 0) uniform sampler2D scene_color;
+layout(set = 0, binding = 2) uniform sampler2D scene_depth;
+
+#define CAMERA_SET 1
+#include "include/camera.glsl"
+
+layout(push_constant) uniform Push {
+    float max_radius;
+    int samples;
+} push;
+
+const float GOLDEN_ANGLE = 2.39996323;
+
+float view_distance(vec2 uv) {
+    float depth = texture(scene_depth, uv).r;
+    vec4 view = camera.inverse_projection * vec4(uv * 2.0 - 1.0, depth, 1.0);
+    return -view.z / view.w;
+}
+
+// Circle of confusion radius in pixels, grows towards the aperture size at infinity
+float circle_of_confusion(float distance) {
+    float focal_distance = camera.lens.x;
+    float aperture = camera.lens.y;
+    return min(aperture * abs(1.0 - focal_distance / max(distance, 0.0001)), push.max_radius);
+}
+
+void main() {
+    vec3 color = texture(scene_color, in_uv).rgb;
+    float center_distance = view_distance(in_uv);
+    float center_coc = circle_of_confusion(center_distance);
+    if (camera.lens.y <= 0.0 || push.samples < 1) {
+        out_color = vec4(color, 1.0);
+        return;
+    }
+
+    // Gather over a disk of the largest possible radius; a sample only contributes if its own circle
+    // reaches this pixel, which keeps sharp foreground edges from blurring over an out of focus background
+    vec3 sum = color;
+    float total = 1.0;
+    for (int i = 0; i < push.samples; i++) {
+        float radius = sqrt(float(i + 1) / float(push.samples)) * push.max_radius;
+        float theta = float(i) * GOLDEN_ANGLE;
+        vec2 sample_uv = in_uv + vec2(cos(theta), sin(theta)) * radius * camera.viewport.zw;
+
+        float sample_distance = view_distance(sample_uv);
+        float sample_coc = circle_of_confusion(sample_distance);
+        // Samples behind the center can't spread further than the center's own blur
+        if (sample_distance > center_distance) {
+            sample_coc = min(sample_coc, center_coc);
+        }
+
+        float weight = smoothstep(radius - 1.0, radius + 1.0, sample_coc);
+        sum += texture(scene_color, sample_uv).rgb * weight;
+        total += weight;
+    }
+
+    out_color = vec4(sum / total, 1.0);
+}
\ No newline at end of file
@@ -0,0 +1,13 @@
+#version 450
+
+layout(location = 0) out vec2 out_uv;
+
+void main() {
+    // A single triangle covering the screen, no vertex buffer needed
+    out_uv = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2);
+    gl_Position = vec4(out_uv * 2.0 - 1.0, 0.0, 1.0);
+#ifdef FAR_PLANE
+    // Behind everything drawn before it, the depth test leaves it only the pixels that are still empty
+    gl_Position.z = 1.0;
+#endif
+}
\ No newline at end of file
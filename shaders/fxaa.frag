@@ -0,0 +1,126 @@
+#version 450
+
+layout(location = 0) in vec2 in_uv;
+
+layout(location = 0) out vec4 out_color;
+
+layout(set = 0, binding = 0) uniform sampler2D scene_color;
+
+#define CAMERA_SET 1
+#include "include/camera.glsl"
+
+layout(push_constant) uniform Push {
+    float edge_threshold;
+    float edge_threshold_min;
+    float subpixel_blend;
+} push;
+
+const int EDGE_STEPS = 8;
+
+// Edges are found on perceptual luma of the color clamped to displayable range
+float luma(vec2 uv) {
+    vec3 color = clamp(texture(scene_color, uv).rgb, 0.0, 1.0);
+    return sqrt(dot(color, vec3(0.299, 0.587, 0.114)));
+}
+
+void main() {
+    vec2 texel = camera.viewport.zw;
+    vec3 color = texture(scene_color, in_uv).rgb;
+
+    float center = luma(in_uv);
+    float north = luma(in_uv + vec2(0.0, -texel.y));
+    float south = luma(in_uv + vec2(0.0, texel.y));
+    float east = luma(in_uv + vec2(texel.x, 0.0));
+    float west = luma(in_uv + vec2(-texel.x, 0.0));
+
+    float luma_min = min(center, min(min(north, south), min(east, west)));
+    float luma_max = max(center, max(max(north, south), max(east, west)));
+    float contrast = luma_max - luma_min;
+    if (contrast < max(push.edge_threshold_min, luma_max * push.edge_threshold)) {
+        out_color = vec4(color, 1.0);
+        return;
+    }
+
+    float north_east = luma(in_uv + vec2(texel.x, -texel.y));
+    float north_west = luma(in_uv + vec2(-texel.x, -texel.y));
+    float south_east = luma(in_uv + vec2(texel.x, texel.y));
+    float south_west = luma(in_uv + vec2(-texel.x, texel.y));
+
+    // Sub-pixel aliasing: how much the center differs from its whole neighborhood
+    float average = (2.0 * (north + south + east + west) + north_east + north_west + south_east + south_west) / 12.0;
+    float subpixel = smoothstep(0.0, 1.0, clamp(abs(average - center) / contrast, 0.0, 1.0));
+    subpixel = subpixel * subpixel * push.subpixel_blend;
+
+    float horizontal = abs(north + south - 2.0 * center) * 2.0 + abs(north_east + south_east - 2.0 * east) + abs(north_west + south_west - 2.0 * west);
+    float vertical = abs(east + west - 2.0 * center) * 2.0 + abs(north_east + north_west - 2.0 * north) + abs(south_east + south_west - 2.0 * south);
+    bool is_horizontal = horizontal >= vertical;
+
+    // Step towards the neighbor across the edge with the larger gradient
+    float positive = is_horizontal ? south : east;
+    float negative = is_horizontal ? north : west;
+    float step_length = is_horizontal ? texel.y : texel.x;
+    float gradient_positive = abs(positive - center);
+    float gradient_negative = abs(negative - center);
+    float opposite = positive;
+    float gradient = gradient_positive;
+    if (gradient_negative > gradient_positive) {
+        step_length = -step_length;
+        opposite = negative;
+        gradient = gradient_negative;
+    }
+
+    // Walk along the edge in both directions until the luma pair no longer matches
+    vec2 edge_uv = in_uv;
+    vec2 edge_step;
+    if (is_horizontal) {
+        edge_uv.y += step_length * 0.5;
+        edge_step = vec2(texel.x, 0.0);
+    } else {
+        edge_uv.x += step_length * 0.5;
+        edge_step = vec2(0.0, texel.y);
+    }
+
+    float edge_luma = (center + opposite) * 0.5;
+    float gradient_threshold = gradient * 0.25;
+
+    vec2 uv_positive = edge_uv + edge_step;
+    vec2 uv_negative = edge_uv - edge_step;
+    float delta_positive = luma(uv_positive) - edge_luma;
+    float delta_negative = luma(uv_negative) - edge_luma;
+    for (int i = 0; i < EDGE_STEPS; i++) {
+        bool done_positive = abs(delta_positive) >= gradient_threshold;
+        bool done_negative = abs(delta_negative) >= gradient_threshold;
+        if (done_positive && done_negative) {
+            break;
+        }
+        if (!done_positive) {
+            uv_positive += edge_step;
+            delta_positive = luma(uv_positive) - edge_luma;
+        }
+        if (!done_negative) {
+            uv_negative -= edge_step;
+            delta_negative = luma(uv_negative) - edge_luma;
+        }
+    }
+
+    float distance_positive = is_horizontal ? uv_positive.x - in_uv.x : uv_positive.y - in_uv.y;
+    float distance_negative = is_horizontal ? in_uv.x - uv_negative.x : in_uv.y - uv_negative.y;
+    bool closer_positive = distance_positive < distance_negative;
+    float closest = min(distance_positive, distance_negative);
+    float edge_length = distance_positive + distance_negative;
+
+    // Only blend when the closer edge end has the opposite luma trend of the center
+    bool center_smaller = center - edge_luma < 0.0;
+    bool correct = ((closer_positive ? delta_positive : delta_negative) < 0.0) != center_smaller;
+    float edge_blend = correct ? 0.5 - closest / edge_length : 0.0;
+
+    float blend = max(edge_blend, subpixel);
+    vec2 final_uv = in_uv;
+    if (is_horizontal) {
+        final_uv.y += blend * step_length;
+    } else {
+        final_uv.x += blend * step_length;
+    }
+
+    out_color = vec4(texture(scene_color, final_uv).rgb, 1.0);
+}
\ No newline at end of file
@@ -0,0 +1,12 @@
+#version 450
+
+layout(location = 0) out vec4 out_color;
+
+layout(push_constant) uniform Push {
+    mat4 model;
+    vec4 color;
+} push;
+
+void main() {
+    out_color = push.color;
+}
\ No newline at end of file
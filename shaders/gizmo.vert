@@ -0,0 +1,16 @@
+#version 450
+
+layout(location = 0) in vec3 in_position;
+
+#include "include/camera.glsl"
+
+layout(push_constant) uniform Push {
+    mat4 model;
+    vec4 color;
+} push;
+
+void main() {
+    gl_Position = camera.projection * camera.view * push.model * vec4(in_position, 1.0);
+    // Drawn after the TAA resolve, so the jitter would only make the handles shake
+    gl_Position.xy -= camera.jitter.xy * gl_Position.w;
+}
\ No newline at end of file
@@ -0,0 +1,58 @@
+#version 450
+
+layout(location = 0) in vec2 in_uv;
+
+layout(location = 0) out vec4 out_color;
+
+layout(set = 0, binding = 0) uniform sampler2D scene_color;
+layout(set = 0, binding = 1) uniform sampler2D scene_normal;
+layout(set = 0, binding = 2) uniform sampler2D scene_depth;
+
+#define CAMERA_SET 1
+#include "include/camera.glsl"
+
+layout(push_constant) uniform Push {
+    vec4 sun_direction;
+    vec4 color;
+    int samples;
+    float density;
+    float decay;
+    float weight;
+    float exposure;
+} push;
+
+// Only pixels where nothing was drawn let sun light through
+vec3 sky_mask(vec2 uv) {
+    if (any(lessThan(uv, vec2(0.0))) || any(greaterThan(uv, vec2(1.0)))) {
+        return vec3(0.0);
+    }
+    return texture(scene_depth, uv).r >= 1.0 ? push.color.rgb : vec3(0.0);
+}
+
+void main() {
+    vec3 color = texture(scene_color, in_uv).rgb;
+
+    // The sun is infinitely far away, so its direction is projected with w = 0
+    vec4 sun_clip = camera.projection * vec4(mat3(camera.view) * push.sun_direction.xyz, 0.0);
+    if (sun_clip.w <= 0.0) {
+        out_color = vec4(color, 1.0);
+        return;
+    }
+    vec2 sun_uv = sun_clip.xy / sun_clip.w * 0.5 + 0.5;
+
+    vec2 step_uv = (in_uv - sun_uv) * push.density / float(push.samples);
+    vec2 sample_uv = in_uv;
+    float illumination_decay = 1.0;
+    vec3 shafts = vec3(0.0);
+    for (int i = 0; i < push.samples; i++) {
+        sample_uv -= step_uv;
+        shafts += sky_mask(sample_uv) * illumination_decay * push.weight;
+        illumination_decay *= push.decay;
+    }
+
+    // Fade out as the sun leaves the screen instead of popping
+    vec2 edge = clamp(vec2(1.0) - abs(sun_uv * 2.0 - 1.0), 0.0, 1.0);
+    float visibility = clamp((edge.x + edge.y) * 2.0, 0.0, 1.0);
+
+    out_color = vec4(color + shafts * push.exposure * visibility, 1.0);
+}
\ No newline at end of file
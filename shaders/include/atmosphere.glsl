@@ -0,0 +1,99 @@
+#ifndef ATMOSPHERE_GLSL
+#define ATMOSPHERE_GLSL
+
+// Single scattering of sunlight through a planet's atmosphere (Nishita). Atmosphere::sky_radiance in atmosphere.rs
+// is the same raymarch on the CPU, keep them in sync.
+
+// Must match Atmosphere::uniform in atmosphere.rs
+struct AtmosphereParams {
+    // Direction towards the sun, sun intensity
+    vec4 sun;
+    // Rayleigh scattering coefficients per meter, Rayleigh scale height
+    vec4 rayleigh;
+    // Mie scattering coefficient per meter, Mie scale height, Mie anisotropy, altitude of the viewer
+    vec4 mie;
+    // Ground radius, atmosphere radius
+    vec4 radii;
+};
+
+const uint SKY_SAMPLES = 16;
+const uint SKY_LIGHT_SAMPLES = 8;
+const float ATMOSPHERE_PI = 3.14159265;
+
+// Distances along the ray to where it enters and leaves the sphere around the planet's center, negative both when
+// it misses
+vec2 sphere_intersection(vec3 origin, vec3 direction, float radius) {
+    float b = dot(origin, direction);
+    float c = dot(origin, origin) - radius * radius;
+    float discriminant = b * b - c;
+    if (discriminant < 0.0) {
+        return vec2(-1.0);
+    }
+    float root = sqrt(discriminant);
+    return vec2(-b - root, -b + root);
+}
+
+vec3 atmosphere_origin(AtmosphereParams atmosphere) {
+    return vec3(0.0, atmosphere.radii.x + atmosphere.mie.w, 0.0);
+}
+
+// Rayleigh and Mie density along the ray out of the atmosphere, the ground blocks it completely
+vec2 optical_depth(AtmosphereParams atmosphere, vec3 origin, vec3 direction) {
+    if (sphere_intersection(origin, direction, atmosphere.radii.x).x > 0.0) {
+        return vec2(1e9);
+    }
+
+    float step_length = sphere_intersection(origin, direction, atmosphere.radii.y).y / float(SKY_LIGHT_SAMPLES);
+    vec2 depth = vec2(0.0);
+    for (uint i = 0; i < SKY_LIGHT_SAMPLES; i++) {
+        vec3 position = origin + direction * (float(i) + 0.5) * step_length;
+        float height = length(position) - atmosphere.radii.x;
+        depth += exp(-height / vec2(atmosphere.rayleigh.w, atmosphere.mie.y)) * step_length;
+    }
+    return depth;
+}
+
+// Mie particles absorb a little on top of scattering
+vec3 extinction(AtmosphereParams atmosphere, vec2 depth) {
+    return exp(-(atmosphere.rayleigh.xyz * depth.x + 1.1 * atmosphere.mie.x * depth.y));
+}
+
+// Share of the light that makes it out of the atmosphere along `direction`, for the sun's disk
+vec3 atmosphere_transmittance(AtmosphereParams atmosphere, vec3 direction) {
+    return extinction(atmosphere, optical_depth(atmosphere, atmosphere_origin(atmosphere), direction));
+}
+
+// Sunlight scattered towards the viewer along `direction`, up to the ground or the top of the atmosphere
+vec3 sky_radiance(AtmosphereParams atmosphere, vec3 direction) {
+    vec3 origin = atmosphere_origin(atmosphere);
+    vec3 sun = atmosphere.sun.xyz;
+    float ray_length = sphere_intersection(origin, direction, atmosphere.radii.y).y;
+    float ground = sphere_intersection(origin, direction, atmosphere.radii.x).x;
+    if (ground > 0.0) {
+        ray_length = ground;
+    }
+
+    float step_length = ray_length / float(SKY_SAMPLES);
+    vec2 view_depth = vec2(0.0);
+    vec3 rayleigh = vec3(0.0);
+    vec3 mie = vec3(0.0);
+    for (uint i = 0; i < SKY_SAMPLES; i++) {
+        vec3 position = origin + direction * (float(i) + 0.5) * step_length;
+        float height = length(position) - atmosphere.radii.x;
+        vec2 density = exp(-height / vec2(atmosphere.rayleigh.w, atmosphere.mie.y)) * step_length;
+        view_depth += density;
+
+        vec3 attenuation = extinction(atmosphere, view_depth + optical_depth(atmosphere, position, sun));
+        rayleigh += density.x * attenuation;
+        mie += density.y * attenuation;
+    }
+
+    float mu = dot(direction, sun);
+    float g = atmosphere.mie.z;
+    float rayleigh_phase = 3.0 / (16.0 * ATMOSPHERE_PI) * (1.0 + mu * mu);
+    float mie_phase = 3.0 / (8.0 * ATMOSPHERE_PI) * ((1.0 - g * g) * (1.0 + mu * mu))
+        / ((2.0 + g * g) * pow(1.0 + g * g - 2.0 * g * mu, 1.5));
+    return atmosphere.sun.w * (rayleigh * atmosphere.rayleigh.xyz * rayleigh_phase + mie * atmosphere.mie.x * mie_phase);
+}
+
+#endif
\ No newline at end of file
@@ -0,0 +1,29 @@
+#ifndef CAMERA_GLSL
+#define CAMERA_GLSL
+
+// Must match CameraUniform in camera.rs. Scene passes bind the camera at set 0, post passes define CAMERA_SET
+// before including this since their inputs come first.
+#ifndef CAMERA_SET
+#define CAMERA_SET 0
+#endif
+
+layout(set = CAMERA_SET, binding = 0) uniform Camera {
+    mat4 view;
+    mat4 projection;
+    mat4 inverse_projection;
+    vec4 position;
+    vec4 clip_plane;
+    vec4 viewport;
+    vec4 near_far;
+    mat4 previous_view_projection;
+    vec4 lens;
+    vec4 jitter;
+    vec4 viewport_offset;
+    // Seconds animated materials are at, zero for reflection and capture cameras
+    vec4 time;
+    // Wind::uniform in wind.rs, calm for reflection and capture cameras
+    vec4 wind;
+    vec4 wind_gusts;
+} camera;
+
+#endif
\ No newline at end of file
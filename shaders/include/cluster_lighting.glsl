@@ -0,0 +1,47 @@
+#ifndef CLUSTER_LIGHTING_GLSL
+#define CLUSTER_LIGHTING_GLSL
+
+// Direct light on a scene fragment from the lights of its cluster, for fragment shaders drawn with the scene's light
+// set. Include after camera.glsl and lighting.glsl.
+
+#include "clusters.glsl"
+
+layout(std430, set = LIGHTING_SET, binding = 1) readonly buffer Clusters {
+    uint cluster_light_counts[];
+};
+
+layout(std430, set = LIGHTING_SET, binding = 2) readonly buffer ClusterLights {
+    uint cluster_light_indices[];
+};
+
+uint cluster_index(float view_depth) {
+    // Clusters cover the camera's own view, which starts at viewport_offset for split screen
+    uvec2 tile = uvec2((gl_FragCoord.xy - camera.viewport_offset.xy) / camera.viewport.xy * vec2(CLUSTER_GRID.xy));
+    float near = camera.near_far.x;
+    float far = camera.near_far.y;
+    uint slice = uint(max(log(view_depth / near) / log(far / near) * float(CLUSTER_GRID.z), 0.0));
+    tile = min(tile, CLUSTER_GRID.xy - 1);
+    slice = min(slice, CLUSTER_GRID.z - 1);
+    return tile.x + tile.y * CLUSTER_GRID.x + slice * CLUSTER_GRID.x * CLUSTER_GRID.y;
+}
+
+vec3 direct_lighting(vec3 world_position, vec3 normal, float view_depth) {
+    vec3 lighting = shade_sun(normal);
+
+    // Clusters are built for the main camera, reflection and cubemap cameras have to check every light
+    if (camera.near_far.z == 0.0) {
+        uint cluster = cluster_index(view_depth);
+        uint count = cluster_light_counts[cluster];
+        for (uint i = 0; i < count; i++) {
+            lighting += shade_light(lights[cluster_light_indices[cluster * MAX_LIGHTS_PER_CLUSTER + i]], world_position, normal);
+        }
+    } else {
+        for (uint i = 0; i < light_count; i++) {
+            lighting += shade_light(lights[i], world_position, normal);
+        }
+    }
+
+    return lighting;
+}
+
+#endif
\ No newline at end of file
@@ -0,0 +1,11 @@
+#ifndef CLUSTERS_GLSL
+#define CLUSTERS_GLSL
+
+// Specialized with CLUSTER_SPECIALIZATION from clustered_lighting.rs, the defaults only keep the shader valid on its own
+layout(constant_id = 0) const uint CLUSTER_GRID_X = 16;
+layout(constant_id = 1) const uint CLUSTER_GRID_Y = 9;
+layout(constant_id = 2) const uint CLUSTER_GRID_Z = 24;
+layout(constant_id = 3) const uint MAX_LIGHTS_PER_CLUSTER = 128;
+const uvec3 CLUSTER_GRID = uvec3(CLUSTER_GRID_X, CLUSTER_GRID_Y, CLUSTER_GRID_Z);
+
+#endif
\ No newline at end of file
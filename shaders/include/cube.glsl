@@ -0,0 +1,18 @@
+#ifndef CUBE_GLSL
+#define CUBE_GLSL
+
+// Direction through a point of a face, in the +X, -X, +Y, -Y, +Z, -Z order of cube layers (see cubemap.rs).
+// `uv` goes from 0 to 1 across the face, the result isn't normalized.
+vec3 cube_face_direction(uint face, vec2 uv) {
+    vec2 p = uv * 2.0 - 1.0;
+    switch (face) {
+        case 0: return vec3(1.0, -p.y, -p.x);
+        case 1: return vec3(-1.0, -p.y, p.x);
+        case 2: return vec3(p.x, 1.0, p.y);
+        case 3: return vec3(p.x, -1.0, -p.y);
+        case 4: return vec3(p.x, -p.y, 1.0);
+        default: return vec3(-p.x, -p.y, -1.0);
+    }
+}
+
+#endif
\ No newline at end of file
@@ -0,0 +1,23 @@
+#ifndef FRUSTUM_GLSL
+#define FRUSTUM_GLSL
+
+// Bounding sphere tests against the view of camera.glsl, include after it
+
+bool outside(vec4 plane, vec3 center, float radius) {
+    return dot(plane.xyz, center) + plane.w < -radius * length(plane.xyz);
+}
+
+bool sphere_in_view(vec4 sphere) {
+    // Frustum planes straight from the rows of the view projection, depth going from 0 to w
+    mat4 m = transpose(camera.projection * camera.view);
+    if (outside(m[3] + m[0], sphere.xyz, sphere.w) || outside(m[3] - m[0], sphere.xyz, sphere.w)
+        || outside(m[3] + m[1], sphere.xyz, sphere.w) || outside(m[3] - m[1], sphere.xyz, sphere.w)
+        || outside(m[2], sphere.xyz, sphere.w) || outside(m[3] - m[2], sphere.xyz, sphere.w)) {
+        return false;
+    }
+
+    // Reflection cameras clip everything behind their plane, a zero plane keeps everything
+    return camera.clip_plane == vec4(0.0) || !outside(camera.clip_plane, sphere.xyz, sphere.w);
+}
+
+#endif
\ No newline at end of file
@@ -0,0 +1,64 @@
+#ifndef LIGHT_PROBES_GLSL
+#define LIGHT_PROBES_GLSL
+
+// Ambient from the SH light probe grid, see light_probes.rs
+
+#include "lights.glsl"
+#include "sh.glsl"
+
+// Must match GpuLightProbeGrid in light_probes.rs
+layout(std430, set = LIGHTING_SET, binding = 10) readonly buffer LightProbeGrid {
+    // xyz position of the first probe, w 1.0 once every probe has been baked
+    vec4 grid_origin;
+    vec4 grid_spacing;
+    uvec4 grid_counts;
+};
+
+// SH_COEFFICIENTS per probe, x fastest, then y, then z
+layout(std430, set = LIGHTING_SET, binding = 11) readonly buffer LightProbeCoefficients {
+    vec4 sh_coefficients[];
+};
+
+vec3 evaluate_probe(uint probe, vec3 normal) {
+    float basis[SH_COEFFICIENTS] = sh_basis(normal);
+    vec3 result = vec3(0.0);
+    for (uint i = 0; i < SH_COEFFICIENTS; i++) {
+        result += sh_coefficients[probe * SH_COEFFICIENTS + i].rgb * basis[i];
+    }
+    return result;
+}
+
+// Trilinear blend of the eight probes around the position, positions outside the grid use its border probes.
+// Probes behind the surface are weighted down like in DDGI, so light doesn't leak through thin walls.
+// Returns `fallback` until the grid is baked.
+vec3 probe_ambient(vec3 world_position, vec3 normal, vec3 fallback) {
+    if (grid_origin.w == 0.0) {
+        return fallback;
+    }
+
+    vec3 max_cell = vec3(grid_counts.xyz - 1u);
+    vec3 cell = clamp((world_position - grid_origin.xyz) / grid_spacing.xyz, vec3(0.0), max_cell);
+    uvec3 base = uvec3(min(floor(cell), max(max_cell - 1.0, vec3(0.0))));
+    vec3 t = clamp(cell - vec3(base), vec3(0.0), vec3(1.0));
+
+    vec3 ambient_light = vec3(0.0);
+    float weight_sum = 0.0;
+    for (uint corner = 0; corner < 8; corner++) {
+        uvec3 offset = uvec3(corner & 1u, (corner >> 1) & 1u, corner >> 2);
+        uvec3 probe = min(base + offset, grid_counts.xyz - 1u);
+        vec3 weights = mix(1.0 - t, t, vec3(offset));
+
+        // Smooth backface term, never quite zero so a surface with every probe behind it still gets some light
+        vec3 to_probe = grid_origin.xyz + vec3(probe) * grid_spacing.xyz - world_position;
+        float facing = (dot(normalize(to_probe + normal * 0.001), normal) + 1.0) * 0.5;
+        float weight = weights.x * weights.y * weights.z * (facing * facing + 0.2);
+
+        uint index = probe.x + probe.y * grid_counts.x + probe.z * grid_counts.x * grid_counts.y;
+        ambient_light += evaluate_probe(index, normal) * weight;
+        weight_sum += weight;
+    }
+
+    return max(ambient_light / max(weight_sum, 0.0001), vec3(0.0));
+}
+
+#endif
\ No newline at end of file
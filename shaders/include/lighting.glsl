@@ -0,0 +1,196 @@
+#ifndef LIGHTING_GLSL
+#define LIGHTING_GLSL
+
+// Direct lighting from the light buffer with filtered shadows, for fragment shaders drawn with the scene's light set
+
+#include "lights.glsl"
+
+// Must match POINT_SHADOW_NEAR in shadows.rs and shadow_cube.vert, and SPOT_LIGHT_NEAR in lights.rs
+const float POINT_SHADOW_NEAR = 0.05;
+const float SPOT_LIGHT_NEAR = 0.05;
+
+// Must match ShadowFilter in shadows.rs
+const uint SHADOW_FILTER_HARDWARE = 0;
+const uint SHADOW_FILTER_PCF = 1;
+const uint SHADOW_FILTER_PCSS = 2;
+const uint MAX_SHADOW_SAMPLES = 16;
+
+const vec2 POISSON_DISK[16] = vec2[](
+    vec2(-0.94201624, -0.39906216), vec2(0.94558609, -0.76890725),
+    vec2(-0.09418410, -0.92938870), vec2(0.34495938, 0.29387760),
+    vec2(-0.91588581, 0.45771432), vec2(-0.81544232, -0.87912464),
+    vec2(-0.38277543, 0.27676845), vec2(0.97484398, 0.75648379),
+    vec2(0.44323325, -0.97511554), vec2(0.53742981, -0.47373420),
+    vec2(-0.26496911, -0.41893023), vec2(0.79197514, 0.19090188),
+    vec2(-0.24188840, 0.99706507), vec2(-0.81409955, 0.91437590),
+    vec2(0.19984126, 0.78641367), vec2(0.14383161, -0.14100790)
+);
+
+layout(set = LIGHTING_SET, binding = 3) uniform sampler2DArrayShadow shadow_maps;
+layout(set = LIGHTING_SET, binding = 4) uniform sampler2DArray light_cookies;
+layout(set = LIGHTING_SET, binding = 5) uniform samplerCubeArrayShadow point_shadow_maps;
+// Same maps without depth comparison, for the PCSS blocker search
+layout(set = LIGHTING_SET, binding = 6) uniform sampler2DArray spot_shadow_depths;
+layout(set = LIGHTING_SET, binding = 7) uniform samplerCubeArray point_shadow_depths;
+
+// Rotating the disk per pixel trades banding for noise
+mat2 poisson_rotation() {
+    float angle = 6.2831853 * fract(52.9829189 * fract(dot(gl_FragCoord.xy, vec2(0.06711056, 0.00583715))));
+    float s = sin(angle);
+    float c = cos(angle);
+    return mat2(c, s, -s, c);
+}
+
+float linearize_depth(float depth, float near, float far) {
+    return near * far / (far - depth * (far - near));
+}
+
+// Average linear depth of the occluders around the lookup (radius in shadow map uv), negative when nothing blocks the light
+float spot_blocker_distance(float layer, vec2 uv, float receiver, float far, float search_radius, mat2 rotation) {
+    uint samples = clamp(shadow_filter.z, 1, MAX_SHADOW_SAMPLES);
+    float total = 0.0;
+    uint count = 0;
+    for (uint i = 0; i < samples; i++) {
+        vec2 offset = rotation * POISSON_DISK[i] * search_radius;
+        float blocker = linearize_depth(texture(spot_shadow_depths, vec3(uv + offset, layer)).r, SPOT_LIGHT_NEAR, far);
+        if (blocker < receiver) {
+            total += blocker;
+            count++;
+        }
+    }
+    return count == 0 ? -1.0 : total / float(count);
+}
+
+float spot_shadow(Light light, vec2 uv, float depth) {
+    float layer = light.spot_params.w;
+    if (shadow_filter.x == SHADOW_FILTER_HARDWARE) {
+        return texture(shadow_maps, vec4(uv, layer, depth));
+    }
+
+    vec2 texel = 1.0 / vec2(textureSize(shadow_maps, 0).xy);
+    mat2 rotation = poisson_rotation();
+    float radius = shadow_params.x * texel.x;
+
+    // Contact hardening: the penumbra grows with the distance between the blocker and the receiver
+    if (shadow_filter.x == SHADOW_FILTER_PCSS) {
+        float far = light.position_radius.w;
+        float light_size = shadow_params.y;
+        float receiver = linearize_depth(depth, SPOT_LIGHT_NEAR, far);
+        float blocker = spot_blocker_distance(layer, uv, receiver, far, light_size * (receiver - SPOT_LIGHT_NEAR) / receiver, rotation);
+        if (blocker < 0.0) {
+            return 1.0;
+        }
+        radius = max(light_size * (receiver - blocker) / blocker, texel.x);
+    }
+
+    uint samples = clamp(shadow_filter.y, 1, MAX_SHADOW_SAMPLES);
+    float lit = 0.0;
+    for (uint i = 0; i < samples; i++) {
+        vec2 offset = rotation * POISSON_DISK[i] * radius;
+        lit += texture(shadow_maps, vec4(uv + offset, layer, depth));
+    }
+    return lit / float(samples);
+}
+
+// Cone mask, cookie and shadow of a spot light, all looked up through the light's projection
+vec3 spot_factor(Light light, vec3 world_position, vec3 direction_to_light) {
+    float cos_angle = dot(-direction_to_light, normalize(light.direction_falloff.xyz));
+    float cone = smoothstep(light.spot_params.y, light.spot_params.x, cos_angle);
+    cone = pow(cone, max(light.direction_falloff.w, 0.001));
+    if (cone <= 0.0) {
+        return vec3(0.0);
+    }
+
+    vec4 light_clip = light.view_projection * vec4(world_position, 1.0);
+    vec3 light_ndc = light_clip.xyz / light_clip.w;
+    vec2 light_uv = light_ndc.xy * 0.5 + 0.5;
+
+    vec3 mask = vec3(cone);
+    if (light.spot_params.z >= 0.0) {
+        mask *= texture(light_cookies, vec3(light_uv, light.spot_params.z)).rgb;
+    }
+    if (light.spot_params.w >= 0.0) {
+        mask *= spot_shadow(light, light_uv, light_ndc.z);
+    }
+    return mask;
+}
+
+// The cube faces store projected depth, so the reference is the depth along the dominant axis
+float point_shadow(Light light, vec3 light_to_fragment) {
+    vec3 magnitude = abs(light_to_fragment);
+    float axis_distance = max(magnitude.x, max(magnitude.y, magnitude.z));
+    float far = light.position_radius.w;
+    float depth = far * (axis_distance - POINT_SHADOW_NEAR) / ((far - POINT_SHADOW_NEAR) * axis_distance);
+    float layer = light.spot_params.w;
+    if (shadow_filter.x == SHADOW_FILTER_HARDWARE) {
+        return texture(point_shadow_maps, vec4(light_to_fragment, layer), depth);
+    }
+
+    // Offsets are taken on the plane perpendicular to the lookup direction, one texel of a face at unit distance is 2 / size
+    vec3 direction = normalize(light_to_fragment);
+    vec3 up = abs(direction.y) < 0.99 ? vec3(0.0, 1.0, 0.0) : vec3(1.0, 0.0, 0.0);
+    vec3 tangent = normalize(cross(up, direction));
+    vec3 bitangent = cross(direction, tangent);
+    float texel = 2.0 / float(textureSize(point_shadow_maps, 0).x);
+    mat2 rotation = poisson_rotation();
+    float radius = shadow_params.x * texel;
+
+    if (shadow_filter.x == SHADOW_FILTER_PCSS) {
+        float light_size = shadow_params.y;
+        float search_radius = light_size * (axis_distance - POINT_SHADOW_NEAR) / axis_distance;
+        uint blocker_samples = clamp(shadow_filter.z, 1, MAX_SHADOW_SAMPLES);
+        float total = 0.0;
+        uint count = 0;
+        for (uint i = 0; i < blocker_samples; i++) {
+            vec2 offset = rotation * POISSON_DISK[i] * search_radius;
+            vec3 sample_direction = direction + tangent * offset.x + bitangent * offset.y;
+            float blocker = linearize_depth(texture(point_shadow_depths, vec4(sample_direction, layer)).r, POINT_SHADOW_NEAR, far);
+            if (blocker < axis_distance) {
+                total += blocker;
+                count++;
+            }
+        }
+        if (count == 0) {
+            return 1.0;
+        }
+        float blocker = total / float(count);
+        radius = max(light_size * (axis_distance - blocker) / blocker, texel);
+    }
+
+    uint samples = clamp(shadow_filter.y, 1, MAX_SHADOW_SAMPLES);
+    float lit = 0.0;
+    for (uint i = 0; i < samples; i++) {
+        vec2 offset = rotation * POISSON_DISK[i] * radius;
+        vec3 sample_direction = direction + tangent * offset.x + bitangent * offset.y;
+        lit += texture(point_shadow_maps, vec4(sample_direction, layer), depth);
+    }
+    return lit / float(samples);
+}
+
+vec3 shade_light(Light light, vec3 world_position, vec3 normal) {
+    vec3 to_light = light.position_radius.xyz - world_position;
+    float distance = length(to_light);
+    float radius = light.position_radius.w;
+    vec3 direction_to_light = to_light / distance;
+
+    // Inverse square falloff, windowed so it reaches zero at the culling radius
+    float window = clamp(1.0 - pow(distance / radius, 4.0), 0.0, 1.0);
+    float attenuation = window * window / (distance * distance + 1.0);
+
+    float lambert = max(dot(normal, direction_to_light), 0.0);
+    vec3 radiance = light.color_intensity.rgb * light.color_intensity.w * lambert * attenuation;
+
+    if (light.light_type == LIGHT_TYPE_SPOT) {
+        radiance *= spot_factor(light, world_position, direction_to_light);
+    } else if (light.spot_params.w >= 0.0) {
+        radiance *= point_shadow(light, -to_light);
+    }
+    return radiance;
+}
+
+// The directional light reaches everywhere unshadowed
+vec3 shade_sun(vec3 normal) {
+    return sun_color.rgb * max(dot(normal, sun_direction.xyz), 0.0);
+}
+
+#endif
\ No newline at end of file
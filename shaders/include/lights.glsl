@@ -0,0 +1,37 @@
+#ifndef LIGHTS_GLSL
+#define LIGHTS_GLSL
+
+// Must match the LIGHT_TYPE_* constants in lights.rs
+const uint LIGHT_TYPE_POINT = 0;
+const uint LIGHT_TYPE_SPOT = 1;
+
+// Must match GpuLight in lights.rs
+struct Light {
+    vec4 position_radius;
+    vec4 color_intensity;
+    vec4 direction_falloff;
+    // cos(inner angle), cos(outer angle), cookie layer, shadow layer
+    vec4 spot_params;
+    mat4 view_projection;
+    uint light_type;
+};
+
+// LIGHTING_SET in pipeline.rs, compute passes with a smaller layout define their own
+#ifndef LIGHTING_SET
+#define LIGHTING_SET 3
+#endif
+
+layout(std430, set = LIGHTING_SET, binding = 0) readonly buffer Lights {
+    uint light_count;
+    vec4 ambient;
+    // filter mode, filter samples, blocker search samples
+    uvec4 shadow_filter;
+    // filter radius in texels, light size
+    vec4 shadow_params;
+    // Direction towards the directional light, its color times intensity (zero without one)
+    vec4 sun_direction;
+    vec4 sun_color;
+    Light lights[];
+};
+
+#endif
\ No newline at end of file
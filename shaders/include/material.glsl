@@ -0,0 +1,29 @@
+#ifndef MATERIAL_GLSL
+#define MATERIAL_GLSL
+
+// Must match MaterialUniform in material.rs
+struct MaterialData {
+    // rgb and opacity, only transparent materials blend with the opacity
+    vec4 color;
+    // roughness, 1.0 when normal_map is the material's own, parallax depth in texture coordinates, alpha cutoff
+    vec4 params;
+    // 1.0 when material_texture is the object's color texture, how much the wind sways the vertices, unused
+    vec4 texture_params;
+    // Water materials only, see WaterMaterial::uniform in water.rs. Deep color and depth fade, wave direction (xz),
+    // wave length and height, wave speed, refraction, foam width and 1.0 for planar reflections
+    vec4 water[3];
+};
+
+// Every material's parameters, bound at MATERIAL_SET and indexed by the material index the vertex shader passes on
+layout(std430, set = 1, binding = 0) readonly buffer Materials {
+    MaterialData materials[];
+};
+
+// Depends on the material (the planar reflection for reflective ones, the lightmap for lightmapped ones, the object's
+// color texture for the rest), white when it has none
+layout(set = 1, binding = 1) uniform sampler2D material_texture;
+
+// Tangent space, a flat normal when the material has none
+layout(set = 1, binding = 2) uniform sampler2D normal_map;
+
+#endif
\ No newline at end of file
@@ -0,0 +1,12 @@
+#ifndef MOTION_GLSL
+#define MOTION_GLSL
+
+// Screen space offset since the previous frame, in uv units
+vec2 motion_vector(vec4 clip_position, vec4 previous_clip_position, vec2 jitter) {
+    // The jitter is removed so a static scene has no motion while TAA is shaking the projection
+    vec2 current = clip_position.xy / clip_position.w - jitter;
+    vec2 previous = previous_clip_position.xy / previous_clip_position.w;
+    return (current - previous) * 0.5;
+}
+
+#endif
\ No newline at end of file
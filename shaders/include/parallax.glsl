@@ -0,0 +1,54 @@
+#ifndef PARALLAX_GLSL
+#define PARALLAX_GLSL
+
+// Set by ParallaxQuality::scene_specialization, pipelines that don't set them get offset mapping
+layout(constant_id = 5) const uint PARALLAX_MODE = 1;
+layout(constant_id = 6) const uint PARALLAX_MIN_STEPS = 8;
+layout(constant_id = 7) const uint PARALLAX_MAX_STEPS = 32;
+
+const uint PARALLAX_OFF = 0;
+const uint PARALLAX_OFFSET = 1;
+const uint PARALLAX_OCCLUSION = 2;
+
+// Depth below the surface at `tex_coord`, from the height in the alpha of the height map (1.0 at the surface)
+float parallax_depth(sampler2D height_map, vec2 tex_coord, vec2 dx, vec2 dy) {
+    return 1.0 - textureGrad(height_map, tex_coord, dx, dy).a;
+}
+
+// Where the view ray hits the height field, `view` is the tangent space direction towards the camera and `scale` how
+// deep a height of 0.0 goes in texture coordinates
+vec2 parallax_tex_coord(sampler2D height_map, vec2 tex_coord, vec3 view, float scale) {
+    if (PARALLAX_MODE == PARALLAX_OFF || scale == 0.0) {
+        return tex_coord;
+    }
+
+    // Derivatives of the unshifted coordinates, the loop below would make them meaningless
+    vec2 dx = dFdx(tex_coord);
+    vec2 dy = dFdy(tex_coord);
+    if (PARALLAX_MODE == PARALLAX_OFFSET) {
+        // Without dividing by view.z, which limits the offset at grazing angles
+        return tex_coord - view.xy * parallax_depth(height_map, tex_coord, dx, dy) * scale;
+    }
+
+    float steps = mix(float(PARALLAX_MAX_STEPS), float(PARALLAX_MIN_STEPS), abs(view.z));
+    float layer_depth = 1.0 / steps;
+    vec2 step_offset = view.xy / max(view.z, 0.05) * scale * layer_depth;
+
+    vec2 current = tex_coord;
+    float depth = parallax_depth(height_map, current, dx, dy);
+    float ray_depth = 0.0;
+    for (uint i = 0; i < PARALLAX_MAX_STEPS && ray_depth < depth; i++) {
+        current -= step_offset;
+        depth = parallax_depth(height_map, current, dx, dy);
+        ray_depth += layer_depth;
+    }
+
+    // Between the last step above the height field and the first below it, where the two depth differences cross
+    vec2 previous = current + step_offset;
+    float after = depth - ray_depth;
+    float before = parallax_depth(height_map, previous, dx, dy) - (ray_depth - layer_depth);
+    float weight = after / (after - before);
+    return mix(current, previous, clamp(weight, 0.0, 1.0));
+}
+
+#endif
\ No newline at end of file
@@ -0,0 +1,78 @@
+#ifndef REFLECTION_PROBES_GLSL
+#define REFLECTION_PROBES_GLSL
+
+// Local reflections from the prefiltered probe cubes, see reflection_probes.rs
+
+#include "lights.glsl"
+
+// Must match the constants in reflection_probes.rs
+const uint PROBE_ROUGHNESS_LEVELS = 5;
+const uint PROBE_SHAPE_SPHERE = 0;
+const uint PROBE_SHAPE_BOX = 1;
+
+// Must match GpuReflectionProbe in reflection_probes.rs
+struct ReflectionProbe {
+    // xyz position, w blend distance
+    vec4 position_blend;
+    // Box half extents, or the radius in every component for spheres
+    vec4 extents;
+    // slot in the cube array, shape
+    uvec4 params;
+};
+
+layout(set = LIGHTING_SET, binding = 8) uniform samplerCubeArray reflection_probe_maps;
+
+layout(std430, set = LIGHTING_SET, binding = 9) readonly buffer ReflectionProbes {
+    uint probe_count;
+    ReflectionProbe probes[];
+};
+
+// 1 inside the volume, falling off to 0 over the blend distance towards its border
+float probe_weight(ReflectionProbe probe, vec3 world_position) {
+    vec3 local = world_position - probe.position_blend.xyz;
+    float inside = probe.params.y == PROBE_SHAPE_BOX
+        ? min(min(probe.extents.x - abs(local.x), probe.extents.y - abs(local.y)), probe.extents.z - abs(local.z))
+        : probe.extents.x - length(local);
+    return clamp(inside / max(probe.position_blend.w, 0.0001), 0.0, 1.0);
+}
+
+// Box probes intersect the reflection ray with the box, so what's reflected sits on its walls instead of at infinity
+vec3 probe_direction(ReflectionProbe probe, vec3 world_position, vec3 direction) {
+    if (probe.params.y != PROBE_SHAPE_BOX) {
+        return direction;
+    }
+
+    vec3 center = probe.position_blend.xyz;
+    vec3 first = (center + probe.extents.xyz - world_position) / direction;
+    vec3 second = (center - probe.extents.xyz - world_position) / direction;
+    vec3 furthest = max(first, second);
+    float distance = min(min(furthest.x, furthest.y), furthest.z);
+    return world_position + direction * distance - center;
+}
+
+vec3 sample_probe(ReflectionProbe probe, vec3 direction, float roughness) {
+    float level = clamp(roughness, 0.0, 1.0) * float(PROBE_ROUGHNESS_LEVELS - 1);
+    float lower = floor(level);
+    float upper = min(lower + 1.0, float(PROBE_ROUGHNESS_LEVELS - 1));
+    float first_cube = float(probe.params.x * PROBE_ROUGHNESS_LEVELS);
+    vec3 lower_color = texture(reflection_probe_maps, vec4(direction, first_cube + lower)).rgb;
+    vec3 upper_color = texture(reflection_probe_maps, vec4(direction, first_cube + upper)).rgb;
+    return mix(lower_color, upper_color, level - lower);
+}
+
+// Blend of the probes covering the position, the coverage in alpha is 0 outside of every probe
+vec4 reflection_probe_radiance(vec3 world_position, vec3 direction, float roughness) {
+    vec3 radiance = vec3(0.0);
+    float total_weight = 0.0;
+    for (uint i = 0; i < probe_count; i++) {
+        float weight = probe_weight(probes[i], world_position);
+        if (weight > 0.0) {
+            radiance += sample_probe(probes[i], probe_direction(probes[i], world_position, direction), roughness) * weight;
+            total_weight += weight;
+        }
+    }
+
+    return vec4(radiance / max(total_weight, 1.0), min(total_weight, 1.0));
+}
+
+#endif
\ No newline at end of file
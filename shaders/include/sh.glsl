@@ -0,0 +1,22 @@
+#ifndef SH_GLSL
+#define SH_GLSL
+
+// Must match SH_COEFFICIENTS in light_probes.rs
+const uint SH_COEFFICIENTS = 9;
+
+// Real L2 spherical harmonics for a unit direction
+float[SH_COEFFICIENTS] sh_basis(vec3 n) {
+    return float[SH_COEFFICIENTS](
+        0.282095,
+        0.488603 * n.y,
+        0.488603 * n.z,
+        0.488603 * n.x,
+        1.092548 * n.x * n.y,
+        1.092548 * n.y * n.z,
+        0.315392 * (3.0 * n.z * n.z - 1.0),
+        1.092548 * n.x * n.z,
+        0.546274 * (n.x * n.x - n.y * n.y)
+    );
+}
+
+#endif
\ No newline at end of file
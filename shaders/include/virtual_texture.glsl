@@ -0,0 +1,56 @@
+#ifndef VIRTUAL_TEXTURE_GLSL
+#define VIRTUAL_TEXTURE_GLSL
+
+// The virtual texture at VIRTUAL_TEXTURE_SET, see virtual_texture.rs. Pages are PAGE_SIZE texels square and sit in the
+// cache with PAGE_BORDER texels of their neighbours around them.
+
+const uint PAGE_SIZE = 128;
+const uint PAGE_BORDER = 4;
+const uint SLOT_SIZE = PAGE_SIZE + 2 * PAGE_BORDER;
+
+// Pages across level 0, levels and slots across the cache, then an entry for every page of every level, finest level
+// first: the x and y of the slot holding it or its closest resident ancestor in the low two bytes, that page's level in
+// the third
+layout(std430, set = 4, binding = 0) readonly buffer PageTable {
+    uvec4 page_table_info;
+    uint page_table[];
+};
+layout(set = 4, binding = 1) uniform sampler2D page_cache;
+// Indexed like the page table entries, the feedback pass sets the pages it saw to 1
+layout(std430, set = 4, binding = 2) buffer PageRequests {
+    uint page_requests[];
+};
+
+// Level sampled about once per pixel, `lod_bias` levels coarser
+uint virtual_level(vec2 tex_coord, float lod_bias) {
+    vec2 texels = tex_coord * float(page_table_info.x * PAGE_SIZE);
+    vec2 dx = dFdx(texels);
+    vec2 dy = dFdy(texels);
+    float lod = 0.5 * log2(max(dot(dx, dx), dot(dy, dy))) + lod_bias;
+    return min(uint(max(lod, 0.0)), page_table_info.y - 1);
+}
+
+// The page table entry of the page `tex_coord` is on at `level`, the texture repeats
+uint virtual_entry(vec2 tex_coord, uint level) {
+    uint offset = 0;
+    for (uint finer = 0; finer < level; finer++) {
+        uint finer_pages = page_table_info.x >> finer;
+        offset += finer_pages * finer_pages;
+    }
+    uint pages = page_table_info.x >> level;
+    uvec2 page = min(uvec2(fract(tex_coord) * float(pages)), uvec2(pages - 1));
+    return offset + page.y * pages + page.x;
+}
+
+// Bilinear within the finest resident page, without blending between levels
+vec4 sample_virtual(vec2 tex_coord) {
+    uint entry = page_table[virtual_entry(tex_coord, virtual_level(tex_coord, 0.0))];
+    uvec2 slot = uvec2(entry & 0xff, (entry >> 8) & 0xff);
+    uint pages = page_table_info.x >> (entry >> 16);
+
+    vec2 in_page = fract(fract(tex_coord) * float(pages));
+    vec2 texel = vec2(slot * SLOT_SIZE + PAGE_BORDER) + in_page * float(PAGE_SIZE);
+    return textureLod(page_cache, texel / float(page_table_info.z * SLOT_SIZE), 0.0);
+}
+
+#endif
@@ -0,0 +1,40 @@
+#ifndef WATER_GLSL
+#define WATER_GLSL
+
+// Waves of water materials, shared by the water's vertex and fragment shaders. Include after material.glsl.
+
+const uint WAVE_COUNT = 4;
+// Every wave turns away from the material's direction by its angle and is shorter and lower by its scale
+const float WAVE_ANGLES[WAVE_COUNT] = float[](0.0, 0.55, -0.4, 1.05);
+const float WAVE_SCALES[WAVE_COUNT] = float[](1.0, 0.62, 0.38, 0.24);
+
+// Sum of Gerstner waves: offset of the calm surface point at `position` (world xz) and the surface normal there.
+// Steepness is spread over the waves so the crests never loop over themselves.
+vec3 water_waves(MaterialData material, vec2 position, float time, out vec3 normal) {
+    vec2 direction = material.water[1].xy;
+    float wave_length = material.water[1].z;
+    float height = material.water[1].w;
+    float speed = material.water[2].x;
+
+    vec3 offset = vec3(0.0);
+    normal = vec3(0.0, 1.0, 0.0);
+    for (uint i = 0; i < WAVE_COUNT; i++) {
+        float angle = WAVE_ANGLES[i];
+        vec2 wave_direction = mat2(cos(angle), sin(angle), -sin(angle), cos(angle)) * direction;
+        float frequency = 6.2831853 / (wave_length * WAVE_SCALES[i]);
+        float amplitude = height * WAVE_SCALES[i];
+        float steepness = min(0.8 / (frequency * amplitude * float(WAVE_COUNT) + 0.0001), 1.0);
+
+        float phase = frequency * (dot(wave_direction, position) - speed * time);
+        float c = cos(phase);
+        float s = sin(phase);
+        offset += vec3(steepness * amplitude * wave_direction.x * c, amplitude * s, steepness * amplitude * wave_direction.y * c);
+        normal -= vec3(wave_direction.x * frequency * amplitude * c, steepness * frequency * amplitude * s,
+            wave_direction.y * frequency * amplitude * c);
+    }
+
+    normal = normalize(normal);
+    return offset;
+}
+
+#endif
\ No newline at end of file
@@ -0,0 +1,24 @@
+#ifndef WIND_GLSL
+#define WIND_GLSL
+
+// Wind of the camera block, see Wind in wind.rs. Include after camera.glsl.
+
+// How far a vertex at `world_position` is pushed by the wind, `sway` scales the bend of the vertex
+vec3 wind_offset(vec3 world_position, float sway) {
+    if (sway <= 0.0) {
+        return vec3(0.0);
+    }
+
+    vec2 direction = camera.wind.xy;
+    float time = camera.time.x;
+    // Gusts roll over the scene along the wind, every plant flutters a little out of step with its neighbours
+    float gust = 0.5 + 0.5 * sin((dot(world_position.xz, direction) - time * camera.wind_gusts.y) * 6.2831853 / camera.wind_gusts.x);
+    float flutter = sin(time * camera.wind_gusts.z + dot(world_position, vec3(1.3, 0.7, 1.7)));
+    float bend = (camera.wind.z + camera.wind.w * gust) * (1.0 + 0.3 * flutter) * sway;
+
+    // Bending over lowers the vertex a little so the plant doesn't look stretched
+    vec2 horizontal = direction * bend;
+    return vec3(horizontal.x, -0.5 * dot(horizontal, horizontal), horizontal.y);
+}
+
+#endif
\ No newline at end of file
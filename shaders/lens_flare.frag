@@ -0,0 +1,27 @@
+#version 450
+
+layout(location = 0) in vec2 in_offset;
+layout(location = 1) in vec3 in_color;
+layout(location = 2) flat in uint in_shape;
+
+layout(location = 0) out vec4 out_color;
+
+// Must match the constants in lens_flare.vert
+const uint FLARE_GLOW = 0;
+const uint FLARE_GHOST = 1;
+
+void main() {
+    float radius = length(in_offset);
+    float shape;
+    if (in_shape == FLARE_GLOW) {
+        shape = pow(max(1.0 - radius, 0.0), 4.0);
+    } else if (in_shape == FLARE_GHOST) {
+        // Soft disc, a little brighter towards its rim like the reflection of an aperture
+        shape = (1.0 - smoothstep(0.8, 1.0, radius)) * mix(0.5, 1.0, radius) * 0.3;
+    } else {
+        // Faint ring
+        shape = smoothstep(0.8, 0.9, radius) * (1.0 - smoothstep(0.9, 1.0, radius)) * 0.2;
+    }
+
+    out_color = vec4(in_color * shape, 1.0);
+}
\ No newline at end of file
@@ -0,0 +1,91 @@
+#version 450
+
+layout(location = 0) out vec2 out_offset;
+layout(location = 1) out vec3 out_color;
+layout(location = 2) flat out uint out_shape;
+
+layout(set = 0, binding = 2) uniform sampler2D scene_depth;
+
+#define CAMERA_SET 1
+#include "include/camera.glsl"
+
+// Must match FlarePushConstants in lens_flare.rs
+layout(push_constant) uniform Push {
+    // World position of the light (w = 1.0) or the direction towards it (w = 0.0)
+    vec4 position;
+    // Color times intensity of the light, size of the flare
+    vec4 color;
+    // Radius of the occlusion test in uv units
+    vec4 params;
+} push;
+
+// Must match the constants in lens_flare.frag
+const uint FLARE_GLOW = 0;
+const uint FLARE_GHOST = 1;
+const uint FLARE_HALO = 2;
+
+// One instance per element, along the line from the light through the center of the screen: 1.0 is on the light,
+// negative ones are mirrored to the other side
+const uint FLARE_ELEMENTS = 7;
+const float ELEMENT_POSITIONS[FLARE_ELEMENTS] = float[](1.0, 0.55, 0.3, -0.2, -0.45, -0.7, -1.0);
+// In units of half the screen's height
+const float ELEMENT_SIZES[FLARE_ELEMENTS] = float[](0.3, 0.04, 0.07, 0.05, 0.12, 0.03, 0.45);
+const vec3 ELEMENT_TINTS[FLARE_ELEMENTS] = vec3[](
+    vec3(1.0), vec3(0.5, 0.6, 1.0), vec3(0.4, 1.0, 0.5), vec3(1.0, 0.6, 0.3), vec3(0.4, 0.5, 1.0), vec3(1.0, 0.4, 0.6),
+    vec3(0.3, 0.3, 0.4)
+);
+const uint ELEMENT_SHAPES[FLARE_ELEMENTS] = uint[](FLARE_GLOW, FLARE_GHOST, FLARE_GHOST, FLARE_GHOST, FLARE_GHOST, FLARE_GHOST,
+    FLARE_HALO);
+
+const vec2 CORNERS[6] = vec2[](vec2(-1.0, -1.0), vec2(1.0, -1.0), vec2(1.0, 1.0), vec2(-1.0, -1.0), vec2(1.0, 1.0), vec2(-1.0, 1.0));
+
+// Grid of depth samples around the light
+const int OCCLUSION_GRID = 4;
+
+// Share of the area around the light that nothing in the scene is in front of
+float light_visibility(vec2 uv, float depth, float aspect_ratio) {
+    float visible = 0.0;
+    for (int x = 0; x < OCCLUSION_GRID; x++) {
+        for (int y = 0; y < OCCLUSION_GRID; y++) {
+            vec2 offset = ((vec2(x, y) + 0.5) / float(OCCLUSION_GRID) * 2.0 - 1.0) * push.params.x * vec2(1.0 / aspect_ratio, 1.0);
+            vec2 sample_uv = uv + offset;
+            bool on_screen = all(greaterThanEqual(sample_uv, vec2(0.0))) && all(lessThanEqual(sample_uv, vec2(1.0)));
+            if (on_screen && texture(scene_depth, sample_uv).r >= depth) {
+                visible += 1.0;
+            }
+        }
+    }
+    return visible / float(OCCLUSION_GRID * OCCLUSION_GRID);
+}
+
+void main() {
+    uint element = gl_InstanceIndex;
+    vec2 corner = CORNERS[gl_VertexIndex];
+    out_offset = corner;
+    out_shape = ELEMENT_SHAPES[element];
+
+    vec4 clip_position = camera.projection * camera.view * push.position;
+    float visibility = 0.0;
+    vec2 light = vec2(0.0);
+    float aspect_ratio = camera.viewport.x / camera.viewport.y;
+    if (clip_position.w > 0.0) {
+        vec3 ndc = clip_position.xyz / clip_position.w;
+        light = ndc.xy;
+        // Only the empty sky lets through the light of a directional light
+        float depth = push.position.w == 0.0 ? 1.0 : ndc.z;
+        // Fade out as the light leaves the screen instead of popping
+        vec2 edge = clamp(vec2(1.0) - abs(light), 0.0, 1.0);
+        visibility = light_visibility(light * 0.5 + 0.5, depth, aspect_ratio) * clamp((edge.x + edge.y) * 2.0, 0.0, 1.0);
+    }
+
+    if (visibility <= 0.0) {
+        // Outside of the clip volume, nothing is rasterized
+        gl_Position = vec4(2.0, 2.0, 2.0, 1.0);
+        out_color = vec3(0.0);
+        return;
+    }
+
+    float size = ELEMENT_SIZES[element] * push.color.w;
+    gl_Position = vec4(light * ELEMENT_POSITIONS[element] + corner * size * vec2(1.0 / aspect_ratio, 1.0), 0.0, 1.0);
+    out_color = push.color.rgb * ELEMENT_TINTS[element] * visibility;
+}
\ No newline at end of file
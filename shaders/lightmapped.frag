@@ -0,0 +1,28 @@
+#version 450
+
+layout (location = 0) in vec3 in_normal;
+layout (location = 4) in vec4 in_clip_position;
+layout (location = 5) in vec4 in_previous_clip_position;
+layout (location = 6) in vec2 in_lightmap_uv;
+layout (location = 7) flat in uint in_object_index;
+layout (location = 8) flat in uint in_material_index;
+
+layout (location = 0) out vec4 color;
+layout (location = 1) out vec4 normal_roughness;
+layout (location = 2) out vec2 motion;
+layout (location = 3) out uint object_id;
+
+#include "include/camera.glsl"
+#include "include/material.glsl"
+#include "include/motion.glsl"
+
+void main() {
+    MaterialData material = materials[in_material_index];
+    // The material texture is the object's lightmap, which holds all of its static lighting, direct and bounced
+    vec3 lighting = texture(material_texture, in_lightmap_uv).rgb;
+
+    color = vec4(material.color.rgb * lighting, 1.0);
+    normal_roughness = vec4(normalize(in_normal), material.params.x);
+    motion = motion_vector(in_clip_position, in_previous_clip_position, camera.jitter.xy);
+    object_id = in_object_index + 1;
+}
\ No newline at end of file
@@ -0,0 +1,37 @@
+#version 450
+
+layout(location = 0) in vec2 in_uv;
+
+layout(location = 0) out vec4 out_color;
+
+layout(set = 0, binding = 0) uniform sampler2D scene_color;
+layout(set = 0, binding = 3) uniform sampler2D scene_motion;
+
+layout(push_constant) uniform Push {
+    float shutter;
+    int samples;
+    float max_length;
+} push;
+
+void main() {
+    // Motion is the uv offset from the previous frame to this one
+    vec2 velocity = texture(scene_motion, in_uv).xy * push.shutter;
+    float speed = length(velocity);
+    if (speed > push.max_length) {
+        velocity *= push.max_length / speed;
+    }
+
+    vec3 color = texture(scene_color, in_uv).rgb;
+    if (speed < 0.0001 || push.samples < 2) {
+        out_color = vec4(color, 1.0);
+        return;
+    }
+
+    // Samples are centered on the pixel so the blur covers both halves of the shutter interval
+    for (int i = 0; i < push.samples; i++) {
+        float t = float(i) / float(push.samples - 1) - 0.5;
+        color += texture(scene_color, in_uv + velocity * t).rgb;
+    }
+
+    out_color = vec4(color / float(push.samples + 1), 1.0);
+}
\ No newline at end of file
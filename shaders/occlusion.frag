@@ -0,0 +1,5 @@
+#version 450
+
+// Occlusion queries only count the samples passing the depth test, nothing is written
+void main() {
+}
\ No newline at end of file
@@ -0,0 +1,34 @@
+#version 450
+
+#ifndef BOUNDS
+layout(location = 0) in vec3 in_position;
+#endif
+
+#include "include/camera.glsl"
+
+layout(push_constant) uniform Push {
+    mat4 model;
+} push;
+
+#ifdef BOUNDS
+// Two triangles for every face of the cube from -1 to 1, corner i has its x, y and z in bits 0, 1 and 2
+const uint CUBE_INDICES[36] = uint[](
+    0, 2, 6, 0, 6, 4,
+    1, 3, 7, 1, 7, 5,
+    0, 1, 5, 0, 5, 4,
+    2, 3, 7, 2, 7, 6,
+    0, 1, 3, 0, 3, 2,
+    4, 5, 7, 4, 7, 6
+);
+#endif
+
+void main() {
+#ifdef BOUNDS
+    uint corner = CUBE_INDICES[gl_VertexIndex];
+    vec3 position = vec3(corner & 1u, (corner >> 1) & 1u, (corner >> 2) & 1u) * 2.0 - 1.0;
+#else
+    vec3 position = in_position;
+#endif
+
+    gl_Position = camera.projection * camera.view * push.model * vec4(position, 1.0);
+}
\ No newline at end of file
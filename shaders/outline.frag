@@ -0,0 +1,21 @@
+#version 450
+
+layout(location = 0) out vec4 color;
+layout(location = 1) out vec4 normal_roughness;
+layout(location = 2) out vec2 motion;
+layout(location = 3) out uint object_id;
+
+layout(push_constant) uniform Push {
+    mat4 model;
+    vec4 color;
+    float width;
+} push;
+
+void main() {
+    color = push.color;
+    // Fully rough so screen space reflections skip the outline
+    normal_roughness = vec4(0.0, 0.0, 1.0, 1.0);
+    motion = vec2(0.0);
+    // Not pickable, clicks on the outline select nothing
+    object_id = 0;
+}
\ No newline at end of file
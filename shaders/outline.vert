@@ -0,0 +1,25 @@
+#version 450
+
+layout(location = 0) in vec3 in_position;
+
+#include "include/camera.glsl"
+
+layout(push_constant) uniform Push {
+    mat4 model;
+    vec4 color;
+    float width;
+} push;
+
+void main() {
+    mat4 view_projection = camera.projection * camera.view;
+    vec4 clip_position = view_projection * push.model * vec4(in_position, 1.0);
+    vec4 clip_center = view_projection * push.model * vec4(0.0, 0.0, 0.0, 1.0);
+
+    // Pushes every vertex `width` pixels away from the projected origin, which also grows flat meshes seen face on
+    vec2 direction = clip_position.xy / clip_position.w - clip_center.xy / clip_center.w;
+    if (clip_center.w > 0.0 && length(direction) > 0.00001) {
+        clip_position.xy += normalize(direction) * push.width * 2.0 * camera.viewport.zw * clip_position.w;
+    }
+
+    gl_Position = clip_position;
+}
\ No newline at end of file
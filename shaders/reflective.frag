@@ -0,0 +1,31 @@
+#version 450
+
+layout (location = 0) in vec3 in_normal;
+layout (location = 4) in vec4 in_clip_position;
+layout (location = 5) in vec4 in_previous_clip_position;
+layout (location = 7) flat in uint in_object_index;
+layout (location = 8) flat in uint in_material_index;
+
+layout (location = 0) out vec4 color;
+layout (location = 1) out vec4 normal_roughness;
+layout (location = 2) out vec2 motion;
+layout (location = 3) out uint object_id;
+
+#include "include/camera.glsl"
+#include "include/material.glsl"
+#include "include/motion.glsl"
+
+void main() {
+    MaterialData material = materials[in_material_index];
+    // The reflection was rendered from the mirrored camera with the same projection into the same part of
+    // a target as large as this one, so the surface samples it at its own screen position
+    vec2 screen_uv = gl_FragCoord.xy / vec2(textureSize(material_texture, 0));
+    vec3 reflected = texture(material_texture, screen_uv).rgb;
+
+    color = vec4(reflected * material.color.rgb, 1.0);
+
+    // Already reflective, keep screen space reflections off this surface
+    normal_roughness = vec4(normalize(in_normal), 1.0);
+    motion = motion_vector(in_clip_position, in_previous_clip_position, camera.jitter.xy);
+    object_id = in_object_index + 1;
+}
\ No newline at end of file
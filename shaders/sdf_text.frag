@@ -0,0 +1,57 @@
+#version 450
+
+layout(location = 0) in vec2 in_uv;
+layout(location = 1) flat in vec4 in_cell;
+
+layout(location = 0) out vec4 out_color;
+
+// Distances to the outline, 0.5 on it and higher inside, or an icon's colors
+layout(set = 0, binding = 0) uniform sampler2D font_atlas;
+
+layout(push_constant) uniform Push {
+    vec4 color;
+    vec4 outline_color;
+    vec4 shadow_color;
+    vec2 shadow_offset;
+    vec2 screen_size;
+    float outline_width;
+    float shadow_softness;
+    uint quad_base;
+    uint layer;
+} push;
+
+// Text is drawn in layers, every glyph's shadow first, then the outlines and the fills, so no glyph covers its neighbours
+const uint LAYER_SHADOW = 0u;
+const uint LAYER_OUTLINE = 1u;
+const uint LAYER_FILL = 2u;
+const uint LAYER_ICON = 3u;
+
+// Coverage of everything at least `edge` inside, antialiased over about a pixel whatever the scale
+float coverage(float distance, float edge, float softness) {
+    float width = max(fwidth(distance) * 0.5, 1e-4) + softness;
+    return smoothstep(edge - width, edge + width, distance);
+}
+
+void main() {
+    float alpha;
+    vec4 color;
+    if (push.layer == LAYER_SHADOW) {
+        vec2 uv = clamp(in_uv - push.shadow_offset, in_cell.xy, in_cell.zw);
+        alpha = coverage(texture(font_atlas, uv).r, 0.5 - push.outline_width, push.shadow_softness);
+        color = push.shadow_color;
+    } else if (push.layer == LAYER_OUTLINE) {
+        alpha = coverage(texture(font_atlas, in_uv).r, 0.5 - push.outline_width, 0.0);
+        color = push.outline_color;
+    } else if (push.layer == LAYER_FILL) {
+        alpha = coverage(texture(font_atlas, in_uv).r, 0.5, 0.0);
+        color = push.color;
+    } else {
+        // Straight alpha images, the color tints them
+        alpha = 1.0;
+        color = texture(font_atlas, in_uv) * push.color;
+    }
+
+    // Blended premultiplied like the UI, colors are gamma encoded and written as they are
+    alpha *= color.a;
+    out_color = vec4(color.rgb * alpha, alpha);
+}
\ No newline at end of file
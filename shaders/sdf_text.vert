@@ -0,0 +1,37 @@
+#version 450
+
+layout(location = 0) out vec2 out_uv;
+// The glyph's cell in the atlas, shadows don't sample past it into the neighbouring glyphs
+layout(location = 1) flat out vec4 out_cell;
+
+// Quads are 8 floats: top left and bottom right in pixels, then the same corners in the atlas
+layout(std430, set = 1, binding = 0) readonly buffer Quads {
+    float quads[];
+};
+
+layout(push_constant) uniform Push {
+    vec4 color;
+    vec4 outline_color;
+    vec4 shadow_color;
+    vec2 shadow_offset;
+    vec2 screen_size;
+    float outline_width;
+    float shadow_softness;
+    // Word offset of the frame's quads, the upload ring is bound as a whole
+    uint quad_base;
+    uint layer;
+} push;
+
+const vec2 CORNERS[6] = vec2[](vec2(0.0, 0.0), vec2(1.0, 0.0), vec2(0.0, 1.0), vec2(0.0, 1.0), vec2(1.0, 0.0), vec2(1.0, 1.0));
+
+void main() {
+    // Six vertices per quad without an index buffer, firstVertex points at the run's first quad
+    uint base = push.quad_base + uint(gl_VertexIndex / 6) * 8u;
+    vec2 corner = CORNERS[gl_VertexIndex % 6];
+    vec4 rect = vec4(quads[base], quads[base + 1], quads[base + 2], quads[base + 3]);
+    vec4 cell = vec4(quads[base + 4], quads[base + 5], quads[base + 6], quads[base + 7]);
+
+    out_uv = mix(cell.xy, cell.zw, corner);
+    out_cell = cell;
+    gl_Position = vec4(2.0 * mix(rect.xy, rect.zw, corner) / push.screen_size - 1.0, 0.0, 1.0);
+}
\ No newline at end of file
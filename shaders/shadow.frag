@@ -0,0 +1,5 @@
+#version 450
+
+// Depth only, nothing to shade
+void main() {
+}
\ No newline at end of file
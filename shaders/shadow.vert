@@ -0,0 +1,12 @@
+#version 450
+
+layout(location = 0) in vec3 in_position;
+
+layout(push_constant) uniform Push {
+    mat4 light_view_projection;
+    mat4 model;
+} push;
+
+void main() {
+    gl_Position = push.light_view_projection * push.model * vec4(in_position, 1.0);
+}
\ No newline at end of file
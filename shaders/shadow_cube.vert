@@ -0,0 +1,36 @@
+#version 450
+#extension GL_EXT_multiview : require
+
+// All six faces of a point light's shadow cube in one multiview pass, gl_ViewIndex is the face
+layout(location = 0) in vec3 in_position;
+
+layout(push_constant) uniform Push {
+    mat4 model;
+    // xyz position, w radius (the far plane)
+    vec4 light_position_radius;
+} push;
+
+// Must match POINT_SHADOW_NEAR in shadows.rs
+const float POINT_SHADOW_NEAR = 0.05;
+
+// Must match cube_face_views in cubemap.rs, in the +X, -X, +Y, -Y, +Z, -Z order of cube array layers
+const vec3 FACE_FORWARD[6] = vec3[](
+    vec3(1.0, 0.0, 0.0), vec3(-1.0, 0.0, 0.0), vec3(0.0, 1.0, 0.0),
+    vec3(0.0, -1.0, 0.0), vec3(0.0, 0.0, 1.0), vec3(0.0, 0.0, -1.0)
+);
+const vec3 FACE_UP[6] = vec3[](
+    vec3(0.0, -1.0, 0.0), vec3(0.0, -1.0, 0.0), vec3(0.0, 0.0, 1.0),
+    vec3(0.0, 0.0, -1.0), vec3(0.0, -1.0, 0.0), vec3(0.0, -1.0, 0.0)
+);
+
+void main() {
+    // The look_at view followed by cube_face_projection, without building either matrix
+    vec3 forward = FACE_FORWARD[gl_ViewIndex];
+    vec3 side = normalize(cross(forward, FACE_UP[gl_ViewIndex]));
+    vec3 up = cross(side, forward);
+
+    vec3 relative = (push.model * vec4(in_position, 1.0)).xyz - push.light_position_radius.xyz;
+    float far = push.light_position_radius.w;
+    float depth = dot(forward, relative);
+    gl_Position = vec4(dot(side, relative), dot(up, relative), far * (depth - POINT_SHADOW_NEAR) / (far - POINT_SHADOW_NEAR), depth);
+}
@@ -0,0 +1,53 @@
+#version 450
+
+layout(location = 0) in vec2 in_uv;
+
+layout (location = 0) out vec4 color;
+layout (location = 1) out vec4 normal_roughness;
+layout (location = 2) out vec2 motion;
+layout (location = 3) out uint object_id;
+
+#include "include/camera.glsl"
+#include "include/atmosphere.glsl"
+#include "include/motion.glsl"
+
+// This frame's clouds, see clouds.frag
+layout(set = 1, binding = 0) uniform sampler2D clouds;
+
+// Must match SkyPushConstants in atmosphere.rs
+layout(push_constant) uniform Push {
+    AtmosphereParams atmosphere;
+    // Whether to blend in the clouds
+    uint clouds;
+} push;
+
+// Must match CLOUD_DOWNSCALE in clouds.rs
+const float CLOUD_DOWNSCALE = 2.0;
+
+// Cosine of the sun disk's angular radius, a little larger than the real sun's so TAA doesn't lose it
+const float SUN_DISK_COS = 0.99996;
+// Radiance of the disk relative to the sun's intensity
+const float SUN_DISK_BRIGHTNESS = 20.0;
+
+void main() {
+    // Only the rotation of the view applies to the sky, it's infinitely far away
+    vec4 view_position = camera.inverse_projection * vec4(in_uv * 2.0 - 1.0, 1.0, 1.0);
+    vec3 direction = normalize(transpose(mat3(camera.view)) * (view_position.xyz / view_position.w));
+
+    vec3 sky = sky_radiance(push.atmosphere, direction);
+    float sun = smoothstep(SUN_DISK_COS, mix(SUN_DISK_COS, 1.0, 0.3), dot(direction, push.atmosphere.sun.xyz));
+    if (sun > 0.0) {
+        sky += sun * SUN_DISK_BRIGHTNESS * push.atmosphere.sun.w * atmosphere_transmittance(push.atmosphere, direction);
+    }
+
+    if (push.clouds != 0) {
+        vec4 cloud = texture(clouds, gl_FragCoord.xy / (CLOUD_DOWNSCALE * vec2(textureSize(clouds, 0))));
+        sky = sky * cloud.a + cloud.rgb;
+    }
+
+    color = vec4(sky, 1.0);
+    normal_roughness = vec4(0.0);
+    vec4 clip_position = camera.projection * camera.view * vec4(direction, 0.0);
+    motion = motion_vector(clip_position, camera.previous_view_projection * vec4(direction, 0.0), camera.jitter.xy);
+    object_id = 0;
+}
\ No newline at end of file
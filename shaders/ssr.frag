@@ -0,0 +1,97 @@
+#version 450
+
+layout(location = 0) in vec2 in_uv;
+
+layout(location = 0) out vec4 out_color;
+
+layout(set = 0, binding = 0) uniform sampler2D scene_color;
+layout(set = 0, binding = 1) uniform sampler2D scene_normal;
+layout(set = 0, binding = 2) uniform sampler2D scene_depth;
+
+#define CAMERA_SET 1
+#include "include/camera.glsl"
+
+layout(push_constant) uniform Push {
+    vec4 environment_top;
+    vec4 environment_bottom;
+    float max_distance;
+    float thickness;
+    int max_steps;
+    float blur_radius;
+    float max_roughness;
+} push;
+
+const float GOLDEN_ANGLE = 2.39996323;
+const int BLUR_TAPS = 8;
+
+vec3 view_position(vec2 uv, float depth) {
+    vec4 view = camera.inverse_projection * vec4(uv * 2.0 - 1.0, depth, 1.0);
+    return view.xyz / view.w;
+}
+
+vec2 project(vec3 position) {
+    vec4 clip = camera.projection * vec4(position, 1.0);
+    return (clip.xy / clip.w) * 0.5 + 0.5;
+}
+
+// Used when the ray leaves the screen or never hits anything
+vec3 environment(vec3 view_direction) {
+    vec3 world_direction = transpose(mat3(camera.view)) * view_direction;
+    return mix(push.environment_bottom.rgb, push.environment_top.rgb, world_direction.y * 0.5 + 0.5);
+}
+
+// Rougher surfaces get a wider gather around the hit point
+vec3 blurred(vec2 uv, float roughness) {
+    float radius = roughness * push.blur_radius;
+    vec3 sum = texture(scene_color, uv).rgb;
+    for (int i = 0; i < BLUR_TAPS; i++) {
+        float r = sqrt(float(i + 1) / float(BLUR_TAPS)) * radius;
+        float theta = float(i) * GOLDEN_ANGLE;
+        vec2 offset = vec2(cos(theta), sin(theta)) * r * camera.viewport.zw;
+        sum += texture(scene_color, uv + offset).rgb;
+    }
+    return sum / float(BLUR_TAPS + 1);
+}
+
+void main() {
+    vec3 color = texture(scene_color, in_uv).rgb;
+    vec4 normal_roughness = texture(scene_normal, in_uv);
+    float depth = texture(scene_depth, in_uv).r;
+    float roughness = normal_roughness.w;
+
+    if (depth >= 1.0 || roughness >= push.max_roughness) {
+        out_color = vec4(color, 1.0);
+        return;
+    }
+
+    vec3 position = view_position(in_uv, depth);
+    vec3 normal = normalize(normal_roughness.xyz);
+    vec3 view_direction = normalize(position);
+    vec3 ray = reflect(view_direction, normal);
+
+    vec3 reflection = environment(ray);
+
+    float step_size = push.max_distance / float(push.max_steps);
+    vec3 sample_position = position + normal * 0.01;
+    for (int i = 0; i < push.max_steps; i++) {
+        sample_position += ray * step_size;
+        vec2 sample_uv = project(sample_position);
+        if (any(lessThan(sample_uv, vec2(0.0))) || any(greaterThan(sample_uv, vec2(1.0)))) {
+            break;
+        }
+
+        // View space looks down -z, so the ray is behind the surface once it is further away than it
+        float surface_z = view_position(sample_uv, texture(scene_depth, sample_uv).r).z;
+        float delta = surface_z - sample_position.z;
+        if (delta > 0.0 && delta < push.thickness) {
+            vec2 edge = smoothstep(vec2(0.0), vec2(0.1), sample_uv) * (1.0 - smoothstep(vec2(0.9), vec2(1.0), sample_uv));
+            reflection = mix(reflection, blurred(sample_uv, roughness), edge.x * edge.y);
+            break;
+        }
+    }
+
+    float fresnel = 0.04 + 0.96 * pow(1.0 - max(dot(-view_direction, normal), 0.0), 5.0);
+    float strength = fresnel * (1.0 - roughness / push.max_roughness);
+
+    out_color = vec4(mix(color, reflection, strength), 1.0);
+}
\ No newline at end of file
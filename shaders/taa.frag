@@ -0,0 +1,43 @@
+#version 450
+
+layout(location = 0) in vec2 in_uv;
+
+layout(location = 0) out vec4 out_color;
+
+layout(set = 0, binding = 0) uniform sampler2D scene_color;
+layout(set = 0, binding = 3) uniform sampler2D scene_motion;
+
+#define CAMERA_SET 1
+#include "include/camera.glsl"
+
+layout(set = 2, binding = 0) uniform sampler2D history;
+
+layout(push_constant) uniform Push {
+    float blend;
+    uint reset;
+} push;
+
+void main() {
+    vec3 current = texture(scene_color, in_uv).rgb;
+
+    // Clamping the history to the current 3x3 neighborhood rejects most of the ghosting from disocclusion
+    vec3 neighborhood_min = current;
+    vec3 neighborhood_max = current;
+    for (int x = -1; x <= 1; x++) {
+        for (int y = -1; y <= 1; y++) {
+            vec3 neighbor = texture(scene_color, in_uv + vec2(x, y) * camera.viewport.zw).rgb;
+            neighborhood_min = min(neighborhood_min, neighbor);
+            neighborhood_max = max(neighborhood_max, neighbor);
+        }
+    }
+
+    vec2 history_uv = in_uv - texture(scene_motion, in_uv).xy;
+    bool offscreen = any(lessThan(history_uv, vec2(0.0))) || any(greaterThan(history_uv, vec2(1.0)));
+    if (push.reset != 0 || offscreen) {
+        out_color = vec4(current, 1.0);
+        return;
+    }
+
+    vec3 previous = clamp(texture(history, history_uv).rgb, neighborhood_min, neighborhood_max);
+    out_color = vec4(mix(previous, current, push.blend), 1.0);
+}
\ No newline at end of file
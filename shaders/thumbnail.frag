@@ -0,0 +1,48 @@
+#version 450
+
+layout(location = 0) in vec3 in_normal;
+
+layout(location = 0) out vec4 out_color;
+
+// Must match ThumbnailPushConstants in thumbnail.rs
+layout(push_constant) uniform Push {
+    mat4 view_projection;
+    vec4 color_roughness;
+    vec4 view_direction;
+} push;
+
+// Studio setup around the fixed thumbnail camera: a warm key light from the upper left, a cool and dim fill from the
+// right and a rim light from behind that separates the silhouette from the transparent background
+const vec3 KEY_DIRECTION = vec3(-0.2, 0.8, 0.6);
+const vec3 KEY_COLOR = vec3(1.0, 0.95, 0.85) * 1.2;
+const vec3 FILL_DIRECTION = vec3(0.9, 0.2, 0.3);
+const vec3 FILL_COLOR = vec3(0.55, 0.65, 0.8) * 0.4;
+const vec3 RIM_DIRECTION = vec3(-0.4, 0.5, -0.8);
+const vec3 RIM_COLOR = vec3(1.0) * 0.6;
+const vec3 AMBIENT = vec3(0.12, 0.12, 0.14);
+
+vec3 light(vec3 normal, vec3 view, vec3 direction, vec3 color, vec3 albedo, float roughness) {
+    vec3 l = normalize(direction);
+    float diffuse = max(dot(normal, l), 0.0);
+    float shininess = mix(128.0, 4.0, roughness);
+    float specular = pow(max(dot(normal, normalize(l + view)), 0.0), shininess) * (1.0 - roughness) * 0.5;
+    return color * (albedo * diffuse + specular * step(0.0, dot(normal, l)));
+}
+
+void main() {
+    vec3 normal = normalize(in_normal);
+    vec3 view = normalize(push.view_direction.xyz);
+    // Open meshes show their back faces, lit like the front
+    normal = dot(normal, view) < 0.0 ? -normal : normal;
+    vec3 albedo = push.color_roughness.rgb;
+    float roughness = clamp(push.color_roughness.a, 0.0, 1.0);
+
+    vec3 color = AMBIENT * albedo
+        + light(normal, view, KEY_DIRECTION, KEY_COLOR, albedo, roughness)
+        + light(normal, view, FILL_DIRECTION, FILL_COLOR, albedo, roughness);
+    // Strongest where the surface turns away from the camera
+    float rim = pow(1.0 - max(dot(normal, view), 0.0), 3.0) * max(dot(normal, normalize(RIM_DIRECTION)), 0.0);
+    color += RIM_COLOR * rim;
+
+    out_color = vec4(color, 1.0);
+}
@@ -0,0 +1,19 @@
+#version 450
+
+layout(location = 0) in vec3 in_position;
+layout(location = 2) in vec3 in_normal;
+
+layout(location = 0) out vec3 out_normal;
+
+// Must match ThumbnailPushConstants in thumbnail.rs
+layout(push_constant) uniform Push {
+    mat4 view_projection;
+    vec4 color_roughness;
+    vec4 view_direction;
+} push;
+
+void main() {
+    // The framing only translates and scales uniformly, the normals stay as they are
+    out_normal = in_normal;
+    gl_Position = push.view_projection * vec4(in_position, 1.0);
+}
@@ -0,0 +1,13 @@
+#version 450
+
+layout(location = 0) in vec2 in_uv;
+layout(location = 1) in vec4 in_color;
+
+layout(location = 0) out vec4 out_color;
+
+layout(set = 0, binding = 0) uniform sampler2D ui_texture;
+
+void main() {
+    // Colors and textures are premultiplied and gamma encoded, blended as is into the UNORM swapchain image
+    out_color = in_color * texture(ui_texture, in_uv);
+}
\ No newline at end of file
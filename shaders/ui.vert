@@ -0,0 +1,30 @@
+#version 450
+
+layout(location = 0) out vec2 out_uv;
+layout(location = 1) out vec4 out_color;
+
+// egui vertices are 20 bytes (pos, uv, packed RGBA8 color), read as floats since they don't fit a std430 struct
+layout(std430, set = 1, binding = 0) readonly buffer Vertices {
+    float vertices[];
+};
+
+layout(std430, set = 1, binding = 1) readonly buffer Indices {
+    uint indices[];
+};
+
+layout(push_constant) uniform Push {
+    vec2 screen_size;
+    uint vertex_offset;
+    // Word offset of the frame's vertices, the upload ring is bound as a whole
+    uint vertex_base;
+} push;
+
+void main() {
+    // Drawn without an index buffer, firstVertex points at the mesh's first index
+    uint base = push.vertex_base + (push.vertex_offset + indices[gl_VertexIndex]) * 5;
+    vec2 position = vec2(vertices[base], vertices[base + 1]);
+
+    out_uv = vec2(vertices[base + 2], vertices[base + 3]);
+    out_color = unpackUnorm4x8(floatBitsToUint(vertices[base + 4]));
+    gl_Position = vec4(2.0 * position / push.screen_size - 1.0, 0.0, 1.0);
+}
\ No newline at end of file
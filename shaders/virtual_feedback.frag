@@ -0,0 +1,13 @@
+#version 450
+
+layout (location = 9) in vec2 in_tex_coord;
+
+#include "include/virtual_texture.glsl"
+
+// FEEDBACK_SCALE in virtual_texture.rs, the texture coordinates change that much faster per feedback pixel
+const float FEEDBACK_SCALE = 8.0;
+
+// Depth tested like the scene, so only pages of visible surfaces are asked for
+void main() {
+    page_requests[virtual_entry(in_tex_coord, virtual_level(in_tex_coord, -log2(FEEDBACK_SCALE)))] = 1u;
+}
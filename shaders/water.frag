@@ -0,0 +1,105 @@
+#version 450
+
+layout (location = 0) in vec3 in_normal;
+layout (location = 1) in vec3 in_world_position;
+layout (location = 2) in vec3 in_world_normal;
+layout (location = 3) in float in_view_depth;
+layout (location = 4) in vec4 in_clip_position;
+layout (location = 5) in vec4 in_previous_clip_position;
+layout (location = 7) flat in uint in_object_index;
+layout (location = 8) flat in uint in_material_index;
+
+layout (location = 0) out vec4 color;
+layout (location = 1) out vec4 normal_roughness;
+layout (location = 2) out vec2 motion;
+layout (location = 3) out uint object_id;
+
+#include "include/camera.glsl"
+#include "include/lighting.glsl"
+#include "include/cluster_lighting.glsl"
+#include "include/light_probes.glsl"
+#include "include/material.glsl"
+#include "include/reflection_probes.glsl"
+#include "include/motion.glsl"
+#include "include/water.glsl"
+
+// WATER_SET in water.rs, the scene as it was before the water pass
+layout(set = 4, binding = 0) uniform sampler2D scene_color;
+layout(set = 4, binding = 1) uniform sampler2D scene_depth;
+
+// What screen space reflections see, the ripples are in the normal
+const float WATER_ROUGHNESS = 0.05;
+// How much the normal map's ripples bend the waves' normal
+const float RIPPLE_STRENGTH = 0.4;
+
+// Distance along the view axis of what's at `depth` in the depth buffer
+float view_distance(float depth) {
+    vec4 view = camera.inverse_projection * vec4(0.0, 0.0, depth, 1.0);
+    return -view.z / view.w;
+}
+
+// The waves' normal at the pixel, with the normal map's ripples scrolling along at two scales when there is one
+vec3 water_normal(MaterialData material) {
+    vec3 normal;
+    water_waves(material, in_world_position.xz, camera.time.x, normal);
+    if (material.params.y == 0.0) {
+        return normal;
+    }
+
+    float wave_length = material.water[1].z;
+    vec2 scroll = material.water[1].xy * material.water[2].x * camera.time.x / wave_length;
+    vec2 tex_coord = in_world_position.xz / wave_length;
+    vec2 ripples = texture(normal_map, tex_coord + scroll * 0.5).xy + texture(normal_map, tex_coord * 2.3 - scroll * 0.3).xy - 1.0;
+    return normalize(normal + vec3(ripples.x, 0.0, ripples.y) * RIPPLE_STRENGTH);
+}
+
+void main() {
+    MaterialData material = materials[in_material_index];
+    vec3 normal = water_normal(material);
+    vec3 view_direction = normalize(camera.position.xyz - in_world_position);
+    vec3 lighting = probe_ambient(in_world_position, normal, ambient.rgb) + direct_lighting(in_world_position, normal, in_view_depth);
+
+    // How far the view travels through the water before it hits what's below
+    vec2 screen_uv = gl_FragCoord.xy / vec2(textureSize(scene_color, 0));
+    float floor_distance = view_distance(texture(scene_depth, screen_uv).r);
+    float water_depth = max(floor_distance - in_view_depth, 0.0);
+
+    // The waves bend the view into the water, less so in the shallows where the shore would smear. Things in front of
+    // the water at the bent position would be pulled into it, those keep the straight view.
+    float refraction = material.water[2].y;
+    vec2 refracted_uv = screen_uv + normal.xz * refraction * min(water_depth, 1.0);
+    float refracted_distance = view_distance(texture(scene_depth, refracted_uv).r);
+    if (refracted_distance < in_view_depth) {
+        refracted_uv = screen_uv;
+        refracted_distance = floor_distance;
+    }
+    vec3 below = texture(scene_color, refracted_uv).rgb * material.color.rgb;
+    float fade = 1.0 - exp(-max(refracted_distance - in_view_depth, 0.0) / material.water[0].w);
+    below = mix(below, material.water[0].rgb * lighting, fade);
+
+    // The planar reflection was rendered for this view's part of a target as large as the scene's, like reflective.frag
+    // samples it. Screen space reflections are added by their post effect, the probes fill in what's off screen.
+    bool planar = material.water[2].w != 0.0;
+    vec3 reflection;
+    if (planar) {
+        vec2 reflection_uv = gl_FragCoord.xy / vec2(textureSize(material_texture, 0)) + normal.xz * refraction;
+        reflection = texture(material_texture, reflection_uv).rgb;
+    } else {
+        vec4 probe = reflection_probe_radiance(in_world_position, reflect(-view_direction, normal), WATER_ROUGHNESS);
+        reflection = mix(ambient.rgb, probe.rgb, probe.a);
+    }
+    float fresnel = 0.02 + 0.98 * pow(1.0 - max(dot(normal, view_direction), 0.0), 5.0);
+
+    // Foam where the floor comes up to the surface, broken up by a pattern drifting with the time
+    float foam_width = material.water[2].z;
+    float foam = foam_width > 0.0 ? 1.0 - smoothstep(0.0, foam_width, water_depth) : 0.0;
+    float time = camera.time.x;
+    float pattern = sin(dot(in_world_position.xz, vec2(7.1, 5.3)) + time * 2.0) * sin(dot(in_world_position.xz, vec2(-4.7, 8.9)) - time * 1.3);
+    foam *= smoothstep(0.3, 0.7, 0.5 + 0.5 * pattern + foam * 0.5);
+
+    color = vec4(mix(mix(below, reflection, fresnel), lighting, foam), 1.0);
+    // Planar reflections keep screen space reflections off the surface like reflective.frag does
+    normal_roughness = vec4(normalize(mat3(camera.view) * normal), planar ? 1.0 : WATER_ROUGHNESS);
+    motion = motion_vector(in_clip_position, in_previous_clip_position, camera.jitter.xy);
+    object_id = in_object_index + 1;
+}
\ No newline at end of file
@@ -0,0 +1,103 @@
+// The engine as an Android activity, started by the `ANativeActivity_onCreate` entry point in examples/android.rs.
+// Package and run it with `cargo apk run --example android`, the meshes of the APK's assets are put into the scene.
+
+use std::path::PathBuf;
+
+use winit::event::{Event, WindowEvent};
+use winit::event_loop::ControlFlow;
+
+use crate::assets::{AssetKind, AssetManager};
+use crate::touch::{self, Gesture, TouchInput};
+use crate::vulkan::camera_controller::{CameraController, OrbitController};
+use crate::vulkan::lights::PointLight;
+use crate::vulkan::renderer::{RendererSettings, VulkanRenderer};
+use crate::vulkan::window::{FullscreenMode, VulkanWindow, WindowBackend, WindowStyle};
+
+const WINDOW_TITLE: &str = "Reverie";
+
+// The native window only exists between `Resumed` and `Suspended`, so the renderer is created on the first resume and
+// gives up its surface whenever the app goes to the background
+pub fn run() {
+    let (event_loop, mut window) = VulkanWindow::create_window(WINDOW_TITLE, 0, 0, FullscreenMode::Windowed, WindowStyle::default(), true,
+        WindowBackend::Auto)
+        .expect("Failed to create the window!");
+
+    let mut renderer: Option<VulkanRenderer> = None;
+    let mut controller: Option<CameraController> = None;
+    let mut touch_input = TouchInput::default();
+
+    event_loop.run(move |event, _, controlflow| match event {
+        Event::Resumed => {
+            let size = window.window.inner_size();
+            window.width = size.width;
+            window.height = size.height;
+
+            match &mut renderer {
+                Some(renderer) => renderer.resume(&window).expect("Failed to resume rendering!"),
+                None => {
+                    let created = create_renderer(&window);
+                    controller = Some(CameraController::Orbit(OrbitController::from_camera(&created.camera)));
+                    renderer = Some(created);
+                }
+            }
+        }
+        Event::Suspended => {
+            if let Some(renderer) = &mut renderer {
+                renderer.suspend().expect("Failed to suspend rendering!");
+            }
+        }
+        Event::WindowEvent { event, .. } => match (event, &mut renderer, &mut controller) {
+            (WindowEvent::CloseRequested, _, _) => *controlflow = ControlFlow::Exit,
+            (WindowEvent::Resized(size), Some(renderer), _) => renderer.resize(size.width, size.height),
+            (WindowEvent::Touch(touch), Some(renderer), Some(controller)) => match touch_input.handle(&touch) {
+                Some(Gesture::Tap { x, y }) => {
+                    renderer.selected = renderer.pick(x as u32, y as u32).expect("Failed to pick object!");
+                }
+                Some(gesture) => {
+                    touch::apply_gesture(controller, &renderer.camera, gesture, renderer.viewport.rect.extent.height as f32);
+                }
+                None => {}
+            },
+            _ => {}
+        },
+        Event::MainEventsCleared => window.window.request_redraw(),
+        Event::RedrawRequested(_) => {
+            if let (Some(renderer), Some(controller)) = (&mut renderer, &controller) {
+                if renderer.is_suspended() {
+                    return;
+                }
+                controller.apply(&mut renderer.camera);
+
+                renderer.ui.begin_frame(renderer.swapchain.extent);
+                renderer.end_ui_frame()
+                    .expect("Failed to finish the UI frame!");
+                renderer.fill_commandbuffers()
+                    .expect("Failed to write commands!");
+                renderer.draw_frame();
+            }
+        }
+        _ => {}
+    });
+}
+
+fn create_renderer(window: &VulkanWindow) -> VulkanRenderer {
+    // The validation layers aren't packaged into the APK
+    let settings = RendererSettings {
+        validation: false,
+        ..Default::default()
+    };
+    let mut renderer = VulkanRenderer::new(window, &settings).expect("Failed to create the renderer!");
+    renderer.camera.position = uv::Vec3::new(0.0, 1.5, 3.0);
+    renderer.lights.push(PointLight::new(uv::Vec3::new(0.5, 2.0, 1.0), uv::Vec3::one(), 6.0, 8.0));
+
+    let mut assets = AssetManager::new(PathBuf::new());
+    for index in 0..assets.assets.len() {
+        if assets.assets[index].kind == AssetKind::Mesh {
+            if let Err(error) = assets.instantiate(&mut renderer, index, uv::Vec3::zero()) {
+                tracing::error!("Failed to load {}: {}", assets.assets[index].name(), error);
+            }
+        }
+    }
+
+    renderer
+}
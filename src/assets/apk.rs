@@ -0,0 +1,33 @@
+// Assets packaged into the APK, on Android they take the place of the files under the asset root. Paths are relative to
+// the APK's asset directory.
+
+use std::ffi::CString;
+use std::io::{Error, ErrorKind, Read};
+use std::path::{Path, PathBuf};
+
+fn c_path(path: &Path) -> std::io::Result<CString> {
+    let path = path.to_str().ok_or_else(|| Error::new(ErrorKind::InvalidInput, "Asset path isn't valid UTF-8"))?;
+    CString::new(path.trim_start_matches("./")).map_err(|error| Error::new(ErrorKind::InvalidInput, error))
+}
+
+pub fn read(path: &Path) -> std::io::Result<Vec<u8>> {
+    let mut asset = ndk_glue::native_activity()
+        .asset_manager()
+        .open(&c_path(path)?)
+        .ok_or_else(|| Error::new(ErrorKind::NotFound, format!("{} isn't packaged into the APK", path.display())))?;
+    let mut bytes = vec![];
+    asset.read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+// The NDK only lists the files of a directory, subdirectories have to be named in the asset root to be found
+pub fn collect_files(directory: &Path, paths: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    let entries = ndk_glue::native_activity()
+        .asset_manager()
+        .open_dir(&c_path(directory)?)
+        .ok_or_else(|| Error::new(ErrorKind::NotFound, format!("{} isn't packaged into the APK", directory.display())))?;
+    for entry in entries {
+        paths.push(directory.join(entry.to_string_lossy().as_ref()));
+    }
+    Ok(())
+}
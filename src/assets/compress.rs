@@ -0,0 +1,491 @@
+// Block compression of textures at load time, so source art kept as PNGs doesn't take RGBA8 sized room on the device.
+// Color goes to BC7 (mode 6, one RGBA line per 4x4 block), normal maps without a height in their alpha to BC5, which
+// keeps x and y and lets the shaders rebuild z. Every level of the mip chain is encoded on the job pool, and the result
+// is cached on disk keyed by the texels, so a texture is only compressed once.
+
+use std::path::Path;
+
+use ash::vk;
+use rayon::prelude::*;
+
+use crate::jobs;
+use crate::vulkan::texture_streaming::MipChain;
+
+// Bumped whenever the encoders change, so stale cache entries are never read
+const ENCODER_VERSION: u32 = 1;
+const CACHE_MAGIC: &[u8; 4] = b"RVBC";
+
+// Interpolation weights of BC7's 4 bit indices, out of 64
+const BC7_WEIGHTS: [u32; 16] = [0, 4, 9, 13, 17, 21, 26, 30, 34, 38, 43, 47, 51, 55, 60, 64];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextureUsage {
+    Color,
+    // Tangent space normals, with the parallax height in the alpha where it isn't 255 everywhere
+    NormalMap,
+}
+
+// BC7 unless it's a normal map without a height to keep
+pub fn format_for(usage: TextureUsage, rgba: &[u8]) -> vk::Format {
+    match usage {
+        TextureUsage::NormalMap if rgba.chunks(4).all(|texel| texel[3] == 255) => vk::Format::BC5_UNORM_BLOCK,
+        _ => vk::Format::BC7_UNORM_BLOCK
+    }
+}
+
+// The mip chain of the texture in the format `format_for` picks, read from `cache` when it was compressed before and
+// written there otherwise. Failing to use the cache only costs the time to compress again.
+pub fn load_or_compress(cache: Option<&Path>, extent: vk::Extent2D, rgba: &[u8], usage: TextureUsage) -> MipChain {
+    let format = format_for(usage, rgba);
+    let path = cache.map(|cache| cache.join(format!("{:016x}.bcn", cache_key(extent, format, rgba))));
+
+    if let Some(path) = &path {
+        match read_cached(path, extent, format) {
+            Ok(Some(chain)) => return chain,
+            Ok(None) => {}
+            Err(error) => tracing::warn!("Failed to read compressed texture {}: {}", path.display(), error)
+        }
+    }
+
+    let start = std::time::Instant::now();
+    let chain = compress(&MipChain::from_rgba8(extent, rgba), format);
+    tracing::debug!("Compressed a {}x{} texture to {:?} in {:.1?}", extent.width, extent.height, format, start.elapsed());
+
+    if let Some(path) = &path {
+        if let Err(error) = write_cached(path, &chain) {
+            tracing::warn!("Failed to cache compressed texture {}: {}", path.display(), error);
+        }
+    }
+    chain
+}
+
+// Every level of an RGBA8 chain in `format`, BC7_UNORM_BLOCK or BC5_UNORM_BLOCK
+pub fn compress(chain: &MipChain, format: vk::Format) -> MipChain {
+    assert_eq!(chain.format, vk::Format::R8G8B8A8_UNORM, "Only RGBA8 mip chains can be compressed!");
+    let encode: fn(&[[u8; 4]; 16]) -> [u8; 16] = match format {
+        vk::Format::BC7_UNORM_BLOCK => encode_bc7,
+        vk::Format::BC5_UNORM_BLOCK => encode_bc5,
+        _ => panic!("Can't compress to {:?}!", format)
+    };
+
+    let levels = jobs::pool().install(|| (0..chain.level_count())
+        .map(|level| {
+            let extent = chain.level_extent(level);
+            let texels = &chain.levels[level as usize];
+            (0..extent.height.div_ceil(4))
+                .into_par_iter()
+                .flat_map_iter(|block_y| (0..extent.width.div_ceil(4)).flat_map(move |block_x| encode(&block(texels, extent, block_x, block_y))))
+                .collect()
+        })
+        .collect());
+
+    MipChain::from_levels(chain.extent, format, levels)
+}
+
+// The 4x4 texels of a block, repeating the last row and column where the level ends inside it
+fn block(texels: &[u8], extent: vk::Extent2D, block_x: u32, block_y: u32) -> [[u8; 4]; 16] {
+    let mut block = [[0; 4]; 16];
+    for (index, texel) in block.iter_mut().enumerate() {
+        let x = (block_x * 4 + index as u32 % 4).min(extent.width - 1);
+        let y = (block_y * 4 + index as u32 / 4).min(extent.height - 1);
+        let offset = ((y * extent.width + x) * 4) as usize;
+        texel.copy_from_slice(&texels[offset..offset + 4]);
+    }
+    block
+}
+
+// Bits of a block from the lowest up
+struct BitWriter {
+    bits: u128,
+    position: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, value: u32, count: u32) {
+        self.bits |= (value as u128) << self.position;
+        self.position += count;
+    }
+}
+
+// Mode 6: the endpoints of the line through the block's colors along their principal axis, 7 bits per channel plus a
+// p-bit shared by the channels of each endpoint
+fn encode_bc7(texels: &[[u8; 4]; 16]) -> [u8; 16] {
+    let (low, high) = principal_endpoints(texels);
+    let mut endpoints = [quantize_bc7_endpoint(low), quantize_bc7_endpoint(high)];
+    let palette = |endpoints: [([u32; 4], u32); 2]| -> [[u32; 4]; 16] {
+        let [first, second] = endpoints.map(|(color, p_bit)| color.map(|channel| channel << 1 | p_bit));
+        BC7_WEIGHTS.map(|weight| [0, 1, 2, 3].map(|channel| ((64 - weight) * first[channel] + weight * second[channel] + 32) >> 6))
+    };
+
+    let colors = palette(endpoints);
+    let mut indices = texels.map(|texel| nearest(&colors, texel));
+    // The first index has its top bit implied 0, swapping the endpoints mirrors the indices
+    if indices[0] >= 8 {
+        endpoints.swap(0, 1);
+        indices = indices.map(|index| 15 - index);
+    }
+
+    let mut writer = BitWriter { bits: 0, position: 0 };
+    writer.write(1 << 6, 7);
+    for (first, second) in endpoints[0].0.into_iter().zip(endpoints[1].0) {
+        writer.write(first, 7);
+        writer.write(second, 7);
+    }
+    writer.write(endpoints[0].1, 1);
+    writer.write(endpoints[1].1, 1);
+    for (position, &index) in indices.iter().enumerate() {
+        writer.write(index as u32, if position == 0 { 3 } else { 4 });
+    }
+    writer.bits.to_le_bytes()
+}
+
+// Ends of the texels' extent along the axis they vary most in, found by power iteration on their covariance
+fn principal_endpoints(texels: &[[u8; 4]; 16]) -> ([f32; 4], [f32; 4]) {
+    let points = texels.map(|texel| texel.map(f32::from));
+    let mut mean = [0.0f32; 4];
+    for point in &points {
+        for (sum, value) in mean.iter_mut().zip(point) {
+            *sum += value / 16.0;
+        }
+    }
+
+    let mut covariance = [[0.0f32; 4]; 4];
+    for point in &points {
+        let offset = [0, 1, 2, 3].map(|channel| point[channel] - mean[channel]);
+        for (row, covariance_row) in covariance.iter_mut().enumerate() {
+            for (column, value) in covariance_row.iter_mut().enumerate() {
+                *value += offset[row] * offset[column];
+            }
+        }
+    }
+
+    let mut axis = [1.0f32, 1.0, 1.0, 0.25];
+    for _ in 0..8 {
+        let next: [f32; 4] = [0, 1, 2, 3].map(|row| (0..4).map(|column| covariance[row][column] * axis[column]).sum());
+        let length = next.iter().map(|value| value * value).sum::<f32>().sqrt();
+        if length < 1e-6 {
+            // All texels are the same color
+            return (mean, mean);
+        }
+        axis = next.map(|value| value / length);
+    }
+
+    let projections = points.map(|point| (0..4).map(|channel| (point[channel] - mean[channel]) * axis[channel]).sum::<f32>());
+    let min = projections.iter().copied().fold(f32::INFINITY, f32::min);
+    let max = projections.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+    let along = |distance: f32| [0, 1, 2, 3].map(|channel| (mean[channel] + axis[channel] * distance).clamp(0.0, 255.0));
+    (along(min), along(max))
+}
+
+// 7 bit channels and the p-bit that together come closest to `color`
+fn quantize_bc7_endpoint(color: [f32; 4]) -> ([u32; 4], u32) {
+    [0, 1]
+        .map(|p_bit| {
+            let quantized = color.map(|channel| ((channel - p_bit as f32) / 2.0).round().clamp(0.0, 127.0) as u32);
+            let error: f32 = (0..4).map(|channel| ((quantized[channel] << 1 | p_bit) as f32 - color[channel]).powi(2)).sum();
+            ((quantized, p_bit), error)
+        })
+        .into_iter()
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(endpoint, _)| endpoint)
+        .unwrap()
+}
+
+fn nearest(colors: &[[u32; 4]; 16], texel: [u8; 4]) -> u8 {
+    let error = |color: &[u32; 4]| -> u32 { (0..4).map(|channel| (color[channel] as i32 - texel[channel] as i32).pow(2) as u32).sum() };
+    (0..16).min_by_key(|&index| error(&colors[index])).unwrap() as u8
+}
+
+// Red and green as two BC4 blocks
+fn encode_bc5(texels: &[[u8; 4]; 16]) -> [u8; 16] {
+    let mut result = [0; 16];
+    result[..8].copy_from_slice(&encode_bc4(texels.map(|texel| texel[0])));
+    result[8..].copy_from_slice(&encode_bc4(texels.map(|texel| texel[1])));
+    result
+}
+
+// The block's extremes as endpoints with six values between them, 3 bit indices
+fn encode_bc4(values: [u8; 16]) -> [u8; 8] {
+    let high = *values.iter().max().unwrap() as u32;
+    let low = *values.iter().min().unwrap() as u32;
+    // Index 0 is `high`, 1 `low` and 2 to 7 step from one to the other
+    let palette: [u32; 8] = [0, 1, 2, 3, 4, 5, 6, 7].map(|index| match index {
+        0 => high,
+        1 => low,
+        _ => ((8 - index) * high + (index - 1) * low) / 7
+    });
+
+    let mut bits = high as u64 | (low as u64) << 8;
+    for (position, &value) in values.iter().enumerate() {
+        let index = (0..8).min_by_key(|&index| (palette[index] as i32 - value as i32).abs()).unwrap();
+        bits |= (index as u64) << (16 + 3 * position);
+    }
+    bits.to_le_bytes()
+}
+
+// FNV-1a of the texels, their size and format and the encoder version
+fn cache_key(extent: vk::Extent2D, format: vk::Format, rgba: &[u8]) -> u64 {
+    let header = [extent.width, extent.height, format.as_raw() as u32, ENCODER_VERSION];
+    header
+        .iter()
+        .flat_map(|value| value.to_le_bytes())
+        .chain(rgba.iter().copied())
+        .fold(0xcbf29ce484222325, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3))
+}
+
+// The magic, version, format, extent and level count as u32s, then every level's length and bytes.
+// `None` when there's no entry or one from another encoder version.
+fn read_cached(path: &Path, extent: vk::Extent2D, format: vk::Format) -> std::io::Result<Option<MipChain>> {
+    let data = match std::fs::read(path) {
+        Ok(data) => data,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(error) => return Err(error)
+    };
+
+    let mut reader = Reader { data: &data, offset: 0 };
+    if reader.bytes(4)? != CACHE_MAGIC {
+        return Err(corrupt());
+    }
+    let header = [reader.u32()?, reader.u32()?, reader.u32()?, reader.u32()?];
+    if header != [ENCODER_VERSION, format.as_raw() as u32, extent.width, extent.height] {
+        return Ok(None);
+    }
+
+    let level_count = reader.u32()?;
+    if level_count != extent.width.max(extent.height).ilog2() + 1 {
+        return Err(corrupt());
+    }
+    let mut levels = Vec::with_capacity(level_count as usize);
+    for _ in 0..level_count {
+        let length = reader.u32()? as usize;
+        levels.push(reader.bytes(length)?.to_vec());
+    }
+
+    Ok(Some(MipChain::from_levels(extent, format, levels)))
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, length: usize) -> std::io::Result<&'a [u8]> {
+        let bytes = self.data.get(self.offset..self.offset + length).ok_or_else(corrupt)?;
+        self.offset += length;
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> std::io::Result<u32> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+}
+
+fn corrupt() -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, "Truncated or corrupt cache entry")
+}
+
+// Written next to the entry and renamed over it, a run that stops halfway leaves no broken entry behind
+fn write_cached(path: &Path, chain: &MipChain) -> std::io::Result<()> {
+    if let Some(directory) = path.parent() {
+        std::fs::create_dir_all(directory)?;
+    }
+
+    let mut data = CACHE_MAGIC.to_vec();
+    for value in [ENCODER_VERSION, chain.format.as_raw() as u32, chain.extent.width, chain.extent.height, chain.level_count()] {
+        data.extend_from_slice(&value.to_le_bytes());
+    }
+    for level in &chain.levels {
+        data.extend_from_slice(&(level.len() as u32).to_le_bytes());
+        data.extend_from_slice(level);
+    }
+
+    let temporary = path.with_extension("tmp");
+    std::fs::write(&temporary, data)?;
+    std::fs::rename(temporary, path)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Bits of a block from the lowest up, as the BC7 and BC4 layouts list them
+    struct BitReader {
+        bits: u128,
+        position: u32,
+    }
+
+    impl BitReader {
+        fn read(&mut self, count: u32) -> u32 {
+            let value = (self.bits >> self.position) as u32 & ((1u64 << count) - 1) as u32;
+            self.position += count;
+            value
+        }
+    }
+
+    fn decode_bc7(block: [u8; 16]) -> [[u8; 4]; 16] {
+        let mut reader = BitReader { bits: u128::from_le_bytes(block), position: 0 };
+        assert_eq!(reader.read(7), 1 << 6, "Not a mode 6 block");
+        let mut channels = [[0; 2]; 4];
+        for channel in &mut channels {
+            *channel = [reader.read(7), reader.read(7)];
+        }
+        let p_bits = [reader.read(1), reader.read(1)];
+        let endpoints = [0, 1].map(|endpoint| channels.map(|channel| channel[endpoint] << 1 | p_bits[endpoint]));
+
+        // The anchor index is a bit shorter
+        let mut texels = [[0; 4]; 16];
+        for (position, texel) in texels.iter_mut().enumerate() {
+            let weight = BC7_WEIGHTS[reader.read(if position == 0 { 3 } else { 4 }) as usize];
+            *texel = [0, 1, 2, 3].map(|channel| (((64 - weight) * endpoints[0][channel] + weight * endpoints[1][channel] + 32) >> 6) as u8);
+        }
+        assert_eq!(reader.position, 128);
+        texels
+    }
+
+    fn decode_bc4(block: [u8; 8]) -> [u8; 16] {
+        let mut bits = [0; 16];
+        bits[..8].copy_from_slice(&block);
+        let mut reader = BitReader { bits: u128::from_le_bytes(bits), position: 0 };
+        let (first, second) = (reader.read(8), reader.read(8));
+        let value = |index: u32| match (index, first > second) {
+            (0, _) => first,
+            (1, _) => second,
+            (_, true) => ((8 - index) * first + (index - 1) * second) / 7,
+            (6, false) => 0,
+            (7, false) => 255,
+            (_, false) => ((6 - index) * first + (index - 1) * second) / 5
+        };
+        [(); 16].map(|_| value(reader.read(3)) as u8)
+    }
+
+    fn max_error(texels: &[[u8; 4]; 16], decoded: &[[u8; 4]; 16]) -> u8 {
+        texels.iter().flatten().zip(decoded.iter().flatten()).map(|(a, b)| a.abs_diff(*b)).max().unwrap()
+    }
+
+    fn gradient(reversed: bool) -> [[u8; 4]; 16] {
+        let mut texels = [[0; 4]; 16];
+        for (index, texel) in texels.iter_mut().enumerate() {
+            let step = match reversed {
+                true => 15 - index as u8,
+                false => index as u8
+            };
+            *texel = [40 + step * 12, 200 - step * 8, 90, 255 - step * 4];
+        }
+        texels
+    }
+
+    #[test]
+    fn bc7_solid_block() {
+        for color in [[200, 100, 50, 255], [0, 0, 0, 0], [255, 255, 255, 255], [17, 128, 3, 64]] {
+            let texels = [color; 16];
+            assert!(max_error(&texels, &decode_bc7(encode_bc7(&texels))) <= 1, "{:?}", color);
+        }
+    }
+
+    #[test]
+    fn bc7_two_color_block_is_exact() {
+        // Even channels at one end and odd ones at the other, which only come out exact with each endpoint's own p-bit
+        let texels: [[u8; 4]; 16] = std::array::from_fn(|index| match index % 3 {
+            0 => [10, 20, 30, 40],
+            _ => [201, 211, 221, 231]
+        });
+        assert_eq!(decode_bc7(encode_bc7(&texels)), texels);
+    }
+
+    #[test]
+    fn bc7_gradient_block() {
+        // The reversed gradient starts at the far end, which swaps the endpoints to keep the anchor index's top bit 0
+        for reversed in [false, true] {
+            let texels = gradient(reversed);
+            assert!(max_error(&texels, &decode_bc7(encode_bc7(&texels))) <= 4, "reversed: {}", reversed);
+        }
+    }
+
+    #[test]
+    fn bc4_solid_block() {
+        for value in [0, 77, 255] {
+            assert_eq!(decode_bc4(encode_bc4([value; 16])), [value; 16]);
+        }
+    }
+
+    #[test]
+    fn bc4_gradient_block() {
+        let values: [u8; 16] = std::array::from_fn(|index| 30 + index as u8 * 13);
+        let decoded = decode_bc4(encode_bc4(values));
+        // Eight values over a range of 195 are 28 apart
+        assert!(values.iter().zip(decoded).all(|(value, decoded)| value.abs_diff(decoded) <= 14));
+        assert_eq!((decoded[0], decoded[15]), (30, 225));
+    }
+
+    #[test]
+    fn bc5_keeps_red_and_green() {
+        let texels = gradient(false);
+        let block = encode_bc5(&texels);
+        let (red, green) = (decode_bc4(block[..8].try_into().unwrap()), decode_bc4(block[8..].try_into().unwrap()));
+        for (texel, (red, green)) in texels.iter().zip(red.into_iter().zip(green)) {
+            assert!(texel[0].abs_diff(red) <= 14 && texel[1].abs_diff(green) <= 14);
+        }
+    }
+
+    #[test]
+    fn edge_blocks_repeat_the_last_row_and_column() {
+        // 3x2, texel (x, y) is [x, y, 0, 255]
+        let texels: Vec<u8> = (0..2).flat_map(|y| (0..3).flat_map(move |x| [x, y, 0, 255])).collect();
+        let block = block(&texels, vk::Extent2D { width: 3, height: 2 }, 0, 0);
+        for (index, texel) in block.iter().enumerate() {
+            assert_eq!(*texel, [(index % 4).min(2) as u8, (index / 4).min(1) as u8, 0, 255]);
+        }
+    }
+
+    #[test]
+    fn levels_under_a_block_take_a_whole_one() {
+        let extent = vk::Extent2D { width: 6, height: 3 };
+        let rgba: Vec<u8> = (0..6 * 3).flat_map(|_| [10, 20, 30, 255]).collect();
+        let chain = compress(&MipChain::from_rgba8(extent, &rgba), vk::Format::BC7_UNORM_BLOCK);
+
+        // 6x3, 3x1 and 1x1
+        let lengths: Vec<usize> = chain.levels.iter().map(|level| level.len()).collect();
+        assert_eq!(lengths, vec![2 * 16, 16, 16]);
+        for level in &chain.levels {
+            for block in level.chunks(16) {
+                assert!(max_error(&[[10, 20, 30, 255]; 16], &decode_bc7(block.try_into().unwrap())) <= 1);
+            }
+        }
+    }
+
+    #[test]
+    fn cached_chain_is_read_back() {
+        let cache = std::env::temp_dir().join(format!("reverie-texture-cache-{}", std::process::id()));
+        let extent = vk::Extent2D { width: 8, height: 8 };
+        let rgba: Vec<u8> = (0..64u8).flat_map(|index| [index * 4, 255 - index * 2, index, 255]).collect();
+
+        let compressed = load_or_compress(Some(&cache), extent, &rgba, TextureUsage::Color);
+        let path = cache.join(format!("{:016x}.bcn", cache_key(extent, vk::Format::BC7_UNORM_BLOCK, &rgba)));
+        let cached = read_cached(&path, extent, vk::Format::BC7_UNORM_BLOCK).unwrap().unwrap();
+        assert_eq!(cached.levels, compressed.levels);
+        assert_eq!(load_or_compress(Some(&cache), extent, &rgba, TextureUsage::Color).levels, compressed.levels);
+
+        // An entry for another format or size isn't used, a cut short one is an error
+        assert!(read_cached(&path, extent, vk::Format::BC5_UNORM_BLOCK).unwrap().is_none());
+        assert!(read_cached(&path, vk::Extent2D { width: 8, height: 4 }, vk::Format::BC7_UNORM_BLOCK).unwrap().is_none());
+        let data = std::fs::read(&path).unwrap();
+        std::fs::write(&path, &data[..data.len() - 5]).unwrap();
+        assert!(read_cached(&path, extent, vk::Format::BC7_UNORM_BLOCK).is_err());
+        std::fs::remove_dir_all(&cache).unwrap();
+    }
+
+    #[test]
+    fn cache_key_covers_texels_size_and_format() {
+        let extent = vk::Extent2D { width: 2, height: 2 };
+        let rgba = [7; 16];
+        let key = cache_key(extent, vk::Format::BC7_UNORM_BLOCK, &rgba);
+        assert_eq!(key, cache_key(extent, vk::Format::BC7_UNORM_BLOCK, &rgba));
+        assert_ne!(key, cache_key(extent, vk::Format::BC5_UNORM_BLOCK, &rgba));
+        assert_ne!(key, cache_key(vk::Extent2D { width: 4, height: 1 }, vk::Format::BC7_UNORM_BLOCK, &rgba));
+        let mut changed = rgba;
+        changed[9] = 8;
+        assert_ne!(key, cache_key(extent, vk::Format::BC7_UNORM_BLOCK, &changed));
+    }
+}
\ No newline at end of file
@@ -0,0 +1,664 @@
+pub mod compress;
+pub mod obj;
+pub mod optimize;
+pub mod preload;
+pub mod simplify;
+pub mod snapshot;
+#[cfg(target_os = "android")]
+mod apk;
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use rayon::prelude::*;
+
+use crate::jobs;
+use compress::TextureUsage;
+use preload::{LoadProgress, Preload, PreloadCache, Prepared};
+use crate::vulkan::game_object::GameObject;
+use crate::vulkan::mesh::{Mesh, MeshLod};
+use crate::vulkan::post::grading::Lut;
+use crate::vulkan::renderer::VulkanRenderer;
+use crate::vulkan::texture_streaming::MipChain;
+use crate::vulkan::vertex::Vertex;
+
+// Longest side of generated thumbnails, in pixels
+pub const THUMBNAIL_SIZE: usize = 64;
+// Distance in front of the camera things land when dropped where the ray misses the ground
+const DROP_DISTANCE: f32 = 3.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AssetKind {
+    Mesh,
+    Texture,
+    Lut,
+}
+
+impl AssetKind {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "obj" => Some(AssetKind::Mesh),
+            "png" => Some(AssetKind::Texture),
+            "cube" => Some(AssetKind::Lut),
+            _ => None
+        }
+    }
+}
+
+// Tightly packed RGBA8, premultiplied alpha
+pub struct Thumbnail {
+    pub size: [usize; 2],
+    pub rgba: Vec<u8>,
+    // Drawn by the GPU, see `AssetManager::render_thumbnails`. Meshes get a software rendered one until then.
+    pub rendered: bool,
+}
+
+pub struct Asset {
+    pub path: PathBuf,
+    pub kind: AssetKind,
+    pub thumbnail: Option<Thumbnail>,
+    // Bumped by every reimport and new thumbnail, so views can tell their copies are stale
+    pub version: u32,
+}
+
+impl Asset {
+    pub fn name(&self) -> String {
+        self.path.file_name().map_or_else(String::new, |name| name.to_string_lossy().into_owned())
+    }
+}
+
+// A mesh file parsed, optimized and simplified, ready to upload
+pub struct PreparedMesh {
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+    pub lods: Vec<MeshLod>,
+}
+
+// A texture file decoded, and block compressed for renderers that sample compressed textures
+pub enum PreparedTexture {
+    Rgba(ash::vk::Extent2D, Vec<u8>),
+    Compressed(MipChain),
+}
+
+// Files under `root` the engine knows how to load, found by extension. Game objects created from mesh assets are remembered,
+// so reimporting a mesh updates them in place.
+pub struct AssetManager {
+    pub root: PathBuf,
+    pub assets: Vec<Asset>,
+    // Game object id -> mesh asset it was instantiated from
+    instances: HashMap<usize, PathBuf>,
+    // Game object id -> texture file applied as its color texture or normal map, what snapshots refer to them by and what
+    // reimporting the texture rebinds
+    color_textures: HashMap<usize, PathBuf>,
+    normal_maps: HashMap<usize, PathBuf>,
+    // The color grading LUT last applied from an asset, reapplied when that asset is reimported
+    active_lut: Option<PathBuf>,
+    // Block compressed textures are kept here between runs, see `compress::load_or_compress`. `None` compresses them
+    // on every load.
+    pub texture_cache: Option<PathBuf>,
+    // Files read ahead of time, see `preload`
+    preloaded: PreloadCache,
+    preloading: HashSet<Preload>,
+    progress: LoadProgress,
+}
+
+impl AssetManager {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        let root = root.into();
+        let mut manager = Self {
+            texture_cache: Some(root.join(".cache").join("textures")),
+            root,
+            assets: vec![],
+            instances: HashMap::new(),
+            color_textures: HashMap::new(),
+            normal_maps: HashMap::new(),
+            active_lut: None,
+            preloaded: PreloadCache::default(),
+            preloading: HashSet::new(),
+            progress: LoadProgress::default()
+        };
+        if let Err(error) = manager.scan() {
+            tracing::warn!("Failed to scan assets in {}: {}", manager.root.display(), error);
+        }
+        manager
+    }
+
+    // Picks up new files and drops vanished ones, assets already known keep their thumbnails
+    pub fn scan(&mut self) -> std::io::Result<()> {
+        let mut paths = vec![];
+        collect_files(&self.root, &mut paths)?;
+        paths.sort();
+
+        let mut known: HashMap<PathBuf, Asset> = self.assets.drain(..).map(|asset| (asset.path.clone(), asset)).collect();
+        let mut is_new = vec![];
+        for path in paths {
+            let (asset, new) = match known.remove(&path) {
+                Some(asset) => (asset, false),
+                None => match AssetKind::from_path(&path) {
+                    Some(kind) => (Asset {
+                        path,
+                        kind,
+                        thumbnail: None,
+                        version: 0
+                    }, true),
+                    None => continue
+                }
+            };
+            self.assets.push(asset);
+            is_new.push(new);
+        }
+
+        // New assets are decoded for their thumbnails on the job threads
+        jobs::pool().install(|| self.assets
+            .par_iter_mut()
+            .zip(is_new)
+            .filter(|(_, new)| *new)
+            .for_each(|(asset, _)| asset.thumbnail = generate_thumbnail(asset)));
+
+        Ok(())
+    }
+
+    // Replaces up to `limit` software rendered mesh thumbnails with ones from the renderer's `ThumbnailRenderer`, meant to be
+    // called every frame so the GPU work is spread out
+    pub fn render_thumbnails(&mut self, renderer: &mut VulkanRenderer, limit: usize) {
+        let pending = self.assets
+            .iter_mut()
+            .filter(|asset| asset.kind == AssetKind::Mesh && asset.thumbnail.as_ref().is_some_and(|thumbnail| !thumbnail.rendered))
+            .take(limit);
+        for asset in pending {
+            match render_mesh_thumbnail(renderer, &asset.path) {
+                Ok(thumbnail) => {
+                    asset.thumbnail = Some(thumbnail);
+                    asset.version += 1;
+                }
+                Err(error) => {
+                    // Keeps the software one instead of trying again every frame
+                    tracing::warn!("Failed to render the thumbnail of {}: {}", asset.path.display(), error);
+                    if let Some(thumbnail) = &mut asset.thumbnail {
+                        thumbnail.rendered = true;
+                    }
+                }
+            }
+        }
+    }
+
+    // Parsed and optimized for drawing, see `optimize::optimize`
+    pub fn load_mesh(path: &Path) -> Result<(Vec<Vertex>, Vec<u32>), Box<dyn std::error::Error>> {
+        let (vertices, indices) = obj::parse(&read_to_string(path)?)?;
+        Ok(optimize::optimize(&vertices, &indices))
+    }
+
+    // What uploading the mesh needs, on top of `load_mesh` the levels of detail
+    pub fn prepare_mesh(path: &Path) -> Result<PreparedMesh, Box<dyn std::error::Error>> {
+        let (vertices, indices) = Self::load_mesh(path)?;
+        let lods = simplify::lod_chain(&vertices, &indices);
+        Ok(PreparedMesh { vertices, indices, lods })
+    }
+
+    // Any PNG, expanded to tightly packed RGBA8
+    pub fn load_texture(path: &Path) -> Result<([u32; 2], Vec<u8>), Box<dyn std::error::Error>> {
+        let mut decoder = png::Decoder::new(std::io::Cursor::new(read(path)?));
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+        let mut reader = decoder.read_info()?;
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buffer)?;
+        buffer.truncate(info.buffer_size());
+
+        let rgba = match info.color_type {
+            png::ColorType::Rgba => buffer,
+            png::ColorType::Rgb => buffer.chunks(3).flat_map(|rgb| [rgb[0], rgb[1], rgb[2], 255]).collect(),
+            png::ColorType::GrayscaleAlpha => buffer.chunks(2).flat_map(|gray| [gray[0], gray[0], gray[0], gray[1]]).collect(),
+            png::ColorType::Grayscale => buffer.iter().flat_map(|&gray| [gray, gray, gray, 255]).collect(),
+            png::ColorType::Indexed => return Err("Indexed PNG was not expanded".into())
+        };
+
+        Ok(([info.width, info.height], rgba))
+    }
+
+    // `compressed` through `compress::load_or_compress` with the texture cache `cache`
+    pub fn prepare_texture(cache: Option<&Path>, path: &Path, usage: TextureUsage, compressed: bool
+    ) -> Result<PreparedTexture, Box<dyn std::error::Error>> {
+        let ([width, height], rgba) = Self::load_texture(path)?;
+        let extent = ash::vk::Extent2D { width, height };
+        Ok(match compressed {
+            true => PreparedTexture::Compressed(compress::load_or_compress(cache, extent, &rgba, usage)),
+            false => PreparedTexture::Rgba(extent, rgba)
+        })
+    }
+
+    // Creates a game object from a mesh asset at `position` and returns its id
+    pub fn instantiate(&mut self, renderer: &mut VulkanRenderer, index: usize, position: uv::Vec3) -> Result<usize, Box<dyn std::error::Error>> {
+        let asset = &self.assets[index];
+        if asset.kind != AssetKind::Mesh {
+            return Err(format!("{} is not a mesh", asset.name()).into());
+        }
+
+        let path = asset.path.clone();
+        self.instantiate_file(renderer, &path, position)
+    }
+
+    // Same as `instantiate` for a mesh file that doesn't have to be under `root`
+    pub fn instantiate_file(&mut self, renderer: &mut VulkanRenderer, path: &Path, position: uv::Vec3) -> Result<usize, Box<dyn std::error::Error>> {
+        let mesh = match self.preloaded(&Preload::Mesh(path.to_path_buf())) {
+            Some(Prepared::Mesh(prepared)) => upload_mesh(renderer, &prepared)?,
+            _ => create_mesh(renderer, path)?
+        };
+        let mut game_object = GameObject::new(mesh, uv::Vec3::broadcast(0.8));
+        if let Some(stem) = path.file_stem() {
+            game_object.name = stem.to_string_lossy().into_owned();
+        }
+        game_object.transform3d.translation = position;
+
+        let id = game_object.get_id();
+        self.instances.insert(id, path.to_path_buf());
+        renderer.game_objects.push(game_object);
+        Ok(id)
+    }
+
+    // Mesh file the game object was instantiated from
+    pub fn mesh_file(&self, id: usize) -> Option<&Path> {
+        self.instances.get(&id).map(PathBuf::as_path)
+    }
+
+    pub fn apply_lut(&mut self, renderer: &mut VulkanRenderer, index: usize) -> Result<(), Box<dyn std::error::Error>> {
+        let asset = &self.assets[index];
+        if asset.kind != AssetKind::Lut {
+            return Err(format!("{} is not a LUT", asset.name()).into());
+        }
+
+        let path = asset.path.clone();
+        self.apply_lut_file(renderer, &path)
+    }
+
+    // Same as `apply_lut` for a LUT file that doesn't have to be under `root`
+    pub fn apply_lut_file(&mut self, renderer: &mut VulkanRenderer, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        let lut = Lut::from_cube(&read_to_string(path)?)?;
+        renderer.set_color_grading_lut(&lut)?;
+        self.active_lut = Some(path.to_path_buf());
+        Ok(())
+    }
+
+    // Adds a file from anywhere to the scene at the window position (x, y) in physical pixels, like one dropped onto
+    // the window: meshes are placed on the ground under it, textures go on the object under it or on a new cube there
+    // and LUTs become the color grading LUT. Returns the id of the game object that was added or textured.
+    pub fn import_file(&mut self, renderer: &mut VulkanRenderer, path: &Path, x: f32, y: f32) -> Result<Option<usize>, Box<dyn std::error::Error>> {
+        let kind = match AssetKind::from_path(path) {
+            Some(kind) => kind,
+            None => return Err(format!("{} is not an OBJ mesh, PNG texture or .cube LUT", path.display()).into())
+        };
+
+        match kind {
+            AssetKind::Mesh => {
+                // Dropped on the letterbox bars, there's no scene under the cursor
+                let position = match drop_point(renderer, x, y) {
+                    Some(position) => position,
+                    None => return Ok(None)
+                };
+                self.instantiate_file(renderer, path, position).map(Some)
+            }
+            AssetKind::Texture => {
+                let id = match renderer.pick(x as u32, y as u32)? {
+                    Some(id) => id,
+                    None => {
+                        let position = match drop_point(renderer, x, y) {
+                            Some(position) => position,
+                            None => return Ok(None)
+                        };
+                        let mesh = Mesh::cube(&renderer.device, &mut renderer.allocator, uv::Vec3::one())?;
+                        let mut game_object = GameObject::new(mesh, uv::Vec3::one());
+                        if let Some(stem) = path.file_stem() {
+                            game_object.name = stem.to_string_lossy().into_owned();
+                        }
+                        // Resting on the ground rather than half in it
+                        game_object.transform3d.translation = position + uv::Vec3::unit_y() * 0.5;
+                        let id = game_object.get_id();
+                        renderer.game_objects.push(game_object);
+                        id
+                    }
+                };
+                let index = renderer.game_objects.iter().position(|game_object| game_object.get_id() == id).unwrap();
+                self.apply_color_texture_file(renderer, path, index)?;
+                Ok(Some(id))
+            }
+            AssetKind::Lut => {
+                self.apply_lut_file(renderer, path)?;
+                Ok(None)
+            }
+        }
+    }
+
+    // Uses a texture asset as the color texture of the game object at `object` in the renderer's list, streamed by how
+    // large the object is on screen
+    pub fn apply_color_texture(&mut self, renderer: &mut VulkanRenderer, index: usize, object: usize) -> Result<(), Box<dyn std::error::Error>> {
+        let asset = &self.assets[index];
+        if asset.kind != AssetKind::Texture {
+            return Err(format!("{} is not a texture", asset.name()).into());
+        }
+
+        let path = asset.path.clone();
+        self.apply_color_texture_file(renderer, &path, object)
+    }
+
+    // Same as `apply_color_texture` for a texture file that doesn't have to be under `root`
+    pub fn apply_color_texture_file(&mut self, renderer: &mut VulkanRenderer, path: &Path, object: usize) -> Result<(), Box<dyn std::error::Error>> {
+        let prepared = self.texture(renderer, Preload::ColorTexture(path.to_path_buf()), TextureUsage::Color)?;
+        stream_texture(renderer, object, &prepared, TextureUsage::Color)?;
+        self.color_textures.insert(renderer.game_objects[object].get_id(), path.to_path_buf());
+        Ok(())
+    }
+
+    // Uses a texture asset as the normal map of the game object at `object` in the renderer's list
+    pub fn apply_normal_map(&mut self, renderer: &mut VulkanRenderer, index: usize, object: usize) -> Result<(), Box<dyn std::error::Error>> {
+        let asset = &self.assets[index];
+        if asset.kind != AssetKind::Texture {
+            return Err(format!("{} is not a texture", asset.name()).into());
+        }
+
+        let path = asset.path.clone();
+        self.apply_normal_map_file(renderer, &path, object)
+    }
+
+    // Same as `apply_normal_map` for a texture file that doesn't have to be under `root`
+    pub fn apply_normal_map_file(&mut self, renderer: &mut VulkanRenderer, path: &Path, object: usize) -> Result<(), Box<dyn std::error::Error>> {
+        let prepared = self.texture(renderer, Preload::NormalMap(path.to_path_buf()), TextureUsage::NormalMap)?;
+        stream_texture(renderer, object, &prepared, TextureUsage::NormalMap)?;
+        self.normal_maps.insert(renderer.game_objects[object].get_id(), path.to_path_buf());
+        Ok(())
+    }
+
+    // The preloaded copy, or the file read right away
+    fn texture(&self, renderer: &VulkanRenderer, file: Preload, usage: TextureUsage) -> Result<Arc<PreparedTexture>, Box<dyn std::error::Error>> {
+        match self.preloaded(&file) {
+            Some(Prepared::Texture(prepared)) => Ok(prepared),
+            _ => Ok(Arc::new(Self::prepare_texture(self.texture_cache.as_deref(), file.path(), usage, renderer.supports_texture_compression())?))
+        }
+    }
+
+    // Reads the asset from disk again, refreshing its thumbnail and everything in the scene that was created from it: the
+    // meshes of its instances, the textures of the objects it was applied to and the active LUT
+    pub fn reimport(&mut self, renderer: &mut VulkanRenderer, index: usize) -> Result<(), Box<dyn std::error::Error>> {
+        // A preloaded copy would be out of date now
+        self.preloaded.lock().unwrap().retain(|file, _| file.path() != self.assets[index].path);
+        let asset = &mut self.assets[index];
+        asset.thumbnail = generate_thumbnail(asset);
+        asset.version += 1;
+
+        match asset.kind {
+            AssetKind::Mesh => {
+                // Forget instances that were deleted in the meantime
+                self.instances.retain(|id, _| renderer.game_objects.iter().any(|game_object| game_object.get_id() == *id));
+
+                let path = asset.path.clone();
+                let ids: Vec<usize> = self.instances.iter().filter(|(_, source)| **source == path).map(|(id, _)| *id).collect();
+                if ids.is_empty() {
+                    return Ok(());
+                }
+
+                let prepared = Self::prepare_mesh(&path)?;
+                unsafe { renderer.device.device_wait_idle()? };
+                for id in ids {
+                    let mesh = upload_mesh(renderer, &prepared)?;
+                    let index = renderer.game_objects.iter().position(|game_object| game_object.get_id() == id).unwrap();
+                    let mut old_mesh = std::mem::replace(&mut renderer.game_objects[index].mesh, mesh);
+                    old_mesh.destroy(&renderer.device, &mut renderer.allocator);
+                }
+            }
+            AssetKind::Texture => {
+                let path = asset.path.clone();
+                let compressed = renderer.supports_texture_compression();
+                for usage in [TextureUsage::Color, TextureUsage::NormalMap] {
+                    // Objects still showing the texture, one whose texture was removed since keeps its entry
+                    let applied = match usage {
+                        TextureUsage::Color => &self.color_textures,
+                        TextureUsage::NormalMap => &self.normal_maps
+                    };
+                    let objects: Vec<usize> = renderer.game_objects
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, game_object)| match usage {
+                            TextureUsage::Color => game_object.texture.is_some(),
+                            TextureUsage::NormalMap => game_object.normal_map.is_some()
+                        })
+                        .filter(|(_, game_object)| applied.get(&game_object.get_id()) == Some(&path))
+                        .map(|(object, _)| object)
+                        .collect();
+                    if objects.is_empty() {
+                        continue;
+                    }
+
+                    let prepared = Self::prepare_texture(self.texture_cache.as_deref(), &path, usage, compressed)?;
+                    for object in objects {
+                        stream_texture(renderer, object, &prepared, usage)?;
+                    }
+                }
+            }
+            AssetKind::Lut if self.active_lut.as_ref() == Some(&asset.path) => self.apply_lut(renderer, index)?,
+            AssetKind::Lut => {}
+        }
+
+        Ok(())
+    }
+}
+
+// Reads from the APK on Android, see `apk`
+#[cfg(not(target_os = "android"))]
+fn read(path: &Path) -> std::io::Result<Vec<u8>> {
+    std::fs::read(path)
+}
+
+#[cfg(target_os = "android")]
+fn read(path: &Path) -> std::io::Result<Vec<u8>> {
+    apk::read(path)
+}
+
+fn read_to_string(path: &Path) -> std::io::Result<String> {
+    String::from_utf8(read(path)?).map_err(|error| std::io::Error::new(std::io::ErrorKind::InvalidData, error))
+}
+
+// A missing root is an empty one
+#[cfg(not(target_os = "android"))]
+fn collect_files(directory: &Path, paths: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    if !directory.is_dir() {
+        return Ok(());
+    }
+    for entry in std::fs::read_dir(directory)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_files(&path, paths)?;
+        } else {
+            paths.push(path);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "android")]
+fn collect_files(directory: &Path, paths: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    apk::collect_files(directory, paths)
+}
+
+// Where a ray through the window position (x, y) in physical pixels hits the ground plane, or a bit in front of the
+// camera when it doesn't. `None` over the letterbox bars.
+pub fn drop_point(renderer: &VulkanRenderer, x: f32, y: f32) -> Option<uv::Vec3> {
+    let ray = renderer.screen_ray(x, y)?;
+    let distance = match ray.direction.y < -f32::EPSILON {
+        true => -ray.origin.y / ray.direction.y,
+        false => DROP_DISTANCE
+    };
+    Some(ray.at(distance))
+}
+
+fn create_mesh(renderer: &mut VulkanRenderer, path: &Path) -> Result<Mesh, Box<dyn std::error::Error>> {
+    upload_mesh(renderer, &AssetManager::prepare_mesh(path)?)
+}
+
+fn upload_mesh(renderer: &mut VulkanRenderer, prepared: &PreparedMesh) -> Result<Mesh, Box<dyn std::error::Error>> {
+    let mut mesh = Mesh::new(&renderer.device, &mut renderer.allocator, prepared.vertices.len(), prepared.indices.len())?;
+    mesh.update_vertex_buffer(&prepared.vertices);
+    mesh.update_index_buffer(&prepared.indices);
+    mesh.build_meshlets(&prepared.vertices, &prepared.indices);
+    mesh.set_lods(prepared.lods.clone());
+    Ok(mesh)
+}
+
+// Replaces the object's color texture or normal map
+fn stream_texture(renderer: &mut VulkanRenderer, object: usize, prepared: &PreparedTexture, usage: TextureUsage) -> Result<(), ash::vk::Result> {
+    match (prepared, usage) {
+        (PreparedTexture::Compressed(chain), TextureUsage::Color) => renderer.stream_color_mips(object, chain.clone()),
+        (PreparedTexture::Rgba(extent, rgba), TextureUsage::Color) => renderer.stream_color_texture(object, *extent, rgba),
+        (PreparedTexture::Compressed(chain), TextureUsage::NormalMap) => renderer.stream_normal_mips(object, chain.clone()),
+        (PreparedTexture::Rgba(extent, rgba), TextureUsage::NormalMap) => renderer.stream_normal_map(object, *extent, rgba)
+    }
+}
+
+fn render_mesh_thumbnail(renderer: &mut VulkanRenderer, path: &Path) -> Result<Thumbnail, Box<dyn std::error::Error>> {
+    let mut mesh = create_mesh(renderer, path)?;
+    let result = renderer.render_thumbnail(&mesh, uv::Vec3::broadcast(0.8), 0.5, THUMBNAIL_SIZE as u32);
+    mesh.destroy(&renderer.device, &mut renderer.allocator);
+
+    Ok(Thumbnail {
+        size: [THUMBNAIL_SIZE, THUMBNAIL_SIZE],
+        rgba: result?,
+        rendered: true
+    })
+}
+
+// Assets that fail to load just don't get a thumbnail, the browser shows their name instead
+fn generate_thumbnail(asset: &Asset) -> Option<Thumbnail> {
+    let result = match asset.kind {
+        AssetKind::Mesh => AssetManager::load_mesh(&asset.path).map(|(vertices, indices)| mesh_thumbnail(&vertices, &indices)),
+        AssetKind::Texture => AssetManager::load_texture(&asset.path).map(|(size, rgba)| texture_thumbnail(size, &rgba)),
+        AssetKind::Lut => read_to_string(&asset.path)
+            .map_err(|error| error.into())
+            .and_then(|source| Lut::from_cube(&source))
+            .map(|lut| lut_thumbnail(&lut))
+    };
+
+    match result {
+        Ok(thumbnail) => Some(thumbnail),
+        Err(error) => {
+            tracing::warn!("Failed to load {}: {}", asset.path.display(), error);
+            None
+        }
+    }
+}
+
+// Nearest neighbour downscale keeping the aspect ratio
+fn texture_thumbnail(size: [u32; 2], rgba: &[u8]) -> Thumbnail {
+    let [width, height] = [size[0] as usize, size[1] as usize];
+    let scale = (THUMBNAIL_SIZE as f32 / width.max(height) as f32).min(1.0);
+    let thumbnail_size = [((width as f32 * scale) as usize).max(1), ((height as f32 * scale) as usize).max(1)];
+
+    let mut pixels = Vec::with_capacity(thumbnail_size[0] * thumbnail_size[1] * 4);
+    for y in 0..thumbnail_size[1] {
+        for x in 0..thumbnail_size[0] {
+            let source = ((y * height / thumbnail_size[1]) * width + x * width / thumbnail_size[0]) * 4;
+            let alpha = rgba[source + 3] as u32;
+            pixels.extend((0..3).map(|channel| (rgba[source + channel] as u32 * alpha / 255) as u8));
+            pixels.push(alpha as u8);
+        }
+    }
+
+    Thumbnail {
+        size: thumbnail_size,
+        rgba: pixels,
+        rendered: false
+    }
+}
+
+// Flat shaded software render from above and to the side, so no GPU work is needed while scanning
+fn mesh_thumbnail(vertices: &[Vertex], indices: &[u32]) -> Thumbnail {
+    let view = uv::Mat3::from_rotation_x(0.5) * uv::Mat3::from_rotation_y(-0.7);
+    let light = uv::Vec3::new(0.4, 0.8, 0.45).normalized();
+    let projected: Vec<uv::Vec3> = vertices.iter().map(|vertex| view * vertex.pos).collect();
+
+    let (min, max) = projected.iter().fold((uv::Vec3::broadcast(f32::MAX), uv::Vec3::broadcast(f32::MIN)),
+        |(min, max), position| (min.min_by_component(*position), max.max_by_component(*position)));
+    let extent = (max - min).x.max((max - min).y).max(f32::EPSILON);
+    let center = (min + max) * 0.5;
+    // Fits the larger side into the image with a small margin, y points down in the image
+    let to_pixel = |position: uv::Vec3| uv::Vec3::new(
+        ((position.x - center.x) / extent * 0.9 + 0.5) * THUMBNAIL_SIZE as f32,
+        ((center.y - position.y) / extent * 0.9 + 0.5) * THUMBNAIL_SIZE as f32,
+        position.z
+    );
+
+    let mut rgba = vec![0; THUMBNAIL_SIZE * THUMBNAIL_SIZE * 4];
+    let mut depth = vec![f32::MIN; THUMBNAIL_SIZE * THUMBNAIL_SIZE];
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [triangle[0], triangle[1], triangle[2]].map(|index| projected[index as usize]);
+        let normal = (b - a).cross(c - a);
+        if normal.mag_sq() == 0.0 {
+            continue;
+        }
+        let shade = (0.25 + 0.75 * (view.transposed() * normal.normalized()).dot(light).abs()).min(1.0);
+        let value = (shade * 220.0) as u8;
+
+        let [a, b, c] = [a, b, c].map(to_pixel);
+        let area = (b.x - a.x) * (c.y - a.y) - (b.y - a.y) * (c.x - a.x);
+        if area.abs() < f32::EPSILON {
+            continue;
+        }
+
+        let min_x = a.x.min(b.x).min(c.x).floor().max(0.0) as usize;
+        let min_y = a.y.min(b.y).min(c.y).floor().max(0.0) as usize;
+        let max_x = (a.x.max(b.x).max(c.x).ceil() as usize).min(THUMBNAIL_SIZE);
+        let max_y = (a.y.max(b.y).max(c.y).ceil() as usize).min(THUMBNAIL_SIZE);
+        for y in min_y..max_y {
+            for x in min_x..max_x {
+                let (px, py) = (x as f32 + 0.5, y as f32 + 0.5);
+                let wa = ((b.x - px) * (c.y - py) - (b.y - py) * (c.x - px)) / area;
+                let wb = ((c.x - px) * (a.y - py) - (c.y - py) * (a.x - px)) / area;
+                let wc = 1.0 - wa - wb;
+                if wa < 0.0 || wb < 0.0 || wc < 0.0 {
+                    continue;
+                }
+
+                // Larger z is closer to the viewer after the rotation
+                let z = a.z * wa + b.z * wb + c.z * wc;
+                let pixel = y * THUMBNAIL_SIZE + x;
+                if z > depth[pixel] {
+                    depth[pixel] = z;
+                    rgba[pixel * 4..pixel * 4 + 4].copy_from_slice(&[value, value, value, 255]);
+                }
+            }
+        }
+    }
+
+    Thumbnail {
+        size: [THUMBNAIL_SIZE, THUMBNAIL_SIZE],
+        rgba,
+        rendered: false
+    }
+}
+
+// A hue sweep from left to right, dark at the bottom and bright at the top, graded by the LUT
+fn lut_thumbnail(lut: &Lut) -> Thumbnail {
+    let size = lut.size as usize;
+    let mut rgba = Vec::with_capacity(THUMBNAIL_SIZE * THUMBNAIL_SIZE * 4);
+    for y in 0..THUMBNAIL_SIZE {
+        let value = 1.0 - y as f32 / (THUMBNAIL_SIZE - 1) as f32;
+        for x in 0..THUMBNAIL_SIZE {
+            let hue = x as f32 / THUMBNAIL_SIZE as f32 * 6.0;
+            let color = [
+                ((hue - 3.0).abs() - 1.0).clamp(0.0, 1.0),
+                (2.0 - (hue - 2.0).abs()).clamp(0.0, 1.0),
+                (2.0 - (hue - 4.0).abs()).clamp(0.0, 1.0),
+            ].map(|channel| channel * value);
+
+            // Nearest entry, red varies fastest in the table
+            let [r, g, b] = color.map(|channel| (channel * (size - 1) as f32).round() as usize);
+            let entry = ((b * size + g) * size + r) * 4;
+            rgba.extend((0..3).map(|channel| (lut.data[entry + channel].clamp(0.0, 1.0) * 255.0) as u8));
+            rgba.push(255);
+        }
+    }
+
+    Thumbnail {
+        size: [THUMBNAIL_SIZE, THUMBNAIL_SIZE],
+        rgba,
+        rendered: false
+    }
+}
\ No newline at end of file
@@ -0,0 +1,111 @@
+use std::collections::HashMap;
+
+use crate::vulkan::vertex::{generate_tangents, Vertex};
+
+// Positions, texture coordinates, normals and faces of a Wavefront OBJ file. Groups and materials are ignored, polygons
+// are triangulated as fans and every distinct position/texture coordinate/normal triple becomes one vertex. Tangents
+// are generated from the texture coordinates.
+pub fn parse(source: &str) -> Result<(Vec<Vertex>, Vec<u32>), Box<dyn std::error::Error>> {
+    let mut positions: Vec<uv::Vec3> = vec![];
+    let mut normals: Vec<uv::Vec3> = vec![];
+    let mut tex_coords: Vec<uv::Vec2> = vec![];
+    let mut vertices: Vec<Vertex> = vec![];
+    let mut indices: Vec<u32> = vec![];
+    let mut unique: HashMap<(usize, Option<usize>, Option<usize>), u32> = HashMap::new();
+
+    for (line_number, line) in source.lines().enumerate() {
+        let mut tokens = line.split_whitespace();
+        match tokens.next() {
+            Some("v") => positions.push(parse_vec3(tokens, line_number)?),
+            Some("vn") => normals.push(parse_vec3(tokens, line_number)?),
+            Some("vt") => tex_coords.push(parse_vec2(tokens, line_number)?),
+            Some("f") => {
+                let mut corners = vec![];
+                for token in tokens {
+                    let mut parts = token.split('/');
+                    let position = resolve_index(parts.next(), positions.len(), line_number)?
+                        .ok_or_else(|| format!("Line {}: face corner without a position", line_number + 1))?;
+                    let tex_coord = resolve_index(parts.next(), tex_coords.len(), line_number)?;
+                    let normal = resolve_index(parts.next(), normals.len(), line_number)?;
+
+                    let index = *unique.entry((position, tex_coord, normal)).or_insert_with(|| {
+                        vertices.push(Vertex {
+                            pos: positions[position],
+                            color: uv::Vec3::one(),
+                            normal: normal.map_or(uv::Vec3::zero(), |normal| normals[normal]),
+                            lightmap_uv: uv::Vec2::zero(),
+                            tex_coord: tex_coord.map_or(uv::Vec2::zero(), |tex_coord| tex_coords[tex_coord]),
+                            tangent: uv::Vec4::zero()
+                        });
+                        vertices.len() as u32 - 1
+                    });
+                    corners.push(index);
+                }
+
+                if corners.len() < 3 {
+                    return Err(format!("Line {}: face with fewer than 3 corners", line_number + 1).into());
+                }
+                for i in 1..corners.len() - 1 {
+                    indices.extend([corners[0], corners[i], corners[i + 1]]);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Files without normals get smooth ones, averaged from the faces around each vertex
+    if normals.is_empty() {
+        for triangle in indices.chunks(3) {
+            let [a, b, c] = [triangle[0], triangle[1], triangle[2]].map(|index| vertices[index as usize].pos);
+            let normal = (b - a).cross(c - a);
+            for &index in triangle {
+                vertices[index as usize].normal += normal;
+            }
+        }
+        for vertex in &mut vertices {
+            if vertex.normal.mag_sq() > 0.0 {
+                vertex.normal.normalize();
+            }
+        }
+    }
+
+    generate_tangents(&mut vertices, &indices);
+
+    Ok((vertices, indices))
+}
+
+fn parse_vec3<'a>(mut tokens: impl Iterator<Item = &'a str>, line_number: usize) -> Result<uv::Vec3, Box<dyn std::error::Error>> {
+    let mut component = || -> Result<f32, Box<dyn std::error::Error>> {
+        let token = tokens.next().ok_or_else(|| format!("Line {}: expected 3 components", line_number + 1))?;
+        Ok(token.parse()?)
+    };
+    Ok(uv::Vec3::new(component()?, component()?, component()?))
+}
+
+// OBJ texture coordinates start at the bottom left, Vulkan samples from the top left
+fn parse_vec2<'a>(mut tokens: impl Iterator<Item = &'a str>, line_number: usize) -> Result<uv::Vec2, Box<dyn std::error::Error>> {
+    let mut component = || -> Result<f32, Box<dyn std::error::Error>> {
+        let token = tokens.next().ok_or_else(|| format!("Line {}: expected 2 components", line_number + 1))?;
+        Ok(token.parse()?)
+    };
+    let (u, v) = (component()?, component()?);
+    Ok(uv::Vec2::new(u, 1.0 - v))
+}
+
+// OBJ indices start at 1, negative ones count back from the last element so far
+fn resolve_index(token: Option<&str>, count: usize, line_number: usize) -> Result<Option<usize>, Box<dyn std::error::Error>> {
+    let token = match token {
+        Some(token) if !token.is_empty() => token,
+        _ => return Ok(None)
+    };
+
+    let index: i64 = token.parse()?;
+    let resolved = match index {
+        index if index > 0 => index - 1,
+        index => count as i64 + index
+    };
+    match resolved >= 0 && resolved < count as i64 {
+        true => Ok(Some(resolved as usize)),
+        false => Err(format!("Line {}: index {} out of range", line_number + 1, index).into())
+    }
+}
\ No newline at end of file
@@ -0,0 +1,387 @@
+use std::collections::HashMap;
+
+use crate::vulkan::vertex::Vertex;
+
+// Entries of the simulated post transform cache, the size Forsyth's scores are tuned for
+const CACHE_SIZE: usize = 32;
+// Cache the statistics are measured with, closer to the small FIFOs of real GPUs
+const STATS_CACHE_SIZE: u32 = 16;
+const LAST_TRIANGLE_SCORE: f32 = 0.75;
+const CACHE_DECAY_POWER: f32 = 1.5;
+const VALENCE_BOOST_SCALE: f32 = 2.0;
+const VALENCE_BOOST_POWER: f32 = 0.5;
+
+// Runs once when a mesh is imported so drawing it costs nothing extra. Identical vertices are welded, triangles are
+// ordered for the post transform cache and then in clusters facing outwards first to cut overdraw, and vertices are
+// laid out in the order the triangles first use them.
+pub fn optimize(vertices: &[Vertex], indices: &[u32]) -> (Vec<Vertex>, Vec<u32>) {
+    let (vertices, indices) = weld(vertices, indices);
+    let before = average_cache_miss_ratio(&indices, vertices.len());
+
+    let indices = optimize_vertex_cache(&indices, vertices.len());
+    let indices = optimize_overdraw(&vertices, &indices);
+    let (vertices, indices) = optimize_vertex_fetch(&vertices, &indices);
+
+    tracing::debug!("Optimized mesh of {} vertices and {} triangles, cache misses per triangle {:.2} -> {:.2}", vertices.len(),
+        indices.len() / 3, before, average_cache_miss_ratio(&indices, vertices.len()));
+    (vertices, indices)
+}
+
+// Merges vertices whose attributes are bit for bit the same and drops the triangles that collapse doing so
+pub fn weld(vertices: &[Vertex], indices: &[u32]) -> (Vec<Vertex>, Vec<u32>) {
+    let mut welded: Vec<Vertex> = Vec::with_capacity(vertices.len());
+    let mut unique: HashMap<[u32; 17], u32> = HashMap::with_capacity(vertices.len());
+    let remap: Vec<u32> = vertices
+        .iter()
+        .map(|vertex| *unique.entry(vertex_key(vertex)).or_insert_with(|| {
+            welded.push(*vertex);
+            welded.len() as u32 - 1
+        }))
+        .collect();
+
+    let indices = indices
+        .chunks_exact(3)
+        .map(|triangle| [triangle[0], triangle[1], triangle[2]].map(|index| remap[index as usize]))
+        .filter(|[a, b, c]| a != b && b != c && a != c)
+        .flatten()
+        .collect();
+    (welded, indices)
+}
+
+// Tom Forsyth's linear speed vertex cache optimization. Triangles are emitted greedily by the score of their vertices,
+// which favours vertices recently used and vertices with few triangles left so no stragglers are left behind.
+pub fn optimize_vertex_cache(indices: &[u32], vertex_count: usize) -> Vec<u32> {
+    let triangle_count = indices.len() / 3;
+    if triangle_count == 0 {
+        return vec![];
+    }
+
+    // Triangles around each vertex, packed into one list. The first `live[vertex]` entries are those not emitted yet.
+    let mut live = vec![0u32; vertex_count];
+    for &index in indices {
+        live[index as usize] += 1;
+    }
+    let mut offsets = Vec::with_capacity(vertex_count);
+    let mut total = 0;
+    for &count in &live {
+        offsets.push(total);
+        total += count as usize;
+    }
+    let mut adjacency = vec![0u32; indices.len()];
+    let mut filled = offsets.clone();
+    for (triangle, corners) in indices.chunks_exact(3).enumerate() {
+        for &vertex in corners {
+            adjacency[filled[vertex as usize]] = triangle as u32;
+            filled[vertex as usize] += 1;
+        }
+    }
+
+    let mut cache_position: Vec<Option<usize>> = vec![None; vertex_count];
+    let mut vertex_scores: Vec<f32> = live.iter().map(|&count| vertex_score(None, count)).collect();
+    let mut triangle_scores: Vec<f32> = indices
+        .chunks_exact(3)
+        .map(|corners| corners.iter().map(|&vertex| vertex_scores[vertex as usize]).sum())
+        .collect();
+    let mut emitted = vec![false; triangle_count];
+    let mut cache: Vec<u32> = Vec::with_capacity(CACHE_SIZE + 3);
+    let mut optimized = Vec::with_capacity(indices.len());
+    // Where to look for a new start when every triangle around the cache has been emitted
+    let mut dead_end_cursor = 0;
+
+    let mut next = (0..triangle_count).max_by(|&a, &b| triangle_scores[a].total_cmp(&triangle_scores[b]));
+    while let Some(triangle) = next {
+        emitted[triangle] = true;
+        let corners = &indices[triangle * 3..triangle * 3 + 3];
+        optimized.extend_from_slice(corners);
+
+        for &vertex in corners {
+            let vertex = vertex as usize;
+            let triangles = &mut adjacency[offsets[vertex]..offsets[vertex] + live[vertex] as usize];
+            if let Some(position) = triangles.iter().position(|&other| other as usize == triangle) {
+                triangles.swap(position, triangles.len() - 1);
+                live[vertex] -= 1;
+            }
+        }
+
+        // The corners move to the front, whatever falls off the end leaves the cache
+        let mut updated: Vec<u32> = corners.to_vec();
+        updated.extend(cache.iter().filter(|vertex| !corners.contains(vertex)));
+        for (position, &vertex) in updated.iter().enumerate() {
+            let vertex = vertex as usize;
+            cache_position[vertex] = Some(position).filter(|&position| position < CACHE_SIZE);
+            vertex_scores[vertex] = vertex_score(cache_position[vertex], live[vertex]);
+        }
+
+        next = None;
+        let mut best_score = f32::MIN;
+        for &vertex in &updated {
+            let vertex = vertex as usize;
+            for &other in &adjacency[offsets[vertex]..offsets[vertex] + live[vertex] as usize] {
+                let other = other as usize;
+                let score = indices[other * 3..other * 3 + 3].iter().map(|&corner| vertex_scores[corner as usize]).sum();
+                triangle_scores[other] = score;
+                if score > best_score {
+                    best_score = score;
+                    next = Some(other);
+                }
+            }
+        }
+
+        updated.truncate(CACHE_SIZE);
+        cache = updated;
+
+        if next.is_none() {
+            while dead_end_cursor < triangle_count && emitted[dead_end_cursor] {
+                dead_end_cursor += 1;
+            }
+            next = Some(dead_end_cursor).filter(|&cursor| cursor < triangle_count);
+        }
+    }
+
+    optimized
+}
+
+// Splits the cache ordered triangles into clusters where the cache restarts anyway, at triangles whose vertices all
+// miss, and draws the clusters facing away from the mesh's centre first since they tend to hide the rest. Orders
+// within a cluster are kept, so the cache efficiency barely changes.
+pub fn optimize_overdraw(vertices: &[Vertex], indices: &[u32]) -> Vec<u32> {
+    let mut cache = CacheSimulation::new(vertices.len(), STATS_CACHE_SIZE);
+    let mut clusters: Vec<&[u32]> = vec![];
+    let mut start = 0;
+    for (triangle, corners) in indices.chunks_exact(3).enumerate() {
+        let misses = corners.iter().filter(|&&vertex| cache.access(vertex)).count();
+        if misses == 3 && triangle * 3 > start {
+            clusters.push(&indices[start..triangle * 3]);
+            start = triangle * 3;
+        }
+    }
+    if start < indices.len() {
+        clusters.push(&indices[start..]);
+    }
+
+    let mesh_centre = match vertices.is_empty() {
+        true => uv::Vec3::zero(),
+        false => vertices.iter().fold(uv::Vec3::zero(), |sum, vertex| sum + vertex.pos) / vertices.len() as f32
+    };
+
+    // Area weighted normal and centroid of each cluster
+    let mut sorted: Vec<(f32, &[u32])> = clusters
+        .into_iter()
+        .map(|cluster| {
+            let mut normal = uv::Vec3::zero();
+            let mut centroid = uv::Vec3::zero();
+            let mut area = 0.0;
+            for triangle in cluster.chunks_exact(3) {
+                let [a, b, c] = [triangle[0], triangle[1], triangle[2]].map(|index| vertices[index as usize].pos);
+                let cross = (b - a).cross(c - a);
+                let triangle_area = cross.mag();
+                normal += cross;
+                centroid += (a + b + c) * (triangle_area / 3.0);
+                area += triangle_area;
+            }
+            let centroid = match area > 0.0 {
+                true => centroid / area,
+                false => mesh_centre
+            };
+            let facing = match normal.mag_sq() > 0.0 {
+                true => (centroid - mesh_centre).dot(normal.normalized()),
+                false => f32::MIN
+            };
+            (facing, cluster)
+        })
+        .collect();
+    sorted.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+
+    sorted.into_iter().flat_map(|(_, cluster)| cluster.iter().copied()).collect()
+}
+
+// Lays the vertices out in the order the index buffer first reaches them, dropping any that aren't used
+pub fn optimize_vertex_fetch(vertices: &[Vertex], indices: &[u32]) -> (Vec<Vertex>, Vec<u32>) {
+    let mut remap: Vec<Option<u32>> = vec![None; vertices.len()];
+    let mut reordered = Vec::with_capacity(vertices.len());
+    let indices = indices
+        .iter()
+        .map(|&index| *remap[index as usize].get_or_insert_with(|| {
+            reordered.push(vertices[index as usize]);
+            reordered.len() as u32 - 1
+        }))
+        .collect();
+    (reordered, indices)
+}
+
+// Vertices transformed per triangle drawn, 3 at worst and approaching 0.5 for a large regular grid
+pub fn average_cache_miss_ratio(indices: &[u32], vertex_count: usize) -> f32 {
+    if indices.is_empty() {
+        return 0.0;
+    }
+    let mut cache = CacheSimulation::new(vertex_count, STATS_CACHE_SIZE);
+    let misses = indices.iter().filter(|&&vertex| cache.access(vertex)).count();
+    misses as f32 / (indices.len() / 3) as f32
+}
+
+fn vertex_score(cache_position: Option<usize>, live_triangles: u32) -> f32 {
+    if live_triangles == 0 {
+        return -1.0;
+    }
+
+    let cache_score = match cache_position {
+        None => 0.0,
+        // The last triangle's vertices get a fixed score, so the next triangle doesn't just reuse the same edge
+        Some(position) if position < 3 => LAST_TRIANGLE_SCORE,
+        Some(position) => (1.0 - (position - 3) as f32 / (CACHE_SIZE - 3) as f32).powf(CACHE_DECAY_POWER)
+    };
+    cache_score + VALENCE_BOOST_SCALE * (live_triangles as f32).powf(-VALENCE_BOOST_POWER)
+}
+
+// Bit patterns of every attribute, with negative zero folded into zero
+fn vertex_key(vertex: &Vertex) -> [u32; 17] {
+    let Vertex { pos, color, normal, lightmap_uv, tex_coord, tangent } = vertex;
+    [pos.x, pos.y, pos.z, color.x, color.y, color.z, normal.x, normal.y, normal.z, lightmap_uv.x, lightmap_uv.y, tex_coord.x,
+        tex_coord.y, tangent.x, tangent.y, tangent.z, tangent.w]
+        .map(|component| (component + 0.0).to_bits())
+}
+
+// FIFO post transform cache, a vertex is cached while fewer than `size` misses happened since its own
+struct CacheSimulation {
+    timestamps: Vec<u32>,
+    time: u32,
+    size: u32,
+}
+
+impl CacheSimulation {
+    fn new(vertex_count: usize, size: u32) -> Self {
+        Self {
+            timestamps: vec![0; vertex_count],
+            time: size + 1,
+            size
+        }
+    }
+
+    // Whether `vertex` missed
+    fn access(&mut self, vertex: u32) -> bool {
+        let timestamp = &mut self.timestamps[vertex as usize];
+        match self.time - *timestamp > self.size {
+            true => {
+                *timestamp = self.time;
+                self.time += 1;
+                true
+            },
+            false => false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: usize = 16;
+
+    fn vertex(x: f32, y: f32, z: f32) -> Vertex {
+        Vertex {
+            pos: uv::Vec3::new(x, y, z),
+            color: uv::Vec3::one(),
+            normal: uv::Vec3::unit_y(),
+            lightmap_uv: uv::Vec2::zero(),
+            tex_coord: uv::Vec2::new(x, z),
+            tangent: uv::Vec4::unit_x()
+        }
+    }
+
+    // SIZE by SIZE quads facing up, each with its own four vertices like an exporter that doesn't share them, the
+    // triangles in a scrambled order
+    fn grid() -> (Vec<Vertex>, Vec<u32>) {
+        let mut vertices = vec![];
+        let mut triangles = vec![];
+        for (x, z) in (0..SIZE).flat_map(|z| (0..SIZE).map(move |x| (x as f32, z as f32))) {
+            let first = vertices.len() as u32;
+            vertices.extend([vertex(x, 0.0, z), vertex(x, 0.0, z + 1.0), vertex(x + 1.0, 0.0, z), vertex(x + 1.0, 0.0, z + 1.0)]);
+            triangles.extend([[first, first + 1, first + 2], [first + 2, first + 1, first + 3]]);
+        }
+        // 97 is prime to the triangle count, so this visits every triangle once
+        let indices = (0..triangles.len()).flat_map(|triangle| triangles[triangle * 97 % triangles.len()]).collect();
+        (vertices, indices)
+    }
+
+    // Each triangle by its corners' positions, starting at the smallest corner so the winding is kept
+    fn triangles(vertices: &[Vertex], indices: &[u32]) -> Vec<[[u32; 3]; 3]> {
+        let mut triangles: Vec<[[u32; 3]; 3]> = indices
+            .chunks_exact(3)
+            .map(|triangle| {
+                let corners = [triangle[0], triangle[1], triangle[2]].map(|index| {
+                    let pos = vertices[index as usize].pos;
+                    [pos.x, pos.y, pos.z].map(f32::to_bits)
+                });
+                let first = (0..3).min_by_key(|&corner| corners[corner]).unwrap();
+                [0, 1, 2].map(|offset| corners[(first + offset) % 3])
+            })
+            .collect();
+        triangles.sort();
+        triangles
+    }
+
+    #[test]
+    fn weld_merges_identical_vertices() {
+        let (vertices, indices) = grid();
+        let (welded, welded_indices) = weld(&vertices, &indices);
+        assert_eq!(welded.len(), (SIZE + 1) * (SIZE + 1));
+        assert_eq!(triangles(&welded, &welded_indices), triangles(&vertices, &indices));
+    }
+
+    #[test]
+    fn weld_drops_collapsed_triangles() {
+        let mut vertices = vec![vertex(0.0, 0.0, 0.0), vertex(1.0, 0.0, 0.0), vertex(0.0, 0.0, 1.0), vertex(1.0, 0.0, 0.0)];
+        // Negative zero is the same position
+        vertices.push(vertex(-0.0, 0.0, 0.0));
+        let (welded, indices) = weld(&vertices, &[0, 2, 1, 1, 2, 3, 4, 2, 1]);
+        assert_eq!(welded.len(), 3);
+        assert_eq!(indices, vec![0, 2, 1, 0, 2, 1]);
+    }
+
+    #[test]
+    fn optimize_keeps_the_triangles() {
+        let (vertices, indices) = grid();
+        let (optimized, optimized_indices) = optimize(&vertices, &indices);
+        assert_eq!(optimized_indices.len(), indices.len());
+        assert_eq!(triangles(&optimized, &optimized_indices), triangles(&vertices, &indices));
+    }
+
+    #[test]
+    fn vertices_are_ordered_by_first_use() {
+        let (mut vertices, indices) = weld(&grid().0, &grid().1);
+        // Not used by any triangle
+        vertices.push(vertex(0.5, 1.0, 0.5));
+        let (reordered, reordered_indices) = optimize_vertex_fetch(&vertices, &indices);
+        assert_eq!(reordered.len(), vertices.len() - 1);
+
+        let mut next = 0;
+        for &index in &reordered_indices {
+            assert!(index <= next, "Vertex {} is used before vertex {}", index, next);
+            if index == next {
+                next += 1;
+            }
+        }
+        assert_eq!(next as usize, reordered.len());
+        assert_eq!(triangles(&reordered, &reordered_indices), triangles(&vertices, &indices));
+    }
+
+    #[test]
+    fn cache_misses_drop_on_a_grid() {
+        let (vertices, indices) = weld(&grid().0, &grid().1);
+        let before = average_cache_miss_ratio(&indices, vertices.len());
+        let cache_ordered = optimize_vertex_cache(&indices, vertices.len());
+        let after = average_cache_miss_ratio(&cache_ordered, vertices.len());
+        assert!(after < before && after < 1.0, "{} -> {}", before, after);
+
+        // The overdraw order only moves whole clusters, which mustn't undo that
+        let (optimized, optimized_indices) = optimize(&grid().0, &grid().1);
+        assert!(average_cache_miss_ratio(&optimized_indices, optimized.len()) <= after * 1.1);
+    }
+
+    #[test]
+    fn empty_meshes_stay_empty() {
+        let (vertices, indices) = optimize(&[], &[]);
+        assert!(vertices.is_empty() && indices.is_empty());
+        assert_eq!(average_cache_miss_ratio(&[], 0), 0.0);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,158 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use rayon::prelude::*;
+
+use super::compress::TextureUsage;
+use super::{AssetManager, PreparedMesh, PreparedTexture};
+use crate::jobs;
+
+// Files read ahead of time on a background thread, so a heavy scene streams in while frames keep coming. Parsing,
+// simplifying and block compressing run on the job pool, only the upload is left for when the file is used, which
+// `AssetManager` does from the preloaded copy instead of reading the file again. Copies are kept until
+// `AssetManager::clear_preloaded`, so objects sharing a mesh all get it.
+
+// What a file is preloaded as, which decides how textures are compressed
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Preload {
+    Mesh(PathBuf),
+    ColorTexture(PathBuf),
+    NormalMap(PathBuf),
+}
+
+impl Preload {
+    pub fn path(&self) -> &Path {
+        match self {
+            Preload::Mesh(path) | Preload::ColorTexture(path) | Preload::NormalMap(path) => path
+        }
+    }
+}
+
+#[derive(Clone)]
+pub(super) enum Prepared {
+    Mesh(Arc<PreparedMesh>),
+    Texture(Arc<PreparedTexture>),
+}
+
+pub(super) type PreloadCache = Arc<Mutex<HashMap<Preload, Prepared>>>;
+
+#[derive(Default)]
+struct ProgressState {
+    done: AtomicUsize,
+    total: AtomicUsize,
+    // File name of what was started last
+    current: Mutex<String>,
+}
+
+// How far the preloads of an `AssetManager` are, shared with whatever shows it. Counts every file since the last time
+// all of them were done.
+#[derive(Clone, Default)]
+pub struct LoadProgress(Arc<ProgressState>);
+
+impl LoadProgress {
+    // From 0.0 to 1.0, 1.0 with nothing to load
+    pub fn fraction(&self) -> f32 {
+        match self.0.total.load(Ordering::Acquire) {
+            0 => 1.0,
+            total => self.0.done.load(Ordering::Acquire).min(total) as f32 / total as f32
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.0.done.load(Ordering::Acquire) >= self.0.total.load(Ordering::Acquire)
+    }
+
+    // Files done and files in total
+    pub fn counts(&self) -> (usize, usize) {
+        (self.0.done.load(Ordering::Acquire), self.0.total.load(Ordering::Acquire))
+    }
+
+    // Name of a file being loaded, empty before the first one
+    pub fn current(&self) -> String {
+        self.0.current.lock().unwrap().clone()
+    }
+
+    fn add(&self, count: usize) {
+        if self.is_finished() {
+            self.0.done.store(0, Ordering::Release);
+            self.0.total.store(0, Ordering::Release);
+        }
+        self.0.total.fetch_add(count, Ordering::AcqRel);
+    }
+
+    fn start(&self, preload: &Preload) {
+        let path = preload.path();
+        *self.0.current.lock().unwrap() = path.file_name().map_or_else(|| path.display().to_string(), |name| name.to_string_lossy().into_owned());
+    }
+
+    fn advance(&self) {
+        self.0.done.fetch_add(1, Ordering::AcqRel);
+    }
+}
+
+impl AssetManager {
+    // Starts reading the files on a background thread and returns right away, files already preloaded or on their way
+    // are skipped. Textures are compressed if the renderer samples compressed ones, pass what
+    // `VulkanRenderer::supports_texture_compression` says.
+    pub fn preload(&mut self, files: impl IntoIterator<Item = Preload>, compress: bool) -> LoadProgress {
+        if self.progress.is_finished() {
+            self.preloading.clear();
+        }
+        let files: Vec<Preload> = {
+            let cache = self.preloaded.lock().unwrap();
+            files.into_iter().filter(|file| !cache.contains_key(file) && !self.preloading.contains(file)).collect()
+        };
+        if files.is_empty() {
+            return self.progress.clone();
+        }
+        self.preloading.extend(files.iter().cloned());
+        self.progress.add(files.len());
+
+        let progress = self.progress.clone();
+        let cache = self.preloaded.clone();
+        let texture_cache = self.texture_cache.clone();
+        std::thread::Builder::new()
+            .name("Asset Preloader".to_string())
+            .spawn(move || jobs::pool().install(|| files.par_iter().for_each(|file| {
+                progress.start(file);
+                let prepared = match file {
+                    Preload::Mesh(path) => AssetManager::prepare_mesh(path).map(|mesh| Prepared::Mesh(Arc::new(mesh))),
+                    Preload::ColorTexture(path) => AssetManager::prepare_texture(texture_cache.as_deref(), path, TextureUsage::Color, compress)
+                        .map(|texture| Prepared::Texture(Arc::new(texture))),
+                    Preload::NormalMap(path) => AssetManager::prepare_texture(texture_cache.as_deref(), path, TextureUsage::NormalMap, compress)
+                        .map(|texture| Prepared::Texture(Arc::new(texture)))
+                };
+                // Loading the file when it's used reports the error again, where it can be handled
+                match prepared {
+                    Ok(prepared) => {
+                        cache.lock().unwrap().insert(file.clone(), prepared);
+                    }
+                    Err(error) => tracing::warn!("Failed to preload {}: {}", file.path().display(), error)
+                }
+                progress.advance();
+            })))
+            .expect("Failed to spawn the asset preloader!");
+
+        self.progress.clone()
+    }
+
+    // Of every preload since the last time they were all done
+    pub fn progress(&self) -> LoadProgress {
+        self.progress.clone()
+    }
+
+    pub fn is_preloaded(&self, file: &Preload) -> bool {
+        self.preloaded.lock().unwrap().contains_key(file)
+    }
+
+    // Frees the preloaded copies, files are read again from then on
+    pub fn clear_preloaded(&mut self) {
+        self.preloaded.lock().unwrap().clear();
+    }
+
+    pub(super) fn preloaded(&self, file: &Preload) -> Option<Prepared> {
+        self.preloaded.lock().unwrap().get(file).cloned()
+    }
+}
\ No newline at end of file
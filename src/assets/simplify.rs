@@ -0,0 +1,325 @@
+use std::collections::HashMap;
+
+use crate::vulkan::mesh::MeshLod;
+use crate::vulkan::vertex::Vertex;
+
+use super::optimize;
+
+// Triangle counts tried for each level after the first, relative to the full mesh
+pub const LOD_RATIOS: [f32; 3] = [0.5, 0.25, 0.125];
+// Meshes with fewer triangles are cheap enough at full detail
+pub const LOD_MIN_TRIANGLES: usize = 256;
+// A level has to drop at least this share of the previous one's triangles to be worth keeping
+const LOD_MIN_REDUCTION: f32 = 0.2;
+
+// Sum of squared distances to a set of planes, weighted by the area of the triangle each came from
+#[derive(Clone, Copy, Default)]
+struct Quadric {
+    xx: f64, xy: f64, xz: f64, xw: f64,
+    yy: f64, yz: f64, yw: f64,
+    zz: f64, zw: f64,
+    ww: f64,
+    weight: f64,
+}
+
+impl Quadric {
+    fn from_plane(normal: uv::Vec3, distance: f32, weight: f32) -> Self {
+        let [a, b, c, d] = [normal.x, normal.y, normal.z, distance].map(f64::from);
+        let weight = f64::from(weight);
+        Self {
+            xx: a * a * weight, xy: a * b * weight, xz: a * c * weight, xw: a * d * weight,
+            yy: b * b * weight, yz: b * c * weight, yw: b * d * weight,
+            zz: c * c * weight, zw: c * d * weight,
+            ww: d * d * weight,
+            weight
+        }
+    }
+
+    fn add(&mut self, other: &Self) {
+        self.xx += other.xx; self.xy += other.xy; self.xz += other.xz; self.xw += other.xw;
+        self.yy += other.yy; self.yz += other.yz; self.yw += other.yw;
+        self.zz += other.zz; self.zw += other.zw;
+        self.ww += other.ww;
+        self.weight += other.weight;
+    }
+
+    // Mean squared distance of `point` to the planes
+    fn error(&self, point: uv::Vec3) -> f32 {
+        if self.weight <= 0.0 {
+            return 0.0;
+        }
+        let [x, y, z] = [point.x, point.y, point.z].map(f64::from);
+        let sum = self.xx * x * x + self.yy * y * y + self.zz * z * z + self.ww
+            + 2.0 * (self.xy * x * y + self.xz * x * z + self.yz * y * z + self.xw * x + self.yw * y + self.zw * z);
+        (sum / self.weight).max(0.0) as f32
+    }
+}
+
+// Collapses edges by the quadric error of moving one end onto the other, until about `target_ratio` of the triangles
+// are left or nothing more can go. Vertices only ever move onto other vertices, so the result indexes the same vertex
+// list. Vertices on open borders and attribute seams (one position, several vertices) are locked to keep the outline
+// and the seams closed. Returns the indices and the furthest any surface moved, in the mesh's own units.
+pub fn simplify(vertices: &[Vertex], indices: &[u32], target_ratio: f32) -> (Vec<u32>, f32) {
+    let target_index_count = ((indices.len() / 3) as f32 * target_ratio.clamp(0.0, 1.0)) as usize * 3;
+    let locked = locked_vertices(vertices, indices);
+
+    let mut quadrics = vec![Quadric::default(); vertices.len()];
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [triangle[0], triangle[1], triangle[2]].map(|index| vertices[index as usize].pos);
+        let cross = (b - a).cross(c - a);
+        let area = cross.mag() * 0.5;
+        if area <= 0.0 {
+            continue;
+        }
+        let normal = cross.normalized();
+        let quadric = Quadric::from_plane(normal, -normal.dot(a), area);
+        for &index in triangle {
+            quadrics[index as usize].add(&quadric);
+        }
+    }
+
+    let mut indices = indices.to_vec();
+    let mut error = 0.0f32;
+    // Every pass collapses edges that don't share a vertex with one already collapsed in the same pass
+    while indices.len() > target_index_count {
+        let mut adjacency: Vec<Vec<u32>> = vec![vec![]; vertices.len()];
+        for (triangle, corners) in indices.chunks_exact(3).enumerate() {
+            for &vertex in corners {
+                adjacency[vertex as usize].push(triangle as u32);
+            }
+        }
+
+        let mut candidates: Vec<(f32, u32, u32)> = indices
+            .chunks_exact(3)
+            .flat_map(|triangle| [(triangle[0], triangle[1]), (triangle[1], triangle[2]), (triangle[2], triangle[0])])
+            .flat_map(|(a, b)| [(a, b), (b, a)])
+            .filter(|&(from, _)| !locked[from as usize])
+            .map(|(from, to)| (quadrics[from as usize].error(vertices[to as usize].pos), from, to))
+            .collect();
+        candidates.sort_by(|(a, _, _), (b, _, _)| a.total_cmp(b));
+
+        let mut remap: Vec<u32> = (0..vertices.len() as u32).collect();
+        let mut touched = vec![false; vertices.len()];
+        let mut remaining = indices.len();
+        for (cost, from, to) in candidates {
+            if remaining <= target_index_count {
+                break;
+            }
+            let [from, to] = [from as usize, to as usize];
+            if touched[from] || touched[to] || flips(vertices, &indices, &adjacency[from], from, to) {
+                continue;
+            }
+
+            // The triangles around `from` change shape, another collapse moving one of their corners in the same pass
+            // would make the flip check above wrong
+            remap[from] = to as u32;
+            for &triangle in &adjacency[from] {
+                for &corner in &indices[triangle as usize * 3..triangle as usize * 3 + 3] {
+                    touched[corner as usize] = true;
+                }
+            }
+            let quadric = quadrics[from];
+            quadrics[to].add(&quadric);
+            error = error.max(cost);
+            remaining -= 3 * adjacency[from]
+                .iter()
+                .filter(|&&triangle| indices[triangle as usize * 3..triangle as usize * 3 + 3].contains(&(to as u32)))
+                .count();
+        }
+        if remaining == indices.len() {
+            break;
+        }
+
+        indices = indices
+            .chunks_exact(3)
+            .map(|triangle| [triangle[0], triangle[1], triangle[2]].map(|index| remap[index as usize]))
+            .filter(|[a, b, c]| a != b && b != c && a != c)
+            .flatten()
+            .collect();
+    }
+
+    (indices, error.sqrt())
+}
+
+// Coarser versions of an imported mesh, each simplified from the full mesh and ordered for the vertex cache. Stops
+// early when simplifying no longer removes much, which happens on meshes made mostly of borders and seams.
+pub fn lod_chain(vertices: &[Vertex], indices: &[u32]) -> Vec<MeshLod> {
+    if indices.len() / 3 < LOD_MIN_TRIANGLES {
+        return vec![];
+    }
+
+    let mut lods: Vec<MeshLod> = vec![];
+    for ratio in LOD_RATIOS {
+        let (simplified, error) = simplify(vertices, indices, ratio);
+        let previous = lods.last().map_or(indices.len(), |lod| lod.indices.len());
+        if simplified.len() as f32 > previous as f32 * (1.0 - LOD_MIN_REDUCTION) {
+            break;
+        }
+        lods.push(MeshLod {
+            indices: optimize::optimize_vertex_cache(&simplified, vertices.len()),
+            error
+        });
+    }
+
+    tracing::debug!("Generated {} levels of detail with {:?} triangles", lods.len(),
+        lods.iter().map(|lod| lod.indices.len() / 3).collect::<Vec<_>>());
+    lods
+}
+
+// Whether moving `from` onto `to` turns any remaining triangle around `from` over
+fn flips(vertices: &[Vertex], indices: &[u32], triangles: &[u32], from: usize, to: usize) -> bool {
+    triangles.iter().any(|&triangle| {
+        let corners = &indices[triangle as usize * 3..triangle as usize * 3 + 3];
+        if corners.contains(&(to as u32)) {
+            return false;
+        }
+        let [a, b, c] = [corners[0], corners[1], corners[2]].map(|index| vertices[index as usize].pos);
+        let moved = [corners[0], corners[1], corners[2]].map(|index| match index as usize == from {
+            true => vertices[to].pos,
+            false => vertices[index as usize].pos
+        });
+        let before = (b - a).cross(c - a);
+        let after = (moved[1] - moved[0]).cross(moved[2] - moved[0]);
+        before.dot(after) <= 0.0
+    })
+}
+
+// Vertices sharing their position with another vertex, or on an edge used by a single triangle
+fn locked_vertices(vertices: &[Vertex], indices: &[u32]) -> Vec<bool> {
+    let position_key = |vertex: &Vertex| [vertex.pos.x, vertex.pos.y, vertex.pos.z].map(|component| (component + 0.0).to_bits());
+    let mut positions: HashMap<[u32; 3], u32> = HashMap::with_capacity(vertices.len());
+    for vertex in vertices {
+        *positions.entry(position_key(vertex)).or_insert(0) += 1;
+    }
+    let mut locked: Vec<bool> = vertices.iter().map(|vertex| positions[&position_key(vertex)] > 1).collect();
+
+    let mut edges: HashMap<(u32, u32), u32> = HashMap::with_capacity(indices.len());
+    for triangle in indices.chunks_exact(3) {
+        for (a, b) in [(triangle[0], triangle[1]), (triangle[1], triangle[2]), (triangle[2], triangle[0])] {
+            *edges.entry((a.min(b), a.max(b))).or_insert(0) += 1;
+        }
+    }
+    for ((a, b), _) in edges.into_iter().filter(|&(_, count)| count == 1) {
+        locked[a as usize] = true;
+        locked[b as usize] = true;
+    }
+
+    locked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    const SIZE: usize = 8;
+
+    fn vertex(x: f32, y: f32, z: f32) -> Vertex {
+        Vertex {
+            pos: uv::Vec3::new(x, y, z),
+            color: uv::Vec3::one(),
+            normal: uv::Vec3::unit_y(),
+            lightmap_uv: uv::Vec2::zero(),
+            tex_coord: uv::Vec2::new(x, z),
+            tangent: uv::Vec4::unit_x()
+        }
+    }
+
+    // SIZE by SIZE quads facing up, at the height `height` gives each grid point
+    fn grid(height: impl Fn(usize, usize) -> f32) -> (Vec<Vertex>, Vec<u32>) {
+        let index = |x: usize, z: usize| (z * (SIZE + 1) + x) as u32;
+        let vertices = (0..=SIZE).flat_map(|z| (0..=SIZE).map(move |x| (x, z))).map(|(x, z)| vertex(x as f32, height(x, z), z as f32)).collect();
+        let indices = (0..SIZE)
+            .flat_map(|z| (0..SIZE).map(move |x| (x, z)))
+            .flat_map(|(x, z)| [index(x, z), index(x, z + 1), index(x + 1, z), index(x + 1, z), index(x, z + 1), index(x + 1, z + 1)])
+            .collect();
+        (vertices, indices)
+    }
+
+    fn bumpy(x: usize, z: usize) -> f32 {
+        ((x * 7 + z * 3) % 5) as f32 * 0.1
+    }
+
+    fn normals(vertices: &[Vertex], indices: &[u32]) -> Vec<uv::Vec3> {
+        indices
+            .chunks_exact(3)
+            .map(|triangle| {
+                let [a, b, c] = [triangle[0], triangle[1], triangle[2]].map(|index| vertices[index as usize].pos);
+                (b - a).cross(c - a)
+            })
+            .collect()
+    }
+
+    // Edges used by a single triangle
+    fn outline(indices: &[u32]) -> HashSet<(u32, u32)> {
+        let mut edges: HashMap<(u32, u32), u32> = HashMap::new();
+        for triangle in indices.chunks_exact(3) {
+            for (a, b) in [(triangle[0], triangle[1]), (triangle[1], triangle[2]), (triangle[2], triangle[0])] {
+                *edges.entry((a.min(b), a.max(b))).or_insert(0) += 1;
+            }
+        }
+        edges.into_iter().filter(|&(_, count)| count == 1).map(|(edge, _)| edge).collect()
+    }
+
+    #[test]
+    fn meets_the_target_ratio() {
+        let (vertices, indices) = grid(bumpy);
+        let (simplified, _) = simplify(&vertices, &indices, 0.5);
+        let triangles = indices.len() / 3;
+        assert!(simplified.len() / 3 <= triangles / 2 && simplified.len() / 3 >= triangles / 2 - 4, "{} triangles", simplified.len() / 3);
+        assert_eq!(simplify(&vertices, &indices, 1.0).0, indices);
+    }
+
+    #[test]
+    fn flat_plane_simplifies_without_error() {
+        let (vertices, indices) = grid(|_, _| 1.0);
+        let (simplified, error) = simplify(&vertices, &indices, 0.25);
+        assert!(simplified.len() < indices.len() / 2);
+        assert_eq!(error, 0.0);
+
+        let (vertices, indices) = grid(bumpy);
+        assert!(simplify(&vertices, &indices, 0.25).1 > 0.0);
+    }
+
+    #[test]
+    fn keeps_the_outline() {
+        let (vertices, indices) = grid(bumpy);
+        let (simplified, _) = simplify(&vertices, &indices, 0.1);
+        assert_eq!(outline(&simplified), outline(&indices));
+    }
+
+    #[test]
+    fn keeps_seams_closed() {
+        // The middle column of points is split into two vertices, the right half of the grid uses the copies
+        let (mut vertices, mut indices) = grid(bumpy);
+        let middle: Vec<u32> = (0..=SIZE).map(|z| (z * (SIZE + 1) + SIZE / 2) as u32).collect();
+        let copies: Vec<u32> = (0..middle.len() as u32).map(|offset| vertices.len() as u32 + offset).collect();
+        let split: Vec<Vertex> = middle.iter().map(|&index| Vertex { tex_coord: uv::Vec2::new(1.0, 1.0), ..vertices[index as usize] }).collect();
+        vertices.extend(split);
+        for triangle in indices.chunks_exact_mut(3) {
+            let right = triangle.iter().any(|&index| index as usize % (SIZE + 1) > SIZE / 2);
+            for index in triangle.iter_mut().filter(|_| right) {
+                if let Some(position) = middle.iter().position(|middle| middle == index) {
+                    *index = copies[position];
+                }
+            }
+        }
+
+        let (simplified, _) = simplify(&vertices, &indices, 0.1);
+        assert!(simplified.len() < indices.len() / 2);
+        let used: HashSet<u32> = simplified.iter().copied().collect();
+        assert!(middle.iter().chain(&copies).all(|index| used.contains(index)));
+        assert_eq!(outline(&simplified), outline(&indices));
+    }
+
+    #[test]
+    fn never_flips_triangles() {
+        let (vertices, indices) = grid(bumpy);
+        assert!(normals(&vertices, &indices).iter().all(|normal| normal.y > 0.0));
+        // Triangles standing upright along the locked border close the gap to the coarser inside, none may face down
+        for ratio in [0.5, 0.25, 0.1] {
+            let (simplified, _) = simplify(&vertices, &indices, ratio);
+            assert!(normals(&vertices, &simplified).iter().all(|normal| normal.y >= 0.0), "Flipped at {}", ratio);
+        }
+    }
+}
\ No newline at end of file
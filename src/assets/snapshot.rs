@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use super::AssetManager;
+use super::preload::Preload;
+use crate::vulkan::camera::Camera;
+use crate::vulkan::game_object::{GameObject, Transform3DComponent};
+use crate::vulkan::lights::{DirectionalLight, PointLight, SpotLight};
+use crate::vulkan::material::Material;
+use crate::vulkan::renderer::VulkanRenderer;
+use crate::vulkan::water::WaterMaterial;
+use crate::vulkan::wind::Wind;
+
+// How a game object is shaded, its textures referred to by the files they were applied from. Baked lightmaps aren't
+// kept, lightmapped objects load as `Material::Basic` until they're baked again.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MaterialRefs {
+    pub material: Material,
+    pub color: uv::Vec3,
+    pub opacity: f32,
+    pub roughness: f32,
+    pub parallax_depth: f32,
+    pub alpha_cutoff: f32,
+    pub wind: f32,
+    pub water: WaterMaterial,
+    pub color_texture: Option<PathBuf>,
+    pub normal_map: Option<PathBuf>,
+}
+
+impl MaterialRefs {
+    fn capture(game_object: &GameObject, assets: &AssetManager) -> Self {
+        let id = game_object.get_id();
+        Self {
+            material: game_object.material,
+            color: game_object.color,
+            opacity: game_object.opacity,
+            roughness: game_object.roughness,
+            parallax_depth: game_object.parallax_depth,
+            alpha_cutoff: game_object.alpha_cutoff,
+            wind: game_object.wind,
+            water: game_object.water,
+            color_texture: game_object.texture.and(assets.color_textures.get(&id).cloned()),
+            normal_map: game_object.normal_map.and(assets.normal_maps.get(&id).cloned())
+        }
+    }
+
+    // Everything but the textures, which have to be loaded
+    fn apply(&self, game_object: &mut GameObject) {
+        game_object.material = match self.material {
+            Material::Lightmapped => Material::Basic,
+            material => material
+        };
+        game_object.color = self.color;
+        game_object.opacity = self.opacity;
+        game_object.roughness = self.roughness;
+        game_object.parallax_depth = self.parallax_depth;
+        game_object.alpha_cutoff = self.alpha_cutoff;
+        game_object.wind = self.wind;
+        game_object.water = self.water;
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ObjectSnapshot {
+    // Id of the object when it was captured, what `parent` refers to. Loaded objects get new ids.
+    pub id: usize,
+    pub name: String,
+    pub parent: Option<usize>,
+    // Mesh file the object was instantiated from, `None` for meshes built in code, which can't be loaded again
+    pub mesh: Option<PathBuf>,
+    pub transform: Transform3DComponent,
+    pub material: MaterialRefs,
+}
+
+// A scene in a form any serde format can write: the game objects with their components and the renderer's lights,
+// camera and wind. Meshes and textures are referred to by their files, which are read again on load, so scenes, prefabs
+// and save games stay small.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct WorldSnapshot {
+    pub objects: Vec<ObjectSnapshot>,
+    pub lights: Vec<PointLight>,
+    pub spot_lights: Vec<SpotLight>,
+    pub sun: Option<DirectionalLight>,
+    pub ambient_light: uv::Vec3,
+    pub camera: Camera,
+    pub wind: Wind,
+}
+
+impl WorldSnapshot {
+    // `assets` knows which files the objects were loaded from
+    pub fn capture(renderer: &VulkanRenderer, assets: &AssetManager) -> Self {
+        let objects = renderer.game_objects
+            .iter()
+            .map(|game_object| ObjectSnapshot {
+                id: game_object.get_id(),
+                name: game_object.name.clone(),
+                parent: game_object.parent,
+                mesh: assets.instances.get(&game_object.get_id()).cloned(),
+                transform: game_object.transform3d,
+                material: MaterialRefs::capture(game_object, assets)
+            })
+            .collect();
+
+        Self {
+            objects,
+            lights: renderer.lights.clone(),
+            spot_lights: renderer.spot_lights.clone(),
+            sun: renderer.sun,
+            ambient_light: renderer.ambient_light,
+            camera: renderer.camera.clone(),
+            wind: renderer.wind
+        }
+    }
+
+    // Every mesh and texture file the snapshot loads, to preload it before `load`
+    pub fn files(&self) -> Vec<Preload> {
+        let mut files = vec![];
+        for object in &self.objects {
+            files.extend(object.mesh.clone().map(Preload::Mesh));
+            files.extend(object.material.color_texture.clone().map(Preload::ColorTexture));
+            files.extend(object.material.normal_map.clone().map(Preload::NormalMap));
+        }
+        files
+    }
+
+    // Replaces the renderer's game objects, lights, camera and wind with the snapshot's. Returns the new id of every
+    // loaded object by its id in the snapshot, objects without a mesh file are skipped. The camera keeps the aspect
+    // ratio of the current viewport.
+    pub fn load(&self, renderer: &mut VulkanRenderer, assets: &mut AssetManager) -> Result<HashMap<usize, usize>, Box<dyn std::error::Error>> {
+        let ids: Vec<usize> = renderer.game_objects.iter().map(GameObject::get_id).collect();
+        for id in ids {
+            renderer.remove_game_object(id)?;
+        }
+
+        let mut loaded = HashMap::new();
+        for object in &self.objects {
+            let mesh = match &object.mesh {
+                Some(mesh) => mesh,
+                None => {
+                    tracing::warn!("{} has no mesh file, it isn't loaded", object.name);
+                    continue;
+                }
+            };
+
+            let id = assets.instantiate_file(renderer, mesh, object.transform.translation)?;
+            let index = renderer.game_objects.iter().position(|game_object| game_object.get_id() == id).unwrap();
+            let game_object = &mut renderer.game_objects[index];
+            game_object.name = object.name.clone();
+            game_object.transform3d = object.transform;
+            object.material.apply(game_object);
+
+            if let Some(path) = &object.material.color_texture {
+                assets.apply_color_texture_file(renderer, path, index)?;
+            }
+            if let Some(path) = &object.material.normal_map {
+                assets.apply_normal_map_file(renderer, path, index)?;
+            }
+            loaded.insert(object.id, id);
+        }
+
+        // Only known once every object has its new id, objects whose parent wasn't loaded become roots
+        for object in &self.objects {
+            if let Some(&id) = loaded.get(&object.id) {
+                let game_object = renderer.game_objects.iter_mut().find(|game_object| game_object.get_id() == id).unwrap();
+                game_object.parent = object.parent.and_then(|parent| loaded.get(&parent).copied());
+            }
+        }
+
+        renderer.lights = self.lights.clone();
+        renderer.spot_lights = self.spot_lights.clone();
+        renderer.sun = self.sun;
+        renderer.ambient_light = self.ambient_light;
+        renderer.camera = Camera {
+            aspect_ratio: renderer.camera.aspect_ratio,
+            ..self.camera.clone()
+        };
+        renderer.wind = self.wind;
+
+        Ok(loaded)
+    }
+}
\ No newline at end of file
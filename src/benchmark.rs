@@ -0,0 +1,75 @@
+use crate::vulkan::renderer::VulkanRenderer;
+
+// Frames rendered before measuring starts, while pipelines, caches and the driver settle
+const WARMUP_FRAMES: u32 = 16;
+// Allocations listed in the memory report, largest first
+const REPORTED_ALLOCATIONS: usize = 8;
+
+// Collects the time of a fixed number of frames and prints a summary, for comparing performance across changes
+pub struct Benchmark {
+    pub frames: u32,
+    pub warmup: u32,
+    frame_times: Vec<f32>,
+    skipped: u32,
+}
+
+impl Benchmark {
+    pub fn new(frames: u32) -> Self {
+        Self {
+            frames,
+            warmup: WARMUP_FRAMES,
+            frame_times: Vec::with_capacity(frames as usize),
+            skipped: 0
+        }
+    }
+
+    // Adds the duration of a frame in seconds, true for the frame that completes the measurement
+    pub fn record(&mut self, frame_time: f32) -> bool {
+        if self.skipped < self.warmup {
+            self.skipped += 1;
+            return false;
+        }
+        if self.is_finished() {
+            return false;
+        }
+        self.frame_times.push(frame_time);
+        self.is_finished()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.frame_times.len() >= self.frames as usize
+    }
+
+    // Nearest rank percentile of the measured frame times in seconds, `percentile` goes from 0 to 100
+    pub fn percentile(&self, percentile: f32) -> f32 {
+        let mut sorted = self.frame_times.clone();
+        sorted.sort_by(f32::total_cmp);
+        match sorted.len() {
+            0 => 0.0,
+            count => {
+                let rank = (percentile / 100.0 * count as f32).ceil() as usize;
+                sorted[rank.clamp(1, count) - 1]
+            }
+        }
+    }
+
+    pub fn print_report(&self, renderer: &VulkanRenderer) {
+        let device_name = unsafe { std::ffi::CStr::from_ptr(renderer.physical_device_properties.device_name.as_ptr()) };
+        let extent = renderer.viewport.render_extent;
+        let average = self.frame_times.iter().sum::<f32>() / self.frame_times.len().max(1) as f32;
+        let ms = |seconds: f32| seconds * 1000.0;
+
+        println!("Benchmark: {} frames at {}x{} on {}", self.frame_times.len(), extent.width, extent.height, device_name.to_string_lossy());
+        println!("Frame time (ms): avg {:.3}  min {:.3}  p50 {:.3}  p90 {:.3}  p95 {:.3}  p99 {:.3}  max {:.3}",
+            ms(average), ms(self.percentile(0.0)), ms(self.percentile(50.0)), ms(self.percentile(90.0)), ms(self.percentile(95.0)),
+            ms(self.percentile(99.0)), ms(self.percentile(100.0)));
+        println!("Average FPS: {:.1}", 1.0 / average.max(f32::EPSILON));
+
+        let stats = renderer.render_stats();
+        println!("Draws: {} scene, {} reflection, {} shadow ({} shadow views), {} triangles",
+            stats.scene_draws, stats.reflection_draws, stats.shadow_draws, stats.shadow_views, stats.triangles);
+        println!("Scene: {} game objects, {} point lights, {} spot lights", stats.game_objects, stats.point_lights, stats.spot_lights);
+        // The allocator's own breakdown, used against reserved memory followed by the largest allocations
+        print!("{:.*?}", REPORTED_ALLOCATIONS, *renderer.allocator);
+    }
+}
\ No newline at end of file
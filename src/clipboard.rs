@@ -0,0 +1,50 @@
+use std::cell::RefCell;
+use std::sync::Mutex;
+
+// Text on the system clipboard, for the UI and for game code. Opened on first use on each thread that uses it, as not
+// every platform's clipboard can move between threads. Where there's no system clipboard, headless or on a platform
+// arboard doesn't support, text is only copied within the process.
+
+thread_local! {
+    static SYSTEM: RefCell<Option<arboard::Clipboard>> = RefCell::new(open());
+}
+
+// Stands in for the system clipboard while there's none
+static LOCAL: Mutex<String> = Mutex::new(String::new());
+
+fn open() -> Option<arboard::Clipboard> {
+    arboard::Clipboard::new()
+        .map_err(|error| tracing::warn!("No system clipboard, copied text stays within the game: {}", error))
+        .ok()
+}
+
+// `None` when the clipboard holds no text, like an image or nothing at all
+pub fn get_text() -> Option<String> {
+    let system = SYSTEM.with(|system| system.borrow_mut().as_mut().map(|clipboard| clipboard.get_text().ok()));
+    match system {
+        Some(text) => text,
+        None => {
+            let local = LOCAL.lock().unwrap();
+            match local.is_empty() {
+                true => None,
+                false => Some(local.clone())
+            }
+        }
+    }
+}
+
+pub fn set_text(text: impl Into<String>) {
+    let text = text.into();
+    let copied = SYSTEM.with(|system| match system.borrow_mut().as_mut() {
+        Some(clipboard) => {
+            if let Err(error) = clipboard.set_text(text.as_str()) {
+                tracing::warn!("Failed to copy to the clipboard: {}", error);
+            }
+            true
+        }
+        None => false
+    });
+    if !copied {
+        *LOCAL.lock().unwrap() = text;
+    }
+}
\ No newline at end of file
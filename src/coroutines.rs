@@ -0,0 +1,319 @@
+use std::cell::{Cell, RefCell};
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll, Waker};
+
+// Gameplay sequences written as async blocks that run across simulation ticks instead of as hand-rolled state machines:
+//
+//   coroutines.start(|co| async move {
+//       co.seconds(2.0).await;
+//       co.with(|world| world.spawn_enemy());
+//   });
+//
+// `Coroutines::update` runs once per simulation tick. Every coroutine that isn't waiting continues up to its next
+// `await` on one of the waits of `Co`, in the order they were started, so deterministic runs sequence the same way.
+// Awaiting other futures works as long as they finish without a waker, nothing ever wakes a coroutine up.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CoroutineId(u64);
+
+type Coroutine = (CoroutineId, Pin<Box<dyn Future<Output = ()>>>);
+
+struct Shared<C> {
+    // Simulated seconds and ticks `update` ran for
+    time: Cell<f64>,
+    tick: Cell<u64>,
+    // The context passed to `update`, only set while it polls the coroutines and taken out while `with` lends it
+    context: Cell<*mut C>,
+    // Not run yet, the ones started between updates wait for the next one
+    started: RefCell<Vec<Coroutine>>,
+    next_id: Cell<u64>,
+}
+
+impl<C> Shared<C> {
+    fn start(&self, coroutine: impl Future<Output = ()> + 'static) -> CoroutineId {
+        let id = CoroutineId(self.next_id.get());
+        self.next_id.set(id.0 + 1);
+        self.started.borrow_mut().push((id, Box::pin(coroutine)));
+        id
+    }
+}
+
+// What a coroutine waits with and reaches the context `C` through. Cheap to clone into nested async blocks.
+pub struct Co<C> {
+    shared: Rc<Shared<C>>,
+}
+
+impl<C> Clone for Co<C> {
+    fn clone(&self) -> Self {
+        Self { shared: self.shared.clone() }
+    }
+}
+
+impl<C: 'static> Co<C> {
+    // Lends the context to `use_context`. Panics when called outside of `Coroutines::update` or from within another `with`.
+    pub fn with<R>(&self, use_context: impl FnOnce(&mut C) -> R) -> R {
+        let context = self.shared.context.replace(std::ptr::null_mut());
+        assert!(!context.is_null(), "Coroutine context used outside of an update or while already lent!");
+        // Safety: the pointer comes from the `&mut C` that `update` holds while polling, and it's taken out of the cell
+        // for the duration of the call, so there's never a second reference to it
+        let result = use_context(unsafe { &mut *context });
+        self.shared.context.set(context);
+        result
+    }
+
+    pub fn time(&self) -> f64 {
+        self.shared.time.get()
+    }
+
+    pub fn tick(&self) -> u64 {
+        self.shared.tick.get()
+    }
+
+    // Continues once `seconds` of simulated time passed
+    pub fn seconds(&self, seconds: f32) -> impl Future<Output = ()> {
+        let shared = self.shared.clone();
+        let mut until = None;
+        std::future::poll_fn(move |_| {
+            let until = *until.get_or_insert(shared.time.get() + seconds as f64);
+            match shared.time.get() >= until {
+                true => Poll::Ready(()),
+                false => Poll::Pending
+            }
+        })
+    }
+
+    // Continues `ticks` updates later
+    pub fn ticks(&self, ticks: u64) -> impl Future<Output = ()> {
+        let shared = self.shared.clone();
+        let mut until = None;
+        std::future::poll_fn(move |_| {
+            let until = *until.get_or_insert(shared.tick.get() + ticks);
+            match shared.tick.get() >= until {
+                true => Poll::Ready(()),
+                false => Poll::Pending
+            }
+        })
+    }
+
+    pub fn next_tick(&self) -> impl Future<Output = ()> {
+        self.ticks(1)
+    }
+
+    // Continues on the first update `condition` holds on, checked right away too
+    pub fn until(&self, mut condition: impl FnMut(&mut C) -> bool) -> impl Future<Output = ()> {
+        let co = self.clone();
+        std::future::poll_fn(move |_| match co.with(&mut condition) {
+            true => Poll::Ready(()),
+            false => Poll::Pending
+        })
+    }
+
+    // Runs alongside this one, up to its first wait later in the same update
+    pub fn start<F, Fut>(&self, coroutine: F) -> CoroutineId
+    where F: FnOnce(Co<C>) -> Fut, Fut: Future<Output = ()> + 'static {
+        self.shared.start(coroutine(self.clone()))
+    }
+}
+
+pub struct Coroutines<C> {
+    shared: Rc<Shared<C>>,
+    running: Vec<Coroutine>,
+}
+
+impl<C: 'static> Default for Coroutines<C> {
+    fn default() -> Self {
+        Self {
+            shared: Rc::new(Shared {
+                time: Cell::new(0.0),
+                tick: Cell::new(0),
+                context: Cell::new(std::ptr::null_mut()),
+                started: RefCell::new(vec![]),
+                next_id: Cell::new(0)
+            }),
+            running: vec![]
+        }
+    }
+}
+
+impl<C: 'static> Coroutines<C> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Runs up to its first wait on the next update
+    pub fn start<F, Fut>(&mut self, coroutine: F) -> CoroutineId
+    where F: FnOnce(Co<C>) -> Fut, Fut: Future<Output = ()> + 'static {
+        self.shared.start(coroutine(Co { shared: self.shared.clone() }))
+    }
+
+    // Drops it wherever it waits, false when it already finished
+    pub fn cancel(&mut self, id: CoroutineId) -> bool {
+        let count = self.running.len() + self.shared.started.borrow().len();
+        self.running.retain(|(running, _)| *running != id);
+        self.shared.started.borrow_mut().retain(|(started, _)| *started != id);
+        self.running.len() + self.shared.started.borrow().len() != count
+    }
+
+    pub fn clear(&mut self) {
+        self.running.clear();
+        drop(self.shared.started.take());
+    }
+
+    pub fn is_running(&self, id: CoroutineId) -> bool {
+        self.running.iter().any(|(running, _)| *running == id) || self.shared.started.borrow().iter().any(|(started, _)| *started == id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.running.len() + self.shared.started.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Advances by one tick of `delta` seconds and continues the coroutines done waiting
+    pub fn update(&mut self, delta: f32, context: &mut C) {
+        self.shared.time.set(self.shared.time.get() + delta.max(0.0) as f64);
+        self.shared.tick.set(self.shared.tick.get() + 1);
+        self.running.append(&mut self.shared.started.borrow_mut());
+
+        self.shared.context.set(context);
+        // Unset again however polling ends, a coroutine that panics mustn't leave the cell pointing at a dead context
+        let _lent = Lent(&self.shared.context);
+        let mut task_context = Context::from_waker(Waker::noop());
+        // Coroutines started by others run after them, still on this update
+        let mut index = 0;
+        loop {
+            if index == self.running.len() {
+                let mut started = self.shared.started.take();
+                if started.is_empty() {
+                    break;
+                }
+                self.running.append(&mut started);
+            }
+
+            match self.running[index].1.as_mut().poll(&mut task_context) {
+                Poll::Ready(()) => drop(self.running.remove(index)),
+                Poll::Pending => index += 1
+            }
+        }
+    }
+}
+
+// The coroutines waiting to start hold the shared state themselves
+impl<C> Drop for Coroutines<C> {
+    fn drop(&mut self) {
+        drop(self.shared.started.take());
+    }
+}
+
+struct Lent<'a, C>(&'a Cell<*mut C>);
+
+impl<C> Drop for Lent<'_, C> {
+    fn drop(&mut self) {
+        self.0.set(std::ptr::null_mut());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // What each coroutine logged and the tick it did so on
+    type Log = Vec<(&'static str, u64)>;
+
+    fn note(co: &Co<Log>, name: &'static str) {
+        let tick = co.tick();
+        co.with(|log| log.push((name, tick)));
+    }
+
+    fn run(coroutines: &mut Coroutines<Log>, updates: usize, delta: f32) -> Log {
+        let mut log = vec![];
+        for _ in 0..updates {
+            coroutines.update(delta, &mut log);
+        }
+        log
+    }
+
+    #[test]
+    fn ticks_resume_on_the_right_tick() {
+        let mut coroutines = Coroutines::new();
+        coroutines.start(|co| async move {
+            note(&co, "start");
+            co.ticks(3).await;
+            note(&co, "three later");
+            co.next_tick().await;
+            note(&co, "next");
+        });
+        assert_eq!(run(&mut coroutines, 6, 0.25), vec![("start", 1), ("three later", 4), ("next", 5)]);
+        assert!(coroutines.is_empty());
+    }
+
+    #[test]
+    fn seconds_resume_once_the_time_passed() {
+        let mut coroutines = Coroutines::new();
+        coroutines.start(|co| async move {
+            // The first update already ran the clock to 0.25, so this is due at 0.75
+            co.seconds(0.5).await;
+            note(&co, "half");
+            // Due at 1.375, which the update running the clock to 1.5 reaches
+            co.seconds(0.625).await;
+            note(&co, "later");
+            co.seconds(0.0).await;
+            note(&co, "right away");
+        });
+        assert_eq!(run(&mut coroutines, 8, 0.25), vec![("half", 3), ("later", 6), ("right away", 6)]);
+    }
+
+    #[test]
+    fn until_checks_the_context() {
+        let mut coroutines = Coroutines::new();
+        coroutines.start(|co| async move {
+            co.until(|log: &mut Log| log.len() >= 2).await;
+            note(&co, "done");
+        });
+        let mut log = vec![("first", 0)];
+        coroutines.update(0.25, &mut log);
+        log.push(("second", 0));
+        assert_eq!(log.len(), 2);
+        coroutines.update(0.25, &mut log);
+        assert_eq!(log.last(), Some(&("done", 2)));
+    }
+
+    #[test]
+    fn coroutines_run_in_the_order_they_started() {
+        let mut coroutines = Coroutines::new();
+        coroutines.start(|co| async move {
+            note(&co, "first");
+            co.start(|co| async move { note(&co, "nested") });
+            co.next_tick().await;
+            note(&co, "first again");
+        });
+        coroutines.start(|co| async move {
+            note(&co, "second");
+            co.next_tick().await;
+            note(&co, "second again");
+        });
+        assert_eq!(run(&mut coroutines, 2, 0.25), vec![("first", 1), ("second", 1), ("nested", 1), ("first again", 2),
+            ("second again", 2)]);
+    }
+
+    #[test]
+    fn cancelled_coroutines_stop_where_they_wait() {
+        let mut coroutines = Coroutines::new();
+        let waiting = coroutines.start(|co| async move {
+            co.ticks(2).await;
+            note(&co, "never");
+        });
+        let unstarted = coroutines.start(|co| async move { note(&co, "never started") });
+        assert!(coroutines.cancel(unstarted));
+        assert!(run(&mut coroutines, 1, 0.25).is_empty());
+
+        assert!(coroutines.is_running(waiting));
+        assert!(coroutines.cancel(waiting));
+        assert!(!coroutines.cancel(waiting));
+        assert!(run(&mut coroutines, 4, 0.25).is_empty());
+    }
+}
\ No newline at end of file
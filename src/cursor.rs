@@ -0,0 +1,148 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use winit::event::WindowEvent;
+use winit::window::{CursorIcon, Window};
+
+use crate::assets::AssetManager;
+
+// The mouse cursor of the window: one of the system's shapes, drawn by the OS, or an image of the game's. winit has
+// no way to hand the OS an image for its cursor, so images are drawn in software: the OS cursor is hidden and the
+// image follows the pointer on top of everything egui draws. That trails the pointer by the frame latency.
+
+// Tightly packed RGBA8 with straight alpha, the hotspot is the pixel that points
+#[derive(Clone, Debug, PartialEq)]
+pub struct CursorImage {
+    pub size: [u32; 2],
+    pub rgba: Vec<u8>,
+    pub hotspot: [u32; 2],
+}
+
+impl CursorImage {
+    pub fn new(size: [u32; 2], rgba: Vec<u8>, hotspot: [u32; 2]) -> Self {
+        assert_eq!(rgba.len(), (size[0] * size[1] * 4) as usize, "Cursor image has the wrong size for its extent!");
+        Self {
+            size,
+            rgba,
+            hotspot: [hotspot[0].min(size[0].saturating_sub(1)), hotspot[1].min(size[1].saturating_sub(1))]
+        }
+    }
+
+    // Any PNG
+    pub fn load(path: &Path, hotspot: [u32; 2]) -> Result<Self, Box<dyn std::error::Error>> {
+        let (size, rgba) = AssetManager::load_texture(path)?;
+        Ok(Self::new(size, rgba, hotspot))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Cursor {
+    System(CursorIcon),
+    // By the name it was added with, the default arrow while there's none by that name
+    Image(String),
+    Hidden,
+}
+
+struct LoadedCursor {
+    image: CursorImage,
+    // Uploaded the first time it's shown
+    texture: Option<egui::TextureHandle>,
+}
+
+pub struct Cursors {
+    images: HashMap<String, LoadedCursor>,
+    current: Cursor,
+    // What the window was told last, visibility and icon
+    applied: Option<(bool, CursorIcon)>,
+    // In pixels, `None` while the pointer is outside the window
+    position: Option<egui::Pos2>,
+    // Screen pixels per image pixel
+    pub scale: f32,
+    layer: egui::LayerId,
+}
+
+impl Default for Cursors {
+    fn default() -> Self {
+        Self {
+            images: HashMap::new(),
+            current: Cursor::System(CursorIcon::Default),
+            applied: None,
+            position: None,
+            scale: 1.0,
+            layer: egui::LayerId::new(egui::Order::Debug, egui::Id::new("software_cursor"))
+        }
+    }
+}
+
+impl Cursors {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Replaces the image of that name, if there is one
+    pub fn add(&mut self, name: impl Into<String>, image: CursorImage) {
+        self.images.insert(name.into(), LoadedCursor { image, texture: None });
+    }
+
+    pub fn remove(&mut self, name: &str) -> bool {
+        self.images.remove(name).is_some()
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.images.contains_key(name)
+    }
+
+    // Shown from the next `update`
+    pub fn set(&mut self, cursor: Cursor) {
+        self.current = cursor;
+    }
+
+    pub fn current(&self) -> &Cursor {
+        &self.current
+    }
+
+    // Follows the pointer, pass every window event
+    pub fn handle_event(&mut self, event: &WindowEvent) {
+        match event {
+            WindowEvent::CursorMoved { position, .. } => self.position = Some(egui::pos2(position.x as f32, position.y as f32)),
+            WindowEvent::CursorLeft { .. } => self.position = None,
+            _ => {}
+        }
+    }
+
+    // Once per frame while the UI frame is built, tells the window which cursor to show and draws software cursors
+    pub fn update(&mut self, window: &Window, context: &egui::Context) {
+        let image = match &self.current {
+            Cursor::Image(name) => self.images.get_mut(name.as_str()),
+            _ => None
+        };
+        let wanted = match (&self.current, &image) {
+            (Cursor::System(icon), _) => (true, *icon),
+            (Cursor::Hidden, _) | (Cursor::Image(_), Some(_)) => (false, CursorIcon::Default),
+            (Cursor::Image(_), None) => (true, CursorIcon::Default)
+        };
+        if self.applied != Some(wanted) {
+            window.set_cursor_visible(wanted.0);
+            window.set_cursor_icon(wanted.1);
+            self.applied = Some(wanted);
+        }
+
+        let (cursor, position) = match (image, self.position) {
+            (Some(cursor), Some(position)) => (cursor, position),
+            _ => return
+        };
+        let texture = cursor.texture.get_or_insert_with(|| {
+            let image = &cursor.image;
+            let pixels = egui::ColorImage::from_rgba_unmultiplied([image.size[0] as usize, image.size[1] as usize], &image.rgba);
+            context.load_texture("cursor", pixels, egui::TextureFilter::Linear)
+        });
+
+        // Pixels to points
+        let pixels_per_point = context.pixels_per_point();
+        let hotspot = egui::vec2(cursor.image.hotspot[0] as f32, cursor.image.hotspot[1] as f32) * self.scale;
+        let size = egui::vec2(cursor.image.size[0] as f32, cursor.image.size[1] as f32) * self.scale;
+        let rect = egui::Rect::from_min_size(((position.to_vec2() - hotspot) / pixels_per_point).to_pos2(), size / pixels_per_point);
+        let uv = egui::Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(1.0, 1.0));
+        context.layer_painter(self.layer).add(egui::Shape::image(texture.id(), rect, uv, egui::Color32::WHITE));
+    }
+}
\ No newline at end of file
@@ -0,0 +1,131 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use crate::assets::{drop_point, Asset, AssetKind, AssetManager};
+use crate::vulkan::renderer::VulkanRenderer;
+
+// Width of an asset tile and the size of its thumbnail, in points
+const TILE_SIZE: f32 = 72.0;
+
+#[derive(Default)]
+pub struct AssetBrowserPanel {
+    // Uploaded thumbnails and the asset version they were made from
+    thumbnails: HashMap<PathBuf, (u32, egui::TextureHandle)>,
+    // Index of the asset being dragged towards the scene
+    dragging: Option<usize>,
+}
+
+impl AssetBrowserPanel {
+    // Tiles for every asset the manager found. Meshes dragged onto the scene are placed where the cursor hits the ground
+    // plane, LUTs dropped there become the color grading LUT.
+    pub fn show(&mut self, context: &egui::Context, renderer: &mut VulkanRenderer, assets: &mut AssetManager) {
+        // One GPU thumbnail a frame replaces the software ones made while scanning
+        assets.render_thumbnails(renderer, 1);
+
+        let mut rescan = false;
+        let mut reimport = None;
+
+        egui::TopBottomPanel::bottom("assets").resizable(true).default_height(140.0).show(context, |ui| {
+            ui.horizontal(|ui| {
+                ui.heading("Assets");
+                ui.label(assets.root.display().to_string());
+                rescan = ui.button("Rescan").clicked();
+            });
+
+            egui::ScrollArea::horizontal().show(ui, |ui| {
+                ui.horizontal(|ui| {
+                    for (index, asset) in assets.assets.iter().enumerate() {
+                        ui.vertical(|ui| {
+                            ui.set_width(TILE_SIZE);
+
+                            let preview = match self.thumbnail(ui.ctx(), asset) {
+                                Some(texture) => ui.add(egui::Image::new(texture.id(), thumbnail_size(texture.size_vec2()))),
+                                None => ui.add_sized([TILE_SIZE, TILE_SIZE], egui::Label::new(format!("{:?}", asset.kind)))
+                            };
+                            let preview = preview.interact(egui::Sense::drag());
+                            if preview.drag_started() && asset.kind != AssetKind::Texture {
+                                self.dragging = Some(index);
+                            }
+                            preview.on_hover_text(asset.path.display().to_string());
+
+                            ui.add(egui::Label::new(asset.name()).wrap(true));
+                            if ui.small_button("Reimport").clicked() {
+                                reimport = Some(index);
+                            }
+                        });
+                    }
+                });
+            });
+        });
+
+        if let Some(index) = self.dragging {
+            egui::show_tooltip_at_pointer(context, egui::Id::new("asset_drag"), |ui| ui.label(assets.assets[index].name()));
+
+            if context.input().pointer.any_released() {
+                self.dragging = None;
+                // Only drops on the scene count, not on one of the panels
+                let position = context.input().pointer.hover_pos();
+                if let (Some(position), false) = (position, context.is_pointer_over_area()) {
+                    if let Err(error) = Self::drop(renderer, assets, index, position) {
+                        tracing::warn!("Failed to add {} to the scene: {}", assets.assets[index].name(), error);
+                    }
+                }
+            }
+        }
+
+        if let Some(index) = reimport {
+            if let Err(error) = assets.reimport(renderer, index) {
+                tracing::warn!("Failed to reimport {}: {}", assets.assets[index].name(), error);
+            }
+        }
+        if rescan {
+            if let Err(error) = assets.scan() {
+                tracing::warn!("Failed to scan assets in {}: {}", assets.root.display(), error);
+            }
+            self.dragging = None;
+            self.thumbnails.retain(|path, _| assets.assets.iter().any(|asset| asset.path == *path));
+        }
+    }
+
+    fn drop(renderer: &mut VulkanRenderer, assets: &mut AssetManager, index: usize, position: egui::Pos2) -> Result<(), Box<dyn std::error::Error>> {
+        match assets.assets[index].kind {
+            AssetKind::Mesh => {
+                let scale = renderer.ui.pixels_per_point;
+                // Dropped on the letterbox bars, there's no scene under the cursor
+                let position = match drop_point(renderer, position.x * scale, position.y * scale) {
+                    Some(position) => position,
+                    None => return Ok(())
+                };
+                let id = assets.instantiate(renderer, index, position)?;
+                renderer.selected = Some(id);
+            }
+            AssetKind::Lut => assets.apply_lut(renderer, index)?,
+            AssetKind::Texture => {}
+        }
+        Ok(())
+    }
+
+    // Uploads the asset's thumbnail the first time it's shown and again after every reimport
+    fn thumbnail(&mut self, context: &egui::Context, asset: &Asset) -> Option<&egui::TextureHandle> {
+        let thumbnail = asset.thumbnail.as_ref()?;
+        let stale = self.thumbnails.get(&asset.path).is_none_or(|(version, _)| *version != asset.version);
+        if stale {
+            let pixels = thumbnail.rgba
+                .chunks_exact(4)
+                .map(|pixel| egui::Color32::from_rgba_premultiplied(pixel[0], pixel[1], pixel[2], pixel[3]))
+                .collect();
+            let image = egui::ColorImage {
+                size: thumbnail.size,
+                pixels
+            };
+            let texture = context.load_texture(asset.name(), image, egui::TextureFilter::Linear);
+            self.thumbnails.insert(asset.path.clone(), (asset.version, texture));
+        }
+        self.thumbnails.get(&asset.path).map(|(_, texture)| texture)
+    }
+}
+
+// Scales the thumbnail to fit a tile without changing its aspect ratio
+fn thumbnail_size(size: egui::Vec2) -> egui::Vec2 {
+    size * (TILE_SIZE / size.x.max(size.y))
+}
\ No newline at end of file
@@ -0,0 +1,110 @@
+use crate::simulation::SimulationClock;
+use crate::frame_limiter::FrameLimiter;
+use crate::profiling;
+use crate::localization;
+use crate::tr;
+
+// Offered when turning the cap on from the overlay
+const DEFAULT_FPS_CAP: f32 = 60.0;
+
+// Indent per nesting level of the profiled scopes
+const SCOPE_INDENT: f32 = 12.0;
+
+// Frame timing, the frame rate cap, the simulation controls, the UI language and the profiled scopes in a small window over the scene
+pub struct DebugOverlay {
+    pub open: bool,
+}
+
+impl Default for DebugOverlay {
+    fn default() -> Self {
+        Self {
+            open: true
+        }
+    }
+}
+
+impl DebugOverlay {
+    // `frame_time` is the duration of the last frame in seconds
+    pub fn show(&mut self, context: &egui::Context, clock: &mut SimulationClock, limiter: &mut FrameLimiter, frame_time: f32) {
+        egui::Window::new(tr!("debug.title")).id(egui::Id::new("debug_overlay")).open(&mut self.open).resizable(false).default_pos([220.0, 10.0]).show(context, |ui| {
+            ui.label(tr!("debug.frame_time", fps = format!("{:.0}", 1.0 / frame_time.max(f32::EPSILON)),
+                milliseconds = format!("{:.3}", frame_time * 1000.0)));
+
+            let mut capped = limiter.fps_cap().is_some();
+            let mut fps_cap = limiter.fps_cap().unwrap_or(DEFAULT_FPS_CAP);
+            ui.horizontal(|ui| {
+                let changed = ui.checkbox(&mut capped, tr!("debug.fps_cap")).changed()
+                    | ui.add_enabled(capped, egui::DragValue::new(&mut fps_cap).clamp_range(1.0..=1000.0).speed(1.0)).changed();
+                if changed {
+                    limiter.set_fps_cap(capped.then_some(fps_cap));
+                }
+            });
+
+            if let Some(refresh_rate) = limiter.refresh_rate() {
+                let mut divided = limiter.refresh_divisor().is_some();
+                let mut divisor = limiter.refresh_divisor().unwrap_or(1);
+                ui.horizontal(|ui| {
+                    let changed = ui.add_enabled(!capped, egui::Checkbox::new(&mut divided, tr!("debug.every"))).changed()
+                        | ui.add_enabled(!capped && divided, egui::DragValue::new(&mut divisor).clamp_range(1..=8)).changed();
+                    ui.label(tr!("debug.refresh", hz = format!("{:.0}", refresh_rate)));
+                    if changed {
+                        limiter.set_refresh_divisor(divided.then_some(divisor));
+                    }
+                });
+            }
+
+            ui.separator();
+            ui.label(tr!("debug.tick", tick = clock.tick(), seconds = format!("{:.2}", clock.time())));
+            if clock.is_deterministic() {
+                ui.label(tr!("debug.deterministic", seed = clock.seed()));
+            }
+
+            ui.horizontal(|ui| {
+                if ui.button(if clock.is_paused() { tr!("debug.play") } else { tr!("debug.pause") }).clicked() {
+                    clock.toggle_paused();
+                }
+                if ui.add_enabled(clock.is_paused(), egui::Button::new(tr!("debug.step"))).clicked() {
+                    clock.step();
+                }
+            });
+
+            let mut time_scale = clock.time_scale;
+            if ui.add(egui::Slider::new(&mut time_scale, 0.0..=4.0).text(tr!("debug.time_scale"))).changed() {
+                clock.set_time_scale(time_scale);
+            }
+
+            ui.separator();
+            let current = localization::read(|localization| localization.language().to_string());
+            let languages: Vec<(String, String)> = localization::read(|localization| {
+                localization.languages().map(|(id, name)| (id.to_string(), name.to_string())).collect()
+            });
+            let selected = languages.iter().find(|(id, _)| *id == current).map_or(current.clone(), |(_, name)| name.clone());
+            egui::ComboBox::from_label(tr!("debug.language")).selected_text(selected).show_ui(ui, |ui| {
+                for (id, name) in &languages {
+                    if ui.selectable_label(*id == current, name).clicked() && *id != current {
+                        localization::set_language(id, context);
+                    }
+                }
+            });
+
+            ui.separator();
+            let mut profiling = profiling::enabled();
+            if ui.checkbox(&mut profiling, tr!("debug.profile_scopes")).changed() {
+                profiling::set_enabled(profiling);
+            }
+            if profiling {
+                egui::Grid::new("profile_scopes").striped(true).show(ui, |ui| {
+                    for scope in profiling::frame_report() {
+                        ui.horizontal(|ui| {
+                            ui.add_space(scope.depth as f32 * SCOPE_INDENT);
+                            ui.label(scope.name);
+                        });
+                        ui.label(format!("{:.2} ms", scope.average_milliseconds));
+                        ui.label(format!("{}x", scope.calls));
+                        ui.end_row();
+                    }
+                });
+            }
+        });
+    }
+}
\ No newline at end of file
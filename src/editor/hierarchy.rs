@@ -0,0 +1,154 @@
+use std::collections::{HashMap, HashSet};
+
+use ash::vk;
+
+use crate::vulkan::game_object::{GameObject, is_ancestor};
+use crate::vulkan::mesh::Mesh;
+use crate::vulkan::renderer::VulkanRenderer;
+
+// Horizontal offset per nesting level
+const INDENT: f32 = 14.0;
+// Space taken by the expand button, so leaves line up with their siblings
+const EXPANDER_WIDTH: f32 = 18.0;
+
+enum HierarchyAction {
+    Select(usize),
+    Create { parent: Option<usize> },
+    Delete(usize),
+    Reparent { id: usize, parent: Option<usize> },
+}
+
+#[derive(Default)]
+pub struct HierarchyPanel {
+    // Objects whose children are hidden
+    collapsed: HashSet<usize>,
+    dragging: Option<usize>,
+}
+
+impl HierarchyPanel {
+    // Tree of the scene's game objects. Clicking selects, dropping a row onto another makes it a child (or a root when dropped on
+    // empty space), and the buttons create a cube under the selection or delete the selection.
+    pub fn show(&mut self, context: &egui::Context, renderer: &mut VulkanRenderer) -> Result<(), vk::Result> {
+        let mut action = None;
+
+        egui::SidePanel::left("hierarchy").resizable(true).default_width(200.0).show(context, |ui| {
+            ui.heading("Hierarchy");
+            ui.horizontal(|ui| {
+                if ui.button("Create").clicked() {
+                    action = Some(HierarchyAction::Create { parent: renderer.selected });
+                }
+                if ui.add_enabled(renderer.selected.is_some(), egui::Button::new("Delete")).clicked() {
+                    action = renderer.selected.map(HierarchyAction::Delete);
+                }
+            });
+            ui.separator();
+
+            // Objects whose parent is gone are listed as roots, like `world_matrices` treats them
+            let ids: HashSet<usize> = renderer.game_objects.iter().map(|game_object| game_object.get_id()).collect();
+            let mut children: HashMap<Option<usize>, Vec<usize>> = HashMap::new();
+            for game_object in &renderer.game_objects {
+                let parent = game_object.parent.filter(|parent| ids.contains(parent));
+                children.entry(parent).or_default().push(game_object.get_id());
+            }
+
+            let mut drop_target = None;
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                let tree = Tree {
+                    game_objects: &renderer.game_objects,
+                    children: &children,
+                    selected: renderer.selected
+                };
+                for &id in children.get(&None).into_iter().flatten() {
+                    self.show_node(ui, &tree, id, 0, &mut action, &mut drop_target);
+                }
+            });
+
+            if let Some(id) = self.dragging {
+                let name = renderer.game_objects.iter()
+                    .find(|game_object| game_object.get_id() == id)
+                    .map_or("", |game_object| game_object.name.as_str());
+                egui::show_tooltip_at_pointer(ui.ctx(), egui::Id::new("hierarchy_drag"), |ui| ui.label(name));
+
+                if ui.input().pointer.any_released() {
+                    // Released over a row parents to it, anywhere else in the panel moves the object to the root
+                    if drop_target.is_some() || ui.rect_contains_pointer(ui.max_rect()) {
+                        action = Some(HierarchyAction::Reparent { id, parent: drop_target });
+                    }
+                    self.dragging = None;
+                }
+            }
+        });
+
+        match action {
+            Some(HierarchyAction::Select(id)) => renderer.selected = Some(id),
+            Some(HierarchyAction::Create { parent }) => {
+                let mesh = Mesh::cube(&renderer.device, &mut renderer.allocator, uv::Vec3::one())?;
+                let mut game_object = GameObject::new(mesh, uv::Vec3::broadcast(0.8));
+                game_object.name = format!("Cube {}", game_object.get_id());
+                game_object.parent = parent;
+                renderer.selected = Some(game_object.get_id());
+                renderer.game_objects.push(game_object);
+            }
+            Some(HierarchyAction::Delete(id)) => renderer.remove_game_object(id)?,
+            Some(HierarchyAction::Reparent { id, parent }) => {
+                // An object can't move into its own subtree, that would make a cycle. The local transform is kept,
+                // so the object moves along with its new parent.
+                let valid = parent.is_none_or(|parent| !is_ancestor(&renderer.game_objects, id, parent));
+                match renderer.game_objects.iter_mut().find(|game_object| game_object.get_id() == id) {
+                    Some(game_object) if valid => game_object.parent = parent,
+                    _ => {}
+                }
+            }
+            None => {}
+        }
+
+        Ok(())
+    }
+
+    fn show_node(&mut self, ui: &mut egui::Ui, tree: &Tree, id: usize, depth: usize, action: &mut Option<HierarchyAction>,
+        drop_target: &mut Option<usize>
+    ) {
+        let name = tree.game_objects.iter()
+            .find(|game_object| game_object.get_id() == id)
+            .map_or("", |game_object| game_object.name.as_str());
+        let children = tree.children.get(&Some(id)).map_or(&[][..], |children| children.as_slice());
+        let expanded = !self.collapsed.contains(&id);
+
+        ui.horizontal(|ui| {
+            ui.add_space(depth as f32 * INDENT);
+            if children.is_empty() {
+                ui.add_space(EXPANDER_WIDTH);
+            } else if ui.small_button(if expanded { "-" } else { "+" }).clicked() {
+                match expanded {
+                    true => self.collapsed.insert(id),
+                    false => self.collapsed.remove(&id)
+                };
+            }
+
+            let response = ui.selectable_label(tree.selected == Some(id), name).interact(egui::Sense::click_and_drag());
+            if response.clicked() {
+                *action = Some(HierarchyAction::Select(id));
+            }
+            if response.drag_started() {
+                self.dragging = Some(id);
+            }
+            if self.dragging.is_some() && self.dragging != Some(id) && ui.rect_contains_pointer(response.rect) {
+                *drop_target = Some(id);
+                ui.painter().rect_stroke(response.rect, 2.0, ui.visuals().selection.stroke);
+            }
+        });
+
+        if expanded {
+            for &child in children {
+                self.show_node(ui, tree, child, depth + 1, action, drop_target);
+            }
+        }
+    }
+}
+
+struct Tree<'a> {
+    game_objects: &'a [GameObject],
+    // Child ids per parent id, `None` holds the roots
+    children: &'a HashMap<Option<usize>, Vec<usize>>,
+    selected: Option<usize>,
+}
\ No newline at end of file
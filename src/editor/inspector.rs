@@ -0,0 +1,253 @@
+use crate::clipboard;
+use crate::vulkan::game_object::{GameObject, Transform3DComponent};
+use crate::vulkan::lights::{PointLight, SpotLight};
+use crate::vulkan::material::Material;
+use crate::vulkan::pipeline::BlendMode;
+use crate::vulkan::renderer::VulkanRenderer;
+use crate::vulkan::water::{WaterMaterial, WaterReflections};
+
+// Editable view of a value as rows of a two column grid, true if anything changed
+pub trait Inspect {
+    fn inspect(&mut self, ui: &mut egui::Ui) -> bool;
+}
+
+// Shows one component of the selected game object, see `InspectorPanel::register`
+pub type ComponentInspector = Box<dyn Fn(&mut egui::Ui, &mut GameObject) -> bool>;
+
+// Components of the selected game object, in registration order, followed by the scene's lights
+pub struct InspectorPanel {
+    components: Vec<(String, ComponentInspector)>,
+}
+
+impl Default for InspectorPanel {
+    fn default() -> Self {
+        let mut inspector = Self {
+            components: vec![]
+        };
+
+        inspector.register("Transform", Box::new(|ui, game_object| game_object.transform3d.inspect(ui)));
+        inspector.register("Material", Box::new(|ui, game_object| {
+            let mut changed = false;
+            row(ui, "Kind", |ui| {
+                egui::ComboBox::from_id_source("material_kind")
+                    .selected_text(format!("{:?}", game_object.material))
+                    .show_ui(ui, |ui| {
+                        // Lightmapped only makes sense once a lightmap has been baked for the object
+                        let lightmapped = game_object.lightmap.map(|_| Material::Lightmapped);
+                        let transparent = [BlendMode::Alpha, BlendMode::Additive, BlendMode::Premultiplied].map(Material::Transparent);
+                        let materials = [Material::Basic, Material::Reflective, Material::Cutout, Material::Water, Material::Virtual]
+                            .into_iter()
+                            .chain(lightmapped)
+                            .chain(transparent);
+                        for material in materials {
+                            changed |= ui.selectable_value(&mut game_object.material, material, format!("{:?}", material)).changed();
+                        }
+                    });
+            });
+            changed |= color_row(ui, "Color", &mut game_object.color);
+            match game_object.material {
+                Material::Cutout => {
+                    changed |= row(ui, "Cutoff", |ui| ui.add(egui::Slider::new(&mut game_object.alpha_cutoff, 0.0..=1.0)).changed());
+                },
+                Material::Transparent(_) => {
+                    changed |= row(ui, "Opacity", |ui| ui.add(egui::Slider::new(&mut game_object.opacity, 0.0..=1.0)).changed());
+                },
+                Material::Water => {
+                    changed |= game_object.water.inspect(ui);
+                },
+                _ => {}
+            }
+            changed |= row(ui, "Roughness", |ui| ui.add(egui::Slider::new(&mut game_object.roughness, 0.0..=1.0)).changed());
+            changed |= row(ui, "Wind", |ui| ui.add(egui::Slider::new(&mut game_object.wind, 0.0..=2.0)).changed());
+            // The height comes from the normal map, without one there's nothing to displace
+            if game_object.normal_map.is_some() {
+                changed |= row(ui, "Parallax", |ui| ui.add(egui::Slider::new(&mut game_object.parallax_depth, 0.0..=0.2)).changed());
+            }
+            changed
+        }));
+
+        inspector
+    }
+}
+
+impl InspectorPanel {
+    // Adds a section shown for every selected game object, after the ones registered before it
+    pub fn register(&mut self, name: &str, inspector: ComponentInspector) {
+        self.components.push((name.to_string(), inspector));
+    }
+
+    pub fn show(&mut self, context: &egui::Context, renderer: &mut VulkanRenderer) {
+        egui::SidePanel::right("inspector").resizable(true).default_width(260.0).show(context, |ui| {
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                ui.heading("Inspector");
+
+                match renderer.selected_index() {
+                    Some(index) => {
+                        let game_object = &mut renderer.game_objects[index];
+                        ui.text_edit_singleline(&mut game_object.name);
+                        for (name, inspector) in &self.components {
+                            egui::CollapsingHeader::new(name.as_str()).default_open(true).show(ui, |ui| {
+                                egui::Grid::new(name.as_str()).num_columns(2).show(ui, |ui| inspector(ui, game_object));
+                            });
+                        }
+                    }
+                    None => {
+                        ui.label("Nothing selected");
+                    }
+                }
+
+                ui.separator();
+                egui::CollapsingHeader::new("Lights").default_open(false).show(ui, |ui| {
+                    for (index, light) in renderer.lights.iter_mut().enumerate() {
+                        egui::CollapsingHeader::new(format!("Point light {}", index)).show(ui, |ui| {
+                            egui::Grid::new(("point_light", index)).num_columns(2).show(ui, |ui| light.inspect(ui));
+                        });
+                    }
+                    for (index, light) in renderer.spot_lights.iter_mut().enumerate() {
+                        egui::CollapsingHeader::new(format!("Spot light {}", index)).show(ui, |ui| {
+                            egui::Grid::new(("spot_light", index)).num_columns(2).show(ui, |ui| light.inspect(ui));
+                        });
+                    }
+                });
+            });
+        });
+    }
+}
+
+impl Inspect for Transform3DComponent {
+    fn inspect(&mut self, ui: &mut egui::Ui) -> bool {
+        let mut changed = vec3_row(ui, "Translation", &mut self.translation, 0.01);
+
+        let (mut roll, mut pitch, mut yaw) = self.euler_angles();
+        // Pitch, yaw and roll turn around the x, y and z axes
+        let rotated = row(ui, "Rotation", |ui| {
+            let mut changed = false;
+            for (axis, angle) in [("x", &mut pitch), ("y", &mut yaw), ("z", &mut roll)] {
+                ui.label(axis);
+                changed |= ui.drag_angle(angle).changed();
+            }
+            changed
+        });
+        if rotated {
+            self.rotation = uv::Rotor3::from_euler_angles(roll, pitch, yaw);
+        }
+
+        changed |= rotated;
+        changed |= vec3_row(ui, "Scale", &mut self.scale, 0.01);
+        changed
+    }
+}
+
+impl Inspect for PointLight {
+    fn inspect(&mut self, ui: &mut egui::Ui) -> bool {
+        let mut changed = vec3_row(ui, "Position", &mut self.position, 0.01);
+        changed |= color_row(ui, "Color", &mut self.color);
+        changed |= row(ui, "Intensity", |ui| ui.add(egui::DragValue::new(&mut self.intensity).speed(0.05).clamp_range(0.0..=f32::MAX)).changed());
+        changed |= row(ui, "Radius", |ui| ui.add(egui::DragValue::new(&mut self.radius).speed(0.05).clamp_range(0.01..=f32::MAX)).changed());
+        changed |= row(ui, "Shadows", |ui| ui.checkbox(&mut self.cast_shadows, "").changed());
+        changed
+    }
+}
+
+impl Inspect for SpotLight {
+    fn inspect(&mut self, ui: &mut egui::Ui) -> bool {
+        let mut changed = vec3_row(ui, "Position", &mut self.position, 0.01);
+        if vec3_row(ui, "Direction", &mut self.direction, 0.01) {
+            // Zero would leave the cone without an orientation
+            self.direction = match self.direction.mag_sq() > 0.0 {
+                true => self.direction.normalized(),
+                false => -uv::Vec3::unit_y()
+            };
+            changed = true;
+        }
+        changed |= color_row(ui, "Color", &mut self.color);
+        changed |= row(ui, "Intensity", |ui| ui.add(egui::DragValue::new(&mut self.intensity).speed(0.05).clamp_range(0.0..=f32::MAX)).changed());
+        changed |= row(ui, "Range", |ui| ui.add(egui::DragValue::new(&mut self.range).speed(0.05).clamp_range(0.01..=f32::MAX)).changed());
+        changed |= row(ui, "Inner angle", |ui| ui.drag_angle(&mut self.inner_angle).changed());
+        changed |= row(ui, "Outer angle", |ui| ui.drag_angle(&mut self.outer_angle).changed());
+        // The fade between the cones needs the inner one inside the outer one
+        self.inner_angle = self.inner_angle.clamp(0.0, self.outer_angle);
+        changed |= row(ui, "Falloff", |ui| ui.add(egui::DragValue::new(&mut self.falloff).speed(0.01).clamp_range(0.01..=f32::MAX)).changed());
+        changed |= row(ui, "Shadows", |ui| ui.checkbox(&mut self.cast_shadows, "").changed());
+        changed
+    }
+}
+
+// One labelled grid row, the contents go in the second column
+impl Inspect for WaterMaterial {
+    fn inspect(&mut self, ui: &mut egui::Ui) -> bool {
+        let mut changed = color_row(ui, "Deep color", &mut self.deep_color);
+        changed |= row(ui, "Depth fade", |ui| ui.add(egui::DragValue::new(&mut self.depth_fade).speed(0.05).clamp_range(0.01..=f32::MAX)).changed());
+        // The direction is edited as an angle around the y axis, its length doesn't matter
+        let mut angle = self.wave_direction.y.atan2(self.wave_direction.x);
+        if row(ui, "Wave angle", |ui| ui.drag_angle(&mut angle).changed()) {
+            self.wave_direction = uv::Vec2::new(angle.cos(), angle.sin());
+            changed = true;
+        }
+        changed |= row(ui, "Wave length", |ui| ui.add(egui::DragValue::new(&mut self.wave_length).speed(0.05).clamp_range(0.01..=f32::MAX)).changed());
+        changed |= row(ui, "Wave height", |ui| ui.add(egui::DragValue::new(&mut self.wave_height).speed(0.01).clamp_range(0.0..=f32::MAX)).changed());
+        changed |= row(ui, "Wave speed", |ui| ui.add(egui::DragValue::new(&mut self.wave_speed).speed(0.05)).changed());
+        changed |= row(ui, "Refraction", |ui| ui.add(egui::Slider::new(&mut self.refraction, 0.0..=0.1)).changed());
+        changed |= row(ui, "Foam width", |ui| ui.add(egui::DragValue::new(&mut self.foam_width).speed(0.01).clamp_range(0.0..=f32::MAX)).changed());
+        row(ui, "Reflections", |ui| {
+            egui::ComboBox::from_id_source("water_reflections")
+                .selected_text(format!("{:?}", self.reflections))
+                .show_ui(ui, |ui| {
+                    for reflections in [WaterReflections::ScreenSpace, WaterReflections::Planar] {
+                        changed |= ui.selectable_value(&mut self.reflections, reflections, format!("{:?}", reflections)).changed();
+                    }
+                });
+        });
+        changed
+    }
+}
+
+fn row<R>(ui: &mut egui::Ui, label: &str, contents: impl FnOnce(&mut egui::Ui) -> R) -> R {
+    ui.label(label);
+    let result = ui.horizontal(contents).inner;
+    ui.end_row();
+    result
+}
+
+// Right clicking the label copies the value as "x, y, z" or pastes one
+fn vec3_row(ui: &mut egui::Ui, label: &str, value: &mut uv::Vec3, speed: f64) -> bool {
+    let mut pasted = false;
+    ui.add(egui::Label::new(label).sense(egui::Sense::click())).context_menu(|ui| {
+        if ui.button("Copy").clicked() {
+            clipboard::set_text(format!("{}, {}, {}", value.x, value.y, value.z));
+            ui.close_menu();
+        }
+        let clipboard = clipboard::get_text().and_then(|text| parse_vec3(&text));
+        if ui.add_enabled(clipboard.is_some(), egui::Button::new("Paste")).clicked() {
+            *value = clipboard.unwrap_or(*value);
+            pasted = true;
+            ui.close_menu();
+        }
+    });
+    let changed = ui.horizontal(|ui| {
+        ui.add(egui::DragValue::new(&mut value.x).speed(speed).prefix("x ")).changed()
+            | ui.add(egui::DragValue::new(&mut value.y).speed(speed).prefix("y ")).changed()
+            | ui.add(egui::DragValue::new(&mut value.z).speed(speed).prefix("z ")).changed()
+    }).inner;
+    ui.end_row();
+    pasted | changed
+}
+
+// Three numbers split by commas or spaces, in brackets or not
+fn parse_vec3(text: &str) -> Option<uv::Vec3> {
+    let text = text.trim().trim_start_matches(['(', '[']).trim_end_matches([')', ']']);
+    let numbers: Vec<f32> = text.split([',', ' ', '\t']).filter(|part| !part.is_empty()).map(|part| part.parse().ok()).collect::<Option<_>>()?;
+    match numbers[..] {
+        [x, y, z] => Some(uv::Vec3::new(x, y, z)),
+        _ => None
+    }
+}
+
+fn color_row(ui: &mut egui::Ui, label: &str, color: &mut uv::Vec3) -> bool {
+    let mut rgb = [color.x, color.y, color.z];
+    let changed = row(ui, label, |ui| ui.color_edit_button_rgb(&mut rgb).changed());
+    if changed {
+        *color = uv::Vec3::new(rgb[0], rgb[1], rgb[2]);
+    }
+    changed
+}
\ No newline at end of file
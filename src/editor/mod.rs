@@ -0,0 +1,39 @@
+pub mod hierarchy;
+pub mod inspector;
+pub mod asset_browser;
+pub mod debug_overlay;
+
+use ash::vk;
+
+use crate::assets::AssetManager;
+use crate::simulation::SimulationClock;
+use crate::frame_limiter::FrameLimiter;
+use crate::vulkan::renderer::VulkanRenderer;
+
+use hierarchy::HierarchyPanel;
+use inspector::InspectorPanel;
+use asset_browser::AssetBrowserPanel;
+use debug_overlay::DebugOverlay;
+
+// In-engine editor, egui panels drawn on top of the scene
+#[derive(Default)]
+pub struct Editor {
+    pub hierarchy: HierarchyPanel,
+    pub inspector: InspectorPanel,
+    pub asset_browser: AssetBrowserPanel,
+    pub debug_overlay: DebugOverlay,
+}
+
+impl Editor {
+    // Builds every panel, call between `ui.begin_frame` and `end_ui_frame`. `frame_time` is in seconds.
+    pub fn show(&mut self, context: &egui::Context, renderer: &mut VulkanRenderer, assets: &mut AssetManager, clock: &mut SimulationClock,
+        limiter: &mut FrameLimiter, frame_time: f32
+    ) -> Result<(), vk::Result> {
+        // The bottom panel goes first so the side panels stop above it
+        self.asset_browser.show(context, renderer, assets);
+        self.hierarchy.show(context, renderer)?;
+        self.inspector.show(context, renderer);
+        self.debug_overlay.show(context, clock, limiter, frame_time);
+        Ok(())
+    }
+}
\ No newline at end of file
@@ -0,0 +1,77 @@
+use std::path::{Path, PathBuf};
+
+use winit::event::WindowEvent;
+
+use crate::tr;
+
+// Files dragged onto the window from the system's file manager. winit reports every file of a drag on its own and
+// knows nothing of where the pointer is, which is tracked here from the cursor events around the drag. Some
+// platforms don't move the cursor during a drag, the position is where it last was then.
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum FileDropEvent {
+    // A file being dragged came over the window
+    Hovered(PathBuf),
+    // Physical pixels from the top left of the window
+    Dropped { path: PathBuf, x: f32, y: f32 },
+    // The drag left the window or was given up
+    Cancelled,
+}
+
+#[derive(Default)]
+pub struct FileDrops {
+    hovered: Vec<PathBuf>,
+    cursor: (f32, f32),
+}
+
+impl FileDrops {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Pass every window event, `None` for those that aren't about dragged files
+    pub fn handle(&mut self, event: &WindowEvent) -> Option<FileDropEvent> {
+        match event {
+            WindowEvent::CursorMoved { position, .. } => {
+                self.cursor = (position.x as f32, position.y as f32);
+                None
+            }
+            WindowEvent::HoveredFile(path) => {
+                self.hovered.push(path.clone());
+                Some(FileDropEvent::Hovered(path.clone()))
+            }
+            WindowEvent::DroppedFile(path) => {
+                self.hovered.retain(|hovered| hovered != path);
+                Some(FileDropEvent::Dropped { path: path.clone(), x: self.cursor.0, y: self.cursor.1 })
+            }
+            WindowEvent::HoveredFileCancelled => {
+                self.hovered.clear();
+                Some(FileDropEvent::Cancelled)
+            }
+            _ => None
+        }
+    }
+
+    // Files over the window that haven't been dropped yet
+    pub fn hovered(&self) -> &[PathBuf] {
+        &self.hovered
+    }
+
+    // Tells what dropping the files would do while they're over the window
+    pub fn show(&self, context: &egui::Context) {
+        if self.hovered.is_empty() {
+            return;
+        }
+        let names: Vec<String> = self.hovered.iter().map(|path| file_name(path)).collect();
+
+        let screen = context.input().screen_rect();
+        let painter = context.layer_painter(egui::LayerId::new(egui::Order::Foreground, egui::Id::new("file_drop")));
+        painter.rect_filled(screen, 0.0, egui::Color32::from_black_alpha(120));
+        painter.text(screen.center(), egui::Align2::CENTER_CENTER, tr!("drop.hint", files = names.join(", ")),
+            egui::FontId::proportional(24.0), egui::Color32::WHITE);
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().map_or_else(|| path.display().to_string(), |name| name.to_string_lossy().into_owned())
+}
\ No newline at end of file
@@ -0,0 +1,192 @@
+use std::time::{Duration, Instant};
+
+// Sleeping wakes up late by up to a scheduler tick, the last stretch before a deadline is spun instead
+const DEFAULT_SPIN_THRESHOLD: Duration = Duration::from_micros(1500);
+// Weight of the newest frame in the smoothed frame time
+const SMOOTHING: f32 = 0.1;
+// Frame times this close to the cap's interval count as exactly the interval
+const SNAP_TOLERANCE: f32 = 0.1;
+
+// Optional frame rate cap, with a separate one for while the window is in the background. `wait` blocks until the next
+// frame is due, deadlines follow each other at a fixed interval so the pacing doesn't drift with however late each wait returned.
+// Knowing the display's refresh rate, the cap can also be a fraction of it (half rate on a 144 Hz panel is 72 fps), which
+// keeps every frame on screen for the same number of refreshes.
+pub struct FrameLimiter {
+    pub spin_threshold: Duration,
+    fps_cap: Option<f32>,
+    background_fps_cap: Option<f32>,
+    // Of the monitor the window is on, in Hz
+    refresh_rate: Option<f32>,
+    // Renders every n-th refresh while there's no explicit cap
+    refresh_divisor: Option<u32>,
+    focused: bool,
+    // Between frames under the cap in effect
+    interval: Option<Duration>,
+    next_frame: Option<Instant>,
+    smoothed_delta: Option<f32>,
+}
+
+impl FrameLimiter {
+    // No cap (or one that isn't positive) renders as fast as the swapchain allows. Without a background cap the regular
+    // one applies in the background too.
+    pub fn new(fps_cap: Option<f32>, background_fps_cap: Option<f32>) -> Self {
+        let mut limiter = Self {
+            spin_threshold: DEFAULT_SPIN_THRESHOLD,
+            fps_cap,
+            background_fps_cap,
+            refresh_rate: None,
+            refresh_divisor: None,
+            focused: true,
+            interval: None,
+            next_frame: None,
+            smoothed_delta: None
+        };
+        limiter.update_interval();
+        limiter
+    }
+
+    pub fn fps_cap(&self) -> Option<f32> {
+        self.fps_cap
+    }
+
+    pub fn set_fps_cap(&mut self, fps_cap: Option<f32>) {
+        self.fps_cap = fps_cap;
+        self.update_interval();
+    }
+
+    pub fn background_fps_cap(&self) -> Option<f32> {
+        self.background_fps_cap
+    }
+
+    pub fn set_background_fps_cap(&mut self, background_fps_cap: Option<f32>) {
+        self.background_fps_cap = background_fps_cap;
+        self.update_interval();
+    }
+
+    pub fn refresh_rate(&self) -> Option<f32> {
+        self.refresh_rate
+    }
+
+    // Call whenever the window may have moved to another monitor, see `VulkanWindow::refresh_rate`
+    pub fn set_refresh_rate(&mut self, refresh_rate: Option<f32>) {
+        let refresh_rate = refresh_rate.filter(|rate| *rate > 0.0);
+        if refresh_rate != self.refresh_rate {
+            self.refresh_rate = refresh_rate;
+            self.update_interval();
+        }
+    }
+
+    pub fn refresh_divisor(&self) -> Option<u32> {
+        self.refresh_divisor
+    }
+
+    // 1 paces frames to every refresh, 2 to every other one and so on. Ignored while an fps cap is set or the refresh rate
+    // is unknown.
+    pub fn set_refresh_divisor(&mut self, refresh_divisor: Option<u32>) {
+        self.refresh_divisor = refresh_divisor.filter(|divisor| *divisor > 0);
+        self.update_interval();
+    }
+
+    // Frames per second the limiter currently paces to, `None` when it doesn't
+    pub fn frame_rate(&self) -> Option<f32> {
+        self.interval.map(|interval| 1.0 / interval.as_secs_f32())
+    }
+
+    // Call when the window gains or loses focus
+    pub fn set_focused(&mut self, focused: bool) {
+        self.focused = focused;
+        self.update_interval();
+    }
+
+    fn update_interval(&mut self) {
+        let refresh_cap = self.refresh_rate.zip(self.refresh_divisor).map(|(rate, divisor)| rate / divisor as f32);
+        let cap = match self.focused {
+            true => self.fps_cap.or(refresh_cap),
+            false => self.background_fps_cap.or(self.fps_cap).or(refresh_cap)
+        };
+        // A cap so small its interval doesn't fit a `Duration` is as good as none
+        self.interval = cap.filter(|fps| *fps > 0.0).and_then(|fps| Duration::try_from_secs_f32(1.0 / fps).ok());
+        self.next_frame = None;
+    }
+
+    // Blocks until the next frame is due, call once per frame before starting it
+    pub fn wait(&mut self) {
+        let interval = match self.interval {
+            Some(interval) => interval,
+            None => return
+        };
+
+        let now = Instant::now();
+        let deadline = match self.next_frame {
+            // A frame that ran more than an interval late starts the schedule over, rather than rushing the following ones
+            Some(deadline) if now < deadline + interval => deadline,
+            _ => now
+        };
+
+        if let Some(sleep) = deadline.checked_duration_since(now).and_then(|left| left.checked_sub(self.spin_threshold)) {
+            std::thread::sleep(sleep);
+        }
+        while Instant::now() < deadline {
+            std::hint::spin_loop();
+        }
+
+        self.next_frame = Some(deadline + interval);
+    }
+
+    // Evens out the measured frame time (in seconds) for things driven by it, like the simulation clock. While capped,
+    // times close to the interval are taken as the interval itself. With a known refresh rate, times close to a whole
+    // number of refreshes are taken as exactly that, since that's how long the frame is shown with vsync.
+    pub fn smooth(&mut self, frame_delta: f32) -> f32 {
+        if let Some(interval) = self.interval.map(|interval| interval.as_secs_f32()) {
+            if (frame_delta - interval).abs() <= interval * SNAP_TOLERANCE {
+                self.smoothed_delta = Some(interval);
+                return interval;
+            }
+        }
+        if let Some(refresh_interval) = self.refresh_rate.map(|rate| 1.0 / rate) {
+            let refreshes = (frame_delta / refresh_interval).round().max(1.0);
+            let snapped = refreshes * refresh_interval;
+            if (frame_delta - snapped).abs() <= refresh_interval * SNAP_TOLERANCE {
+                self.smoothed_delta = Some(snapped);
+                return snapped;
+            }
+        }
+
+        let smoothed = match self.smoothed_delta {
+            Some(smoothed) => smoothed + (frame_delta - smoothed) * SMOOTHING,
+            None => frame_delta
+        };
+        self.smoothed_delta = Some(smoothed);
+        smoothed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tiny_caps_leave_the_frame_rate_uncapped() {
+        for cap in [1e-20, f32::MIN_POSITIVE, 1e-45] {
+            let limiter = FrameLimiter::new(Some(cap), None);
+            assert_eq!(limiter.frame_rate(), None, "{}", cap);
+        }
+        let mut limiter = FrameLimiter::new(None, None);
+        limiter.set_refresh_rate(Some(1e-40));
+        limiter.set_refresh_divisor(Some(2));
+        assert_eq!(limiter.frame_rate(), None);
+    }
+
+    #[test]
+    fn caps_pick_the_interval() {
+        let mut limiter = FrameLimiter::new(Some(50.0), Some(10.0));
+        assert!((limiter.frame_rate().unwrap() - 50.0).abs() < 0.01);
+        limiter.set_focused(false);
+        assert!((limiter.frame_rate().unwrap() - 10.0).abs() < 0.01);
+        for cap in [0.0, -30.0, f32::NAN] {
+            limiter.set_background_fps_cap(Some(cap));
+            limiter.set_fps_cap(Some(cap));
+            assert_eq!(limiter.frame_rate(), None);
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,454 @@
+use egui::{Rect, Vec2};
+
+use super::{Gui, WidgetId};
+
+// Flex style layout: a widget lines its children up in a row or a column, sized by their `Style`, and spreads them
+// along the line with `justify` and across it with `align`. Anchored children leave the line and sit against an edge
+// or corner of their parent instead, like HUD elements against the screen. Sizes are in points.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Size {
+    // As large as the content
+    Fit,
+    Fixed(f32),
+    // Fraction of the parent's inner size
+    Percent(f32),
+    // Shares the room left in the parent's line with the other fills, by weight. Across the line it takes all of it.
+    Fill(f32),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Row,
+    Column,
+}
+
+// Along the line
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Justify {
+    Start,
+    Center,
+    End,
+    // The first child at the start, the last at the end and the room spread between them
+    SpaceBetween,
+}
+
+// Across the line
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Align {
+    Start,
+    Center,
+    End,
+    // Children that fit their content take the whole line
+    Stretch,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Anchor {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl Anchor {
+    // Where in the parent it sits, 0 at the left or top and 1 at the right or bottom
+    pub fn factors(self) -> Vec2 {
+        match self {
+            Anchor::TopLeft => Vec2::new(0.0, 0.0),
+            Anchor::Top => Vec2::new(0.5, 0.0),
+            Anchor::TopRight => Vec2::new(1.0, 0.0),
+            Anchor::Left => Vec2::new(0.0, 0.5),
+            Anchor::Center => Vec2::new(0.5, 0.5),
+            Anchor::Right => Vec2::new(1.0, 0.5),
+            Anchor::BottomLeft => Vec2::new(0.0, 1.0),
+            Anchor::Bottom => Vec2::new(0.5, 1.0),
+            Anchor::BottomRight => Vec2::new(1.0, 1.0)
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Edges {
+    pub left: f32,
+    pub right: f32,
+    pub top: f32,
+    pub bottom: f32,
+}
+
+impl Edges {
+    pub fn all(size: f32) -> Self {
+        Self { left: size, right: size, top: size, bottom: size }
+    }
+
+    pub fn symmetric(horizontal: f32, vertical: f32) -> Self {
+        Self { left: horizontal, right: horizontal, top: vertical, bottom: vertical }
+    }
+
+    pub fn sum(&self) -> Vec2 {
+        Vec2::new(self.left + self.right, self.top + self.bottom)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Style {
+    pub width: Size,
+    pub height: Size,
+    // How the children line up
+    pub direction: Direction,
+    pub justify: Justify,
+    pub align: Align,
+    pub padding: Edges,
+    // Between children
+    pub gap: f32,
+    // Takes it out of the parent's line, `offset` moves it from there
+    pub anchor: Option<Anchor>,
+    pub offset: Vec2,
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Self {
+            width: Size::Fit,
+            height: Size::Fit,
+            direction: Direction::Column,
+            justify: Justify::Start,
+            align: Align::Start,
+            padding: Edges::default(),
+            gap: 0.0,
+            anchor: None,
+            offset: Vec2::ZERO
+        }
+    }
+}
+
+impl Style {
+    fn size(&self, main: bool, direction: Direction) -> Size {
+        match (main, direction) {
+            (true, Direction::Row) | (false, Direction::Column) => self.width,
+            (true, Direction::Column) | (false, Direction::Row) => self.height
+        }
+    }
+}
+
+// The component of `vector` along the line, and across it
+fn main_cross(vector: Vec2, direction: Direction) -> (f32, f32) {
+    match direction {
+        Direction::Row => (vector.x, vector.y),
+        Direction::Column => (vector.y, vector.x)
+    }
+}
+
+fn from_main_cross(main: f32, cross: f32, direction: Direction) -> Vec2 {
+    match direction {
+        Direction::Row => Vec2::new(main, cross),
+        Direction::Column => Vec2::new(cross, main)
+    }
+}
+
+impl Gui {
+    pub(super) fn layout(&mut self, screen: Rect) {
+        self.measure(self.root);
+        self.arrange(self.root, screen);
+    }
+
+    // Size of the content with the padding, bottom up. Fixed sizes count as they are, the others as their content.
+    fn measure(&mut self, id: WidgetId) -> Vec2 {
+        let children = self.visible_children(id);
+        let (style, intrinsic) = {
+            let node = self.node(id);
+            (node.widget.style, node.intrinsic)
+        };
+
+        let (mut main, mut cross, mut count) = (0.0f32, 0.0f32, 0usize);
+        for child in children {
+            let size = self.measure(child);
+            if self.node(child).widget.style.anchor.is_some() {
+                continue;
+            }
+            let (child_main, child_cross) = main_cross(size, style.direction);
+            main += child_main;
+            cross = cross.max(child_cross);
+            count += 1;
+        }
+        main += style.gap * count.saturating_sub(1) as f32;
+
+        let content = from_main_cross(main, cross, style.direction).max(intrinsic);
+        let mut size = content + style.padding.sum();
+        if let Size::Fixed(width) = style.width {
+            size.x = width;
+        }
+        if let Size::Fixed(height) = style.height {
+            size.y = height;
+        }
+        self.node_mut(id).measured = size;
+        size
+    }
+
+    // Places the widget at `rect` and its children inside it, top down
+    fn arrange(&mut self, id: WidgetId, rect: Rect) {
+        self.node_mut(id).rect = rect;
+        let style = self.node(id).widget.style;
+        let inner = Rect::from_min_max(rect.min + Vec2::new(style.padding.left, style.padding.top),
+            rect.max - Vec2::new(style.padding.right, style.padding.bottom));
+        let inner = Rect::from_min_size(inner.min, inner.size().max(Vec2::ZERO));
+
+        let (in_line, anchored): (Vec<WidgetId>, Vec<WidgetId>) = self.visible_children(id)
+            .into_iter()
+            .partition(|&child| self.node(child).widget.style.anchor.is_none());
+
+        let direction = style.direction;
+        let (inner_main, inner_cross) = main_cross(inner.size(), direction);
+
+        // Along the line: everything but the fills first, then the fills share what's left
+        let mut sizes = vec![0.0f32; in_line.len()];
+        let mut weights = 0.0;
+        for (size, &child) in sizes.iter_mut().zip(&in_line) {
+            let node = self.node(child);
+            let measured = main_cross(node.measured, direction).0;
+            *size = match node.widget.style.size(true, direction) {
+                Size::Fit => measured,
+                Size::Fixed(fixed) => fixed,
+                Size::Percent(percent) => inner_main * percent,
+                Size::Fill(weight) => {
+                    weights += weight.max(0.0);
+                    0.0
+                }
+            };
+        }
+        let gaps = style.gap * in_line.len().saturating_sub(1) as f32;
+        let remaining = (inner_main - sizes.iter().sum::<f32>() - gaps).max(0.0);
+        for (size, &child) in sizes.iter_mut().zip(&in_line) {
+            if let Size::Fill(weight) = self.node(child).widget.style.size(true, direction) {
+                if weights > 0.0 {
+                    *size = remaining * weight.max(0.0) / weights;
+                }
+            }
+        }
+
+        let free = (inner_main - sizes.iter().sum::<f32>() - gaps).max(0.0);
+        let (mut position, spacing) = match style.justify {
+            Justify::Start => (0.0, style.gap),
+            Justify::Center => (free / 2.0, style.gap),
+            Justify::End => (free, style.gap),
+            Justify::SpaceBetween => match in_line.len() > 1 {
+                true => (0.0, style.gap + free / (in_line.len() - 1) as f32),
+                false => (0.0, style.gap)
+            }
+        };
+
+        for (&child, main_size) in in_line.iter().zip(sizes) {
+            let node = self.node(child);
+            let measured = main_cross(node.measured, direction).1;
+            let cross_size = match node.widget.style.size(false, direction) {
+                Size::Fit if style.align == Align::Stretch => inner_cross,
+                Size::Fit => measured,
+                Size::Fixed(fixed) => fixed,
+                Size::Percent(percent) => inner_cross * percent,
+                Size::Fill(_) => inner_cross
+            };
+            let cross_position = match style.align {
+                Align::Start | Align::Stretch => 0.0,
+                Align::Center => (inner_cross - cross_size) / 2.0,
+                Align::End => inner_cross - cross_size
+            };
+
+            let min = inner.min + from_main_cross(position, cross_position, direction) + node.widget.style.offset;
+            self.arrange(child, Rect::from_min_size(min, from_main_cross(main_size, cross_size, direction)));
+            position += main_size + spacing;
+        }
+
+        for child in anchored {
+            let node = self.node(child);
+            let child_style = node.widget.style;
+            let axis = |size: Size, measured: f32, available: f32| match size {
+                Size::Fit => measured,
+                Size::Fixed(fixed) => fixed,
+                Size::Percent(percent) => available * percent,
+                Size::Fill(_) => available
+            };
+            let size = Vec2::new(axis(child_style.width, node.measured.x, inner.width()), axis(child_style.height, node.measured.y, inner.height()));
+            let factors = child_style.anchor.map_or(Vec2::ZERO, Anchor::factors);
+            let min = inner.min + (inner.size() - size) * factors + child_style.offset;
+            self.arrange(child, Rect::from_min_size(min, size));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use egui::Pos2;
+    use winit::event::{MouseButton, VirtualKeyCode};
+
+    use crate::gui::widget::Widget;
+    use crate::gui::GuiEvent;
+    use crate::input::InputEvent;
+
+    fn fixed(width: f32, height: f32) -> Style {
+        Style { width: Size::Fixed(width), height: Size::Fixed(height), ..Style::default() }
+    }
+
+    // A `width` by `height` panel laid out like `style` at the top left of the screen
+    fn container(style: Style, width: f32, height: f32) -> (Gui, WidgetId) {
+        let mut gui = Gui::new("layout test", egui::Order::Foreground);
+        let container = gui.add(gui.root(), Widget::panel(Style { width: Size::Fixed(width), height: Size::Fixed(height), ..style }));
+        (gui, container)
+    }
+
+    fn add(gui: &mut Gui, parent: WidgetId, style: Style) -> WidgetId {
+        gui.add(parent, Widget::panel(style))
+    }
+
+    fn lay_out(gui: &mut Gui) {
+        gui.layout(Rect::from_min_size(Pos2::ZERO, Vec2::new(800.0, 600.0)));
+    }
+
+    fn span(gui: &Gui, id: WidgetId) -> (f32, f32) {
+        let rect = gui.rect(id).unwrap();
+        (rect.min.x, rect.width())
+    }
+
+    #[test]
+    fn fills_share_the_room_left_by_weight() {
+        let row = Style { direction: Direction::Row, padding: Edges::all(10.0), gap: 10.0, ..Style::default() };
+        let (mut gui, container) = container(row, 400.0, 100.0);
+        let first = add(&mut gui, container, fixed(100.0, 20.0));
+        let light = add(&mut gui, container, Style { width: Size::Fill(1.0), ..fixed(0.0, 20.0) });
+        let heavy = add(&mut gui, container, Style { width: Size::Fill(3.0), ..fixed(0.0, 20.0) });
+        lay_out(&mut gui);
+
+        // 380 inside the padding, less 100 and two gaps leaves 260
+        assert_eq!(span(&gui, first), (10.0, 100.0));
+        assert_eq!(span(&gui, light), (120.0, 65.0));
+        assert_eq!(span(&gui, heavy), (195.0, 195.0));
+        assert_eq!(gui.rect(heavy).unwrap().min.y, 10.0);
+    }
+
+    #[test]
+    fn fills_shrink_to_nothing_in_a_full_line() {
+        let (mut gui, container) = container(Style { direction: Direction::Row, ..Style::default() }, 200.0, 50.0);
+        add(&mut gui, container, fixed(150.0, 20.0));
+        let half = add(&mut gui, container, Style { width: Size::Percent(0.5), ..fixed(0.0, 20.0) });
+        let fill = add(&mut gui, container, Style { width: Size::Fill(1.0), ..fixed(0.0, 20.0) });
+        lay_out(&mut gui);
+
+        // The line runs past the end, the fill gets no room rather than a negative width
+        assert_eq!(span(&gui, half), (150.0, 100.0));
+        assert_eq!(span(&gui, fill), (250.0, 0.0));
+    }
+
+    #[test]
+    fn justify_spreads_the_free_room() {
+        for (justify, starts) in [(Justify::Start, [0.0, 70.0]), (Justify::Center, [90.0, 160.0]), (Justify::End, [180.0, 250.0]),
+            (Justify::SpaceBetween, [0.0, 250.0])] {
+            let (mut gui, container) = container(Style { direction: Direction::Row, justify, gap: 20.0, ..Style::default() }, 300.0, 50.0);
+            let children = [add(&mut gui, container, fixed(50.0, 20.0)), add(&mut gui, container, fixed(50.0, 20.0))];
+            lay_out(&mut gui);
+            assert_eq!(children.map(|child| span(&gui, child).0), starts, "{:?}", justify);
+        }
+    }
+
+    #[test]
+    fn align_places_children_across_the_line() {
+        for (align, x, width) in [(Align::Start, 5.0, 100.0), (Align::Center, 100.0, 100.0), (Align::End, 195.0, 100.0), (Align::Stretch, 5.0, 290.0)] {
+            let (mut gui, container) = container(Style { align, padding: Edges::symmetric(5.0, 0.0), ..Style::default() }, 300.0, 100.0);
+            // Fixed sizes stay as they are when stretched, only the ones fitting their content take the line
+            let fitting = add(&mut gui, container, Style { height: Size::Fixed(20.0), ..Style::default() });
+            add(&mut gui, fitting, fixed(100.0, 20.0));
+            let sized = add(&mut gui, container, fixed(100.0, 20.0));
+            lay_out(&mut gui);
+            assert_eq!(span(&gui, fitting), (x, width), "{:?}", align);
+            assert_eq!(span(&gui, sized).1, 100.0);
+        }
+    }
+
+    #[test]
+    fn fitting_widgets_wrap_their_children() {
+        let mut gui = Gui::new("layout test", egui::Order::Foreground);
+        let root = gui.root();
+        let column = add(&mut gui, root, Style { padding: Edges::all(4.0), gap: 5.0, ..Style::default() });
+        add(&mut gui, column, fixed(50.0, 20.0));
+        add(&mut gui, column, fixed(30.0, 20.0));
+        let hidden = add(&mut gui, column, fixed(80.0, 80.0));
+        gui.set_visible(hidden, false);
+        lay_out(&mut gui);
+
+        // Hidden children take no room and no gap
+        assert_eq!(gui.rect(column).unwrap().size(), Vec2::new(58.0, 53.0));
+    }
+
+    #[test]
+    fn anchored_children_leave_the_line() {
+        let (mut gui, container) = container(Style { padding: Edges::all(10.0), ..Style::default() }, 400.0, 300.0);
+        let corner = add(&mut gui, container, Style { anchor: Some(Anchor::BottomRight), offset: Vec2::new(-5.0, -5.0), ..fixed(40.0, 20.0) });
+        let centre = add(&mut gui, container, Style { anchor: Some(Anchor::Center), ..fixed(100.0, 50.0) });
+        let in_line = add(&mut gui, container, fixed(60.0, 30.0));
+        lay_out(&mut gui);
+
+        assert_eq!(gui.rect(corner).unwrap().min, Pos2::new(345.0, 265.0));
+        assert_eq!(gui.rect(centre).unwrap().min, Pos2::new(150.0, 125.0));
+        assert_eq!(gui.rect(in_line).unwrap().min, Pos2::new(10.0, 10.0));
+    }
+
+    fn button(gui: &mut Gui, parent: WidgetId, style: Style) -> WidgetId {
+        gui.add(parent, Widget::button("button").with_style(style))
+    }
+
+    fn move_to(gui: &mut Gui, x: f32, y: f32) -> bool {
+        gui.handle(&InputEvent::CursorMoved { x, y })
+    }
+
+    #[test]
+    fn the_topmost_button_takes_the_hover() {
+        let (mut gui, container) = container(Style::default(), 400.0, 300.0);
+        let below = button(&mut gui, container, fixed(100.0, 50.0));
+        // Later siblings are drawn over earlier ones
+        let above = button(&mut gui, container, Style { anchor: Some(Anchor::TopLeft), ..fixed(50.0, 50.0) });
+        let hidden = button(&mut gui, container, Style { anchor: Some(Anchor::TopLeft), ..fixed(50.0, 50.0) });
+        gui.set_visible(hidden, false);
+        lay_out(&mut gui);
+
+        assert!(move_to(&mut gui, 25.0, 25.0));
+        assert_eq!(gui.hovered(), Some(above));
+        move_to(&mut gui, 75.0, 25.0);
+        assert_eq!(gui.hovered(), Some(below));
+        // The panel behind them draws nothing, so the scene gets the cursor
+        assert!(!move_to(&mut gui, 200.0, 200.0));
+        assert_eq!(gui.hovered(), None);
+        assert_eq!(gui.take_events(), vec![GuiEvent::HoverStarted(above), GuiEvent::HoverEnded(above), GuiEvent::HoverStarted(below),
+            GuiEvent::HoverEnded(below)]);
+
+        gui.get_mut(above).unwrap().enabled = false;
+        move_to(&mut gui, 25.0, 25.0);
+        assert_eq!(gui.hovered(), Some(below));
+    }
+
+    #[test]
+    fn clicks_and_tab_focus_follow_the_drawing_order() {
+        let (mut gui, container) = container(Style { direction: Direction::Row, ..Style::default() }, 400.0, 300.0);
+        let first = button(&mut gui, container, fixed(100.0, 50.0));
+        let second = button(&mut gui, container, fixed(100.0, 50.0));
+        lay_out(&mut gui);
+
+        move_to(&mut gui, 150.0, 25.0);
+        gui.handle(&InputEvent::Button { button: MouseButton::Left, pressed: true });
+        gui.handle(&InputEvent::Button { button: MouseButton::Left, pressed: false });
+        assert_eq!(gui.focused(), Some(second));
+        assert!(gui.clicked(second));
+        gui.take_events();
+
+        let tab = InputEvent::Key { key: VirtualKeyCode::Tab, pressed: true };
+        gui.handle(&tab);
+        assert_eq!(gui.focused(), Some(first));
+        gui.handle(&tab);
+        assert_eq!(gui.focused(), Some(second));
+        assert_eq!(gui.take_events(), vec![GuiEvent::FocusLost(second), GuiEvent::FocusGained(first), GuiEvent::FocusLost(first),
+            GuiEvent::FocusGained(second)]);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,386 @@
+pub mod layout;
+pub mod widget;
+
+use egui::{Align2, Pos2, Rect, Vec2};
+use winit::event::{MouseButton, VirtualKeyCode};
+
+use crate::input::InputEvent;
+
+use layout::Style;
+use widget::{Widget, WidgetKind};
+
+// Retained UI for the HUDs and menus a game ships with, unlike the editor's immediate mode egui panels. Widgets live in
+// a tree that's built once and changed when something happens, laid out by `layout` and drawn as egui shapes, so they
+// go through the same pipeline, fonts and textures as the editor. Input is routed to the topmost button under the
+// cursor, clicks and focus changes come back as `GuiEvent`s.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct WidgetId {
+    index: u32,
+    // Ids of removed widgets don't find the widget that took their slot
+    generation: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GuiEvent {
+    // Pressed and released over the button, or Enter or Space while it had focus
+    Clicked(WidgetId),
+    HoverStarted(WidgetId),
+    HoverEnded(WidgetId),
+    FocusGained(WidgetId),
+    FocusLost(WidgetId),
+}
+
+struct Node {
+    widget: Widget,
+    parent: Option<WidgetId>,
+    children: Vec<WidgetId>,
+    // Of the text or image, without padding
+    intrinsic: Vec2,
+    measured: Vec2,
+    rect: Rect,
+}
+
+struct Slot {
+    generation: u32,
+    node: Option<Node>,
+}
+
+pub struct Gui {
+    slots: Vec<Slot>,
+    free: Vec<u32>,
+    root: WidgetId,
+    layer: egui::LayerId,
+    hovered: Option<WidgetId>,
+    pressed: Option<WidgetId>,
+    focused: Option<WidgetId>,
+    // In points, like the layout
+    cursor: Option<Pos2>,
+    pixels_per_point: f32,
+    events: Vec<GuiEvent>,
+}
+
+impl Gui {
+    // `name` tells the egui layers of several `Gui`s apart, `order` stacks them against the editor's windows
+    pub fn new(name: &str, order: egui::Order) -> Self {
+        let root = Node {
+            widget: Widget::panel(Style { width: layout::Size::Fill(1.0), height: layout::Size::Fill(1.0), ..Style::default() }),
+            parent: None,
+            children: vec![],
+            intrinsic: Vec2::ZERO,
+            measured: Vec2::ZERO,
+            rect: Rect::NOTHING
+        };
+        Self {
+            slots: vec![Slot { generation: 0, node: Some(root) }],
+            free: vec![],
+            root: WidgetId { index: 0, generation: 0 },
+            layer: egui::LayerId::new(order, egui::Id::new(name)),
+            hovered: None,
+            pressed: None,
+            focused: None,
+            cursor: None,
+            pixels_per_point: 1.0,
+            events: vec![]
+        }
+    }
+
+    // Covers the screen, anchor widgets to it for the edges and corners
+    pub fn root(&self) -> WidgetId {
+        self.root
+    }
+
+    pub fn add(&mut self, parent: WidgetId, widget: Widget) -> WidgetId {
+        assert!(self.get(parent).is_some(), "Parent widget was removed!");
+        let node = Node {
+            widget,
+            parent: Some(parent),
+            children: vec![],
+            intrinsic: Vec2::ZERO,
+            measured: Vec2::ZERO,
+            rect: Rect::NOTHING
+        };
+        let id = match self.free.pop() {
+            Some(index) => {
+                let slot = &mut self.slots[index as usize];
+                slot.node = Some(node);
+                WidgetId { index, generation: slot.generation }
+            }
+            None => {
+                self.slots.push(Slot { generation: 0, node: Some(node) });
+                WidgetId { index: self.slots.len() as u32 - 1, generation: 0 }
+            }
+        };
+        self.node_mut(parent).children.push(id);
+        id
+    }
+
+    // Removes the widget and everything below it, the root stays
+    pub fn remove(&mut self, id: WidgetId) {
+        if id == self.root || self.get(id).is_none() {
+            return;
+        }
+        if let Some(parent) = self.node(id).parent {
+            self.node_mut(parent).children.retain(|child| *child != id);
+        }
+
+        let mut removing = vec![id];
+        while let Some(id) = removing.pop() {
+            let slot = &mut self.slots[id.index as usize];
+            if let Some(node) = slot.node.take() {
+                removing.extend(node.children);
+            }
+            slot.generation += 1;
+            self.free.push(id.index);
+            for state in [&mut self.hovered, &mut self.pressed, &mut self.focused] {
+                if *state == Some(id) {
+                    *state = None;
+                }
+            }
+        }
+    }
+
+    // Removes everything but the root
+    pub fn clear(&mut self) {
+        for child in self.node(self.root).children.clone() {
+            self.remove(child);
+        }
+    }
+
+    pub fn get(&self, id: WidgetId) -> Option<&Widget> {
+        self.slots.get(id.index as usize)
+            .filter(|slot| slot.generation == id.generation)
+            .and_then(|slot| slot.node.as_ref())
+            .map(|node| &node.widget)
+    }
+
+    pub fn get_mut(&mut self, id: WidgetId) -> Option<&mut Widget> {
+        self.slots.get_mut(id.index as usize)
+            .filter(|slot| slot.generation == id.generation)
+            .and_then(|slot| slot.node.as_mut())
+            .map(|node| &mut node.widget)
+    }
+
+    // Of labels and buttons
+    pub fn set_text(&mut self, id: WidgetId, text: impl Into<String>) {
+        if let Some(WidgetKind::Label { text: current } | WidgetKind::Button { text: current }) = self.get_mut(id).map(|widget| &mut widget.kind) {
+            *current = text.into();
+        }
+    }
+
+    pub fn set_visible(&mut self, id: WidgetId, visible: bool) {
+        if let Some(widget) = self.get_mut(id) {
+            widget.visible = visible;
+        }
+    }
+
+    pub fn children(&self, id: WidgetId) -> &[WidgetId] {
+        match self.get(id) {
+            Some(_) => &self.node(id).children,
+            None => &[]
+        }
+    }
+
+    // Where the last `show` put it, in points
+    pub fn rect(&self, id: WidgetId) -> Option<Rect> {
+        self.get(id).map(|_| self.node(id).rect)
+    }
+
+    pub fn hovered(&self) -> Option<WidgetId> {
+        self.hovered
+    }
+
+    pub fn focused(&self) -> Option<WidgetId> {
+        self.focused
+    }
+
+    pub fn set_focus(&mut self, id: Option<WidgetId>) {
+        let id = id.filter(|&id| self.get(id).is_some_and(Widget::is_interactive));
+        if id == self.focused {
+            return;
+        }
+        if let Some(previous) = self.focused {
+            self.events.push(GuiEvent::FocusLost(previous));
+        }
+        if let Some(id) = id {
+            self.events.push(GuiEvent::FocusGained(id));
+        }
+        self.focused = id;
+    }
+
+    // What happened since the last call, in order
+    pub fn take_events(&mut self) -> Vec<GuiEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    // Whether `take_events` would report a click on `id`, without taking the events
+    pub fn clicked(&self, id: WidgetId) -> bool {
+        self.events.contains(&GuiEvent::Clicked(id))
+    }
+
+    // True if the UI used the event, the scene should then ignore it. Pointer events are used over any widget that
+    // draws something, releases never are, so buttons held by the scene don't get stuck.
+    pub fn handle(&mut self, event: &InputEvent) -> bool {
+        match *event {
+            InputEvent::CursorMoved { x, y } => {
+                let cursor = Pos2::new(x / self.pixels_per_point, y / self.pixels_per_point);
+                self.cursor = Some(cursor);
+                self.update_hover();
+                self.covers(cursor)
+            }
+            InputEvent::Button { button: MouseButton::Left, pressed: true } => {
+                self.pressed = self.hovered;
+                self.set_focus(self.hovered);
+                self.cursor.is_some_and(|cursor| self.covers(cursor))
+            }
+            InputEvent::Button { button: MouseButton::Left, pressed: false } => {
+                if let Some(pressed) = self.pressed.take() {
+                    if self.hovered == Some(pressed) {
+                        self.events.push(GuiEvent::Clicked(pressed));
+                    }
+                }
+                false
+            }
+            InputEvent::Button { pressed, .. } => pressed && self.cursor.is_some_and(|cursor| self.covers(cursor)),
+            InputEvent::Scroll { .. } => self.cursor.is_some_and(|cursor| self.covers(cursor)),
+            InputEvent::Key { key: VirtualKeyCode::Tab, pressed: true } => {
+                let focusable: Vec<WidgetId> = self.paint_order().into_iter().filter(|&id| self.node(id).widget.is_interactive()).collect();
+                if focusable.is_empty() {
+                    return false;
+                }
+                let next = match self.focused.and_then(|focused| focusable.iter().position(|&id| id == focused)) {
+                    Some(index) => focusable[(index + 1) % focusable.len()],
+                    None => focusable[0]
+                };
+                self.set_focus(Some(next));
+                true
+            }
+            InputEvent::Key { key: VirtualKeyCode::Return | VirtualKeyCode::NumpadEnter | VirtualKeyCode::Space, pressed: true } => {
+                match self.focused {
+                    Some(focused) => {
+                        self.events.push(GuiEvent::Clicked(focused));
+                        true
+                    }
+                    None => false
+                }
+            }
+            InputEvent::Key { .. } => false
+        }
+    }
+
+    // Lays the tree out over the screen and draws it, call between `ui.begin_frame` and `end_ui_frame`
+    pub fn show(&mut self, context: &egui::Context) {
+        self.pixels_per_point = context.pixels_per_point();
+
+        let order = self.paint_order();
+        {
+            let fonts = context.fonts();
+            for &id in &order {
+                let node = self.node_mut(id);
+                node.intrinsic = match &node.widget.kind {
+                    WidgetKind::Panel => Vec2::ZERO,
+                    WidgetKind::Label { text } | WidgetKind::Button { text } => {
+                        fonts.layout_no_wrap(text.clone(), node.widget.visuals.font.clone(), node.widget.visuals.text_color).size()
+                    }
+                    WidgetKind::Image { size, .. } => *size
+                };
+            }
+        }
+        let screen = context.input().screen_rect();
+        self.layout(screen);
+        // Widgets may have moved under a cursor that didn't
+        self.update_hover();
+
+        let painter = context.layer_painter(self.layer);
+        for id in order {
+            let node = self.node(id);
+            let (widget, rect) = (&node.widget, node.rect);
+            let visuals = &widget.visuals;
+            let background = match widget.kind {
+                WidgetKind::Button { .. } if !widget.enabled => visuals.disabled,
+                WidgetKind::Button { .. } if self.pressed == Some(id) && self.hovered == Some(id) => visuals.pressed,
+                WidgetKind::Button { .. } if self.hovered == Some(id) => visuals.hovered,
+                _ => visuals.background
+            };
+            if background.a() > 0 {
+                painter.rect_filled(rect, visuals.rounding, background);
+            }
+            if visuals.border.width > 0.0 {
+                painter.rect_stroke(rect, visuals.rounding, visuals.border);
+            }
+
+            match &widget.kind {
+                WidgetKind::Panel => {}
+                WidgetKind::Label { text } => {
+                    let position = rect.min + Vec2::new(widget.style.padding.left, widget.style.padding.top);
+                    painter.text(position, Align2::LEFT_TOP, text, visuals.font.clone(), visuals.text_color);
+                }
+                WidgetKind::Button { text } => {
+                    let color = match widget.enabled {
+                        true => visuals.text_color,
+                        false => visuals.text_color.linear_multiply(0.5)
+                    };
+                    painter.text(rect.center(), Align2::CENTER_CENTER, text, visuals.font.clone(), color);
+                }
+                WidgetKind::Image { texture, uv, .. } => {
+                    painter.add(egui::Shape::image(*texture, rect, *uv, visuals.tint));
+                }
+            }
+
+            if self.focused == Some(id) {
+                painter.rect_stroke(rect.expand(visuals.focus.width), visuals.rounding, visuals.focus);
+            }
+        }
+    }
+
+    fn node(&self, id: WidgetId) -> &Node {
+        self.slots[id.index as usize].node.as_ref().expect("Widget was removed!")
+    }
+
+    fn node_mut(&mut self, id: WidgetId) -> &mut Node {
+        self.slots[id.index as usize].node.as_mut().expect("Widget was removed!")
+    }
+
+    fn visible_children(&self, id: WidgetId) -> Vec<WidgetId> {
+        self.node(id).children.iter().copied().filter(|&child| self.node(child).widget.visible).collect()
+    }
+
+    // Visible widgets parents first, later siblings over earlier ones
+    fn paint_order(&self) -> Vec<WidgetId> {
+        let mut order = vec![];
+        let mut stack = vec![self.root];
+        while let Some(id) = stack.pop() {
+            order.push(id);
+            stack.extend(self.visible_children(id).into_iter().rev());
+        }
+        order
+    }
+
+    // The topmost button under the cursor
+    fn update_hover(&mut self) {
+        let hovered = self.cursor.and_then(|cursor| {
+            self.paint_order()
+                .into_iter()
+                .rev()
+                .find(|&id| self.node(id).widget.is_interactive() && self.node(id).rect.contains(cursor))
+        });
+        if hovered != self.hovered {
+            if let Some(previous) = self.hovered {
+                self.events.push(GuiEvent::HoverEnded(previous));
+            }
+            if let Some(id) = hovered {
+                self.events.push(GuiEvent::HoverStarted(id));
+            }
+            self.hovered = hovered;
+        }
+    }
+
+    // Whether a widget that draws something is under `position`
+    fn covers(&self, position: Pos2) -> bool {
+        self.paint_order().into_iter().any(|id| {
+            let widget = &self.node(id).widget;
+            let draws = widget.is_interactive() || widget.visuals.background.a() > 0 || matches!(widget.kind, WidgetKind::Image { .. });
+            id != self.root && draws && self.node(id).rect.contains(position)
+        })
+    }
+}
\ No newline at end of file
@@ -0,0 +1,132 @@
+use egui::{Color32, FontId, Rect, Stroke, TextureId, Vec2};
+
+use super::layout::Style;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum WidgetKind {
+    // Only lays out its children, and draws a background if it has one
+    Panel,
+    Label { text: String },
+    Button { text: String },
+    // A texture registered with egui, stretched over the widget. `size` is what it fits to, `uv` the part shown.
+    Image { texture: TextureId, size: Vec2, uv: Rect },
+}
+
+// How a widget looks, the colors used for each state of buttons
+#[derive(Clone, Debug, PartialEq)]
+pub struct Visuals {
+    pub background: Color32,
+    pub hovered: Color32,
+    pub pressed: Color32,
+    pub disabled: Color32,
+    pub border: Stroke,
+    // Around focused widgets
+    pub focus: Stroke,
+    pub rounding: f32,
+    pub text_color: Color32,
+    pub font: FontId,
+    // Multiplies images
+    pub tint: Color32,
+}
+
+impl Default for Visuals {
+    fn default() -> Self {
+        Self {
+            background: Color32::TRANSPARENT,
+            hovered: Color32::TRANSPARENT,
+            pressed: Color32::TRANSPARENT,
+            disabled: Color32::TRANSPARENT,
+            border: Stroke::none(),
+            focus: Stroke::new(2.0, Color32::from_rgb(255, 200, 80)),
+            rounding: 0.0,
+            text_color: Color32::WHITE,
+            font: FontId::proportional(18.0),
+            tint: Color32::WHITE
+        }
+    }
+}
+
+impl Visuals {
+    pub fn button() -> Self {
+        Self {
+            background: Color32::from_rgba_unmultiplied(40, 44, 52, 230),
+            hovered: Color32::from_rgba_unmultiplied(60, 66, 78, 240),
+            pressed: Color32::from_rgba_unmultiplied(28, 30, 36, 240),
+            disabled: Color32::from_rgba_unmultiplied(40, 44, 52, 120),
+            border: Stroke::new(1.0, Color32::from_gray(90)),
+            rounding: 4.0,
+            ..Self::default()
+        }
+    }
+
+    pub fn panel() -> Self {
+        Self {
+            background: Color32::from_rgba_unmultiplied(16, 18, 22, 200),
+            rounding: 6.0,
+            ..Self::default()
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Widget {
+    pub kind: WidgetKind,
+    pub style: Style,
+    pub visuals: Visuals,
+    // Hidden widgets take no room and their children are hidden too
+    pub visible: bool,
+    // Disabled buttons don't react to input
+    pub enabled: bool,
+}
+
+impl Widget {
+    pub fn new(kind: WidgetKind, style: Style, visuals: Visuals) -> Self {
+        Self {
+            kind,
+            style,
+            visuals,
+            visible: true,
+            enabled: true
+        }
+    }
+
+    pub fn panel(style: Style) -> Self {
+        Self::new(WidgetKind::Panel, style, Visuals::default())
+    }
+
+    pub fn label(text: impl Into<String>) -> Self {
+        Self::new(WidgetKind::Label { text: text.into() }, Style::default(), Visuals::default())
+    }
+
+    pub fn button(text: impl Into<String>) -> Self {
+        let style = Style { padding: super::layout::Edges::symmetric(16.0, 8.0), ..Style::default() };
+        Self::new(WidgetKind::Button { text: text.into() }, style, Visuals::button())
+    }
+
+    pub fn image(texture: TextureId, size: Vec2) -> Self {
+        let uv = Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(1.0, 1.0));
+        Self::new(WidgetKind::Image { texture, size, uv }, Style::default(), Visuals::default())
+    }
+
+    pub fn with_style(mut self, style: Style) -> Self {
+        self.style = style;
+        self
+    }
+
+    pub fn with_visuals(mut self, visuals: Visuals) -> Self {
+        self.visuals = visuals;
+        self
+    }
+
+    // Buttons take hover, clicks and focus, everything else lets them through to what's below
+    pub fn is_interactive(&self) -> bool {
+        matches!(self.kind, WidgetKind::Button { .. }) && self.enabled
+    }
+
+    pub fn text(&self) -> Option<&str> {
+        match &self.kind {
+            WidgetKind::Label { text } | WidgetKind::Button { text } => Some(text),
+            _ => None
+        }
+    }
+}
\ No newline at end of file
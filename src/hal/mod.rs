@@ -0,0 +1,159 @@
+// A thin interface to the GPU that doesn't name a graphics API. The ash backend in `vulkan::hal` implements it, another
+// backend (wgpu, Metal, DX12) only has to implement these traits for code written against them to run on it.
+// It covers buffers, textures, compute pipelines and recording copies and dispatches, not render passes or presenting.
+
+// Where the memory of a buffer lives
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemoryLocation {
+    GpuOnly,
+    // Host visible, written by the CPU every frame or once for uploads
+    CpuToGpu,
+    // Host visible, for reading results back
+    GpuToCpu,
+}
+
+// Any combination of what a buffer is used for
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BufferUsage {
+    pub vertex: bool,
+    pub index: bool,
+    pub uniform: bool,
+    pub storage: bool,
+    pub indirect: bool,
+    pub copy_src: bool,
+    pub copy_dst: bool,
+}
+
+pub struct BufferDesc<'a> {
+    pub name: &'a str,
+    // In bytes
+    pub size: u64,
+    pub usage: BufferUsage,
+    pub location: MemoryLocation,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextureFormat {
+    Rgba8Unorm,
+    Rgba8Srgb,
+    Bgra8Unorm,
+    Rgba16Float,
+    Rgba32Float,
+    R32Float,
+    Depth32Float,
+}
+
+impl TextureFormat {
+    // Bytes per texel, tightly packed texel data of a texture is width * height * layers times this
+    pub fn texel_size(self) -> u32 {
+        match self {
+            TextureFormat::Rgba8Unorm | TextureFormat::Rgba8Srgb | TextureFormat::Bgra8Unorm => 4,
+            TextureFormat::Rgba16Float => 8,
+            TextureFormat::Rgba32Float => 16,
+            TextureFormat::R32Float | TextureFormat::Depth32Float => 4,
+        }
+    }
+
+    pub fn is_depth(self) -> bool {
+        self == TextureFormat::Depth32Float
+    }
+}
+
+// Any combination of what a texture is used for
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TextureUsage {
+    pub sampled: bool,
+    pub storage: bool,
+    pub render_target: bool,
+    pub copy_src: bool,
+    pub copy_dst: bool,
+}
+
+pub struct TextureDesc<'a> {
+    pub name: &'a str,
+    pub width: u32,
+    pub height: u32,
+    // 1 for a plain 2D texture, more for a 2D array
+    pub layers: u32,
+    pub format: TextureFormat,
+    pub usage: TextureUsage,
+}
+
+// What a pipeline expects at each binding of its only set, in binding order
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BindingKind {
+    UniformBuffer,
+    StorageBuffer,
+    SampledTexture,
+    StorageTexture,
+}
+
+pub struct ComputePipelineDesc<'a> {
+    pub name: &'a str,
+    // SPIR-V with a `main` entry point, backends that don't take SPIR-V translate it
+    pub shader: &'a [u32],
+    pub bindings: &'a [BindingKind],
+    pub push_constant_size: u32,
+}
+
+// A resource bound for a dispatch, matched up with the pipeline's `BindingKind` at the same index
+pub enum Binding<'a, D: Device + ?Sized> {
+    Buffer(&'a D::Buffer),
+    Texture(&'a D::Texture),
+}
+
+pub trait Buffer {
+    fn size(&self) -> u64;
+}
+
+pub trait Texture {
+    fn width(&self) -> u32;
+    fn height(&self) -> u32;
+    fn layers(&self) -> u32;
+    fn format(&self) -> TextureFormat;
+}
+
+pub trait Pipeline {
+    fn bindings(&self) -> &[BindingKind];
+}
+
+// Records commands for `Device::submit`. Every command sees the results of the ones recorded before it, backends put
+// whatever barriers that takes in between.
+pub trait CommandEncoder {
+    type Device: Device<CommandEncoder = Self> + ?Sized;
+
+    // `size` bytes from the start of both buffers
+    fn copy_buffer(&mut self, src: &<Self::Device as Device>::Buffer, dst: &<Self::Device as Device>::Buffer, size: u64);
+    // Tightly packed texels covering every layer of the texture
+    fn copy_buffer_to_texture(&mut self, src: &<Self::Device as Device>::Buffer, dst: &<Self::Device as Device>::Texture);
+    fn copy_texture_to_buffer(&mut self, src: &<Self::Device as Device>::Texture, dst: &<Self::Device as Device>::Buffer);
+    fn dispatch(&mut self, pipeline: &<Self::Device as Device>::Pipeline, bindings: &[Binding<'_, Self::Device>], push_constants: &[u8],
+        groups: [u32; 3]
+    ) -> Result<(), <Self::Device as Device>::Error>;
+}
+
+// Creates resources and runs recorded commands. Resources have to be destroyed through the device that created them,
+// after the commands using them have finished.
+pub trait Device {
+    type Buffer: Buffer;
+    type Texture: Texture;
+    type Pipeline: Pipeline;
+    type CommandEncoder: CommandEncoder<Device = Self>;
+    type Error: std::error::Error;
+
+    fn create_buffer(&mut self, desc: &BufferDesc) -> Result<Self::Buffer, Self::Error>;
+    // Only for buffers in host visible memory, `offset` is in bytes
+    fn write_buffer(&mut self, buffer: &mut Self::Buffer, offset: u64, data: &[u8]);
+    fn read_buffer(&self, buffer: &Self::Buffer, offset: u64, data: &mut [u8]);
+    fn destroy_buffer(&mut self, buffer: Self::Buffer);
+
+    fn create_texture(&mut self, desc: &TextureDesc) -> Result<Self::Texture, Self::Error>;
+    fn destroy_texture(&mut self, texture: Self::Texture);
+
+    fn create_compute_pipeline(&mut self, desc: &ComputePipelineDesc) -> Result<Self::Pipeline, Self::Error>;
+    fn destroy_pipeline(&mut self, pipeline: Self::Pipeline);
+
+    fn create_command_encoder(&mut self) -> Result<Self::CommandEncoder, Self::Error>;
+    // Runs the recorded commands and waits for them to finish. Meant for loading and tools, not for anything per frame.
+    fn submit(&mut self, encoder: Self::CommandEncoder) -> Result<(), Self::Error>;
+}
@@ -0,0 +1,163 @@
+pub mod recording;
+
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+use winit::event::{ElementState, KeyboardInput, MouseButton, MouseScrollDelta, VirtualKeyCode, WindowEvent};
+
+// Scroll distance of a line for touchpads and other devices that scroll by pixels
+const PIXELS_PER_LINE: f32 = 40.0;
+
+// Mouse and keyboard input the scene reacts to, in a form recordings can hold
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum InputEvent {
+    Key { key: VirtualKeyCode, pressed: bool },
+    Button { button: MouseButton, pressed: bool },
+    // Physical pixels from the top left of the window
+    CursorMoved { x: f32, y: f32 },
+    // Lines
+    Scroll { x: f32, y: f32 },
+}
+
+impl InputEvent {
+    // `None` for window events that aren't input, and keys without a virtual key code
+    pub fn from_window_event(event: &WindowEvent) -> Option<Self> {
+        match event {
+            WindowEvent::KeyboardInput { input: KeyboardInput { state, virtual_keycode: Some(key), .. }, .. } => {
+                Some(InputEvent::Key { key: *key, pressed: *state == ElementState::Pressed })
+            }
+            WindowEvent::MouseInput { state, button, .. } => Some(InputEvent::Button { button: *button, pressed: *state == ElementState::Pressed }),
+            WindowEvent::CursorMoved { position, .. } => Some(InputEvent::CursorMoved { x: position.x as f32, y: position.y as f32 }),
+            WindowEvent::MouseWheel { delta, .. } => Some(match delta {
+                MouseScrollDelta::LineDelta(x, y) => InputEvent::Scroll { x: *x, y: *y },
+                MouseScrollDelta::PixelDelta(position) => {
+                    InputEvent::Scroll { x: position.x as f32 / PIXELS_PER_LINE, y: position.y as f32 / PIXELS_PER_LINE }
+                }
+            }),
+            _ => None
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Binding {
+    Key(VirtualKeyCode),
+    Button(MouseButton),
+}
+
+// What's held and what changed since the last `end_frame`, fed with `handle`. Gameplay asks for named actions bound to
+// keys and buttons, so it doesn't care where the input came from, the window or a recording being played back.
+#[derive(Default)]
+pub struct InputState {
+    held: HashSet<Binding>,
+    pressed: HashSet<Binding>,
+    released: HashSet<Binding>,
+    cursor: uv::Vec2,
+    scroll: uv::Vec2,
+    actions: HashMap<String, Vec<Binding>>,
+}
+
+impl InputState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // An action is held while any of its bindings is
+    pub fn bind(&mut self, action: &str, binding: Binding) {
+        let bindings = self.actions.entry(action.to_string()).or_default();
+        if !bindings.contains(&binding) {
+            bindings.push(binding);
+        }
+    }
+
+    pub fn unbind_all(&mut self, action: &str) {
+        self.actions.remove(action);
+    }
+
+    pub fn bindings(&self, action: &str) -> &[Binding] {
+        self.actions.get(action).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn handle(&mut self, event: &InputEvent) {
+        let (binding, pressed) = match *event {
+            InputEvent::Key { key, pressed } => (Binding::Key(key), pressed),
+            InputEvent::Button { button, pressed } => (Binding::Button(button), pressed),
+            InputEvent::CursorMoved { x, y } => {
+                self.cursor = uv::Vec2::new(x, y);
+                return;
+            }
+            InputEvent::Scroll { x, y } => {
+                self.scroll += uv::Vec2::new(x, y);
+                return;
+            }
+        };
+
+        // Key repeat sends presses of held keys again
+        match pressed {
+            true => if self.held.insert(binding) {
+                self.pressed.insert(binding);
+            },
+            false => if self.held.remove(&binding) {
+                self.released.insert(binding);
+            }
+        }
+    }
+
+    // Forgets what changed, call it once everything that reacts to input ran for the frame
+    pub fn end_frame(&mut self) {
+        self.pressed.clear();
+        self.released.clear();
+        self.scroll = uv::Vec2::zero();
+    }
+
+    // Releases everything, like when the window loses focus and the releases would go elsewhere
+    pub fn release_all(&mut self) {
+        self.released.extend(self.held.drain());
+    }
+
+    pub fn is_held(&self, binding: Binding) -> bool {
+        self.held.contains(&binding)
+    }
+
+    pub fn was_pressed(&self, binding: Binding) -> bool {
+        self.pressed.contains(&binding)
+    }
+
+    pub fn was_released(&self, binding: Binding) -> bool {
+        self.released.contains(&binding)
+    }
+
+    pub fn is_key_held(&self, key: VirtualKeyCode) -> bool {
+        self.is_held(Binding::Key(key))
+    }
+
+    pub fn is_button_held(&self, button: MouseButton) -> bool {
+        self.is_held(Binding::Button(button))
+    }
+
+    pub fn action_held(&self, action: &str) -> bool {
+        self.bindings(action).iter().any(|binding| self.is_held(*binding))
+    }
+
+    pub fn action_pressed(&self, action: &str) -> bool {
+        self.bindings(action).iter().any(|binding| self.was_pressed(*binding))
+    }
+
+    pub fn action_released(&self, action: &str) -> bool {
+        self.bindings(action).iter().any(|binding| self.was_released(*binding)) && !self.action_held(action)
+    }
+
+    // 1.0 while only `positive` is held, -1.0 while only `negative` is
+    pub fn axis(&self, positive: &str, negative: &str) -> f32 {
+        self.action_held(positive) as i32 as f32 - self.action_held(negative) as i32 as f32
+    }
+
+    pub fn cursor(&self) -> uv::Vec2 {
+        self.cursor
+    }
+
+    // Lines scrolled since the last `end_frame`
+    pub fn scroll(&self) -> uv::Vec2 {
+        self.scroll
+    }
+}
\ No newline at end of file
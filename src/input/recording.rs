@@ -0,0 +1,316 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+
+use super::InputEvent;
+
+// File layout: the magic and version, a bincode `RecordingHeader`, then one bincode `RecordedInput` after another until
+// the end. Events are appended through a buffer that's written out whenever it fills up, so a recording cut short by a
+// crash still plays back up to the last full buffer. Anything else that doesn't parse fails to open.
+const MAGIC: &[u8; 4] = b"RVRI";
+const VERSION: u32 = 1;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RecordingHeader {
+    pub ticks_per_second: f32,
+    // Seed of the deterministic simulation it was recorded in, see `SimulationClock::set_deterministic`. Playing back
+    // from the same seed and scene repeats the recorded run exactly.
+    pub seed: Option<u64>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RecordedInput {
+    // First simulation tick that could react to it
+    pub tick: u64,
+    // Seconds since the recording started
+    pub time: f32,
+    pub event: InputEvent,
+}
+
+fn invalid_data(error: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+pub struct InputRecorder {
+    writer: BufWriter<File>,
+    start: Instant,
+    events: u64,
+}
+
+impl InputRecorder {
+    pub fn create(path: &Path, header: &RecordingHeader) -> io::Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        bincode::serialize_into(&mut writer, header).map_err(invalid_data)?;
+
+        Ok(Self {
+            writer,
+            start: Instant::now(),
+            events: 0
+        })
+    }
+
+    pub fn record(&mut self, tick: u64, event: InputEvent) -> io::Result<()> {
+        let input = RecordedInput { tick, time: self.start.elapsed().as_secs_f32(), event };
+        bincode::serialize_into(&mut self.writer, &input).map_err(invalid_data)?;
+        self.events += 1;
+        Ok(())
+    }
+
+    // Writes out what's buffered, which also happens when the recorder is dropped. Events still buffered are lost if
+    // the process dies.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    pub fn events(&self) -> u64 {
+        self.events
+    }
+}
+
+impl Drop for InputRecorder {
+    fn drop(&mut self) {
+        if let Err(error) = self.flush() {
+            tracing::error!("Failed to write the end of the input recording: {}", error);
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlaybackTiming {
+    // Events come back on the tick they were recorded at, hold the clock at `InputPlayer::next_tick` to hit it exactly
+    Ticks,
+    // At the time since the start they were recorded at, for recordings of a simulation that wasn't deterministic
+    RealTime,
+}
+
+pub struct InputPlayer {
+    header: RecordingHeader,
+    events: VecDeque<RecordedInput>,
+    pub timing: PlaybackTiming,
+    // When the first poll happened
+    start: Option<Instant>,
+}
+
+impl InputPlayer {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut magic = [0u8; 4];
+        let mut version = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        reader.read_exact(&mut version)?;
+        if &magic != MAGIC {
+            return Err(invalid_data(format!("{} isn't an input recording", path.display())));
+        }
+        if u32::from_le_bytes(version) != VERSION {
+            return Err(invalid_data(format!("{} is version {} of input recordings, this is version {}", path.display(),
+                u32::from_le_bytes(version), VERSION)));
+        }
+        let header: RecordingHeader = bincode::deserialize_from(&mut reader).map_err(invalid_data)?;
+
+        let mut events = vec![];
+        while !reader.fill_buf()?.is_empty() {
+            match bincode::deserialize_from::<_, RecordedInput>(&mut reader) {
+                Ok(input) => events.push(input),
+                // The buffer written out last ended in the middle of an event
+                Err(error) if matches!(&*error, bincode::ErrorKind::Io(error) if error.kind() == io::ErrorKind::UnexpectedEof) => {
+                    tracing::warn!("{} is cut short after {} events", path.display(), events.len());
+                    break;
+                }
+                Err(error) => return Err(invalid_data(format!("{} is corrupt after {} events: {}", path.display(), events.len(), error)))
+            }
+        }
+        Ok(Self::new(header, events))
+    }
+
+    // Recordings made in deterministic mode play back by tick, the others in real time
+    pub fn new(header: RecordingHeader, events: Vec<RecordedInput>) -> Self {
+        Self {
+            timing: match header.seed {
+                Some(_) => PlaybackTiming::Ticks,
+                None => PlaybackTiming::RealTime
+            },
+            header,
+            events: events.into(),
+            start: None
+        }
+    }
+
+    pub fn header(&self) -> &RecordingHeader {
+        &self.header
+    }
+
+    // Events due at simulation tick `tick` or the time `now`, depending on `timing`
+    pub fn poll(&mut self, tick: u64, now: Instant) -> Vec<InputEvent> {
+        let elapsed = (now - *self.start.get_or_insert(now)).as_secs_f32();
+        let mut due = vec![];
+        while let Some(input) = self.events.front() {
+            let ready = match self.timing {
+                PlaybackTiming::Ticks => input.tick <= tick,
+                PlaybackTiming::RealTime => input.time <= elapsed
+            };
+            if !ready {
+                break;
+            }
+            due.push(self.events.pop_front().unwrap().event);
+        }
+        due
+    }
+
+    // Tick of the next event when playing back by tick, the simulation mustn't run past it before it's polled
+    pub fn next_tick(&self) -> Option<u64> {
+        match self.timing {
+            PlaybackTiming::Ticks => self.events.front().map(|input| input.tick),
+            PlaybackTiming::RealTime => None
+        }
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.events.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use std::time::Duration;
+    use winit::event::{MouseButton, VirtualKeyCode};
+
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(name: &str) -> Self {
+            Self(std::env::temp_dir().join(format!("reverie-{}-{}.rec", name, std::process::id())))
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    const JUMP: InputEvent = InputEvent::Key { key: VirtualKeyCode::Space, pressed: true };
+    const FIRE: InputEvent = InputEvent::Button { button: MouseButton::Left, pressed: true };
+    const AIM: InputEvent = InputEvent::CursorMoved { x: 320.0, y: 240.0 };
+
+    fn recorded(name: &str, events: &[(u64, InputEvent)]) -> TempFile {
+        let file = TempFile::new(name);
+        let mut recorder = InputRecorder::create(&file.0, &RecordingHeader { ticks_per_second: 60.0, seed: Some(7) }).unwrap();
+        for &(tick, event) in events {
+            recorder.record(tick, event).unwrap();
+        }
+        assert_eq!(recorder.events(), events.len() as u64);
+        file
+    }
+
+    fn input(tick: u64, time: f32, event: InputEvent) -> RecordedInput {
+        RecordedInput { tick, time, event }
+    }
+
+    fn header(seed: Option<u64>) -> RecordingHeader {
+        RecordingHeader { ticks_per_second: 60.0, seed }
+    }
+
+    #[test]
+    fn round_trip() {
+        let file = recorded("round-trip", &[(0, JUMP), (3, FIRE), (3, AIM)]);
+        let player = InputPlayer::open(&file.0).unwrap();
+        assert_eq!((player.header().ticks_per_second, player.header().seed), (60.0, Some(7)));
+        assert_eq!(player.timing, PlaybackTiming::Ticks);
+
+        let events: Vec<(u64, InputEvent)> = player.events.iter().map(|input| (input.tick, input.event)).collect();
+        assert_eq!(events, vec![(0, JUMP), (3, FIRE), (3, AIM)]);
+        assert!(player.events.iter().all(|input| input.time >= 0.0 && input.time < 60.0));
+    }
+
+    #[test]
+    fn plays_back_by_tick() {
+        let mut player = InputPlayer::new(header(Some(1)), vec![input(0, 0.0, JUMP), input(2, 9.0, FIRE), input(2, 9.0, AIM),
+            input(5, 0.0, JUMP)]);
+        let now = Instant::now();
+        assert_eq!(player.poll(1, now), vec![JUMP]);
+        assert_eq!(player.next_tick(), Some(2));
+        assert_eq!(player.poll(2, now), vec![FIRE, AIM]);
+        // The recorded time doesn't matter
+        assert!(player.poll(4, now + Duration::from_secs(60)).is_empty());
+        assert_eq!((player.next_tick(), player.remaining()), (Some(5), 1));
+        assert_eq!(player.poll(9, now), vec![JUMP]);
+    }
+
+    #[test]
+    fn plays_back_in_real_time() {
+        let mut player = InputPlayer::new(header(None), vec![input(100, 0.0, JUMP), input(0, 0.5, FIRE), input(0, 1.5, AIM)]);
+        assert_eq!(player.timing, PlaybackTiming::RealTime);
+        assert_eq!(player.next_tick(), None);
+
+        // Time counts from the first poll, the ticks don't matter
+        let start = Instant::now();
+        assert_eq!(player.poll(0, start), vec![JUMP]);
+        assert!(player.poll(1000, start + Duration::from_millis(400)).is_empty());
+        assert_eq!(player.poll(0, start + Duration::from_millis(600)), vec![FIRE]);
+        assert_eq!(player.poll(0, start + Duration::from_secs(2)), vec![AIM]);
+    }
+
+    #[test]
+    fn finishes_at_the_end_of_the_recording() {
+        let mut player = InputPlayer::new(header(Some(1)), vec![input(0, 0.0, JUMP), input(1, 0.0, FIRE)]);
+        assert!(!player.is_finished());
+        assert_eq!(player.poll(10, Instant::now()), vec![JUMP, FIRE]);
+        assert!(player.is_finished());
+        assert_eq!((player.remaining(), player.next_tick()), (0, None));
+        assert!(player.poll(11, Instant::now()).is_empty());
+    }
+
+    #[test]
+    fn plays_a_cut_short_recording_up_to_the_cut() {
+        let file = recorded("cut-short", &[(0, JUMP), (1, FIRE), (2, AIM)]);
+        let length = std::fs::metadata(&file.0).unwrap().len();
+        File::options().write(true).open(&file.0).unwrap().set_len(length - 3).unwrap();
+
+        let player = InputPlayer::open(&file.0).unwrap();
+        assert_eq!(player.events.iter().map(|input| input.event).collect::<Vec<_>>(), vec![JUMP, FIRE]);
+    }
+
+    #[test]
+    fn corrupt_events_fail_to_open() {
+        let file = recorded("corrupt", &[(0, JUMP), (1, JUMP)]);
+        let mut data = std::fs::read(&file.0).unwrap();
+        // The last byte is the second key's `pressed`
+        *data.last_mut().unwrap() = 7;
+        std::fs::write(&file.0, &data).unwrap();
+
+        let error = InputPlayer::open(&file.0).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(error.to_string().contains("corrupt after 1 events"));
+    }
+
+    #[test]
+    fn broken_headers_fail_to_open() {
+        let file = recorded("header", &[(0, JUMP)]);
+        let data = std::fs::read(&file.0).unwrap();
+
+        let mut other_version = data.clone();
+        other_version[4..8].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        let mut not_a_recording = data.clone();
+        not_a_recording[..4].copy_from_slice(b"RIFF");
+        // The header's ticks per second and no more
+        let cut_in_the_header = data[..12].to_vec();
+
+        for (broken, kind) in [(other_version, io::ErrorKind::InvalidData), (not_a_recording, io::ErrorKind::InvalidData),
+            (cut_in_the_header, io::ErrorKind::InvalidData), (data[..6].to_vec(), io::ErrorKind::UnexpectedEof)] {
+            std::fs::write(&file.0, &broken).unwrap();
+            assert_eq!(InputPlayer::open(&file.0).err().map(|error| error.kind()), Some(kind));
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,164 @@
+use std::sync::{Mutex, OnceLock};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static POOL: OnceLock<rayon::ThreadPool> = OnceLock::new();
+
+// The work-stealing pool shared by the engine, started on first use. It leaves a core each to the game and render threads,
+// parallel iterators run on it inside `pool().install(...)`.
+pub fn pool() -> &'static rayon::ThreadPool {
+    POOL.get_or_init(|| {
+        let threads = std::thread::available_parallelism().map_or(1, |count| count.get().saturating_sub(2).max(1));
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .thread_name(|index| format!("job-{}", index))
+            .build()
+            .expect("Failed to start the job threads!")
+    })
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TaskId(usize);
+
+struct Task<'a> {
+    name: &'static str,
+    run: Mutex<Option<Box<dyn FnOnce() + Send + 'a>>>,
+    // Tasks started once this and their other dependencies finished
+    dependents: Vec<usize>,
+    // Dependencies that haven't finished yet
+    waiting: AtomicUsize,
+}
+
+// Work of one frame and what has to finish before each part starts. `run` spreads the tasks over the pool as their
+// dependencies complete and returns once all of them did, so tasks can borrow anything that outlives the graph.
+// Dependencies have to be added first, which rules out cycles, `add` panics on any that weren't.
+#[derive(Default)]
+pub struct TaskGraph<'a> {
+    tasks: Vec<Task<'a>>,
+}
+
+impl<'a> TaskGraph<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, name: &'static str, dependencies: &[TaskId], task: impl FnOnce() + Send + 'a) -> TaskId {
+        let id = self.tasks.len();
+        for dependency in dependencies {
+            // Dependencies come first, an id that doesn't is from another graph
+            assert!(dependency.0 < id, "Task {} depends on a task that isn't in its graph!", name);
+            self.tasks[dependency.0].dependents.push(id);
+        }
+        self.tasks.push(Task {
+            name,
+            run: Mutex::new(Some(Box::new(task))),
+            dependents: vec![],
+            waiting: AtomicUsize::new(dependencies.len())
+        });
+        TaskId(id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.tasks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tasks.is_empty()
+    }
+
+    // Blocks until every task ran, a panicking task is resumed here after the others finished
+    pub fn run(self) {
+        let tasks = &self.tasks;
+        pool().scope(|scope| {
+            for (index, _) in tasks.iter().enumerate().filter(|(_, task)| task.waiting.load(Ordering::Acquire) == 0) {
+                spawn(scope, tasks, index);
+            }
+        });
+    }
+}
+
+fn spawn<'scope>(scope: &rayon::Scope<'scope>, tasks: &'scope [Task<'_>], index: usize) {
+    scope.spawn(move |scope| {
+        let task = &tasks[index];
+        let _span = tracing::trace_span!("task", name = task.name).entered();
+        crate::profile_scope!(task.name);
+        if let Some(run) = task.run.lock().expect("Task panicked!").take() {
+            run();
+        }
+        for &dependent in &task.dependents {
+            if tasks[dependent].waiting.fetch_sub(1, Ordering::AcqRel) == 1 {
+                spawn(scope, tasks, dependent);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::panic::{self, AssertUnwindSafe};
+
+    fn position(log: &[&str], name: &str) -> usize {
+        log.iter().position(|&logged| logged == name).unwrap()
+    }
+
+    #[test]
+    fn dependencies_finish_first() {
+        let log = Mutex::new(vec![]);
+        let log = &log;
+        let mut graph = TaskGraph::new();
+        let decode = graph.add("decode", &[], move || log.lock().unwrap().push("decode"));
+        let cull = graph.add("cull", &[], move || log.lock().unwrap().push("cull"));
+        let upload = graph.add("upload", &[decode], move || log.lock().unwrap().push("upload"));
+        graph.add("record", &[upload, cull], move || log.lock().unwrap().push("record"));
+        graph.add("unrelated", &[], move || log.lock().unwrap().push("unrelated"));
+        assert_eq!(graph.len(), 5);
+        graph.run();
+
+        let log = log.lock().unwrap();
+        assert_eq!(log.len(), 5);
+        assert!(position(&log, "decode") < position(&log, "upload"));
+        assert!(position(&log, "upload") < position(&log, "record"));
+        assert!(position(&log, "cull") < position(&log, "record"));
+    }
+
+    #[test]
+    fn tasks_write_through_borrows() {
+        let mut sums = [0; 16];
+        let mut graph = TaskGraph::new();
+        for (index, sum) in sums.iter_mut().enumerate() {
+            graph.add("sum", &[], move || *sum = (0..=index).sum::<usize>());
+        }
+        graph.run();
+        assert_eq!(sums[15], 120);
+        assert!(sums.iter().enumerate().all(|(index, &sum)| sum == index * (index + 1) / 2));
+    }
+
+    #[test]
+    fn empty_graph_returns() {
+        let graph = TaskGraph::new();
+        assert!(graph.is_empty());
+        graph.run();
+    }
+
+    #[test]
+    #[should_panic(expected = "Task late depends on a task that isn't in its graph!")]
+    fn unknown_dependency_panics() {
+        let mut graph = TaskGraph::new();
+        let first = graph.add("first", &[], || {});
+        graph.add("late", &[first, TaskId(4)], || {});
+    }
+
+    #[test]
+    fn panic_reaches_run_after_the_other_tasks() {
+        let ran = AtomicUsize::new(0);
+        let ran = &ran;
+        let mut graph = TaskGraph::new();
+        let failing = graph.add("failing", &[], || panic!("task failed"));
+        graph.add("after failing", &[failing], move || { ran.fetch_add(100, Ordering::Relaxed); });
+        for _ in 0..4 {
+            graph.add("independent", &[], move || { ran.fetch_add(1, Ordering::Relaxed); });
+        }
+        assert!(panic::catch_unwind(AssertUnwindSafe(|| graph.run())).is_err());
+        assert_eq!(ran.load(Ordering::Relaxed), 4);
+    }
+}
\ No newline at end of file
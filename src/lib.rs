@@ -0,0 +1,31 @@
+pub mod vulkan;
+pub mod hal;
+pub mod utils;
+pub mod editor;
+pub mod assets;
+pub mod simulation;
+pub mod logging;
+pub mod settings;
+pub mod benchmark;
+pub mod frame_limiter;
+pub mod render_thread;
+pub mod jobs;
+pub mod schedule;
+pub mod touch;
+#[cfg(target_os = "android")]
+pub mod android;
+#[cfg(feature = "xr")]
+pub mod xr;
+pub mod profiling;
+pub mod net;
+pub mod input;
+pub mod save;
+pub mod localization;
+pub mod timers;
+pub mod coroutines;
+pub mod states;
+pub mod gui;
+pub mod cursor;
+pub mod file_drop;
+pub mod clipboard;
+pub mod loading;
\ No newline at end of file
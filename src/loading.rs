@@ -0,0 +1,102 @@
+use crate::assets::preload::LoadProgress;
+use crate::states::{GameState, InputCapture, Transition};
+use crate::tr;
+
+// Covers everything with a splash while the asset manager preloads files on its background threads. The game loop
+// keeps presenting frames meanwhile, so the spinner shows the window isn't stuck even when a single file takes long.
+// Once every file is in and the bar filled up, the screen pops itself or switches to the state it was given.
+
+const DOTS: usize = 8;
+// Seconds per turn of the spinner
+const TURN: f32 = 1.2;
+// How quickly the bar catches up with the progress, per second
+const EASING: f32 = 6.0;
+
+pub struct LoadingScreen<C> {
+    progress: LoadProgress,
+    next: Option<Box<dyn GameState<C>>>,
+    // Fraction the bar shows, eased towards the progress so it doesn't jump file by file
+    shown: f32,
+    time: f32,
+}
+
+impl<C> LoadingScreen<C> {
+    // From `AssetManager::preload` or `AssetManager::progress`
+    pub fn new(progress: LoadProgress) -> Self {
+        Self {
+            progress,
+            next: None,
+            shown: 0.0,
+            time: 0.0
+        }
+    }
+
+    // Switches to `next` once loaded instead of popping
+    pub fn then(mut self, next: Box<dyn GameState<C>>) -> Self {
+        self.next = Some(next);
+        self
+    }
+}
+
+impl<C> GameState<C> for LoadingScreen<C> {
+    fn name(&self) -> &str {
+        "Loading screen"
+    }
+
+    fn update(&mut self, _context: &mut C, delta: f32) -> Transition<C> {
+        self.time += delta;
+        let target = self.progress.fraction();
+        self.shown += (target - self.shown) * (1.0 - (-EASING * delta).exp());
+
+        if !self.progress.is_finished() || self.shown < 0.99 {
+            return Transition::None;
+        }
+        match self.next.take() {
+            Some(next) => Transition::Switch(next),
+            None => Transition::Pop
+        }
+    }
+
+    fn draw(&mut self, _context: &mut C, ui: &egui::Context) -> Transition<C> {
+        let screen = ui.input().screen_rect();
+        let painter = ui.layer_painter(egui::LayerId::new(egui::Order::Foreground, egui::Id::new("loading_screen")));
+        painter.rect_filled(screen, 0.0, egui::Color32::from_rgb(16, 16, 20));
+
+        // The brightest dot goes around once per turn, the others trail behind it
+        let center = screen.center() - egui::vec2(0.0, 48.0);
+        let head = (self.time / TURN).fract() * DOTS as f32;
+        for dot in 0..DOTS {
+            let angle = dot as f32 / DOTS as f32 * std::f32::consts::TAU;
+            let behind = (head - dot as f32).rem_euclid(DOTS as f32) / DOTS as f32;
+            let alpha = (255.0 * (1.0 - behind).powi(2)).max(40.0) as u8;
+            let position = center + egui::vec2(angle.sin(), -angle.cos()) * 20.0;
+            painter.circle_filled(position, 4.0, egui::Color32::from_white_alpha(alpha));
+        }
+
+        let title_position = center + egui::vec2(0.0, 48.0);
+        painter.text(title_position, egui::Align2::CENTER_CENTER, tr!("loading.title"), egui::FontId::proportional(24.0), egui::Color32::WHITE);
+
+        let width = (screen.width() * 0.4).clamp(160.0, 480.0);
+        let bar = egui::Rect::from_center_size(title_position + egui::vec2(0.0, 36.0), egui::vec2(width, 8.0));
+        painter.rect_filled(bar, 4.0, egui::Color32::from_gray(48));
+        let filled = egui::Rect::from_min_size(bar.min, egui::vec2(bar.width() * self.shown.clamp(0.0, 1.0), bar.height()));
+        painter.rect_filled(filled, 4.0, egui::Color32::from_rgb(255, 204, 102));
+
+        let (done, total) = self.progress.counts();
+        let status = match self.progress.current() {
+            current if current.is_empty() || done >= total => format!("{:.0}%", self.shown * 100.0),
+            current => tr!("loading.status", percent = format!("{:.0}", self.shown * 100.0), file = current)
+        };
+        painter.text(bar.center_bottom() + egui::vec2(0.0, 16.0), egui::Align2::CENTER_CENTER, status, egui::FontId::proportional(14.0),
+            egui::Color32::from_gray(160));
+        Transition::None
+    }
+
+    fn input_capture(&self) -> InputCapture {
+        InputCapture::All
+    }
+
+    fn draws_below(&self) -> bool {
+        false
+    }
+}
\ No newline at end of file
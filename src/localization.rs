@@ -0,0 +1,459 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::Display;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock, RwLock};
+
+// String tables per locale, picked at runtime. Locale files (`<id>.lang`, like en.lang or de-AT.lang) hold one
+// `key = message` per line, `#` starts a comment and `@name`/`@font` lines describe the locale:
+//
+//   @name = Deutsch
+//   @font = fonts/NotoSans.ttf
+//   inventory.title = Inventar
+//   inventory.items.one = {count} Gegenstand
+//   inventory.items.other = {count} Gegenstände
+//
+// Messages take named arguments in braces, `{{` and `}}` are literal braces. With a `count` argument the first language
+// with variants of the key picks the one for the count's CLDR plural category in that language (`.zero`, `.one`,
+// `.two`, `.few`, `.many` or `.other`), or `.other` when it lacks that one. `plural_category` knows the rules of the
+// major language families, languages it doesn't know count like English with only `.one` and `.other`. Keys missing in
+// the current language come from its base language (de for de-AT), then from the fallback language, and show as the
+// key itself as a last resort.
+
+// Built into the engine so its UI has text without any files next to it, games add to them with `load_dir`
+const BUILT_IN: &[(&str, &str)] = &[
+    ("en", include_str!("../locales/en.lang")),
+    ("de", include_str!("../locales/de.lang"))
+];
+
+static LOCALIZATION: OnceLock<RwLock<Localization>> = OnceLock::new();
+
+// The message of `$key` in the current language, with arguments given as `name = value`
+#[macro_export]
+macro_rules! tr {
+    ($key:expr) => {
+        $crate::localization::text($key)
+    };
+    ($key:expr, $($name:ident = $value:expr),+ $(,)?) => {
+        $crate::localization::format($key, &[$((stringify!($name), &$value as &dyn std::fmt::Display)),+])
+    };
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Locale {
+    pub id: String,
+    // What language pickers show, the id when the file doesn't say
+    pub name: String,
+    // Font with the glyphs this language needs, used where egui's built-in fonts lack them
+    pub font: Option<PathBuf>,
+    messages: HashMap<String, String>,
+}
+
+impl Locale {
+    // Errors name the first malformed line
+    pub fn parse(id: &str, source: &str) -> Result<Self, String> {
+        let mut locale = Locale { id: id.to_string(), name: id.to_string(), ..Default::default() };
+        for (number, line) in source.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, message) = line.split_once('=').ok_or_else(|| format!("Line {} of {} has no '='", number + 1, id))?;
+            let (key, message) = (key.trim(), message.trim());
+            match key {
+                "@name" => locale.name = message.to_string(),
+                "@font" => locale.font = Some(PathBuf::from(message)),
+                _ if key.is_empty() || key.starts_with('@') => return Err(format!("Line {} of {} has no valid key", number + 1, id)),
+                _ => {
+                    locale.messages.insert(key.to_string(), message.to_string());
+                }
+            }
+        }
+        Ok(locale)
+    }
+
+    // The id is the file stem, fonts are relative to the file
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let id = path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+        let source = std::fs::read_to_string(path)?;
+        let mut locale = Self::parse(&id, &source).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+        if let (Some(font), Some(directory)) = (&locale.font, path.parent()) {
+            locale.font = Some(directory.join(font));
+        }
+        Ok(locale)
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.messages.get(key).map(String::as_str)
+    }
+}
+
+pub struct Localization {
+    locales: BTreeMap<String, Locale>,
+    language: String,
+    fallback: String,
+    // Changes with every switch of language, for anything caching localized text
+    generation: u64,
+    // Keys already warned about
+    missing: Mutex<HashSet<String>>,
+}
+
+impl Default for Localization {
+    fn default() -> Self {
+        let mut localization = Self {
+            locales: BTreeMap::new(),
+            language: "en".to_string(),
+            fallback: "en".to_string(),
+            generation: 0,
+            missing: Mutex::new(HashSet::new())
+        };
+        for (id, source) in BUILT_IN {
+            localization.add(Locale::parse(id, source).expect("Built-in locale doesn't parse!"));
+        }
+        localization
+    }
+}
+
+impl Localization {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Messages of a locale that's already known are added to it, replacing the ones with the same key
+    pub fn add(&mut self, locale: Locale) {
+        match self.locales.get_mut(&locale.id) {
+            Some(existing) => {
+                if locale.name != locale.id {
+                    existing.name = locale.name;
+                }
+                if locale.font.is_some() {
+                    existing.font = locale.font;
+                }
+                existing.messages.extend(locale.messages);
+            }
+            None => {
+                self.locales.insert(locale.id.clone(), locale);
+            }
+        }
+        self.generation += 1;
+    }
+
+    // Adds every .lang file in `directory`, returns how many there were
+    pub fn load_dir(&mut self, directory: &Path) -> io::Result<usize> {
+        let mut loaded = 0;
+        for entry in std::fs::read_dir(directory)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|extension| extension == "lang") {
+                self.add(Locale::load(&path)?);
+                loaded += 1;
+            }
+        }
+        Ok(loaded)
+    }
+
+    // Ids and names of the known locales
+    pub fn languages(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.locales.values().map(|locale| (locale.id.as_str(), locale.name.as_str()))
+    }
+
+    pub fn language(&self) -> &str {
+        &self.language
+    }
+
+    // Also takes languages without a locale of their own, their base language or the fallback fills in
+    pub fn set_language(&mut self, id: &str) {
+        if !self.chain_of(id).iter().any(|id| self.locales.contains_key(*id)) {
+            tracing::warn!("No locale for {}, using {}", id, self.fallback);
+        }
+        self.language = id.to_string();
+        self.generation += 1;
+    }
+
+    pub fn set_fallback(&mut self, id: &str) {
+        self.fallback = id.to_string();
+        self.generation += 1;
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    // The language, its base language and the fallback, without repeats
+    fn chain_of<'a>(&'a self, language: &'a str) -> Vec<&'a str> {
+        let mut chain = vec![language];
+        if let Some((base, _)) = language.split_once(['-', '_']) {
+            chain.push(base);
+        }
+        if !chain.contains(&self.fallback.as_str()) {
+            chain.push(&self.fallback);
+        }
+        chain
+    }
+
+    fn locales(&self) -> impl Iterator<Item = &Locale> {
+        self.chain_of(&self.language).into_iter().filter_map(|id| self.locales.get(id))
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.locales().find_map(|locale| locale.get(key))
+    }
+
+    pub fn text(&self, key: &str) -> String {
+        match self.get(key) {
+            Some(message) => message.to_string(),
+            None => {
+                self.warn_missing(key);
+                key.to_string()
+            }
+        }
+    }
+
+    pub fn format(&self, key: &str, arguments: &[(&str, &dyn Display)]) -> String {
+        let count = arguments.iter().find(|(name, _)| *name == "count").map(|(_, value)| value.to_string());
+        let plural = count.and_then(|count| self.locales().find_map(|locale| {
+            locale.get(&format!("{}.{}", key, plural_category(&locale.id, &count))).or_else(|| locale.get(&format!("{}.other", key)))
+        }));
+
+        match plural.or_else(|| self.get(key)) {
+            Some(message) => substitute(message, arguments),
+            None => {
+                self.warn_missing(key);
+                key.to_string()
+            }
+        }
+    }
+
+    fn warn_missing(&self, key: &str) {
+        if self.missing.lock().unwrap().insert(key.to_string()) {
+            tracing::warn!("No message {} in {}", key, self.language);
+        }
+    }
+
+    // Fonts of the current language and its fallbacks, most specific first
+    pub fn fonts(&self) -> Vec<PathBuf> {
+        self.locales().filter_map(|locale| locale.font.clone()).collect()
+    }
+}
+
+// `{name}` replaced by the argument, unknown names are kept as they are
+fn substitute(message: &str, arguments: &[(&str, &dyn Display)]) -> String {
+    let mut result = String::with_capacity(message.len());
+    let mut rest = message;
+    while let Some(index) = rest.find(['{', '}']) {
+        result.push_str(&rest[..index]);
+        let tail = &rest[index..];
+        if tail.starts_with("{{") || tail.starts_with("}}") {
+            result.push_str(&tail[..1]);
+            rest = &tail[2..];
+            continue;
+        }
+
+        match (tail.starts_with('{'), tail.find('}')) {
+            (true, Some(end)) => {
+                let name = &tail[1..end];
+                match arguments.iter().find(|(argument, _)| *argument == name) {
+                    Some((_, value)) => result.push_str(&value.to_string()),
+                    None => result.push_str(&tail[..=end])
+                }
+                rest = &tail[end + 1..];
+            }
+            _ => {
+                result.push_str(&tail[..1]);
+                rest = &tail[1..];
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+// CLDR cardinal plural category of `count` as it's shown, "1" and "1.0" can differ. Counts that aren't numbers are
+// "other".
+fn plural_category(language: &str, count: &str) -> &'static str {
+    let digits = count.trim().trim_start_matches('-');
+    let (integer, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+    let (i, n) = match (integer.parse::<u64>(), digits.parse::<f64>()) {
+        (Ok(i), Ok(n)) if fraction.chars().all(|digit| digit.is_ascii_digit()) => (i, n),
+        _ => return "other"
+    };
+    // Visible fraction digits, and whether there are any besides trailing zeros
+    let v = fraction.len();
+    let t = fraction.trim_end_matches('0').len();
+    let whole = v == 0;
+    let millions = whole && i != 0 && i % 1_000_000 == 0;
+
+    let base = language.split(['-', '_']).next().unwrap_or_default().to_ascii_lowercase();
+    match base.as_str() {
+        "ja" | "zh" | "ko" | "vi" | "th" | "id" | "ms" | "lo" | "my" | "km" => "other",
+        "fr" | "pt" if i <= 1 => "one",
+        "fr" | "pt" | "es" | "it" | "ca" if millions => "many",
+        "es" if n == 1.0 => "one",
+        "da" if n == 1.0 || (t != 0 && i <= 1) => "one",
+        "ru" | "uk" | "be" if whole => match (i % 10, i % 100) {
+            (1, 11) => "many",
+            (1, _) => "one",
+            (2..=4, 12..=14) => "many",
+            (2..=4, _) => "few",
+            _ => "many"
+        },
+        "pl" if whole => match (i, i % 10, i % 100) {
+            (1, _, _) => "one",
+            (_, 2..=4, 12..=14) => "many",
+            (_, 2..=4, _) => "few",
+            _ => "many"
+        },
+        "cs" | "sk" => match (i, whole) {
+            (_, false) => "many",
+            (1, true) => "one",
+            (2..=4, true) => "few",
+            _ => "other"
+        },
+        "ar" if whole || t == 0 => match (i, i % 100) {
+            (0, _) => "zero",
+            (1, _) => "one",
+            (2, _) => "two",
+            (_, 3..=10) => "few",
+            (_, 11..=99) => "many",
+            _ => "other"
+        },
+        "he" => match (i, whole) {
+            (1, true) | (0, false) => "one",
+            (2, true) => "two",
+            _ => "other"
+        },
+        "ru" | "uk" | "be" | "pl" | "ar" | "fr" | "pt" | "es" | "da" => "other",
+        _ if i == 1 && whole => "one",
+        _ => "other"
+    }
+}
+
+fn global() -> &'static RwLock<Localization> {
+    LOCALIZATION.get_or_init(|| RwLock::new(Localization::new()))
+}
+
+// Shared by the whole engine, what `tr!` reads
+pub fn read<R>(read: impl FnOnce(&Localization) -> R) -> R {
+    read(&global().read().unwrap())
+}
+
+pub fn write<R>(write: impl FnOnce(&mut Localization) -> R) -> R {
+    write(&mut global().write().unwrap())
+}
+
+pub fn text(key: &str) -> String {
+    read(|localization| localization.text(key))
+}
+
+pub fn format(key: &str, arguments: &[(&str, &dyn Display)]) -> String {
+    read(|localization| localization.format(key, arguments))
+}
+
+// Like "de-AT" from LC_ALL, LC_MESSAGES or LANG such as "de_AT.UTF-8"
+pub fn system_language() -> Option<String> {
+    ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|variable| std::env::var(variable).ok())
+        .find(|value| !value.is_empty())
+        .map(|value| value.split(['.', '@']).next().unwrap_or_default().replace('_', "-"))
+        .filter(|language| !language.is_empty() && language != "C" && language != "POSIX")
+}
+
+// Switches the language and gives egui the fonts it needs, on top of its built-in ones
+pub fn set_language(id: &str, context: &egui::Context) {
+    write(|localization| localization.set_language(id));
+    apply_fonts(context);
+}
+
+// egui's built-in fonts with the current language's fonts as fallbacks for the glyphs they lack
+pub fn apply_fonts(context: &egui::Context) {
+    let mut fonts = egui::FontDefinitions::default();
+    for path in read(Localization::fonts) {
+        let data = match std::fs::read(&path) {
+            Ok(data) => data,
+            Err(error) => {
+                tracing::warn!("Failed to load the font {}: {}", path.display(), error);
+                continue;
+            }
+        };
+        let name = path.display().to_string();
+        fonts.font_data.insert(name.clone(), egui::FontData::from_owned(data));
+        for family in [egui::FontFamily::Proportional, egui::FontFamily::Monospace] {
+            fonts.families.entry(family).or_default().push(name.clone());
+        }
+    }
+    context.set_fonts(fonts);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn localization(locales: &[(&str, &str)]) -> Localization {
+        let mut localization = Localization::new();
+        for (id, source) in locales {
+            localization.add(Locale::parse(id, source).unwrap());
+        }
+        localization
+    }
+
+    #[test]
+    fn plural_categories() {
+        let categories = |language, counts: &[&str]| counts.iter().map(|count| plural_category(language, count)).collect::<Vec<_>>();
+        assert_eq!(categories("en", &["0", "1", "2", "1.0", "-1"]), ["other", "one", "other", "other", "one"]);
+        assert_eq!(categories("fr", &["0", "1.5", "2", "1000000"]), ["one", "one", "other", "many"]);
+        assert_eq!(categories("ru", &["1", "21", "11", "3", "13", "5", "1.5"]), ["one", "one", "many", "few", "many", "many", "other"]);
+        assert_eq!(categories("pl", &["1", "21", "22", "12", "5"]), ["one", "many", "few", "many", "many"]);
+        assert_eq!(categories("cs", &["1", "3", "5", "0.5"]), ["one", "few", "other", "many"]);
+        assert_eq!(categories("ar", &["0", "1", "2", "3", "11", "100", "2.5"]), ["zero", "one", "two", "few", "many", "other", "other"]);
+        assert_eq!(categories("ja", &["1"]), ["other"]);
+        assert_eq!(categories("de-AT", &["1", "many"]), ["one", "other"]);
+    }
+
+    #[test]
+    fn format_picks_the_plural_variant() {
+        let localization = localization(&[
+            ("en", "test.apples.one = {count} apple\ntest.apples.other = {count} apples"),
+            ("ru", "test.apples.one = {count} яблоко\ntest.apples.few = {count} яблока\ntest.apples.other = {count} яблок")
+        ]);
+        let apples = |localization: &Localization, count: f32| localization.format("test.apples", &[("count", &count)]);
+        assert_eq!((apples(&localization, 1.0), apples(&localization, 3.0)), ("1 apple".to_string(), "3 apples".to_string()));
+
+        let mut localization = localization;
+        localization.set_language("ru");
+        assert_eq!(apples(&localization, 21.0), "21 яблоко");
+        assert_eq!(apples(&localization, 3.0), "3 яблока");
+        // No `.many` in the locale, `.other` stands in
+        assert_eq!(apples(&localization, 5.0), "5 яблок");
+    }
+
+    #[test]
+    fn substitutes_arguments() {
+        let name: &dyn Display = &"Ada";
+        assert_eq!(substitute("Hello {name}!", &[("name", name)]), "Hello Ada!");
+        assert_eq!(substitute("{{name}} is {name}", &[("name", name)]), "{name} is Ada");
+        assert_eq!(substitute("{unknown} {name", &[("name", name)]), "{unknown} {name");
+        assert_eq!(substitute("}", &[]), "}");
+    }
+
+    #[test]
+    fn falls_back_through_base_and_fallback_language() {
+        let mut localization = localization(&[
+            ("en", "test.greeting = Hello\ntest.farewell = Goodbye\ntest.thanks = Thanks"),
+            ("de", "test.greeting = Hallo\ntest.farewell = Tschüss"),
+            ("de-AT", "test.greeting = Servus")
+        ]);
+        localization.set_language("de-AT");
+        assert_eq!(localization.text("test.greeting"), "Servus");
+        assert_eq!(localization.text("test.farewell"), "Tschüss");
+        assert_eq!(localization.text("test.thanks"), "Thanks");
+        assert_eq!(localization.text("test.missing"), "test.missing");
+        assert_eq!(localization.format("test.missing", &[("count", &2)]), "test.missing");
+    }
+
+    #[test]
+    fn rejects_lines_without_a_key() {
+        assert!(Locale::parse("en", "# comment\n\n@name = English").is_ok());
+        assert!(Locale::parse("en", "no equals sign").unwrap_err().contains("Line 1"));
+        assert!(Locale::parse("en", "ok = fine\n = no key").unwrap_err().contains("Line 2"));
+    }
+}
\ No newline at end of file
@@ -0,0 +1,22 @@
+use tracing_subscriber::EnvFilter;
+
+// Environment variable holding the filter directives, e.g. `REVERIE_LOG=warn,reverie::assets=debug,vulkan=info`
+pub const LOG_ENV_VAR: &str = "REVERIE_LOG";
+// Used when the variable is unset or can't be parsed
+const DEFAULT_FILTER: &str = "warn,reverie=info,vulkan=warn";
+
+// Installs the global subscriber, call once before creating the renderer. Records from crates using `log` (like
+// gpu-allocator) are forwarded to it as well.
+pub fn init() {
+    let filter = match std::env::var(LOG_ENV_VAR) {
+        Ok(directives) => EnvFilter::try_new(&directives).unwrap_or_else(|error| {
+            eprintln!("[Reverie] Invalid {} filter \"{}\", using \"{}\": {}", LOG_ENV_VAR, directives, DEFAULT_FILTER, error);
+            EnvFilter::new(DEFAULT_FILTER)
+        }),
+        Err(_) => EnvFilter::new(DEFAULT_FILTER)
+    };
+
+    if tracing_subscriber::fmt().with_env_filter(filter).try_init().is_err() {
+        eprintln!("[Reverie] A global logger is already installed, keeping it");
+    }
+}
\ No newline at end of file
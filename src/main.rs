@@ -1,20 +1,72 @@
-pub mod vulkan;
-pub mod utils;
-
+use std::sync::Mutex;
 use std::time::Instant;
 
-use vulkan::{renderer::*, vertex::Vertex, mesh::Mesh, window::VulkanWindow, game_object::GameObject};
+use reverie::{vulkan, editor, assets, simulation, schedule, settings, benchmark, frame_limiter, logging, render_thread, touch, net, input, save, localization, coroutines, states, gui, cursor, file_drop, loading, tr};
+use vulkan::{renderer::*, vertex::Vertex, mesh::Mesh, window::VulkanWindow, game_object::GameObject, material::Material};
+use vulkan::reflection::ReflectionPlane;
+use vulkan::lights::{PointLight, SpotLight};
+use vulkan::gizmo::GizmoMode;
+use vulkan::screenshot::ScreenshotSettings;
+use vulkan::sdf_font::{SdfFont, SdfFontSettings};
+use vulkan::sdf_text::{TextShadow, TextStyle};
+use vulkan::rich_text::{FontFamily, TextAlign, TextBox};
+use vulkan::camera_controller::{CameraController, FlyController, OrbitController};
+use vulkan::game_object::{world_matrices, Transform3DComponent};
+use editor::Editor;
+use assets::AssetManager;
+use assets::preload::Preload;
+use simulation::{SimulationClock, StateHash};
+use schedule::{Access, Schedule};
+use settings::Settings;
+use benchmark::Benchmark;
+use frame_limiter::FrameLimiter;
+use render_thread::RenderThread;
+use touch::{Gesture, TouchInput};
+use coroutines::Coroutines;
+use cursor::{Cursor, CursorImage, Cursors};
+use file_drop::{FileDropEvent, FileDrops};
+use loading::LoadingScreen;
+use states::{GameState, InputCapture, StateStack, Transition};
+use gui::{Gui, GuiEvent, WidgetId};
+use gui::layout::{Align, Anchor, Edges, Size, Style};
+use gui::widget::{Visuals, Widget};
+use input::{Binding, InputEvent, InputState};
+use input::recording::{InputPlayer, InputRecorder, RecordingHeader};
+use save::{Migrations, SaveGame, SavedCamera, SavedTransforms};
+use net::{NetConfig, NetEvent, NetworkRole};
+use net::replication::{NetObjectId, ReplicatedComponents, ReplicationClient, ReplicationConfig, ReplicationServer};
 
-use winit::event::WindowEvent;
+use winit::event::{MouseButton, VirtualKeyCode, WindowEvent};
+use winit::window::CursorIcon;
 
 const WINDOW_TITLE: &'static str = "Reverie";
-const WINDOW_WIDTH: u32 = 800;
-const WINDOW_HEIGHT: u32 = 600;
+// Both sides create the square, the host turns it and clients follow
+const SQUARE_NET_ID: NetObjectId = 0;
+const QUICK_SAVE: &str = "quicksave.sav";
+// The square hops this high every few seconds
+const HOP_HEIGHT: f32 = 0.2;
+const HOP_SECONDS: f32 = 0.5;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let (event_loop, window) = VulkanWindow::create_window(WINDOW_TITLE, WINDOW_WIDTH, WINDOW_HEIGHT)?;
+    logging::init();
+
+    let settings = Settings::from_args();
+
+    let (event_loop, window) = VulkanWindow::create_window(WINDOW_TITLE, settings.width, settings.height, settings.fullscreen,
+        settings.window_style, !settings.headless && !settings.list_monitors, settings.window_backend)?;
+
+    if settings.list_monitors {
+        for (index, monitor) in window.monitors().iter().enumerate() {
+            println!("{}: {} {}x{} at {:?}", index, monitor.name, monitor.width, monitor.height, monitor.position);
+            for (index, mode) in monitor.video_modes.iter().enumerate() {
+                println!("    {}: {}x{} {:.2} Hz {} bit", index, mode.width, mode.height, mode.refresh_rate_millihertz as f32 / 1000.0, mode.bit_depth);
+            }
+        }
+        return Ok(());
+    }
 
-    let mut renderer = VulkanRenderer::new(&window)?;
+    let mut renderer = VulkanRenderer::new(&window, &settings.renderer)?;
+    renderer.set_viewport_mode(settings.viewport)?;
 
     let mut now = Instant::now();
     
@@ -22,20 +74,36 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let vertices: [Vertex; 4] = [
         Vertex {
-            pos: uv::Vec2::new(-0.5, -0.5),
+            pos: uv::Vec3::new(-0.5, -0.5, 0.0),
             color: uv::Vec3::new(1.0, 0.0, 0.0),
+            normal: uv::Vec3::unit_z(),
+            lightmap_uv: uv::Vec2::zero(),
+            tex_coord: uv::Vec2::new(0.0, 1.0),
+            tangent: uv::Vec4::new(1.0, 0.0, 0.0, 1.0),
         },
         Vertex {
-            pos: uv::Vec2::new(0.5, -0.5),
+            pos: uv::Vec3::new(0.5, -0.5, 0.0),
             color: uv::Vec3::new(0.0, 1.0, 0.0),
+            normal: uv::Vec3::unit_z(),
+            lightmap_uv: uv::Vec2::zero(),
+            tex_coord: uv::Vec2::new(1.0, 1.0),
+            tangent: uv::Vec4::new(1.0, 0.0, 0.0, 1.0),
         },
         Vertex {
-            pos: uv::Vec2::new(0.5, 0.5),
+            pos: uv::Vec3::new(0.5, 0.5, 0.0),
             color: uv::Vec3::new(0.0, 0.0, 1.0),
+            normal: uv::Vec3::unit_z(),
+            lightmap_uv: uv::Vec2::zero(),
+            tex_coord: uv::Vec2::new(1.0, 0.0),
+            tangent: uv::Vec4::new(1.0, 0.0, 0.0, 1.0),
         },
         Vertex {
-            pos: uv::Vec2::new(-0.5, 0.5),
+            pos: uv::Vec3::new(-0.5, 0.5, 0.0),
             color: uv::Vec3::new(1.0, 1.0, 1.0),
+            normal: uv::Vec3::unit_z(),
+            lightmap_uv: uv::Vec2::zero(),
+            tex_coord: uv::Vec2::new(0.0, 0.0),
+            tangent: uv::Vec4::new(1.0, 0.0, 0.0, 1.0),
         },
     ];
 
@@ -44,25 +112,243 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         2, 3, 0
     ];
 
-    mesh1.vertex_buffers[0].update_buffer(&vertices);
+    mesh1.update_vertex_buffer(&vertices);
     mesh1.update_index_buffer(&indices);
 
     let mut square = GameObject::new(mesh1, uv::Vec3::new(0.0, 0.0, 1.0));
-    square.transform2d.translation.x = 0.2;
+    square.transform3d.translation.x = 0.2;
+    square.transform3d.translation.y = 0.6;
 
+    // Spun by the fixed update, so pausing and stepping the simulation is visible
+    let square_id = square.get_id();
     renderer.game_objects.push(square);
 
+    let mut mirror_mesh = Mesh::new(&renderer.device, &mut renderer.allocator, 4, 6)?;
+    let mirror_vertices: [Vertex; 4] = [
+        Vertex { pos: uv::Vec3::new(-1.0, 0.0, 1.0), color: uv::Vec3::one(), normal: uv::Vec3::unit_y(), lightmap_uv: uv::Vec2::zero(),
+            tex_coord: uv::Vec2::new(0.0, 1.0), tangent: uv::Vec4::new(1.0, 0.0, 0.0, 1.0) },
+        Vertex { pos: uv::Vec3::new(1.0, 0.0, 1.0), color: uv::Vec3::one(), normal: uv::Vec3::unit_y(), lightmap_uv: uv::Vec2::zero(),
+            tex_coord: uv::Vec2::new(1.0, 1.0), tangent: uv::Vec4::new(1.0, 0.0, 0.0, 1.0) },
+        Vertex { pos: uv::Vec3::new(1.0, 0.0, -1.0), color: uv::Vec3::one(), normal: uv::Vec3::unit_y(), lightmap_uv: uv::Vec2::zero(),
+            tex_coord: uv::Vec2::new(1.0, 0.0), tangent: uv::Vec4::new(1.0, 0.0, 0.0, 1.0) },
+        Vertex { pos: uv::Vec3::new(-1.0, 0.0, -1.0), color: uv::Vec3::one(), normal: uv::Vec3::unit_y(), lightmap_uv: uv::Vec2::zero(),
+            tex_coord: uv::Vec2::new(0.0, 0.0), tangent: uv::Vec4::new(1.0, 0.0, 0.0, 1.0) },
+    ];
+    mirror_mesh.update_vertex_buffer(&mirror_vertices);
+    mirror_mesh.update_index_buffer(&indices);
+
+    let mut mirror = GameObject::new(mirror_mesh, uv::Vec3::new(0.8, 0.9, 1.0));
+    mirror.material = Material::Reflective;
+    renderer.game_objects.push(mirror);
+
+    renderer.enable_planar_reflection(ReflectionPlane::new(uv::Vec3::unit_y(), uv::Vec3::zero()))?;
+    renderer.camera.position = uv::Vec3::new(0.0, 1.0, 2.5);
+    // Only the scene covers the desktop
+    if settings.window_style.transparent {
+        renderer.camera.clear.color = uv::Vec4::zero();
+    }
+
+    renderer.lights.push(PointLight::new(uv::Vec3::new(0.5, 1.0, 1.0), uv::Vec3::one(), 4.0, 5.0));
+    renderer.spot_lights.push(SpotLight::new(uv::Vec3::new(-1.0, 2.0, 1.0), uv::Vec3::new(0.5, -1.0, -0.4),
+        uv::Vec3::new(1.0, 0.9, 0.7), 8.0, 6.0, 30f32.to_radians()));
+
+    // Right drag orbits (or looks around in fly mode, moving with WASD/QE), middle drag pans, Tab switches modes
+    let mut controller = CameraController::Orbit(OrbitController::from_camera(&renderer.camera));
+    let mut input = InputState::new();
+    for (action, key) in [("forward", VirtualKeyCode::W), ("back", VirtualKeyCode::S), ("left", VirtualKeyCode::A),
+        ("right", VirtualKeyCode::D), ("up", VirtualKeyCode::E), ("down", VirtualKeyCode::Q)] {
+        input.bind(action, Binding::Key(key));
+    }
+    // Touch screens drag and pinch the camera like the mouse buttons do, a tap picks like a click
+    let mut touch_input = TouchInput::default();
+    // A crosshair drawn in software while flying
+    let mut cursors = Cursors::new();
+    let crosshair: Vec<u8> = (0..32 * 32).flat_map(|index| {
+        let (x, y) = ((index % 32) as f32 - 16.0, (index / 32) as f32 - 16.0);
+        let ring = ((x * x + y * y).sqrt() - 10.0).abs() < 1.2;
+        let cross = (x.abs() < 1.0 || y.abs() < 1.0) && (x * x + y * y) > 16.0 && (x * x + y * y) < 196.0;
+        match ring || cross {
+            true => [255, 255, 255, 230],
+            false => [0, 0, 0, 0]
+        }
+    }).collect();
+    cursors.add("crosshair", CursorImage::new([32, 32], crosshair, [16, 16]));
+    let mut file_drops = FileDrops::new();
+
+    // Locales of the game next to the engine's built-in ones, then the UI language from the command line or the system
+    let locales = std::path::Path::new("locales");
+    if locales.is_dir() {
+        if let Err(error) = localization::write(|localization| localization.load_dir(locales)) {
+            tracing::warn!("Failed to load the locales in {}: {}", locales.display(), error);
+        }
+    }
+    let language = settings.language.clone().or_else(localization::system_language).unwrap_or_else(|| "en".to_string());
+    localization::set_language(&language, &renderer.ui.context);
+
+    // Title drawn from a distance field atlas of egui's own font, sharp at any size
+    let title_font = {
+        let data = egui::FontDefinitions::default().font_data["Ubuntu-Light"].font.to_vec();
+        let font = SdfFont::new(data, SdfFontSettings::default()).map_err(|error| format!("Failed to load the title font: {}", error))?;
+        renderer.add_sdf_font(font)?
+    };
+    let title_style = TextStyle {
+        size: 56.0,
+        color: uv::Vec4::new(1.0, 0.95, 0.85, 1.0),
+        outline: Some((2.0, uv::Vec4::new(0.1, 0.08, 0.2, 1.0))),
+        shadow: Some(TextShadow { offset: uv::Vec2::new(4.0, 4.0), softness: 3.0, color: uv::Vec4::new(0.0, 0.0, 0.0, 0.6) })
+    };
+    // A caption under it in markup, wrapped to a box and cut off after two lines
+    let caption_family = FontFamily::new(title_font);
+    let caption_style = TextStyle {
+        size: 22.0,
+        shadow: Some(TextShadow { offset: uv::Vec2::new(2.0, 2.0), softness: 1.0, color: uv::Vec4::new(0.0, 0.0, 0.0, 0.6) }),
+        ..TextStyle::default()
+    };
+    let caption_box = TextBox { max_width: Some(420.0), align: TextAlign::Center, max_lines: Some(2), ..TextBox::default() };
+    let coin: Vec<u8> = (0..32 * 32).flat_map(|index| {
+        let (x, y) = ((index % 32) as f32 - 15.5, (index / 32) as f32 - 15.5);
+        let distance = (x * x + y * y).sqrt();
+        let shade = match distance < 11.0 {
+            true => 220,
+            false => 180
+        };
+        [255, shade, 60, ((16.0 - distance).clamp(0.0, 1.0) * 255.0) as u8]
+    }).collect();
+    renderer.add_text_icon("coin", ash::vk::Extent2D { width: 32, height: 32 }, &coin)?;
+
+    let mut editor = Editor::default();
+    let mut assets = AssetManager::new("assets");
+    let mut clock = SimulationClock::new(60.0);
+    if let Some(seed) = settings.deterministic {
+        clock.set_deterministic(seed);
+    }
+
+    let mut player = match &settings.replay_input {
+        Some(path) => {
+            let player = InputPlayer::open(path).map_err(|error| format!("Failed to open the input recording {}: {}", path.display(), error))?;
+            // Same simulation as when it was recorded
+            clock = SimulationClock::new(player.header().ticks_per_second);
+            if let Some(seed) = player.header().seed {
+                clock.set_deterministic(seed);
+            }
+            tracing::info!("Replaying {} recorded input events from {}", player.remaining(), path.display());
+            Some(player)
+        }
+        None => None
+    };
+    let mut recorder = match &settings.record_input {
+        Some(path) => {
+            let header = RecordingHeader {
+                ticks_per_second: 1.0 / clock.fixed_delta,
+                seed: clock.is_deterministic().then(|| clock.seed())
+            };
+            Some(InputRecorder::create(path, &header).map_err(|error| format!("Failed to create {}: {}", path.display(), error))?)
+        }
+        None => None
+    };
+    let mut benchmark = settings.benchmark.map(Benchmark::new);
+    let mut limiter = FrameLimiter::new(settings.fps_cap, settings.background_fps_cap);
+    limiter.set_refresh_divisor(settings.refresh_divisor);
+    limiter.set_refresh_rate(window.refresh_rate());
+
+    // Read in the background behind a loading screen, added once it's in
+    let mut pending_scene = settings.scene.clone();
+    if let Some(scene) = &pending_scene {
+        assets.preload([Preload::Mesh(scene.clone())], renderer.supports_texture_compression());
+    }
+
+    let mut network = match &settings.network {
+        Some(role) => Some(role.open(NetConfig::default()).map_err(|error| format!("Failed to open {:?}: {}", role, error))?),
+        None => None
+    };
+    let mut replication_server = None;
+    let mut replication_client = None;
+    match &settings.network {
+        Some(NetworkRole::Host(_)) => {
+            let mut server = ReplicationServer::new(ReplicationConfig::default());
+            server.replicate_as(SQUARE_NET_ID, square_id, ReplicatedComponents::TRANSFORM);
+            replication_server = Some(server);
+        }
+        Some(NetworkRole::Connect(_)) => {
+            let mut client = ReplicationClient::new(ReplicationConfig::default());
+            client.bind(SQUARE_NET_ID, square_id);
+            replication_client = Some(client);
+        }
+        None => {}
+    }
+
+    // Systems of a simulation tick, run on the job threads. The square spins before its state is hashed, as they
+    // conflict over its transform.
+    let mut systems: Schedule<TickWorld> = Schedule::new();
+    systems.add("Spin square", Access::new().write::<Transform3DComponent>(), |world: &TickWorld| {
+        if let Some(square) = &mut *world.square.lock().unwrap() {
+            square.rotation = (uv::Rotor3::from_rotation_xz(world.fixed_delta) * square.rotation).normalized();
+        }
+    });
+    systems.add("Hash state", Access::new().read::<Transform3DComponent>().write::<StateHash>(), |world: &TickWorld| {
+        let mut hash = StateHash::new();
+        if let Some(square) = &*world.square.lock().unwrap() {
+            hash.write_transform(square);
+        }
+        *world.hash.lock().unwrap() = hash;
+    });
+    systems.set_ordered(clock.is_deterministic());
+
+    // Sequences of the simulation, working on its copy of the square's transform. They stay on this thread and run
+    // after the systems of each tick.
+    let mut sequences: Coroutines<Option<Transform3DComponent>> = Coroutines::new();
+    sequences.start(|co| async move {
+        co.seconds(2.0).await;
+        loop {
+            let start = co.time();
+            let mut height = 0.0;
+            while co.time() - start < HOP_SECONDS as f64 {
+                let t = ((co.time() - start) as f32 / HOP_SECONDS).min(1.0);
+                let next = (t * std::f32::consts::PI).sin() * HOP_HEIGHT;
+                co.with(|square| if let Some(square) = square {
+                    square.translation.y += next - height;
+                });
+                height = next;
+                co.next_tick().await;
+            }
+            co.with(|square| if let Some(square) = square {
+                square.translation.y -= height;
+            });
+            co.seconds(3.0).await;
+        }
+    });
+
+    // Gameplay at the bottom, Escape pauses it under a menu
+    let mut states: StateStack<SimulationClock> = StateStack::new();
+    states.push(Box::new(Gameplay::new()), &mut clock);
+    if pending_scene.is_some() {
+        states.push(Box::new(LoadingScreen::new(assets.progress())), &mut clock);
+    }
+
+    // Recording, submission and presentation happen on the render thread from here on. Window events wait for the next
+    // frame of the game loop, so the renderer is locked once per frame instead of once per event.
+    let render_thread = RenderThread::spawn(renderer)?;
+    let mut window_events: Vec<WindowEvent<'static>> = Vec::new();
+
     event_loop.run(move |event, _, controlflow| match event {
         winit::event::Event::WindowEvent {event, ..} => match event {
             WindowEvent::CloseRequested => {
                 *controlflow = winit::event_loop::ControlFlow::Exit;
             }
-            _ => {}
+            WindowEvent::Focused(focused) => limiter.set_focused(focused),
+            // The window may have ended up on a monitor with another refresh rate
+            WindowEvent::Moved(_) => limiter.set_refresh_rate(window.refresh_rate()),
+            _ => window_events.extend(event.to_static())
         }
         winit::event::Event::MainEventsCleared => {
             window.window.request_redraw();
         }
         winit::event::Event::RedrawRequested(_) => {
+            if !render_thread.is_running() {
+                *controlflow = winit::event_loop::ControlFlow::Exit;
+                return;
+            }
+            limiter.wait();
             let delta_time = now.elapsed().as_secs_f32() * 1000.0;
             now = Instant::now();
             let fps = ((1000.0 / delta_time) * 10.0).round() / 10.0;
@@ -70,11 +356,421 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             window.window.set_title(&format!("{} - FPS: {:.0} ({:.3}ms)",
                 WINDOW_TITLE, fps.round(), delta_time));
 
-            VulkanRenderer::fill_commandbuffers(&renderer.command_buffers, &renderer.device, &renderer.renderpass, &renderer.swapchain, &renderer.pipeline, &renderer.game_objects)
-                .expect("Failed to write commands!");
+            let mut guard = render_thread.lock();
+            let renderer = &mut *guard;
+            let mut scene_events = vec![];
+            for event in window_events.drain(..) {
+                cursors.handle_event(&event);
+                // Files dropped onto the window are added where they land
+                if let Some(FileDropEvent::Dropped { path, x, y }) = file_drops.handle(&event) {
+                    match assets.import_file(renderer, &path, x, y) {
+                        Ok(Some(id)) => renderer.selected = Some(id),
+                        Ok(None) => {}
+                        Err(error) => tracing::warn!("Failed to add the dropped file {}: {}", path.display(), error)
+                    }
+                }
+                // The editor UI sees every event first, the scene only gets what it didn't use
+                let consumed = renderer.ui.handle_event(&event);
+                match event {
+                    // Going in or out of exclusive fullscreen resizes the window as well
+                    WindowEvent::Resized(size) => {
+                        renderer.resize(size.width, size.height);
+                        renderer.update_fullscreen(&window);
+                        limiter.set_refresh_rate(window.refresh_rate());
+                    }
+                    // Kept up to date over the UI as well, so moving off it doesn't jump. Replays move it themselves.
+                    WindowEvent::CursorMoved { position, .. } if consumed && player.is_none() => {
+                        input.handle(&InputEvent::CursorMoved { x: position.x as f32, y: position.y as f32 });
+                    }
+                    _ if consumed => {}
+                    WindowEvent::Touch(touch) => match touch_input.handle(&touch) {
+                        Some(Gesture::Tap { x, y }) => {
+                            renderer.selected = renderer.pick(x as u32, y as u32)
+                                .expect("Failed to pick object!");
+                        }
+                        Some(gesture) => {
+                            touch::apply_gesture(&mut controller, &renderer.camera, gesture, renderer.viewport.rect.extent.height as f32);
+                        }
+                        None => {}
+                    }
+                    _ => if let Some(event) = InputEvent::from_window_event(&event) {
+                        // Releases reach the scene under menus as well, so nothing stays held once they close
+                        let released = matches!(event, InputEvent::Key { pressed: false, .. } | InputEvent::Button { pressed: false, .. });
+                        if !states.handle_input(&mut clock, &event) || released {
+                            scene_events.push(event);
+                        }
+                    }
+                }
+            }
+
+            // A replay stands in for the mouse and keyboard until it's over
+            if let Some(replay) = &mut player {
+                scene_events = replay.poll(clock.tick(), Instant::now());
+                if replay.is_finished() {
+                    tracing::info!("Finished replaying the input recording");
+                    clock.set_tick_limit(None);
+                    player = None;
+                }
+            }
+            let recorded = match &mut recorder {
+                Some(active) => scene_events.iter().try_for_each(|event| active.record(clock.tick(), *event)),
+                None => Ok(())
+            };
+            if let Err(error) = recorded {
+                tracing::error!("Failed to record input, stopped recording: {}", error);
+                recorder = None;
+            }
+
+            for event in scene_events {
+                let cursor = input.cursor();
+                input.handle(&event);
+                // Letters move the fly camera while looking around
+                let flying = matches!(controller, CameraController::Fly(_)) && input.is_button_held(MouseButton::Right);
+
+                match event {
+                    InputEvent::CursorMoved { x, y } => {
+                        let (dx, dy) = (x - cursor.x, y - cursor.y);
+                        match &mut controller {
+                            CameraController::Orbit(orbit) if input.is_button_held(MouseButton::Right) => orbit.rotate(dx, dy),
+                            CameraController::Orbit(orbit) if input.is_button_held(MouseButton::Middle) => {
+                                orbit.pan(&renderer.camera, dx, dy, renderer.viewport.rect.extent.height as f32);
+                            }
+                            CameraController::Fly(fly) if input.is_button_held(MouseButton::Right) => fly.look(dx, dy),
+                            _ => renderer.gizmo_cursor_moved(x, y)
+                        }
+                    }
+                    InputEvent::Button { button: MouseButton::Left, pressed: true } => {
+                        let grabbed_gizmo = renderer.gizmo_pressed(cursor.x, cursor.y);
+                        if !grabbed_gizmo {
+                            renderer.selected = renderer.pick(cursor.x as u32, cursor.y as u32)
+                                .expect("Failed to pick object!");
+                        }
+                    }
+                    InputEvent::Button { button: MouseButton::Left, pressed: false } => renderer.gizmo_released(),
+                    InputEvent::Button { .. } => {}
+                    InputEvent::Scroll { y, .. } => match &mut controller {
+                        CameraController::Orbit(orbit) => orbit.dolly(y),
+                        CameraController::Fly(fly) => fly.speed = (fly.speed * (1.0 + y * 0.1)).max(0.1)
+                    },
+                    InputEvent::Key { pressed: false, .. } => {}
+                    InputEvent::Key { key, pressed: true } => match key {
+                        VirtualKeyCode::Tab => {
+                            controller = controller.toggled(&renderer.camera);
+                            cursors.set(match controller {
+                                CameraController::Fly(_) => Cursor::Image("crosshair".to_string()),
+                                CameraController::Orbit(_) => Cursor::System(CursorIcon::Default)
+                            });
+                        }
+                        VirtualKeyCode::F => {
+                            if let (CameraController::Orbit(orbit), Some(index)) = (&mut controller, renderer.selected_index()) {
+                                let transform = &renderer.game_objects[index].transform3d;
+                                let radius = transform.scale.x.max(transform.scale.y).max(transform.scale.z);
+                                let center = world_matrices(&renderer.game_objects)[index].cols[3].xyz();
+                                orbit.focus_on(&renderer.camera, center, radius);
+                            }
+                        }
+                        VirtualKeyCode::W if !flying => renderer.gizmo.mode = GizmoMode::Translate,
+                        VirtualKeyCode::E if !flying => renderer.gizmo.mode = GizmoMode::Rotate,
+                        VirtualKeyCode::R if !flying => renderer.gizmo.mode = GizmoMode::Scale,
+                        VirtualKeyCode::P => clock.toggle_paused(),
+                        VirtualKeyCode::N => clock.step(),
+                        VirtualKeyCode::F12 => match renderer.capture_screenshot(ScreenshotSettings::default()) {
+                            Ok(screenshot) => match screenshot.save("screenshot.png") {
+                                Ok(()) => tracing::info!("Saved a {}x{} screenshot", screenshot.width, screenshot.height),
+                                Err(error) => tracing::error!("Failed to save the screenshot: {}", error)
+                            },
+                            Err(error) => tracing::error!("Failed to capture a screenshot: {}", error)
+                        },
+                        VirtualKeyCode::F5 => match quick_save(renderer, &clock) {
+                            Ok(()) => tracing::info!("Saved the game to {}", QUICK_SAVE),
+                            Err(error) => tracing::error!("Failed to save the game: {}", error)
+                        },
+                        VirtualKeyCode::F9 => match quick_load(renderer) {
+                            Ok(()) => {
+                                controller = match controller {
+                                    CameraController::Orbit(_) => CameraController::Orbit(OrbitController::from_camera(&renderer.camera)),
+                                    CameraController::Fly(_) => CameraController::Fly(FlyController::from_camera(&renderer.camera))
+                                };
+                                tracing::info!("Loaded the game from {}", QUICK_SAVE);
+                            }
+                            Err(error) => tracing::error!("Failed to load the game: {}", error)
+                        },
+                        _ => {}
+                    }
+                }
+            }
+
+            if let CameraController::Fly(fly) = &mut controller {
+                if input.is_button_held(MouseButton::Right) {
+                    let direction = uv::Vec3::new(input.axis("right", "left"), input.axis("up", "down"), input.axis("forward", "back"));
+                    fly.travel(direction, delta_time / 1000.0);
+                }
+            }
+            controller.apply(&mut renderer.camera);
+
+            if assets.progress().is_finished() {
+                if let Some(scene) = pending_scene.take() {
+                    if let Err(error) = assets.instantiate_file(renderer, &scene, uv::Vec3::zero()) {
+                        tracing::error!("Failed to load the scene {}: {}", scene.display(), error);
+                    }
+                }
+            }
+
+            let context = renderer.ui.begin_frame(renderer.swapchain.extent);
+            if !settings.headless {
+                reverie::profile_scope!("Editor");
+                editor.show(&context, renderer, &mut assets, &mut clock, &mut limiter, delta_time / 1000.0)
+                    .expect("Failed to update the editor!");
+            }
+            if !settings.headless {
+                let width = renderer.sdf_text.font(title_font).measure("Reverie", title_style.size).x;
+                let position = uv::Vec2::new((renderer.swapchain.extent.width as f32 - width) / 2.0, 24.0);
+                renderer.sdf_text.draw(title_font, "Reverie", position, &title_style);
+                let caption = "[color=#ffcc66]Rich text[/color] with [i]variants[/i], inline icons [icon=coin] and word wrapping, \
+                    cut off with an ellipsis once it runs past the two lines of its box";
+                let position = uv::Vec2::new((renderer.swapchain.extent.width as f32 - 420.0) / 2.0, 96.0);
+                renderer.sdf_text.draw_rich(&caption_family, caption, position, &caption_style, &caption_box);
+            }
+            states.update(&mut clock, delta_time / 1000.0);
+            states.draw(&mut clock, &context);
+            cursors.update(&window.window, &context);
+            file_drops.show(&context);
+            renderer.end_ui_frame()
+                .expect("Failed to finish the UI frame!");
+            if states.should_quit() {
+                *controlflow = winit::event_loop::ControlFlow::Exit;
+            }
+
+            if let Some(benchmark) = &mut benchmark {
+                if benchmark.record(delta_time / 1000.0) {
+                    benchmark.print_report(renderer);
+                    *controlflow = winit::event_loop::ControlFlow::Exit;
+                }
+            }
 
-            renderer.draw_frame();
+            if let (Some(server), Some(endpoint)) = (&mut replication_server, &mut network) {
+                server.update(endpoint, &renderer.game_objects, &assets, Instant::now());
+            }
+            if let Some(client) = &mut replication_client {
+                client.set_view(renderer.camera.position);
+                client.apply(renderer, &mut assets, Instant::now());
+            }
+
+            // The simulation works on its own copy of what it changes and hands the result over in the frame packet.
+            // Clients leave the square to replication.
+            let square_transform = renderer.game_objects.iter()
+                .find(|game_object| game_object.get_id() == square_id && replication_client.is_none())
+                .map(|square| square.transform3d);
+            drop(guard);
+
+            // Ticks stop where the next recorded event is due, so it comes in on the same tick as when it was recorded
+            if let Some(replay) = &player {
+                clock.set_tick_limit(replay.next_tick());
+            }
+            let ticks = clock.advance(limiter.smooth(delta_time / 1000.0));
+            let world = TickWorld { fixed_delta: clock.fixed_delta, square: Mutex::new(square_transform), hash: Mutex::default() };
+            for tick in clock.tick() - ticks as u64..clock.tick() {
+                reverie::profile_scope!("Simulation Tick");
+                systems.run(&world);
+                sequences.update(clock.fixed_delta, &mut world.square.lock().unwrap());
+                // Replays of a deterministic run log the same hashes up to where they diverged
+                if clock.is_deterministic() {
+                    tracing::trace!("Tick {} hashes to {:016x}", tick, world.hash.lock().unwrap().finish());
+                }
+            }
+            let square_transform = world.square.into_inner().unwrap();
+            input.end_frame();
+
+            if let Some(endpoint) = &mut network {
+                let now = Instant::now();
+                for event in endpoint.update(now) {
+                    let replicated = replication_server.as_mut().is_some_and(|server| server.receive(&event))
+                        || replication_client.as_mut().is_some_and(|client| client.receive(&event, now));
+                    if replicated {
+                        continue;
+                    }
+                    match event {
+                        NetEvent::Connected(peer) => tracing::info!("{:?} connected from {:?}", peer, endpoint.peer_addr(peer)),
+                        NetEvent::Disconnected(peer, reason) => tracing::info!("{:?} disconnected: {:?}", peer, reason),
+                        NetEvent::Message(_) => {}
+                    }
+                }
+                if let Some(client) = &mut replication_client {
+                    client.update(endpoint, now);
+                }
+            }
+
+            let mut packet = render_thread.packet();
+            packet.transforms.extend(square_transform.map(|transform| (square_id, transform)));
+            render_thread.publish(packet);
         }
+        // Writes out what the recorder still buffers
+        winit::event::Event::LoopDestroyed => recorder = None,
         _ => {}
     });
+}
+
+// What the systems of a simulation tick share
+struct TickWorld {
+    fixed_delta: f32,
+    // Copy of the square's transform, `None` when it's left to replication
+    square: Mutex<Option<Transform3DComponent>>,
+    // Of the state after the tick's systems
+    hash: Mutex<StateHash>,
+}
+
+// The object transforms and the camera, the rest of the scene is rebuilt on start anyway
+fn quick_save(renderer: &VulkanRenderer, clock: &SimulationClock) -> Result<(), Box<dyn std::error::Error>> {
+    let mut save = SaveGame::new("Quick save", clock.time());
+    save.put(&SavedTransforms::capture(&renderer.game_objects, |_| true))?;
+    save.put(&SavedCamera(renderer.camera.clone()))?;
+    save.write(std::path::Path::new(QUICK_SAVE))?;
+    Ok(())
+}
+
+fn quick_load(renderer: &mut VulkanRenderer) -> Result<(), Box<dyn std::error::Error>> {
+    let save = SaveGame::read(std::path::Path::new(QUICK_SAVE))?;
+    let migrations = Migrations::new();
+    if let Some(transforms) = save.get::<SavedTransforms>(&migrations)? {
+        transforms.apply(&mut renderer.game_objects);
+    }
+    if let Some(SavedCamera(camera)) = save.get::<SavedCamera>(&migrations)? {
+        renderer.camera = vulkan::camera::Camera { aspect_ratio: renderer.camera.aspect_ratio, ..camera };
+    }
+    Ok(())
+}
+
+// The scene itself, which the game loop still drives. Lets input through to it, with a HUD over it.
+struct Gameplay {
+    hud: Gui,
+    hint: WidgetId,
+    // Localization generation the texts are in
+    language: u64,
+}
+
+impl Gameplay {
+    fn new() -> Self {
+        let mut hud = Gui::new("hud", egui::Order::Background);
+        let hint = hud.add(hud.root(), Widget::label(tr!("hud.menu_hint")).with_style(Style {
+            anchor: Some(Anchor::BottomLeft),
+            offset: egui::vec2(12.0, -12.0),
+            ..Style::default()
+        }));
+        Self { hud, hint, language: localization::read(|localization| localization.generation()) }
+    }
+}
+
+impl GameState<SimulationClock> for Gameplay {
+    fn name(&self) -> &str {
+        "Gameplay"
+    }
+
+    fn handle_input(&mut self, _clock: &mut SimulationClock, event: &InputEvent) -> (bool, Transition<SimulationClock>) {
+        match event {
+            InputEvent::Key { key: VirtualKeyCode::Escape, pressed: true } => (true, Transition::Push(Box::new(PauseMenu::new()))),
+            _ => (self.hud.handle(event), Transition::None)
+        }
+    }
+
+    fn draw(&mut self, _clock: &mut SimulationClock, ui: &egui::Context) -> Transition<SimulationClock> {
+        let language = localization::read(|localization| localization.generation());
+        if language != self.language {
+            self.hud.set_text(self.hint, tr!("hud.menu_hint"));
+            self.language = language;
+        }
+        self.hud.show(ui);
+        Transition::None
+    }
+
+    fn input_capture(&self) -> InputCapture {
+        InputCapture::PassThrough
+    }
+}
+
+// Holds the simulation while it's open and keeps input from the scene
+struct PauseMenu {
+    // Paused from the debug overlay before, stays paused when the menu closes
+    was_paused: bool,
+    gui: Gui,
+    title: WidgetId,
+    resume: WidgetId,
+    quit: WidgetId,
+    language: u64,
+}
+
+impl PauseMenu {
+    fn new() -> Self {
+        let mut gui = Gui::new("pause_menu", egui::Order::Foreground);
+        let shade = Visuals { background: egui::Color32::from_black_alpha(120), ..Visuals::default() };
+        let shade = gui.add(gui.root(), Widget::panel(Style {
+            width: Size::Fill(1.0),
+            height: Size::Fill(1.0),
+            anchor: Some(Anchor::Center),
+            ..Style::default()
+        }).with_visuals(shade));
+        let menu = gui.add(shade, Widget::panel(Style {
+            anchor: Some(Anchor::Center),
+            padding: Edges::all(16.0),
+            gap: 8.0,
+            align: Align::Stretch,
+            ..Style::default()
+        }).with_visuals(Visuals::panel()));
+        let title = gui.add(menu, Widget::label(tr!("pause.title"))
+            .with_visuals(Visuals { font: egui::FontId::proportional(28.0), ..Visuals::default() }));
+        let resume = gui.add(menu, Widget::button(tr!("pause.resume")));
+        let quit = gui.add(menu, Widget::button(tr!("pause.quit")));
+        gui.set_focus(Some(resume));
+
+        Self {
+            was_paused: false,
+            gui,
+            title,
+            resume,
+            quit,
+            language: localization::read(|localization| localization.generation())
+        }
+    }
+}
+
+impl GameState<SimulationClock> for PauseMenu {
+    fn name(&self) -> &str {
+        "Pause menu"
+    }
+
+    fn enter(&mut self, clock: &mut SimulationClock) {
+        self.was_paused = clock.is_paused();
+        clock.set_paused(true);
+    }
+
+    fn exit(&mut self, clock: &mut SimulationClock) {
+        clock.set_paused(self.was_paused);
+    }
+
+    fn handle_input(&mut self, _clock: &mut SimulationClock, event: &InputEvent) -> (bool, Transition<SimulationClock>) {
+        match event {
+            InputEvent::Key { key: VirtualKeyCode::Escape, pressed: true } => (true, Transition::Pop),
+            _ => (self.gui.handle(event), Transition::None)
+        }
+    }
+
+    fn draw(&mut self, _clock: &mut SimulationClock, ui: &egui::Context) -> Transition<SimulationClock> {
+        let language = localization::read(|localization| localization.generation());
+        if language != self.language {
+            self.gui.set_text(self.title, tr!("pause.title"));
+            self.gui.set_text(self.resume, tr!("pause.resume"));
+            self.gui.set_text(self.quit, tr!("pause.quit"));
+            self.language = language;
+        }
+        self.gui.show(ui);
+
+        let mut transition = Transition::None;
+        for event in self.gui.take_events() {
+            match event {
+                GuiEvent::Clicked(id) if id == self.resume => transition = Transition::Pop,
+                GuiEvent::Clicked(id) if id == self.quit => transition = Transition::Quit,
+                _ => {}
+            }
+        }
+        transition
+    }
 }
\ No newline at end of file
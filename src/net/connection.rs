@@ -0,0 +1,426 @@
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use super::packet::{self, MessageHeader, PayloadHeader, MAX_PACKET_SIZE, MESSAGE_HEADER_SIZE, PAYLOAD_HEADER_SIZE};
+use super::ChannelKind;
+
+// Sent packets remembered for their acks, older ones count as lost
+const SENT_PACKETS: usize = 256;
+// Weight of a new sample in the smoothed round trip time
+const RTT_SMOOTHING: f32 = 0.1;
+// Reliable messages arriving further ahead of the next one expected are dropped instead of buffered, the sender
+// resends them anyway
+const RECEIVE_WINDOW: u16 = 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConnectionState {
+    // Waiting for the server to accept
+    Connecting,
+    Connected,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ConnectionStats {
+    // Smoothed, zero until the first ack
+    pub rtt: Duration,
+    pub packets_sent: u64,
+    pub packets_received: u64,
+    // Sent packets that were never acked
+    pub packets_lost: u64,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    pub messages_resent: u64,
+}
+
+struct Outgoing {
+    header: MessageHeader,
+    payload: Vec<u8>,
+    // When it was last put into a packet
+    sent: Option<Instant>,
+}
+
+struct SentPacket {
+    sequence: u16,
+    time: Instant,
+    // The reliable messages in it, by channel and id
+    reliable: Vec<(u8, u16)>,
+}
+
+// Message bytes of a packet and the reliable messages in it, by channel and id
+type PacketBody = (Vec<u8>, Vec<(u8, u16)>);
+
+struct Channel {
+    kind: ChannelKind,
+    next_send_id: u16,
+    // Reliable: the next id to deliver, the ones after it that arrived early wait in `early`
+    next_receive_id: u16,
+    early: HashMap<u16, Vec<u8>>,
+    // Sequenced: the newest id delivered
+    newest: Option<u16>,
+}
+
+// One peer on the other end of the socket. Every packet acks the last 33 packets received, reliable messages are sent
+// again until a packet carrying them is acked.
+pub struct Connection {
+    pub addr: SocketAddr,
+    pub state: ConnectionState,
+    channels: Vec<Channel>,
+    // Sent once with the next packets
+    unreliable: Vec<Outgoing>,
+    // Until acked
+    reliable: VecDeque<Outgoing>,
+    sent: VecDeque<SentPacket>,
+    local_sequence: u16,
+    // Newest sequence received and which of the 32 before it were received too
+    remote_sequence: u16,
+    ack_bits: u32,
+    received_any: bool,
+    // Received something the other side should hear back about soon
+    needs_ack: bool,
+    pub last_sent: Instant,
+    pub last_received: Instant,
+    rtt: Option<f32>,
+    stats: ConnectionStats,
+}
+
+impl Connection {
+    pub fn new(addr: SocketAddr, state: ConnectionState, channels: &[ChannelKind], now: Instant) -> Self {
+        Self {
+            addr,
+            state,
+            channels: channels.iter().map(|&kind| Channel {
+                kind,
+                next_send_id: 0,
+                next_receive_id: 0,
+                early: HashMap::new(),
+                newest: None
+            }).collect(),
+            unreliable: vec![],
+            reliable: VecDeque::new(),
+            sent: VecDeque::new(),
+            // Before the other side received anything it acks sequence 0, which is only used again after wrapping around
+            local_sequence: 1,
+            remote_sequence: 0,
+            ack_bits: 0,
+            received_any: false,
+            needs_ack: false,
+            last_sent: now,
+            last_received: now,
+            rtt: None,
+            stats: ConnectionStats::default()
+        }
+    }
+
+    pub fn stats(&self) -> ConnectionStats {
+        ConnectionStats {
+            rtt: Duration::from_secs_f32(self.rtt.unwrap_or(0.0)),
+            ..self.stats
+        }
+    }
+
+    // Messages sent on reliable channels but not acked yet
+    pub fn unacked(&self) -> usize {
+        self.reliable.len()
+    }
+
+    // `channel` must exist and the payload be at most `MAX_MESSAGE_SIZE`
+    pub fn queue(&mut self, channel: usize, payload: Vec<u8>) {
+        let state = &mut self.channels[channel];
+        let message = Outgoing {
+            header: MessageHeader { channel: channel as u8, id: state.next_send_id },
+            payload,
+            sent: None
+        };
+        state.next_send_id = state.next_send_id.wrapping_add(1);
+
+        match state.kind {
+            ChannelKind::Reliable => self.reliable.push_back(message),
+            ChannelKind::Unreliable | ChannelKind::Sequenced => self.unreliable.push(message)
+        }
+    }
+
+    // Takes in a payload packet, the messages it delivers go to `delivered` in order with their channel
+    pub fn receive(&mut self, header: PayloadHeader, messages: &[u8], now: Instant, delivered: &mut Vec<(usize, Vec<u8>)>) {
+        self.last_received = now;
+        self.needs_ack = true;
+        self.stats.packets_received += 1;
+        self.stats.bytes_received += (PAYLOAD_HEADER_SIZE + messages.len()) as u64;
+
+        self.receive_acks(header.ack, header.ack_bits, now);
+        if !self.track_sequence(header.sequence) {
+            return;
+        }
+
+        for (message, payload) in packet::read_messages(messages) {
+            let channel = match self.channels.get_mut(message.channel as usize) {
+                Some(channel) => channel,
+                None => continue
+            };
+
+            match channel.kind {
+                ChannelKind::Unreliable => delivered.push((message.channel as usize, payload.to_vec())),
+                ChannelKind::Sequenced => {
+                    if channel.newest.is_none_or(|newest| packet::sequence_greater(message.id, newest)) {
+                        channel.newest = Some(message.id);
+                        delivered.push((message.channel as usize, payload.to_vec()));
+                    }
+                }
+                ChannelKind::Reliable => {
+                    if message.id == channel.next_receive_id {
+                        delivered.push((message.channel as usize, payload.to_vec()));
+                        channel.next_receive_id = channel.next_receive_id.wrapping_add(1);
+                        while let Some(payload) = channel.early.remove(&channel.next_receive_id) {
+                            delivered.push((message.channel as usize, payload));
+                            channel.next_receive_id = channel.next_receive_id.wrapping_add(1);
+                        }
+                    } else if packet::sequence_greater(message.id, channel.next_receive_id)
+                        && message.id.wrapping_sub(channel.next_receive_id) < RECEIVE_WINDOW {
+                        channel.early.insert(message.id, payload.to_vec());
+                    }
+                }
+            }
+        }
+    }
+
+    // Notes the sequence for the acks, false if the packet was seen before or is too old to tell
+    fn track_sequence(&mut self, sequence: u16) -> bool {
+        if !self.received_any {
+            self.received_any = true;
+            self.remote_sequence = sequence;
+            return true;
+        }
+
+        if packet::sequence_greater(sequence, self.remote_sequence) {
+            let shift = sequence.wrapping_sub(self.remote_sequence) as u32;
+            self.ack_bits = match shift {
+                1..=31 => (self.ack_bits << shift) | (1 << (shift - 1)),
+                32 => 1 << 31,
+                _ => 0
+            };
+            self.remote_sequence = sequence;
+            return true;
+        }
+
+        let distance = self.remote_sequence.wrapping_sub(sequence) as u32;
+        if distance == 0 || distance > 32 {
+            return false;
+        }
+        let bit = 1 << (distance - 1);
+        let duplicate = self.ack_bits & bit != 0;
+        self.ack_bits |= bit;
+        !duplicate
+    }
+
+    fn receive_acks(&mut self, ack: u16, ack_bits: u32, now: Instant) {
+        let mut index = 0;
+        while index < self.sent.len() {
+            let distance = ack.wrapping_sub(self.sent[index].sequence) as u32;
+            let acked = distance == 0 || (distance <= 32 && ack_bits & (1 << (distance - 1)) != 0);
+            if !acked {
+                index += 1;
+                continue;
+            }
+
+            let sent = self.sent.remove(index).unwrap();
+            let sample = (now - sent.time).as_secs_f32();
+            self.rtt = Some(match self.rtt {
+                Some(rtt) => rtt + (sample - rtt) * RTT_SMOOTHING,
+                None => sample
+            });
+            if !sent.reliable.is_empty() {
+                self.reliable.retain(|message| !sent.reliable.contains(&(message.header.channel, message.header.id)));
+            }
+        }
+    }
+
+    // Datagrams for everything queued and the reliable messages due for a resend, or a single empty one when there's
+    // nothing to send but acks are owed or `keep_alive` has passed. Reliable messages go again after `resend_delay`,
+    // or one and a half round trips if that's longer.
+    pub fn write_packets(&mut self, protocol_id: u32, now: Instant, resend_delay: Duration, keep_alive: Duration) -> Vec<Vec<u8>> {
+        let resend_delay = resend_delay.max(Duration::from_secs_f32(self.rtt.unwrap_or(0.0) * 1.5));
+        let unreliable = std::mem::take(&mut self.unreliable);
+
+        let mut bodies: Vec<PacketBody> = vec![];
+        let due = self.reliable
+            .iter_mut()
+            .filter(|message| message.sent.is_none_or(|sent| now - sent >= resend_delay))
+            .map(|message| {
+                if message.sent.is_some() {
+                    self.stats.messages_resent += 1;
+                }
+                message.sent = Some(now);
+                (&*message, true)
+            });
+        for (message, reliable) in due.chain(unreliable.iter().map(|message| (message, false))) {
+            let full = bodies.last().is_none_or(|(body, _)| {
+                PAYLOAD_HEADER_SIZE + body.len() + MESSAGE_HEADER_SIZE + message.payload.len() > MAX_PACKET_SIZE
+            });
+            if full {
+                bodies.push((vec![], vec![]));
+            }
+            let (body, ids) = bodies.last_mut().unwrap();
+            packet::write_message(body, message.header, &message.payload);
+            if reliable {
+                ids.push((message.header.channel, message.header.id));
+            }
+        }
+
+        if bodies.is_empty() && (self.needs_ack || now - self.last_sent >= keep_alive) {
+            bodies.push((vec![], vec![]));
+        }
+
+        let mut datagrams = Vec::with_capacity(bodies.len());
+        for (body, reliable) in bodies {
+            let header = PayloadHeader {
+                sequence: self.local_sequence,
+                ack: self.remote_sequence,
+                ack_bits: self.ack_bits
+            };
+            let mut datagram = Vec::with_capacity(PAYLOAD_HEADER_SIZE + body.len());
+            packet::write_payload(&mut datagram, protocol_id, header, &body);
+
+            self.sent.push_back(SentPacket { sequence: self.local_sequence, time: now, reliable });
+            if self.sent.len() > SENT_PACKETS {
+                self.sent.pop_front();
+                self.stats.packets_lost += 1;
+            }
+            self.local_sequence = self.local_sequence.wrapping_add(1);
+            self.stats.packets_sent += 1;
+            self.stats.bytes_sent += datagram.len() as u64;
+            datagrams.push(datagram);
+        }
+
+        if !datagrams.is_empty() {
+            self.needs_ack = false;
+            self.last_sent = now;
+        }
+        datagrams
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::net::packet::PacketKind;
+
+    const PROTOCOL_ID: u32 = 1;
+    const RESEND_DELAY: Duration = Duration::from_millis(100);
+    const KEEP_ALIVE: Duration = Duration::from_secs(1);
+    const CHANNELS: [ChannelKind; 3] = [ChannelKind::Reliable, ChannelKind::Unreliable, ChannelKind::Sequenced];
+
+    fn pair(now: Instant) -> (Connection, Connection) {
+        let connection = |port| Connection::new(SocketAddr::from(([127, 0, 0, 1], port)), ConnectionState::Connected, &CHANNELS, now);
+        (connection(1), connection(2))
+    }
+
+    fn deliver(to: &mut Connection, datagram: &[u8], now: Instant) -> Vec<(usize, Vec<u8>)> {
+        let (kind, body) = packet::read_header(datagram, PROTOCOL_ID).unwrap();
+        assert_eq!(kind, PacketKind::Payload);
+        let (header, messages) = packet::read_payload_header(body).unwrap();
+        let mut delivered = vec![];
+        to.receive(header, messages, now, &mut delivered);
+        delivered
+    }
+
+    fn send(from: &mut Connection, to: &mut Connection, now: Instant) -> Vec<(usize, Vec<u8>)> {
+        from.write_packets(PROTOCOL_ID, now, RESEND_DELAY, KEEP_ALIVE)
+            .iter()
+            .flat_map(|datagram| deliver(to, datagram, now))
+            .collect()
+    }
+
+    #[test]
+    fn acks_the_last_33_sequences() {
+        let (mut connection, _) = pair(Instant::now());
+        assert!(connection.track_sequence(10));
+        assert!(connection.track_sequence(12));
+        assert_eq!((connection.remote_sequence, connection.ack_bits), (12, 0b10));
+        assert!(connection.track_sequence(11));
+        assert_eq!(connection.ack_bits, 0b11);
+        assert!(!connection.track_sequence(11));
+        assert!(!connection.track_sequence(12));
+
+        assert!(connection.track_sequence(44));
+        assert_eq!(connection.ack_bits, 1 << 31);
+        assert!(!connection.track_sequence(11));
+        assert!(connection.track_sequence(100));
+        assert_eq!(connection.ack_bits, 0);
+    }
+
+    #[test]
+    fn acks_across_the_sequence_wraparound() {
+        let (mut connection, _) = pair(Instant::now());
+        assert!(connection.track_sequence(65534));
+        assert!(connection.track_sequence(1));
+        assert_eq!((connection.remote_sequence, connection.ack_bits), (1, 0b100));
+        assert!(connection.track_sequence(65535));
+        assert!(connection.track_sequence(0));
+        assert_eq!(connection.ack_bits, 0b111);
+    }
+
+    #[test]
+    fn acked_messages_are_not_resent() {
+        let now = Instant::now();
+        let (mut client, mut server) = pair(now);
+        client.queue(0, b"reliable".to_vec());
+        client.queue(1, b"unreliable".to_vec());
+        assert_eq!(send(&mut client, &mut server, now), vec![(0, b"reliable".to_vec()), (1, b"unreliable".to_vec())]);
+        assert_eq!(client.unacked(), 1);
+
+        send(&mut server, &mut client, now);
+        assert_eq!(client.unacked(), 0);
+        // Only the ack owed for the server's packet goes out
+        let datagrams = client.write_packets(PROTOCOL_ID, now + RESEND_DELAY, RESEND_DELAY, KEEP_ALIVE);
+        assert_eq!(datagrams.iter().map(|datagram| datagram.len()).collect::<Vec<_>>(), vec![PAYLOAD_HEADER_SIZE]);
+        assert_eq!(client.stats().messages_resent, 0);
+    }
+
+    #[test]
+    fn lost_messages_are_resent() {
+        let now = Instant::now();
+        let (mut client, mut server) = pair(now);
+        client.queue(0, b"reliable".to_vec());
+        client.queue(1, b"unreliable".to_vec());
+        assert_eq!(client.write_packets(PROTOCOL_ID, now, RESEND_DELAY, KEEP_ALIVE).len(), 1);
+
+        // Not due yet, and nothing else to send
+        assert!(client.write_packets(PROTOCOL_ID, now + RESEND_DELAY / 2, RESEND_DELAY, KEEP_ALIVE).is_empty());
+        let later = now + RESEND_DELAY;
+        assert_eq!(send(&mut client, &mut server, later), vec![(0, b"reliable".to_vec())]);
+        assert_eq!(client.stats().messages_resent, 1);
+
+        // A duplicate that arrives late isn't delivered twice
+        client.reliable[0].sent = None;
+        assert!(send(&mut client, &mut server, later).is_empty());
+    }
+
+    #[test]
+    fn reliable_messages_arrive_in_order_across_the_id_wraparound() {
+        let now = Instant::now();
+        let (mut client, mut server) = pair(now);
+        client.channels[0].next_send_id = 65534;
+        server.channels[0].next_receive_id = 65534;
+
+        let datagrams: Vec<Vec<u8>> = (0..4u8).map(|index| {
+            client.queue(0, vec![index]);
+            client.write_packets(PROTOCOL_ID, now, RESEND_DELAY, KEEP_ALIVE).remove(0)
+        }).collect();
+        assert!(deliver(&mut server, &datagrams[2], now).is_empty());
+        assert!(deliver(&mut server, &datagrams[1], now).is_empty());
+        assert_eq!(deliver(&mut server, &datagrams[0], now), vec![(0, vec![0]), (0, vec![1]), (0, vec![2])]);
+        assert_eq!(deliver(&mut server, &datagrams[3], now), vec![(0, vec![3])]);
+    }
+
+    #[test]
+    fn sequenced_messages_drop_older_ones() {
+        let now = Instant::now();
+        let (mut client, mut server) = pair(now);
+        let datagrams: Vec<Vec<u8>> = (0..2u8).map(|index| {
+            client.queue(2, vec![index]);
+            client.write_packets(PROTOCOL_ID, now, RESEND_DELAY, KEEP_ALIVE).remove(0)
+        }).collect();
+        assert_eq!(deliver(&mut server, &datagrams[1], now), vec![(2, vec![1])]);
+        assert!(deliver(&mut server, &datagrams[0], now).is_empty());
+    }
+}
\ No newline at end of file
@@ -0,0 +1,382 @@
+pub mod connection;
+pub mod packet;
+pub mod replication;
+
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::{Duration, Instant};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use connection::{Connection, ConnectionState, ConnectionStats};
+use packet::{PacketKind, MAX_MESSAGE_SIZE, MAX_PACKET_SIZE};
+
+// Channels of `NetConfig::default`
+pub const CHANNEL_UNRELIABLE: usize = 0;
+pub const CHANNEL_SEQUENCED: usize = 1;
+pub const CHANNEL_RELIABLE: usize = 2;
+// Snapshots and their acks, see `replication`
+pub const CHANNEL_REPLICATION: usize = 3;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChannelKind {
+    // Sent once, may arrive out of order, twice or not at all
+    Unreliable,
+    // Like `Unreliable`, but messages older than one already delivered are dropped. For state that's sent over and
+    // over, like transforms.
+    Sequenced,
+    // Resent until acked and delivered in the order they were sent
+    Reliable,
+}
+
+#[derive(Clone, Debug)]
+pub struct NetConfig {
+    // Datagrams of any other protocol or version are ignored
+    pub protocol_id: u32,
+    // At most 256, both sides must use the same ones
+    pub channels: Vec<ChannelKind>,
+    // Connections a server accepts, clients asking when it's full are denied
+    pub max_clients: usize,
+    // Without anything received for this long a connection is dropped
+    pub timeout: Duration,
+    // An empty packet goes out when nothing else was sent for this long, clients ask to connect again as often
+    pub keep_alive: Duration,
+    // Shortest wait before a reliable message is sent again, it's longer on connections with a slower round trip
+    pub resend_delay: Duration,
+}
+
+impl Default for NetConfig {
+    fn default() -> Self {
+        Self {
+            protocol_id: u32::from_le_bytes(*b"RVR1"),
+            channels: vec![ChannelKind::Unreliable, ChannelKind::Sequenced, ChannelKind::Reliable, ChannelKind::Unreliable],
+            max_clients: 16,
+            timeout: Duration::from_secs(10),
+            keep_alive: Duration::from_millis(250),
+            resend_delay: Duration::from_millis(100)
+        }
+    }
+}
+
+// The other end of a connection, a client on the server and the server on a client
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct PeerId(u32);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DisconnectReason {
+    // The peer disconnected
+    Closed,
+    TimedOut,
+    // The server was full
+    Denied,
+}
+
+#[derive(Clone, Debug)]
+pub struct Message {
+    pub peer: PeerId,
+    pub channel: usize,
+    pub payload: Vec<u8>,
+}
+
+impl Message {
+    // A message sent with `Endpoint::send_message`
+    pub fn decode<T: DeserializeOwned>(&self) -> io::Result<T> {
+        bincode::deserialize(&self.payload).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum NetEvent {
+    Connected(PeerId),
+    // Not sent for peers disconnected with `Endpoint::disconnect`
+    Disconnected(PeerId, DisconnectReason),
+    Message(Message),
+}
+
+// How the engine takes part in a networked session, see `--host` and `--connect`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NetworkRole {
+    // A server on this port of every interface
+    Host(u16),
+    // A client of the server at this address, like "192.168.0.2:7777"
+    Connect(String),
+}
+
+impl NetworkRole {
+    pub fn open(&self, config: NetConfig) -> io::Result<Endpoint> {
+        match self {
+            NetworkRole::Host(port) => Endpoint::host(SocketAddr::from(([0, 0, 0, 0], *port)), config),
+            NetworkRole::Connect(addr) => Endpoint::connect(addr.as_str(), config).map(|(endpoint, _)| endpoint)
+        }
+    }
+}
+
+struct Peer {
+    id: PeerId,
+    connection: Connection,
+}
+
+// A UDP socket with its connections, either a server accepting clients or a client with a single connection to a
+// server. Nothing happens in the background: `update` receives, resends and sends, call it once per frame or tick.
+pub struct Endpoint {
+    socket: UdpSocket,
+    config: NetConfig,
+    // Servers accept new connections, clients only talk to the server
+    accepting: bool,
+    peers: Vec<Peer>,
+    next_peer: u32,
+    events: Vec<NetEvent>,
+}
+
+impl Endpoint {
+    // A server listening on `addr`, e.g. "0.0.0.0:7777"
+    pub fn host(addr: impl ToSocketAddrs, config: NetConfig) -> io::Result<Self> {
+        Self::bind(addr, config, true)
+    }
+
+    // A client connecting to the server at `addr` from any free port. `NetEvent::Connected` tells when it's accepted,
+    // messages sent before go out then.
+    pub fn connect(addr: impl ToSocketAddrs, config: NetConfig) -> io::Result<(Self, PeerId)> {
+        let server = addr.to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "No address to connect to"))?;
+        let local: SocketAddr = match server {
+            SocketAddr::V4(_) => ([0, 0, 0, 0], 0).into(),
+            SocketAddr::V6(_) => ([0u16; 8], 0).into()
+        };
+
+        let mut endpoint = Self::bind(local, config, false)?;
+        let id = endpoint.add_peer(server, ConnectionState::Connecting);
+        // Far enough back for the first request to go out with the first update
+        let now = Instant::now();
+        endpoint.peers[0].connection.last_sent = now.checked_sub(endpoint.config.keep_alive).unwrap_or(now);
+        Ok((endpoint, id))
+    }
+
+    fn bind(addr: impl ToSocketAddrs, config: NetConfig, accepting: bool) -> io::Result<Self> {
+        assert!(config.channels.len() <= 256, "At most 256 channels");
+        let socket = UdpSocket::bind(addr)?;
+        socket.set_nonblocking(true)?;
+
+        Ok(Self {
+            socket,
+            config,
+            accepting,
+            peers: vec![],
+            next_peer: 0,
+            events: vec![]
+        })
+    }
+
+    fn add_peer(&mut self, addr: SocketAddr, state: ConnectionState) -> PeerId {
+        let id = PeerId(self.next_peer);
+        self.next_peer += 1;
+        self.peers.push(Peer { id, connection: Connection::new(addr, state, &self.config.channels, Instant::now()) });
+        id
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    pub fn config(&self) -> &NetConfig {
+        &self.config
+    }
+
+    // Peers that finished connecting
+    pub fn peers(&self) -> impl Iterator<Item = PeerId> + '_ {
+        self.peers.iter().filter(|peer| peer.connection.state == ConnectionState::Connected).map(|peer| peer.id)
+    }
+
+    pub fn is_connected(&self, peer: PeerId) -> bool {
+        self.peer(peer).is_some_and(|peer| peer.connection.state == ConnectionState::Connected)
+    }
+
+    pub fn peer_addr(&self, peer: PeerId) -> Option<SocketAddr> {
+        self.peer(peer).map(|peer| peer.connection.addr)
+    }
+
+    pub fn stats(&self, peer: PeerId) -> Option<ConnectionStats> {
+        self.peer(peer).map(|peer| peer.connection.stats())
+    }
+
+    fn peer(&self, id: PeerId) -> Option<&Peer> {
+        self.peers.iter().find(|peer| peer.id == id)
+    }
+
+    // Queued for the next `update`, at most `MAX_MESSAGE_SIZE` bytes
+    pub fn send(&mut self, peer: PeerId, channel: usize, payload: Vec<u8>) -> io::Result<()> {
+        if channel >= self.config.channels.len() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("There is no channel {}", channel)));
+        }
+        if payload.len() > MAX_MESSAGE_SIZE {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                format!("{} byte message is larger than the {} bytes that fit into a packet", payload.len(), MAX_MESSAGE_SIZE)));
+        }
+
+        let peer = self.peers.iter_mut()
+            .find(|candidate| candidate.id == peer)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotConnected, "Unknown peer"))?;
+        peer.connection.queue(channel, payload);
+        Ok(())
+    }
+
+    // `send` with the message serialized by bincode, read it with `Message::decode`
+    pub fn send_message<T: Serialize>(&mut self, peer: PeerId, channel: usize, message: &T) -> io::Result<()> {
+        let payload = bincode::serialize(message).map_err(|error| io::Error::new(io::ErrorKind::InvalidInput, error))?;
+        self.send(peer, channel, payload)
+    }
+
+    // To every connected peer
+    pub fn broadcast(&mut self, channel: usize, payload: &[u8]) -> io::Result<()> {
+        let peers: Vec<PeerId> = self.peers().collect();
+        for peer in peers {
+            self.send(peer, channel, payload.to_vec())?;
+        }
+        Ok(())
+    }
+
+    pub fn broadcast_message<T: Serialize>(&mut self, channel: usize, message: &T) -> io::Result<()> {
+        let payload = bincode::serialize(message).map_err(|error| io::Error::new(io::ErrorKind::InvalidInput, error))?;
+        self.broadcast(channel, &payload)
+    }
+
+    // Tells the peer right away and forgets it, reliable messages it hasn't acked are lost
+    pub fn disconnect(&mut self, peer: PeerId) {
+        if let Some(index) = self.peers.iter().position(|candidate| candidate.id == peer) {
+            let peer = self.peers.remove(index);
+            self.send_control(peer.connection.addr, PacketKind::Disconnect);
+        }
+    }
+
+    pub fn disconnect_all(&mut self) {
+        for peer in std::mem::take(&mut self.peers) {
+            self.send_control(peer.connection.addr, PacketKind::Disconnect);
+        }
+    }
+
+    fn send_control(&self, addr: SocketAddr, kind: PacketKind) {
+        let mut datagram = vec![];
+        packet::write_header(&mut datagram, self.config.protocol_id, kind);
+        self.send_datagram(addr, &datagram);
+    }
+
+    // A full send buffer drops the datagram like the network would, the reliable channels take care of it
+    fn send_datagram(&self, addr: SocketAddr, datagram: &[u8]) {
+        if let Err(error) = self.socket.send_to(datagram, addr) {
+            if error.kind() != io::ErrorKind::WouldBlock {
+                tracing::debug!("Failed to send to {}: {}", addr, error);
+            }
+        }
+    }
+
+    // Receives everything waiting on the socket, drops connections that timed out and sends what was queued. Returns
+    // what happened since the last update.
+    pub fn update(&mut self, now: Instant) -> Vec<NetEvent> {
+        crate::profile_scope!("Network");
+        let mut buffer = [0u8; MAX_PACKET_SIZE];
+        loop {
+            match self.socket.recv_from(&mut buffer) {
+                Ok((length, addr)) => self.receive(&buffer[..length], addr, now),
+                Err(error) if error.kind() == io::ErrorKind::WouldBlock => break,
+                // ICMP port unreachable from a peer that's gone shows up here on some platforms, the timeout handles it
+                Err(error) if error.kind() == io::ErrorKind::ConnectionReset => continue,
+                Err(error) => {
+                    tracing::warn!("Failed to receive: {}", error);
+                    break;
+                }
+            }
+        }
+
+        let timeout = self.config.timeout;
+        let events = &mut self.events;
+        self.peers.retain(|peer| {
+            let timed_out = now - peer.connection.last_received > timeout;
+            if timed_out {
+                events.push(NetEvent::Disconnected(peer.id, DisconnectReason::TimedOut));
+            }
+            !timed_out
+        });
+
+        let mut datagrams = vec![];
+        for peer in &mut self.peers {
+            let connection = &mut peer.connection;
+            match connection.state {
+                ConnectionState::Connecting => if now - connection.last_sent >= self.config.keep_alive {
+                    connection.last_sent = now;
+                    let mut datagram = vec![];
+                    packet::write_header(&mut datagram, self.config.protocol_id, PacketKind::Connect);
+                    datagrams.push((connection.addr, datagram));
+                },
+                ConnectionState::Connected => {
+                    let written = connection.write_packets(self.config.protocol_id, now, self.config.resend_delay, self.config.keep_alive);
+                    datagrams.extend(written.into_iter().map(|datagram| (connection.addr, datagram)));
+                }
+            }
+        }
+        for (addr, datagram) in datagrams {
+            self.send_datagram(addr, &datagram);
+        }
+
+        std::mem::take(&mut self.events)
+    }
+
+    fn receive(&mut self, datagram: &[u8], addr: SocketAddr, now: Instant) {
+        let (kind, body) = match packet::read_header(datagram, self.config.protocol_id) {
+            Some(header) => header,
+            None => return
+        };
+        let index = self.peers.iter().position(|peer| peer.connection.addr == addr);
+
+        match (kind, index) {
+            (PacketKind::Connect, None) if self.accepting => {
+                match self.peers.len() < self.config.max_clients {
+                    true => {
+                        let id = self.add_peer(addr, ConnectionState::Connected);
+                        self.send_control(addr, PacketKind::Accept);
+                        self.events.push(NetEvent::Connected(id));
+                    }
+                    false => self.send_control(addr, PacketKind::Deny)
+                }
+            }
+            // The accept got lost
+            (PacketKind::Connect, Some(_)) if self.accepting => self.send_control(addr, PacketKind::Accept),
+            (PacketKind::Accept, Some(index)) => {
+                let peer = &mut self.peers[index];
+                if peer.connection.state == ConnectionState::Connecting {
+                    peer.connection.state = ConnectionState::Connected;
+                    peer.connection.last_received = now;
+                    self.events.push(NetEvent::Connected(peer.id));
+                }
+            }
+            (PacketKind::Deny, Some(index)) if self.peers[index].connection.state == ConnectionState::Connecting => {
+                let peer = self.peers.remove(index);
+                self.events.push(NetEvent::Disconnected(peer.id, DisconnectReason::Denied));
+            }
+            (PacketKind::Disconnect, Some(index)) => {
+                let peer = self.peers.remove(index);
+                self.events.push(NetEvent::Disconnected(peer.id, DisconnectReason::Closed));
+            }
+            (PacketKind::Payload, Some(index)) if self.peers[index].connection.state == ConnectionState::Connected => {
+                let (header, messages) = match packet::read_payload_header(body) {
+                    Some(header) => header,
+                    None => return
+                };
+                let peer = &mut self.peers[index];
+                let mut delivered = vec![];
+                peer.connection.receive(header, messages, now, &mut delivered);
+                let id = peer.id;
+                self.events.extend(delivered.into_iter().map(|(channel, payload)| NetEvent::Message(Message { peer: id, channel, payload })));
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Drop for Endpoint {
+    // So peers don't wait for the timeout
+    fn drop(&mut self) {
+        self.disconnect_all();
+    }
+}
\ No newline at end of file
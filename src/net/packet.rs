@@ -0,0 +1,177 @@
+// Datagram layout, little endian:
+//   u32 protocol id, u8 kind
+//   Payload packets continue with u16 sequence, u16 ack, u32 ack bits, then messages of
+//   u8 channel, u16 message id, u16 length and the bytes
+// Every other kind ends after the kind.
+
+// Small enough to never be fragmented by IPv4 or IPv6 on the way
+pub const MAX_PACKET_SIZE: usize = 1200;
+const HEADER_SIZE: usize = 5;
+pub const PAYLOAD_HEADER_SIZE: usize = HEADER_SIZE + 8;
+pub const MESSAGE_HEADER_SIZE: usize = 5;
+// Largest message that fits into a packet on its own
+pub const MAX_MESSAGE_SIZE: usize = MAX_PACKET_SIZE - PAYLOAD_HEADER_SIZE - MESSAGE_HEADER_SIZE;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PacketKind {
+    // Client asking to join, sent until it's accepted or denied
+    Connect,
+    Accept,
+    // The server is full
+    Deny,
+    Disconnect,
+    // Messages and acks, an empty one keeps the connection alive
+    Payload,
+}
+
+impl PacketKind {
+    fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(PacketKind::Connect),
+            1 => Some(PacketKind::Accept),
+            2 => Some(PacketKind::Deny),
+            3 => Some(PacketKind::Disconnect),
+            4 => Some(PacketKind::Payload),
+            _ => None
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PayloadHeader {
+    pub sequence: u16,
+    // Latest sequence received from the other side, bit n of `ack_bits` acks `ack - 1 - n`
+    pub ack: u16,
+    pub ack_bits: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MessageHeader {
+    pub channel: u8,
+    pub id: u16,
+}
+
+// Whether sequence `a` is newer than `b`, with wrapping
+pub fn sequence_greater(a: u16, b: u16) -> bool {
+    a != b && a.wrapping_sub(b) < 0x8000
+}
+
+pub fn write_header(buffer: &mut Vec<u8>, protocol_id: u32, kind: PacketKind) {
+    buffer.clear();
+    buffer.extend_from_slice(&protocol_id.to_le_bytes());
+    buffer.push(kind as u8);
+}
+
+// `messages` as written by `write_message`
+pub fn write_payload(buffer: &mut Vec<u8>, protocol_id: u32, header: PayloadHeader, messages: &[u8]) {
+    write_header(buffer, protocol_id, PacketKind::Payload);
+    buffer.extend_from_slice(&header.sequence.to_le_bytes());
+    buffer.extend_from_slice(&header.ack.to_le_bytes());
+    buffer.extend_from_slice(&header.ack_bits.to_le_bytes());
+    buffer.extend_from_slice(messages);
+}
+
+pub fn write_message(buffer: &mut Vec<u8>, header: MessageHeader, payload: &[u8]) {
+    buffer.push(header.channel);
+    buffer.extend_from_slice(&header.id.to_le_bytes());
+    buffer.extend_from_slice(&(payload.len() as u16).to_le_bytes());
+    buffer.extend_from_slice(payload);
+}
+
+// `None` for datagrams of another protocol or that are cut short
+pub fn read_header(datagram: &[u8], protocol_id: u32) -> Option<(PacketKind, &[u8])> {
+    if datagram.len() < HEADER_SIZE || u32::from_le_bytes(datagram[0..4].try_into().unwrap()) != protocol_id {
+        return None;
+    }
+    Some((PacketKind::from_u8(datagram[4])?, &datagram[HEADER_SIZE..]))
+}
+
+pub fn read_payload_header(body: &[u8]) -> Option<(PayloadHeader, &[u8])> {
+    if body.len() < PAYLOAD_HEADER_SIZE - HEADER_SIZE {
+        return None;
+    }
+    let header = PayloadHeader {
+        sequence: u16::from_le_bytes(body[0..2].try_into().unwrap()),
+        ack: u16::from_le_bytes(body[2..4].try_into().unwrap()),
+        ack_bits: u32::from_le_bytes(body[4..8].try_into().unwrap())
+    };
+    Some((header, &body[8..]))
+}
+
+// The messages of a payload packet, stops at the first one that's cut short
+pub fn read_messages(mut body: &[u8]) -> impl Iterator<Item = (MessageHeader, &[u8])> {
+    std::iter::from_fn(move || {
+        if body.len() < MESSAGE_HEADER_SIZE {
+            return None;
+        }
+        let header = MessageHeader {
+            channel: body[0],
+            id: u16::from_le_bytes(body[1..3].try_into().unwrap())
+        };
+        let length = u16::from_le_bytes(body[3..5].try_into().unwrap()) as usize;
+        let payload = body.get(MESSAGE_HEADER_SIZE..MESSAGE_HEADER_SIZE + length)?;
+        body = &body[MESSAGE_HEADER_SIZE + length..];
+        Some((header, payload))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROTOCOL_ID: u32 = 0x5245_5645;
+
+    #[test]
+    fn payload_round_trip() {
+        let header = PayloadHeader { sequence: 65535, ack: 7, ack_bits: 0x8000_0001 };
+        let mut messages = vec![];
+        write_message(&mut messages, MessageHeader { channel: 1, id: 300 }, b"hello");
+        write_message(&mut messages, MessageHeader { channel: 0, id: 0 }, b"");
+        let mut datagram = vec![];
+        write_payload(&mut datagram, PROTOCOL_ID, header, &messages);
+        assert_eq!(datagram.len(), PAYLOAD_HEADER_SIZE + 2 * MESSAGE_HEADER_SIZE + 5);
+
+        let (kind, body) = read_header(&datagram, PROTOCOL_ID).unwrap();
+        assert_eq!(kind, PacketKind::Payload);
+        let (read, body) = read_payload_header(body).unwrap();
+        assert_eq!(read, header);
+        let read: Vec<_> = read_messages(body).collect();
+        assert_eq!(read, vec![
+            (MessageHeader { channel: 1, id: 300 }, &b"hello"[..]),
+            (MessageHeader { channel: 0, id: 0 }, &b""[..])
+        ]);
+    }
+
+    #[test]
+    fn rejects_other_protocols_and_short_datagrams() {
+        let mut datagram = vec![];
+        write_header(&mut datagram, PROTOCOL_ID, PacketKind::Accept);
+        assert_eq!(read_header(&datagram, PROTOCOL_ID).map(|(kind, _)| kind), Some(PacketKind::Accept));
+        assert!(read_header(&datagram, PROTOCOL_ID + 1).is_none());
+        assert!(read_header(&datagram[..HEADER_SIZE - 1], PROTOCOL_ID).is_none());
+        datagram[4] = 5;
+        assert!(read_header(&datagram, PROTOCOL_ID).is_none());
+        assert!(read_payload_header(&[0; 7]).is_none());
+    }
+
+    #[test]
+    fn stops_at_a_cut_short_message() {
+        let mut messages = vec![];
+        write_message(&mut messages, MessageHeader { channel: 0, id: 1 }, b"first");
+        write_message(&mut messages, MessageHeader { channel: 0, id: 2 }, b"second");
+        messages.pop();
+        let read: Vec<_> = read_messages(&messages).map(|(header, _)| header.id).collect();
+        assert_eq!(read, vec![1]);
+    }
+
+    #[test]
+    fn sequences_wrap_around() {
+        assert!(sequence_greater(1, 0));
+        assert!(!sequence_greater(0, 1));
+        assert!(!sequence_greater(5, 5));
+        assert!(sequence_greater(0, 65535));
+        assert!(sequence_greater(10, 65530));
+        assert!(!sequence_greater(65530, 10));
+        assert!(!sequence_greater(0x8000, 0));
+    }
+}
\ No newline at end of file
@@ -0,0 +1,777 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use uv::Slerp;
+
+use super::packet::MAX_MESSAGE_SIZE;
+use super::{Endpoint, NetEvent, PeerId, CHANNEL_REPLICATION};
+use crate::assets::AssetManager;
+use crate::vulkan::game_object::{self, GameObject, Transform3DComponent};
+use crate::vulkan::material::Material;
+use crate::vulkan::renderer::VulkanRenderer;
+
+// Game object state kept in sync from a server to its clients. The server sends every client the components that
+// changed since the last snapshot that client acked, so nothing is resent once it arrived and a lost snapshot is made
+// up for by the next one. Clients only hear about the objects near the position they report, and play transforms
+// back a little in the past, interpolated between the snapshots around that time.
+
+// Snapshots kept per client until they're acked, older ones count as lost
+const SENT_SNAPSHOTS: usize = 64;
+// Room for the snapshot's own fields next to its updates
+const SNAPSHOT_OVERHEAD: usize = 32;
+// Received transforms kept per object for interpolation
+const BUFFERED_TRANSFORMS: usize = 16;
+// Weight of a later sample in the estimate of the server's clock
+const CLOCK_SMOOTHING: f64 = 0.05;
+
+// Identifies a replicated object on the server and every client, game object ids differ between them
+pub type NetObjectId = u32;
+
+// An update for a snapshot, with its object and the full state it leaves it in, `None` for despawns
+type PlannedUpdate = (ObjectUpdate, NetObjectId, Option<ObjectState>);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReplicatedComponents {
+    pub transform: bool,
+    // Material, color, opacity and roughness
+    pub material: bool,
+}
+
+impl ReplicatedComponents {
+    pub const TRANSFORM: Self = Self { transform: true, material: false };
+    pub const ALL: Self = Self { transform: true, material: true };
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MaterialState {
+    pub material: Material,
+    pub color: uv::Vec3,
+    pub opacity: f32,
+    pub roughness: f32,
+}
+
+// The replicated components of an object. In updates only the ones that changed are set.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ObjectState {
+    // Relative to the parent, parents aren't replicated
+    pub transform: Option<Transform3DComponent>,
+    pub material: Option<MaterialState>,
+}
+
+impl ObjectState {
+    fn capture(game_object: &GameObject, components: ReplicatedComponents) -> Self {
+        Self {
+            transform: components.transform.then_some(game_object.transform3d),
+            material: components.material.then_some(MaterialState {
+                material: game_object.material,
+                color: game_object.color,
+                opacity: game_object.opacity,
+                roughness: game_object.roughness
+            })
+        }
+    }
+
+    // Components that differ from `baseline`
+    fn delta(&self, baseline: &ObjectState) -> ObjectState {
+        ObjectState {
+            transform: self.transform.filter(|transform| baseline.transform != Some(*transform)),
+            material: self.material.filter(|material| baseline.material != Some(*material))
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.transform.is_none() && self.material.is_none()
+    }
+}
+
+// What a client needs to create an object it hasn't seen before
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SpawnInfo {
+    pub name: String,
+    // Mesh file the server's object was instantiated from, `None` for meshes built in code
+    pub mesh: Option<PathBuf>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+enum ObjectUpdate {
+    Spawn { id: NetObjectId, info: SpawnInfo, state: ObjectState },
+    Update { id: NetObjectId, delta: ObjectState },
+    // Removed on the server or out of the client's interest
+    Despawn { id: NetObjectId },
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+enum ReplicationMessage {
+    // Server to client, a snapshot too large for a message is split into several with their own sequence
+    Snapshot { sequence: u32, time: f64, updates: Vec<ObjectUpdate> },
+    // Client to server
+    Ack { sequence: u32 },
+    View { position: uv::Vec3 },
+}
+
+#[derive(Clone, Debug)]
+pub struct ReplicationConfig {
+    // Time between snapshots
+    pub send_interval: Duration,
+    // Clients only hear about objects this close to the position they report, `None` sends everything
+    pub interest_radius: Option<f32>,
+    // How far in the past clients play transforms back, a few send intervals hide a lost snapshot or two
+    pub interpolation_delay: Duration,
+}
+
+impl Default for ReplicationConfig {
+    fn default() -> Self {
+        Self {
+            send_interval: Duration::from_millis(33),
+            interest_radius: None,
+            interpolation_delay: Duration::from_millis(100)
+        }
+    }
+}
+
+struct Replicated {
+    // Game object id on the server
+    object: usize,
+    components: ReplicatedComponents,
+    // Sent regardless of the interest radius
+    always_relevant: bool,
+}
+
+struct SentSnapshot {
+    sequence: u32,
+    // Full state of every object it updated, `None` for despawns
+    states: Vec<(NetObjectId, Option<ObjectState>)>,
+}
+
+#[derive(Default)]
+struct ClientView {
+    position: Option<uv::Vec3>,
+    // State the client is known to have and the snapshot it came with
+    acked: HashMap<NetObjectId, (u32, ObjectState)>,
+    // Objects the client may have and the last snapshot that spawned them, until a despawn is acked
+    spawned: HashMap<NetObjectId, u32>,
+    // Waiting for their ack
+    sent: VecDeque<SentSnapshot>,
+}
+
+impl ClientView {
+    fn ack(&mut self, sequence: u32) {
+        let index = match self.sent.iter().position(|sent| sent.sequence == sequence) {
+            Some(index) => index,
+            None => return
+        };
+        let sent = self.sent.remove(index).unwrap();
+
+        for (id, state) in sent.states {
+            match state {
+                // Acks can arrive out of order, an older one mustn't replace what a newer one acked
+                Some(state) => if self.acked.get(&id).is_none_or(|(acked, _)| sequence > *acked) {
+                    self.acked.insert(id, (sequence, state));
+                },
+                None => {
+                    if self.acked.get(&id).is_some_and(|(acked, _)| *acked < sequence) {
+                        self.acked.remove(&id);
+                    }
+                    if self.spawned.get(&id).is_some_and(|spawned| *spawned < sequence) {
+                        self.spawned.remove(&id);
+                    }
+                }
+            }
+        }
+    }
+
+    // What brings the client from the state it acked to `current`: spawns, deltas and despawns, each with the object
+    // and the full state it leaves it in, `None` for despawns. `current` holds each object's world position, state and
+    // whether it's always relevant, `spawn_info` describes the ones the client hasn't seen.
+    fn updates(&self, current: &[(NetObjectId, uv::Vec3, ObjectState, bool)], interest_radius: Option<f32>,
+        spawn_info: impl Fn(NetObjectId) -> SpawnInfo) -> Vec<PlannedUpdate> {
+        let mut updates = vec![];
+        let mut relevant = HashSet::new();
+        for &(id, position, state, always_relevant) in current {
+            let in_range = match (interest_radius, self.position) {
+                (Some(radius), Some(view)) => (position - view).mag_sq() <= radius * radius,
+                _ => true
+            };
+            if !always_relevant && !in_range {
+                continue;
+            }
+            relevant.insert(id);
+
+            match self.acked.get(&id) {
+                Some((_, acked)) => {
+                    let delta = state.delta(acked);
+                    if !delta.is_empty() {
+                        updates.push((ObjectUpdate::Update { id, delta }, id, Some(state)));
+                    }
+                }
+                None => updates.push((ObjectUpdate::Spawn { id, info: spawn_info(id), state }, id, Some(state)))
+            }
+        }
+        let despawned = self.spawned.keys().filter(|id| !relevant.contains(id)).copied().collect::<Vec<_>>();
+        updates.extend(despawned.into_iter().map(|id| (ObjectUpdate::Despawn { id }, id, None)));
+        updates
+    }
+
+    // Remembers a snapshot that went out until it's acked
+    fn sent(&mut self, sequence: u32, updates: &[ObjectUpdate], states: Vec<(NetObjectId, Option<ObjectState>)>) {
+        for update in updates {
+            if let ObjectUpdate::Spawn { id, .. } = update {
+                self.spawned.insert(*id, sequence);
+            }
+        }
+        self.sent.push_back(SentSnapshot { sequence, states });
+        if self.sent.len() > SENT_SNAPSHOTS {
+            self.sent.pop_front();
+        }
+    }
+}
+
+pub struct ReplicationServer {
+    pub config: ReplicationConfig,
+    objects: HashMap<NetObjectId, Replicated>,
+    next_id: NetObjectId,
+    clients: HashMap<PeerId, ClientView>,
+    sequence: u32,
+    start: Instant,
+    last_sent: Option<Instant>,
+}
+
+impl ReplicationServer {
+    pub fn new(config: ReplicationConfig) -> Self {
+        Self {
+            config,
+            objects: HashMap::new(),
+            next_id: 0,
+            clients: HashMap::new(),
+            sequence: 0,
+            start: Instant::now(),
+            last_sent: None
+        }
+    }
+
+    // Starts sending the game object's components to the clients, which spawn it from its mesh file
+    pub fn replicate(&mut self, object: usize, components: ReplicatedComponents) -> NetObjectId {
+        while self.objects.contains_key(&self.next_id) {
+            self.next_id += 1;
+        }
+        let id = self.next_id;
+        self.replicate_as(id, object, components);
+        id
+    }
+
+    // Replicates under an id agreed on beforehand, for objects that clients create themselves and `bind`, like the
+    // ones of a level both sides load
+    pub fn replicate_as(&mut self, id: NetObjectId, object: usize, components: ReplicatedComponents) {
+        self.objects.insert(id, Replicated { object, components, always_relevant: false });
+    }
+
+    pub fn set_always_relevant(&mut self, id: NetObjectId, always_relevant: bool) {
+        if let Some(replicated) = self.objects.get_mut(&id) {
+            replicated.always_relevant = always_relevant;
+        }
+    }
+
+    // Clients despawn the object with the next snapshot. Objects removed from the renderer are despawned anyway.
+    pub fn stop_replicating(&mut self, id: NetObjectId) {
+        self.objects.remove(&id);
+    }
+
+    // Takes the events of `Endpoint::update` and returns false for the ones left to the application, which includes
+    // connects and disconnects
+    pub fn receive(&mut self, event: &NetEvent) -> bool {
+        match event {
+            NetEvent::Connected(peer) => {
+                self.clients.insert(*peer, ClientView::default());
+                false
+            }
+            NetEvent::Disconnected(peer, _) => {
+                self.clients.remove(peer);
+                false
+            }
+            NetEvent::Message(message) if message.channel == CHANNEL_REPLICATION => {
+                let client = match self.clients.get_mut(&message.peer) {
+                    Some(client) => client,
+                    None => return true
+                };
+                match message.decode() {
+                    Ok(ReplicationMessage::Ack { sequence }) => client.ack(sequence),
+                    Ok(ReplicationMessage::View { position }) => client.position = Some(position),
+                    Ok(ReplicationMessage::Snapshot { .. }) => {}
+                    Err(error) => tracing::debug!("Invalid replication message from {:?}: {}", message.peer, error)
+                }
+                true
+            }
+            NetEvent::Message(_) => false
+        }
+    }
+
+    // Queues a snapshot for every client once `send_interval` has passed, `Endpoint::update` sends them. `assets` knows
+    // the mesh files clients spawn objects from.
+    pub fn update(&mut self, endpoint: &mut Endpoint, game_objects: &[GameObject], assets: &AssetManager, now: Instant) {
+        crate::profile_scope!("Replication");
+        if self.last_sent.is_some_and(|last_sent| now - last_sent < self.config.send_interval) {
+            return;
+        }
+        self.last_sent = Some(now);
+        let time = (now - self.start).as_secs_f64();
+
+        // Interest is decided by where objects are in the world, not relative to their parent
+        let world = game_object::world_matrices(game_objects);
+        let indices: HashMap<usize, usize> = game_objects.iter().enumerate().map(|(index, game_object)| (game_object.get_id(), index)).collect();
+        let current: Vec<(NetObjectId, uv::Vec3, ObjectState, bool)> = self.objects
+            .iter()
+            .filter_map(|(&id, replicated)| {
+                let index = *indices.get(&replicated.object)?;
+                let state = ObjectState::capture(&game_objects[index], replicated.components);
+                Some((id, world[index].cols[3].xyz(), state, replicated.always_relevant))
+            })
+            .collect();
+        let spawn_info = |id: NetObjectId| {
+            let game_object = &game_objects[indices[&self.objects[&id].object]];
+            SpawnInfo {
+                name: game_object.name.clone(),
+                mesh: assets.mesh_file(game_object.get_id()).map(PathBuf::from)
+            }
+        };
+
+        for (&peer, client) in &mut self.clients {
+            let updates = client.updates(&current, self.config.interest_radius, spawn_info);
+
+            // Split into messages that fit into a packet
+            let mut message = vec![];
+            let mut states = vec![];
+            let mut size = SNAPSHOT_OVERHEAD;
+            for (update, id, state) in updates {
+                let update_size = bincode::serialized_size(&update).unwrap_or_default() as usize;
+                if !message.is_empty() && size + update_size > MAX_MESSAGE_SIZE {
+                    send_snapshot(endpoint, peer, client, &mut self.sequence, time, std::mem::take(&mut message), std::mem::take(&mut states));
+                    size = SNAPSHOT_OVERHEAD;
+                }
+                size += update_size;
+                message.push(update);
+                states.push((id, state));
+            }
+            if !message.is_empty() {
+                send_snapshot(endpoint, peer, client, &mut self.sequence, time, message, states);
+            }
+        }
+    }
+}
+
+fn send_snapshot(endpoint: &mut Endpoint, peer: PeerId, client: &mut ClientView, sequence: &mut u32, time: f64,
+    updates: Vec<ObjectUpdate>, states: Vec<(NetObjectId, Option<ObjectState>)>) {
+    *sequence += 1;
+    // One that fails to go out is as good as lost, the next ones make up for it
+    client.sent(*sequence, &updates, states);
+    let message = ReplicationMessage::Snapshot { sequence: *sequence, time, updates };
+    if let Err(error) = endpoint.send_message(peer, CHANNEL_REPLICATION, &message) {
+        tracing::warn!("Failed to send a snapshot to {:?}: {}", peer, error);
+    }
+}
+
+struct RemoteObject {
+    // Game object id on this client, `None` until it's spawned or bound
+    local: Option<usize>,
+    // Created by the application through `bind`, left alone on despawn
+    bound: bool,
+    // By server time
+    transforms: VecDeque<(f64, Transform3DComponent)>,
+    material: Option<MaterialState>,
+    material_sequence: u32,
+    material_changed: bool,
+}
+
+impl RemoteObject {
+    fn new(local: Option<usize>) -> Self {
+        Self {
+            local,
+            bound: local.is_some(),
+            transforms: VecDeque::new(),
+            material: None,
+            material_sequence: 0,
+            material_changed: false
+        }
+    }
+
+    // `send_interval` in seconds
+    fn apply(&mut self, state: ObjectState, sequence: u32, time: f64, send_interval: f64) {
+        // Unchanged transforms aren't sent, so after a pause the last one still held a snapshot earlier. Without it
+        // the object would start moving at the time of that last one, slowed down over the whole pause.
+        if let (Some(_), Some(&(last_time, last))) = (state.transform, self.transforms.back()) {
+            if time - send_interval > last_time {
+                self.transforms.push_back((time - send_interval, last));
+            }
+        }
+
+        // Out of order snapshots are sorted in, one that's older than everything buffered is of no use anymore
+        if let Some(transform) = state.transform {
+            let index = self.transforms.partition_point(|(buffered, _)| *buffered < time);
+            let duplicate = self.transforms.get(index).is_some_and(|(buffered, _)| *buffered == time);
+            let too_old = index == 0 && self.transforms.len() == BUFFERED_TRANSFORMS;
+            if !duplicate && !too_old {
+                self.transforms.insert(index, (time, transform));
+                while self.transforms.len() > BUFFERED_TRANSFORMS {
+                    self.transforms.pop_front();
+                }
+            }
+        }
+        if let Some(material) = state.material {
+            if sequence > self.material_sequence {
+                self.material = Some(material);
+                self.material_sequence = sequence;
+                self.material_changed = true;
+            }
+        }
+    }
+
+    // Between the buffered transforms around `time`, held at the first or last one outside of them
+    fn transform_at(&self, time: f64) -> Option<Transform3DComponent> {
+        let index = self.transforms.partition_point(|(buffered, _)| *buffered <= time);
+        match (index.checked_sub(1).and_then(|before| self.transforms.get(before)), self.transforms.get(index)) {
+            (Some((before_time, before)), Some((after_time, after))) => {
+                let t = ((time - before_time) / (after_time - before_time)) as f32;
+                Some(Transform3DComponent {
+                    translation: before.translation + (after.translation - before.translation) * t,
+                    rotation: before.rotation.slerp(after.rotation, t),
+                    scale: before.scale + (after.scale - before.scale) * t
+                })
+            }
+            (Some((_, transform)), None) | (None, Some((_, transform))) => Some(*transform),
+            (None, None) => None
+        }
+    }
+}
+
+// The client side of `ReplicationServer`. Call `receive` with the endpoint's events, `update` once per frame and then
+// `apply`, or `take_spawns`, `take_despawns`, `transforms` and `take_materials` for applications that create and
+// change game objects themselves.
+pub struct ReplicationClient {
+    pub config: ReplicationConfig,
+    objects: HashMap<NetObjectId, RemoteObject>,
+    spawns: Vec<(NetObjectId, SpawnInfo)>,
+    despawns: Vec<usize>,
+    acks: Vec<(PeerId, u32)>,
+    view: Option<uv::Vec3>,
+    server: Option<PeerId>,
+    start: Instant,
+    // Server time minus local time, estimated from the snapshots that arrived quickest
+    clock_offset: Option<f64>,
+    last_view_sent: Option<Instant>,
+}
+
+impl ReplicationClient {
+    // `send_interval` has to match the server's, clients report their view as often
+    pub fn new(config: ReplicationConfig) -> Self {
+        Self {
+            config,
+            objects: HashMap::new(),
+            spawns: vec![],
+            despawns: vec![],
+            acks: vec![],
+            view: None,
+            server: None,
+            start: Instant::now(),
+            clock_offset: None,
+            last_view_sent: None
+        }
+    }
+
+    // The object the server replicates as `id` is this client's game object `object`, see
+    // `ReplicationServer::replicate_as`
+    pub fn bind(&mut self, id: NetObjectId, object: usize) {
+        let remote = self.objects.entry(id).or_insert_with(|| RemoteObject::new(None));
+        remote.local = Some(object);
+        remote.bound = true;
+        self.spawns.retain(|(spawn, _)| *spawn != id);
+    }
+
+    // Where the client looks from, what the server's interest radius is measured from
+    pub fn set_view(&mut self, position: uv::Vec3) {
+        self.view = Some(position);
+    }
+
+    // Takes the events of `Endpoint::update` and returns false for the ones left to the application
+    pub fn receive(&mut self, event: &NetEvent, now: Instant) -> bool {
+        let message = match event {
+            NetEvent::Message(message) if message.channel == CHANNEL_REPLICATION => message,
+            _ => return false
+        };
+        let (sequence, time, updates) = match message.decode() {
+            Ok(ReplicationMessage::Snapshot { sequence, time, updates }) => (sequence, time, updates),
+            Ok(_) => return true,
+            Err(error) => {
+                tracing::debug!("Invalid replication message from {:?}: {}", message.peer, error);
+                return true;
+            }
+        };
+        self.server = Some(message.peer);
+        self.acks.push((message.peer, sequence));
+
+        // Snapshots arrive late by the trip over the network, the largest offset is the closest to the real one. Clocks
+        // drift, so smaller ones still pull it down a little.
+        let offset = time - (now - self.start).as_secs_f64();
+        self.clock_offset = Some(match self.clock_offset {
+            Some(current) if offset < current => current + (offset - current) * CLOCK_SMOOTHING,
+            _ => offset
+        });
+
+        let send_interval = self.config.send_interval.as_secs_f64();
+        for update in updates {
+            match update {
+                ObjectUpdate::Spawn { id, info, state } => {
+                    let remote = self.objects.entry(id).or_insert_with(|| {
+                        self.spawns.push((id, info));
+                        RemoteObject::new(None)
+                    });
+                    remote.apply(state, sequence, time, send_interval);
+                }
+                ObjectUpdate::Update { id, delta } => if let Some(remote) = self.objects.get_mut(&id) {
+                    remote.apply(delta, sequence, time, send_interval);
+                },
+                ObjectUpdate::Despawn { id } => if let Some(remote) = self.objects.remove(&id) {
+                    self.spawns.retain(|(spawn, _)| *spawn != id);
+                    if let (Some(local), false) = (remote.local, remote.bound) {
+                        self.despawns.push(local);
+                    }
+                    // Bound objects stay bound for when they come back into interest
+                    if remote.bound {
+                        self.objects.insert(id, RemoteObject::new(remote.local));
+                    }
+                }
+            }
+        }
+        true
+    }
+
+    // Queues the acks and the view, `Endpoint::update` sends them
+    pub fn update(&mut self, endpoint: &mut Endpoint, now: Instant) {
+        for (peer, sequence) in self.acks.drain(..) {
+            if let Err(error) = endpoint.send_message(peer, CHANNEL_REPLICATION, &ReplicationMessage::Ack { sequence }) {
+                tracing::debug!("Failed to ack snapshot {}: {}", sequence, error);
+            }
+        }
+
+        let view_due = self.last_view_sent.is_none_or(|last_sent| now - last_sent >= self.config.send_interval);
+        if let (Some(server), Some(position), true) = (self.server, self.view, view_due) {
+            self.last_view_sent = Some(now);
+            if let Err(error) = endpoint.send_message(server, CHANNEL_REPLICATION, &ReplicationMessage::View { position }) {
+                tracing::debug!("Failed to send the view: {}", error);
+            }
+        }
+    }
+
+    // Objects to create, `bind` them to the new game objects
+    pub fn take_spawns(&mut self) -> Vec<(NetObjectId, SpawnInfo)> {
+        std::mem::take(&mut self.spawns)
+    }
+
+    // Game objects to remove
+    pub fn take_despawns(&mut self) -> Vec<usize> {
+        std::mem::take(&mut self.despawns)
+    }
+
+    // Server time transforms are played back at
+    fn playback_time(&self, now: Instant) -> Option<f64> {
+        let offset = self.clock_offset?;
+        Some((now - self.start).as_secs_f64() + offset - self.config.interpolation_delay.as_secs_f64())
+    }
+
+    // Interpolated transform of every spawned or bound object, by game object id
+    pub fn transforms(&self, now: Instant) -> Vec<(usize, Transform3DComponent)> {
+        let time = match self.playback_time(now) {
+            Some(time) => time,
+            None => return vec![]
+        };
+        self.objects
+            .values()
+            .filter_map(|remote| Some((remote.local?, remote.transform_at(time)?)))
+            .collect()
+    }
+
+    // Materials that changed since the last call, by game object id
+    pub fn take_materials(&mut self) -> Vec<(usize, MaterialState)> {
+        self.objects
+            .values_mut()
+            .filter(|remote| remote.material_changed && remote.local.is_some())
+            .filter_map(|remote| {
+                remote.material_changed = false;
+                Some((remote.local?, remote.material?))
+            })
+            .collect()
+    }
+
+    // Spawns objects from their mesh files, removes despawned ones and applies transforms and materials to the
+    // renderer's game objects. Objects with meshes built in code have to be bound instead.
+    pub fn apply(&mut self, renderer: &mut VulkanRenderer, assets: &mut AssetManager, now: Instant) {
+        crate::profile_scope!("Replication");
+        for (id, info) in self.take_spawns() {
+            let mesh = match &info.mesh {
+                Some(mesh) => mesh,
+                None => {
+                    tracing::warn!("Replicated object {} has no mesh file, bind it to spawn it", info.name);
+                    continue;
+                }
+            };
+            match assets.instantiate_file(renderer, mesh, uv::Vec3::zero()) {
+                Ok(object) => {
+                    if let Some(game_object) = renderer.game_objects.iter_mut().find(|game_object| game_object.get_id() == object) {
+                        game_object.name = info.name;
+                    }
+                    if let Some(remote) = self.objects.get_mut(&id) {
+                        remote.local = Some(object);
+                    }
+                }
+                Err(error) => tracing::error!("Failed to spawn {} from {}: {}", info.name, mesh.display(), error)
+            }
+        }
+
+        for object in self.take_despawns() {
+            if let Err(error) = renderer.remove_game_object(object) {
+                tracing::error!("Failed to despawn game object {}: {}", object, error);
+            }
+        }
+
+        let transforms: HashMap<usize, Transform3DComponent> = self.transforms(now).into_iter().collect();
+        let materials: HashMap<usize, MaterialState> = self.take_materials().into_iter().collect();
+        for game_object in &mut renderer.game_objects {
+            if let Some(transform) = transforms.get(&game_object.get_id()) {
+                game_object.transform3d = *transform;
+            }
+            if let Some(material) = materials.get(&game_object.get_id()) {
+                game_object.material = material.material;
+                game_object.color = material.color;
+                game_object.opacity = material.opacity;
+                game_object.roughness = material.roughness;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::net::{Message, PeerId};
+
+    fn state(x: f32, color: f32) -> ObjectState {
+        ObjectState {
+            transform: Some(Transform3DComponent {
+                translation: uv::Vec3::new(x, 0.0, 0.0),
+                rotation: uv::Rotor3::identity(),
+                scale: uv::Vec3::one()
+            }),
+            material: Some(MaterialState { material: Material::Basic, color: uv::Vec3::broadcast(color), opacity: 1.0, roughness: 1.0 })
+        }
+    }
+
+    fn spawn_info(id: NetObjectId) -> SpawnInfo {
+        SpawnInfo { name: format!("Object {}", id), mesh: None }
+    }
+
+    // Plans the client's next snapshot for objects at the origin and remembers it as sent
+    fn snapshot(client: &mut ClientView, sequence: u32, states: &[(NetObjectId, ObjectState)]) -> Vec<ObjectUpdate> {
+        let current: Vec<_> = states.iter().map(|&(id, state)| (id, uv::Vec3::zero(), state, false)).collect();
+        let (updates, states): (Vec<_>, Vec<_>) = client.updates(&current, None, spawn_info)
+            .into_iter()
+            .map(|(update, id, state)| (update, (id, state)))
+            .unzip();
+        client.sent(sequence, &updates, states);
+        updates
+    }
+
+    #[test]
+    fn delta_keeps_the_changed_components() {
+        let baseline = state(0.0, 1.0);
+        assert!(baseline.delta(&baseline).is_empty());
+        let moved = state(1.0, 1.0).delta(&baseline);
+        assert_eq!((moved.transform, moved.material), (state(1.0, 1.0).transform, None));
+        let both = state(1.0, 0.5).delta(&baseline);
+        assert_eq!(both, state(1.0, 0.5));
+        assert_eq!(state(1.0, 0.5).delta(&ObjectState::default()), state(1.0, 0.5));
+    }
+
+    #[test]
+    fn spawns_once_acked_then_sends_deltas() {
+        let mut client = ClientView::default();
+        let updates = snapshot(&mut client, 1, &[(7, state(0.0, 1.0))]);
+        assert!(matches!(updates[..], [ObjectUpdate::Spawn { id: 7, state: spawned, .. }] if spawned == state(0.0, 1.0)));
+
+        // Spawned again until the client acks it
+        assert!(matches!(snapshot(&mut client, 2, &[(7, state(0.0, 1.0))])[..], [ObjectUpdate::Spawn { .. }]));
+        client.ack(1);
+        assert!(snapshot(&mut client, 3, &[(7, state(0.0, 1.0))]).is_empty());
+        let updates = snapshot(&mut client, 4, &[(7, state(2.0, 1.0))]);
+        assert!(matches!(updates[..], [ObjectUpdate::Update { id: 7, delta }] if delta == state(2.0, 1.0).delta(&state(0.0, 1.0))));
+    }
+
+    #[test]
+    fn deltas_build_on_the_last_acked_baseline() {
+        let mut client = ClientView::default();
+        snapshot(&mut client, 1, &[(7, state(0.0, 1.0))]);
+        client.ack(1);
+        // Snapshot 2 is dropped, so 3 repeats its color change along with the new position
+        snapshot(&mut client, 2, &[(7, state(0.0, 0.5))]);
+        let updates = snapshot(&mut client, 3, &[(7, state(1.0, 0.5))]);
+        assert!(matches!(updates[..], [ObjectUpdate::Update { delta, .. }] if delta == state(1.0, 0.5)));
+
+        // An ack arriving late doesn't replace the newer baseline
+        client.ack(3);
+        client.ack(2);
+        assert_eq!(client.acked[&7], (3, state(1.0, 0.5)));
+        assert!(snapshot(&mut client, 4, &[(7, state(1.0, 0.5))]).is_empty());
+    }
+
+    #[test]
+    fn despawns_objects_that_leave_until_acked() {
+        let mut client = ClientView::default();
+        snapshot(&mut client, 1, &[(7, state(0.0, 1.0)), (8, state(0.0, 1.0))]);
+        client.ack(1);
+
+        assert!(matches!(snapshot(&mut client, 2, &[(8, state(0.0, 1.0))])[..], [ObjectUpdate::Despawn { id: 7 }]));
+        // Lost, so it goes again
+        assert!(matches!(snapshot(&mut client, 3, &[(8, state(0.0, 1.0))])[..], [ObjectUpdate::Despawn { id: 7 }]));
+        client.ack(3);
+        assert!(!client.spawned.contains_key(&7) && !client.acked.contains_key(&7));
+        assert!(snapshot(&mut client, 4, &[(8, state(0.0, 1.0))]).is_empty());
+
+        // Coming back spawns it anew
+        assert!(matches!(snapshot(&mut client, 5, &[(7, state(0.0, 1.0)), (8, state(0.0, 1.0))])[..], [ObjectUpdate::Spawn { id: 7, .. }]));
+    }
+
+    #[test]
+    fn interest_radius_leaves_out_far_objects() {
+        let client = ClientView { position: Some(uv::Vec3::zero()), ..Default::default() };
+        let far = uv::Vec3::new(100.0, 0.0, 0.0);
+        let current = [(1, uv::Vec3::zero(), state(0.0, 1.0), false), (2, far, state(0.0, 1.0), false), (3, far, state(0.0, 1.0), true)];
+        let mut ids: Vec<_> = client.updates(&current, Some(10.0), spawn_info).into_iter().map(|(_, id, _)| id).collect();
+        ids.sort();
+        assert_eq!(ids, vec![1, 3]);
+    }
+
+    #[test]
+    fn client_spawns_and_despawns() {
+        let now = Instant::now();
+        let mut client = ReplicationClient::new(ReplicationConfig::default());
+        let receive = |client: &mut ReplicationClient, sequence, updates| {
+            let message = ReplicationMessage::Snapshot { sequence, time: sequence as f64, updates };
+            let payload = bincode::serialize(&message).unwrap();
+            assert!(client.receive(&NetEvent::Message(Message { peer: PeerId(0), channel: CHANNEL_REPLICATION, payload }), now));
+        };
+
+        receive(&mut client, 1, vec![ObjectUpdate::Spawn { id: 7, info: spawn_info(7), state: state(0.0, 1.0) }]);
+        let spawns = client.take_spawns();
+        assert_eq!(spawns.iter().map(|(id, info)| (*id, info.name.as_str())).collect::<Vec<_>>(), vec![(7, "Object 7")]);
+        client.bind(7, 42);
+        assert_eq!(client.take_materials().into_iter().map(|(object, _)| object).collect::<Vec<_>>(), vec![42]);
+
+        // Bound objects are left to the application
+        receive(&mut client, 2, vec![ObjectUpdate::Despawn { id: 7 }]);
+        assert!(client.take_despawns().is_empty());
+
+        receive(&mut client, 3, vec![ObjectUpdate::Spawn { id: 8, info: spawn_info(8), state: state(0.0, 1.0) }]);
+        client.objects.get_mut(&8).unwrap().local = Some(43);
+        receive(&mut client, 4, vec![ObjectUpdate::Despawn { id: 8 }]);
+        assert_eq!(client.take_despawns(), vec![43]);
+    }
+}
\ No newline at end of file
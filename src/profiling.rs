@@ -0,0 +1,165 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// CPU profiling with `profile_scope!`. The built-in profiler sums the scopes of every frame into a hierarchy that the
+// debug overlay shows, without any external tool. Builds with the `tracy` feature also stream every scope, the GPU
+// passes (see `vulkan::gpu_profiler`) and a mark per frame to the Tracy profiler UI once it connects.
+
+// Weight of the newest frame in the averages
+const AVERAGE_WEIGHT: f32 = 0.1;
+
+#[cfg(feature = "tracy")]
+static CLIENT: std::sync::OnceLock<tracy_client::Client> = std::sync::OnceLock::new();
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static PROFILER: Mutex<Profiler> = Mutex::new(Profiler::new());
+
+thread_local! {
+    // Names of the scopes open on this thread, outermost first
+    static STACK: RefCell<Vec<&'static str>> = const { RefCell::new(vec![]) };
+}
+
+// Times the enclosing block as a scope named `$name`, nested in the scopes open on the same thread. Scopes on job
+// threads start their own hierarchy.
+#[macro_export]
+macro_rules! profile_scope {
+    ($name:expr) => {
+        let _profile_scope = $crate::profiling::ProfileScope::new($name);
+    };
+}
+
+// One line of the hierarchy, for the frame before the current one
+#[derive(Clone, Debug)]
+pub struct ScopeTiming {
+    pub name: &'static str,
+    // Scopes it's nested in
+    pub depth: usize,
+    pub calls: u32,
+    // Summed over the calls and nested scopes included, so scopes on several threads can add up to more than the frame
+    pub milliseconds: f32,
+    // Smoothed over the last frames, what the overlay shows
+    pub average_milliseconds: f32,
+}
+
+struct Profiler {
+    // Time and calls of every path of scope names so far this frame
+    current: BTreeMap<Vec<&'static str>, (Duration, u32)>,
+    averages: BTreeMap<Vec<&'static str>, f32>,
+    report: Vec<ScopeTiming>,
+}
+
+impl Profiler {
+    const fn new() -> Self {
+        Self {
+            current: BTreeMap::new(),
+            averages: BTreeMap::new(),
+            report: vec![]
+        }
+    }
+}
+
+// Created by `profile_scope!`, the scope ends when it's dropped
+pub struct ProfileScope {
+    // `None` while the built-in profiler is off
+    start: Option<Instant>,
+    #[cfg(feature = "tracy")]
+    _zone: Option<tracy_client::Span>,
+}
+
+impl ProfileScope {
+    #[track_caller]
+    pub fn new(name: &'static str) -> Self {
+        let start = ENABLED.load(Ordering::Relaxed).then(|| {
+            STACK.with(|stack| stack.borrow_mut().push(name));
+            Instant::now()
+        });
+
+        #[cfg(feature = "tracy")]
+        let location = std::panic::Location::caller();
+        Self {
+            start,
+            #[cfg(feature = "tracy")]
+            _zone: tracy_client::Client::running().map(|client| client.span_alloc(Some(name), "", location.file(), location.line(), 0))
+        }
+    }
+}
+
+impl Drop for ProfileScope {
+    fn drop(&mut self) {
+        if let Some(start) = self.start {
+            let elapsed = start.elapsed();
+            let path = STACK.with(|stack| {
+                let mut stack = stack.borrow_mut();
+                let path = stack.clone();
+                stack.pop();
+                path
+            });
+
+            let mut profiler = PROFILER.lock().unwrap();
+            let (time, calls) = profiler.current.entry(path).or_default();
+            *time += elapsed;
+            *calls += 1;
+        }
+    }
+}
+
+// Starts the Tracy client, `VulkanRenderer::new` calls it. Zones entered before are dropped.
+pub fn start() {
+    #[cfg(feature = "tracy")]
+    CLIENT.get_or_init(tracy_client::Client::start);
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+// Scopes already open when it's turned on aren't timed
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+    if !enabled {
+        let mut profiler = PROFILER.lock().unwrap();
+        *profiler = Profiler::new();
+    }
+}
+
+// The last finished frame's scopes, each right after the scope it's nested in, siblings sorted by name
+pub fn frame_report() -> Vec<ScopeTiming> {
+    PROFILER.lock().unwrap().report.clone()
+}
+
+// Closes the frame for the built-in profiler and Tracy, called after every submission
+pub fn end_frame() {
+    #[cfg(feature = "tracy")]
+    if let Some(client) = CLIENT.get() {
+        client.frame_mark();
+    }
+
+    if !enabled() {
+        return;
+    }
+    let mut profiler = PROFILER.lock().unwrap();
+    let current = std::mem::take(&mut profiler.current);
+    let previous = std::mem::take(&mut profiler.averages);
+
+    // Paths sort right after their prefix, which puts the hierarchy in order
+    let mut report = Vec::with_capacity(current.len());
+    for (path, (time, calls)) in current {
+        let milliseconds = time.as_secs_f32() * 1000.0;
+        let average_milliseconds = match previous.get(&path) {
+            Some(average) => average + (milliseconds - average) * AVERAGE_WEIGHT,
+            None => milliseconds
+        };
+        report.push(ScopeTiming {
+            name: path[path.len() - 1],
+            depth: path.len() - 1,
+            calls,
+            milliseconds,
+            average_milliseconds
+        });
+        profiler.averages.insert(path, average_milliseconds);
+    }
+    profiler.report = report;
+}
\ No newline at end of file
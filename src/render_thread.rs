@@ -0,0 +1,166 @@
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use crate::vulkan::game_object::Transform3DComponent;
+use crate::vulkan::renderer::VulkanRenderer;
+
+// How long the render thread waits for a new packet before presenting the last one again
+const STALE_PACKET_TIMEOUT: Duration = Duration::from_millis(16);
+
+// What the game thread decided about a frame, handed to the render thread without touching the renderer
+#[derive(Default)]
+pub struct FramePacket {
+    // Transforms of game objects by id, written to the matching objects before recording
+    pub transforms: Vec<(usize, Transform3DComponent)>,
+}
+
+impl FramePacket {
+    fn apply(&self, renderer: &mut VulkanRenderer) {
+        for (id, transform) in &self.transforms {
+            if let Some(game_object) = renderer.game_objects.iter_mut().find(|game_object| game_object.get_id() == *id) {
+                game_object.transform3d = *transform;
+            }
+        }
+    }
+}
+
+// The packet the game thread published and the render thread hasn't taken yet, plus the one it took last time, which goes
+// back to the game thread to be filled again so the transform list keeps its allocation
+#[derive(Default)]
+struct PacketSlots {
+    pending: Option<FramePacket>,
+    spare: Option<FramePacket>,
+}
+
+struct Shared {
+    renderer: Mutex<VulkanRenderer>,
+    slots: Mutex<PacketSlots>,
+    published: Condvar,
+    taken: Condvar,
+    running: AtomicBool,
+}
+
+// Records, submits and presents frames on its own thread, so a long game update only delays the content of the frames and
+// not their presentation: without a new packet in time the last one is drawn again. The game thread stays at most one
+// packet ahead, `publish` waits until the render thread took the previous one.
+// Structural changes (adding objects, the editor, picking, ...) still go through `lock`, which waits for the frame being
+// recorded to be submitted.
+pub struct RenderThread {
+    shared: Arc<Shared>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl RenderThread {
+    pub fn spawn(renderer: VulkanRenderer) -> std::io::Result<Self> {
+        let shared = Arc::new(Shared {
+            renderer: Mutex::new(renderer),
+            slots: Mutex::new(PacketSlots::default()),
+            published: Condvar::new(),
+            taken: Condvar::new(),
+            running: AtomicBool::new(true),
+        });
+
+        let thread_shared = shared.clone();
+        let handle = std::thread::Builder::new()
+            .name("render".into())
+            .spawn(move || render_loop(&thread_shared))?;
+
+        Ok(Self {
+            shared,
+            handle: Some(handle)
+        })
+    }
+
+    // Exclusive access to the renderer between two frames
+    pub fn lock(&self) -> MutexGuard<'_, VulkanRenderer> {
+        self.shared.renderer.lock().expect("Render thread panicked!")
+    }
+
+    // An empty packet to fill for the next frame, reusing a consumed one when there is one
+    pub fn packet(&self) -> FramePacket {
+        let mut slots = self.shared.slots.lock().expect("Render thread panicked!");
+        let mut packet = slots.spare.take().unwrap_or_default();
+        packet.transforms.clear();
+        packet
+    }
+
+    // Hands the packet to the render thread, waiting for it to take the previous one first. Don't hold `lock` while calling this.
+    pub fn publish(&self, packet: FramePacket) {
+        let mut slots = self.shared.slots.lock().expect("Render thread panicked!");
+        while slots.pending.is_some() && self.is_running() {
+            slots = self.shared.taken.wait(slots).expect("Render thread panicked!");
+        }
+        slots.pending = Some(packet);
+        self.shared.published.notify_one();
+    }
+
+    // False once the render thread stopped, after a panic for instance
+    pub fn is_running(&self) -> bool {
+        self.shared.running.load(Ordering::Acquire)
+    }
+
+    // Stops drawing and waits for the frames in flight, the renderer is dropped with the last handle to it
+    pub fn stop(&mut self) {
+        self.shared.running.store(false, Ordering::Release);
+        self.shared.published.notify_all();
+        if let Some(handle) = self.handle.take() {
+            if handle.join().is_err() {
+                tracing::error!("Render thread panicked");
+            }
+        }
+        if let Ok(renderer) = self.shared.renderer.lock() {
+            unsafe {
+                renderer.device.device_wait_idle()
+                    .expect("Failed to wait for the device!");
+            }
+        }
+    }
+}
+
+impl Drop for RenderThread {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+fn render_loop(shared: &Shared) {
+    // Stops the game thread from waiting on a packet nobody takes anymore if drawing panics
+    struct StopOnExit<'a>(&'a Shared);
+    impl Drop for StopOnExit<'_> {
+        fn drop(&mut self) {
+            self.0.running.store(false, Ordering::Release);
+            self.0.taken.notify_all();
+        }
+    }
+    let _stop = StopOnExit(shared);
+
+    let mut current: Option<FramePacket> = None;
+    while shared.running.load(Ordering::Acquire) {
+        {
+            let mut slots = shared.slots.lock().expect("Game thread panicked!");
+            if slots.pending.is_none() {
+                slots = shared.published.wait_timeout(slots, STALE_PACKET_TIMEOUT).expect("Game thread panicked!").0;
+            }
+            if let Some(packet) = slots.pending.take() {
+                slots.spare = current.replace(packet);
+                shared.taken.notify_one();
+            }
+        }
+        if !shared.running.load(Ordering::Acquire) {
+            break;
+        }
+        // Nothing to show before the first packet
+        let packet = match &current {
+            Some(packet) => packet,
+            None => continue
+        };
+
+        let mut renderer = shared.renderer.lock().expect("Game thread panicked!");
+        packet.apply(&mut renderer);
+        renderer.fill_commandbuffers()
+            .expect("Failed to write commands!");
+        renderer.draw_frame();
+    }
+}
\ No newline at end of file
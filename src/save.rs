@@ -0,0 +1,332 @@
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use bincode::Options;
+use flate2::Compression;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::vulkan::camera::Camera;
+use crate::vulkan::game_object::{GameObject, Transform3DComponent};
+
+// Save games hold the parts of the world a game picks, unlike `WorldSnapshot` which captures the whole scene. Every part
+// is a `SaveComponent` stored under its key with the version it was written with, so a game can change one part's
+// layout and still load old saves through a migration, and saves from older builds missing a part just don't have it.
+//
+// File layout: the magic and format version, then gzip compressed bincode of the `SaveHeader` followed by the sections.
+
+const MAGIC: &[u8; 4] = b"RVRS";
+const FORMAT_VERSION: u32 = 1;
+// Most bytes the header or the sections may decode from, so a corrupt length can't ask for an enormous allocation
+const MAX_SAVE_SIZE: u64 = 256 * 1024 * 1024;
+
+pub trait SaveComponent: Serialize + DeserializeOwned {
+    // Unique among the components of a game, the engine's start with "reverie."
+    const KEY: &'static str;
+    // Raised whenever the serialized layout changes, with a migration from the previous version added
+    const VERSION: u32;
+}
+
+// What save slot lists show, readable without loading the rest
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SaveHeader {
+    pub description: String,
+    // Seconds since the Unix epoch
+    pub created: u64,
+    // Simulated seconds the game was played for, see `SimulationClock::time`
+    pub play_time: f64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct Section {
+    version: u32,
+    data: Vec<u8>,
+}
+
+type Migration = Box<dyn Fn(&[u8]) -> Result<Vec<u8>, Box<dyn Error>> + Send + Sync>;
+
+// Upgrades components saved with older versions one version at a time
+#[derive(Default)]
+pub struct Migrations {
+    // By key and the version they upgrade from
+    migrations: HashMap<(&'static str, u32), Migration>,
+}
+
+impl Migrations {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Reads the component as `Old`, the layout of version `from`, and writes it as `New`, the layout of `from + 1`
+    pub fn add<Old, New>(&mut self, key: &'static str, from: u32, migrate: impl Fn(Old) -> New + Send + Sync + 'static)
+    where Old: DeserializeOwned, New: Serialize {
+        let migration = move |data: &[u8]| -> Result<Vec<u8>, Box<dyn Error>> {
+            let old: Old = bincode::deserialize(data)?;
+            Ok(bincode::serialize(&migrate(old))?)
+        };
+        self.migrations.insert((key, from), Box::new(migration));
+    }
+
+    fn upgrade<T: SaveComponent>(&self, section: &Section) -> Result<Vec<u8>, Box<dyn Error>> {
+        if section.version > T::VERSION {
+            return Err(format!("{} was saved with version {}, newer than {}", T::KEY, section.version, T::VERSION).into());
+        }
+
+        let mut data = section.data.clone();
+        for version in section.version..T::VERSION {
+            let migration = self.migrations
+                .get(&(T::KEY, version))
+                .ok_or_else(|| format!("No migration of {} from version {}", T::KEY, version))?;
+            data = migration(&data).map_err(|error| format!("Failed to migrate {} from version {}: {}", T::KEY, version, error))?;
+        }
+        Ok(data)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SaveGame {
+    pub header: SaveHeader,
+    sections: BTreeMap<String, Section>,
+}
+
+impl SaveGame {
+    pub fn new(description: impl Into<String>, play_time: f64) -> Self {
+        Self {
+            header: SaveHeader {
+                description: description.into(),
+                created: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs()),
+                play_time
+            },
+            sections: BTreeMap::new()
+        }
+    }
+
+    // Replaces what was saved under the component's key
+    pub fn put<T: SaveComponent>(&mut self, component: &T) -> Result<(), Box<dyn Error>> {
+        let data = bincode::serialize(component)?;
+        self.sections.insert(T::KEY.to_string(), Section { version: T::VERSION, data });
+        Ok(())
+    }
+
+    // `None` when the save doesn't have the component, older versions go through `migrations`
+    pub fn get<T: SaveComponent>(&self, migrations: &Migrations) -> Result<Option<T>, Box<dyn Error>> {
+        let section = match self.sections.get(T::KEY) {
+            Some(section) => section,
+            None => return Ok(None)
+        };
+        let data = migrations.upgrade::<T>(section)?;
+        Ok(Some(bincode::deserialize(&data)?))
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.sections.contains_key(key)
+    }
+
+    pub fn remove(&mut self, key: &str) {
+        self.sections.remove(key);
+    }
+
+    // Writes next to `path` first and renames it over, so a crash while saving leaves the previous save intact
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let temporary = path.with_extension("tmp");
+        {
+            let mut writer = BufWriter::new(File::create(&temporary)?);
+            writer.write_all(MAGIC)?;
+            writer.write_all(&FORMAT_VERSION.to_le_bytes())?;
+            let mut encoder = GzEncoder::new(writer, Compression::default());
+            options().serialize_into(&mut encoder, &self.header).map_err(invalid_data)?;
+            options().serialize_into(&mut encoder, &self.sections).map_err(invalid_data)?;
+            encoder.finish()?.into_inner().map_err(|error| error.into_error())?.sync_all()?;
+        }
+        std::fs::rename(&temporary, path)
+    }
+
+    pub fn read(path: &Path) -> io::Result<Self> {
+        let mut decoder = open(path)?;
+        let header = options().deserialize_from(&mut decoder).map_err(invalid_data)?;
+        let sections = options().deserialize_from(&mut decoder).map_err(invalid_data)?;
+        // The gzip trailer's checksum is only checked once the stream is read to its end
+        io::copy(&mut decoder, &mut io::sink())?;
+        Ok(Self { header, sections })
+    }
+
+    // Only decompresses as far as the header
+    pub fn read_header(path: &Path) -> io::Result<SaveHeader> {
+        options().deserialize_from(open(path)?).map_err(invalid_data)
+    }
+}
+
+// The encoding of `bincode::serialize`, with a limit
+fn options() -> impl Options {
+    bincode::options().with_fixint_encoding().allow_trailing_bytes().with_limit(MAX_SAVE_SIZE)
+}
+
+fn invalid_data(error: impl Into<Box<dyn Error + Send + Sync>>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+fn open(path: &Path) -> io::Result<GzDecoder<BufReader<File>>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut magic = [0u8; 4];
+    let mut version = [0u8; 4];
+    reader.read_exact(&mut magic)?;
+    reader.read_exact(&mut version)?;
+    if &magic != MAGIC {
+        return Err(invalid_data(format!("{} isn't a save game", path.display())));
+    }
+    if u32::from_le_bytes(version) != FORMAT_VERSION {
+        return Err(invalid_data(format!("{} is in save format {}, this build reads {}", path.display(), u32::from_le_bytes(version),
+            FORMAT_VERSION)));
+    }
+    Ok(GzDecoder::new(reader))
+}
+
+// Transforms of game objects, matched by name on load. Objects sharing a name are matched in the order they come in.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SavedTransforms {
+    pub objects: Vec<(String, Transform3DComponent)>,
+}
+
+impl SaveComponent for SavedTransforms {
+    const KEY: &'static str = "reverie.transforms";
+    const VERSION: u32 = 1;
+}
+
+impl SavedTransforms {
+    pub fn capture(game_objects: &[GameObject], mut include: impl FnMut(&GameObject) -> bool) -> Self {
+        Self {
+            objects: game_objects
+                .iter()
+                .filter(|game_object| include(game_object))
+                .map(|game_object| (game_object.name.clone(), game_object.transform3d))
+                .collect()
+        }
+    }
+
+    // Returns how many objects were found
+    pub fn apply(&self, game_objects: &mut [GameObject]) -> usize {
+        let mut taken = vec![false; game_objects.len()];
+        let mut found = 0;
+        for (name, transform) in &self.objects {
+            let index = (0..game_objects.len()).find(|&index| !taken[index] && game_objects[index].name == *name);
+            if let Some(index) = index {
+                taken[index] = true;
+                game_objects[index].transform3d = *transform;
+                found += 1;
+            }
+        }
+        found
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SavedCamera(pub Camera);
+
+impl SaveComponent for SavedCamera {
+    const KEY: &'static str = "reverie.camera";
+    const VERSION: u32 = 1;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Inventory {
+        items: Vec<(String, u32)>,
+    }
+
+    impl SaveComponent for Inventory {
+        const KEY: &'static str = "test.inventory";
+        const VERSION: u32 = 2;
+    }
+
+    // Removed when it goes out of scope
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(name: &str) -> Self {
+            Self(std::env::temp_dir().join(format!("reverie-{}-{}.sav", name, std::process::id())))
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    fn saved(name: &str) -> TempFile {
+        let file = TempFile::new(name);
+        let mut save = SaveGame::new("Before the bridge", 12.5);
+        save.put(&Inventory { items: vec![("rope".to_string(), 2), ("lantern".to_string(), 1)] }).unwrap();
+        save.write(&file.0).unwrap();
+        file
+    }
+
+    #[test]
+    fn round_trip() {
+        let file = saved("round-trip");
+        let header = SaveGame::read_header(&file.0).unwrap();
+        assert_eq!((header.description.as_str(), header.play_time), ("Before the bridge", 12.5));
+
+        let save = SaveGame::read(&file.0).unwrap();
+        assert!(save.contains(Inventory::KEY) && !save.contains(SavedTransforms::KEY));
+        let inventory: Inventory = save.get(&Migrations::new()).unwrap().unwrap();
+        assert_eq!(inventory, Inventory { items: vec![("rope".to_string(), 2), ("lantern".to_string(), 1)] });
+        assert!(save.get::<SavedTransforms>(&Migrations::new()).unwrap().is_none());
+    }
+
+    #[test]
+    fn rejects_another_format_version() {
+        let file = saved("format-version");
+        let mut bytes = std::fs::read(&file.0).unwrap();
+        bytes[MAGIC.len()..MAGIC.len() + 4].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        std::fs::write(&file.0, &bytes).unwrap();
+        assert_eq!(SaveGame::read(&file.0).unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        bytes[0] = b'X';
+        std::fs::write(&file.0, &bytes).unwrap();
+        assert_eq!(SaveGame::read_header(&file.0).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn rejects_a_bad_checksum() {
+        let file = saved("checksum");
+        let mut bytes = std::fs::read(&file.0).unwrap();
+        // The gzip trailer ends in the checksum and the length
+        let checksum = bytes.len() - 8;
+        bytes[checksum] ^= 0xff;
+        std::fs::write(&file.0, &bytes).unwrap();
+        assert!(SaveGame::read(&file.0).is_err());
+    }
+
+    #[test]
+    fn migrates_older_components_and_refuses_newer_ones() {
+        #[derive(Serialize, Deserialize)]
+        struct OldInventory {
+            items: Vec<String>,
+        }
+
+        let mut save = SaveGame::new("", 0.0);
+        save.sections.insert(Inventory::KEY.to_string(), Section {
+            version: 1,
+            data: bincode::serialize(&OldInventory { items: vec!["rope".to_string()] }).unwrap()
+        });
+        assert!(save.get::<Inventory>(&Migrations::new()).is_err());
+
+        let mut migrations = Migrations::new();
+        migrations.add(Inventory::KEY, 1, |old: OldInventory| Inventory { items: old.items.into_iter().map(|item| (item, 1)).collect() });
+        assert_eq!(save.get::<Inventory>(&migrations).unwrap(), Some(Inventory { items: vec![("rope".to_string(), 1)] }));
+
+        save.sections.get_mut(Inventory::KEY).unwrap().version = 3;
+        assert!(save.get::<Inventory>(&migrations).is_err());
+    }
+}
\ No newline at end of file
@@ -0,0 +1,209 @@
+use std::any::TypeId;
+
+use crate::jobs::{TaskGraph, TaskId};
+
+// Component types a system reads and writes
+#[derive(Clone, Debug, Default)]
+pub struct Access {
+    reads: Vec<TypeId>,
+    writes: Vec<TypeId>,
+}
+
+impl Access {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn read<T: 'static>(mut self) -> Self {
+        self.reads.push(TypeId::of::<T>());
+        self
+    }
+
+    pub fn write<T: 'static>(mut self) -> Self {
+        self.writes.push(TypeId::of::<T>());
+        self
+    }
+
+    // Whether running both systems at once would race: one writes what the other touches
+    pub fn conflicts(&self, other: &Access) -> bool {
+        let touches = |access: &Access, component: &TypeId| access.reads.contains(component) || access.writes.contains(component);
+        self.writes.iter().any(|component| touches(other, component)) || other.writes.iter().any(|component| touches(self, component))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SystemId(usize);
+
+struct System<W> {
+    name: &'static str,
+    access: Access,
+    // Systems that have to finish before this one starts, on top of the conflicting ones added before it
+    after: Vec<usize>,
+    run: Box<dyn Fn(&W) + Send + Sync>,
+}
+
+// Runs the systems of a world `W` on the job threads. Systems get shared access to the world and reach their components
+// through it (locks or cells of the storage), the schedule makes sure two systems whose `Access` conflicts never run at
+// the same time, so that access doesn't contend. Conflicting systems run in the order they were added unless `order`
+// says otherwise, everything else runs in parallel.
+pub struct Schedule<W> {
+    systems: Vec<System<W>>,
+    // One after another on the calling thread
+    ordered: bool,
+}
+
+impl<W: Sync> Default for Schedule<W> {
+    fn default() -> Self {
+        Self {
+            systems: vec![],
+            ordered: false
+        }
+    }
+}
+
+impl<W: Sync> Schedule<W> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, name: &'static str, access: Access, system: impl Fn(&W) + Send + Sync + 'static) -> SystemId {
+        self.systems.push(System {
+            name,
+            access,
+            after: vec![],
+            run: Box::new(system)
+        });
+        SystemId(self.systems.len() - 1)
+    }
+
+    // `then` starts only after `first` finished, whether they conflict or not
+    pub fn order(&mut self, first: SystemId, then: SystemId) {
+        self.systems[then.0].after.push(first.0);
+    }
+
+    // Runs the systems one at a time in `sorted` order instead of in parallel, for deterministic simulations. Systems
+    // whose access doesn't conflict can still come out differently in parallel, like when they draw from one random
+    // number generator or push into one queue behind a lock.
+    pub fn set_ordered(&mut self, ordered: bool) {
+        self.ordered = ordered;
+    }
+
+    pub fn name(&self, id: SystemId) -> &'static str {
+        self.systems[id.0].name
+    }
+
+    // Order the systems start in when they conflict: the explicit constraints first, the order they were added otherwise.
+    // Err names a system held up by a cycle of constraints.
+    pub fn sorted(&self) -> Result<Vec<SystemId>, &'static str> {
+        let mut waiting: Vec<usize> = self.systems.iter().map(|system| system.after.len()).collect();
+        let mut sorted = Vec::with_capacity(self.systems.len());
+        let mut done = vec![false; self.systems.len()];
+        while sorted.len() < self.systems.len() {
+            let next = match (0..self.systems.len()).find(|&index| !done[index] && waiting[index] == 0) {
+                Some(next) => next,
+                None => return Err(self.systems[(0..self.systems.len()).find(|&index| !done[index]).unwrap()].name)
+            };
+            done[next] = true;
+            sorted.push(SystemId(next));
+            for (index, system) in self.systems.iter().enumerate() {
+                waiting[index] -= system.after.iter().filter(|&&first| first == next).count();
+            }
+        }
+        Ok(sorted)
+    }
+
+    // Runs every system once and returns when all of them finished
+    pub fn run(&self, world: &W) {
+        let sorted = self.sorted()
+            .unwrap_or_else(|name| panic!("System {} waits on a cycle of ordering constraints!", name));
+
+        if self.ordered {
+            for &SystemId(index) in &sorted {
+                let system = &self.systems[index];
+                crate::profile_scope!(system.name);
+                (system.run)(world);
+            }
+            return;
+        }
+
+        let mut graph = TaskGraph::new();
+        let mut tasks: Vec<Option<TaskId>> = vec![None; self.systems.len()];
+        for (position, &SystemId(index)) in sorted.iter().enumerate() {
+            let system = &self.systems[index];
+            let dependencies: Vec<TaskId> = sorted[..position]
+                .iter()
+                .filter(|&&SystemId(earlier)| system.after.contains(&earlier) || system.access.conflicts(&self.systems[earlier].access))
+                .filter_map(|&SystemId(earlier)| tasks[earlier])
+                .collect();
+            tasks[index] = Some(graph.add(system.name, &dependencies, move || (system.run)(world)));
+        }
+        graph.run();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    struct Position;
+    struct Velocity;
+
+    fn schedule(count: usize) -> Schedule<Mutex<Vec<&'static str>>> {
+        let mut schedule = Schedule::new();
+        for name in ["first", "second", "third", "fourth"].into_iter().take(count) {
+            schedule.add(name, Access::new().write::<Position>(), move |log: &Mutex<Vec<&'static str>>| log.lock().unwrap().push(name));
+        }
+        schedule
+    }
+
+    #[test]
+    fn reads_do_not_conflict() {
+        let reader = Access::new().read::<Position>();
+        assert!(!reader.conflicts(&Access::new().read::<Position>()));
+        assert!(!reader.conflicts(&Access::new().write::<Velocity>()));
+    }
+
+    #[test]
+    fn writes_conflict_both_ways() {
+        let writer = Access::new().read::<Velocity>().write::<Position>();
+        assert!(writer.conflicts(&Access::new().read::<Position>()));
+        assert!(Access::new().read::<Position>().conflicts(&writer));
+        assert!(writer.conflicts(&Access::new().write::<Position>()));
+        assert!(Access::new().write::<Velocity>().conflicts(&writer));
+    }
+
+    #[test]
+    fn sorted_keeps_the_added_order() {
+        let sorted = schedule(3).sorted().unwrap();
+        assert_eq!(sorted, vec![SystemId(0), SystemId(1), SystemId(2)]);
+    }
+
+    #[test]
+    fn sorted_follows_explicit_order() {
+        let mut schedule = schedule(3);
+        schedule.order(SystemId(2), SystemId(0));
+        schedule.order(SystemId(1), SystemId(2));
+        assert_eq!(schedule.sorted().unwrap(), vec![SystemId(1), SystemId(2), SystemId(0)]);
+    }
+
+    #[test]
+    fn sorted_names_a_system_in_a_cycle() {
+        let mut schedule = schedule(3);
+        schedule.order(SystemId(1), SystemId(2));
+        schedule.order(SystemId(2), SystemId(1));
+        assert_eq!(schedule.sorted(), Err("second"));
+    }
+
+    #[test]
+    fn conflicting_systems_run_in_sorted_order() {
+        for ordered in [false, true] {
+            let mut schedule = schedule(4);
+            schedule.order(SystemId(3), SystemId(1));
+            schedule.set_ordered(ordered);
+            let log = Mutex::new(vec![]);
+            schedule.run(&log);
+            assert_eq!(log.into_inner().unwrap(), vec!["first", "third", "fourth", "second"]);
+        }
+    }
+}
\ No newline at end of file
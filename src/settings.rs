@@ -0,0 +1,340 @@
+use std::path::PathBuf;
+
+use ash::vk;
+use clap::Parser;
+
+use crate::net::NetworkRole;
+use crate::vulkan::light_probes::GiQuality;
+use crate::vulkan::renderer::RendererSettings;
+use crate::vulkan::parallax::ParallaxQuality;
+use crate::vulkan::shading_rate::ShadingRateMode;
+use crate::vulkan::viewport::ViewportMode;
+use crate::vulkan::window::{FullscreenMode, WindowBackend, WindowStyle};
+
+// Everything the engine is started with. The defaults are overridden by the command line, see `Cli`.
+pub struct Settings {
+    pub width: u32,
+    pub height: u32,
+    pub fullscreen: FullscreenMode,
+    pub window_backend: WindowBackend,
+    pub window_style: WindowStyle,
+    // Mesh loaded into the scene at the origin
+    pub scene: Option<PathBuf>,
+    // Prints the monitors and their video modes instead of running
+    pub list_monitors: bool,
+    // Runs with the window hidden and without the editor
+    pub headless: bool,
+    // Frames to measure before printing a report and exiting, see `Benchmark`
+    pub benchmark: Option<u32>,
+    // Frame rate caps while the window has focus and while it doesn't, see `FrameLimiter`
+    pub fps_cap: Option<f32>,
+    pub background_fps_cap: Option<f32>,
+    // Without an fps cap, renders every n-th refresh of the monitor
+    pub refresh_divisor: Option<u32>,
+    pub viewport: ViewportMode,
+    pub renderer: RendererSettings,
+    // Hosts a game or joins one, `None` plays alone
+    pub network: Option<NetworkRole>,
+    // Seed of the deterministic simulation mode, see `SimulationClock::set_deterministic`
+    pub deterministic: Option<u64>,
+    // Scene input is written to this file as it happens, see `InputRecorder`
+    pub record_input: Option<PathBuf>,
+    // Input recording played back in place of the mouse and keyboard, see `InputPlayer`
+    pub replay_input: Option<PathBuf>,
+    // Language of the UI like "de" or "de-AT", the system's when `None`, see `Localization`
+    pub language: Option<String>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            width: 800,
+            height: 600,
+            fullscreen: FullscreenMode::Windowed,
+            window_backend: WindowBackend::Auto,
+            window_style: WindowStyle::default(),
+            scene: None,
+            list_monitors: false,
+            headless: false,
+            benchmark: None,
+            fps_cap: None,
+            background_fps_cap: None,
+            refresh_divisor: None,
+            viewport: ViewportMode::Fill,
+            renderer: RendererSettings::default(),
+            network: None,
+            deterministic: None,
+            record_input: None,
+            replay_input: None,
+            language: None
+        }
+    }
+}
+
+impl Settings {
+    // Defaults with the process arguments applied, exits with usage information when they can't be parsed
+    pub fn from_args() -> Self {
+        let mut settings = Self::default();
+        Cli::parse().apply(&mut settings);
+        settings
+    }
+}
+
+#[derive(Parser)]
+#[command(name = "reverie", version, about = "Reverie renderer")]
+pub struct Cli {
+    // Doc comments below are clap's help text
+    /// Window width in logical pixels
+    #[arg(long)]
+    width: Option<u32>,
+    /// Window height in logical pixels
+    #[arg(long)]
+    height: Option<u32>,
+    /// Start borderless fullscreen on the current monitor
+    #[arg(long, conflicts_with = "exclusive_fullscreen")]
+    fullscreen: bool,
+    /// Start fullscreen with the monitor's video mode changed, for the lowest latency
+    #[arg(long)]
+    exclusive_fullscreen: bool,
+    /// Monitor to go fullscreen on, an index into --list-monitors
+    #[arg(long, value_name = "INDEX")]
+    monitor: Option<usize>,
+    /// Video mode for --exclusive-fullscreen, an index into the monitor's modes in --list-monitors
+    #[arg(long, value_name = "INDEX", requires = "exclusive_fullscreen")]
+    video_mode: Option<usize>,
+    /// Print the monitors and their video modes, then exit
+    #[arg(long)]
+    list_monitors: bool,
+    /// Display server to open the window on: auto, wayland or x11 (Linux and BSD only)
+    #[arg(long, value_name = "BACKEND", value_parser = parse_window_backend)]
+    window_backend: Option<WindowBackend>,
+    /// Let the desktop show through where nothing is drawn (needs a compositor)
+    #[arg(long)]
+    transparent: bool,
+    /// Keep the window above all others
+    #[arg(long)]
+    always_on_top: bool,
+    /// Open the window without a title bar and borders
+    #[arg(long)]
+    no_decorations: bool,
+    /// GPU to render with, an index into the device list or part of its name
+    #[arg(long, value_name = "INDEX|NAME")]
+    gpu: Option<String>,
+    /// Don't enable the Vulkan validation layers
+    #[arg(long)]
+    no_validation: bool,
+    /// OBJ mesh to load into the scene
+    #[arg(long, value_name = "PATH")]
+    scene: Option<PathBuf>,
+    /// Keep the window hidden and skip the editor
+    #[arg(long)]
+    headless: bool,
+    /// Render this many frames headless without vsync, then print frame time percentiles, draw stats and memory usage
+    #[arg(long, value_name = "FRAMES", value_parser = clap::value_parser!(u32).range(1..))]
+    benchmark: Option<u32>,
+    /// Render at most this many frames per second
+    #[arg(long, value_name = "FPS")]
+    fps_cap: Option<f32>,
+    /// Frame rate cap while the window is in the background
+    #[arg(long, value_name = "FPS")]
+    background_fps_cap: Option<f32>,
+    /// Render every n-th refresh of the monitor, 2 is half rate (72 fps at 144 Hz). Ignored with --fps-cap.
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    refresh_divisor: Option<u32>,
+    /// Keep the scene at this aspect ratio, like 16:9 or 1.6, with bars filling the rest of the window
+    #[arg(long, value_name = "W:H", value_parser = parse_aspect, conflicts_with = "virtual_resolution")]
+    aspect: Option<f32>,
+    /// Render the scene at this resolution, like 320x180, scaled to fit the window with bars around it
+    #[arg(long, value_name = "WxH", value_parser = parse_resolution)]
+    virtual_resolution: Option<vk::Extent2D>,
+    /// Global illumination from the light probe grid: off, baked, low or high (updated every frame)
+    #[arg(long, value_name = "TIER", value_parser = parse_gi_quality)]
+    gi: Option<GiQuality>,
+    /// Variable rate shading of the scene: off, draw (coarser everywhere) or image (coarser at the edges and in motion)
+    #[arg(long, value_name = "MODE", value_parser = parse_shading_rate)]
+    vrs: Option<ShadingRateMode>,
+    /// Fetch scene vertices from a storage buffer by index instead of vertex input (needs GPU culling)
+    #[arg(long)]
+    vertex_pulling: bool,
+    /// Parallax of height mapped materials: off, offset, or occlusion with optional step counts like occlusion:8:32
+    #[arg(long, value_name = "QUALITY", value_parser = parse_parallax)]
+    parallax: Option<ParallaxQuality>,
+    /// Video memory streamed textures may take together, in MiB
+    #[arg(long, value_name = "MIB")]
+    texture_budget: Option<u64>,
+    /// Host a game for others to join on this UDP port
+    #[arg(long, value_name = "PORT", conflicts_with = "connect")]
+    host: Option<u16>,
+    /// Join the game hosted at this address, like 192.168.0.2:7777
+    #[arg(long, value_name = "ADDRESS")]
+    connect: Option<String>,
+    /// Run the simulation reproducibly from this random seed
+    #[arg(long, value_name = "SEED")]
+    deterministic: Option<u64>,
+    /// Record the mouse and keyboard input of the scene to this file
+    #[arg(long, value_name = "PATH", conflicts_with = "replay_input")]
+    record_input: Option<PathBuf>,
+    /// Play back an input recording instead of the mouse and keyboard, with its seed if it was recorded deterministic
+    #[arg(long, value_name = "PATH")]
+    replay_input: Option<PathBuf>,
+    /// Language of the user interface, like en or de-AT, instead of the system's
+    #[arg(long, value_name = "ID")]
+    language: Option<String>,
+}
+
+impl Cli {
+    // Only what was given on the command line replaces the current value
+    pub fn apply(self, settings: &mut Settings) {
+        if let Some(width) = self.width {
+            settings.width = width;
+        }
+        if let Some(height) = self.height {
+            settings.height = height;
+        }
+        if self.exclusive_fullscreen {
+            settings.fullscreen = FullscreenMode::Exclusive { monitor: self.monitor, video_mode: self.video_mode };
+        } else if self.fullscreen {
+            settings.fullscreen = FullscreenMode::Borderless { monitor: self.monitor };
+        }
+        settings.list_monitors |= self.list_monitors;
+        if let Some(backend) = self.window_backend {
+            settings.window_backend = backend;
+        }
+        settings.window_style.transparent |= self.transparent;
+        settings.window_style.always_on_top |= self.always_on_top;
+        settings.window_style.decorations &= !self.no_decorations;
+        settings.headless |= self.headless;
+        settings.renderer.validation &= !self.no_validation;
+        if self.gpu.is_some() {
+            settings.renderer.gpu = self.gpu;
+        }
+        if self.scene.is_some() {
+            settings.scene = self.scene;
+        }
+        if self.fps_cap.is_some() {
+            settings.fps_cap = self.fps_cap;
+        }
+        if self.background_fps_cap.is_some() {
+            settings.background_fps_cap = self.background_fps_cap;
+        }
+        if self.refresh_divisor.is_some() {
+            settings.refresh_divisor = self.refresh_divisor;
+        }
+        if let Some(aspect) = self.aspect {
+            settings.viewport = ViewportMode::FixedAspect(aspect);
+        }
+        if let Some(resolution) = self.virtual_resolution {
+            settings.viewport = ViewportMode::VirtualResolution(resolution);
+        }
+        if let Some(gi) = self.gi {
+            settings.renderer.gi = gi;
+        }
+        if let Some(vrs) = self.vrs {
+            settings.renderer.shading_rate = vrs;
+        }
+        settings.renderer.vertex_pulling |= self.vertex_pulling;
+        if let Some(parallax) = self.parallax {
+            settings.renderer.parallax = parallax;
+        }
+        if let Some(budget) = self.texture_budget {
+            settings.renderer.texture_streaming.budget = budget << 20;
+        }
+        if let Some(port) = self.host {
+            settings.network = Some(NetworkRole::Host(port));
+        }
+        if let Some(addr) = self.connect {
+            settings.network = Some(NetworkRole::Connect(addr));
+        }
+        if self.deterministic.is_some() {
+            settings.deterministic = self.deterministic;
+        }
+        if self.record_input.is_some() {
+            settings.record_input = self.record_input;
+        }
+        if self.replay_input.is_some() {
+            settings.replay_input = self.replay_input;
+        }
+        if self.language.is_some() {
+            settings.language = self.language;
+        }
+        if let Some(frames) = self.benchmark {
+            settings.benchmark = Some(frames);
+            settings.headless = true;
+            settings.renderer.vsync = false;
+        }
+    }
+}
+
+// "16:9" or a plain ratio like "1.78"
+fn parse_aspect(value: &str) -> Result<f32, String> {
+    let aspect = match value.split_once(':') {
+        Some((width, height)) => {
+            let width = width.trim().parse::<f32>().map_err(|error| error.to_string())?;
+            let height = height.trim().parse::<f32>().map_err(|error| error.to_string())?;
+            width / height
+        }
+        None => value.trim().parse::<f32>().map_err(|error| error.to_string())?
+    };
+
+    match aspect.is_finite() && aspect > 0.0 {
+        true => Ok(aspect),
+        false => Err(format!("{} isn't a positive aspect ratio", value))
+    }
+}
+
+// "320x180"
+fn parse_resolution(value: &str) -> Result<vk::Extent2D, String> {
+    let (width, height) = value.split_once(['x', 'X'])
+        .ok_or_else(|| format!("{} isn't a resolution like 320x180", value))?;
+    let width = width.trim().parse::<u32>().map_err(|error| error.to_string())?;
+    let height = height.trim().parse::<u32>().map_err(|error| error.to_string())?;
+
+    match width > 0 && height > 0 {
+        true => Ok(vk::Extent2D { width, height }),
+        false => Err(format!("{} has a zero size", value))
+    }
+}
+
+fn parse_window_backend(value: &str) -> Result<WindowBackend, String> {
+    match value.to_lowercase().as_str() {
+        "auto" => Ok(WindowBackend::Auto),
+        "wayland" => Ok(WindowBackend::Wayland),
+        "x11" => Ok(WindowBackend::X11),
+        _ => Err(format!("{} isn't one of auto, wayland or x11", value))
+    }
+}
+
+fn parse_gi_quality(value: &str) -> Result<GiQuality, String> {
+    match value.to_lowercase().as_str() {
+        "off" => Ok(GiQuality::Off),
+        "baked" => Ok(GiQuality::Baked),
+        "low" => Ok(GiQuality::Low),
+        "high" => Ok(GiQuality::High),
+        _ => Err(format!("{} isn't one of off, baked, low or high", value))
+    }
+}
+
+fn parse_shading_rate(value: &str) -> Result<ShadingRateMode, String> {
+    match value.to_lowercase().as_str() {
+        "off" => Ok(ShadingRateMode::Off),
+        "draw" => Ok(ShadingRateMode::PerDraw),
+        "image" => Ok(ShadingRateMode::Image),
+        _ => Err(format!("{} isn't one of off, draw or image", value))
+    }
+}
+
+// Steps head on and at grazing angles, the defaults when left out
+fn parse_parallax(value: &str) -> Result<ParallaxQuality, String> {
+    let lower = value.to_lowercase();
+    let mut parts = lower.split(':');
+    match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some("off"), None, None, None) => Ok(ParallaxQuality::Off),
+        (Some("offset"), None, None, None) => Ok(ParallaxQuality::Offset),
+        (Some("occlusion"), None, None, None) => Ok(ParallaxQuality::default()),
+        (Some("occlusion"), Some(min_steps), Some(max_steps), None) => {
+            let parse = |steps: &str| steps.parse::<u32>().map_err(|error| format!("{}: {}", steps, error));
+            Ok(ParallaxQuality::Occlusion { min_steps: parse(min_steps)?, max_steps: parse(max_steps)? })
+        },
+        _ => Err(format!("{} isn't one of off, offset, occlusion or occlusion:MIN:MAX", value))
+    }
+}
\ No newline at end of file
@@ -0,0 +1,323 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::vulkan::game_object::Transform3DComponent;
+
+// Fixed timestep clock for the simulation. Rendering runs every frame regardless, the clock decides how many fixed ticks
+// each frame should run: none while paused (unless a single step was requested), more when the time scale speeds it up.
+//
+// In deterministic mode the simulation has to come out bit for bit the same from the same seed and inputs, on the same
+// build and platform. That's what lockstep networking and replay verification rely on. The wall clock then only paces
+// how many ticks run per frame, the simulation itself only sees `fixed_delta`, `tick`, and random numbers from `rng`.
+// Systems have to run in a fixed order too, see `Schedule::set_ordered`, and iterate nothing in hash order.
+pub struct SimulationClock {
+    // Simulated seconds per tick
+    pub fixed_delta: f32,
+    // Multiplies the real time fed to the accumulator, 1.0 is real time
+    pub time_scale: f32,
+    // Ticks run by a single frame at most, so a long hitch doesn't snowball into ever longer frames
+    pub max_ticks_per_frame: u32,
+    paused: bool,
+    pending_steps: u32,
+    accumulator: f32,
+    tick: u64,
+    seed: u64,
+    deterministic: bool,
+    // Ticks past this aren't run, lockstep holds the simulation here until every peer's input for the next tick arrived
+    tick_limit: Option<u64>,
+}
+
+impl SimulationClock {
+    pub fn new(ticks_per_second: f32) -> Self {
+        Self {
+            fixed_delta: 1.0 / ticks_per_second,
+            time_scale: 1.0,
+            max_ticks_per_frame: 8,
+            paused: false,
+            pending_steps: 0,
+            accumulator: 0.0,
+            tick: 0,
+            // Different on every run until a seed is fixed
+            seed: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_nanos() as u64),
+            deterministic: false,
+            tick_limit: None
+        }
+    }
+
+    // Fixes the seed of `rng` and starts over at tick 0, so the run can be reproduced from here
+    pub fn set_deterministic(&mut self, seed: u64) {
+        self.deterministic = true;
+        self.seed = seed;
+        self.tick = 0;
+        self.accumulator = 0.0;
+        self.pending_steps = 0;
+    }
+
+    pub fn is_deterministic(&self) -> bool {
+        self.deterministic
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    // `None` runs freely
+    pub fn set_tick_limit(&mut self, tick_limit: Option<u64>) {
+        self.tick_limit = tick_limit;
+    }
+
+    // Random numbers for the current tick. The same seed, tick and stream always give the same numbers, however many
+    // other streams were drawn from before, so systems running in parallel each take their own stream.
+    pub fn rng(&self, stream: &str) -> SimulationRng {
+        let mut hash = StateHash::new();
+        hash.write_u64(self.seed);
+        hash.write_u64(self.tick);
+        hash.write(stream.as_bytes());
+        SimulationRng::new(hash.finish())
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+        // Time spent paused must not be caught up on afterwards
+        self.accumulator = 0.0;
+    }
+
+    pub fn toggle_paused(&mut self) {
+        self.set_paused(!self.paused);
+    }
+
+    // Runs exactly one tick on the next frame, only while paused
+    pub fn step(&mut self) {
+        if self.paused {
+            self.pending_steps += 1;
+        }
+    }
+
+    pub fn set_time_scale(&mut self, time_scale: f32) {
+        self.time_scale = time_scale.max(0.0);
+    }
+
+    // Ticks run so far
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    // Simulated seconds so far
+    pub fn time(&self) -> f64 {
+        self.tick as f64 * self.fixed_delta as f64
+    }
+
+    // How many fixed ticks to run for a frame that took `frame_delta` seconds
+    pub fn advance(&mut self, frame_delta: f32) -> u32 {
+        let ticks = match self.paused {
+            true => std::mem::take(&mut self.pending_steps),
+            false => {
+                self.accumulator += frame_delta * self.time_scale;
+                let ticks = (self.accumulator / self.fixed_delta) as u32;
+                self.accumulator -= ticks as f32 * self.fixed_delta;
+                if ticks > self.max_ticks_per_frame {
+                    self.accumulator = 0.0;
+                }
+                ticks
+            }
+        }.min(self.max_ticks_per_frame);
+
+        // Held back ticks stay in the accumulator and run once the limit moves on
+        let ticks = match self.tick_limit {
+            Some(limit) => {
+                let allowed = ticks.min(limit.saturating_sub(self.tick) as u32);
+                match self.paused {
+                    true => self.pending_steps += ticks - allowed,
+                    false => self.accumulator += (ticks - allowed) as f32 * self.fixed_delta
+                }
+                allowed
+            }
+            None => ticks
+        };
+
+        self.tick += ticks as u64;
+        ticks
+    }
+
+    // Fraction of a tick the accumulator holds, for interpolating between the last two simulation states
+    pub fn alpha(&self) -> f32 {
+        self.accumulator / self.fixed_delta
+    }
+}
+
+// SplitMix64, small and fast with the same sequence on every platform. Not for anything that has to be unpredictable.
+#[derive(Clone, Debug)]
+pub struct SimulationRng {
+    state: u64,
+}
+
+impl SimulationRng {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    // In [0, 1)
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    // In [min, max)
+    pub fn range(&mut self, min: f32, max: f32) -> f32 {
+        min + (max - min) * self.next_f32()
+    }
+
+    // In [0, count), `count` must not be 0
+    pub fn index(&mut self, count: usize) -> usize {
+        ((self.next_u64() as u128 * count as u128) >> 64) as usize
+    }
+
+    pub fn chance(&mut self, probability: f32) -> bool {
+        self.next_f32() < probability
+    }
+}
+
+// FNV-1a over the exact bits of the simulation state. Two runs that hash the same each tick didn't diverge, lockstep
+// peers and replays compare these to find the first tick that did.
+#[derive(Clone, Debug)]
+pub struct StateHash {
+    hash: u64,
+}
+
+impl Default for StateHash {
+    fn default() -> Self {
+        Self { hash: 0xcbf2_9ce4_8422_2325 }
+    }
+}
+
+impl StateHash {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.hash ^= byte as u64;
+            self.hash = self.hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        self.write(&value.to_le_bytes());
+    }
+
+    // -0.0 and 0.0 or differing NaNs hash differently, they can lead to different results later
+    pub fn write_f32(&mut self, value: f32) {
+        self.write(&value.to_bits().to_le_bytes());
+    }
+
+    pub fn write_vec3(&mut self, value: uv::Vec3) {
+        self.write_f32(value.x);
+        self.write_f32(value.y);
+        self.write_f32(value.z);
+    }
+
+    pub fn write_transform(&mut self, transform: &Transform3DComponent) {
+        self.write_vec3(transform.translation);
+        let rotor = transform.rotation;
+        for value in [rotor.s, rotor.bv.xy, rotor.bv.xz, rotor.bv.yz] {
+            self.write_f32(value);
+        }
+        self.write_vec3(transform.scale);
+    }
+
+    pub fn finish(&self) -> u64 {
+        self.hash
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Frame times in 1/256ths of a second and 64 ticks per second, four units a tick, keep the sums exact
+    const UNIT: f32 = 1.0 / 256.0;
+
+    fn ticks(clock: &mut SimulationClock, frames: &[u32]) -> Vec<u32> {
+        frames.iter().map(|&units| clock.advance(units as f32 * UNIT)).collect()
+    }
+
+    #[test]
+    fn uneven_frames_carry_the_remainder() {
+        let mut clock = SimulationClock::new(64.0);
+        assert_eq!(ticks(&mut clock, &[3, 7, 1, 12, 5]), vec![0, 2, 0, 3, 2]);
+        assert_eq!((clock.tick(), clock.alpha()), (7, 0.0));
+        assert_eq!(ticks(&mut clock, &[6]), vec![1]);
+        assert_eq!(clock.alpha(), 0.5);
+    }
+
+    #[test]
+    fn long_frames_are_clamped_without_catching_up() {
+        let mut clock = SimulationClock::new(64.0);
+        // Exactly the most a frame may run keeps the remainder
+        assert_eq!(ticks(&mut clock, &[4 * 8 + 2]), vec![8]);
+        assert_eq!(clock.alpha(), 0.5);
+        // A hitch of a second runs 8 ticks and drops the rest instead of spiralling
+        assert_eq!(ticks(&mut clock, &[256, 4, 4]), vec![8, 1, 1]);
+        assert_eq!((clock.tick(), clock.alpha()), (18, 0.0));
+    }
+
+    #[test]
+    fn time_scale_and_pausing() {
+        let mut clock = SimulationClock::new(64.0);
+        clock.set_time_scale(2.0);
+        assert_eq!(ticks(&mut clock, &[4, 5]), vec![2, 2]);
+        assert_eq!(clock.alpha(), 0.5);
+
+        clock.set_paused(true);
+        clock.step();
+        clock.step();
+        assert_eq!(ticks(&mut clock, &[100, 100]), vec![2, 0]);
+        // Paused time isn't caught up on, the half tick left before the pause is gone too
+        clock.set_paused(false);
+        assert_eq!(ticks(&mut clock, &[1, 1]), vec![0, 1]);
+        assert_eq!(clock.tick(), 7);
+    }
+
+    #[test]
+    fn tick_limit_holds_ticks_back() {
+        let mut clock = SimulationClock::new(64.0);
+        clock.set_tick_limit(Some(3));
+        assert_eq!(ticks(&mut clock, &[20, 4]), vec![3, 0]);
+        clock.set_tick_limit(Some(10));
+        assert_eq!(ticks(&mut clock, &[0]), vec![3]);
+        clock.set_tick_limit(None);
+        assert_eq!(ticks(&mut clock, &[0]), vec![0]);
+    }
+
+    #[test]
+    fn rng_depends_on_seed_tick_and_stream() {
+        let mut clock = SimulationClock::new(64.0);
+        clock.set_deterministic(7);
+        let first = clock.rng("spawns").next_u64();
+        assert_eq!(clock.rng("spawns").next_u64(), first);
+        assert_ne!(clock.rng("weather").next_u64(), first);
+        clock.advance(4.0 * UNIT);
+        assert_ne!(clock.rng("spawns").next_u64(), first);
+
+        let mut rng = SimulationRng::new(1);
+        for _ in 0..1000 {
+            assert!((0.0..1.0).contains(&rng.next_f32()));
+            assert!(rng.index(3) < 3);
+        }
+    }
+}
\ No newline at end of file
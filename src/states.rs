@@ -0,0 +1,388 @@
+use crate::input::InputEvent;
+
+// High level states of the app like the main menu, gameplay and the pause screen, kept on a stack. Pushing a pause
+// screen over gameplay leaves gameplay as it was and popping it continues where it stopped. `C` is what the states
+// work on, passed to every hook.
+//
+// States return a `Transition` from the hooks that react to something, applied by the stack once the hook returned.
+// A transition acts on the state that returned it: a state under an overlay that pops or switches takes the overlays
+// above it along. When several states return one in the same hook, the higher state's goes first.
+
+pub enum Transition<C> {
+    None,
+    // Covers the current state with another one
+    Push(Box<dyn GameState<C>>),
+    // Removes the current state and the ones above it
+    Pop,
+    // Replaces the current state and the ones above it
+    Switch(Box<dyn GameState<C>>),
+    // Replaces every state, like going back to the main menu from gameplay under a pause screen
+    Reset(Box<dyn GameState<C>>),
+    Quit,
+}
+
+// Who gets input the top state doesn't use
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InputCapture {
+    // Nothing below gets input, the scene included
+    All,
+    // Everything the state didn't use goes on to the state below, and to the scene under the bottom state
+    PassThrough,
+}
+
+pub trait GameState<C> {
+    fn name(&self) -> &str;
+
+    // When it's added to the stack and when it leaves it
+    fn enter(&mut self, _context: &mut C) {}
+    fn exit(&mut self, _context: &mut C) {}
+
+    // When a state is pushed over it and when it's on top again
+    fn cover(&mut self, _context: &mut C) {}
+    fn uncover(&mut self, _context: &mut C) {}
+
+    // True when it used the event
+    fn handle_input(&mut self, _context: &mut C, _event: &InputEvent) -> (bool, Transition<C>) {
+        (false, Transition::None)
+    }
+
+    // Once per frame with the frame's duration in seconds
+    fn update(&mut self, _context: &mut C, _delta: f32) -> Transition<C> {
+        Transition::None
+    }
+
+    // Once per frame after `update`, bottom to top so the top state draws over the rest
+    fn draw(&mut self, _context: &mut C, _ui: &egui::Context) -> Transition<C> {
+        Transition::None
+    }
+
+    fn input_capture(&self) -> InputCapture {
+        InputCapture::All
+    }
+
+    // Whether the states below keep updating while this one covers them, like a HUD over gameplay
+    fn updates_below(&self) -> bool {
+        false
+    }
+
+    // Whether the states below are still drawn, false for screens that cover everything
+    fn draws_below(&self) -> bool {
+        true
+    }
+}
+
+pub struct StateStack<C> {
+    states: Vec<Box<dyn GameState<C>>>,
+    quit: bool,
+}
+
+impl<C> Default for StateStack<C> {
+    fn default() -> Self {
+        Self {
+            states: vec![],
+            quit: false
+        }
+    }
+}
+
+impl<C> StateStack<C> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, mut state: Box<dyn GameState<C>>, context: &mut C) {
+        if let Some(top) = self.states.last_mut() {
+            top.cover(context);
+        }
+        tracing::debug!("Entering the {} state", state.name());
+        state.enter(context);
+        self.states.push(state);
+    }
+
+    pub fn pop(&mut self, context: &mut C) {
+        if let Some(mut state) = self.states.pop() {
+            tracing::debug!("Leaving the {} state", state.name());
+            state.exit(context);
+        }
+        match self.states.last_mut() {
+            Some(top) => top.uncover(context),
+            None => self.quit = true
+        }
+    }
+
+    pub fn switch(&mut self, mut state: Box<dyn GameState<C>>, context: &mut C) {
+        if let Some(mut top) = self.states.pop() {
+            tracing::debug!("Leaving the {} state", top.name());
+            top.exit(context);
+        }
+        tracing::debug!("Entering the {} state", state.name());
+        state.enter(context);
+        self.states.push(state);
+    }
+
+    // Exits every state, the top first
+    pub fn clear(&mut self, context: &mut C) {
+        while let Some(mut state) = self.states.pop() {
+            tracing::debug!("Leaving the {} state", state.name());
+            state.exit(context);
+        }
+    }
+
+    // Applies a transition as if the top state returned it
+    pub fn apply(&mut self, transition: Transition<C>, context: &mut C) {
+        self.apply_at(self.states.len().saturating_sub(1), transition, context);
+    }
+
+    // A transition returned by the state at `index`. States above it leave first when it pops or switches.
+    fn apply_at(&mut self, index: usize, transition: Transition<C>, context: &mut C) {
+        if matches!(transition, Transition::Pop | Transition::Switch(_)) {
+            // They leave without the state below being uncovered in between
+            while self.states.len() > index + 1 {
+                let mut state = self.states.pop().unwrap();
+                tracing::debug!("Leaving the {} state", state.name());
+                state.exit(context);
+            }
+        }
+        match transition {
+            Transition::None => {}
+            Transition::Push(state) => self.push(state, context),
+            Transition::Pop => self.pop(context),
+            Transition::Switch(state) => self.switch(state, context),
+            Transition::Reset(state) => {
+                self.clear(context);
+                self.push(state, context);
+            }
+            Transition::Quit => self.quit = true
+        }
+    }
+
+    // Transitions by the index of the state that returned them, the highest first. Ones from states an earlier
+    // transition removed, and any after a reset, are dropped.
+    fn apply_all(&mut self, mut transitions: Vec<(usize, Transition<C>)>, context: &mut C) {
+        transitions.sort_by(|(a, _), (b, _)| b.cmp(a));
+        for (index, transition) in transitions {
+            if index >= self.states.len() {
+                continue;
+            }
+            let reset = matches!(transition, Transition::Reset(_));
+            self.apply_at(index, transition, context);
+            if reset {
+                break;
+            }
+        }
+    }
+
+    // Offers the event to the states from the top down. False when it's left for the scene: no state used it and none
+    // captures input.
+    pub fn handle_input(&mut self, context: &mut C, event: &InputEvent) -> bool {
+        let mut transitions = vec![];
+        let mut used = false;
+        for (index, state) in self.states.iter_mut().enumerate().rev() {
+            let (handled, transition) = state.handle_input(context, event);
+            transitions.push((index, transition));
+            if handled || state.input_capture() == InputCapture::All {
+                used = true;
+                break;
+            }
+        }
+        self.apply_all(transitions, context);
+        used
+    }
+
+    // Updates the top state and those it lets update below it
+    pub fn update(&mut self, context: &mut C, delta: f32) {
+        let mut transitions = vec![];
+        for (index, state) in self.states.iter_mut().enumerate().rev() {
+            transitions.push((index, state.update(context, delta)));
+            if !state.updates_below() {
+                break;
+            }
+        }
+        self.apply_all(transitions, context);
+    }
+
+    // Draws from the lowest state still visible up to the top
+    pub fn draw(&mut self, context: &mut C, ui: &egui::Context) {
+        let lowest = self.states.iter().rposition(|state| !state.draws_below()).unwrap_or(0);
+        let mut transitions = vec![];
+        for (index, state) in self.states.iter_mut().enumerate().skip(lowest) {
+            transitions.push((index, state.draw(context, ui)));
+        }
+        self.apply_all(transitions, context);
+    }
+
+    pub fn top(&self) -> Option<&str> {
+        self.states.last().map(|state| state.name())
+    }
+
+    pub fn len(&self) -> usize {
+        self.states.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.states.is_empty()
+    }
+
+    // Set by a `Transition::Quit`, or once the last state was popped
+    pub fn should_quit(&self) -> bool {
+        self.quit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // What the states did, and the transitions they return next by name
+    #[derive(Default)]
+    struct Script {
+        log: Vec<String>,
+        next: Vec<(&'static str, Transition<Script>)>,
+    }
+
+    impl Script {
+        fn take(&mut self, name: &str) -> Transition<Script> {
+            match self.next.iter().position(|(state, _)| *state == name) {
+                Some(index) => self.next.remove(index).1,
+                None => Transition::None
+            }
+        }
+    }
+
+    struct State {
+        name: &'static str,
+        overlay: bool,
+    }
+
+    fn state(name: &'static str) -> Box<dyn GameState<Script>> {
+        Box::new(State { name, overlay: false })
+    }
+
+    // Lets input and updates through to the states below
+    fn overlay(name: &'static str) -> Box<dyn GameState<Script>> {
+        Box::new(State { name, overlay: true })
+    }
+
+    impl GameState<Script> for State {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        fn enter(&mut self, script: &mut Script) {
+            script.log.push(format!("enter {}", self.name));
+        }
+
+        fn exit(&mut self, script: &mut Script) {
+            script.log.push(format!("exit {}", self.name));
+        }
+
+        fn cover(&mut self, script: &mut Script) {
+            script.log.push(format!("cover {}", self.name));
+        }
+
+        fn uncover(&mut self, script: &mut Script) {
+            script.log.push(format!("uncover {}", self.name));
+        }
+
+        fn handle_input(&mut self, script: &mut Script, _event: &InputEvent) -> (bool, Transition<Script>) {
+            (false, script.take(self.name))
+        }
+
+        fn update(&mut self, script: &mut Script, _delta: f32) -> Transition<Script> {
+            script.log.push(format!("update {}", self.name));
+            script.take(self.name)
+        }
+
+        fn draw(&mut self, script: &mut Script, _ui: &egui::Context) -> Transition<Script> {
+            script.take(self.name)
+        }
+
+        fn input_capture(&self) -> InputCapture {
+            match self.overlay {
+                true => InputCapture::PassThrough,
+                false => InputCapture::All
+            }
+        }
+
+        fn updates_below(&self) -> bool {
+            self.overlay
+        }
+    }
+
+    fn names(states: &StateStack<Script>) -> Vec<&str> {
+        states.states.iter().map(|state| state.name()).collect()
+    }
+
+    #[test]
+    fn push_and_pop() {
+        let (mut states, mut script) = (StateStack::new(), Script::default());
+        states.push(state("game"), &mut script);
+        script.next.push(("game", Transition::Push(state("pause"))));
+        states.update(&mut script, 0.0);
+        assert_eq!(names(&states), ["game", "pause"]);
+
+        script.next.push(("pause", Transition::Pop));
+        states.update(&mut script, 0.0);
+        assert_eq!(names(&states), ["game"]);
+        assert_eq!(script.log, ["enter game", "update game", "cover game", "enter pause", "update pause", "exit pause", "uncover game"]);
+
+        states.apply(Transition::Pop, &mut script);
+        assert!(states.is_empty() && states.should_quit());
+    }
+
+    #[test]
+    fn switch_replaces_the_top() {
+        let (mut states, mut script) = (StateStack::new(), Script::default());
+        states.push(state("menu"), &mut script);
+        states.push(state("options"), &mut script);
+        script.log.clear();
+        script.next.push(("options", Transition::Switch(state("credits"))));
+        states.update(&mut script, 0.0);
+        assert_eq!(names(&states), ["menu", "credits"]);
+        assert_eq!(script.log, ["update options", "exit options", "enter credits"]);
+
+        states.apply(Transition::Reset(state("title")), &mut script);
+        assert_eq!(names(&states), ["title"]);
+        assert!(!states.should_quit());
+    }
+
+    #[test]
+    fn transitions_act_on_the_state_that_returned_them() {
+        let (mut states, mut script) = (StateStack::new(), Script::default());
+        states.push(state("menu"), &mut script);
+        states.push(state("game"), &mut script);
+        states.push(overlay("hud"), &mut script);
+        script.log.clear();
+
+        // The game under its HUD ends, the HUD goes with it and the menu is uncovered once
+        script.next.push(("game", Transition::Pop));
+        states.update(&mut script, 0.0);
+        assert_eq!(names(&states), ["menu"]);
+        assert_eq!(script.log, ["update hud", "update game", "exit hud", "exit game", "uncover menu"]);
+
+        states.push(state("game"), &mut script);
+        states.push(overlay("hud"), &mut script);
+        script.next.push(("game", Transition::Switch(state("results"))));
+        assert!(states.handle_input(&mut script, &InputEvent::Scroll { x: 0.0, y: 1.0 }));
+        assert_eq!(names(&states), ["menu", "results"]);
+    }
+
+    #[test]
+    fn the_higher_transition_goes_first() {
+        let (mut states, mut script) = (StateStack::new(), Script::default());
+        states.push(state("game"), &mut script);
+        states.push(overlay("hud"), &mut script);
+        script.next.push(("game", Transition::Switch(state("results"))));
+        script.next.push(("hud", Transition::Push(state("chat"))));
+        states.draw(&mut script, &egui::Context::default());
+        // The chat was pushed over the HUD, then the game's switch replaced all three
+        assert_eq!(names(&states), ["results"]);
+
+        // Nothing below a reset acts on the new stack
+        states.push(overlay("hud"), &mut script);
+        script.next.push(("results", Transition::Pop));
+        script.next.push(("hud", Transition::Reset(state("title"))));
+        states.update(&mut script, 0.0);
+        assert_eq!(names(&states), ["title"]);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,198 @@
+// Callbacks run after a delay or at an interval of simulated time. Update them once per simulation tick with the tick's
+// `fixed_delta`, so they pause and speed up with the clock and fire on the same tick in deterministic runs. Timers due
+// on the same update fire in the order they're due, ties in the order they were added.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TimerId(u64);
+
+struct Timer<C> {
+    id: TimerId,
+    // Simulated seconds since the timers were created
+    due: f64,
+    interval: Option<f64>,
+    callback: Box<dyn FnMut(&mut C)>,
+}
+
+// `C` is what the callbacks change, like the world or the part of it the simulation owns
+pub struct Timers<C> {
+    timers: Vec<Timer<C>>,
+    time: f64,
+    next_id: u64,
+}
+
+impl<C> Default for Timers<C> {
+    fn default() -> Self {
+        Self {
+            timers: vec![],
+            time: 0.0,
+            next_id: 0
+        }
+    }
+}
+
+impl<C> Timers<C> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Runs `callback` once, `delay` seconds from now
+    pub fn after(&mut self, delay: f32, callback: impl FnMut(&mut C) + 'static) -> TimerId {
+        self.add(delay as f64, None, Box::new(callback))
+    }
+
+    // Runs `callback` every `interval` seconds, the first time one interval from now. An update longer than the interval
+    // runs it as often as it would have run in between.
+    pub fn every(&mut self, interval: f32, callback: impl FnMut(&mut C) + 'static) -> TimerId {
+        // Shorter intervals would fire forever within one update
+        let interval = (interval as f64).max(f64::from(f32::EPSILON));
+        self.add(interval, Some(interval), Box::new(callback))
+    }
+
+    fn add(&mut self, delay: f64, interval: Option<f64>, callback: Box<dyn FnMut(&mut C)>) -> TimerId {
+        let id = TimerId(self.next_id);
+        self.next_id += 1;
+        self.timers.push(Timer { id, due: self.time + delay.max(0.0), interval, callback });
+        id
+    }
+
+    // False when it already fired or was cancelled
+    pub fn cancel(&mut self, id: TimerId) -> bool {
+        let count = self.timers.len();
+        self.timers.retain(|timer| timer.id != id);
+        self.timers.len() != count
+    }
+
+    pub fn clear(&mut self) {
+        self.timers.clear();
+    }
+
+    pub fn is_active(&self, id: TimerId) -> bool {
+        self.timers.iter().any(|timer| timer.id == id)
+    }
+
+    // Seconds until it fires next
+    pub fn remaining(&self, id: TimerId) -> Option<f32> {
+        self.timers.iter().find(|timer| timer.id == id).map(|timer| (timer.due - self.time) as f32)
+    }
+
+    pub fn len(&self) -> usize {
+        self.timers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.timers.is_empty()
+    }
+
+    // Simulated seconds the timers were updated for
+    pub fn time(&self) -> f64 {
+        self.time
+    }
+
+    // Advances by `delta` seconds and fires what came due
+    pub fn update(&mut self, delta: f32, context: &mut C) {
+        self.time += delta.max(0.0) as f64;
+        loop {
+            let next = self.timers
+                .iter()
+                .enumerate()
+                .filter(|(_, timer)| timer.due <= self.time)
+                .min_by(|(_, a), (_, b)| a.due.total_cmp(&b.due).then(a.id.cmp(&b.id)))
+                .map(|(index, _)| index);
+            let index = match next {
+                Some(index) => index,
+                None => break
+            };
+
+            let timer = &mut self.timers[index];
+            (timer.callback)(context);
+            match timer.interval {
+                Some(interval) => timer.due += interval,
+                None => {
+                    self.timers.remove(index);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn log(name: &'static str) -> impl FnMut(&mut Vec<&'static str>) {
+        move |fired: &mut Vec<&'static str>| fired.push(name)
+    }
+
+    #[test]
+    fn after_fires_once_when_due() {
+        let mut timers = Timers::new();
+        let mut fired = vec![];
+        let id = timers.after(0.5, log("once"));
+        timers.update(0.25, &mut fired);
+        assert!(fired.is_empty());
+        assert_eq!(timers.remaining(id), Some(0.25));
+
+        timers.update(0.25, &mut fired);
+        timers.update(1.0, &mut fired);
+        assert_eq!(fired, vec!["once"]);
+        assert!(!timers.is_active(id) && timers.is_empty());
+    }
+
+    #[test]
+    fn repeating_timers_catch_up_in_a_long_update() {
+        let mut timers = Timers::new();
+        let mut fired = vec![];
+        timers.every(0.25, log("tick"));
+        timers.update(1.125, &mut fired);
+        assert_eq!(fired.len(), 4);
+
+        // The fifth is due at 1.25
+        timers.update(0.0625, &mut fired);
+        assert_eq!(fired.len(), 4);
+        timers.update(0.0625, &mut fired);
+        assert_eq!(fired.len(), 5);
+    }
+
+    #[test]
+    fn timers_fire_in_the_order_they_are_due() {
+        let mut timers = Timers::new();
+        let mut fired = vec![];
+        timers.every(0.25, log("every"));
+        timers.after(0.625, log("late"));
+        timers.after(0.5, log("tied"));
+        timers.update(1.0, &mut fired);
+        // At 0.5 the repeating timer was added first
+        assert_eq!(fired, vec!["every", "every", "tied", "late", "every", "every"]);
+    }
+
+    #[test]
+    fn cancelled_timers_do_not_fire() {
+        let mut timers = Timers::new();
+        let mut fired = vec![];
+        let repeating = timers.every(0.25, log("every"));
+        let once = timers.after(0.5, log("once"));
+        timers.update(0.25, &mut fired);
+
+        assert!(timers.cancel(repeating));
+        assert!(!timers.cancel(repeating));
+        timers.update(1.0, &mut fired);
+        assert_eq!(fired, vec!["every", "once"]);
+        // Already fired
+        assert!(!timers.cancel(once));
+    }
+
+    #[test]
+    fn delays_count_from_the_last_update() {
+        let mut timers: Timers<Vec<&'static str>> = Timers::new();
+        let mut fired = vec![];
+        timers.after(0.25, log("first"));
+        timers.update(0.5, &mut fired);
+        assert_eq!(timers.time(), 0.5);
+        // Delays count from the time the timers were updated to
+        timers.after(0.25, log("second"));
+        timers.update(0.125, &mut fired);
+        assert_eq!(fired, vec!["first"]);
+        timers.update(0.125, &mut fired);
+        assert_eq!(fired, vec!["first", "second"]);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,108 @@
+use std::collections::HashMap;
+
+use winit::dpi::PhysicalPosition;
+use winit::event::{Touch, TouchPhase};
+
+use crate::vulkan::camera::Camera;
+use crate::vulkan::camera_controller::CameraController;
+
+// Pixels a finger may travel and still count as tapping in place
+const TAP_SLOP: f64 = 12.0;
+// Pinching to twice (or half) the finger distance dollies as far as this many mouse wheel lines
+const PINCH_LINES: f32 = 5.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Gesture {
+    // One finger moved, in pixels
+    Drag { dx: f32, dy: f32 },
+    // Two fingers moved, `amount` in mouse wheel lines (positive spreads them apart) and the movement of their midpoint
+    Pinch { amount: f32, dx: f32, dy: f32 },
+    // One finger lifted close to where it went down, alone the whole time
+    Tap { x: f32, y: f32 },
+}
+
+// Turns winit's touch events into gestures. Only the first two fingers down take part, any more are ignored until they lift.
+#[derive(Default)]
+pub struct TouchInput {
+    touches: HashMap<u64, PhysicalPosition<f64>>,
+    // Where the only finger down went down, cleared once it strays or a second one joins
+    tap_start: Option<PhysicalPosition<f64>>,
+}
+
+impl TouchInput {
+    pub fn handle(&mut self, touch: &Touch) -> Option<Gesture> {
+        match touch.phase {
+            TouchPhase::Started => {
+                if self.touches.len() >= 2 {
+                    return None;
+                }
+                self.touches.insert(touch.id, touch.location);
+                self.tap_start = match self.touches.len() {
+                    1 => Some(touch.location),
+                    _ => None
+                };
+                None
+            }
+            TouchPhase::Moved => {
+                let previous = self.touches.get(&touch.id).copied()?;
+                let (midpoint, distance) = self.span();
+                self.touches.insert(touch.id, touch.location);
+                if self.tap_start.is_some_and(|start| distance_between(start, touch.location) > TAP_SLOP) {
+                    self.tap_start = None;
+                }
+
+                match self.touches.len() {
+                    1 => Some(Gesture::Drag {
+                        dx: (touch.location.x - previous.x) as f32,
+                        dy: (touch.location.y - previous.y) as f32
+                    }),
+                    _ => {
+                        let (new_midpoint, new_distance) = self.span();
+                        Some(Gesture::Pinch {
+                            amount: (new_distance / distance.max(1.0)).log2() as f32 * PINCH_LINES,
+                            dx: (new_midpoint.x - midpoint.x) as f32,
+                            dy: (new_midpoint.y - midpoint.y) as f32
+                        })
+                    }
+                }
+            }
+            TouchPhase::Ended | TouchPhase::Cancelled => {
+                self.touches.remove(&touch.id)?;
+                let tap_start = self.tap_start.take();
+                match (touch.phase, tap_start) {
+                    (TouchPhase::Ended, Some(_)) => Some(Gesture::Tap { x: touch.location.x as f32, y: touch.location.y as f32 }),
+                    _ => None
+                }
+            }
+        }
+    }
+
+    // Midpoint of the fingers down and the distance between the first two
+    fn span(&self) -> (PhysicalPosition<f64>, f64) {
+        let mut positions = self.touches.values();
+        match (positions.next(), positions.next()) {
+            (Some(&a), Some(&b)) => (PhysicalPosition::new((a.x + b.x) * 0.5, (a.y + b.y) * 0.5), distance_between(a, b)),
+            (Some(&a), None) => (a, 0.0),
+            _ => (PhysicalPosition::new(0.0, 0.0), 0.0)
+        }
+    }
+}
+
+fn distance_between(a: PhysicalPosition<f64>, b: PhysicalPosition<f64>) -> f64 {
+    (a.x - b.x).hypot(a.y - b.y)
+}
+
+// Dragging orbits or looks around, pinching dollies and pans (or changes the fly speed). Taps are left to the caller,
+// they pick like a click.
+pub fn apply_gesture(controller: &mut CameraController, camera: &Camera, gesture: Gesture, viewport_height: f32) {
+    match (controller, gesture) {
+        (CameraController::Orbit(orbit), Gesture::Drag { dx, dy }) => orbit.rotate(dx, dy),
+        (CameraController::Orbit(orbit), Gesture::Pinch { amount, dx, dy }) => {
+            orbit.dolly(amount);
+            orbit.pan(camera, dx, dy, viewport_height);
+        }
+        (CameraController::Fly(fly), Gesture::Drag { dx, dy }) => fly.look(dx, dy),
+        (CameraController::Fly(fly), Gesture::Pinch { amount, .. }) => fly.speed = (fly.speed * (1.0 + amount * 0.1)).max(0.1),
+        (_, Gesture::Tap { .. }) => {}
+    }
+}
@@ -0,0 +1,191 @@
+use std::ffi::CStr;
+
+use ash::vk;
+
+use super::crash_diagnostics;
+use super::multiview::MultiviewSupport;
+use super::physical_device::PhysicalDevice;
+use super::portability::PortabilitySubset;
+use super::shading_rate::ShadingRateSupport;
+
+// PCI vendor ids, Khronos ids for the vendors without one
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Vendor {
+    Amd,
+    Nvidia,
+    Intel,
+    Arm,
+    Qualcomm,
+    ImgTec,
+    Apple,
+    Mesa,
+    Other(u32),
+}
+
+impl Vendor {
+    fn from_id(id: u32) -> Self {
+        match id {
+            0x1002 => Vendor::Amd,
+            0x10de => Vendor::Nvidia,
+            0x8086 => Vendor::Intel,
+            0x13b5 => Vendor::Arm,
+            0x5143 => Vendor::Qualcomm,
+            0x1010 => Vendor::ImgTec,
+            0x106b => Vendor::Apple,
+            0x10005 => Vendor::Mesa,
+            id => Vendor::Other(id)
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AdapterType {
+    Discrete,
+    Integrated,
+    Virtual,
+    Cpu,
+    Other,
+}
+
+// The limits settings usually have to stay within
+#[derive(Clone, Copy, Debug)]
+pub struct AdapterLimits {
+    pub max_texture_size: u32,
+    pub max_texture_layers: u32,
+    pub max_anisotropy: f32,
+    // Highest sample count usable for both color and depth attachments
+    pub max_samples: u32,
+    pub max_color_attachments: u32,
+    pub max_push_constants_size: u32,
+    pub max_uniform_buffer_range: u32,
+    pub max_storage_buffer_range: u32,
+    pub max_compute_invocations: u32,
+    pub max_compute_group_count: [u32; 3],
+    // Nanoseconds per timestamp tick
+    pub timestamp_period: f32,
+    // Largest device local heap in bytes
+    pub device_local_memory: u64,
+}
+
+// Optional features the renderer can use or falls back from, whether the device has them
+#[derive(Clone, Copy, Debug)]
+pub struct AdapterFeatures {
+    // GPU culling and vertex pulling
+    pub multi_draw_indirect: bool,
+    pub indirect_count: bool,
+    pub fragment_shading_rate: bool,
+    pub shading_rate_image: bool,
+    // Point light shadow cubes in one pass
+    pub multiview: bool,
+    pub full_screen_exclusive: bool,
+    // Device lost reports that say which pass the GPU was in
+    pub crash_diagnostics: bool,
+    // A layered implementation like MoltenVK leaving parts of Vulkan out
+    pub portability_subset: bool,
+    pub sampler_anisotropy: bool,
+    pub texture_compression_bc: bool,
+    pub texture_compression_etc2: bool,
+    pub texture_compression_astc: bool,
+    pub geometry_shader: bool,
+    pub tessellation_shader: bool,
+}
+
+// What the GPU the renderer runs on is and can do, for settings menus, telemetry and bug reports
+#[derive(Clone, Debug)]
+pub struct AdapterInfo {
+    pub name: String,
+    pub vendor: Vendor,
+    pub vendor_id: u32,
+    pub device_id: u32,
+    pub adapter_type: AdapterType,
+    // Decoded the way the vendor's own tools show it
+    pub driver_version: String,
+    // Highest Vulkan version the device supports, major, minor and patch
+    pub api_version: (u32, u32, u32),
+    pub limits: AdapterLimits,
+    pub features: AdapterFeatures,
+    // Every device extension, enabled or not
+    pub extensions: Vec<String>,
+}
+
+impl AdapterInfo {
+    pub fn query(entry: &ash::Entry, instance: &ash::Instance, physical_device: vk::PhysicalDevice) -> Self {
+        let props = unsafe { instance.get_physical_device_properties(physical_device) };
+        let device_features = unsafe { instance.get_physical_device_features(physical_device) };
+        let memory = unsafe { instance.get_physical_device_memory_properties(physical_device) };
+        let extensions: Vec<String> = unsafe { instance.enumerate_device_extension_properties(physical_device).unwrap_or_default() }
+            .iter()
+            .map(|extension| unsafe { CStr::from_ptr(extension.extension_name.as_ptr()) }.to_string_lossy().into_owned())
+            .collect();
+        let vendor = Vendor::from_id(props.vendor_id);
+        let limits = &props.limits;
+        let samples = limits.framebuffer_color_sample_counts & limits.framebuffer_depth_sample_counts;
+        let shading_rate = ShadingRateSupport::query(instance, physical_device);
+
+        Self {
+            name: unsafe { CStr::from_ptr(props.device_name.as_ptr()) }.to_string_lossy().into_owned(),
+            vendor,
+            vendor_id: props.vendor_id,
+            device_id: props.device_id,
+            adapter_type: match props.device_type {
+                vk::PhysicalDeviceType::DISCRETE_GPU => AdapterType::Discrete,
+                vk::PhysicalDeviceType::INTEGRATED_GPU => AdapterType::Integrated,
+                vk::PhysicalDeviceType::VIRTUAL_GPU => AdapterType::Virtual,
+                vk::PhysicalDeviceType::CPU => AdapterType::Cpu,
+                _ => AdapterType::Other
+            },
+            driver_version: driver_version(vendor, props.driver_version),
+            api_version: (vk::api_version_major(props.api_version), vk::api_version_minor(props.api_version),
+                vk::api_version_patch(props.api_version)),
+            limits: AdapterLimits {
+                max_texture_size: limits.max_image_dimension2_d,
+                max_texture_layers: limits.max_image_array_layers,
+                max_anisotropy: limits.max_sampler_anisotropy,
+                max_samples: (0..7).rev().map(|bit| 1 << bit).find(|&count| samples.contains(vk::SampleCountFlags::from_raw(count))).unwrap_or(1),
+                max_color_attachments: limits.max_color_attachments,
+                max_push_constants_size: limits.max_push_constants_size,
+                max_uniform_buffer_range: limits.max_uniform_buffer_range,
+                max_storage_buffer_range: limits.max_storage_buffer_range,
+                max_compute_invocations: limits.max_compute_work_group_invocations,
+                max_compute_group_count: limits.max_compute_work_group_count,
+                timestamp_period: limits.timestamp_period,
+                device_local_memory: memory.memory_heaps[..memory.memory_heap_count as usize]
+                    .iter()
+                    .filter(|heap| heap.flags.contains(vk::MemoryHeapFlags::DEVICE_LOCAL))
+                    .map(|heap| heap.size)
+                    .max()
+                    .unwrap_or(0)
+            },
+            features: AdapterFeatures {
+                multi_draw_indirect: PhysicalDevice::supports_multi_draw_indirect(instance, physical_device),
+                indirect_count: PhysicalDevice::supports_indirect_count(instance, physical_device),
+                fragment_shading_rate: shading_rate.is_some(),
+                shading_rate_image: shading_rate.is_some_and(|support| support.attachment_texel_size.is_some()),
+                multiview: MultiviewSupport::query(instance, physical_device).is_some(),
+                full_screen_exclusive: super::full_screen_exclusive::supported(entry, instance, physical_device),
+                crash_diagnostics: !crash_diagnostics::device_extensions(instance, physical_device).is_empty(),
+                portability_subset: PortabilitySubset::query(instance, physical_device).is_some(),
+                sampler_anisotropy: device_features.sampler_anisotropy == vk::TRUE,
+                texture_compression_bc: device_features.texture_compression_bc == vk::TRUE,
+                texture_compression_etc2: device_features.texture_compression_etc2 == vk::TRUE,
+                texture_compression_astc: device_features.texture_compression_astc_ldr == vk::TRUE,
+                geometry_shader: device_features.geometry_shader == vk::TRUE,
+                tessellation_shader: device_features.tessellation_shader == vk::TRUE
+            },
+            extensions
+        }
+    }
+
+    pub fn has_extension(&self, name: &str) -> bool {
+        self.extensions.iter().any(|extension| extension == name)
+    }
+}
+
+// Vulkan only says the version is 32 bits, NVIDIA and Intel on Windows pack it their own way
+fn driver_version(vendor: Vendor, version: u32) -> String {
+    match vendor {
+        Vendor::Nvidia => format!("{}.{}.{}.{}", version >> 22, (version >> 14) & 0xff, (version >> 6) & 0xff, version & 0x3f),
+        Vendor::Intel if cfg!(windows) => format!("{}.{}", version >> 14, version & 0x3fff),
+        _ => format!("{}.{}.{}", version >> 22, (version >> 12) & 0x3ff, version & 0xfff)
+    }
+}
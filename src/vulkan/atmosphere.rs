@@ -0,0 +1,229 @@
+use ash::vk;
+
+use crate::utils::any_as_u8_slice;
+
+use super::clouds::CloudSettings;
+use super::lights::DirectionalLight;
+use super::pipeline::{Pipeline, PipelineConfig};
+use super::render_target::RenderTarget;
+use super::swapchain::VulkanSwapchain;
+
+pub const SKY_VERT: &[u32] = vk_shader_macros::include_glsl!("./shaders/fullscreen.vert", kind: vert, define: FAR_PLANE);
+pub const SKY_FRAG: &[u32] = vk_shader_macros::include_glsl!("./shaders/sky.frag", kind: frag);
+
+// Must match shaders/include/atmosphere.glsl
+const SKY_SAMPLES: u32 = 16;
+const SKY_LIGHT_SAMPLES: u32 = 8;
+// Directions over the upper hemisphere the sky is averaged over for the ambient light
+const AMBIENT_SAMPLES: u32 = 32;
+
+// Physically based sky, sunlight scattered once on its way through a planet's atmosphere: the air scatters blue the
+// most (Rayleigh), haze scatters every color forward around the sun (Mie). The defaults are the Earth's, in meters.
+// The same model gives the scene's sun color and ambient light, see `sun_light` and `ambient`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Atmosphere {
+    // Towards the sun, it's night once it's below the horizon
+    pub sun_direction: uv::Vec3,
+    pub sun_intensity: f32,
+    // Scattering coefficients per meter at the ground, thinning out exponentially with height
+    pub rayleigh: uv::Vec3,
+    pub rayleigh_height: f32,
+    pub mie: f32,
+    pub mie_height: f32,
+    // Towards 1.0 the haze gathers closer around the sun
+    pub mie_anisotropy: f32,
+    pub ground_radius: f32,
+    pub atmosphere_radius: f32,
+    // Of the scene above the ground
+    pub altitude: f32,
+    // Raymarched in front of the sky, see `Clouds`
+    pub clouds: Option<CloudSettings>,
+}
+
+impl Default for Atmosphere {
+    fn default() -> Self {
+        Self {
+            sun_direction: uv::Vec3::new(0.4, 0.5, 0.3).normalized(),
+            sun_intensity: 10.0,
+            rayleigh: uv::Vec3::new(5.8e-6, 13.5e-6, 33.1e-6),
+            rayleigh_height: 8000.0,
+            mie: 21e-6,
+            mie_height: 1200.0,
+            mie_anisotropy: 0.76,
+            ground_radius: 6360e3,
+            atmosphere_radius: 6420e3,
+            altitude: 1.0,
+            clouds: None,
+        }
+    }
+}
+
+impl Atmosphere {
+    // Packed as `AtmosphereParams` in shaders/include/atmosphere.glsl expects it
+    pub fn uniform(&self) -> [uv::Vec4; 4] {
+        let sun = self.sun_direction.normalized();
+        [
+            uv::Vec4::new(sun.x, sun.y, sun.z, self.sun_intensity),
+            uv::Vec4::new(self.rayleigh.x, self.rayleigh.y, self.rayleigh.z, self.rayleigh_height),
+            uv::Vec4::new(self.mie, self.mie_height, self.mie_anisotropy, self.altitude),
+            uv::Vec4::new(self.ground_radius, self.atmosphere_radius, 0.0, 0.0)
+        ]
+    }
+
+    // The sun as it reaches the ground, reddened by the air it went through and gone below the horizon
+    pub fn sun_light(&self) -> DirectionalLight {
+        let direction = self.sun_direction.normalized();
+        let color = self.extinction(self.optical_depth(self.origin(), direction));
+        DirectionalLight::new(direction, color, self.sun_intensity)
+    }
+
+    // Sky light on an upward facing surface, the average of the sky over cosine weighted directions
+    pub fn ambient(&self) -> uv::Vec3 {
+        let golden_angle = std::f32::consts::PI * (3.0 - 5f32.sqrt());
+        let total = (0..AMBIENT_SAMPLES).fold(uv::Vec3::zero(), |total, i| {
+            let u = (i as f32 + 0.5) / AMBIENT_SAMPLES as f32;
+            let angle = i as f32 * golden_angle;
+            let direction = uv::Vec3::new(u.sqrt() * angle.cos(), (1.0 - u).sqrt(), u.sqrt() * angle.sin());
+            total + self.sky_radiance(direction)
+        });
+        total / AMBIENT_SAMPLES as f32
+    }
+
+    // Same raymarch as `sky_radiance` in shaders/include/atmosphere.glsl
+    pub fn sky_radiance(&self, direction: uv::Vec3) -> uv::Vec3 {
+        let origin = self.origin();
+        let sun = self.sun_direction.normalized();
+        let (_, exit) = sphere_intersection(origin, direction, self.atmosphere_radius);
+        let (ground, _) = sphere_intersection(origin, direction, self.ground_radius);
+        let ray_length = match ground > 0.0 {
+            true => ground,
+            false => exit
+        };
+
+        let step_length = ray_length / SKY_SAMPLES as f32;
+        let mut view_depth = (0.0, 0.0);
+        let mut rayleigh = uv::Vec3::zero();
+        let mut mie = uv::Vec3::zero();
+        for i in 0..SKY_SAMPLES {
+            let position = origin + direction * (i as f32 + 0.5) * step_length;
+            let (rayleigh_density, mie_density) = self.density(position);
+            view_depth = (view_depth.0 + rayleigh_density * step_length, view_depth.1 + mie_density * step_length);
+
+            let light_depth = self.optical_depth(position, sun);
+            let attenuation = self.extinction((view_depth.0 + light_depth.0, view_depth.1 + light_depth.1));
+            rayleigh += attenuation * rayleigh_density * step_length;
+            mie += attenuation * mie_density * step_length;
+        }
+
+        let mu = direction.dot(sun);
+        let g = self.mie_anisotropy;
+        let pi = std::f32::consts::PI;
+        let rayleigh_phase = 3.0 / (16.0 * pi) * (1.0 + mu * mu);
+        let mie_phase = 3.0 / (8.0 * pi) * ((1.0 - g * g) * (1.0 + mu * mu)) / ((2.0 + g * g) * (1.0 + g * g - 2.0 * g * mu).powf(1.5));
+        (rayleigh * self.rayleigh * rayleigh_phase + mie * self.mie * mie_phase) * self.sun_intensity
+    }
+
+    fn origin(&self) -> uv::Vec3 {
+        uv::Vec3::new(0.0, self.ground_radius + self.altitude, 0.0)
+    }
+
+    fn density(&self, position: uv::Vec3) -> (f32, f32) {
+        let height = position.mag() - self.ground_radius;
+        ((-height / self.rayleigh_height).exp(), (-height / self.mie_height).exp())
+    }
+
+    // Rayleigh and Mie density along the ray out of the atmosphere, the ground blocks it completely
+    fn optical_depth(&self, origin: uv::Vec3, direction: uv::Vec3) -> (f32, f32) {
+        if sphere_intersection(origin, direction, self.ground_radius).0 > 0.0 {
+            return (1e9, 1e9);
+        }
+
+        let step_length = sphere_intersection(origin, direction, self.atmosphere_radius).1 / SKY_LIGHT_SAMPLES as f32;
+        (0..SKY_LIGHT_SAMPLES).fold((0.0, 0.0), |depth, i| {
+            let (rayleigh, mie) = self.density(origin + direction * (i as f32 + 0.5) * step_length);
+            (depth.0 + rayleigh * step_length, depth.1 + mie * step_length)
+        })
+    }
+
+    fn extinction(&self, (rayleigh, mie): (f32, f32)) -> uv::Vec3 {
+        let optical = self.rayleigh * rayleigh + uv::Vec3::broadcast(1.1 * self.mie * mie);
+        uv::Vec3::new((-optical.x).exp(), (-optical.y).exp(), (-optical.z).exp())
+    }
+}
+
+// Distances along the ray to where it enters and leaves the sphere around the planet's center, negative when it misses
+fn sphere_intersection(origin: uv::Vec3, direction: uv::Vec3, radius: f32) -> (f32, f32) {
+    let b = origin.dot(direction);
+    let c = origin.dot(origin) - radius * radius;
+    let discriminant = b * b - c;
+    match discriminant < 0.0 {
+        true => (-1.0, -1.0),
+        false => (-b - discriminant.sqrt(), -b + discriminant.sqrt())
+    }
+}
+
+// Mirrors the push constant block of sky.frag
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct SkyPushConstants {
+    atmosphere: [uv::Vec4; 4],
+    clouds: u32,
+}
+
+// Draws the atmosphere into the scene pass after the opaque objects, as a fullscreen triangle at the far plane: only
+// the pixels nothing covered pass the depth test, so the sky costs nothing where the scene hides it. It replaces the
+// clear color, which can be left to `ClearOp::DontCare`. The clouds of the view are blended over it.
+pub struct Sky {
+    pub pipeline: Pipeline,
+}
+
+impl Sky {
+    pub fn new(device: &ash::Device, swapchain: &VulkanSwapchain, scene_target: &RenderTarget, scene_config: &PipelineConfig,
+        clouds_set_layout: vk::DescriptorSetLayout
+    ) -> Result<Self, vk::Result> {
+        Ok(Self {
+            pipeline: Self::create_pipeline(device, swapchain, scene_target, scene_config, clouds_set_layout)?
+        })
+    }
+
+    // Only needs the camera of the scene sets, followed by the clouds
+    pub fn create_pipeline(device: &ash::Device, swapchain: &VulkanSwapchain, scene_target: &RenderTarget, scene_config: &PipelineConfig,
+        clouds_set_layout: vk::DescriptorSetLayout
+    ) -> Result<Pipeline, vk::Result> {
+        let set_layouts = [scene_config.set_layouts[0], clouds_set_layout];
+        let config = PipelineConfig {
+            vertex_shader: SKY_VERT,
+            fragment_shader: SKY_FRAG,
+            set_layouts: &set_layouts,
+            push_constant_size: std::mem::size_of::<SkyPushConstants>() as u32,
+            vertex_input: false,
+            depth_write: false,
+            depth_compare: vk::CompareOp::LESS_OR_EQUAL,
+            cull_mode: vk::CullModeFlags::NONE,
+            specialization: &[],
+            dynamic_shading_rate: false,
+            ..*scene_config
+        };
+        Pipeline::new(device, swapchain, &scene_target.renderpass, &config)
+    }
+
+    // Inside a scene pass, `camera_set` is the pass' camera. The clouds are only blended in when `with_clouds`, passes
+    // without clouds of their own (reflections) still bind a set.
+    #[allow(clippy::too_many_arguments)]
+    pub fn record(&self, device: &ash::Device, command_buffer: vk::CommandBuffer, camera_set: vk::DescriptorSet, atmosphere: &Atmosphere,
+        clouds_set: vk::DescriptorSet, with_clouds: bool
+    ) {
+        let push = SkyPushConstants {
+            atmosphere: atmosphere.uniform(),
+            clouds: (with_clouds && atmosphere.clouds.is_some()) as u32
+        };
+        unsafe {
+            device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, self.pipeline.pipeline);
+            device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::GRAPHICS, self.pipeline.layout, 0,
+                &[camera_set, clouds_set], &[]);
+            device.cmd_push_constants(command_buffer, self.pipeline.layout, vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT, 0,
+                any_as_u8_slice(&push));
+            device.cmd_draw(command_buffer, 3, 1, 0, 0);
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,211 @@
+use serde::{Deserialize, Serialize};
+
+use super::wind::Wind;
+
+// What happens to a camera's color or depth before the scene is drawn
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ClearOp {
+    Clear,
+    // Draws on top of what earlier views left, for overlay cameras. The first view of a frame has nothing to keep and clears.
+    Load,
+    // The scene covers every pixel anyway (a skybox), the contents may be left undefined
+    DontCare,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct ClearSettings {
+    // Clear color of the scene color, the other attachments (ids, normals, motion) always clear to zero
+    pub color: uv::Vec4,
+    pub depth: f32,
+    pub color_op: ClearOp,
+    pub depth_op: ClearOp,
+}
+
+impl Default for ClearSettings {
+    fn default() -> Self {
+        Self {
+            color: uv::Vec4::new(0.0, 0.0, 0.0, 1.0),
+            depth: 1.0,
+            color_op: ClearOp::Clear,
+            depth_op: ClearOp::Clear
+        }
+    }
+}
+
+impl ClearSettings {
+    // Draws over the views before it with its own depth, for a HUD model or a picture in picture camera
+    pub fn overlay() -> Self {
+        Self {
+            color_op: ClearOp::Load,
+            ..Default::default()
+        }
+    }
+}
+
+// Angles in radians from the view direction to each edge of the image, left and down are negative. Head mounted displays
+// have a different one for each eye that isn't centered on the view direction.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Fov {
+    pub left: f32,
+    pub right: f32,
+    pub up: f32,
+    pub down: f32,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Camera {
+    pub position: uv::Vec3,
+    pub target: uv::Vec3,
+    pub up: uv::Vec3,
+    pub fov_y: f32,
+    pub aspect_ratio: f32,
+    // Replaces `fov_y` and `aspect_ratio` in the projection when set
+    pub fov: Option<Fov>,
+    pub near: f32,
+    pub far: f32,
+    // Distance that stays sharp under depth of field
+    pub focal_distance: f32,
+    // Blur radius in pixels of objects at infinity, 0.0 keeps everything in focus
+    pub aperture: f32,
+    pub clear: ClearSettings,
+}
+
+impl Camera {
+    pub fn new(position: uv::Vec3, target: uv::Vec3, aspect_ratio: f32) -> Self {
+        Self {
+            position,
+            target,
+            up: uv::Vec3::unit_y(),
+            fov_y: 60f32.to_radians(),
+            aspect_ratio,
+            fov: None,
+            near: 0.1,
+            far: 100.0,
+            focal_distance: 2.5,
+            aperture: 0.0,
+            clear: ClearSettings::default(),
+        }
+    }
+
+    pub fn view_matrix(&self) -> uv::Mat4 {
+        uv::Mat4::look_at(self.position, self.target, self.up)
+    }
+
+    pub fn projection_matrix(&self) -> uv::Mat4 {
+        let fov = match self.fov {
+            Some(fov) => fov,
+            None => return uv::projection::rh_yup::perspective_vk(self.fov_y, self.aspect_ratio, self.near, self.far)
+        };
+
+        // Off center version of `perspective_vk`, y points down in clip space and depth goes from 0 to 1
+        let (left, right, up, down) = (fov.left.tan(), fov.right.tan(), fov.up.tan(), fov.down.tan());
+        let near_minus_far = self.near - self.far;
+        uv::Mat4::new(
+            uv::Vec4::new(2.0 / (right - left), 0.0, 0.0, 0.0),
+            uv::Vec4::new(0.0, -2.0 / (up - down), 0.0, 0.0),
+            uv::Vec4::new((right + left) / (right - left), -(up + down) / (up - down), self.far / near_minus_far, -1.0),
+            uv::Vec4::new(0.0, 0.0, self.near * self.far / near_minus_far, 0.0),
+        )
+    }
+
+    // World space ray through the pixel (x, y), in physical window pixels
+    pub fn screen_ray(&self, x: f32, y: f32, extent: ash::vk::Extent2D) -> Ray {
+        let ndc = uv::Vec2::new(2.0 * x / extent.width as f32 - 1.0, 2.0 * y / extent.height as f32 - 1.0);
+        let inverse_view_projection = (self.projection_matrix() * self.view_matrix()).inversed();
+        let near_point = inverse_view_projection * uv::Vec4::new(ndc.x, ndc.y, 0.0, 1.0);
+
+        Ray {
+            origin: self.position,
+            direction: (near_point.truncated() / near_point.w - self.position).normalized()
+        }
+    }
+
+    pub fn uniform(&self, extent: ash::vk::Extent2D) -> CameraUniform {
+        let mut uniform = CameraUniform::new(self.view_matrix(), self.projection_matrix(), self.position, extent);
+        uniform.near_far = uv::Vec4::new(self.near, self.far, 0.0, 0.0);
+        uniform.lens = uv::Vec4::new(self.focal_distance, self.aperture, 0.0, 0.0);
+        uniform
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Ray {
+    pub origin: uv::Vec3,
+    // Normalized
+    pub direction: uv::Vec3,
+}
+
+impl Ray {
+    pub fn at(&self, distance: f32) -> uv::Vec3 {
+        self.origin + self.direction * distance
+    }
+
+    // Distances along the transformed ray are in the new space's units
+    pub fn transformed(&self, matrix: uv::Mat4) -> Ray {
+        Ray {
+            origin: matrix.transform_point3(self.origin),
+            direction: matrix.transform_vec3(self.direction).normalized()
+        }
+    }
+}
+
+// Mirrors the std140 `Camera` block in shaders/include/camera.glsl
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct CameraUniform {
+    pub view: uv::Mat4,
+    pub projection: uv::Mat4,
+    pub inverse_projection: uv::Mat4,
+    pub position: uv::Vec4,
+    // World space plane (normal, distance); everything behind it is clipped. Zero disables clipping.
+    pub clip_plane: uv::Vec4,
+    // width, height, 1 / width, 1 / height
+    pub viewport: uv::Vec4,
+    // near, far, 1.0 for views that shade every light instead of looking up the light clusters (reflections, cubemaps)
+    pub near_far: uv::Vec4,
+    // Last frame's projection * view, for motion vectors
+    pub previous_view_projection: uv::Mat4,
+    // focal distance, aperture
+    pub lens: uv::Vec4,
+    // Sub-pixel offset applied to the projection this frame, in NDC
+    pub jitter: uv::Vec4,
+    // Top left of the view in the render target, in pixels. Only split screen views don't start at the origin
+    pub viewport_offset: uv::Vec4,
+    // Seconds animated materials (water, wind) are at, only set for the views of the scene itself
+    pub time: uv::Vec4,
+    // `Wind::uniform`, zero (calm) unless `time` is set
+    pub wind: uv::Vec4,
+    pub wind_gusts: uv::Vec4,
+}
+
+impl CameraUniform {
+    pub fn new(view: uv::Mat4, projection: uv::Mat4, position: uv::Vec3, extent: ash::vk::Extent2D) -> Self {
+        let width = extent.width as f32;
+        let height = extent.height as f32;
+
+        Self {
+            view,
+            projection,
+            inverse_projection: projection.inversed(),
+            position: position.into_homogeneous_point(),
+            clip_plane: uv::Vec4::zero(),
+            viewport: uv::Vec4::new(width, height, 1.0 / width, 1.0 / height),
+            near_far: uv::Vec4::zero(),
+            previous_view_projection: projection * view,
+            lens: uv::Vec4::zero(),
+            jitter: uv::Vec4::zero(),
+            viewport_offset: uv::Vec4::zero(),
+            time: uv::Vec4::zero(),
+            wind: uv::Vec4::zero(),
+            wind_gusts: uv::Vec4::new(1.0, 0.0, 0.0, 0.0),
+        }
+    }
+
+    // Moves animated materials to `time` in seconds, swaying in `wind`
+    pub fn animate(&mut self, time: f32, wind: &Wind) {
+        let [wind, wind_gusts] = wind.uniform();
+        self.time = uv::Vec4::new(time, 0.0, 0.0, 0.0);
+        self.wind = wind;
+        self.wind_gusts = wind_gusts;
+    }
+}
\ No newline at end of file
@@ -0,0 +1,137 @@
+use super::camera::Camera;
+
+// Radians per pixel of cursor movement
+const ROTATE_SPEED: f32 = 0.005;
+// Keeps the view direction away from the poles, where `up` would become parallel to it
+const MAX_PITCH: f32 = 1.55;
+
+// Arcball style editor camera circling a focus point
+#[derive(Clone, Copy, Debug)]
+pub struct OrbitController {
+    pub focus: uv::Vec3,
+    pub distance: f32,
+    pub yaw: f32,
+    pub pitch: f32,
+}
+
+impl OrbitController {
+    // Starts out looking at the camera's current target from where the camera is
+    pub fn from_camera(camera: &Camera) -> Self {
+        let offset = camera.position - camera.target;
+        let distance = offset.mag().max(0.01);
+
+        Self {
+            focus: camera.target,
+            distance,
+            yaw: offset.x.atan2(offset.z),
+            pitch: (offset.y / distance).clamp(-1.0, 1.0).asin()
+        }
+    }
+
+    pub fn rotate(&mut self, dx: f32, dy: f32) {
+        self.yaw -= dx * ROTATE_SPEED;
+        self.pitch = (self.pitch + dy * ROTATE_SPEED).clamp(-MAX_PITCH, MAX_PITCH);
+    }
+
+    // Moves the focus in the view plane, scaled so the point under the cursor roughly follows it
+    pub fn pan(&mut self, camera: &Camera, dx: f32, dy: f32, viewport_height: f32) {
+        let world_per_pixel = 2.0 * self.distance * (camera.fov_y * 0.5).tan() / viewport_height;
+        let forward = (camera.target - camera.position).normalized();
+        let right = forward.cross(camera.up).normalized();
+        let up = right.cross(forward);
+        self.focus += (up * dy - right * dx) * world_per_pixel;
+    }
+
+    // Positive amounts move towards the focus, proportional to the distance so zooming feels the same at any scale
+    pub fn dolly(&mut self, amount: f32) {
+        self.distance = (self.distance * (1.0 - amount * 0.1)).max(0.01);
+    }
+
+    // Frames a sphere so it fills the vertical field of view
+    pub fn focus_on(&mut self, camera: &Camera, center: uv::Vec3, radius: f32) {
+        self.focus = center;
+        self.distance = radius.max(0.01) / (camera.fov_y * 0.5).sin();
+    }
+
+    pub fn apply(&self, camera: &mut Camera) {
+        let direction = uv::Vec3::new(
+            self.pitch.cos() * self.yaw.sin(),
+            self.pitch.sin(),
+            self.pitch.cos() * self.yaw.cos(),
+        );
+        camera.position = self.focus + direction * self.distance;
+        camera.target = self.focus;
+        camera.up = uv::Vec3::unit_y();
+    }
+}
+
+// First person fly camera, looking around with the cursor and moving along the view direction
+#[derive(Clone, Copy, Debug)]
+pub struct FlyController {
+    pub position: uv::Vec3,
+    pub yaw: f32,
+    pub pitch: f32,
+    // Units per second
+    pub speed: f32,
+}
+
+impl FlyController {
+    pub fn from_camera(camera: &Camera) -> Self {
+        let forward = (camera.target - camera.position).normalized();
+
+        Self {
+            position: camera.position,
+            yaw: forward.x.atan2(-forward.z),
+            pitch: forward.y.clamp(-1.0, 1.0).asin(),
+            speed: 2.0
+        }
+    }
+
+    pub fn forward(&self) -> uv::Vec3 {
+        uv::Vec3::new(self.pitch.cos() * self.yaw.sin(), self.pitch.sin(), -self.pitch.cos() * self.yaw.cos())
+    }
+
+    pub fn look(&mut self, dx: f32, dy: f32) {
+        self.yaw += dx * ROTATE_SPEED;
+        self.pitch = (self.pitch - dy * ROTATE_SPEED).clamp(-MAX_PITCH, MAX_PITCH);
+    }
+
+    // `direction` is (right, up, forward) in -1..1 per axis
+    pub fn travel(&mut self, direction: uv::Vec3, delta_time: f32) {
+        let forward = self.forward();
+        let right = forward.cross(uv::Vec3::unit_y()).normalized();
+        let movement = right * direction.x + uv::Vec3::unit_y() * direction.y + forward * direction.z;
+        if movement.mag_sq() > 0.0 {
+            self.position += movement.normalized() * self.speed * delta_time;
+        }
+    }
+
+    pub fn apply(&self, camera: &mut Camera) {
+        camera.position = self.position;
+        camera.target = self.position + self.forward();
+        camera.up = uv::Vec3::unit_y();
+    }
+}
+
+// Switching converts the current view, so the camera doesn't jump
+#[derive(Clone, Copy, Debug)]
+pub enum CameraController {
+    Orbit(OrbitController),
+    Fly(FlyController),
+}
+
+impl CameraController {
+    pub fn toggled(&self, camera: &Camera) -> Self {
+        match self {
+            CameraController::Orbit(_) => CameraController::Fly(FlyController::from_camera(camera)),
+            CameraController::Fly(_) => CameraController::Orbit(OrbitController::from_camera(camera)),
+        }
+    }
+
+    pub fn apply(&self, camera: &mut Camera) {
+        match self {
+            CameraController::Orbit(orbit) => orbit.apply(camera),
+            CameraController::Fly(fly) => fly.apply(camera),
+        }
+    }
+}
\ No newline at end of file
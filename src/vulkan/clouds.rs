@@ -0,0 +1,233 @@
+use ash::vk;
+use gpu_allocator::vulkan::Allocator;
+
+use super::atmosphere::Atmosphere;
+use super::camera::{ClearOp, ClearSettings};
+use super::command_pools::Pools;
+use super::descriptor_allocator::DescriptorAllocator;
+use super::descriptors::Descriptors;
+use super::pipeline::{Pipeline, PipelineConfig};
+use super::post::HDR_FORMAT;
+use super::render_target::RenderTarget;
+use super::swapchain::VulkanSwapchain;
+use crate::utils::any_as_u8_slice;
+
+pub const CLOUDS_FRAG: &[u32] = vk_shader_macros::include_glsl!("./shaders/clouds.frag", kind: frag);
+
+// The clouds are raymarched at a fraction of the scene's resolution, must match shaders/clouds.frag and sky.frag
+pub const CLOUD_DOWNSCALE: u32 = 2;
+
+// Layer of clouds of the atmosphere, see `Atmosphere::clouds`. Sizes and heights are in meters.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CloudSettings {
+    // Share of the sky covered, 0.0 clear to 1.0 overcast
+    pub coverage: f32,
+    // Extinction per meter of the thickest parts
+    pub density: f32,
+    // Heights of the layer above the scene
+    pub bottom: f32,
+    pub top: f32,
+    // Size of the noise the clouds are shaped by
+    pub feature_size: f32,
+    // Along xz, in meters per second
+    pub drift: uv::Vec2,
+    // Raymarch steps through the layer, the history smooths out the noise of few steps
+    pub steps: u32,
+    // Weight of the reprojected history, higher is smoother but smears when the camera turns fast
+    pub history_blend: f32,
+}
+
+impl Default for CloudSettings {
+    fn default() -> Self {
+        Self {
+            coverage: 0.45,
+            density: 0.02,
+            bottom: 1500.0,
+            top: 4000.0,
+            feature_size: 3000.0,
+            drift: uv::Vec2::new(10.0, 4.0),
+            steps: 32,
+            history_blend: 0.9,
+        }
+    }
+}
+
+// Mirrors the push constant block of clouds.frag
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct CloudPushConstants {
+    sun: uv::Vec4,
+    sun_color: uv::Vec4,
+    ambient: uv::Vec4,
+    layer: uv::Vec4,
+    shape: uv::Vec4,
+}
+
+// Raymarched clouds, rendered for every view before its scene pass and composited by the sky. Each frame marches
+// the layer with few, randomly offset steps and blends the result with last frame's, reprojected by the camera's
+// rotation, so the noise averages out over a few frames. The two targets take turns being written and read as the
+// history, like the TAA history.
+pub struct Clouds {
+    pub targets: [RenderTarget; 2],
+    pub pipeline: Pipeline,
+    set_layout: vk::DescriptorSetLayout,
+    // Sample targets[i], as the history of the cloud pass and the clouds of the sky
+    sets: [vk::DescriptorSet; 2],
+    // Whether the history holds last frame's clouds, it doesn't after a resize or once the clouds were off
+    history_valid: bool,
+    drawn_frame: Option<u64>,
+}
+
+impl Clouds {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(device: &ash::Device, allocator: &mut Allocator, descriptors: &mut DescriptorAllocator, pools: &Pools, queue: vk::Queue,
+        swapchain: &VulkanSwapchain, extent: vk::Extent2D, camera_set_layout: vk::DescriptorSetLayout
+    ) -> Result<Self, vk::Result> {
+        let set_layout = Descriptors::create_layout(device, &[
+            (vk::DescriptorType::COMBINED_IMAGE_SAMPLER, vk::ShaderStageFlags::FRAGMENT)
+        ])?;
+        let sets = [descriptors.allocate(device, set_layout)?, descriptors.allocate(device, set_layout)?];
+        let targets = Self::create_targets(device, allocator, pools, queue, extent)?;
+
+        let set_layouts = [camera_set_layout, set_layout];
+        let pipeline = Pipeline::new(device, swapchain, &targets[0].renderpass,
+            &PipelineConfig::fullscreen(CLOUDS_FRAG, &set_layouts, std::mem::size_of::<CloudPushConstants>() as u32))?;
+
+        let clouds = Self {
+            targets,
+            pipeline,
+            set_layout,
+            sets,
+            history_valid: false,
+            drawn_frame: None
+        };
+        clouds.write_sets(device);
+
+        Ok(clouds)
+    }
+
+    // At the scene's `extent` divided by `CLOUD_DOWNSCALE`, ready for sampling
+    fn create_targets(device: &ash::Device, allocator: &mut Allocator, pools: &Pools, queue: vk::Queue, extent: vk::Extent2D
+    ) -> Result<[RenderTarget; 2], vk::Result> {
+        let extent = vk::Extent2D {
+            width: extent.width.div_ceil(CLOUD_DOWNSCALE),
+            height: extent.height.div_ceil(CLOUD_DOWNSCALE)
+        };
+        let targets = [
+            RenderTarget::new(device, allocator, extent, &[HDR_FORMAT], false, "Clouds 0")?,
+            RenderTarget::new(device, allocator, extent, &[HDR_FORMAT], false, "Clouds 1")?,
+        ];
+
+        pools.one_time_submit(device, queue, |command_buffer| {
+            for target in &targets {
+                target.colors[0].transition_layout(device, command_buffer, vk::ImageLayout::UNDEFINED, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+            }
+        })?;
+
+        Ok(targets)
+    }
+
+    fn write_sets(&self, device: &ash::Device) {
+        for (set, target) in self.sets.iter().zip(&self.targets) {
+            Descriptors::write_image(device, *set, 0, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, target.descriptor_info(0));
+        }
+    }
+
+    pub fn set_layout(&self) -> vk::DescriptorSetLayout {
+        self.set_layout
+    }
+
+    // Target written this frame, the other one holds last frame's clouds
+    fn write_index(frame_index: u64) -> usize {
+        (frame_index % 2) as usize
+    }
+
+    // The clouds of this frame for the sky, only meaningful once `record` ran for the view
+    pub fn sky_set(&self, frame_index: u64) -> vk::DescriptorSet {
+        self.sets[Self::write_index(frame_index)]
+    }
+
+    // Once per frame before recording, the history is only blended in when the previous frame drew clouds too
+    pub fn update(&mut self, frame_index: u64, enabled: bool) {
+        self.history_valid = enabled && frame_index > 0 && self.drawn_frame == Some(frame_index - 1);
+        if enabled {
+            self.drawn_frame = Some(frame_index);
+        }
+    }
+
+    // Leaves the history out of the next frame, for when the last one showed something else
+    pub fn reset_history(&mut self) {
+        self.drawn_frame = None;
+    }
+
+    pub fn resize(&mut self, device: &ash::Device, allocator: &mut Allocator, pools: &Pools, queue: vk::Queue, extent: vk::Extent2D
+    ) -> Result<(), vk::Result> {
+        for target in &mut self.targets {
+            target.destroy(device, allocator);
+        }
+        self.targets = Self::create_targets(device, allocator, pools, queue, extent)?;
+        self.write_sets(device);
+        self.drawn_frame = None;
+        Ok(())
+    }
+
+    // Outside of any render pass, into the part of this frame's target under the view's `rect` of the scene target
+    #[allow(clippy::too_many_arguments)]
+    pub fn record(&self, device: &ash::Device, command_buffer: vk::CommandBuffer, camera_set: vk::DescriptorSet, rect: vk::Rect2D, first: bool,
+        atmosphere: &Atmosphere, settings: &CloudSettings, frame_index: u64
+    ) {
+        let write_index = Self::write_index(frame_index);
+        let target = &self.targets[write_index];
+        let rect = vk::Rect2D {
+            offset: vk::Offset2D { x: rect.offset.x / CLOUD_DOWNSCALE as i32, y: rect.offset.y / CLOUD_DOWNSCALE as i32 },
+            extent: vk::Extent2D {
+                width: rect.extent.width.div_ceil(CLOUD_DOWNSCALE).min(target.extent.width),
+                height: rect.extent.height.div_ceil(CLOUD_DOWNSCALE).min(target.extent.height)
+            }
+        };
+
+        let sun = atmosphere.sun_light();
+        let sun_color = sun.color * sun.intensity;
+        let ambient = atmosphere.ambient();
+        let history_blend = match self.history_valid {
+            true => settings.history_blend.clamp(0.0, 0.98),
+            false => 0.0
+        };
+        let push = CloudPushConstants {
+            sun: uv::Vec4::new(sun.direction.x, sun.direction.y, sun.direction.z, history_blend),
+            sun_color: uv::Vec4::new(sun_color.x, sun_color.y, sun_color.z, (frame_index % 1024) as f32),
+            ambient: uv::Vec4::new(ambient.x, ambient.y, ambient.z, atmosphere.ground_radius + atmosphere.altitude),
+            layer: uv::Vec4::new(settings.coverage.clamp(0.0, 1.0), settings.density, settings.bottom, settings.top.max(settings.bottom + 1.0)),
+            shape: uv::Vec4::new(settings.feature_size.max(1.0), settings.drift.x, settings.drift.y, settings.steps.max(1) as f32),
+        };
+        // Every pixel of the view is written, nothing needs clearing
+        let clear = ClearSettings {
+            color_op: ClearOp::DontCare,
+            depth_op: ClearOp::DontCare,
+            ..ClearSettings::default()
+        };
+
+        target.begin_pass(device, command_buffer, rect, first, &clear);
+        unsafe {
+            device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, self.pipeline.pipeline);
+            device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::GRAPHICS, self.pipeline.layout, 0,
+                &[camera_set, self.sets[1 - write_index]], &[]);
+            device.cmd_push_constants(command_buffer, self.pipeline.layout, vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT, 0,
+                any_as_u8_slice(&push));
+            device.cmd_draw(command_buffer, 3, 1, 0, 0);
+            device.cmd_end_render_pass(command_buffer);
+        }
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device, allocator: &mut Allocator, descriptors: &mut DescriptorAllocator) -> Result<(), vk::Result> {
+        for set in self.sets {
+            descriptors.free(device, set)?;
+        }
+        self.pipeline.cleanup(device);
+        for target in &mut self.targets {
+            target.destroy(device, allocator);
+        }
+        unsafe { device.destroy_descriptor_set_layout(self.set_layout, None) };
+        Ok(())
+    }
+}
\ No newline at end of file
@@ -0,0 +1,194 @@
+use ash::vk;
+use gpu_allocator::vulkan::Allocator;
+use gpu_allocator::MemoryLocation;
+
+use super::compute_pipeline::ComputePipeline;
+use super::descriptors::Descriptors;
+use super::pipeline::SpecializationConstant;
+use super::light_probes::LightProbes;
+use super::lights::{DirectionalLight, GpuLight, LightBufferHeader, PointLight, SpotLight};
+use super::reflection_probes::ReflectionProbes;
+use super::shadows::{ShadowAssignment, ShadowSettings, ShadowSystem};
+use super::storage_buffer::StorageBuffer;
+
+pub const CLUSTER_CULL_COMP: &[u32] = vk_shader_macros::include_glsl!("./shaders/cluster_cull.comp", kind: comp);
+
+pub const CLUSTER_GRID: [u32; 3] = [16, 9, 24];
+pub const MAX_LIGHTS: usize = 1024;
+pub const MAX_LIGHTS_PER_CLUSTER: usize = 128;
+const CULL_GROUP_SIZE: u32 = 64;
+
+// Hands the sizes above to shaders/include/clusters.glsl, so they're only defined here
+pub const CLUSTER_SPECIALIZATION: [SpecializationConstant; 5] = [
+    SpecializationConstant::uint(0, CLUSTER_GRID[0]),
+    SpecializationConstant::uint(1, CLUSTER_GRID[1]),
+    SpecializationConstant::uint(2, CLUSTER_GRID[2]),
+    SpecializationConstant::uint(3, MAX_LIGHTS_PER_CLUSTER as u32),
+    SpecializationConstant::uint(4, CULL_GROUP_SIZE),
+];
+
+// Forward+ light culling: before the scene pass a compute shader splits the view frustum into
+// froxels and lists the lights touching each one, so fragments only loop over nearby lights.
+pub struct ClusteredLighting {
+    pub set_layout: vk::DescriptorSetLayout,
+    pub sets: Vec<vk::DescriptorSet>,
+    pub cull_pipeline: ComputePipeline,
+    light_buffers: Vec<StorageBuffer>,
+    cluster_buffers: Vec<StorageBuffer>,
+    index_buffers: Vec<StorageBuffer>,
+}
+
+impl ClusteredLighting {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        device: &ash::Device,
+        allocator: &mut Allocator,
+        descriptor_pool: vk::DescriptorPool,
+        camera_set_layout: vk::DescriptorSetLayout,
+        image_count: usize,
+        shadows: &ShadowSystem,
+        cookies: vk::DescriptorImageInfo,
+        probes: &ReflectionProbes,
+    ) -> Result<Self, vk::Result> {
+        let stages = vk::ShaderStageFlags::FRAGMENT | vk::ShaderStageFlags::COMPUTE;
+        let set_layout = Descriptors::create_layout(device, &[
+            (vk::DescriptorType::STORAGE_BUFFER, stages),
+            (vk::DescriptorType::STORAGE_BUFFER, stages),
+            (vk::DescriptorType::STORAGE_BUFFER, stages),
+            (vk::DescriptorType::COMBINED_IMAGE_SAMPLER, vk::ShaderStageFlags::FRAGMENT),
+            (vk::DescriptorType::COMBINED_IMAGE_SAMPLER, vk::ShaderStageFlags::FRAGMENT),
+            (vk::DescriptorType::COMBINED_IMAGE_SAMPLER, vk::ShaderStageFlags::FRAGMENT),
+            (vk::DescriptorType::COMBINED_IMAGE_SAMPLER, vk::ShaderStageFlags::FRAGMENT),
+            (vk::DescriptorType::COMBINED_IMAGE_SAMPLER, vk::ShaderStageFlags::FRAGMENT),
+            (vk::DescriptorType::COMBINED_IMAGE_SAMPLER, vk::ShaderStageFlags::FRAGMENT),
+            (vk::DescriptorType::STORAGE_BUFFER, vk::ShaderStageFlags::FRAGMENT),
+            (vk::DescriptorType::STORAGE_BUFFER, vk::ShaderStageFlags::FRAGMENT),
+            (vk::DescriptorType::STORAGE_BUFFER, vk::ShaderStageFlags::FRAGMENT),
+        ])?;
+
+        let cull_pipeline = ComputePipeline::new(device, CLUSTER_CULL_COMP, &[camera_set_layout, set_layout], 0, &CLUSTER_SPECIALIZATION)?;
+
+        let cluster_count = Self::cluster_count() as u64;
+        let light_buffer_size = (std::mem::size_of::<LightBufferHeader>() + MAX_LIGHTS * std::mem::size_of::<GpuLight>()) as u64;
+        let cluster_buffer_size = cluster_count * std::mem::size_of::<u32>() as u64;
+        let index_buffer_size = cluster_count * (MAX_LIGHTS_PER_CLUSTER * std::mem::size_of::<u32>()) as u64;
+
+        let sets = Descriptors::allocate(device, descriptor_pool, set_layout, image_count)?;
+        let mut light_buffers = Vec::with_capacity(image_count);
+        let mut cluster_buffers = Vec::with_capacity(image_count);
+        let mut index_buffers = Vec::with_capacity(image_count);
+        for (index, set) in sets.iter().enumerate() {
+            let light_buffer = StorageBuffer::new(device, allocator, light_buffer_size, MemoryLocation::CpuToGpu, "Light Buffer");
+            let cluster_buffer = StorageBuffer::new(device, allocator, cluster_buffer_size, MemoryLocation::GpuOnly, "Cluster Buffer");
+            let index_buffer = StorageBuffer::new(device, allocator, index_buffer_size, MemoryLocation::GpuOnly, "Cluster Light Index Buffer");
+
+            Descriptors::write_buffer(device, *set, 0, vk::DescriptorType::STORAGE_BUFFER, light_buffer.descriptor_info());
+            Descriptors::write_buffer(device, *set, 1, vk::DescriptorType::STORAGE_BUFFER, cluster_buffer.descriptor_info());
+            Descriptors::write_buffer(device, *set, 2, vk::DescriptorType::STORAGE_BUFFER, index_buffer.descriptor_info());
+            Descriptors::write_image(device, *set, 3, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, shadows.spot_descriptor_info(true));
+            Descriptors::write_image(device, *set, 4, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, cookies);
+            Descriptors::write_image(device, *set, 5, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, shadows.point_descriptor_info(true));
+            Descriptors::write_image(device, *set, 6, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, shadows.spot_descriptor_info(false));
+            Descriptors::write_image(device, *set, 7, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, shadows.point_descriptor_info(false));
+            Descriptors::write_image(device, *set, 8, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, probes.descriptor_info());
+            Descriptors::write_buffer(device, *set, 9, vk::DescriptorType::STORAGE_BUFFER, probes.buffer_info(index));
+
+            light_buffers.push(light_buffer);
+            cluster_buffers.push(cluster_buffer);
+            index_buffers.push(index_buffer);
+        }
+
+        Ok(Self {
+            set_layout,
+            sets,
+            cull_pipeline,
+            light_buffers,
+            cluster_buffers,
+            index_buffers
+        })
+    }
+
+    pub fn cluster_count() -> u32 {
+        CLUSTER_GRID[0] * CLUSTER_GRID[1] * CLUSTER_GRID[2]
+    }
+
+    // Only has to be called when the cookie texture is replaced, the sets are not in use by the GPU at that point
+    pub fn write_cookies(&self, device: &ash::Device, cookies: vk::DescriptorImageInfo) {
+        for set in &self.sets {
+            Descriptors::write_image(device, *set, 4, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, cookies);
+        }
+    }
+
+    // The light probes capture the scene with the lighting sets, so they're created after them and written in afterwards
+    pub fn write_light_probes(&self, device: &ash::Device, light_probes: &LightProbes) {
+        for (index, set) in self.sets.iter().enumerate() {
+            Descriptors::write_buffer(device, *set, 10, vk::DescriptorType::STORAGE_BUFFER, light_probes.grid_info(index));
+            Descriptors::write_buffer(device, *set, 11, vk::DescriptorType::STORAGE_BUFFER, light_probes.coefficients_info());
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn update(&mut self, index: usize, lights: &[PointLight], spot_lights: &[SpotLight], sun: Option<&DirectionalLight>, shadows: &ShadowAssignment,
+        shadow_settings: &ShadowSettings, ambient: uv::Vec3
+    ) {
+        let light_count = lights.len() + spot_lights.len();
+        if light_count > MAX_LIGHTS {
+            tracing::warn!("{} lights in the scene, only the first {} are used", light_count, MAX_LIGHTS);
+        }
+
+        let gpu_lights: Vec<GpuLight> = lights
+            .iter()
+            .zip(&shadows.point)
+            .map(|(light, &layer)| light.as_gpu_light(layer))
+            .chain(spot_lights.iter().zip(&shadows.spot).map(|(light, &layer)| light.as_gpu_light(layer)))
+            .take(MAX_LIGHTS)
+            .collect();
+        let [sun_direction, sun_color] = sun.map_or([uv::Vec4::zero(); 2], DirectionalLight::uniform);
+
+        let header = LightBufferHeader {
+            count: gpu_lights.len() as u32,
+            _padding: [0; 3],
+            ambient: ambient.into_homogeneous_vector(),
+            shadow_filter: shadow_settings.filter_params(),
+            shadow_params: shadow_settings.size_params(),
+            sun_direction,
+            sun_color,
+        };
+
+        let light_buffer = &mut self.light_buffers[index];
+        light_buffer.update_buffer(0, &[header]);
+        light_buffer.update_buffer(std::mem::size_of::<LightBufferHeader>() as u64, &gpu_lights);
+    }
+
+    pub fn record_culling(&self, device: &ash::Device, command_buffer: vk::CommandBuffer, index: usize, camera_set: vk::DescriptorSet) {
+        let group_count = Self::cluster_count().div_ceil(CULL_GROUP_SIZE);
+
+        let barriers = [vk::MemoryBarrier::builder()
+            .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+            .dst_access_mask(vk::AccessFlags::SHADER_READ)
+            .build()
+        ];
+
+        unsafe {
+            // With split screen an earlier view's fragment shaders may still be reading the previous results
+            device.cmd_pipeline_barrier(command_buffer, vk::PipelineStageFlags::FRAGMENT_SHADER, vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::DependencyFlags::empty(), &[], &[], &[]);
+
+            device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::COMPUTE, self.cull_pipeline.pipeline);
+            device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::COMPUTE, self.cull_pipeline.layout, 0,
+                &[camera_set, self.sets[index]], &[]);
+            device.cmd_dispatch(command_buffer, group_count, 1, 1);
+
+            device.cmd_pipeline_barrier(command_buffer, vk::PipelineStageFlags::COMPUTE_SHADER, vk::PipelineStageFlags::FRAGMENT_SHADER,
+                vk::DependencyFlags::empty(), &barriers, &[], &[]);
+        }
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device, allocator: &mut Allocator) {
+        for buffer in self.light_buffers.iter_mut().chain(self.cluster_buffers.iter_mut()).chain(self.index_buffers.iter_mut()) {
+            buffer.destroy(device, allocator);
+        }
+        self.cull_pipeline.cleanup(device);
+        unsafe { device.destroy_descriptor_set_layout(self.set_layout, None) };
+    }
+}
\ No newline at end of file
@@ -4,6 +4,9 @@ use super::queue::QueueFamilies;
 pub struct Pools {
     pub graphics_command_pool: vk::CommandPool,
     pub transfer_command_pool: vk::CommandPool,
+    // One for each command buffer recorded every frame, so they can be recorded on different threads at once
+    pub frame_command_pools: Vec<vk::CommandPool>,
+    graphics_family: u32,
 }
 
 impl Pools {
@@ -28,14 +31,74 @@ impl Pools {
 
         Ok(Pools {
             graphics_command_pool,
-            transfer_command_pool
+            transfer_command_pool,
+            frame_command_pools: vec![],
+            graphics_family: queue_families.graphics.unwrap()
         })
     }
 
+    // A graphics command buffer from each of the first `amount` frame pools, creating the pools that are missing
+    pub fn allocate_frame_command_buffers(&mut self, logical_device: &ash::Device, amount: usize) -> Result<Vec<vk::CommandBuffer>, vk::Result> {
+        while self.frame_command_pools.len() < amount {
+            let pool_info = vk::CommandPoolCreateInfo::builder()
+                .queue_family_index(self.graphics_family)
+                .flags(vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER);
+            self.frame_command_pools.push(unsafe { logical_device.create_command_pool(&pool_info, None)? });
+        }
+
+        self.frame_command_pools[..amount].iter().map(|&pool| {
+            let commandbuffer_allocate_info = vk::CommandBufferAllocateInfo::builder()
+                .level(vk::CommandBufferLevel::PRIMARY)
+                .command_pool(pool)
+                .command_buffer_count(1);
+            unsafe { logical_device.allocate_command_buffers(&commandbuffer_allocate_info) }.map(|buffers| buffers[0])
+        }).collect()
+    }
+
+    // Frees command buffers from `allocate_frame_command_buffers`
+    pub fn free_frame_command_buffers(&self, logical_device: &ash::Device, command_buffers: &[vk::CommandBuffer]) {
+        for (&pool, &command_buffer) in self.frame_command_pools.iter().zip(command_buffers) {
+            unsafe { logical_device.free_command_buffers(pool, &[command_buffer]) };
+        }
+    }
+
+    // Records and submits a throwaway command buffer, then waits for the queue to go idle.
+    // Meant for uploads at load time, not for anything per frame.
+    pub fn one_time_submit<F: FnOnce(vk::CommandBuffer)>(&self, logical_device: &ash::Device, queue: vk::Queue, record: F) -> Result<(), vk::Result> {
+        let commandbuffer_allocate_info = vk::CommandBufferAllocateInfo::builder()
+            .level(vk::CommandBufferLevel::PRIMARY)
+            .command_pool(self.graphics_command_pool)
+            .command_buffer_count(1);
+        let command_buffer = unsafe { logical_device.allocate_command_buffers(&commandbuffer_allocate_info)? }[0];
+
+        let begin_info = vk::CommandBufferBeginInfo::builder()
+            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+
+        unsafe {
+            logical_device.begin_command_buffer(command_buffer, &begin_info)?;
+            record(command_buffer);
+            logical_device.end_command_buffer(command_buffer)?;
+
+            let command_buffers = [command_buffer];
+            let submit_info = [vk::SubmitInfo::builder()
+                .command_buffers(&command_buffers)
+                .build()
+            ];
+            logical_device.queue_submit(queue, &submit_info, vk::Fence::null())?;
+            logical_device.queue_wait_idle(queue)?;
+            logical_device.free_command_buffers(self.graphics_command_pool, &command_buffers);
+        }
+
+        Ok(())
+    }
+
     pub fn cleanup(&self, logical_device: &ash::Device) {
         unsafe {
             logical_device.destroy_command_pool(self.graphics_command_pool, None);
             logical_device.destroy_command_pool(self.transfer_command_pool, None);
+            for &pool in &self.frame_command_pools {
+                logical_device.destroy_command_pool(pool, None);
+            }
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,76 @@
+use ash::vk;
+
+use super::pipeline::{specialization_data, SpecializationConstant};
+use super::shader_reflection;
+
+pub struct ComputePipeline {
+    pub pipeline: vk::Pipeline,
+    pub layout: vk::PipelineLayout,
+}
+
+impl ComputePipeline {
+    pub fn new(logical_device: &ash::Device, shader: &[u32], set_layouts: &[vk::DescriptorSetLayout], push_constant_size: u32,
+        specialization: &[SpecializationConstant]
+    ) -> Result<Self, vk::Result> {
+        shader_reflection::validate(&[shader], set_layouts, push_constant_size, &[])?;
+
+        let main_function_name = std::ffi::CString::new("main").unwrap();
+
+        let shader_createinfo = vk::ShaderModuleCreateInfo::builder()
+            .code(shader);
+        let shader_module = unsafe { logical_device.create_shader_module(&shader_createinfo, None)? };
+
+        let (map_entries, specialization_bytes) = specialization_data(specialization);
+        let specialization_info = vk::SpecializationInfo::builder()
+            .map_entries(&map_entries)
+            .data(&specialization_bytes);
+
+        let shader_stage = vk::PipelineShaderStageCreateInfo::builder()
+            .stage(vk::ShaderStageFlags::COMPUTE)
+            .module(shader_module)
+            .name(&main_function_name)
+            .specialization_info(&specialization_info);
+
+        let push_constant_range = [vk::PushConstantRange::builder()
+            .stage_flags(vk::ShaderStageFlags::COMPUTE)
+            .offset(0)
+            .size(push_constant_size)
+            .build()
+        ];
+
+        let push_constant_ranges = match push_constant_size {
+            0 => &push_constant_range[..0],
+            _ => &push_constant_range[..]
+        };
+
+        let pipelinelayout_info = vk::PipelineLayoutCreateInfo::builder()
+            .set_layouts(set_layouts)
+            .push_constant_ranges(push_constant_ranges);
+        let pipeline_layout = unsafe { logical_device.create_pipeline_layout(&pipelinelayout_info, None)? };
+
+        let pipeline_info = vk::ComputePipelineCreateInfo::builder()
+            .stage(*shader_stage)
+            .layout(pipeline_layout);
+
+        let compute_pipeline = unsafe {
+            logical_device.create_compute_pipelines(vk::PipelineCache::null(), &[pipeline_info.build()], None)
+                .expect("Failed to create compute pipeline")
+        }[0];
+
+        unsafe {
+            logical_device.destroy_shader_module(shader_module, None);
+        }
+
+        Ok(Self {
+            pipeline: compute_pipeline,
+            layout: pipeline_layout
+        })
+    }
+
+    pub fn cleanup(&self, logical_device: &ash::Device) {
+        unsafe {
+            logical_device.destroy_pipeline(self.pipeline, None);
+            logical_device.destroy_pipeline_layout(self.layout, None);
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,119 @@
+use std::ffi::CStr;
+
+use ash::vk;
+use gpu_allocator::vulkan::Allocator;
+use gpu_allocator::MemoryLocation;
+
+use super::occlusion::{OcclusionQuery, MAX_OCCLUSION_QUERIES};
+use super::storage_buffer::StorageBuffer;
+
+// VK_EXT_conditional_rendering with its feature, the extension alone isn't enough
+pub fn supported(instance: &ash::Instance, physical_device: vk::PhysicalDevice) -> bool {
+    let extensions = unsafe { instance.enumerate_device_extension_properties(physical_device).unwrap_or_default() };
+    let name = vk::ExtConditionalRenderingFn::name();
+    if !extensions.iter().any(|extension| unsafe { CStr::from_ptr(extension.extension_name.as_ptr()) } == name) {
+        return false;
+    }
+
+    let mut conditional_features = vk::PhysicalDeviceConditionalRenderingFeaturesEXT::default();
+    let mut features = vk::PhysicalDeviceFeatures2::builder().push_next(&mut conditional_features);
+    unsafe { instance.get_physical_device_features2(physical_device, &mut features) };
+    conditional_features.conditional_rendering == vk::TRUE
+}
+
+// Draws predicated on a 32 bit value in a GPU buffer, skipped without the CPU ever reading it. The predicate buffer holds the
+// samples every occlusion query counted, copied over once the scene pass is done, so a draw predicated on a query goes by
+// the previous frame. Queries start out visible until they were first tested.
+pub struct ConditionalRendering {
+    functions: vk::ExtConditionalRenderingFn,
+    // One u32 per occlusion query slot
+    predicates: StorageBuffer,
+}
+
+impl ConditionalRendering {
+    pub fn new(instance: &ash::Instance, device: &ash::Device, allocator: &mut Allocator) -> Self {
+        let functions = vk::ExtConditionalRenderingFn::load(|name| unsafe {
+            std::mem::transmute(instance.get_device_proc_addr(device.handle(), name.as_ptr()))
+        });
+
+        // Host visible so the predicates of new queries can be set to visible right away
+        let mut predicates = StorageBuffer::with_usage(device, allocator, MAX_OCCLUSION_QUERIES as u64 * 4,
+            vk::BufferUsageFlags::CONDITIONAL_RENDERING_EXT | vk::BufferUsageFlags::TRANSFER_DST, MemoryLocation::CpuToGpu, "Predicates");
+        predicates.update_buffer(0, &[1u32; MAX_OCCLUSION_QUERIES as usize]);
+
+        Self {
+            functions,
+            predicates
+        }
+    }
+
+    // Frames still in flight may see either value, both draw the object or not for a frame at most
+    pub fn reset(&mut self, query: OcclusionQuery) {
+        self.predicates.update_buffer(query.slot() as u64 * 4, &[1u32]);
+    }
+
+    // Draws and dispatches until `end` only run if the query's target was visible
+    pub fn begin(&self, command_buffer: vk::CommandBuffer, query: OcclusionQuery) {
+        self.begin_buffer(command_buffer, self.predicates.get_buffer(), query.slot() as u64 * 4, false);
+    }
+
+    // Predicated on any buffer created with CONDITIONAL_RENDERING_EXT usage, e.g. written by a culling shader. `offset` must
+    // be a multiple of 4, `inverted` runs them when the value is zero instead.
+    pub fn begin_buffer(&self, command_buffer: vk::CommandBuffer, buffer: vk::Buffer, offset: u64, inverted: bool) {
+        let flags = match inverted {
+            true => vk::ConditionalRenderingFlagsEXT::INVERTED,
+            false => vk::ConditionalRenderingFlagsEXT::empty()
+        };
+        let begin_info = vk::ConditionalRenderingBeginInfoEXT::builder()
+            .buffer(buffer)
+            .offset(offset)
+            .flags(flags);
+        unsafe { (self.functions.cmd_begin_conditional_rendering_ext)(command_buffer, &*begin_info) };
+    }
+
+    pub fn end(&self, command_buffer: vk::CommandBuffer) {
+        unsafe { (self.functions.cmd_end_conditional_rendering_ext)(command_buffer) };
+    }
+
+    // Recorded outside of any render pass after the queries in `slots` ended, for the draws of the next frame
+    pub fn record_update(&self, device: &ash::Device, command_buffer: vk::CommandBuffer, pool: vk::QueryPool, slots: &[usize]) {
+        if slots.is_empty() {
+            return;
+        }
+
+        let read_barrier = [vk::MemoryBarrier::builder()
+            .src_access_mask(vk::AccessFlags::CONDITIONAL_RENDERING_READ_EXT)
+            .dst_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+            .build()
+        ];
+        let write_barrier = [vk::MemoryBarrier::builder()
+            .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+            .dst_access_mask(vk::AccessFlags::CONDITIONAL_RENDERING_READ_EXT)
+            .build()
+        ];
+
+        unsafe {
+            device.cmd_pipeline_barrier(command_buffer, vk::PipelineStageFlags::CONDITIONAL_RENDERING_EXT, vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(), &read_barrier, &[], &[]);
+
+            // Consecutive slots are copied together
+            let mut start = 0;
+            while start < slots.len() {
+                let mut end = start + 1;
+                while end < slots.len() && slots[end] == slots[end - 1] + 1 {
+                    end += 1;
+                }
+                device.cmd_copy_query_pool_results(command_buffer, pool, slots[start] as u32, (end - start) as u32, self.predicates.get_buffer(),
+                    slots[start] as u64 * 4, 4, vk::QueryResultFlags::WAIT);
+                start = end;
+            }
+
+            device.cmd_pipeline_barrier(command_buffer, vk::PipelineStageFlags::TRANSFER, vk::PipelineStageFlags::CONDITIONAL_RENDERING_EXT,
+                vk::DependencyFlags::empty(), &write_barrier, &[], &[]);
+        }
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device, allocator: &mut Allocator) {
+        self.predicates.destroy(device, allocator);
+    }
+}
\ No newline at end of file
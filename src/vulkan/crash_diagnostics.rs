@@ -0,0 +1,309 @@
+use std::ffi::{CStr, CString};
+use std::fmt::Write;
+
+use ash::extensions::{ext, nv};
+use ash::vk;
+use gpu_allocator::vulkan::*;
+use gpu_allocator::MemoryLocation;
+
+#[cfg(feature = "tracy")]
+use super::gpu_profiler::GpuProfiler;
+
+// Largest allocations listed in the report, the breakdown of a full scene runs into the hundreds
+const REPORTED_ALLOCATIONS: usize = 24;
+
+// Device extensions that tell how far the GPU got before it was lost, enabled on devices that have them. Neither is
+// needed for the rest of the report.
+pub fn device_extensions(instance: &ash::Instance, physical_device: vk::PhysicalDevice) -> Vec<&'static CStr> {
+    let extensions = match unsafe { instance.enumerate_device_extension_properties(physical_device) } {
+        Ok(extensions) => extensions,
+        Err(_) => return vec![]
+    };
+    let supported = |name: &CStr| extensions.iter().any(|extension| unsafe { CStr::from_ptr(extension.extension_name.as_ptr()) } == name);
+
+    [nv::DeviceDiagnosticCheckpoints::name(), vk::AmdBufferMarkerFn::name()]
+        .into_iter()
+        .filter(|&name| supported(name))
+        .collect()
+}
+
+// Two markers per frame slot written by VK_AMD_buffer_marker, the pass that began last and the last pass whose commands
+// had all finished when the next one began. The buffer is host visible, so it can still be read after the loss.
+struct BufferMarkers {
+    fp: vk::AmdBufferMarkerFn,
+    buffer: vk::Buffer,
+    allocation: Allocation,
+}
+
+impl BufferMarkers {
+    fn read(&self, slot: usize) -> (u32, u32) {
+        let markers = self.allocation.mapped_slice().expect("Marker buffer isn't mapped!");
+        let marker = |index: usize| u32::from_ne_bytes(markers[index * 4..index * 4 + 4].try_into().unwrap());
+        (marker(slot * 2), marker(slot * 2 + 1))
+    }
+
+    fn clear(&mut self, slot: usize) {
+        let markers = self.allocation.mapped_slice_mut().expect("Marker buffer isn't mapped!");
+        markers[slot * 8..slot * 8 + 8].fill(0);
+    }
+}
+
+// Keeps track of what every frame slot's command buffer does, so a lost device (a GPU hang or crash) can be narrowed
+// down to the passes that were running. See `report` for what ends up in the dump.
+pub struct CrashDiagnostics {
+    debug_utils: ext::DebugUtils,
+    // `None` without the vendor extension
+    checkpoints: Option<nv::DeviceDiagnosticCheckpoints>,
+    markers: Option<BufferMarkers>,
+    // Passes recorded into the command buffers of every frame slot, in order
+    frames: Vec<Vec<&'static str>>,
+    // Fence each slot's command buffer was last submitted with, null until it is
+    fences: Vec<vk::Fence>,
+    // Label of the last submission to the graphics queue and its frame slot, the transfer queue isn't submitted to per frame
+    last_submission: Option<(String, usize)>,
+}
+
+impl CrashDiagnostics {
+    // `device` has to be created with whatever `device_extensions` returned
+    pub fn new(entry: &ash::Entry, instance: &ash::Instance, device: &ash::Device, physical_device: vk::PhysicalDevice, allocator: &mut Allocator,
+        frame_count: usize
+    ) -> Result<Self, vk::Result> {
+        let extensions = device_extensions(instance, physical_device);
+
+        let checkpoints = match extensions.contains(&nv::DeviceDiagnosticCheckpoints::name()) {
+            true => Some(nv::DeviceDiagnosticCheckpoints::new(instance, device)),
+            false => None
+        };
+
+        let markers = match extensions.contains(&vk::AmdBufferMarkerFn::name()) {
+            true => {
+                let fp = vk::AmdBufferMarkerFn::load(|name| unsafe {
+                    std::mem::transmute(instance.get_device_proc_addr(device.handle(), name.as_ptr()))
+                });
+                let buffer_info = vk::BufferCreateInfo::builder()
+                    .size(frame_count as u64 * 8)
+                    .usage(vk::BufferUsageFlags::TRANSFER_DST)
+                    .sharing_mode(vk::SharingMode::EXCLUSIVE);
+                let buffer = unsafe { device.create_buffer(&buffer_info, None)? };
+                let requirements = unsafe { device.get_buffer_memory_requirements(buffer) };
+                let allocation = allocator.allocate(&AllocationCreateDesc {
+                    requirements,
+                    location: MemoryLocation::GpuToCpu,
+                    linear: true,
+                    name: "Crash Markers"
+                }).expect("Failed to allocate memory for the crash markers!");
+                unsafe { device.bind_buffer_memory(buffer, allocation.memory(), allocation.offset())? };
+
+                let mut markers = BufferMarkers { fp, buffer, allocation };
+                for slot in 0..frame_count {
+                    markers.clear(slot);
+                }
+                Some(markers)
+            },
+            false => None
+        };
+
+        if checkpoints.is_none() && markers.is_none() {
+            tracing::debug!("Device has no checkpoints or buffer markers, a lost device is only reported from the host's side");
+        }
+
+        Ok(Self {
+            debug_utils: ext::DebugUtils::new(entry, instance),
+            checkpoints,
+            markers,
+            frames: vec![vec![]; frame_count],
+            fences: vec![vk::Fence::null(); frame_count],
+            last_submission: None
+        })
+    }
+
+    // Marks the passes of a command buffer being recorded for `slot`, hand the result of `Breadcrumbs::finish` to
+    // `recorded` afterwards. A slot's frame can be split over several command buffers submitted together, `first` is the
+    // number of passes recorded into the ones before this one.
+    pub fn breadcrumbs(&self, slot: usize, command_buffer: vk::CommandBuffer, first: usize) -> Breadcrumbs<'_> {
+        Breadcrumbs {
+            diagnostics: self,
+            slot,
+            command_buffer,
+            first,
+            passes: vec![],
+            #[cfg(feature = "tracy")]
+            profiler: None,
+            #[cfg(feature = "tracy")]
+            last: true
+        }
+    }
+
+    // The passes of all the slot's command buffers, in submission order
+    pub fn recorded(&mut self, slot: usize, passes: Vec<&'static str>) {
+        self.frames[slot] = passes;
+    }
+
+    // Passes last recorded for `slot`
+    pub fn passes(&self, slot: usize) -> &[&'static str] {
+        &self.frames[slot]
+    }
+
+    // Call right before the slot's command buffer is submitted, once its previous submission has finished
+    pub fn submitting(&mut self, queue: vk::Queue, slot: usize, fence: vk::Fence, frame_index: u64) {
+        self.fences[slot] = fence;
+        if let Some(markers) = &mut self.markers {
+            markers.clear(slot);
+        }
+
+        let label = format!("Frame {}", frame_index);
+        let label_name = CString::new(label.as_str()).unwrap();
+        let label_info = vk::DebugUtilsLabelEXT::builder().label_name(&label_name);
+        unsafe { self.debug_utils.queue_insert_debug_utils_label(queue, &label_info) };
+        self.last_submission = Some((label, slot));
+    }
+
+    // The last submission, where the checkpoints and markers say the GPU was, the passes of every slot whose fence hadn't
+    // signaled yet and the largest live allocations
+    pub fn report(&self, device: &ash::Device, queue: vk::Queue, allocator: &Allocator) -> String {
+        let mut report = String::new();
+
+        let last_submission = match &self.last_submission {
+            Some((label, slot)) => format!("last submitted \"{}\" from frame slot {}", label, slot),
+            None => "nothing submitted yet".to_string()
+        };
+        writeln!(report, "Graphics queue: {}", last_submission).unwrap();
+
+        if let Some(checkpoints) = &self.checkpoints {
+            let mut data = unsafe { vec![vk::CheckpointDataNV::default(); checkpoints.get_queue_checkpoint_data_len(queue)] };
+            unsafe { checkpoints.get_queue_checkpoint_data(queue, &mut data) };
+            writeln!(report, "Checkpoints reached:").unwrap();
+            for checkpoint in &data {
+                let (slot, marker) = decode_checkpoint(checkpoint.p_checkpoint_marker);
+                writeln!(report, "  {} of frame slot {} at {:?}", self.pass_name(slot, marker), slot, checkpoint.stage).unwrap();
+            }
+        }
+
+        if let Some(markers) = &self.markers {
+            writeln!(report, "Buffer markers:").unwrap();
+            for slot in 0..self.frames.len() {
+                let (began, finished) = markers.read(slot);
+                writeln!(report, "  Frame slot {}: began {}, finished up to {}", slot, self.pass_name(slot, began),
+                    self.pass_name(slot, finished)).unwrap();
+            }
+        }
+
+        writeln!(report, "In flight:").unwrap();
+        for (slot, (passes, &fence)) in self.frames.iter().zip(&self.fences).enumerate() {
+            // A lost device fails the query instead of answering it, so anything but a signaled fence counts
+            if fence != vk::Fence::null() && !matches!(unsafe { device.get_fence_status(fence) }, Ok(true)) {
+                writeln!(report, "  Frame slot {}: {}", slot, passes.join(", ")).unwrap();
+            }
+        }
+
+        write!(report, "{:.*?}", REPORTED_ALLOCATIONS, allocator).unwrap();
+        report
+    }
+
+    // Markers count the passes from 1, 0 is a slot that hasn't begun any
+    fn pass_name(&self, slot: usize, marker: u32) -> String {
+        match self.frames.get(slot).and_then(|passes| passes.get((marker as usize).wrapping_sub(1))) {
+            Some(name) => format!("\"{}\"", name),
+            None => "nothing".to_string()
+        }
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device, allocator: &mut Allocator) {
+        if let Some(markers) = self.markers.take() {
+            allocator.free(markers.allocation).expect("Failed to free crash marker memory!");
+            unsafe { device.destroy_buffer(markers.buffer, None) };
+        }
+    }
+}
+
+// Checkpoint markers are opaque pointers, the slot and pass are packed into the pointer's value instead of pointing anywhere
+fn encode_checkpoint(slot: usize, marker: u32) -> *const std::ffi::c_void {
+    ((slot << 16) | marker as usize) as *const std::ffi::c_void
+}
+
+fn decode_checkpoint(pointer: *mut std::ffi::c_void) -> (usize, u32) {
+    let value = pointer as usize;
+    (value >> 16, (value & 0xffff) as u32)
+}
+
+// Splits one command buffer into named passes. Each pass is wrapped in a debug label, so captures and validation messages
+// name it too, and marked with a checkpoint and buffer markers where the device has them.
+pub struct Breadcrumbs<'a> {
+    diagnostics: &'a CrashDiagnostics,
+    slot: usize,
+    command_buffer: vk::CommandBuffer,
+    first: usize,
+    passes: Vec<&'static str>,
+    #[cfg(feature = "tracy")]
+    profiler: Option<&'a GpuProfiler>,
+    // Whether the slot's last command buffer, which times the end of the frame
+    #[cfg(feature = "tracy")]
+    last: bool,
+}
+
+impl<'a> Breadcrumbs<'a> {
+    // Times every pass for Tracy as well, call before the first pass. The first command buffer of the slot resets the
+    // queries, the `last` one times where the frame ends.
+    #[cfg(feature = "tracy")]
+    pub fn profile(&mut self, profiler: Option<&'a GpuProfiler>, last: bool) {
+        if let Some(profiler) = profiler.filter(|_| self.first == 0) {
+            profiler.record_reset(self.command_buffer, self.slot);
+        }
+        self.profiler = profiler;
+        self.last = last;
+    }
+
+    // Ends the previous pass
+    pub fn pass(&mut self, name: &'static str) {
+        let diagnostics = self.diagnostics;
+        let marker = (self.first + self.passes.len()) as u32 + 1;
+        let label_name = CString::new(name).unwrap();
+        let label = vk::DebugUtilsLabelEXT::builder().label_name(&label_name);
+
+        unsafe {
+            if !self.passes.is_empty() {
+                diagnostics.debug_utils.cmd_end_debug_utils_label(self.command_buffer);
+            }
+            diagnostics.debug_utils.cmd_begin_debug_utils_label(self.command_buffer, &label);
+
+            if let Some(checkpoints) = &diagnostics.checkpoints {
+                checkpoints.cmd_set_checkpoint(self.command_buffer, encode_checkpoint(self.slot, marker));
+            }
+            // The bottom of pipe marker lands once everything recorded before this pass has finished
+            if let Some(markers) = &diagnostics.markers {
+                let offset = self.slot as u64 * 8;
+                (markers.fp.cmd_write_buffer_marker_amd)(self.command_buffer, vk::PipelineStageFlags::TOP_OF_PIPE, markers.buffer, offset,
+                    marker);
+                (markers.fp.cmd_write_buffer_marker_amd)(self.command_buffer, vk::PipelineStageFlags::BOTTOM_OF_PIPE, markers.buffer,
+                    offset + 4, marker - 1);
+            }
+        }
+        #[cfg(feature = "tracy")]
+        if let Some(profiler) = self.profiler {
+            profiler.record_timestamp(self.command_buffer, self.slot, self.first + self.passes.len());
+        }
+
+        self.passes.push(name);
+    }
+
+    // Ends the last pass, the names go to `CrashDiagnostics::recorded`
+    pub fn finish(self) -> Vec<&'static str> {
+        if self.passes.is_empty() {
+            return self.passes;
+        }
+
+        unsafe {
+            self.diagnostics.debug_utils.cmd_end_debug_utils_label(self.command_buffer);
+            if let Some(markers) = &self.diagnostics.markers {
+                (markers.fp.cmd_write_buffer_marker_amd)(self.command_buffer, vk::PipelineStageFlags::BOTTOM_OF_PIPE, markers.buffer,
+                    self.slot as u64 * 8 + 4, (self.first + self.passes.len()) as u32);
+            }
+        }
+        // The end of any other command buffer is where the next one's first pass begins, which has its own timestamp
+        #[cfg(feature = "tracy")]
+        if let Some(profiler) = self.profiler.filter(|_| self.last) {
+            profiler.record_timestamp(self.command_buffer, self.slot, self.first + self.passes.len());
+        }
+        self.passes
+    }
+}
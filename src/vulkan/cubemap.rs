@@ -0,0 +1,240 @@
+use ash::vk;
+use gpu_allocator::vulkan::Allocator;
+
+use super::camera::{CameraUniform, ClearSettings};
+use super::descriptor_allocator::DescriptorAllocator;
+use super::descriptors::Descriptors;
+use super::image::{Image, create_sampler};
+use super::pipeline::{Pipeline, PipelineConfig};
+use super::post::SCENE_FORMATS;
+use super::render_pass::RenderPass;
+use super::renderer::VulkanRenderer;
+use super::swapchain::VulkanSwapchain;
+use super::uniform_buffer::UniformBuffer;
+
+// View matrices for the six cube faces, in the +X, -X, +Y, -Y, +Z, -Z order of cube array layers.
+// shaders/shadow_cube.vert builds the same faces from gl_ViewIndex.
+pub fn cube_face_views(position: uv::Vec3) -> [uv::Mat4; 6] {
+    let faces = [
+        (uv::Vec3::unit_x(), -uv::Vec3::unit_y()),
+        (-uv::Vec3::unit_x(), -uv::Vec3::unit_y()),
+        (uv::Vec3::unit_y(), uv::Vec3::unit_z()),
+        (-uv::Vec3::unit_y(), -uv::Vec3::unit_z()),
+        (uv::Vec3::unit_z(), -uv::Vec3::unit_y()),
+        (-uv::Vec3::unit_z(), -uv::Vec3::unit_y()),
+    ];
+
+    faces.map(|(forward, up)| uv::Mat4::look_at(position, position + forward, up))
+}
+
+// 90 degree projection covering one face. Cube faces are addressed with y pointing down, so the y flip of the
+// Vulkan projection is undone, which also flips the winding of everything drawn with it.
+pub fn cube_face_projection(near: f32, far: f32) -> uv::Mat4 {
+    uv::Mat4::from_nonuniform_scale(uv::Vec3::new(1.0, -1.0, 1.0))
+        * uv::projection::rh_yup::perspective_vk(std::f32::consts::FRAC_PI_2, 1.0, near, far)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CaptureMode {
+    // Redrawn every frame, for content that moves around the capture point
+    EveryFrame,
+    // Drawn once after creation and again after each `request_capture`, the cubemap keeps its faces in between
+    OnDemand,
+}
+
+// Environment map rendered from `position` at runtime. The faces are drawn one after another in six passes with
+// the scene pipeline, the G-buffer attachments besides the color are shared by all faces and thrown away.
+pub struct CubemapCapture {
+    pub position: uv::Vec3,
+    pub near: f32,
+    pub far: f32,
+    pub clear: ClearSettings,
+    pub mode: CaptureMode,
+    pending: bool,
+    pub size: u32,
+    // HDR scene color, viewed as a cube for sampling
+    pub image: Image,
+    face_views: Vec<vk::ImageView>,
+    attachments: Vec<Image>,
+    depth: Image,
+    pub renderpass: vk::RenderPass,
+    framebuffers: Vec<vk::Framebuffer>,
+    pub sampler: vk::Sampler,
+    pub pipeline: Pipeline,
+    // Six per swapchain image, face by face
+    camera_buffers: Vec<UniformBuffer<CameraUniform>>,
+    camera_sets: Vec<vk::DescriptorSet>,
+}
+
+impl CubemapCapture {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        device: &ash::Device,
+        allocator: &mut Allocator,
+        descriptors: &mut DescriptorAllocator,
+        swapchain: &VulkanSwapchain,
+        scene_set_layouts: &[vk::DescriptorSetLayout],
+        position: uv::Vec3,
+        size: u32,
+        mode: CaptureMode,
+    ) -> Result<Self, vk::Result> {
+        let extent = vk::Extent2D { width: size, height: size };
+        let image = Image::new_layered(device, allocator, extent, SCENE_FORMATS[0],
+            vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_SRC, vk::ImageAspectFlags::COLOR,
+            6, vk::ImageViewType::CUBE, "Cubemap Capture")?;
+        let mut attachments = Vec::with_capacity(SCENE_FORMATS.len() - 1);
+        for &format in &SCENE_FORMATS[1..] {
+            attachments.push(Image::new(device, allocator, extent, format, vk::ImageUsageFlags::COLOR_ATTACHMENT, vk::ImageAspectFlags::COLOR,
+                "Cubemap Capture")?);
+        }
+        let depth = Image::new_depth_stencil(device, allocator, extent, vk::ImageUsageFlags::empty(), "Cubemap Capture")?;
+
+        let renderpass = RenderPass::init_offscreen(device, &SCENE_FORMATS, Some(depth.format))?;
+
+        let mut face_views = Vec::with_capacity(6);
+        let mut framebuffers = Vec::with_capacity(6);
+        for face in 0..6 {
+            let view = image.create_layer_view(device, face)?;
+            let mut framebuffer_attachments = vec![view];
+            framebuffer_attachments.extend(attachments.iter().map(|attachment| attachment.view));
+            framebuffer_attachments.push(depth.view);
+            let framebuffer_info = vk::FramebufferCreateInfo::builder()
+                .render_pass(renderpass)
+                .attachments(&framebuffer_attachments)
+                .width(size)
+                .height(size)
+                .layers(1);
+            framebuffers.push(unsafe { device.create_framebuffer(&framebuffer_info, None)? });
+            face_views.push(view);
+        }
+
+        let config = PipelineConfig {
+            front_face: vk::FrontFace::COUNTER_CLOCKWISE,
+            ..PipelineConfig::basic(scene_set_layouts)
+        };
+        let pipeline = Pipeline::new(device, swapchain, &renderpass, &config)?;
+
+        let camera_sets = descriptors.allocate_many(device, scene_set_layouts[0], swapchain.image_count * 6)?;
+        let mut camera_buffers = Vec::with_capacity(camera_sets.len());
+        for set in &camera_sets {
+            let camera_buffer = UniformBuffer::<CameraUniform>::new(device, allocator);
+            Descriptors::write_buffer(device, *set, 0, vk::DescriptorType::UNIFORM_BUFFER, camera_buffer.descriptor_info());
+            camera_buffers.push(camera_buffer);
+        }
+
+        let sampler = create_sampler(device, vk::Filter::LINEAR, vk::SamplerAddressMode::CLAMP_TO_EDGE)?;
+
+        Ok(Self {
+            position,
+            near: 0.1,
+            far: 100.0,
+            clear: ClearSettings::default(),
+            mode,
+            pending: true,
+            size,
+            image,
+            face_views,
+            attachments,
+            depth,
+            renderpass,
+            framebuffers,
+            sampler,
+            pipeline,
+            camera_buffers,
+            camera_sets
+        })
+    }
+
+    pub fn request_capture(&mut self) {
+        self.pending = true;
+    }
+
+    // Whether the faces get drawn in the frame recorded next
+    pub fn needs_capture(&self) -> bool {
+        self.mode == CaptureMode::EveryFrame || self.pending
+    }
+
+    // Called after the next frame is recorded, the only one that draws the faces of a pending capture
+    pub fn captured(&mut self) {
+        self.pending = false;
+    }
+
+    pub fn update(&mut self, index: usize) {
+        let extent = vk::Extent2D { width: self.size, height: self.size };
+        let projection = cube_face_projection(self.near, self.far);
+        for (face, view) in cube_face_views(self.position).into_iter().enumerate() {
+            let mut uniform = CameraUniform::new(view, projection, self.position, extent);
+            // Lights are only clustered for the main camera
+            uniform.near_far = uv::Vec4::new(self.near, self.far, 1.0, 0.0);
+            self.camera_buffers[index * 6 + face].update_buffer(&uniform);
+        }
+    }
+
+    pub fn camera_set(&self, index: usize, face: usize) -> vk::DescriptorSet {
+        self.camera_sets[index * 6 + face]
+    }
+
+    // Begins the pass drawing into `face`, which is left ready for sampling at the end of it
+    pub fn begin_face(&self, device: &ash::Device, command_buffer: vk::CommandBuffer, face: usize) {
+        let mut clear_values = vec![vk::ClearValue {
+            color: vk::ClearColorValue {
+                float32: [0.0, 0.0, 0.0, 1.0]
+            }
+        }; SCENE_FORMATS.len()];
+        clear_values[0].color.float32 = self.clear.color.into();
+        clear_values.push(vk::ClearValue {
+            depth_stencil: vk::ClearDepthStencilValue {
+                depth: self.clear.depth,
+                stencil: 0
+            }
+        });
+
+        let extent = vk::Extent2D { width: self.size, height: self.size };
+        let renderpass_begininfo = vk::RenderPassBeginInfo::builder()
+            .render_pass(self.renderpass)
+            .framebuffer(self.framebuffers[face])
+            .render_area(vk::Rect2D {
+                offset: vk::Offset2D { x: 0, y: 0 },
+                extent
+            })
+            .clear_values(&clear_values);
+
+        unsafe { device.cmd_begin_render_pass(command_buffer, &renderpass_begininfo, vk::SubpassContents::INLINE) };
+        VulkanRenderer::set_viewport(device, command_buffer, extent);
+    }
+
+    pub fn descriptor_info(&self) -> vk::DescriptorImageInfo {
+        vk::DescriptorImageInfo {
+            sampler: self.sampler,
+            image_view: self.image.view,
+            image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
+        }
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device, allocator: &mut Allocator, descriptors: &mut DescriptorAllocator) -> Result<(), vk::Result> {
+        for &set in &self.camera_sets {
+            descriptors.free(device, set)?;
+        }
+        for camera_buffer in &mut self.camera_buffers {
+            camera_buffer.destroy(device, allocator);
+        }
+        unsafe {
+            device.destroy_sampler(self.sampler, None);
+            for &framebuffer in &self.framebuffers {
+                device.destroy_framebuffer(framebuffer, None);
+            }
+            for &view in &self.face_views {
+                device.destroy_image_view(view, None);
+            }
+        }
+        self.pipeline.cleanup(device);
+        RenderPass::cleanup(device, self.renderpass);
+        self.depth.destroy(device, allocator);
+        for attachment in &mut self.attachments {
+            attachment.destroy(device, allocator);
+        }
+        self.image.destroy(device, allocator);
+
+        Ok(())
+    }
+}
\ No newline at end of file
@@ -0,0 +1,24 @@
+use ash::vk;
+
+use super::pipeline::{Pipeline, PipelineConfig};
+use super::swapchain::VulkanSwapchain;
+
+pub const CUTOUT_FRAG: &[u32] = vk_shader_macros::include_glsl!("./shaders/basic.frag", kind: frag, define: CUTOUT);
+pub const ALPHA_TO_COVERAGE_FRAG: &[u32] = vk_shader_macros::include_glsl!("./shaders/basic.frag", kind: frag, define: ALPHA_TO_COVERAGE);
+
+// Scene pipeline of `Material::Cutout`. When `scene_config` draws into multisampled targets the alpha becomes sample
+// coverage, which smooths the edges like MSAA does for geometry, otherwise fragments below the cutoff are discarded.
+// Everything but the fragment shader and coverage comes from the basic scene pipeline's `scene_config`.
+pub fn cutout_pipeline(device: &ash::Device, swapchain: &VulkanSwapchain, renderpass: &vk::RenderPass, scene_config: &PipelineConfig
+) -> Result<Pipeline, vk::Result> {
+    let alpha_to_coverage = scene_config.samples != vk::SampleCountFlags::TYPE_1;
+    let config = PipelineConfig {
+        fragment_shader: match alpha_to_coverage {
+            true => ALPHA_TO_COVERAGE_FRAG,
+            false => CUTOUT_FRAG
+        },
+        alpha_to_coverage,
+        ..*scene_config
+    };
+    Pipeline::new(device, swapchain, renderpass, &config)
+}
\ No newline at end of file
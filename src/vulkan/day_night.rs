@@ -0,0 +1,128 @@
+use super::atmosphere::Atmosphere;
+use super::lights::DirectionalLight;
+use super::renderer::VulkanRenderer;
+
+const HOURS_PER_DAY: f32 = 24.0;
+
+// What a day went through during `DayNightCycle::advance`, for gameplay to react to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DayEvent {
+    Sunrise,
+    Sunset,
+    // Midnight passed, `DayNightCycle::day` counts up
+    NewDay,
+}
+
+// Time of day moving the sun across the sky. Applied to a renderer it sets the atmosphere's sun, a moon opposite of it
+// that lights the night, the night's ambient light and the exposure. Gameplay owns it: it calls `advance` once per
+// frame (or tick) and `apply` with the renderer locked, and may jump to any time or pause it in between.
+#[derive(Clone, Copy, Debug)]
+pub struct DayNightCycle {
+    // Real seconds a whole day takes
+    pub day_length: f32,
+    pub paused: bool,
+    // Radians, tilts the sun's path away from straight overhead towards +z
+    pub latitude: f32,
+    // Radians around the y axis, 0.0 rises the sun in +x
+    pub heading: f32,
+    // The sun's direction is replaced by the cycle's
+    pub atmosphere: Atmosphere,
+    pub moon_color: uv::Vec3,
+    pub moon_intensity: f32,
+    // Flat ambient light at night, when the sky has none
+    pub night_ambient: uv::Vec3,
+    // `CompositeSettings::exposure` at noon and at midnight, the night is brightened to stay visible
+    pub day_exposure: f32,
+    pub night_exposure: f32,
+    // In hours, 12.0 is noon
+    time: f32,
+    day: u32,
+}
+
+impl Default for DayNightCycle {
+    fn default() -> Self {
+        Self {
+            day_length: 600.0,
+            paused: false,
+            latitude: 30f32.to_radians(),
+            heading: 0.0,
+            atmosphere: Atmosphere::default(),
+            moon_color: uv::Vec3::new(0.6, 0.7, 1.0),
+            moon_intensity: 0.3,
+            night_ambient: uv::Vec3::new(0.02, 0.025, 0.04),
+            day_exposure: 1.0,
+            night_exposure: 4.0,
+            time: 9.0,
+            day: 0
+        }
+    }
+}
+
+impl DayNightCycle {
+    pub fn new(day_length: f32, time: f32) -> Self {
+        let mut cycle = Self { day_length, ..Self::default() };
+        cycle.set_time(time);
+        cycle
+    }
+
+    pub fn time(&self) -> f32 {
+        self.time
+    }
+
+    // Jumps to `time` hours of the current day, without events
+    pub fn set_time(&mut self, time: f32) {
+        self.time = time.rem_euclid(HOURS_PER_DAY);
+    }
+
+    // Days passed since the start
+    pub fn day(&self) -> u32 {
+        self.day
+    }
+
+    // Moves the time on by `delta` real seconds
+    pub fn advance(&mut self, delta: f32) -> Vec<DayEvent> {
+        if self.paused || self.day_length <= 0.0 {
+            return Vec::new();
+        }
+
+        let mut events = Vec::new();
+        let was_up = self.sun_direction().y > 0.0;
+        let time = self.time + delta / self.day_length * HOURS_PER_DAY;
+        if time >= HOURS_PER_DAY {
+            self.day += (time / HOURS_PER_DAY) as u32;
+            events.push(DayEvent::NewDay);
+        }
+        self.time = time.rem_euclid(HOURS_PER_DAY);
+
+        match (was_up, self.sun_direction().y > 0.0) {
+            (false, true) => events.push(DayEvent::Sunrise),
+            (true, false) => events.push(DayEvent::Sunset),
+            _ => {}
+        }
+        events
+    }
+
+    // Towards the sun, which rises at 6, is highest at noon and sets at 18
+    pub fn sun_direction(&self) -> uv::Vec3 {
+        let hour_angle = (self.time / HOURS_PER_DAY - 0.5) * std::f32::consts::TAU;
+        let height = hour_angle.cos();
+        let (x, y, z) = (-hour_angle.sin(), height * self.latitude.cos(), height * self.latitude.sin());
+        uv::Vec3::new(x * self.heading.cos() - z * self.heading.sin(), y, x * self.heading.sin() + z * self.heading.cos())
+    }
+
+    // 1.0 during the day, 0.0 at night, blending around sunrise and sunset
+    pub fn daylight(&self) -> f32 {
+        let t = ((self.sun_direction().y + 0.1) / 0.2).clamp(0.0, 1.0);
+        t * t * (3.0 - 2.0 * t)
+    }
+
+    // Overwrites the renderer's atmosphere, sun, ambient light and exposure
+    pub fn apply(&self, renderer: &mut VulkanRenderer) {
+        let sun_direction = self.sun_direction();
+        let night = 1.0 - self.daylight();
+        renderer.atmosphere = Some(Atmosphere { sun_direction, ..self.atmosphere });
+        renderer.sun = Some(DirectionalLight::new(-sun_direction, self.moon_color, self.moon_intensity * night));
+        renderer.ambient_light = self.night_ambient * night;
+        renderer.post_process.composite_settings.exposure = self.day_exposure * (1.0 - night) + self.night_exposure * night;
+    }
+}
\ No newline at end of file
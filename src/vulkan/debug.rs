@@ -11,11 +11,16 @@ unsafe extern "system" fn vulkan_debug_utils_callback(
     p_callback_data: *const vk::DebugUtilsMessengerCallbackDataEXT,
     _p_user_data: *mut ffi::c_void,
 ) -> vk::Bool32 {
-    let message = ffi::CStr::from_ptr((*p_callback_data).p_message);
-    let severity = format!("{:?}", message_severity).to_lowercase();
-    let ty = format!("{:?}", message_type).to_lowercase();
-
-    println!("[Debug][{}][{}] {:?}", severity, ty, message);
+    let message = ffi::CStr::from_ptr((*p_callback_data).p_message).to_string_lossy();
+    let kind = format!("{:?}", message_type).to_lowercase();
+
+    // Logged under the "vulkan" target, so the layers can be filtered apart from the engine, e.g. `REVERIE_LOG=vulkan=info`
+    match message_severity {
+        vk::DebugUtilsMessageSeverityFlagsEXT::ERROR => tracing::error!(target: "vulkan", kind, "{}", message),
+        vk::DebugUtilsMessageSeverityFlagsEXT::WARNING => tracing::warn!(target: "vulkan", kind, "{}", message),
+        vk::DebugUtilsMessageSeverityFlagsEXT::INFO => tracing::info!(target: "vulkan", kind, "{}", message),
+        _ => tracing::trace!(target: "vulkan", kind, "{}", message)
+    }
 
     vk::FALSE
 }
@@ -30,9 +35,10 @@ impl VulkanDebug {
         let debug_utils = ext::DebugUtils::new(entry, instance);
 
         let messenger_info = vk::DebugUtilsMessengerCreateInfoEXT {
+            // Everything is reported, the log filter decides what's shown
             message_severity: vk::DebugUtilsMessageSeverityFlagsEXT::WARNING
-                //| vk::DebugUtilsMessageSeverityFlagsEXT::VERBOSE
-                //| vk::DebugUtilsMessageSeverityFlagsEXT::INFO
+                | vk::DebugUtilsMessageSeverityFlagsEXT::VERBOSE
+                | vk::DebugUtilsMessageSeverityFlagsEXT::INFO
                 | vk::DebugUtilsMessageSeverityFlagsEXT::ERROR,
             message_type: vk::DebugUtilsMessageTypeFlagsEXT::GENERAL
                 | vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE
@@ -0,0 +1,133 @@
+use std::collections::HashMap;
+
+use ash::vk;
+
+use super::descriptors::Descriptors;
+
+// Descriptors of each type a pool holds per set it can allocate, generous enough for every layout the renderer uses
+pub const DEFAULT_POOL_RATIOS: [(vk::DescriptorType, f32); 5] = [
+    (vk::DescriptorType::UNIFORM_BUFFER, 1.0),
+    (vk::DescriptorType::STORAGE_BUFFER, 2.0),
+    (vk::DescriptorType::COMBINED_IMAGE_SAMPLER, 4.0),
+    (vk::DescriptorType::STORAGE_IMAGE, 1.0),
+    (vk::DescriptorType::INPUT_ATTACHMENT, 0.5),
+];
+
+struct Pool {
+    pool: vk::DescriptorPool,
+    allocated: u32,
+}
+
+// Hands out sets from a list of pools, adding a pool whenever the existing ones run out instead of failing.
+// Sets can be freed one by one (dynamic materials, UI textures) or all at once with `reset` (per frame sets),
+// pools that become empty are filled again before new ones are created.
+pub struct DescriptorAllocator {
+    sets_per_pool: u32,
+    pool_ratios: Vec<(vk::DescriptorType, f32)>,
+    pools: Vec<Pool>,
+    // Pools before this one were full the last time they were tried
+    current: usize,
+    owners: HashMap<vk::DescriptorSet, usize>,
+}
+
+impl DescriptorAllocator {
+    pub fn new(sets_per_pool: u32, pool_ratios: &[(vk::DescriptorType, f32)]) -> Self {
+        Self {
+            sets_per_pool,
+            pool_ratios: pool_ratios.to_vec(),
+            pools: vec![],
+            current: 0,
+            owners: HashMap::new()
+        }
+    }
+
+    fn create_pool(&self, device: &ash::Device) -> Result<vk::DescriptorPool, vk::Result> {
+        let pool_sizes: Vec<vk::DescriptorPoolSize> = self.pool_ratios
+            .iter()
+            .map(|&(ty, ratio)| vk::DescriptorPoolSize {
+                ty,
+                descriptor_count: ((ratio * self.sets_per_pool as f32).ceil() as u32).max(1)
+            })
+            .collect();
+
+        Descriptors::create_pool(device, self.sets_per_pool, &pool_sizes)
+    }
+
+    pub fn allocate(&mut self, device: &ash::Device, layout: vk::DescriptorSetLayout) -> Result<vk::DescriptorSet, vk::Result> {
+        loop {
+            if self.current == self.pools.len() {
+                let pool = self.create_pool(device)?;
+                self.pools.push(Pool { pool, allocated: 0 });
+                tracing::debug!("Descriptor allocator grew to {} pools", self.pools.len());
+            }
+
+            let is_new = self.pools[self.current].allocated == 0;
+            match Descriptors::allocate(device, self.pools[self.current].pool, layout, 1) {
+                Ok(sets) => {
+                    self.pools[self.current].allocated += 1;
+                    self.owners.insert(sets[0], self.current);
+                    return Ok(sets[0]);
+                }
+                // Even an empty pool can't hold the layout, another one wouldn't either
+                Err(vk::Result::ERROR_OUT_OF_POOL_MEMORY | vk::Result::ERROR_FRAGMENTED_POOL) if is_new => {
+                    tracing::error!("Descriptor set layout doesn't fit into an empty pool, check the pool ratios");
+                    return Err(vk::Result::ERROR_OUT_OF_POOL_MEMORY);
+                }
+                Err(vk::Result::ERROR_OUT_OF_POOL_MEMORY | vk::Result::ERROR_FRAGMENTED_POOL) => self.current += 1,
+                Err(error) => return Err(error)
+            }
+        }
+    }
+
+    pub fn allocate_many(&mut self, device: &ash::Device, layout: vk::DescriptorSetLayout, amount: usize) -> Result<Vec<vk::DescriptorSet>, vk::Result> {
+        (0..amount).map(|_| self.allocate(device, layout)).collect()
+    }
+
+    // The device must not use the set anymore
+    pub fn free(&mut self, device: &ash::Device, set: vk::DescriptorSet) -> Result<(), vk::Result> {
+        let index = match self.owners.remove(&set) {
+            Some(index) => index,
+            None => {
+                tracing::warn!("Freeing descriptor set {:?} that wasn't allocated here", set);
+                return Ok(());
+            }
+        };
+
+        let pool = &mut self.pools[index];
+        unsafe { device.free_descriptor_sets(pool.pool, &[set])? };
+        pool.allocated -= 1;
+        self.current = self.current.min(index);
+
+        Ok(())
+    }
+
+    // Returns every set at once, the device must not use any of them anymore
+    pub fn reset(&mut self, device: &ash::Device) -> Result<(), vk::Result> {
+        for pool in &mut self.pools {
+            if pool.allocated > 0 {
+                unsafe { device.reset_descriptor_pool(pool.pool, vk::DescriptorPoolResetFlags::empty())? };
+                pool.allocated = 0;
+            }
+        }
+        self.owners.clear();
+        self.current = 0;
+
+        Ok(())
+    }
+
+    pub fn allocated_sets(&self) -> usize {
+        self.owners.len()
+    }
+
+    pub fn pool_count(&self) -> usize {
+        self.pools.len()
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device) {
+        for pool in self.pools.drain(..) {
+            unsafe { device.destroy_descriptor_pool(pool.pool, None) };
+        }
+        self.owners.clear();
+        self.current = 0;
+    }
+}
\ No newline at end of file
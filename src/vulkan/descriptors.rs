@@ -0,0 +1,85 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+
+use ash::vk;
+
+// Type and stages of each binding of a layout, by binding number
+pub type LayoutBindings = Vec<(vk::DescriptorType, vk::ShaderStageFlags)>;
+
+// The bindings of every layout made by `create_layout`. Pipelines check their shaders against them, see
+// `shader_reflection::validate`.
+static LAYOUTS: OnceLock<Mutex<HashMap<vk::DescriptorSetLayout, LayoutBindings>>> = OnceLock::new();
+
+pub struct Descriptors {}
+
+impl Descriptors {
+    pub fn create_pool(logical_device: &ash::Device, max_sets: u32, pool_sizes: &[vk::DescriptorPoolSize]) -> Result<vk::DescriptorPool, vk::Result> {
+        // Sets of optional features (TAA, ...) are freed again when they get turned off
+        let pool_info = vk::DescriptorPoolCreateInfo::builder()
+            .flags(vk::DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET)
+            .max_sets(max_sets)
+            .pool_sizes(pool_sizes);
+
+        unsafe { logical_device.create_descriptor_pool(&pool_info, None) }
+    }
+
+    pub fn create_layout(logical_device: &ash::Device, bindings: &[(vk::DescriptorType, vk::ShaderStageFlags)]) -> Result<vk::DescriptorSetLayout, vk::Result> {
+        let layout_bindings: Vec<vk::DescriptorSetLayoutBinding> = bindings
+            .iter()
+            .enumerate()
+            .map(|(binding, &(descriptor_type, stage_flags))| vk::DescriptorSetLayoutBinding::builder()
+                .binding(binding as u32)
+                .descriptor_type(descriptor_type)
+                .descriptor_count(1)
+                .stage_flags(stage_flags)
+                .build())
+            .collect();
+
+        let layout_info = vk::DescriptorSetLayoutCreateInfo::builder()
+            .bindings(&layout_bindings);
+
+        let layout = unsafe { logical_device.create_descriptor_set_layout(&layout_info, None)? };
+        LAYOUTS.get_or_init(Default::default).lock().unwrap().insert(layout, bindings.to_vec());
+        Ok(layout)
+    }
+
+    // What `layout` was created with, `None` for layouts that didn't come from `create_layout`
+    pub fn layout_bindings(layout: vk::DescriptorSetLayout) -> Option<LayoutBindings> {
+        LAYOUTS.get()?.lock().unwrap().get(&layout).cloned()
+    }
+
+    pub fn allocate(logical_device: &ash::Device, pool: vk::DescriptorPool, layout: vk::DescriptorSetLayout, amount: usize) -> Result<Vec<vk::DescriptorSet>, vk::Result> {
+        let layouts = vec![layout; amount];
+        let allocate_info = vk::DescriptorSetAllocateInfo::builder()
+            .descriptor_pool(pool)
+            .set_layouts(&layouts);
+
+        unsafe { logical_device.allocate_descriptor_sets(&allocate_info) }
+    }
+
+    pub fn write_buffer(logical_device: &ash::Device, set: vk::DescriptorSet, binding: u32, descriptor_type: vk::DescriptorType, info: vk::DescriptorBufferInfo) {
+        let buffer_infos = [info];
+        let writes = [vk::WriteDescriptorSet::builder()
+            .dst_set(set)
+            .dst_binding(binding)
+            .descriptor_type(descriptor_type)
+            .buffer_info(&buffer_infos)
+            .build()
+        ];
+
+        unsafe { logical_device.update_descriptor_sets(&writes, &[]) };
+    }
+
+    pub fn write_image(logical_device: &ash::Device, set: vk::DescriptorSet, binding: u32, descriptor_type: vk::DescriptorType, info: vk::DescriptorImageInfo) {
+        let image_infos = [info];
+        let writes = [vk::WriteDescriptorSet::builder()
+            .dst_set(set)
+            .dst_binding(binding)
+            .descriptor_type(descriptor_type)
+            .image_info(&image_infos)
+            .build()
+        ];
+
+        unsafe { logical_device.update_descriptor_sets(&writes, &[]) };
+    }
+}
\ No newline at end of file
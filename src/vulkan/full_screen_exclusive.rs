@@ -0,0 +1,94 @@
+use std::ffi::CStr;
+
+use ash::vk;
+
+use super::window::VulkanWindow;
+
+// VK_EXT_full_screen_exclusive lets a swapchain take over the display of an exclusive fullscreen window, bypassing the
+// compositor for the lowest presentation latency. Only Windows has it, the instance needs
+// VK_KHR_get_surface_capabilities2 for it.
+pub fn instance_extensions(entry: &ash::Entry) -> Vec<&'static CStr> {
+    if !cfg!(windows) {
+        return vec![];
+    }
+
+    let extensions = entry.enumerate_instance_extension_properties(None).unwrap_or_default();
+    let name = vk::KhrGetSurfaceCapabilities2Fn::name();
+    match extensions.iter().any(|extension| unsafe { CStr::from_ptr(extension.extension_name.as_ptr()) } == name) {
+        true => vec![name],
+        false => vec![]
+    }
+}
+
+// Whether the device can enable the extension, given an instance created with `instance_extensions`
+pub fn supported(entry: &ash::Entry, instance: &ash::Instance, physical_device: vk::PhysicalDevice) -> bool {
+    if instance_extensions(entry).is_empty() {
+        return false;
+    }
+
+    let extensions = unsafe { instance.enumerate_device_extension_properties(physical_device).unwrap_or_default() };
+    let name = vk::ExtFullScreenExclusiveFn::name();
+    extensions.iter().any(|extension| unsafe { CStr::from_ptr(extension.extension_name.as_ptr()) } == name)
+}
+
+// Exclusive mode is application controlled: swapchains are created for the monitor the window is exclusive fullscreen
+// on and acquire it right away, and lose it again when the window leaves fullscreen and the swapchain is recreated.
+pub struct FullScreenExclusive {
+    loader: ash::extensions::ext::FullScreenExclusive,
+    // HMONITOR of the monitor the window owns, kept as an integer so the renderer stays Send
+    monitor: Option<isize>,
+}
+
+impl FullScreenExclusive {
+    pub fn new(instance: &ash::Instance, device: &ash::Device) -> Self {
+        Self {
+            loader: ash::extensions::ext::FullScreenExclusive::new(instance, device),
+            monitor: None
+        }
+    }
+
+    // Takes the monitor from the window, returns whether it changed and the swapchain has to be recreated
+    pub fn update(&mut self, window: &VulkanWindow) -> bool {
+        let monitor = window_monitor(window);
+        let changed = monitor != self.monitor;
+        self.monitor = monitor;
+        changed
+    }
+
+    // Chained into the swapchain create info, `None` while the window isn't exclusive fullscreen
+    pub fn create_info(&self) -> Option<(vk::SurfaceFullScreenExclusiveInfoEXT, vk::SurfaceFullScreenExclusiveWin32InfoEXT)> {
+        let monitor = self.monitor?;
+        Some((
+            vk::SurfaceFullScreenExclusiveInfoEXT::builder()
+                .full_screen_exclusive(vk::FullScreenExclusiveEXT::APPLICATION_CONTROLLED)
+                .build(),
+            vk::SurfaceFullScreenExclusiveWin32InfoEXT::builder()
+                .hmonitor(monitor as vk::HMONITOR)
+                .build()
+        ))
+    }
+
+    // Fails when the window isn't in the foreground, presenting then goes through the compositor as usual
+    pub fn acquire(&self, swapchain: vk::SwapchainKHR) {
+        if self.monitor.is_none() {
+            return;
+        }
+
+        match unsafe { self.loader.acquire_full_screen_exclusive_mode(swapchain) } {
+            Ok(()) => tracing::info!("Acquired exclusive fullscreen"),
+            Err(error) => tracing::warn!("Failed to acquire exclusive fullscreen: {}", error)
+        }
+    }
+}
+
+#[cfg(windows)]
+fn window_monitor(window: &VulkanWindow) -> Option<isize> {
+    use winit::platform::windows::MonitorHandleExtWindows;
+
+    window.exclusive_monitor().map(|monitor| monitor.hmonitor())
+}
+
+#[cfg(not(windows))]
+fn window_monitor(_window: &VulkanWindow) -> Option<isize> {
+    None
+}
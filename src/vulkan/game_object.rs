@@ -1,24 +1,74 @@
+use std::collections::HashMap;
 use std::sync::atomic::{AtomicUsize, Ordering};
 
+use serde::{Deserialize, Serialize};
+
 use super::mesh::Mesh;
+use super::material::Material;
+use super::water::WaterMaterial;
+use super::occlusion::OcclusionQuery;
 
 static OBJECT_COUNTER: AtomicUsize = AtomicUsize::new(0);
 
 pub struct GameObject {
     id: usize,
+    pub name: String,
+    // Id of the game object this one's transform is relative to
+    pub parent: Option<usize>,
     pub mesh: Mesh,
     pub color: uv::Vec3,
-    pub transform2d: Transform2DComponent
+    // From 0.0 to 1.0, only used by `Material::Transparent`
+    pub opacity: f32,
+    // 0.0 is a perfect mirror under screen space reflections, 1.0 fully diffuse
+    pub roughness: f32,
+    pub material: Material,
+    // Id of the lightmap in `MaterialSets` the mesh's second UV channel maps into, used by `Material::Lightmapped`
+    pub lightmap: Option<usize>,
+    // Id of the color texture in `MaterialSets` sampled with the mesh's first UV channel and tinted by `color`. Its alpha
+    // is what `Material::Cutout` tests and transparent materials blend with.
+    pub texture: Option<usize>,
+    // Id of the normal map in `MaterialSets` sampled with the mesh's first UV channel, the vertex normals are used as
+    // they are without one
+    pub normal_map: Option<usize>,
+    // How deep the height in the normal map's alpha goes, in texture coordinates. 0.0 keeps the surface flat, see
+    // `ParallaxQuality`
+    pub parallax_depth: f32,
+    // Alpha below which `Material::Cutout` drops the fragment
+    pub alpha_cutoff: f32,
+    // How much the renderer's `Wind` sways the vertices (foliage), 0.0 for rigid objects
+    pub wind: f32,
+    // Only used by `Material::Water`
+    pub water: WaterMaterial,
+    // Skips the object's scene draws while this query found it hidden the frame before, see
+    // `VulkanRenderer::enable_occlusion_culling`
+    pub occlusion_query: Option<OcclusionQuery>,
+    pub transform3d: Transform3DComponent
 }
 
 impl GameObject {
     pub fn new(mesh: Mesh, color: uv::Vec3) -> Self {
+        let id = OBJECT_COUNTER.fetch_add(1, Ordering::SeqCst);
         Self {
-            id: OBJECT_COUNTER.fetch_add(1, Ordering::SeqCst),
+            id,
+            name: format!("Object {}", id),
+            parent: None,
             mesh,
             color,
-            transform2d: Transform2DComponent {
-                translation: uv::Vec2::default()
+            opacity: 1.0,
+            roughness: 1.0,
+            material: Material::Basic,
+            lightmap: None,
+            texture: None,
+            normal_map: None,
+            parallax_depth: 0.0,
+            alpha_cutoff: 0.5,
+            wind: 0.0,
+            water: WaterMaterial::default(),
+            occlusion_query: None,
+            transform3d: Transform3DComponent {
+                translation: uv::Vec3::zero(),
+                rotation: uv::Rotor3::identity(),
+                scale: uv::Vec3::one()
             }
         }
     }
@@ -28,12 +78,83 @@ impl GameObject {
     }
 }
 
-pub struct Transform2DComponent {
-    pub translation: uv::Vec2,
+// Model matrices with every parent's transform applied, in the same order as `game_objects`.
+// Objects whose parent id isn't in the list are treated as roots.
+pub fn world_matrices(game_objects: &[GameObject]) -> Vec<uv::Mat4> {
+    let indices: HashMap<usize, usize> = game_objects
+        .iter()
+        .enumerate()
+        .map(|(index, game_object)| (game_object.id, index))
+        .collect();
+
+    let mut matrices: Vec<Option<uv::Mat4>> = vec![None; game_objects.len()];
+    for start in 0..game_objects.len() {
+        if matrices[start].is_some() {
+            continue;
+        }
+
+        // Walk up to the root or the first ancestor already resolved, the length check stops at cycles
+        let mut chain = vec![start];
+        let mut matrix = uv::Mat4::identity();
+        while let Some(&parent) = game_objects[chain[chain.len() - 1]].parent.and_then(|id| indices.get(&id)) {
+            if let Some(parent_matrix) = matrices[parent] {
+                matrix = parent_matrix;
+                break;
+            }
+            if chain.len() > game_objects.len() {
+                break;
+            }
+            chain.push(parent);
+        }
+
+        for &index in chain.iter().rev() {
+            matrix = matrix * game_objects[index].transform3d.mat4();
+            matrices[index] = Some(matrix);
+        }
+    }
+
+    matrices.into_iter().map(|matrix| matrix.unwrap_or_else(uv::Mat4::identity)).collect()
+}
+
+// True if `ancestor` is `id` itself or somewhere up its parent chain
+pub fn is_ancestor(game_objects: &[GameObject], ancestor: usize, id: usize) -> bool {
+    let mut current = Some(id);
+    for _ in 0..=game_objects.len() {
+        match current {
+            Some(current_id) if current_id == ancestor => return true,
+            Some(current_id) => {
+                current = game_objects.iter()
+                    .find(|game_object| game_object.id == current_id)
+                    .and_then(|game_object| game_object.parent);
+            }
+            None => return false
+        }
+    }
+    false
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Transform3DComponent {
+    pub translation: uv::Vec3,
+    pub rotation: uv::Rotor3,
+    pub scale: uv::Vec3,
 }
 
-impl Transform2DComponent {
-    pub fn mat2(&self) -> uv::Mat2 {
-        uv::Mat2::identity()
+impl Transform3DComponent {
+    pub fn mat4(&self) -> uv::Mat4 {
+        uv::Mat4::from_translation(self.translation)
+            * self.rotation.into_matrix().into_homogeneous()
+            * uv::Mat4::from_nonuniform_scale(self.scale)
+    }
+
+    // (roll, pitch, yaw) in radians, such that `Rotor3::from_euler_angles` gives the rotation back
+    pub fn euler_angles(&self) -> (f32, f32, f32) {
+        let matrix = self.rotation.into_matrix();
+        let element = |row: usize, column: usize| matrix.cols[column][row];
+        (
+            element(1, 0).atan2(element(1, 1)),
+            (-element(1, 2)).clamp(-1.0, 1.0).asin(),
+            (-element(0, 2)).atan2(element(2, 2))
+        )
     }
 }
\ No newline at end of file
@@ -0,0 +1,367 @@
+use ash::vk;
+use gpu_allocator::vulkan::Allocator;
+
+use super::camera::Ray;
+use super::game_object::Transform3DComponent;
+use super::mesh::Mesh;
+use super::pipeline::{Pipeline, PipelineConfig};
+use super::swapchain::VulkanSwapchain;
+use super::vertex::Vertex;
+
+use crate::utils::any_as_u8_slice;
+
+pub const GIZMO_VERT: &[u32] = vk_shader_macros::include_glsl!("./shaders/gizmo.vert", kind: vert);
+pub const GIZMO_FRAG: &[u32] = vk_shader_macros::include_glsl!("./shaders/gizmo.frag", kind: frag);
+
+const AXIS_COLORS: [uv::Vec4; 3] = [
+    uv::Vec4::new(0.9, 0.2, 0.2, 1.0),
+    uv::Vec4::new(0.3, 0.85, 0.3, 1.0),
+    uv::Vec4::new(0.25, 0.4, 1.0, 1.0),
+];
+const HIGHLIGHT_COLOR: uv::Vec4 = uv::Vec4::new(1.0, 0.85, 0.1, 1.0);
+// Handles are built for a gizmo of length 1, hit testing uses the same proportions
+const SHAFT_LENGTH: f32 = 0.8;
+const SHAFT_WIDTH: f32 = 0.015;
+const TIP_WIDTH: f32 = 0.06;
+const PICK_WIDTH: f32 = 0.08;
+const RING_SEGMENTS: u32 = 48;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GizmoMode {
+    Translate,
+    Rotate,
+    Scale,
+}
+
+// Axes of the space the object's transform is relative to (the world, or its parent), the object's own rotation is ignored
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GizmoAxis {
+    X,
+    Y,
+    Z,
+}
+
+impl GizmoAxis {
+    const ALL: [GizmoAxis; 3] = [GizmoAxis::X, GizmoAxis::Y, GizmoAxis::Z];
+
+    pub fn direction(&self) -> uv::Vec3 {
+        match self {
+            GizmoAxis::X => uv::Vec3::unit_x(),
+            GizmoAxis::Y => uv::Vec3::unit_y(),
+            GizmoAxis::Z => uv::Vec3::unit_z(),
+        }
+    }
+
+    // Turns handles modelled along +X onto this axis
+    fn orientation(&self) -> uv::Mat4 {
+        match self {
+            GizmoAxis::X => uv::Mat4::identity(),
+            GizmoAxis::Y => uv::Mat4::from_rotation_z(std::f32::consts::FRAC_PI_2),
+            GizmoAxis::Z => uv::Mat4::from_rotation_y(-std::f32::consts::FRAC_PI_2),
+        }
+    }
+}
+
+// Mirrors the push constant block of gizmo.vert and gizmo.frag
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct GizmoPushConstants {
+    model: uv::Mat4,
+    color: uv::Vec4,
+}
+
+struct GizmoDrag {
+    axis: GizmoAxis,
+    start: Transform3DComponent,
+    // Position along the axis (translate, scale) or direction in the rotation plane (rotate) where the drag began
+    start_offset: f32,
+    start_direction: uv::Vec3,
+}
+
+// Translate/rotate/scale handles drawn on top of the final image around the selected object. The application forwards
+// cursor rays, hit testing picks the closest handle and dragging edits the object's transform along that axis.
+pub struct Gizmo {
+    pub mode: GizmoMode,
+    // On screen size, as a fraction of the distance to the camera
+    pub size: f32,
+    pub hovered: Option<GizmoAxis>,
+    drag: Option<GizmoDrag>,
+    pipeline: Pipeline,
+    // Handle along +X for every mode, in `GizmoMode` order
+    meshes: [Mesh; 3],
+}
+
+impl Gizmo {
+    pub fn new(device: &ash::Device, allocator: &mut Allocator, swapchain: &VulkanSwapchain, present_renderpass: &vk::RenderPass,
+        camera_set_layout: vk::DescriptorSetLayout
+    ) -> Result<Self, vk::Result> {
+        let set_layouts = [camera_set_layout];
+        let config = PipelineConfig {
+            vertex_shader: GIZMO_VERT,
+            fragment_shader: GIZMO_FRAG,
+            push_constant_size: std::mem::size_of::<GizmoPushConstants>() as u32,
+            depth_test: false,
+            color_attachment_count: 1,
+            cull_mode: vk::CullModeFlags::NONE,
+            ..PipelineConfig::basic(&set_layouts)
+        };
+        let pipeline = Pipeline::new(device, swapchain, present_renderpass, &config)?;
+
+        let mut translate = (vec![], vec![]);
+        add_box(&mut translate, uv::Vec3::new(0.0, -SHAFT_WIDTH, -SHAFT_WIDTH), uv::Vec3::new(SHAFT_LENGTH, SHAFT_WIDTH, SHAFT_WIDTH));
+        add_box(&mut translate, uv::Vec3::new(SHAFT_LENGTH, -TIP_WIDTH, -TIP_WIDTH), uv::Vec3::new(1.0, TIP_WIDTH, TIP_WIDTH));
+
+        let mut scale = (vec![], vec![]);
+        add_box(&mut scale, uv::Vec3::new(0.0, -SHAFT_WIDTH, -SHAFT_WIDTH), uv::Vec3::new(1.0, SHAFT_WIDTH, SHAFT_WIDTH));
+        add_box(&mut scale, uv::Vec3::new(1.0 - TIP_WIDTH * 2.0, -TIP_WIDTH, -TIP_WIDTH), uv::Vec3::new(1.0, TIP_WIDTH, TIP_WIDTH));
+
+        let mut rotate = (vec![], vec![]);
+        add_ring(&mut rotate);
+
+        Ok(Self {
+            mode: GizmoMode::Translate,
+            size: 0.2,
+            hovered: None,
+            drag: None,
+            pipeline,
+            meshes: [
+                create_mesh(device, allocator, &translate)?,
+                create_mesh(device, allocator, &rotate)?,
+                create_mesh(device, allocator, &scale)?,
+            ]
+        })
+    }
+
+    // World space length of the handles, keeping them the same size on screen
+    pub fn scale(&self, origin: uv::Vec3, camera_position: uv::Vec3) -> f32 {
+        (origin - camera_position).mag() * self.size
+    }
+
+    pub fn is_dragging(&self) -> bool {
+        self.drag.is_some()
+    }
+
+    // Closest handle under the ray, if any
+    pub fn hit_test(&self, ray: &Ray, origin: uv::Vec3, scale: f32) -> Option<GizmoAxis> {
+        let mut closest: Option<(f32, GizmoAxis)> = None;
+        for axis in GizmoAxis::ALL {
+            let hit = match self.mode {
+                GizmoMode::Translate | GizmoMode::Scale => {
+                    closest_to_axis(ray, origin, axis.direction()).and_then(|(distance, offset)| {
+                        let gap = (ray.at(distance) - (origin + axis.direction() * offset)).mag();
+                        (distance > 0.0 && (0.0..=scale).contains(&offset) && gap < PICK_WIDTH * scale).then_some(distance)
+                    })
+                },
+                GizmoMode::Rotate => {
+                    intersect_plane(ray, origin, axis.direction()).and_then(|distance| {
+                        let radius = (ray.at(distance) - origin).mag();
+                        ((radius - scale).abs() < PICK_WIDTH * scale).then_some(distance)
+                    })
+                }
+            };
+
+            if let Some(distance) = hit {
+                if closest.is_none_or(|(closest_distance, _)| distance < closest_distance) {
+                    closest = Some((distance, axis));
+                }
+            }
+        }
+
+        closest.map(|(_, axis)| axis)
+    }
+
+    // Returns whether a handle was hit, in which case the click should not select anything else
+    pub fn begin_drag(&mut self, ray: &Ray, transform: &Transform3DComponent, camera_position: uv::Vec3) -> bool {
+        let origin = transform.translation;
+        let axis = match self.hit_test(ray, origin, self.scale(origin, camera_position)) {
+            Some(axis) => axis,
+            None => return false
+        };
+
+        let (start_offset, start_direction) = match self.mode {
+            GizmoMode::Translate | GizmoMode::Scale => {
+                let offset = closest_to_axis(ray, origin, axis.direction()).map_or(0.0, |(_, offset)| offset);
+                (offset, uv::Vec3::zero())
+            },
+            GizmoMode::Rotate => {
+                let direction = intersect_plane(ray, origin, axis.direction())
+                    .map_or(uv::Vec3::zero(), |distance| (ray.at(distance) - origin).normalized());
+                (0.0, direction)
+            }
+        };
+
+        self.hovered = Some(axis);
+        self.drag = Some(GizmoDrag {
+            axis,
+            start: *transform,
+            start_offset,
+            start_direction
+        });
+
+        true
+    }
+
+    // Updates `transform` relative to where the drag started, rays parallel to the handle leave it unchanged
+    pub fn drag(&self, ray: &Ray, transform: &mut Transform3DComponent) {
+        let drag = match &self.drag {
+            Some(drag) => drag,
+            None => return
+        };
+        let origin = drag.start.translation;
+        let axis = drag.axis.direction();
+
+        match self.mode {
+            GizmoMode::Translate => {
+                if let Some((_, offset)) = closest_to_axis(ray, origin, axis) {
+                    transform.translation = drag.start.translation + axis * (offset - drag.start_offset);
+                }
+            },
+            GizmoMode::Scale => {
+                if let Some((_, offset)) = closest_to_axis(ray, origin, axis) {
+                    if drag.start_offset.abs() > f32::EPSILON {
+                        let factor = (offset / drag.start_offset).max(0.01);
+                        let scaling = uv::Vec3::one() + axis * (factor - 1.0);
+                        transform.scale = drag.start.scale * scaling;
+                    }
+                }
+            },
+            GizmoMode::Rotate => {
+                if let Some(distance) = intersect_plane(ray, origin, axis) {
+                    let direction = (ray.at(distance) - origin).normalized();
+                    // Opposite directions have no unique rotation between them
+                    if direction.dot(drag.start_direction) > -0.9999 {
+                        transform.rotation = uv::Rotor3::from_rotation_between(drag.start_direction, direction) * drag.start.rotation;
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn end_drag(&mut self) {
+        self.drag = None;
+    }
+
+    // Recorded inside the present render pass, on top of everything else. `space` is the parent's world matrix, `origin` and
+    // `camera_position` are relative to it.
+    #[allow(clippy::too_many_arguments)]
+    pub fn record(&self, device: &ash::Device, command_buffer: vk::CommandBuffer, camera_set: vk::DescriptorSet, space: uv::Mat4, origin: uv::Vec3,
+        camera_position: uv::Vec3
+    ) {
+        let scale = self.scale(origin, camera_position);
+        let active = self.drag.as_ref().map(|drag| drag.axis).or(self.hovered);
+        let mesh = &self.meshes[self.mode as usize];
+
+        unsafe {
+            device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, self.pipeline.pipeline);
+            device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::GRAPHICS, self.pipeline.layout, 0, &[camera_set], &[]);
+        }
+
+        for (index, axis) in GizmoAxis::ALL.iter().enumerate() {
+            let push = GizmoPushConstants {
+                model: space * uv::Mat4::from_translation(origin) * axis.orientation() * uv::Mat4::from_scale(scale),
+                color: match active == Some(*axis) {
+                    true => HIGHLIGHT_COLOR,
+                    false => AXIS_COLORS[index]
+                }
+            };
+
+            unsafe {
+                device.cmd_push_constants(command_buffer, self.pipeline.layout, vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT, 0,
+                    any_as_u8_slice(&push));
+            }
+            mesh.record_draw(device, command_buffer, 0);
+        }
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device, allocator: &mut Allocator) {
+        self.pipeline.cleanup(device);
+        for mesh in &mut self.meshes {
+            mesh.destroy(device, allocator);
+        }
+    }
+}
+
+// Distance along the ray and offset along the axis of the closest points between both, `None` if they are parallel
+fn closest_to_axis(ray: &Ray, origin: uv::Vec3, axis: uv::Vec3) -> Option<(f32, f32)> {
+    let to_ray = ray.origin - origin;
+    let b = ray.direction.dot(axis);
+    let d = ray.direction.dot(to_ray);
+    let e = axis.dot(to_ray);
+    let denominator = 1.0 - b * b;
+    if denominator < 0.000001 {
+        return None;
+    }
+
+    Some(((b * e - d) / denominator, (e - b * d) / denominator))
+}
+
+// Distance along the ray to the plane through `origin`, only in front of the ray
+fn intersect_plane(ray: &Ray, origin: uv::Vec3, normal: uv::Vec3) -> Option<f32> {
+    let denominator = ray.direction.dot(normal);
+    if denominator.abs() < 0.000001 {
+        return None;
+    }
+
+    let distance = (origin - ray.origin).dot(normal) / denominator;
+    (distance > 0.0).then_some(distance)
+}
+
+type Geometry = (Vec<Vertex>, Vec<u32>);
+
+fn create_mesh(device: &ash::Device, allocator: &mut Allocator, (vertices, indices): &Geometry) -> Result<Mesh, vk::Result> {
+    let mut mesh = Mesh::new(device, allocator, vertices.len(), indices.len())?;
+    mesh.update_vertex_buffer(vertices);
+    mesh.update_index_buffer(indices);
+
+    Ok(mesh)
+}
+
+fn add_quad(geometry: &mut Geometry, corners: [uv::Vec3; 4]) {
+    let (vertices, indices) = geometry;
+    let base = vertices.len() as u32;
+    let normal = (corners[1] - corners[0]).cross(corners[2] - corners[0]).normalized();
+    vertices.extend(corners.iter().map(|&pos| Vertex {
+        pos,
+        color: uv::Vec3::one(),
+        normal,
+        lightmap_uv: uv::Vec2::zero(),
+        tex_coord: uv::Vec2::zero(),
+        tangent: uv::Vec4::zero()
+    }));
+    indices.extend_from_slice(&[base, base + 1, base + 2, base, base + 2, base + 3]);
+}
+
+fn add_box(geometry: &mut Geometry, min: uv::Vec3, max: uv::Vec3) {
+    let corner = |x: bool, y: bool, z: bool| uv::Vec3::new(
+        if x { max.x } else { min.x },
+        if y { max.y } else { min.y },
+        if z { max.z } else { min.z },
+    );
+
+    add_quad(geometry, [corner(false, false, false), corner(false, true, false), corner(false, true, true), corner(false, false, true)]);
+    add_quad(geometry, [corner(true, false, false), corner(true, false, true), corner(true, true, true), corner(true, true, false)]);
+    add_quad(geometry, [corner(false, false, false), corner(false, false, true), corner(true, false, true), corner(true, false, false)]);
+    add_quad(geometry, [corner(false, true, false), corner(true, true, false), corner(true, true, true), corner(false, true, true)]);
+    add_quad(geometry, [corner(false, false, false), corner(true, false, false), corner(true, true, false), corner(false, true, false)]);
+    add_quad(geometry, [corner(false, false, true), corner(false, true, true), corner(true, true, true), corner(true, false, true)]);
+}
+
+// Unit ring around +X, a flat band plus a thin cylinder so it stays visible when seen edge on
+fn add_ring(geometry: &mut Geometry) {
+    let point = |segment: u32, radius: f32, x: f32| {
+        let angle = segment as f32 / RING_SEGMENTS as f32 * std::f32::consts::TAU;
+        uv::Vec3::new(x, angle.cos() * radius, angle.sin() * radius)
+    };
+
+    for segment in 0..RING_SEGMENTS {
+        let next = segment + 1;
+        add_quad(geometry, [
+            point(segment, 1.0 - SHAFT_WIDTH * 2.0, 0.0), point(next, 1.0 - SHAFT_WIDTH * 2.0, 0.0),
+            point(next, 1.0 + SHAFT_WIDTH * 2.0, 0.0), point(segment, 1.0 + SHAFT_WIDTH * 2.0, 0.0),
+        ]);
+        add_quad(geometry, [
+            point(segment, 1.0, -SHAFT_WIDTH * 2.0), point(segment, 1.0, SHAFT_WIDTH * 2.0),
+            point(next, 1.0, SHAFT_WIDTH * 2.0), point(next, 1.0, -SHAFT_WIDTH * 2.0),
+        ]);
+    }
+}
\ No newline at end of file
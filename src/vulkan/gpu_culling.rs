@@ -0,0 +1,426 @@
+use ash::vk;
+use ash::vk::Handle;
+use gpu_allocator::vulkan::Allocator;
+use gpu_allocator::MemoryLocation;
+
+use super::compute_pipeline::ComputePipeline;
+use super::descriptor_allocator::DescriptorAllocator;
+use super::descriptors::Descriptors;
+use super::game_object::GameObject;
+use super::index_buffer::IndexBuffer;
+use super::material::{Material, MaterialSets};
+use super::mesh::{BoundingSphere, LodView};
+use super::meshlet::Meshlet;
+use super::object_buffer::MAX_OBJECTS;
+use super::pipeline::{Pipeline, MATERIAL_SET};
+use super::storage_buffer::StorageBuffer;
+use super::vertex::Vertex;
+use super::vertex_buffer::VertexBuffer;
+
+use rayon::prelude::*;
+
+use crate::jobs;
+use crate::utils::any_as_u8_slice;
+
+pub const DRAW_CULL_COMP: &[u32] = vk_shader_macros::include_glsl!("./shaders/draw_cull.comp", kind: comp);
+
+// One per object and vertex buffer of its mesh, or per meshlet for meshes split into them
+pub const MAX_DRAWS: usize = MAX_OBJECTS * 16;
+// Must match the local size in draw_cull.comp
+const CULL_GROUP_SIZE: u32 = 64;
+
+// Must match Draw in draw_cull.comp
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct GpuDraw {
+    sphere: uv::Vec4,
+    cone: uv::Vec4,
+    first_index: u32,
+    index_count: u32,
+    vertex_offset: i32,
+    object_index: u32,
+    batch: u32,
+    first_command: u32,
+    _padding: [u32; 2],
+}
+
+#[repr(C)]
+struct CullPushConstants {
+    _draw_count: u32,
+    _compact: u32,
+}
+
+// Draws of one material sampling the same texture, their commands take `max_count` slots from `first_command` on
+#[derive(Clone, Copy, Debug)]
+pub struct DrawBatch {
+    pub material: Material,
+    pub set: vk::DescriptorSet,
+    first_command: u32,
+    max_count: u32,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct MeshRange {
+    first_index: u32,
+    index_count: u32,
+    vertex_offset: i32,
+    // Culled by the meshlet's bounds instead of the mesh's
+    meshlet: Option<Meshlet>,
+}
+
+// Copy of every game object's mesh in one vertex and one index buffer, so a single bind serves all indirect draws
+struct MergedGeometry {
+    vertices: VertexBuffer,
+    indices: IndexBuffer,
+    // By game object and level of detail, one range per vertex buffer of its mesh or per meshlet of each. Only the
+    // full mesh is split into meshlets.
+    ranges: Vec<Vec<Vec<MeshRange>>>,
+    // Mesh generations the copy was made from
+    generations: Vec<u64>,
+}
+
+impl MergedGeometry {
+    fn new(device: &ash::Device, allocator: &mut Allocator, game_objects: &[GameObject]) -> Self {
+        let mut vertices: Vec<Vertex> = Vec::new();
+        let mut indices: Vec<u32> = Vec::new();
+        let mut ranges = Vec::with_capacity(game_objects.len());
+        for game_object in game_objects {
+            let mesh = &game_object.mesh;
+            let first_index = indices.len() as u32;
+            match &mesh.index_buffer {
+                Some(index_buffer) => indices.extend_from_slice(index_buffer.read()),
+                // Unindexed meshes draw their vertices in order, which the largest vertex buffer covers
+                None => {
+                    let vertex_count = mesh.vertex_buffers.iter().map(|buffer| buffer.get_vertex_count()).max().unwrap_or(0);
+                    indices.extend(0..vertex_count)
+                }
+            }
+
+            let mut object_ranges = Vec::with_capacity(mesh.vertex_buffers.len() * mesh.meshlets.len().max(1));
+            let mut vertex_offsets = Vec::with_capacity(mesh.vertex_buffers.len());
+            for vertex_buffer in &mesh.vertex_buffers {
+                let vertex_offset = vertices.len() as i32;
+                vertex_offsets.push(vertex_offset);
+                match mesh.meshlets.is_empty() {
+                    true => {
+                        let index_count = match &mesh.index_buffer {
+                            Some(index_buffer) => index_buffer.get_index_count(),
+                            None => vertex_buffer.get_vertex_count()
+                        };
+                        object_ranges.push(MeshRange { first_index, index_count, vertex_offset, meshlet: None });
+                    },
+                    false => object_ranges.extend(mesh.meshlets.iter().map(|meshlet| MeshRange {
+                        first_index: first_index + meshlet.first_index,
+                        index_count: meshlet.index_count,
+                        vertex_offset,
+                        meshlet: Some(*meshlet)
+                    }))
+                }
+                vertices.extend_from_slice(vertex_buffer.read());
+            }
+
+            let mut object_lods = vec![object_ranges];
+            if mesh.index_buffer.is_some() {
+                for lod in &mesh.lods {
+                    let first_index = indices.len() as u32;
+                    indices.extend_from_slice(&lod.indices);
+                    object_lods.push(vertex_offsets
+                        .iter()
+                        .map(|&vertex_offset| MeshRange { first_index, index_count: lod.indices.len() as u32, vertex_offset, meshlet: None })
+                        .collect());
+                }
+            }
+            ranges.push(object_lods);
+        }
+
+        // Buffers can't be empty
+        let mut vertex_buffer = VertexBuffer::new(device, allocator, VertexBuffer::get_vertex_buffer_size(vertices.len().max(1)));
+        vertex_buffer.update_buffer(&vertices);
+        let mut index_buffer = IndexBuffer::new(device, allocator, IndexBuffer::get_index_buffer_size(indices.len().max(1)));
+        index_buffer.update_buffer(&indices);
+
+        Self {
+            vertices: vertex_buffer,
+            indices: index_buffer,
+            ranges,
+            generations: game_objects.iter().map(|game_object| game_object.mesh.generation()).collect()
+        }
+    }
+
+    fn is_current(&self, game_objects: &[GameObject]) -> bool {
+        self.generations.len() == game_objects.len()
+            && self.generations.iter().zip(game_objects).all(|(&generation, game_object)| generation == game_object.mesh.generation())
+    }
+
+    fn destroy(&mut self, device: &ash::Device, allocator: &mut Allocator) {
+        self.vertices.destroy(device, allocator);
+        self.indices.destroy(device, allocator);
+    }
+}
+
+// Drives the scene passes from the GPU: transforms and material indices live in the object buffer, mesh ranges in
+// the draw buffer and all meshes in one vertex and index buffer. A compute pass tests every draw's bounds against the
+// view and writes the indirect commands, grouped by material and texture, so a pass is a handful of multi draws and
+// the CPU never decides which object is visible. With a draw count the visible commands are compacted and counted,
+// without one culled commands just get no instances.
+pub struct GpuCulling {
+    set_layout: vk::DescriptorSetLayout,
+    compact: bool,
+    sets: Vec<vk::DescriptorSet>,
+    pipeline: ComputePipeline,
+    // Per swapchain image
+    draw_buffers: Vec<StorageBuffer>,
+    command_buffers: Vec<StorageBuffer>,
+    count_buffers: Vec<StorageBuffer>,
+    geometry: Option<MergedGeometry>,
+    // Built by `prepare`, uploaded by `update`
+    draws: Vec<GpuDraw>,
+    pub batches: Vec<DrawBatch>,
+}
+
+impl GpuCulling {
+    // `compact` needs vkCmdDrawIndexedIndirectCount, see `PhysicalDevice::supports_indirect_count`
+    pub fn new(device: &ash::Device, allocator: &mut Allocator, descriptors: &mut DescriptorAllocator, image_count: usize,
+        camera_set_layout: vk::DescriptorSetLayout, compact: bool
+    ) -> Result<Self, vk::Result> {
+        let set_layout = Descriptors::create_layout(device, &[
+            (vk::DescriptorType::STORAGE_BUFFER, vk::ShaderStageFlags::COMPUTE),
+            (vk::DescriptorType::STORAGE_BUFFER, vk::ShaderStageFlags::COMPUTE),
+            (vk::DescriptorType::STORAGE_BUFFER, vk::ShaderStageFlags::COMPUTE),
+        ])?;
+        let pipeline = ComputePipeline::new(device, DRAW_CULL_COMP, &[camera_set_layout, set_layout],
+            std::mem::size_of::<CullPushConstants>() as u32, &[])?;
+
+        let draws_size = (MAX_DRAWS * std::mem::size_of::<GpuDraw>()) as u64;
+        let commands_size = (MAX_DRAWS * std::mem::size_of::<vk::DrawIndexedIndirectCommand>()) as u64;
+        let counts_size = (MAX_DRAWS * std::mem::size_of::<u32>()) as u64;
+
+        let sets = descriptors.allocate_many(device, set_layout, image_count)?;
+        let mut draw_buffers = Vec::with_capacity(image_count);
+        let mut command_buffers = Vec::with_capacity(image_count);
+        let mut count_buffers = Vec::with_capacity(image_count);
+        for &set in &sets {
+            let draw_buffer = StorageBuffer::new(device, allocator, draws_size, MemoryLocation::CpuToGpu, "Culling Draws");
+            let command_buffer = StorageBuffer::with_usage(device, allocator, commands_size, vk::BufferUsageFlags::INDIRECT_BUFFER,
+                MemoryLocation::GpuOnly, "Culling Draw Commands");
+            let count_buffer = StorageBuffer::with_usage(device, allocator, counts_size,
+                vk::BufferUsageFlags::INDIRECT_BUFFER | vk::BufferUsageFlags::TRANSFER_DST, MemoryLocation::GpuOnly, "Culling Draw Counts");
+            Descriptors::write_buffer(device, set, 0, vk::DescriptorType::STORAGE_BUFFER, draw_buffer.descriptor_info());
+            Descriptors::write_buffer(device, set, 1, vk::DescriptorType::STORAGE_BUFFER, command_buffer.descriptor_info());
+            Descriptors::write_buffer(device, set, 2, vk::DescriptorType::STORAGE_BUFFER, count_buffer.descriptor_info());
+            draw_buffers.push(draw_buffer);
+            command_buffers.push(command_buffer);
+            count_buffers.push(count_buffer);
+        }
+
+        Ok(Self {
+            set_layout,
+            compact,
+            sets,
+            pipeline,
+            draw_buffers,
+            command_buffers,
+            count_buffers,
+            geometry: None,
+            draws: Vec::new(),
+            batches: Vec::new()
+        })
+    }
+
+    // Groups the frame's draws into batches before its command buffers are recorded. When a mesh changed the merged
+    // geometry is copied again, after waiting for the device to stop drawing the old copy. Returns whether it was, so
+    // descriptors reading the old copy can be rewritten. Levels of detail are picked for `lod_view`, every view drawn
+    // this frame uses the same ones.
+    pub fn prepare(&mut self, device: &ash::Device, allocator: &mut Allocator, game_objects: &[GameObject], models: &[uv::Mat4],
+        materials: &MaterialSets, lod_view: &LodView
+    ) -> Result<bool, vk::Result> {
+        let game_objects = &game_objects[..game_objects.len().min(MAX_OBJECTS)];
+        let rebuilt = !self.geometry.as_ref().is_some_and(|geometry| geometry.is_current(game_objects));
+        if rebuilt {
+            unsafe { device.device_wait_idle()? };
+            if let Some(mut geometry) = self.geometry.take() {
+                geometry.destroy(device, allocator);
+            }
+            self.geometry = Some(MergedGeometry::new(device, allocator, game_objects));
+        }
+        let geometry = match &self.geometry {
+            Some(geometry) => geometry,
+            None => return Ok(rebuilt)
+        };
+
+        let mut grouped: Vec<(vk::DescriptorSet, Material, usize)> = game_objects
+            .iter()
+            .enumerate()
+            .filter_map(|(index, game_object)| materials.set(game_object).map(|set| (set, game_object.material, index)))
+            .collect();
+        grouped.sort_by_key(|&(set, _, _)| set.as_raw());
+
+        let lods: Vec<&[MeshRange]> = game_objects
+            .iter()
+            .zip(models)
+            .zip(&geometry.ranges)
+            .map(|((game_object, &model), object_lods)| {
+                let lod = game_object.mesh.select_lod(model, lod_view).min(object_lods.len() - 1);
+                object_lods[lod].as_slice()
+            })
+            .collect();
+
+        // Meshes split into meshlets have a lot of bounds to transform, objects are spread over the job threads
+        let bounds: Vec<Vec<(uv::Vec4, uv::Vec4)>> = jobs::pool().install(|| grouped
+            .par_iter()
+            .map(|&(_, _, index)| lods[index]
+                .iter()
+                .map(|range| world_bounds(game_objects[index].mesh.bounds, range.meshlet, models[index]))
+                .collect())
+            .collect());
+
+        self.draws.clear();
+        self.batches.clear();
+        for ((set, material, index), object_bounds) in grouped.into_iter().zip(bounds) {
+            for (range, (sphere, cone)) in lods[index].iter().zip(object_bounds) {
+                if self.draws.len() == MAX_DRAWS {
+                    break;
+                }
+                if self.batches.last().map(|batch| batch.set) != Some(set) {
+                    self.batches.push(DrawBatch {
+                        material,
+                        set,
+                        first_command: self.draws.len() as u32,
+                        max_count: 0
+                    });
+                }
+                let batch_index = self.batches.len() - 1;
+                let batch = &mut self.batches[batch_index];
+                batch.max_count += 1;
+
+                self.draws.push(GpuDraw {
+                    sphere,
+                    cone,
+                    first_index: range.first_index,
+                    index_count: range.index_count,
+                    vertex_offset: range.vertex_offset,
+                    object_index: index as u32,
+                    batch: batch_index as u32,
+                    first_command: batch.first_command,
+                    _padding: [0; 2]
+                });
+            }
+        }
+
+        Ok(rebuilt)
+    }
+
+    // The merged vertices of the last `prepare`, read by pipelines pulling their vertices
+    pub fn vertices_info(&self) -> Option<vk::DescriptorBufferInfo> {
+        self.geometry.as_ref().map(|geometry| geometry.vertices.descriptor_info())
+    }
+
+    pub fn update(&mut self, index: usize) {
+        self.draw_buffers[index].update_buffer(0, &self.draws);
+    }
+
+    // Culls against the view of `camera_set`, outside of any render pass. Views drawn one after another in the same
+    // command buffer each cull again, the barriers keep an earlier view's draws from seeing the next view's results.
+    pub fn record_culling(&self, device: &ash::Device, command_buffer: vk::CommandBuffer, index: usize, camera_set: vk::DescriptorSet) {
+        let count_buffer = &self.count_buffers[index];
+        let push = CullPushConstants {
+            _draw_count: self.draws.len() as u32,
+            _compact: self.compact as u32
+        };
+
+        let clear_barriers = [vk::MemoryBarrier::builder()
+            .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+            .dst_access_mask(vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE)
+            .build()
+        ];
+        let draw_barriers = [vk::MemoryBarrier::builder()
+            .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+            .dst_access_mask(vk::AccessFlags::INDIRECT_COMMAND_READ)
+            .build()
+        ];
+
+        unsafe {
+            device.cmd_pipeline_barrier(command_buffer, vk::PipelineStageFlags::DRAW_INDIRECT,
+                vk::PipelineStageFlags::TRANSFER | vk::PipelineStageFlags::COMPUTE_SHADER, vk::DependencyFlags::empty(), &[], &[], &[]);
+            device.cmd_fill_buffer(command_buffer, count_buffer.get_buffer(), 0, count_buffer.get_size(), 0);
+            device.cmd_pipeline_barrier(command_buffer, vk::PipelineStageFlags::TRANSFER, vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::DependencyFlags::empty(), &clear_barriers, &[], &[]);
+
+            device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::COMPUTE, self.pipeline.pipeline);
+            device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::COMPUTE, self.pipeline.layout, 0,
+                &[camera_set, self.sets[index]], &[]);
+            device.cmd_push_constants(command_buffer, self.pipeline.layout, vk::ShaderStageFlags::COMPUTE, 0, any_as_u8_slice(&push));
+            device.cmd_dispatch(command_buffer, (self.draws.len() as u32).div_ceil(CULL_GROUP_SIZE).max(1), 1, 1);
+
+            device.cmd_pipeline_barrier(command_buffer, vk::PipelineStageFlags::COMPUTE_SHADER, vk::PipelineStageFlags::DRAW_INDIRECT,
+                vk::DependencyFlags::empty(), &draw_barriers, &[], &[]);
+        }
+    }
+
+    // Draws the surviving objects of `material` with `pipeline`, which must be bound along with the frame, object and
+    // lighting sets
+    pub fn record_draws(&self, device: &ash::Device, command_buffer: vk::CommandBuffer, index: usize, pipeline: &Pipeline, material: Material) {
+        let geometry = match &self.geometry {
+            Some(geometry) => geometry,
+            None => return
+        };
+        let stride = std::mem::size_of::<vk::DrawIndexedIndirectCommand>() as u32;
+
+        unsafe {
+            device.cmd_bind_vertex_buffers(command_buffer, 0, &[geometry.vertices.get_buffer()], &[0]);
+            device.cmd_bind_index_buffer(command_buffer, geometry.indices.get_buffer(), 0, vk::IndexType::UINT32);
+
+            for (batch_index, batch) in self.batches.iter().enumerate().filter(|(_, batch)| batch.material == material) {
+                device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::GRAPHICS, pipeline.layout, MATERIAL_SET, &[batch.set], &[]);
+                let offset = batch.first_command as u64 * stride as u64;
+                match self.compact {
+                    true => device.cmd_draw_indexed_indirect_count(command_buffer, self.command_buffers[index].get_buffer(), offset,
+                        self.count_buffers[index].get_buffer(), (batch_index * std::mem::size_of::<u32>()) as u64, batch.max_count, stride),
+                    false => device.cmd_draw_indexed_indirect(command_buffer, self.command_buffers[index].get_buffer(), offset, batch.max_count,
+                        stride)
+                }
+            }
+        }
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device, allocator: &mut Allocator, descriptors: &mut DescriptorAllocator) -> Result<(), vk::Result> {
+        for &set in &self.sets {
+            descriptors.free(device, set)?;
+        }
+        for buffer in self.draw_buffers.iter_mut().chain(self.command_buffers.iter_mut()).chain(self.count_buffers.iter_mut()) {
+            buffer.destroy(device, allocator);
+        }
+        if let Some(mut geometry) = self.geometry.take() {
+            geometry.destroy(device, allocator);
+        }
+        self.pipeline.cleanup(device);
+        unsafe { device.destroy_descriptor_set_layout(self.set_layout, None) };
+
+        Ok(())
+    }
+}
+
+// Culling sphere and cone of a draw in world space. A negative radius never gets culled, a cutoff of 1 never counts as
+// back facing, which is also what non uniformly scaled meshlets get since their cone no longer holds.
+fn world_bounds(mesh_bounds: Option<BoundingSphere>, meshlet: Option<Meshlet>, model: uv::Mat4) -> (uv::Vec4, uv::Vec4) {
+    let no_cone = uv::Vec4::new(0.0, 0.0, 1.0, 1.0);
+    let (bounds, cone) = match (meshlet, mesh_bounds) {
+        (Some(meshlet), _) => {
+            let scales = (0..3).map(|axis| model.cols[axis].xyz().mag());
+            let (min_scale, max_scale) = scales.fold((f32::MAX, 0.0f32), |(min, max), scale| (min.min(scale), max.max(scale)));
+            let cone = match max_scale - min_scale <= max_scale * 0.01 {
+                true => {
+                    let axis = (model.truncate().inversed().transposed() * meshlet.cone_axis).normalized();
+                    uv::Vec4::new(axis.x, axis.y, axis.z, meshlet.cone_cutoff)
+                },
+                false => no_cone
+            };
+            (meshlet.bounds, cone)
+        },
+        (None, Some(bounds)) => (bounds, no_cone),
+        (None, None) => return (uv::Vec4::new(0.0, 0.0, 0.0, -1.0), no_cone)
+    };
+
+    let bounds = bounds.transformed(model);
+    (uv::Vec4::new(bounds.center.x, bounds.center.y, bounds.center.z, bounds.radius), cone)
+}
\ No newline at end of file
@@ -0,0 +1,150 @@
+use std::ffi::CStr;
+
+use ash::extensions::ext::CalibratedTimestamps;
+use ash::vk;
+
+use super::command_pools::Pools;
+
+// Timestamps per frame slot, one where each breadcrumb pass begins and one at the end. Passes past the last one aren't timed.
+const MAX_GPU_TIMESTAMPS: u32 = 64;
+
+// VK_EXT_calibrated_timestamps with the device's own time domain, which lines the GPU's clock up with the CPU zones
+pub fn calibration_supported(instance: &ash::Instance, physical_device: vk::PhysicalDevice) -> bool {
+    let extensions = unsafe { instance.enumerate_device_extension_properties(physical_device).unwrap_or_default() };
+    let name = CalibratedTimestamps::name();
+    extensions.iter().any(|extension| unsafe { CStr::from_ptr(extension.extension_name.as_ptr()) } == name)
+}
+
+// GPU zones for Tracy. Every breadcrumb pass of a frame becomes a zone on the "Graphics" track, timed with timestamp
+// queries that are read once the frame slot comes around again, so they show up a few frames behind the CPU.
+pub struct GpuProfiler {
+    device: ash::Device,
+    context: tracy_client::GpuContext,
+    pools: Vec<vk::QueryPool>,
+    // Timestamps only have this many low bits
+    mask: u64,
+    // Passes of every slot's last submission, whose timestamps haven't been read yet
+    submitted: Vec<Vec<&'static str>>,
+}
+
+impl GpuProfiler {
+    // `None` while the Tracy client isn't running or when the graphics queue can't write timestamps
+    pub fn new(entry: &ash::Entry, instance: &ash::Instance, device: &ash::Device, physical_device: vk::PhysicalDevice,
+        properties: &vk::PhysicalDeviceProperties, queue_family_index: u32, pools: &Pools, queue: vk::Queue, frame_count: usize
+    ) -> Result<Option<Self>, vk::Result> {
+        let client = match tracy_client::Client::running() {
+            Some(client) => client,
+            None => return Ok(None)
+        };
+
+        let families = unsafe { instance.get_physical_device_queue_family_properties(physical_device) };
+        let valid_bits = families[queue_family_index as usize].timestamp_valid_bits;
+        if valid_bits == 0 {
+            tracing::warn!("Graphics queue has no timestamps, Tracy only gets the CPU zones");
+            return Ok(None);
+        }
+        let mask = match valid_bits {
+            64 => u64::MAX,
+            bits => (1 << bits) - 1
+        };
+
+        let pool_info = vk::QueryPoolCreateInfo::builder()
+            .query_type(vk::QueryType::TIMESTAMP)
+            .query_count(MAX_GPU_TIMESTAMPS);
+        let query_pools = (0..frame_count)
+            .map(|_| unsafe { device.create_query_pool(&pool_info, None) })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let timestamp = Self::current_timestamp(entry, instance, device, physical_device, pools, queue, query_pools[0])? & mask;
+        let context = match client.new_gpu_context(Some("Graphics"), tracy_client::GpuContextType::Vulkan, timestamp as i64,
+            properties.limits.timestamp_period) {
+            Ok(context) => context,
+            Err(error) => {
+                tracing::warn!("Failed to create the Tracy GPU context: {:?}", error);
+                for pool in query_pools {
+                    unsafe { device.destroy_query_pool(pool, None) };
+                }
+                return Ok(None);
+            }
+        };
+
+        Ok(Some(Self {
+            device: device.clone(),
+            context,
+            pools: query_pools,
+            mask,
+            submitted: vec![vec![]; frame_count]
+        }))
+    }
+
+    // The GPU's clock right now. Calibrated where the device can sample it directly, otherwise a timestamp written by a
+    // throwaway submission, which lands a little late.
+    fn current_timestamp(entry: &ash::Entry, instance: &ash::Instance, device: &ash::Device, physical_device: vk::PhysicalDevice, pools: &Pools,
+        queue: vk::Queue, query_pool: vk::QueryPool
+    ) -> Result<u64, vk::Result> {
+        if calibration_supported(instance, physical_device) {
+            let calibrated = CalibratedTimestamps::new(entry, instance);
+            let domains = unsafe { calibrated.get_physical_device_calibrateable_time_domains(physical_device)? };
+            if domains.contains(&vk::TimeDomainEXT::DEVICE) {
+                let info = [vk::CalibratedTimestampInfoEXT::builder().time_domain(vk::TimeDomainEXT::DEVICE).build()];
+                let (timestamps, _) = unsafe { calibrated.get_calibrated_timestamps(device.handle(), &info)? };
+                return Ok(timestamps[0]);
+            }
+        }
+
+        pools.one_time_submit(device, queue, |command_buffer| unsafe {
+            device.cmd_reset_query_pool(command_buffer, query_pool, 0, 1);
+            device.cmd_write_timestamp(command_buffer, vk::PipelineStageFlags::BOTTOM_OF_PIPE, query_pool, 0);
+        })?;
+        let mut timestamp = [0u64; 1];
+        unsafe { device.get_query_pool_results(query_pool, 0, 1, &mut timestamp, vk::QueryResultFlags::TYPE_64 | vk::QueryResultFlags::WAIT)? };
+        Ok(timestamp[0])
+    }
+
+    // Recorded outside of any render pass before the slot's first timestamp
+    pub fn record_reset(&self, command_buffer: vk::CommandBuffer, slot: usize) {
+        unsafe { self.device.cmd_reset_query_pool(command_buffer, self.pools[slot], 0, MAX_GPU_TIMESTAMPS) };
+    }
+
+    // Lands once everything recorded before has finished
+    pub fn record_timestamp(&self, command_buffer: vk::CommandBuffer, slot: usize, query: usize) {
+        if query < MAX_GPU_TIMESTAMPS as usize {
+            unsafe { self.device.cmd_write_timestamp(command_buffer, vk::PipelineStageFlags::BOTTOM_OF_PIPE, self.pools[slot], query as u32) };
+        }
+    }
+
+    // Call right before the slot's command buffer is submitted, once its previous submission has finished. Hands the
+    // previous submission's passes to Tracy.
+    pub fn submitting(&mut self, slot: usize, passes: &[&'static str]) {
+        let previous = std::mem::replace(&mut self.submitted[slot], passes.to_vec());
+        if previous.is_empty() {
+            return;
+        }
+
+        // Each timestamp followed by whether it was written
+        let count = (previous.len() + 1).min(MAX_GPU_TIMESTAMPS as usize);
+        let mut data = vec![[0u64; 2]; count];
+        let flags = vk::QueryResultFlags::TYPE_64 | vk::QueryResultFlags::WITH_AVAILABILITY;
+        match unsafe { self.device.get_query_pool_results(self.pools[slot], 0, count as u32, &mut data, flags) } {
+            Ok(()) | Err(vk::Result::NOT_READY) => {}
+            Err(_) => return
+        }
+
+        for (name, bounds) in previous.iter().zip(data.windows(2)) {
+            let ([start, start_available], [end, end_available]) = (bounds[0], bounds[1]);
+            if start_available == 0 || end_available == 0 {
+                continue;
+            }
+            if let Ok(mut span) = self.context.span_alloc(name, "", file!(), line!()) {
+                span.end_zone();
+                span.upload_timestamp((start & self.mask) as i64, (end & self.mask) as i64);
+            }
+        }
+    }
+
+    pub fn destroy(&self) {
+        for pool in &self.pools {
+            unsafe { self.device.destroy_query_pool(*pool, None) };
+        }
+    }
+}
\ No newline at end of file
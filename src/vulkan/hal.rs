@@ -0,0 +1,368 @@
+use ash::vk;
+use gpu_allocator::vulkan::*;
+
+use crate::hal::{self, Binding, BindingKind, BufferDesc, ComputePipelineDesc, MemoryLocation, TextureDesc, TextureFormat};
+
+use super::command_pools::Pools;
+use super::compute_pipeline::ComputePipeline;
+use super::descriptor_allocator::{DescriptorAllocator, DEFAULT_POOL_RATIOS};
+use super::descriptors::Descriptors;
+use super::image::{create_sampler, Image};
+
+// The `hal` traits on top of the renderer's device, allocator and graphics queue, see `VulkanRenderer::hal_device`.
+// Textures stay in the GENERAL layout their whole life so commands never have to know what happened to them before.
+pub struct AshDevice<'a> {
+    device: &'a ash::Device,
+    allocator: &'a mut Allocator,
+    pools: &'a Pools,
+    queue: vk::Queue,
+}
+
+impl<'a> AshDevice<'a> {
+    pub fn new(device: &'a ash::Device, allocator: &'a mut Allocator, pools: &'a Pools, queue: vk::Queue) -> Self {
+        Self { device, allocator, pools, queue }
+    }
+}
+
+pub struct AshBuffer {
+    pub buffer: vk::Buffer,
+    allocation: Allocation,
+    size: u64,
+}
+
+impl hal::Buffer for AshBuffer {
+    fn size(&self) -> u64 { self.size }
+}
+
+pub struct AshTexture {
+    pub image: Image,
+    pub sampler: vk::Sampler,
+    format: TextureFormat,
+}
+
+impl hal::Texture for AshTexture {
+    fn width(&self) -> u32 { self.image.extent.width }
+    fn height(&self) -> u32 { self.image.extent.height }
+    fn layers(&self) -> u32 { self.image.layers }
+    fn format(&self) -> TextureFormat { self.format }
+}
+
+pub struct AshPipeline {
+    pub pipeline: ComputePipeline,
+    set_layout: vk::DescriptorSetLayout,
+    bindings: Vec<BindingKind>,
+}
+
+impl hal::Pipeline for AshPipeline {
+    fn bindings(&self) -> &[BindingKind] { &self.bindings }
+}
+
+// A command buffer from the graphics pool, with the descriptor sets of its dispatches
+pub struct AshCommandEncoder<'a> {
+    device: &'a ash::Device,
+    command_buffer: vk::CommandBuffer,
+    descriptors: DescriptorAllocator,
+}
+
+fn vk_format(format: TextureFormat) -> vk::Format {
+    match format {
+        TextureFormat::Rgba8Unorm => vk::Format::R8G8B8A8_UNORM,
+        TextureFormat::Rgba8Srgb => vk::Format::R8G8B8A8_SRGB,
+        TextureFormat::Bgra8Unorm => vk::Format::B8G8R8A8_UNORM,
+        TextureFormat::Rgba16Float => vk::Format::R16G16B16A16_SFLOAT,
+        TextureFormat::Rgba32Float => vk::Format::R32G32B32A32_SFLOAT,
+        TextureFormat::R32Float => vk::Format::R32_SFLOAT,
+        TextureFormat::Depth32Float => vk::Format::D32_SFLOAT,
+    }
+}
+
+fn descriptor_type(kind: BindingKind) -> vk::DescriptorType {
+    match kind {
+        BindingKind::UniformBuffer => vk::DescriptorType::UNIFORM_BUFFER,
+        BindingKind::StorageBuffer => vk::DescriptorType::STORAGE_BUFFER,
+        BindingKind::SampledTexture => vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+        BindingKind::StorageTexture => vk::DescriptorType::STORAGE_IMAGE,
+    }
+}
+
+fn aspect_mask(format: TextureFormat) -> vk::ImageAspectFlags {
+    match format.is_depth() {
+        true => vk::ImageAspectFlags::DEPTH,
+        false => vk::ImageAspectFlags::COLOR
+    }
+}
+
+impl<'a> hal::Device for AshDevice<'a> {
+    type Buffer = AshBuffer;
+    type Texture = AshTexture;
+    type Pipeline = AshPipeline;
+    type CommandEncoder = AshCommandEncoder<'a>;
+    type Error = vk::Result;
+
+    fn create_buffer(&mut self, desc: &BufferDesc) -> Result<AshBuffer, vk::Result> {
+        let flags = [
+            (desc.usage.vertex, vk::BufferUsageFlags::VERTEX_BUFFER),
+            (desc.usage.index, vk::BufferUsageFlags::INDEX_BUFFER),
+            (desc.usage.uniform, vk::BufferUsageFlags::UNIFORM_BUFFER),
+            (desc.usage.storage, vk::BufferUsageFlags::STORAGE_BUFFER),
+            (desc.usage.indirect, vk::BufferUsageFlags::INDIRECT_BUFFER),
+            (desc.usage.copy_src, vk::BufferUsageFlags::TRANSFER_SRC),
+            (desc.usage.copy_dst, vk::BufferUsageFlags::TRANSFER_DST),
+        ];
+        let usage = flags.iter()
+            .filter(|(enabled, _)| *enabled)
+            .fold(vk::BufferUsageFlags::empty(), |usage, &(_, flag)| usage | flag);
+
+        let buffer_create_info = vk::BufferCreateInfo::builder()
+            .size(desc.size)
+            .usage(usage)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+        let buffer = unsafe { self.device.create_buffer(&buffer_create_info, None)? };
+
+        let mem_requirements = unsafe { self.device.get_buffer_memory_requirements(buffer) };
+        let location = match desc.location {
+            MemoryLocation::GpuOnly => gpu_allocator::MemoryLocation::GpuOnly,
+            MemoryLocation::CpuToGpu => gpu_allocator::MemoryLocation::CpuToGpu,
+            MemoryLocation::GpuToCpu => gpu_allocator::MemoryLocation::GpuToCpu,
+        };
+        let allocation = self.allocator.allocate(&AllocationCreateDesc {
+            requirements: mem_requirements,
+            location,
+            linear: true,
+            name: desc.name
+        }).expect("Failed to allocate memory for buffer!");
+
+        unsafe { self.device.bind_buffer_memory(buffer, allocation.memory(), allocation.offset())? };
+
+        Ok(AshBuffer {
+            buffer,
+            allocation,
+            size: desc.size
+        })
+    }
+
+    fn write_buffer(&mut self, buffer: &mut AshBuffer, offset: u64, data: &[u8]) {
+        assert!(offset + data.len() as u64 <= buffer.size, "Buffer write out of bounds!");
+        let dst = buffer.allocation.mapped_ptr().expect("Buffer is not host visible!").as_ptr().cast::<u8>();
+        unsafe { std::ptr::copy_nonoverlapping(data.as_ptr(), dst.add(offset as usize), data.len()) };
+    }
+
+    fn read_buffer(&self, buffer: &AshBuffer, offset: u64, data: &mut [u8]) {
+        assert!(offset + data.len() as u64 <= buffer.size, "Buffer read out of bounds!");
+        let src = buffer.allocation.mapped_ptr().expect("Buffer is not host visible!").as_ptr().cast::<u8>();
+        unsafe { std::ptr::copy_nonoverlapping(src.add(offset as usize), data.as_mut_ptr(), data.len()) };
+    }
+
+    fn destroy_buffer(&mut self, mut buffer: AshBuffer) {
+        self.allocator
+            .free(std::mem::take(&mut buffer.allocation))
+            .expect("Failed to free buffer memory!");
+        unsafe { self.device.destroy_buffer(buffer.buffer, None) };
+    }
+
+    fn create_texture(&mut self, desc: &TextureDesc) -> Result<AshTexture, vk::Result> {
+        let flags = [
+            (desc.usage.sampled, vk::ImageUsageFlags::SAMPLED),
+            (desc.usage.storage, vk::ImageUsageFlags::STORAGE),
+            (desc.usage.render_target && desc.format.is_depth(), vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT),
+            (desc.usage.render_target && !desc.format.is_depth(), vk::ImageUsageFlags::COLOR_ATTACHMENT),
+            (desc.usage.copy_src, vk::ImageUsageFlags::TRANSFER_SRC),
+            (desc.usage.copy_dst, vk::ImageUsageFlags::TRANSFER_DST),
+        ];
+        let usage = flags.iter()
+            .filter(|(enabled, _)| *enabled)
+            .fold(vk::ImageUsageFlags::empty(), |usage, &(_, flag)| usage | flag);
+        let view_type = match desc.layers {
+            1 => vk::ImageViewType::TYPE_2D,
+            _ => vk::ImageViewType::TYPE_2D_ARRAY
+        };
+
+        let extent = vk::Extent2D { width: desc.width, height: desc.height };
+        let mut image = Image::new_layered(self.device, self.allocator, extent, vk_format(desc.format), usage,
+            aspect_mask(desc.format), desc.layers, view_type, desc.name)?;
+        let sampler = match create_sampler(self.device, vk::Filter::LINEAR, vk::SamplerAddressMode::CLAMP_TO_EDGE) {
+            Ok(sampler) => sampler,
+            Err(error) => {
+                image.destroy(self.device, self.allocator);
+                return Err(error);
+            }
+        };
+
+        let device = self.device;
+        self.pools.one_time_submit(device, self.queue, |command_buffer| {
+            image.transition_layout(device, command_buffer, vk::ImageLayout::UNDEFINED, vk::ImageLayout::GENERAL);
+        })?;
+
+        Ok(AshTexture {
+            image,
+            sampler,
+            format: desc.format
+        })
+    }
+
+    fn destroy_texture(&mut self, mut texture: AshTexture) {
+        unsafe { self.device.destroy_sampler(texture.sampler, None) };
+        texture.image.destroy(self.device, self.allocator);
+    }
+
+    fn create_compute_pipeline(&mut self, desc: &ComputePipelineDesc) -> Result<AshPipeline, vk::Result> {
+        let layout_bindings: Vec<(vk::DescriptorType, vk::ShaderStageFlags)> = desc.bindings
+            .iter()
+            .map(|&kind| (descriptor_type(kind), vk::ShaderStageFlags::COMPUTE))
+            .collect();
+        let set_layout = Descriptors::create_layout(self.device, &layout_bindings)?;
+
+        let pipeline = match ComputePipeline::new(self.device, desc.shader, &[set_layout], desc.push_constant_size, &[]) {
+            Ok(pipeline) => pipeline,
+            Err(error) => {
+                tracing::error!("Failed to create compute pipeline {}: {}", desc.name, error);
+                unsafe { self.device.destroy_descriptor_set_layout(set_layout, None) };
+                return Err(error);
+            }
+        };
+
+        Ok(AshPipeline {
+            pipeline,
+            set_layout,
+            bindings: desc.bindings.to_vec()
+        })
+    }
+
+    fn destroy_pipeline(&mut self, pipeline: AshPipeline) {
+        pipeline.pipeline.cleanup(self.device);
+        unsafe { self.device.destroy_descriptor_set_layout(pipeline.set_layout, None) };
+    }
+
+    fn create_command_encoder(&mut self) -> Result<AshCommandEncoder<'a>, vk::Result> {
+        let commandbuffer_allocate_info = vk::CommandBufferAllocateInfo::builder()
+            .level(vk::CommandBufferLevel::PRIMARY)
+            .command_pool(self.pools.graphics_command_pool)
+            .command_buffer_count(1);
+        let command_buffer = unsafe { self.device.allocate_command_buffers(&commandbuffer_allocate_info)? }[0];
+
+        let begin_info = vk::CommandBufferBeginInfo::builder()
+            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+        unsafe { self.device.begin_command_buffer(command_buffer, &begin_info)? };
+
+        Ok(AshCommandEncoder {
+            device: self.device,
+            command_buffer,
+            descriptors: DescriptorAllocator::new(8, &DEFAULT_POOL_RATIOS)
+        })
+    }
+
+    fn submit(&mut self, mut encoder: AshCommandEncoder<'a>) -> Result<(), vk::Result> {
+        let command_buffers = [encoder.command_buffer];
+        let result = unsafe {
+            self.device.end_command_buffer(encoder.command_buffer)
+                .and_then(|_| {
+                    let submit_info = [vk::SubmitInfo::builder()
+                        .command_buffers(&command_buffers)
+                        .build()
+                    ];
+                    self.device.queue_submit(self.queue, &submit_info, vk::Fence::null())
+                })
+                .and_then(|_| self.device.queue_wait_idle(self.queue))
+        };
+
+        unsafe { self.device.free_command_buffers(self.pools.graphics_command_pool, &command_buffers) };
+        encoder.descriptors.destroy(self.device);
+        result
+    }
+}
+
+impl AshCommandEncoder<'_> {
+    // Makes everything written so far visible to whatever comes next. Coarse, but these commands aren't per frame.
+    fn barrier(&self) {
+        let barriers = [vk::MemoryBarrier::builder()
+            .src_access_mask(vk::AccessFlags::MEMORY_WRITE)
+            .dst_access_mask(vk::AccessFlags::MEMORY_READ | vk::AccessFlags::MEMORY_WRITE)
+            .build()
+        ];
+        unsafe {
+            self.device.cmd_pipeline_barrier(self.command_buffer, vk::PipelineStageFlags::ALL_COMMANDS,
+                vk::PipelineStageFlags::ALL_COMMANDS, vk::DependencyFlags::empty(), &barriers, &[], &[]);
+        }
+    }
+
+    fn texture_region(texture: &AshTexture) -> vk::BufferImageCopy {
+        vk::BufferImageCopy::builder()
+            .buffer_offset(0)
+            .image_subresource(vk::ImageSubresourceLayers {
+                aspect_mask: texture.image.aspect_mask,
+                mip_level: 0,
+                base_array_layer: 0,
+                layer_count: texture.image.layers
+            })
+            .image_extent(vk::Extent3D {
+                width: texture.image.extent.width,
+                height: texture.image.extent.height,
+                depth: 1
+            })
+            .build()
+    }
+}
+
+impl<'a> hal::CommandEncoder for AshCommandEncoder<'a> {
+    type Device = AshDevice<'a>;
+
+    fn copy_buffer(&mut self, src: &AshBuffer, dst: &AshBuffer, size: u64) {
+        let regions = [vk::BufferCopy { src_offset: 0, dst_offset: 0, size }];
+        unsafe { self.device.cmd_copy_buffer(self.command_buffer, src.buffer, dst.buffer, &regions) };
+        self.barrier();
+    }
+
+    fn copy_buffer_to_texture(&mut self, src: &AshBuffer, dst: &AshTexture) {
+        let regions = [Self::texture_region(dst)];
+        unsafe {
+            self.device.cmd_copy_buffer_to_image(self.command_buffer, src.buffer, dst.image.image, vk::ImageLayout::GENERAL, &regions);
+        }
+        self.barrier();
+    }
+
+    fn copy_texture_to_buffer(&mut self, src: &AshTexture, dst: &AshBuffer) {
+        let regions = [Self::texture_region(src)];
+        unsafe {
+            self.device.cmd_copy_image_to_buffer(self.command_buffer, src.image.image, vk::ImageLayout::GENERAL, dst.buffer, &regions);
+        }
+        self.barrier();
+    }
+
+    fn dispatch(&mut self, pipeline: &AshPipeline, bindings: &[Binding<'_, AshDevice<'a>>], push_constants: &[u8], groups: [u32; 3]
+    ) -> Result<(), vk::Result> {
+        assert_eq!(bindings.len(), pipeline.bindings.len(), "Dispatch doesn't bind what the pipeline expects!");
+
+        let set = self.descriptors.allocate(self.device, pipeline.set_layout)?;
+        for (index, (binding, &kind)) in bindings.iter().zip(&pipeline.bindings).enumerate() {
+            match (binding, kind) {
+                (Binding::Buffer(buffer), BindingKind::UniformBuffer | BindingKind::StorageBuffer) => {
+                    let info = vk::DescriptorBufferInfo { buffer: buffer.buffer, offset: 0, range: buffer.size };
+                    Descriptors::write_buffer(self.device, set, index as u32, descriptor_type(kind), info);
+                }
+                (Binding::Texture(texture), BindingKind::SampledTexture | BindingKind::StorageTexture) => {
+                    let info = vk::DescriptorImageInfo {
+                        sampler: texture.sampler,
+                        image_view: texture.image.view,
+                        image_layout: vk::ImageLayout::GENERAL
+                    };
+                    Descriptors::write_image(self.device, set, index as u32, descriptor_type(kind), info);
+                }
+                _ => panic!("Binding {} of the dispatch doesn't match the pipeline's {:?}!", index, kind)
+            }
+        }
+
+        unsafe {
+            self.device.cmd_bind_pipeline(self.command_buffer, vk::PipelineBindPoint::COMPUTE, pipeline.pipeline.pipeline);
+            self.device.cmd_bind_descriptor_sets(self.command_buffer, vk::PipelineBindPoint::COMPUTE, pipeline.pipeline.layout, 0,
+                &[set], &[]);
+            if !push_constants.is_empty() {
+                self.device.cmd_push_constants(self.command_buffer, pipeline.pipeline.layout, vk::ShaderStageFlags::COMPUTE, 0,
+                    push_constants);
+            }
+            self.device.cmd_dispatch(self.command_buffer, groups[0], groups[1], groups[2]);
+        }
+        self.barrier();
+
+        Ok(())
+    }
+}
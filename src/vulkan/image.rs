@@ -0,0 +1,287 @@
+use ash::vk;
+use gpu_allocator::vulkan::*;
+use gpu_allocator::MemoryLocation;
+
+pub struct Image {
+    pub image: vk::Image,
+    pub view: vk::ImageView,
+    // Depth only view of depth/stencil images, descriptors can only sample a single aspect
+    pub depth_view: Option<vk::ImageView>,
+    allocation: Allocation,
+    pub format: vk::Format,
+    pub extent: vk::Extent2D,
+    // Only 3D images have a depth above 1
+    pub depth: u32,
+    pub layers: u32,
+    pub mip_levels: u32,
+    pub aspect_mask: vk::ImageAspectFlags,
+}
+
+impl Image {
+    pub fn new(
+        device: &ash::Device,
+        allocator: &mut Allocator,
+        extent: vk::Extent2D,
+        format: vk::Format,
+        usage: vk::ImageUsageFlags,
+        aspect_mask: vk::ImageAspectFlags,
+        name: &str,
+    ) -> Result<Image, vk::Result> {
+        Self::new_layered(device, allocator, extent, format, usage, aspect_mask, 1, vk::ImageViewType::TYPE_2D, name)
+    }
+
+    // `view` covers every layer with the given view type, use `create_layer_view` to target a single layer
+    #[allow(clippy::too_many_arguments)]
+    pub fn new_layered(
+        device: &ash::Device,
+        allocator: &mut Allocator,
+        extent: vk::Extent2D,
+        format: vk::Format,
+        usage: vk::ImageUsageFlags,
+        aspect_mask: vk::ImageAspectFlags,
+        layers: u32,
+        view_type: vk::ImageViewType,
+        name: &str,
+    ) -> Result<Image, vk::Result> {
+        Self::create(device, allocator, vk::ImageType::TYPE_2D, extent, 1, format, usage, aspect_mask, layers, 1, &[], view_type, name)
+    }
+
+    // Levels `0..mip_levels` from `extent` down, with nothing in them yet. Shared between `queue_families` when there is
+    // more than one, so one queue can fill levels while another samples the rest.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new_mipmapped(
+        device: &ash::Device,
+        allocator: &mut Allocator,
+        extent: vk::Extent2D,
+        format: vk::Format,
+        usage: vk::ImageUsageFlags,
+        mip_levels: u32,
+        queue_families: &[u32],
+        name: &str,
+    ) -> Result<Image, vk::Result> {
+        Self::create(device, allocator, vk::ImageType::TYPE_2D, extent, 1, format, usage, vk::ImageAspectFlags::COLOR, 1, mip_levels,
+            queue_families, vk::ImageViewType::TYPE_2D, name)
+    }
+
+    pub fn new_3d(device: &ash::Device, allocator: &mut Allocator, size: u32, format: vk::Format, usage: vk::ImageUsageFlags, name: &str) -> Result<Image, vk::Result> {
+        let extent = vk::Extent2D { width: size, height: size };
+        Self::create(device, allocator, vk::ImageType::TYPE_3D, extent, size, format, usage, vk::ImageAspectFlags::COLOR, 1, 1, &[],
+            vk::ImageViewType::TYPE_3D, name)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn create(
+        device: &ash::Device,
+        allocator: &mut Allocator,
+        image_type: vk::ImageType,
+        extent: vk::Extent2D,
+        depth: u32,
+        format: vk::Format,
+        usage: vk::ImageUsageFlags,
+        aspect_mask: vk::ImageAspectFlags,
+        layers: u32,
+        mip_levels: u32,
+        queue_families: &[u32],
+        view_type: vk::ImageViewType,
+        name: &str,
+    ) -> Result<Image, vk::Result> {
+        let flags = match view_type {
+            vk::ImageViewType::CUBE | vk::ImageViewType::CUBE_ARRAY => vk::ImageCreateFlags::CUBE_COMPATIBLE,
+            _ => vk::ImageCreateFlags::empty()
+        };
+
+        let image_create_info = vk::ImageCreateInfo::builder()
+            .flags(flags)
+            .image_type(image_type)
+            .format(format)
+            .extent(vk::Extent3D {
+                width: extent.width,
+                height: extent.height,
+                depth
+            })
+            .mip_levels(mip_levels)
+            .array_layers(layers)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .usage(usage)
+            .initial_layout(vk::ImageLayout::UNDEFINED);
+        let image_create_info = match queue_families.len() > 1 {
+            true => image_create_info.sharing_mode(vk::SharingMode::CONCURRENT).queue_family_indices(queue_families),
+            false => image_create_info.sharing_mode(vk::SharingMode::EXCLUSIVE)
+        };
+
+        let image = unsafe { device.create_image(&image_create_info, None)? };
+
+        let mem_requirements = unsafe { device.get_image_memory_requirements(image) };
+        let allocation = allocator.allocate(&AllocationCreateDesc {
+            requirements: mem_requirements,
+            location: MemoryLocation::GpuOnly,
+            linear: false,
+            name
+        }).expect("Failed to allocate memory for image!");
+
+        unsafe { device.bind_image_memory(image, allocation.memory(), allocation.offset())? };
+
+        let view = Self::create_view(device, image, format, aspect_mask, view_type, 0, layers, 0, mip_levels)?;
+
+        Ok(Image {
+            image,
+            view,
+            depth_view: None,
+            allocation,
+            format,
+            extent,
+            depth,
+            layers,
+            mip_levels,
+            aspect_mask
+        })
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn create_view(device: &ash::Device, image: vk::Image, format: vk::Format, aspect_mask: vk::ImageAspectFlags,
+        view_type: vk::ImageViewType, base_layer: u32, layer_count: u32, base_mip: u32, mip_count: u32
+    ) -> Result<vk::ImageView, vk::Result> {
+        let subresource_range = vk::ImageSubresourceRange::builder()
+            .aspect_mask(aspect_mask)
+            .base_mip_level(base_mip)
+            .level_count(mip_count)
+            .base_array_layer(base_layer)
+            .layer_count(layer_count);
+        let imageview_create_info = vk::ImageViewCreateInfo::builder()
+            .image(image)
+            .view_type(view_type)
+            .format(format)
+            .subresource_range(*subresource_range);
+
+        unsafe { device.create_image_view(&imageview_create_info, None) }
+    }
+
+    // The caller owns the returned view and has to destroy it before the image
+    pub fn create_layer_view(&self, device: &ash::Device, layer: u32) -> Result<vk::ImageView, vk::Result> {
+        self.create_layers_view(device, vk::ImageViewType::TYPE_2D, layer, 1)
+    }
+
+    // Like `create_layer_view` for a range of layers, e.g. a cube array seen as a 2D array for storage writes
+    pub fn create_layers_view(&self, device: &ash::Device, view_type: vk::ImageViewType, first_layer: u32, layer_count: u32) -> Result<vk::ImageView, vk::Result> {
+        Self::create_view(device, self.image, self.format, self.aspect_mask, view_type, first_layer, layer_count, 0, self.mip_levels)
+    }
+
+    // Every layer from mip level `first_mip` down, sampling it can't pick a finer level than that. The caller owns the view.
+    pub fn create_mips_view(&self, device: &ash::Device, view_type: vk::ImageViewType, first_mip: u32) -> Result<vk::ImageView, vk::Result> {
+        Self::create_view(device, self.image, self.format, self.aspect_mask, view_type, 0, self.layers, first_mip,
+            self.mip_levels - first_mip)
+    }
+
+    pub fn subresource_range(&self) -> vk::ImageSubresourceRange {
+        vk::ImageSubresourceRange {
+            aspect_mask: self.aspect_mask,
+            base_mip_level: 0,
+            level_count: self.mip_levels,
+            base_array_layer: 0,
+            layer_count: self.layers
+        }
+    }
+
+    pub fn new_depth(device: &ash::Device, allocator: &mut Allocator, extent: vk::Extent2D, usage: vk::ImageUsageFlags, name: &str) -> Result<Image, vk::Result> {
+        Self::new(device, allocator, extent, DEPTH_FORMAT, vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | usage, vk::ImageAspectFlags::DEPTH, name)
+    }
+
+    // Used as attachment through `view`, sampled through `depth_view`
+    pub fn new_depth_stencil(device: &ash::Device, allocator: &mut Allocator, extent: vk::Extent2D, usage: vk::ImageUsageFlags, name: &str) -> Result<Image, vk::Result> {
+        let mut image = Self::new(device, allocator, extent, DEPTH_STENCIL_FORMAT, vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | usage,
+            vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL, name)?;
+        image.depth_view = Some(Self::create_view(device, image.image, image.format, vk::ImageAspectFlags::DEPTH, vk::ImageViewType::TYPE_2D, 0, 1,
+            0, 1)?);
+
+        Ok(image)
+    }
+
+    pub fn transition_layout(&self, device: &ash::Device, command_buffer: vk::CommandBuffer, old_layout: vk::ImageLayout, new_layout: vk::ImageLayout) {
+        self.transition_layers(device, command_buffer, 0, self.layers, old_layout, new_layout);
+    }
+
+    // Same as `transition_layout` for part of an array, the other layers keep their layout
+    pub fn transition_layers(&self, device: &ash::Device, command_buffer: vk::CommandBuffer, first_layer: u32, layer_count: u32,
+        old_layout: vk::ImageLayout, new_layout: vk::ImageLayout
+    ) {
+        let (src_access, src_stage) = match old_layout {
+            vk::ImageLayout::UNDEFINED => (vk::AccessFlags::empty(), vk::PipelineStageFlags::TOP_OF_PIPE),
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL => (vk::AccessFlags::TRANSFER_WRITE, vk::PipelineStageFlags::TRANSFER),
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL => (vk::AccessFlags::TRANSFER_READ, vk::PipelineStageFlags::TRANSFER),
+            _ => (vk::AccessFlags::MEMORY_READ | vk::AccessFlags::MEMORY_WRITE, vk::PipelineStageFlags::ALL_COMMANDS)
+        };
+        let (dst_access, dst_stage) = match new_layout {
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL => (vk::AccessFlags::TRANSFER_WRITE, vk::PipelineStageFlags::TRANSFER),
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL => (vk::AccessFlags::TRANSFER_READ, vk::PipelineStageFlags::TRANSFER),
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL => (vk::AccessFlags::SHADER_READ, vk::PipelineStageFlags::FRAGMENT_SHADER | vk::PipelineStageFlags::COMPUTE_SHADER),
+            _ => (vk::AccessFlags::MEMORY_READ | vk::AccessFlags::MEMORY_WRITE, vk::PipelineStageFlags::ALL_COMMANDS)
+        };
+
+        let barriers = [vk::ImageMemoryBarrier::builder()
+            .old_layout(old_layout)
+            .new_layout(new_layout)
+            .src_access_mask(src_access)
+            .dst_access_mask(dst_access)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .image(self.image)
+            .subresource_range(vk::ImageSubresourceRange {
+                base_array_layer: first_layer,
+                layer_count,
+                ..self.subresource_range()
+            })
+            .build()
+        ];
+
+        unsafe {
+            device.cmd_pipeline_barrier(command_buffer, src_stage, dst_stage, vk::DependencyFlags::empty(), &[], &[], &barriers);
+        }
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device, allocator: &mut Allocator) {
+        allocator
+            .free(std::mem::take(&mut self.allocation))
+            .expect("Failed to free image memory!");
+        unsafe {
+            if let Some(depth_view) = self.depth_view {
+                device.destroy_image_view(depth_view, None);
+            }
+            device.destroy_image_view(self.view, None);
+            device.destroy_image(self.image, None);
+        }
+    }
+}
+
+pub const DEPTH_FORMAT: vk::Format = vk::Format::D32_SFLOAT;
+// Scene targets, the stencil marks selected objects for outlining
+pub const DEPTH_STENCIL_FORMAT: vk::Format = vk::Format::D32_SFLOAT_S8_UINT;
+
+// Depth comparison sampler for hardware filtered shadow lookups
+pub fn create_shadow_sampler(device: &ash::Device) -> Result<vk::Sampler, vk::Result> {
+    let sampler_info = vk::SamplerCreateInfo::builder()
+        .mag_filter(vk::Filter::LINEAR)
+        .min_filter(vk::Filter::LINEAR)
+        .mipmap_mode(vk::SamplerMipmapMode::NEAREST)
+        .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_BORDER)
+        .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_BORDER)
+        .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_BORDER)
+        .border_color(vk::BorderColor::FLOAT_OPAQUE_WHITE)
+        .compare_enable(true)
+        .compare_op(vk::CompareOp::LESS_OR_EQUAL);
+
+    unsafe { device.create_sampler(&sampler_info, None) }
+}
+
+pub fn create_sampler(device: &ash::Device, filter: vk::Filter, address_mode: vk::SamplerAddressMode) -> Result<vk::Sampler, vk::Result> {
+    let sampler_info = vk::SamplerCreateInfo::builder()
+        .mag_filter(filter)
+        .min_filter(filter)
+        .mipmap_mode(vk::SamplerMipmapMode::LINEAR)
+        .address_mode_u(address_mode)
+        .address_mode_v(address_mode)
+        .address_mode_w(address_mode)
+        .max_lod(vk::LOD_CLAMP_NONE);
+
+    unsafe { device.create_sampler(&sampler_info, None) }
+}
\ No newline at end of file
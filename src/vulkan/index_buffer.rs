@@ -66,6 +66,12 @@ impl IndexBuffer {
         self.index_count = data.len() as u32;
     }
 
+    // The indices last written with `update_buffer`, read back from the mapped memory
+    pub fn read(&self) -> &[u32] {
+        let src = self.allocation.mapped_ptr().unwrap().cast().as_ptr();
+        unsafe { std::slice::from_raw_parts(src, self.index_count as usize) }
+    }
+
     pub fn get_buffer(&self) -> vk::Buffer { self.buffer }
     pub fn get_index_count(&self) -> u32 { self.index_count }
 }
\ No newline at end of file
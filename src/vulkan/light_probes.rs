@@ -0,0 +1,332 @@
+use ash::vk;
+use gpu_allocator::vulkan::Allocator;
+use gpu_allocator::MemoryLocation;
+
+use super::compute_pipeline::ComputePipeline;
+use super::cubemap::{CaptureMode, CubemapCapture};
+use super::descriptor_allocator::DescriptorAllocator;
+use super::descriptors::Descriptors;
+use super::storage_buffer::StorageBuffer;
+use super::swapchain::VulkanSwapchain;
+
+use crate::utils::any_as_u8_slice;
+
+pub const SH_PROJECT_COMP: &[u32] = vk_shader_macros::include_glsl!("./shaders/sh_project.comp", kind: comp);
+
+// Must match SH_COEFFICIENTS in shaders/include/sh.glsl
+pub const SH_COEFFICIENTS: usize = 9;
+pub const MAX_LIGHT_PROBES: usize = 1024;
+// Irradiance is smooth, a small capture is plenty
+const CAPTURE_SIZE: u32 = 32;
+// Probes updated each frame at `GiQuality::High`, every one has its own capture
+pub const MAX_PROBE_UPDATES: usize = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GiQuality {
+    // Flat ambient light only, no probe is captured
+    Off,
+    // Every probe is captured once after the grid changes or `bake` is called
+    Baked,
+    // Probes are captured again and again, one per frame, so moving lights and objects show up in the bounce light
+    Low,
+    // Like `Low` with MAX_PROBE_UPDATES probes per frame
+    High,
+}
+
+impl GiQuality {
+    fn updates_per_frame(&self) -> usize {
+        match self {
+            GiQuality::High => MAX_PROBE_UPDATES,
+            _ => 1
+        }
+    }
+
+    fn is_dynamic(&self) -> bool {
+        matches!(self, GiQuality::Low | GiQuality::High)
+    }
+}
+
+// Probes on a regular grid, `origin` is the first probe and `counts` the number of probes along each axis
+#[derive(Clone, Copy, Debug)]
+pub struct LightProbeGrid {
+    pub origin: uv::Vec3,
+    pub spacing: uv::Vec3,
+    pub counts: [u32; 3],
+}
+
+impl LightProbeGrid {
+    // Probes on the corners of the box from `min` to `max` and evenly spread between them
+    pub fn new(min: uv::Vec3, max: uv::Vec3, counts: [u32; 3]) -> Self {
+        let counts = counts.map(|count| count.max(1));
+        let steps = uv::Vec3::new(
+            (counts[0] - 1).max(1) as f32,
+            (counts[1] - 1).max(1) as f32,
+            (counts[2] - 1).max(1) as f32
+        );
+
+        Self {
+            origin: min,
+            spacing: (max - min) / steps,
+            counts
+        }
+    }
+
+    pub fn probe_count(&self) -> usize {
+        self.counts.iter().product::<u32>() as usize
+    }
+
+    // x fastest, then y, then z
+    pub fn probe_position(&self, index: usize) -> uv::Vec3 {
+        let index = index as u32;
+        let x = index % self.counts[0];
+        let y = index / self.counts[0] % self.counts[1];
+        let z = index / (self.counts[0] * self.counts[1]);
+        self.origin + self.spacing * uv::Vec3::new(x as f32, y as f32, z as f32)
+    }
+}
+
+// Must match the LightProbeGrid block in shaders/include/light_probes.glsl
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct GpuLightProbeGrid {
+    origin: uv::Vec4,
+    spacing: uv::Vec4,
+    counts: [u32; 4],
+}
+
+#[repr(C)]
+struct ShProjectPushConstants {
+    _probe_index: u32,
+    _size: u32,
+    _hysteresis: f32,
+}
+
+// Diffuse ambient for everything in the scene, dynamic objects included: every probe of the grid captures the scene
+// around it and stores the irradiance as L2 spherical harmonics, fragments interpolate the eight probes around them.
+// Probes are baked one per frame, until the whole grid is baked the flat ambient light is used.
+//
+// With a dynamic `GiQuality` the grid never stops updating, DDGI style: the captures are lit by the probes
+// themselves, so light bounces once more every time the grid comes around, and new results are blended into the
+// old ones with `hysteresis` to hide the probes updating at different times.
+pub struct LightProbes {
+    grid: Option<LightProbeGrid>,
+    pub quality: GiQuality,
+    // Share of the old irradiance kept when a dynamic grid captures a probe again, from 0.0 to 1.0
+    pub hysteresis: f32,
+    // Next probe to capture
+    cursor: usize,
+    // Set by `set_grid` and `bake`, cleared once every probe has been captured since
+    baking: bool,
+    baked: bool,
+    // Probes captured in the frame being recorded, by capture
+    frame_probes: Vec<usize>,
+    captures: Vec<CubemapCapture>,
+    project_set_layout: vk::DescriptorSetLayout,
+    // One per capture
+    project_sets: Vec<vk::DescriptorSet>,
+    project_pipeline: ComputePipeline,
+    // Written by the projection only
+    coefficients: StorageBuffer,
+    grid_buffers: Vec<StorageBuffer>,
+}
+
+impl LightProbes {
+    pub fn new(
+        device: &ash::Device,
+        allocator: &mut Allocator,
+        descriptors: &mut DescriptorAllocator,
+        swapchain: &VulkanSwapchain,
+        scene_set_layouts: &[vk::DescriptorSetLayout],
+    ) -> Result<Self, vk::Result> {
+        let coefficients_size = (MAX_LIGHT_PROBES * SH_COEFFICIENTS * std::mem::size_of::<uv::Vec4>()) as u64;
+        let coefficients = StorageBuffer::new(device, allocator, coefficients_size, MemoryLocation::GpuOnly, "Light Probe Coefficients");
+        let grid_buffers = (0..swapchain.image_count)
+            .map(|_| StorageBuffer::new(device, allocator, std::mem::size_of::<GpuLightProbeGrid>() as u64, MemoryLocation::CpuToGpu,
+                "Light Probe Grid"))
+            .collect();
+
+        let project_set_layout = Descriptors::create_layout(device, &[
+            (vk::DescriptorType::COMBINED_IMAGE_SAMPLER, vk::ShaderStageFlags::COMPUTE),
+            (vk::DescriptorType::STORAGE_BUFFER, vk::ShaderStageFlags::COMPUTE),
+        ])?;
+
+        let mut captures = Vec::with_capacity(MAX_PROBE_UPDATES);
+        let mut project_sets = Vec::with_capacity(MAX_PROBE_UPDATES);
+        for _ in 0..MAX_PROBE_UPDATES {
+            let capture = CubemapCapture::new(device, allocator, descriptors, swapchain, scene_set_layouts, uv::Vec3::zero(), CAPTURE_SIZE,
+                CaptureMode::OnDemand)?;
+            let project_set = descriptors.allocate(device, project_set_layout)?;
+            Descriptors::write_image(device, project_set, 0, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, capture.descriptor_info());
+            Descriptors::write_buffer(device, project_set, 1, vk::DescriptorType::STORAGE_BUFFER, coefficients.descriptor_info());
+            captures.push(capture);
+            project_sets.push(project_set);
+        }
+
+        let project_pipeline = ComputePipeline::new(device, SH_PROJECT_COMP, &[project_set_layout],
+            std::mem::size_of::<ShProjectPushConstants>() as u32, &[])?;
+
+        Ok(Self {
+            grid: None,
+            quality: GiQuality::Baked,
+            hysteresis: 0.5,
+            cursor: 0,
+            baking: false,
+            baked: false,
+            frame_probes: Vec::new(),
+            captures,
+            project_set_layout,
+            project_sets,
+            project_pipeline,
+            coefficients,
+            grid_buffers
+        })
+    }
+
+    pub fn grid(&self) -> Option<&LightProbeGrid> {
+        self.grid.as_ref()
+    }
+
+    // Replaces the grid and bakes it from scratch, `None` goes back to the flat ambient light
+    pub fn set_grid(&mut self, grid: Option<LightProbeGrid>) {
+        if let Some(grid) = &grid {
+            assert!(grid.probe_count() <= MAX_LIGHT_PROBES, "Light probe grid has more than {} probes!", MAX_LIGHT_PROBES);
+        }
+        self.grid = grid;
+        self.baked = false;
+        self.baking = grid.is_some();
+        self.cursor = 0;
+    }
+
+    // Bakes every probe again, the old values stay in use until then
+    pub fn bake(&mut self) {
+        if self.grid.is_some() {
+            self.baking = true;
+            self.cursor = 0;
+        }
+    }
+
+    pub fn is_baked(&self) -> bool {
+        self.baked
+    }
+
+    // Picks the probes captured this frame and moves the captures to them, before the frame's command buffers are recorded
+    pub fn begin_frame(&mut self) {
+        self.frame_probes.clear();
+        let grid = match self.grid {
+            Some(grid) if self.quality != GiQuality::Off && (self.baking || self.quality.is_dynamic()) => grid,
+            _ => return
+        };
+
+        let probe_count = grid.probe_count();
+        let updates = self.quality.updates_per_frame().min(probe_count);
+        for (slot, capture) in self.captures.iter_mut().take(updates).enumerate() {
+            let probe = (self.cursor + slot) % probe_count;
+            capture.position = grid.probe_position(probe);
+            self.frame_probes.push(probe);
+        }
+    }
+
+    // Captures to record this frame by slot, each followed by `record_projection` of the same slot
+    pub fn pending_captures(&self) -> impl Iterator<Item = (usize, &CubemapCapture)> {
+        self.captures.iter().take(self.frame_probes.len()).enumerate()
+    }
+
+    // Called after the next frame is recorded, it projects the probes picked by `begin_frame` and the cursor moves past them
+    pub fn captured(&mut self) {
+        if let Some(grid) = self.grid {
+            let cursor = self.cursor + self.frame_probes.len();
+            if cursor >= grid.probe_count() && !self.frame_probes.is_empty() {
+                self.baked = true;
+                self.baking = false;
+            }
+            self.cursor = cursor % grid.probe_count();
+        }
+        for capture in &mut self.captures {
+            capture.captured();
+        }
+    }
+
+    pub fn update(&mut self, index: usize) {
+        let uniform = match self.grid {
+            Some(grid) => GpuLightProbeGrid {
+                origin: uv::Vec4::new(grid.origin.x, grid.origin.y, grid.origin.z, (self.baked && self.quality != GiQuality::Off) as u32 as f32),
+                spacing: grid.spacing.into_homogeneous_vector(),
+                counts: [grid.counts[0], grid.counts[1], grid.counts[2], 0]
+            },
+            None => GpuLightProbeGrid {
+                origin: uv::Vec4::zero(),
+                spacing: uv::Vec4::one(),
+                counts: [1, 1, 1, 0]
+            }
+        };
+        self.grid_buffers[index].update_buffer(0, &[uniform]);
+
+        for capture in &mut self.captures {
+            capture.update(index);
+        }
+    }
+
+    // Projects the capture of `slot` onto SH, after the capture's passes in the same command buffer
+    pub fn record_projection(&self, device: &ash::Device, command_buffer: vk::CommandBuffer, slot: usize) {
+        let probe_index = match self.frame_probes.get(slot) {
+            Some(&index) => index,
+            None => return
+        };
+
+        let barriers = [vk::MemoryBarrier::builder()
+            .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+            .dst_access_mask(vk::AccessFlags::SHADER_READ)
+            .build()
+        ];
+
+        // The first pass over a new grid has nothing to blend with
+        let push = ShProjectPushConstants {
+            _probe_index: probe_index as u32,
+            _size: CAPTURE_SIZE,
+            _hysteresis: match self.baked && self.quality.is_dynamic() {
+                true => self.hysteresis.clamp(0.0, 1.0),
+                false => 0.0
+            }
+        };
+
+        unsafe {
+            // The captures' own fragments read the coefficients that are about to be overwritten
+            device.cmd_pipeline_barrier(command_buffer, vk::PipelineStageFlags::FRAGMENT_SHADER, vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::DependencyFlags::empty(), &[], &[], &[]);
+
+            device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::COMPUTE, self.project_pipeline.pipeline);
+            device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::COMPUTE, self.project_pipeline.layout, 0,
+                &[self.project_sets[slot]], &[]);
+            device.cmd_push_constants(command_buffer, self.project_pipeline.layout, vk::ShaderStageFlags::COMPUTE, 0, any_as_u8_slice(&push));
+            device.cmd_dispatch(command_buffer, 1, 1, 1);
+
+            device.cmd_pipeline_barrier(command_buffer, vk::PipelineStageFlags::COMPUTE_SHADER, vk::PipelineStageFlags::FRAGMENT_SHADER,
+                vk::DependencyFlags::empty(), &barriers, &[], &[]);
+        }
+    }
+
+    pub fn grid_info(&self, index: usize) -> vk::DescriptorBufferInfo {
+        self.grid_buffers[index].descriptor_info()
+    }
+
+    pub fn coefficients_info(&self) -> vk::DescriptorBufferInfo {
+        self.coefficients.descriptor_info()
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device, allocator: &mut Allocator, descriptors: &mut DescriptorAllocator) -> Result<(), vk::Result> {
+        for &set in &self.project_sets {
+            descriptors.free(device, set)?;
+        }
+        for capture in &mut self.captures {
+            capture.destroy(device, allocator, descriptors)?;
+        }
+        self.project_pipeline.cleanup(device);
+        unsafe { device.destroy_descriptor_set_layout(self.project_set_layout, None) };
+        self.coefficients.destroy(device, allocator);
+        for buffer in &mut self.grid_buffers {
+            buffer.destroy(device, allocator);
+        }
+
+        Ok(())
+    }
+}
\ No newline at end of file
@@ -0,0 +1,556 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+
+use ash::vk;
+
+use super::lights::{PointLight, SpotLight};
+use super::pipeline::{Pipeline, PipelineConfig};
+use super::swapchain::VulkanSwapchain;
+use super::vertex::Vertex;
+
+pub const LIGHTMAPPED_FRAG: &[u32] = vk_shader_macros::include_glsl!("./shaders/lightmapped.frag", kind: frag);
+
+// Triangles per BVH leaf
+const LEAF_SIZE: usize = 4;
+
+#[derive(Clone, Copy, Debug)]
+pub struct LightmapSettings {
+    // Texels along each side of every mesh's lightmap
+    pub resolution: u32,
+    // Texels around each triangle's chart, filled with the nearest point of the triangle so filtering doesn't bleed
+    pub padding: u32,
+    // Hemisphere rays per texel for the bounced light
+    pub samples: u32,
+    // 0 bakes direct light only, sky included
+    pub bounces: u32,
+    // Radiance of rays leaving the scene
+    pub sky: uv::Vec3,
+    // Distance rays start off the surface so they don't hit it again
+    pub bias: f32,
+}
+
+impl Default for LightmapSettings {
+    fn default() -> Self {
+        Self {
+            resolution: 128,
+            padding: 1,
+            samples: 64,
+            bounces: 2,
+            sky: uv::Vec3::broadcast(0.15),
+            bias: 0.001
+        }
+    }
+}
+
+// Static geometry to bake, in the layout of the mesh's buffers
+#[derive(Clone, Debug)]
+pub struct LightmapMesh {
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+    pub model: uv::Mat4,
+    // Diffuse color of the surface, tints the light it bounces onto others
+    pub albedo: uv::Vec3,
+}
+
+pub struct BakedLightmap {
+    // The mesh unwrapped into the lightmap: every triangle gets its own vertices and chart
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+    pub resolution: u32,
+    // RGBA float texels row by row, alpha is 1.0 where a chart covers the texel
+    pub texels: Vec<f32>,
+}
+
+impl BakedLightmap {
+    pub fn extent(&self) -> vk::Extent2D {
+        vk::Extent2D { width: self.resolution, height: self.resolution }
+    }
+}
+
+// Scene pipeline of `Material::Lightmapped`, which takes its lighting from the lightmap alone. Everything but the
+// fragment shader comes from the basic scene pipeline's `scene_config`.
+pub fn lightmapped_pipeline(device: &ash::Device, swapchain: &VulkanSwapchain, renderpass: &vk::RenderPass,
+    scene_config: &PipelineConfig
+) -> Result<Pipeline, vk::Result> {
+    let config = PipelineConfig {
+        fragment_shader: LIGHTMAPPED_FRAG,
+        ..*scene_config
+    };
+    Pipeline::new(device, swapchain, renderpass, &config)
+}
+
+// Lays the triangles out in a grid of square charts, each scaled to fill its chart with its true shape so texel
+// density is even within a triangle. Charts are never shared, which wastes space but leaves no seams to stitch.
+pub fn unwrap(vertices: &[Vertex], indices: &[u32], settings: &LightmapSettings) -> (Vec<Vertex>, Vec<u32>) {
+    let triangle_count = indices.len() / 3;
+    let (columns, chart_size) = chart_layout(triangle_count, settings);
+    let usable = chart_size.saturating_sub(settings.padding * 2).max(1) as f32;
+    let resolution = settings.resolution as f32;
+
+    let mut unwrapped = Vec::with_capacity(triangle_count * 3);
+    for (triangle, corners) in indices.chunks_exact(3).enumerate() {
+        let [a, b, c] = [corners[0], corners[1], corners[2]].map(|index| vertices[index as usize]);
+
+        // Flatten the triangle onto its own plane, with the first edge along x
+        let edge1 = b.pos - a.pos;
+        let edge2 = c.pos - a.pos;
+        let x_axis = edge1.normalized();
+        let y_axis = edge1.cross(edge2).cross(x_axis).normalized();
+        let flat = match edge1.cross(edge2).mag_sq() > f32::EPSILON {
+            true => [uv::Vec2::zero(), uv::Vec2::new(edge1.mag(), 0.0), uv::Vec2::new(edge2.dot(x_axis), edge2.dot(y_axis))],
+            false => [uv::Vec2::zero(), uv::Vec2::new(1.0, 0.0), uv::Vec2::new(0.0, 1.0)]
+        };
+
+        let min = flat[0].min_by_component(flat[1]).min_by_component(flat[2]);
+        let max = flat[0].max_by_component(flat[1]).max_by_component(flat[2]);
+        let scale = usable / (max - min).component_max();
+        let origin = uv::Vec2::new(
+            ((triangle as u32 % columns) * chart_size + settings.padding) as f32,
+            ((triangle as u32 / columns) * chart_size + settings.padding) as f32
+        );
+
+        for (mut vertex, corner) in [a, b, c].into_iter().zip(flat) {
+            vertex.lightmap_uv = (origin + (corner - min) * scale) / resolution;
+            unwrapped.push(vertex);
+        }
+    }
+
+    let indices = (0..unwrapped.len() as u32).collect();
+    (unwrapped, indices)
+}
+
+// Charts per row and texels per chart side
+fn chart_layout(triangle_count: usize, settings: &LightmapSettings) -> (u32, u32) {
+    let columns = ((triangle_count as f32).sqrt().ceil() as u32).max(1);
+    let chart_size = settings.resolution / columns;
+    if chart_size <= settings.padding * 2 {
+        tracing::warn!("Lightmap resolution {} is too low for {} triangles!", settings.resolution, triangle_count);
+    }
+    (columns, chart_size.max(1))
+}
+
+#[derive(Clone, Copy)]
+struct Triangle {
+    position: uv::Vec3,
+    edge1: uv::Vec3,
+    edge2: uv::Vec3,
+    normal: uv::Vec3,
+    albedo: uv::Vec3,
+}
+
+impl Triangle {
+    // Möller-Trumbore, distance along `direction` to the hit
+    fn intersect(&self, origin: uv::Vec3, direction: uv::Vec3) -> Option<f32> {
+        let p = direction.cross(self.edge2);
+        let determinant = self.edge1.dot(p);
+        if determinant.abs() < 1e-9 {
+            return None;
+        }
+        let inverse_determinant = 1.0 / determinant;
+
+        let s = origin - self.position;
+        let u = s.dot(p) * inverse_determinant;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+        let q = s.cross(self.edge1);
+        let v = direction.dot(q) * inverse_determinant;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+
+        let t = self.edge2.dot(q) * inverse_determinant;
+        match t > 0.0 {
+            true => Some(t),
+            false => None
+        }
+    }
+
+    fn bounds(&self) -> (uv::Vec3, uv::Vec3) {
+        let corners = [self.position, self.position + self.edge1, self.position + self.edge2];
+        (
+            corners[0].min_by_component(corners[1]).min_by_component(corners[2]),
+            corners[0].max_by_component(corners[1]).max_by_component(corners[2])
+        )
+    }
+
+    fn centroid(&self) -> uv::Vec3 {
+        self.position + (self.edge1 + self.edge2) / 3.0
+    }
+}
+
+struct BvhNode {
+    min: uv::Vec3,
+    max: uv::Vec3,
+    // First triangle of a leaf, or the right child of an inner node whose left child follows it
+    start: usize,
+    // Zero for inner nodes
+    count: usize,
+}
+
+impl BvhNode {
+    fn hit(&self, origin: uv::Vec3, inverse_direction: uv::Vec3, max_distance: f32) -> bool {
+        let t1 = (self.min - origin) * inverse_direction;
+        let t2 = (self.max - origin) * inverse_direction;
+        let near = t1.min_by_component(t2).component_max();
+        let far = t1.max_by_component(t2).component_min();
+        far >= near.max(0.0) && near < max_distance
+    }
+}
+
+// Every mesh of the bake in world space, split at the median of the longest axis
+struct Scene {
+    triangles: Vec<Triangle>,
+    nodes: Vec<BvhNode>,
+}
+
+impl Scene {
+    fn new(meshes: &[LightmapMesh]) -> Self {
+        let mut triangles = Vec::new();
+        for mesh in meshes {
+            for corners in mesh.indices.chunks_exact(3) {
+                let [a, b, c] = [corners[0], corners[1], corners[2]].map(|index| mesh.model.transform_point3(mesh.vertices[index as usize].pos));
+                let edge1 = b - a;
+                let edge2 = c - a;
+                triangles.push(Triangle {
+                    position: a,
+                    edge1,
+                    edge2,
+                    normal: edge1.cross(edge2).normalized(),
+                    albedo: mesh.albedo
+                });
+            }
+        }
+
+        let mut scene = Self {
+            triangles,
+            nodes: Vec::new()
+        };
+        if !scene.triangles.is_empty() {
+            scene.build(0, scene.triangles.len());
+        }
+        scene
+    }
+
+    fn build(&mut self, start: usize, end: usize) -> usize {
+        let (mut min, mut max) = self.triangles[start].bounds();
+        let mut centroid_min = self.triangles[start].centroid();
+        let mut centroid_max = centroid_min;
+        for triangle in &self.triangles[start..end] {
+            let (triangle_min, triangle_max) = triangle.bounds();
+            min = min.min_by_component(triangle_min);
+            max = max.max_by_component(triangle_max);
+            centroid_min = centroid_min.min_by_component(triangle.centroid());
+            centroid_max = centroid_max.max_by_component(triangle.centroid());
+        }
+
+        let index = self.nodes.len();
+        self.nodes.push(BvhNode { min, max, start, count: end - start });
+
+        let extent = centroid_max - centroid_min;
+        if end - start <= LEAF_SIZE || extent.component_max() <= f32::EPSILON {
+            return index;
+        }
+
+        let axis = match (extent.x >= extent.y, extent.x >= extent.z, extent.y >= extent.z) {
+            (true, true, _) => 0,
+            (_, _, true) => 1,
+            _ => 2
+        };
+        let middle = (start + end) / 2;
+        self.triangles[start..end].select_nth_unstable_by(middle - start, |a, b| {
+            a.centroid().as_array()[axis].total_cmp(&b.centroid().as_array()[axis])
+        });
+
+        self.build(start, middle);
+        let right = self.build(middle, end);
+        self.nodes[index].start = right;
+        self.nodes[index].count = 0;
+        index
+    }
+
+    // Nearest hit closer than `max_distance`, or with `any` the first one found
+    fn intersect(&self, origin: uv::Vec3, direction: uv::Vec3, max_distance: f32, any: bool) -> Option<(f32, usize)> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+
+        let inverse_direction = uv::Vec3::one() / direction;
+        let mut nearest: Option<(f32, usize)> = None;
+        let mut stack = vec![0];
+        while let Some(node_index) = stack.pop() {
+            let node = &self.nodes[node_index];
+            let max_distance = nearest.map(|(distance, _)| distance).unwrap_or(max_distance);
+            if !node.hit(origin, inverse_direction, max_distance) {
+                continue;
+            }
+
+            if node.count == 0 {
+                stack.push(node.start);
+                stack.push(node_index + 1);
+                continue;
+            }
+            for index in node.start..node.start + node.count {
+                if let Some(distance) = self.triangles[index].intersect(origin, direction) {
+                    if distance < max_distance && nearest.is_none_or(|(nearest, _)| distance < nearest) {
+                        nearest = Some((distance, index));
+                        if any {
+                            return nearest;
+                        }
+                    }
+                }
+            }
+        }
+        nearest
+    }
+}
+
+// Collects the static scene, `bake` traces it on the calling thread and `spawn` in the background
+#[derive(Clone, Debug, Default)]
+pub struct LightmapBaker {
+    pub settings: LightmapSettings,
+    pub meshes: Vec<LightmapMesh>,
+    pub point_lights: Vec<PointLight>,
+    pub spot_lights: Vec<SpotLight>,
+}
+
+impl LightmapBaker {
+    pub fn new(settings: LightmapSettings, point_lights: &[PointLight], spot_lights: &[SpotLight]) -> Self {
+        Self {
+            settings,
+            meshes: Vec::new(),
+            point_lights: point_lights.to_vec(),
+            spot_lights: spot_lights.to_vec()
+        }
+    }
+
+    // Index of the mesh's lightmap in the bake results
+    pub fn add_mesh(&mut self, mesh: LightmapMesh) -> usize {
+        self.meshes.push(mesh);
+        self.meshes.len() - 1
+    }
+
+    pub fn bake(&self) -> Vec<BakedLightmap> {
+        self.bake_with_progress(&AtomicUsize::new(0))
+    }
+
+    pub fn spawn(self) -> LightmapJob {
+        let progress = Arc::new(AtomicUsize::new(0));
+        let total = self.meshes.len() * self.settings.resolution as usize;
+        let worker_progress = progress.clone();
+        let handle = std::thread::Builder::new()
+            .name("Lightmap Baker".to_string())
+            .spawn(move || self.bake_with_progress(&worker_progress))
+            .expect("Failed to spawn the lightmap baker!");
+
+        LightmapJob {
+            progress,
+            total,
+            handle: Some(handle)
+        }
+    }
+
+    // `progress` counts the texel rows done, which are split between all cores
+    fn bake_with_progress(&self, progress: &AtomicUsize) -> Vec<BakedLightmap> {
+        let scene = Scene::new(&self.meshes);
+        let resolution = self.settings.resolution as usize;
+        let threads = std::thread::available_parallelism().map(|count| count.get()).unwrap_or(1);
+        let rows_per_thread = resolution.div_ceil(threads);
+
+        let mut baked = Vec::with_capacity(self.meshes.len());
+        for (mesh_index, mesh) in self.meshes.iter().enumerate() {
+            let (vertices, indices) = unwrap(&mesh.vertices, &mesh.indices, &self.settings);
+            let mut texels = vec![0.0; resolution * resolution * 4];
+
+            std::thread::scope(|scope| {
+                for (chunk, rows) in texels.chunks_mut((rows_per_thread * resolution * 4).max(4)).enumerate() {
+                    let (scene, vertices, model) = (&scene, &vertices, mesh.model);
+                    scope.spawn(move || {
+                        for (row, texels) in rows.chunks_mut(resolution * 4).enumerate() {
+                            let y = chunk * rows_per_thread + row;
+                            for (x, texel) in texels.chunks_exact_mut(4).enumerate() {
+                                if let Some(lighting) = self.bake_texel(scene, vertices, model, mesh_index, x as u32, y as u32) {
+                                    texel.copy_from_slice(&[lighting.x, lighting.y, lighting.z, 1.0]);
+                                }
+                            }
+                            progress.fetch_add(1, Ordering::Relaxed);
+                        }
+                    });
+                }
+            });
+
+            baked.push(BakedLightmap {
+                vertices,
+                indices,
+                resolution: self.settings.resolution,
+                texels
+            });
+        }
+        baked
+    }
+
+    // Lighting at the point of the chart covering the texel, `None` for texels outside every chart
+    fn bake_texel(&self, scene: &Scene, vertices: &[Vertex], model: uv::Mat4, mesh_index: usize, x: u32, y: u32) -> Option<uv::Vec3> {
+        let (columns, chart_size) = chart_layout(vertices.len() / 3, &self.settings);
+        let (column, row) = (x / chart_size, y / chart_size);
+        let triangle = (row * columns + column) as usize;
+        if column >= columns || triangle >= vertices.len() / 3 {
+            return None;
+        }
+
+        // Barycentrics of the texel center, pulled onto the triangle for padding texels outside it
+        let corners = &vertices[triangle * 3..triangle * 3 + 3];
+        let resolution = self.settings.resolution as f32;
+        let [a, b, c] = [0, 1, 2].map(|corner| corners[corner].lightmap_uv * resolution);
+        let point = uv::Vec2::new(x as f32 + 0.5, y as f32 + 0.5);
+        let edge_function = |from: uv::Vec2, to: uv::Vec2, point: uv::Vec2| (to.x - from.x) * (point.y - from.y) - (to.y - from.y) * (point.x - from.x);
+        let area = edge_function(a, b, c);
+        let weights = match area.abs() > f32::EPSILON {
+            true => uv::Vec3::new(edge_function(b, c, point) / area, edge_function(c, a, point) / area, edge_function(a, b, point) / area),
+            false => uv::Vec3::broadcast(1.0 / 3.0)
+        };
+        let weights = weights.max_by_component(uv::Vec3::zero());
+        let weights = weights / (weights.x + weights.y + weights.z).max(f32::EPSILON);
+
+        let local_position = corners[0].pos * weights.x + corners[1].pos * weights.y + corners[2].pos * weights.z;
+        let local_normal = corners[0].normal * weights.x + corners[1].normal * weights.y + corners[2].normal * weights.z;
+        let position = model.transform_point3(local_position);
+        let geometric_normal = model.transform_vec3(corners[1].pos - corners[0].pos).cross(model.transform_vec3(corners[2].pos - corners[0].pos));
+        let normal = match local_normal.mag_sq() > f32::EPSILON {
+            true => model.inversed().transposed().transform_vec3(local_normal),
+            false => geometric_normal
+        }.normalized();
+        let origin = position + normal * self.settings.bias;
+
+        let mut seed = (mesh_index as u32).wrapping_mul(0x9e37_79b9) ^ (y * self.settings.resolution + x).wrapping_mul(0x85eb_ca6b) | 1;
+        let mut lighting = self.direct(scene, origin, normal);
+        if self.settings.bounces > 0 && self.settings.samples > 0 {
+            let mut indirect = uv::Vec3::zero();
+            for _ in 0..self.settings.samples {
+                indirect += self.trace(scene, origin, cosine_sample(normal, &mut seed), 1, &mut seed);
+            }
+            lighting += indirect / self.settings.samples as f32;
+        }
+        Some(lighting)
+    }
+
+    // Radiance arriving along the ray. Surfaces reflect `albedo * lighting` like the shaders do, so with cosine
+    // weighted rays the average radiance is directly the lighting the shaders expect.
+    fn trace(&self, scene: &Scene, origin: uv::Vec3, direction: uv::Vec3, depth: u32, seed: &mut u32) -> uv::Vec3 {
+        let (distance, index) = match scene.intersect(origin, direction, f32::MAX, false) {
+            Some(hit) => hit,
+            None => return self.settings.sky
+        };
+
+        let triangle = &scene.triangles[index];
+        // Lit from both sides, closed meshes never show their back faces anyway
+        let normal = match triangle.normal.dot(direction) > 0.0 {
+            true => -triangle.normal,
+            false => triangle.normal
+        };
+        let origin = origin + direction * distance + normal * self.settings.bias;
+
+        let mut lighting = self.direct(scene, origin, normal);
+        if depth < self.settings.bounces {
+            lighting += self.trace(scene, origin, cosine_sample(normal, seed), depth + 1, seed);
+        }
+        triangle.albedo * lighting
+    }
+
+    // Same falloff as shade_light in shaders/include/lighting.glsl, with ray traced shadows and without cookies
+    fn direct(&self, scene: &Scene, position: uv::Vec3, normal: uv::Vec3) -> uv::Vec3 {
+        let shade = |light_position: uv::Vec3, radius: f32, radiance: uv::Vec3, cast_shadows: bool| {
+            let to_light = light_position - position;
+            let distance = to_light.mag();
+            let direction = to_light / distance.max(f32::EPSILON);
+            let window = (1.0 - (distance / radius).powi(4)).clamp(0.0, 1.0);
+            let attenuation = window * window / (distance * distance + 1.0);
+            let lambert = normal.dot(direction).max(0.0);
+            if attenuation * lambert <= 0.0 {
+                return (uv::Vec3::zero(), direction);
+            }
+
+            match cast_shadows && scene.intersect(position, direction, distance, true).is_some() {
+                true => (uv::Vec3::zero(), direction),
+                false => (radiance * lambert * attenuation, direction)
+            }
+        };
+
+        let mut lighting = uv::Vec3::zero();
+        for light in &self.point_lights {
+            lighting += shade(light.position, light.radius, light.color * light.intensity, light.cast_shadows).0;
+        }
+        for light in &self.spot_lights {
+            let (radiance, direction) = shade(light.position, light.range, light.color * light.intensity, light.cast_shadows);
+            let cone = smoothstep(light.outer_angle.cos(), light.inner_angle.cos(), (-direction).dot(light.direction.normalized()));
+            lighting += radiance * cone.powf(light.falloff.max(0.001));
+        }
+        lighting
+    }
+}
+
+pub struct LightmapJob {
+    progress: Arc<AtomicUsize>,
+    total: usize,
+    handle: Option<JoinHandle<Vec<BakedLightmap>>>,
+}
+
+impl LightmapJob {
+    // From 0.0 to 1.0
+    pub fn progress(&self) -> f32 {
+        match self.total {
+            0 => 1.0,
+            total => self.progress.load(Ordering::Relaxed) as f32 / total as f32
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.handle.as_ref().is_none_or(|handle| handle.is_finished())
+    }
+
+    // The lightmaps once the bake is done, in the order the meshes were added. Only returned once.
+    pub fn poll(&mut self) -> Option<Vec<BakedLightmap>> {
+        if !self.is_finished() {
+            return None;
+        }
+        match self.handle.take()?.join() {
+            Ok(baked) => Some(baked),
+            Err(_) => {
+                tracing::error!("Lightmap baker panicked!");
+                None
+            }
+        }
+    }
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    if (edge1 - edge0).abs() <= f32::EPSILON {
+        return if x >= edge1 { 1.0 } else { 0.0 };
+    }
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+// xorshift, uniform in [0, 1)
+fn random(seed: &mut u32) -> f32 {
+    *seed ^= *seed << 13;
+    *seed ^= *seed >> 17;
+    *seed ^= *seed << 5;
+    (*seed >> 8) as f32 / (1 << 24) as f32
+}
+
+// Direction around `normal` with a density proportional to the cosine of their angle
+fn cosine_sample(normal: uv::Vec3, seed: &mut u32) -> uv::Vec3 {
+    let helper = match normal.x.abs() > 0.9 {
+        true => uv::Vec3::unit_y(),
+        false => uv::Vec3::unit_x()
+    };
+    let tangent = helper.cross(normal).normalized();
+    let bitangent = normal.cross(tangent);
+
+    let angle = std::f32::consts::TAU * random(seed);
+    let radius_squared = random(seed);
+    let radius = radius_squared.sqrt();
+    tangent * (radius * angle.cos()) + bitangent * (radius * angle.sin()) + normal * (1.0 - radius_squared).sqrt()
+}
\ No newline at end of file
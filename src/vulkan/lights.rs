@@ -0,0 +1,157 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct PointLight {
+    pub position: uv::Vec3,
+    pub color: uv::Vec3,
+    pub intensity: f32,
+    // Distance at which the light's contribution reaches zero, also used for culling
+    pub radius: f32,
+    // Only a few point lights get a shadow cube each frame, see `ShadowSystem::assign`
+    pub cast_shadows: bool,
+}
+
+impl PointLight {
+    pub fn new(position: uv::Vec3, color: uv::Vec3, intensity: f32, radius: f32) -> Self {
+        Self {
+            position,
+            color,
+            intensity,
+            radius,
+            cast_shadows: true
+        }
+    }
+
+    pub fn as_gpu_light(&self, shadow_layer: Option<u32>) -> GpuLight {
+        let shadow_layer = shadow_layer.map(|layer| layer as f32).unwrap_or(-1.0);
+
+        GpuLight {
+            position_radius: uv::Vec4::new(self.position.x, self.position.y, self.position.z, self.radius),
+            color_intensity: uv::Vec4::new(self.color.x, self.color.y, self.color.z, self.intensity),
+            direction_falloff: uv::Vec4::zero(),
+            spot_params: uv::Vec4::new(0.0, 0.0, -1.0, shadow_layer),
+            view_projection: uv::Mat4::identity(),
+            light_type: LIGHT_TYPE_POINT,
+            _padding: [0; 3],
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct SpotLight {
+    pub position: uv::Vec3,
+    pub direction: uv::Vec3,
+    pub color: uv::Vec3,
+    pub intensity: f32,
+    pub range: f32,
+    // Half angles in radians, the light fades out between the inner and the outer cone
+    pub inner_angle: f32,
+    pub outer_angle: f32,
+    // Exponent applied to the fade between the cones, 1.0 is a plain smoothstep
+    pub falloff: f32,
+    // Layer of the renderer's cookie texture projected through the cone
+    pub cookie: Option<u32>,
+    pub cast_shadows: bool,
+}
+
+impl SpotLight {
+    pub fn new(position: uv::Vec3, direction: uv::Vec3, color: uv::Vec3, intensity: f32, range: f32, outer_angle: f32) -> Self {
+        Self {
+            position,
+            direction: direction.normalized(),
+            color,
+            intensity,
+            range,
+            inner_angle: outer_angle * 0.8,
+            outer_angle,
+            falloff: 1.0,
+            cookie: None,
+            cast_shadows: true
+        }
+    }
+
+    // Projection through the cone, shared by the shadow map and the cookie lookup
+    pub fn view_projection(&self) -> uv::Mat4 {
+        let up = if self.direction.y.abs() > 0.99 { uv::Vec3::unit_z() } else { uv::Vec3::unit_y() };
+        let view = uv::Mat4::look_at(self.position, self.position + self.direction, up);
+        let projection = uv::projection::rh_yup::perspective_vk(self.outer_angle * 2.0, 1.0, SPOT_LIGHT_NEAR, self.range);
+        projection * view
+    }
+
+    pub fn as_gpu_light(&self, shadow_layer: Option<u32>) -> GpuLight {
+        let layer_or_none = |layer: Option<u32>| layer.map(|layer| layer as f32).unwrap_or(-1.0);
+
+        GpuLight {
+            position_radius: uv::Vec4::new(self.position.x, self.position.y, self.position.z, self.range),
+            color_intensity: uv::Vec4::new(self.color.x, self.color.y, self.color.z, self.intensity),
+            direction_falloff: uv::Vec4::new(self.direction.x, self.direction.y, self.direction.z, self.falloff),
+            spot_params: uv::Vec4::new(self.inner_angle.cos(), self.outer_angle.cos(), layer_or_none(self.cookie), layer_or_none(shadow_layer)),
+            view_projection: self.view_projection(),
+            light_type: LIGHT_TYPE_SPOT,
+            _padding: [0; 3],
+        }
+    }
+}
+
+pub const SPOT_LIGHT_NEAR: f32 = 0.05;
+
+// Light from infinitely far away, the sun or the moon. The scene has at most one, it reaches everything and casts no
+// shadows.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct DirectionalLight {
+    // Towards the light
+    pub direction: uv::Vec3,
+    pub color: uv::Vec3,
+    pub intensity: f32,
+}
+
+impl DirectionalLight {
+    pub fn new(direction: uv::Vec3, color: uv::Vec3, intensity: f32) -> Self {
+        Self {
+            direction: direction.normalized(),
+            color,
+            intensity
+        }
+    }
+
+    // `sun_direction` and `sun_color` of the light buffer header
+    pub fn uniform(&self) -> [uv::Vec4; 2] {
+        let color = self.color * self.intensity;
+        [
+            uv::Vec4::new(self.direction.x, self.direction.y, self.direction.z, 0.0),
+            uv::Vec4::new(color.x, color.y, color.z, 0.0)
+        ]
+    }
+}
+
+// Mirrors the std430 `Light` struct in shaders/include/lights.glsl
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct GpuLight {
+    pub position_radius: uv::Vec4,
+    pub color_intensity: uv::Vec4,
+    pub direction_falloff: uv::Vec4,
+    // cos(inner angle), cos(outer angle), cookie layer, shadow layer or cube (negative when unused)
+    pub spot_params: uv::Vec4,
+    pub view_projection: uv::Mat4,
+    pub light_type: u32,
+    pub _padding: [u32; 3],
+}
+
+// Must match the constants in shaders/include/lights.glsl
+pub const LIGHT_TYPE_POINT: u32 = 0;
+pub const LIGHT_TYPE_SPOT: u32 = 1;
+
+// Mirrors the start of the `Lights` storage block, followed by the light array
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct LightBufferHeader {
+    pub count: u32,
+    pub _padding: [u32; 3],
+    pub ambient: uv::Vec4,
+    pub shadow_filter: [u32; 4],
+    pub shadow_params: uv::Vec4,
+    // DirectionalLight::uniform, zero without one
+    pub sun_direction: uv::Vec4,
+    pub sun_color: uv::Vec4,
+}
\ No newline at end of file
@@ -1,11 +1,30 @@
 use ash::vk;
 
+use super::physical_device::PhysicalDevice;
 use super::queue::*;
+use super::shading_rate::ShadingRateSupport;
+use super::crash_diagnostics;
+use super::conditional_rendering;
+use super::portability::PortabilitySubset;
+use super::multiview::MultiviewSupport;
+
+// Lets something other than the renderer create the instance and device from the renderer's create infos and choose
+// the GPU, like an OpenXR runtime that adds extensions of its own and has to render on the headset's GPU
+pub trait DeviceFactory {
+    fn create_instance(&self, entry: &ash::Entry, create_info: &vk::InstanceCreateInfo) -> Result<ash::Instance, vk::Result>;
+    fn physical_device(&self, instance: &ash::Instance) -> Result<vk::PhysicalDevice, vk::Result>;
+    fn create_device(&self, instance: &ash::Instance, physical_device: vk::PhysicalDevice, create_info: &vk::DeviceCreateInfo
+    ) -> Result<ash::Device, vk::Result>;
+}
 
 pub struct LogicalDevice {}
 
 impl LogicalDevice {
-    pub fn new(instance: &ash::Instance, physical_device: vk::PhysicalDevice, queue_families: &QueueFamilies, layer_names: &[&str]
+    // `swapchain` enables presenting, devices rendering offscreen don't need it
+    // `full_screen_exclusive` enables VK_EXT_full_screen_exclusive, only with `swapchain`
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(instance: &ash::Instance, physical_device: vk::PhysicalDevice, queue_families: &QueueFamilies, layer_names: &[&str], swapchain: bool,
+        full_screen_exclusive: bool, factory: Option<&dyn DeviceFactory>
     ) -> Result<(ash::Device, Queues), vk::Result> {
         let layer_names_c: Vec<std::ffi::CString> = layer_names
             .iter()
@@ -29,17 +48,86 @@ impl LogicalDevice {
                 .build()
         ];
 
-        let device_extension_name_pointers: Vec<*const i8> = 
-            vec![
-                ash::extensions::khr::Swapchain::name().as_ptr()
-            ];
+        let shading_rate = ShadingRateSupport::query(instance, physical_device);
+        let mut device_extension_name_pointers: Vec<*const i8> = match swapchain {
+            true => vec![ash::extensions::khr::Swapchain::name().as_ptr()],
+            false => vec![]
+        };
+        if swapchain && full_screen_exclusive {
+            device_extension_name_pointers.push(vk::ExtFullScreenExclusiveFn::name().as_ptr());
+        }
+        if shading_rate.is_some() {
+            device_extension_name_pointers.push(vk::KhrFragmentShadingRateFn::name().as_ptr());
+        }
+        // Draws predicated on occlusion query results, see `ConditionalRendering`
+        let conditional = conditional_rendering::supported(instance, physical_device);
+        if conditional {
+            device_extension_name_pointers.push(vk::ExtConditionalRenderingFn::name().as_ptr());
+        }
+        // Lines the GPU zones up with the CPU ones, see `GpuProfiler`
+        #[cfg(feature = "tracy")]
+        if super::gpu_profiler::calibration_supported(instance, physical_device) {
+            device_extension_name_pointers.push(ash::extensions::ext::CalibratedTimestamps::name().as_ptr());
+        }
+        for extension in crash_diagnostics::device_extensions(instance, physical_device) {
+            device_extension_name_pointers.push(extension.as_ptr());
+        }
+        // MoltenVK and other layered implementations, the features they do have are enabled as they are
+        let mut portability = PortabilitySubset::query(instance, physical_device);
+        if portability.is_some() {
+            device_extension_name_pointers.push(vk::KhrPortabilitySubsetFn::name().as_ptr());
+        }
+        
+        // Clip distances are used to cut geometry at the planar reflection plane,
+        // cube arrays hold the point light shadow maps
+        let multi_draw_indirect = PhysicalDevice::supports_multi_draw_indirect(instance, physical_device);
+        let indirect_count = PhysicalDevice::supports_indirect_count(instance, physical_device);
+        let rate_image = shading_rate.is_some_and(|support| support.attachment_texel_size.is_some());
+        let fragment_stores = PhysicalDevice::supports_fragment_stores(instance, physical_device);
+        // Streamed textures may be block compressed
+        let texture_compression = PhysicalDevice::supports_texture_compression(instance, physical_device);
+        let precise_occlusion = PhysicalDevice::supports_precise_occlusion(instance, physical_device);
+        let features = vk::PhysicalDeviceFeatures::builder()
+            .shader_clip_distance(true)
+            .image_cube_array(true)
+            .multi_draw_indirect(multi_draw_indirect)
+            .draw_indirect_first_instance(multi_draw_indirect)
+            .shader_storage_image_extended_formats(rate_image)
+            .fragment_stores_and_atomics(fragment_stores)
+            .texture_compression_bc(texture_compression)
+            .occlusion_query_precise(precise_occlusion);
+        // Point light shadow cubes are drawn in one multiview pass
+        let mut vulkan11_features = vk::PhysicalDeviceVulkan11Features::builder()
+            .multiview(MultiviewSupport::query(instance, physical_device).is_some());
+        let mut vulkan12_features = vk::PhysicalDeviceVulkan12Features::builder()
+            .draw_indirect_count(indirect_count);
+        let mut shading_rate_features = vk::PhysicalDeviceFragmentShadingRateFeaturesKHR::builder()
+            .pipeline_fragment_shading_rate(shading_rate.is_some())
+            .attachment_fragment_shading_rate(rate_image);
+        let mut conditional_features = vk::PhysicalDeviceConditionalRenderingFeaturesEXT::builder()
+            .conditional_rendering(true);
         
-        let device_create_info = vk::DeviceCreateInfo::builder()
+        let mut device_create_info = vk::DeviceCreateInfo::builder()
+            .push_next(&mut vulkan11_features)
+            .push_next(&mut vulkan12_features)
             .queue_create_infos(&queue_infos)
+            .enabled_features(&features)
             .enabled_extension_names(&device_extension_name_pointers)
             .enabled_layer_names(&layer_name_pointers);
+        if shading_rate.is_some() {
+            device_create_info = device_create_info.push_next(&mut shading_rate_features);
+        }
+        if conditional {
+            device_create_info = device_create_info.push_next(&mut conditional_features);
+        }
+        if let Some(portability) = &mut portability {
+            device_create_info = device_create_info.push_next(&mut portability.features);
+        }
         
-        let logical_device = unsafe { instance.create_device(physical_device, &device_create_info, None)? };
+        let logical_device = match factory {
+            Some(factory) => factory.create_device(instance, physical_device, &device_create_info)?,
+            None => unsafe { instance.create_device(physical_device, &device_create_info, None)? }
+        };
 
         let graphics_queue = unsafe { logical_device.get_device_queue(queue_families.graphics.unwrap(), 0) };
         let transfer_queue = unsafe { logical_device.get_device_queue(queue_families.transfer.unwrap(), 0) };
@@ -0,0 +1,402 @@
+use std::collections::HashMap;
+
+use ash::vk;
+use gpu_allocator::vulkan::Allocator;
+use gpu_allocator::MemoryLocation;
+use serde::{Deserialize, Serialize};
+
+use super::command_pools::Pools;
+use super::descriptor_allocator::DescriptorAllocator;
+use super::descriptors::Descriptors;
+use super::game_object::GameObject;
+use super::object_buffer::MAX_OBJECTS;
+use super::pipeline::BlendMode;
+use super::storage_buffer::StorageBuffer;
+use super::texture::Texture;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Material {
+    Basic,
+    // Samples the planar reflection target in screen space (mirrors, water)
+    Reflective,
+    // Static geometry lit only by its baked lightmap, see `GameObject::lightmap`
+    Lightmapped,
+    // Alpha tested against `GameObject::alpha_cutoff` (foliage, fences), see `cutout_pipeline`
+    Cutout,
+    // Blended over the opaque scene back to front with `GameObject::opacity`, see `TransparentPipelines`
+    Transparent(BlendMode),
+    // Drawn between the opaque and transparent objects with `GameObject::water`, see `Water`
+    Water,
+    // Opaque, colored by the renderer's virtual texture instead of a texture of its own (terrain), see `VirtualTexture`
+    Virtual
+}
+
+// Room for every object to have its own material plus the ones it had over the last frames in flight
+pub const MAX_MATERIALS: usize = MAX_OBJECTS * 4;
+
+// Mirrors `MaterialData` in shaders/include/material.glsl
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct MaterialUniform {
+    // rgb and opacity, only transparent materials blend with the opacity
+    pub color: uv::Vec4,
+    // roughness, 1.0 when a normal map is bound, parallax depth, alpha cutoff of cutout materials
+    pub params: uv::Vec4,
+    // 1.0 when the material texture is the object's color texture, wind response, unused
+    pub texture_params: uv::Vec4,
+    // `WaterMaterial::uniform` of water materials
+    pub water: [uv::Vec4; 3],
+}
+
+// Game objects drawn with the same material and parameters share one slot of the material buffer
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct MaterialKey {
+    pub material: Material,
+    color: [u32; 3],
+    opacity: u32,
+    roughness: u32,
+    lightmap: Option<usize>,
+    texture: Option<usize>,
+    normal_map: Option<usize>,
+    parallax_depth: u32,
+    alpha_cutoff: u32,
+    wind: u32,
+    water: [[u32; 4]; 3],
+}
+
+impl MaterialKey {
+    pub fn new(game_object: &GameObject) -> Self {
+        let color = game_object.color;
+        let water = match game_object.material {
+            Material::Water => game_object.water.uniform(),
+            _ => [uv::Vec4::zero(); 3]
+        };
+        Self {
+            material: game_object.material,
+            color: [color.x.to_bits(), color.y.to_bits(), color.z.to_bits()],
+            opacity: game_object.opacity.to_bits(),
+            roughness: game_object.roughness.to_bits(),
+            lightmap: game_object.lightmap,
+            texture: game_object.texture,
+            normal_map: game_object.normal_map,
+            parallax_depth: game_object.parallax_depth.to_bits(),
+            alpha_cutoff: game_object.alpha_cutoff.to_bits(),
+            wind: game_object.wind.to_bits(),
+            water: water.map(|params| [params.x, params.y, params.z, params.w].map(f32::to_bits))
+        }
+    }
+
+    // Materials whose normal map doesn't exist (anymore) shade with their vertex normals, and have no height to trace.
+    // Without a color texture the material texture is whatever the material samples (lightmap, reflection, ...).
+    fn uniform(&self, normal_mapped: bool, textured: bool) -> MaterialUniform {
+        let [r, g, b] = self.color.map(f32::from_bits);
+        let parallax_depth = match normal_mapped {
+            true => f32::from_bits(self.parallax_depth),
+            false => 0.0
+        };
+        let alpha_cutoff = match self.material {
+            Material::Cutout => f32::from_bits(self.alpha_cutoff),
+            _ => 0.0
+        };
+        MaterialUniform {
+            color: uv::Vec4::new(r, g, b, f32::from_bits(self.opacity)),
+            params: uv::Vec4::new(f32::from_bits(self.roughness), normal_mapped as u32 as f32, parallax_depth, alpha_cutoff),
+            texture_params: uv::Vec4::new(textured as u32 as f32, f32::from_bits(self.wind), 0.0, 0.0),
+            water: self.water.map(|params| {
+                let [x, y, z, w] = params.map(f32::from_bits);
+                uv::Vec4::new(x, y, z, w)
+            })
+        }
+    }
+}
+
+// One of the textures owned by `MaterialSets` that objects sample through their own ids, see `TextureStreamer`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TextureSlot {
+    Color(usize),
+    NormalMap(usize),
+}
+
+struct MaterialEntry {
+    slot: u32,
+    last_used: u64,
+}
+
+// What a set's `material_texture` samples
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum TextureSource {
+    Material(Material),
+    Lightmap(usize),
+    // Kept apart by material, GPU culling batches draws by set and every batch is drawn with one pipeline
+    Color(Material, usize),
+}
+
+// Set 1 of the scene pipelines: the buffer with every material's parameters and the textures the draw samples.
+// Objects find their parameters through the material index in their object data, so only the textures decide the
+// set and all objects sampling the same ones are drawn together. Slots are written when a material first shows up
+// and never change afterwards, editing an object's color just moves it to another slot.
+pub struct MaterialSets {
+    pub set_layout: vk::DescriptorSetLayout,
+    buffer: StorageBuffer,
+    entries: HashMap<MaterialKey, MaterialEntry>,
+    free_slots: Vec<u32>,
+    next_slot: u32,
+    // By texture and normal map
+    sets: HashMap<(TextureSource, Option<usize>), vk::DescriptorSet>,
+    textures: HashMap<Material, vk::DescriptorImageInfo>,
+    // Owned here, sets of lightmapped objects sample their own lightmap instead of the material's texture
+    lightmaps: HashMap<usize, Texture>,
+    next_lightmap: usize,
+    // Owned here like the lightmaps, see `GameObject::texture` and `GameObject::normal_map`
+    color_textures: HashMap<usize, Texture>,
+    next_color_texture: usize,
+    normal_maps: HashMap<usize, Texture>,
+    next_normal_map: usize,
+    // Bound for materials without a texture of their own
+    white: Texture,
+    // Bound for materials without a normal map, points straight out of the surface
+    flat_normal: Texture,
+}
+
+impl MaterialSets {
+    pub fn new(device: &ash::Device, allocator: &mut Allocator, pools: &Pools, queue: vk::Queue) -> Result<Self, vk::Result> {
+        // Water moves its vertices by its material's waves
+        let set_layout = Descriptors::create_layout(device, &[
+            (vk::DescriptorType::STORAGE_BUFFER, vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT),
+            (vk::DescriptorType::COMBINED_IMAGE_SAMPLER, vk::ShaderStageFlags::FRAGMENT),
+            (vk::DescriptorType::COMBINED_IMAGE_SAMPLER, vk::ShaderStageFlags::FRAGMENT)
+        ])?;
+        let white = Texture::from_rgba8(device, allocator, pools, queue, vk::Extent2D { width: 1, height: 1 }, &[255; 4], "Material White")?;
+        let flat_normal = Texture::from_rgba8(device, allocator, pools, queue, vk::Extent2D { width: 1, height: 1 }, &[128, 128, 255, 255],
+            "Material Flat Normal")?;
+        let size = (MAX_MATERIALS * std::mem::size_of::<MaterialUniform>()) as u64;
+        let buffer = StorageBuffer::new(device, allocator, size, MemoryLocation::CpuToGpu, "Material Buffer");
+
+        Ok(Self {
+            set_layout,
+            buffer,
+            entries: HashMap::new(),
+            free_slots: Vec::new(),
+            next_slot: 0,
+            sets: HashMap::new(),
+            textures: HashMap::new(),
+            lightmaps: HashMap::new(),
+            next_lightmap: 0,
+            color_textures: HashMap::new(),
+            next_color_texture: 0,
+            normal_maps: HashMap::new(),
+            next_normal_map: 0,
+            white,
+            flat_normal
+        })
+    }
+
+    // Writes the materials new this frame and frees the slots no game object has used for `frames_in_flight` frames,
+    // by then no submitted frame reads them anymore. Scatter prototypes are passed along with the scene's objects.
+    pub fn update<'a>(&mut self, device: &ash::Device, descriptors: &mut DescriptorAllocator, game_objects: impl IntoIterator<Item = &'a GameObject>,
+        frame_index: u64, frames_in_flight: u64
+    ) -> Result<(), vk::Result> {
+        for game_object in game_objects {
+            let key = MaterialKey::new(game_object);
+            if let Some(entry) = self.entries.get_mut(&key) {
+                entry.last_used = frame_index;
+            } else {
+                let slot = match self.free_slots.pop() {
+                    Some(slot) => slot,
+                    None if (self.next_slot as usize) < MAX_MATERIALS => {
+                        self.next_slot += 1;
+                        self.next_slot - 1
+                    },
+                    None => {
+                        tracing::warn!("Material buffer full, {:?} isn't drawn", game_object.material);
+                        continue;
+                    }
+                };
+                let uniform = key.uniform(self.normal_map(&key).is_some(), matches!(self.source(&key), TextureSource::Color(..)));
+                self.buffer.update_buffer(slot as u64 * std::mem::size_of::<MaterialUniform>() as u64, &[uniform]);
+                self.entries.insert(key, MaterialEntry { slot, last_used: frame_index });
+            }
+
+            let set_key = (self.source(&key), self.normal_map(&key));
+            if !self.sets.contains_key(&set_key) {
+                let set = descriptors.allocate(device, self.set_layout)?;
+                Descriptors::write_buffer(device, set, 0, vk::DescriptorType::STORAGE_BUFFER, self.buffer.descriptor_info());
+                Descriptors::write_image(device, set, 1, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, self.texture(set_key.0));
+                let normal_map = set_key.1.map_or_else(|| self.flat_normal.descriptor_info(), |id| self.normal_maps[&id].descriptor_info());
+                Descriptors::write_image(device, set, 2, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, normal_map);
+                self.sets.insert(set_key, set);
+            }
+        }
+
+        let stale: Vec<MaterialKey> = self.entries
+            .iter()
+            .filter(|(_, entry)| entry.last_used + frames_in_flight < frame_index)
+            .map(|(key, _)| *key)
+            .collect();
+        for key in stale {
+            if let Some(entry) = self.entries.remove(&key) {
+                self.free_slots.push(entry.slot);
+            }
+        }
+
+        Ok(())
+    }
+
+    // Set of the texture the game object samples, `None` until `update` has seen it
+    pub fn set(&self, game_object: &GameObject) -> Option<vk::DescriptorSet> {
+        let key = MaterialKey::new(game_object);
+        match self.entries.contains_key(&key) {
+            true => self.sets.get(&(self.source(&key), self.normal_map(&key))).copied(),
+            false => None
+        }
+    }
+
+    // Slot of the game object's parameters in the material buffer, what its object data points the shaders at
+    pub fn index(&self, game_object: &GameObject) -> u32 {
+        self.entries.get(&MaterialKey::new(game_object)).map_or(0, |entry| entry.slot)
+    }
+
+    fn source(&self, key: &MaterialKey) -> TextureSource {
+        match (key.material, key.lightmap, key.texture) {
+            (Material::Lightmapped, Some(id), _) if self.lightmaps.contains_key(&id) => TextureSource::Lightmap(id),
+            // Reflective, lightmapped and water materials sample their own texture in its place
+            (Material::Basic | Material::Cutout | Material::Transparent(_), _, Some(id)) if self.color_textures.contains_key(&id) => {
+                TextureSource::Color(key.material, id)
+            },
+            _ => TextureSource::Material(key.material)
+        }
+    }
+
+    fn normal_map(&self, key: &MaterialKey) -> Option<usize> {
+        key.normal_map.filter(|id| self.normal_maps.contains_key(id))
+    }
+
+    fn texture(&self, source: TextureSource) -> vk::DescriptorImageInfo {
+        match source {
+            TextureSource::Lightmap(id) => self.lightmaps[&id].descriptor_info(),
+            TextureSource::Color(_, id) => self.color_textures[&id].descriptor_info(),
+            TextureSource::Material(material) => self.textures.get(&material).copied().unwrap_or_else(|| self.white.descriptor_info())
+        }
+    }
+
+    // Takes ownership of a baked lightmap, the returned id goes into `GameObject::lightmap`
+    pub fn add_lightmap(&mut self, texture: Texture) -> usize {
+        let id = self.next_lightmap;
+        self.next_lightmap += 1;
+        self.lightmaps.insert(id, texture);
+        id
+    }
+
+    // Destroys the lightmap and the set sampling it, neither may be in use by the device
+    pub fn remove_lightmap(&mut self, device: &ash::Device, allocator: &mut Allocator, descriptors: &mut DescriptorAllocator,
+        id: usize
+    ) -> Result<(), vk::Result> {
+        self.free_sets(device, descriptors, |(source, _)| source == TextureSource::Lightmap(id))?;
+        if let Some(mut texture) = self.lightmaps.remove(&id) {
+            texture.destroy(device, allocator);
+        }
+
+        Ok(())
+    }
+
+    // Takes ownership of a color texture (RGBA8 holding sRGB color), the returned id goes into `GameObject::texture`
+    pub fn add_color_texture(&mut self, texture: Texture) -> usize {
+        let id = self.next_color_texture;
+        self.next_color_texture += 1;
+        self.color_textures.insert(id, texture);
+        id
+    }
+
+    // Destroys the color texture and the sets sampling it, neither may be in use by the device
+    pub fn remove_color_texture(&mut self, device: &ash::Device, allocator: &mut Allocator, descriptors: &mut DescriptorAllocator,
+        id: usize
+    ) -> Result<(), vk::Result> {
+        self.free_sets(device, descriptors, |(source, _)| matches!(source, TextureSource::Color(_, texture) if texture == id))?;
+        if let Some(mut texture) = self.color_textures.remove(&id) {
+            texture.destroy(device, allocator);
+        }
+
+        Ok(())
+    }
+
+    // Takes ownership of a tangent space normal map (RGBA8, not sRGB), the returned id goes into `GameObject::normal_map`
+    pub fn add_normal_map(&mut self, texture: Texture) -> usize {
+        let id = self.next_normal_map;
+        self.next_normal_map += 1;
+        self.normal_maps.insert(id, texture);
+        id
+    }
+
+    // Destroys the normal map and the sets sampling it, neither may be in use by the device
+    pub fn remove_normal_map(&mut self, device: &ash::Device, allocator: &mut Allocator, descriptors: &mut DescriptorAllocator,
+        id: usize
+    ) -> Result<(), vk::Result> {
+        self.free_sets(device, descriptors, |(_, normal_map)| normal_map == Some(id))?;
+        if let Some(mut texture) = self.normal_maps.remove(&id) {
+            texture.destroy(device, allocator);
+        }
+
+        Ok(())
+    }
+
+    pub fn texture_mut(&mut self, slot: TextureSlot) -> Option<&mut Texture> {
+        match slot {
+            TextureSlot::Color(id) => self.color_textures.get_mut(&id),
+            TextureSlot::NormalMap(id) => self.normal_maps.get_mut(&id)
+        }
+    }
+
+    // Swaps the texture in the slot for `texture` and returns the one it had, which like the sets still sampling it stays
+    // in use until the frames recorded with them have finished. See `detach_sets`.
+    pub fn replace_texture(&mut self, slot: TextureSlot, texture: Texture) -> Option<Texture> {
+        match slot {
+            TextureSlot::Color(id) => self.color_textures.insert(id, texture),
+            TextureSlot::NormalMap(id) => self.normal_maps.insert(id, texture)
+        }
+    }
+
+    // Forgets the sets sampling the slot without freeing them, `update` writes new ones for the texture's current view.
+    // The caller frees the returned sets once no frame in flight uses them anymore.
+    pub fn detach_sets(&mut self, slot: TextureSlot) -> Vec<vk::DescriptorSet> {
+        let keys: Vec<(TextureSource, Option<usize>)> = self.sets
+            .keys()
+            .copied()
+            .filter(|&(source, normal_map)| match slot {
+                TextureSlot::Color(id) => matches!(source, TextureSource::Color(_, texture) if texture == id),
+                TextureSlot::NormalMap(id) => normal_map == Some(id)
+            })
+            .collect();
+        keys.iter().filter_map(|key| self.sets.remove(key)).collect()
+    }
+
+    fn free_sets(&mut self, device: &ash::Device, descriptors: &mut DescriptorAllocator,
+        filter: impl Fn((TextureSource, Option<usize>)) -> bool
+    ) -> Result<(), vk::Result> {
+        let keys: Vec<(TextureSource, Option<usize>)> = self.sets.keys().copied().filter(|&key| filter(key)).collect();
+        for key in keys {
+            if let Some(set) = self.sets.remove(&key) {
+                descriptors.free(device, set)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Points the set of `material` at `info`, it may not be in use by the device
+    pub fn set_texture(&mut self, device: &ash::Device, material: Material, info: vk::DescriptorImageInfo) {
+        self.textures.insert(material, info);
+        for (_, &set) in self.sets.iter().filter(|((source, _), _)| *source == TextureSource::Material(material)) {
+            Descriptors::write_image(device, set, 1, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, info);
+        }
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device, allocator: &mut Allocator) {
+        // The sets go away with the allocator's pools
+        self.buffer.destroy(device, allocator);
+        for (_, mut texture) in self.lightmaps.drain().chain(self.color_textures.drain()).chain(self.normal_maps.drain()) {
+            texture.destroy(device, allocator);
+        }
+        self.white.destroy(device, allocator);
+        self.flat_normal.destroy(device, allocator);
+        unsafe { device.destroy_descriptor_set_layout(self.set_layout, None) };
+    }
+}
\ No newline at end of file
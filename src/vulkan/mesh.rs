@@ -1,13 +1,84 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
 use ash::vk;
 use gpu_allocator::vulkan::Allocator;
 
 use super::vertex_buffer::VertexBuffer;
 use super::index_buffer::IndexBuffer;
+use super::meshlet::{self, Meshlet};
 use super::vertex::Vertex;
+use super::camera::Camera;
+
+static GENERATION_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+// Largest error a coarser level may show on screen, in pixels
+pub const LOD_ERROR_PIXELS: f32 = 1.0;
+
+// Sphere around every vertex of a mesh, in the mesh's own space
+#[derive(Clone, Copy, Debug)]
+pub struct BoundingSphere {
+    pub center: uv::Vec3,
+    pub radius: f32,
+}
+
+impl BoundingSphere {
+    // Centered on the box around the points, which is close enough for culling
+    pub fn from_points(points: impl Iterator<Item = uv::Vec3> + Clone) -> Option<Self> {
+        let (min, max) = points.clone().fold(None, |bounds: Option<(uv::Vec3, uv::Vec3)>, point| match bounds {
+            Some((min, max)) => Some((min.min_by_component(point), max.max_by_component(point))),
+            None => Some((point, point))
+        })?;
+        let center = (min + max) * 0.5;
+        let radius = points.map(|point| (point - center).mag_sq()).fold(0.0, f32::max).sqrt();
+        Some(Self { center, radius })
+    }
+
+    // Sphere around this one after `model`, scaled by the largest axis
+    pub fn transformed(&self, model: uv::Mat4) -> Self {
+        let scale = (0..3).map(|axis| model.cols[axis].xyz().mag()).fold(0.0, f32::max);
+        Self {
+            center: model.transform_point3(self.center),
+            radius: self.radius * scale
+        }
+    }
+}
+
+// Coarser index list over the mesh's own vertices, see `simplify::lod_chain`
+#[derive(Clone, Debug)]
+pub struct MeshLod {
+    pub indices: Vec<u32>,
+    // Furthest any surface moved from the full mesh, in the mesh's own space
+    pub error: f32,
+}
+
+// What picking a level of detail needs to know about the view
+#[derive(Clone, Copy, Debug)]
+pub struct LodView {
+    pub position: uv::Vec3,
+    // Pixels covered by something one unit across at a distance of one unit
+    pub pixels_per_unit: f32,
+}
+
+impl LodView {
+    pub fn new(camera: &Camera, extent: vk::Extent2D) -> Self {
+        Self {
+            position: camera.position,
+            pixels_per_unit: extent.height as f32 / (2.0 * (camera.fov_y * 0.5).tan())
+        }
+    }
+}
 
 pub struct Mesh {
     pub vertex_buffers: Vec<VertexBuffer>,
-    pub index_buffer: Option<IndexBuffer>
+    pub index_buffer: Option<IndexBuffer>,
+    // `None` until vertices are uploaded, such meshes are never culled
+    pub bounds: Option<BoundingSphere>,
+    // Clusters of the index buffer GPU culling tests one by one, empty for meshes culled as a whole
+    pub meshlets: Vec<Meshlet>,
+    // Finest first, not counting the full mesh. Only the GPU culled passes switch to them.
+    pub lods: Vec<MeshLod>,
+    // Changes with every upload, and differs between meshes, so copies of the geometry know when they're stale
+    generation: u64,
 }
 
 impl Mesh {
@@ -19,31 +90,150 @@ impl Mesh {
             let index_buffer = IndexBuffer::new(device, allocator, IndexBuffer::get_index_buffer_size(index_count));
             Ok(Self {
                 vertex_buffers,
-                index_buffer: Some(index_buffer)
+                index_buffer: Some(index_buffer),
+                bounds: None,
+                meshlets: vec![],
+                lods: vec![],
+                generation: GENERATION_COUNTER.fetch_add(1, Ordering::Relaxed)
             })
         } else {
             Ok(Self {
                 vertex_buffers,
                 index_buffer: None,
+                bounds: None,
+                meshlets: vec![],
+                lods: vec![],
+                generation: GENERATION_COUNTER.fetch_add(1, Ordering::Relaxed)
             })
         }
     }
 
+    // Unit cube centered on the origin, every face with its own vertices so the normals stay flat
+    pub fn cube(device: &ash::Device, allocator: &mut Allocator, color: uv::Vec3) -> Result<Self, vk::Result> {
+        // Normal followed by two edge directions whose cross product is the normal, keeping the winding of every face the same
+        let faces = [
+            (uv::Vec3::unit_x(), uv::Vec3::unit_y(), uv::Vec3::unit_z()),
+            (-uv::Vec3::unit_x(), uv::Vec3::unit_z(), uv::Vec3::unit_y()),
+            (uv::Vec3::unit_y(), uv::Vec3::unit_z(), uv::Vec3::unit_x()),
+            (-uv::Vec3::unit_y(), uv::Vec3::unit_x(), uv::Vec3::unit_z()),
+            (uv::Vec3::unit_z(), uv::Vec3::unit_x(), uv::Vec3::unit_y()),
+            (-uv::Vec3::unit_z(), uv::Vec3::unit_y(), uv::Vec3::unit_x()),
+        ];
+
+        let mut vertices = Vec::with_capacity(24);
+        let mut indices = Vec::with_capacity(36);
+        for (normal, u, v) in faces {
+            let first = vertices.len() as u32;
+            for (su, sv) in [(-0.5, -0.5), (0.5, -0.5), (0.5, 0.5), (-0.5, 0.5)] {
+                // cross(normal, u) is v on every face, so u is the tangent with a positive handedness
+                vertices.push(Vertex {
+                    pos: normal * 0.5 + u * su + v * sv,
+                    color,
+                    normal,
+                    lightmap_uv: uv::Vec2::zero(),
+                    tex_coord: uv::Vec2::new(su + 0.5, sv + 0.5),
+                    tangent: uv::Vec4::new(u.x, u.y, u.z, 1.0)
+                });
+            }
+            indices.extend([first, first + 1, first + 2, first + 2, first + 3, first]);
+        }
+
+        let mut mesh = Self::new(device, allocator, vertices.len(), indices.len())?;
+        mesh.update_vertex_buffer(&vertices);
+        mesh.update_index_buffer(&indices);
+        Ok(mesh)
+    }
+
     pub fn update_vertex_buffer(&mut self, data: &[Vertex]) {
         self.vertex_buffers[0].update_buffer(data);
+        self.bounds = BoundingSphere::from_points(data.iter().map(|vertex| vertex.pos));
+        self.meshlets.clear();
+        self.lods.clear();
+        self.generation = GENERATION_COUNTER.fetch_add(1, Ordering::Relaxed);
     }
 
     pub fn update_index_buffer(&mut self, data: &[u32]) {
+        self.meshlets.clear();
+        self.lods.clear();
+        self.generation = GENERATION_COUNTER.fetch_add(1, Ordering::Relaxed);
         match self.index_buffer {
             Some(ref mut index_buffer) => {
                 index_buffer.update_buffer(data);
             },
             None => {
-                println!("No index buffer on mesh");
+                tracing::warn!("No index buffer on mesh");
+            }
+        }
+    }
+
+    // Splits the uploaded triangles into meshlets, done at import time since it walks every triangle. Uploading new
+    // vertices or indices drops them again.
+    pub fn build_meshlets(&mut self, vertices: &[Vertex], indices: &[u32]) {
+        self.meshlets = meshlet::build_meshlets(vertices, indices);
+        self.generation = GENERATION_COUNTER.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // Like `build_meshlets`, dropped again by uploading new vertices or indices
+    pub fn set_lods(&mut self, lods: Vec<MeshLod>) {
+        self.lods = lods;
+        self.generation = GENERATION_COUNTER.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // Coarsest level whose error stays under `LOD_ERROR_PIXELS` when drawn with `model`, 0 is the full mesh and
+    // level `n` is `lods[n - 1]`
+    pub fn select_lod(&self, model: uv::Mat4, view: &LodView) -> usize {
+        let bounds = match self.bounds {
+            Some(bounds) => bounds.transformed(model),
+            None => return 0
+        };
+        let scale = (0..3).map(|axis| model.cols[axis].xyz().mag()).fold(0.0, f32::max);
+        // Inside the bounds nothing is far enough to simplify
+        let distance = (bounds.center - view.position).mag() - bounds.radius;
+        if distance <= 0.0 {
+            return 0;
+        }
+
+        self.lods
+            .iter()
+            .take_while(|lod| lod.error * scale * view.pixels_per_unit / distance <= LOD_ERROR_PIXELS)
+            .count()
+    }
+
+    // Binds and draws every vertex buffer, push constants and descriptor sets are left to the caller.
+    // Scene pipelines read the object's index from `first_instance`, other pipelines pass 0.
+    pub fn record_draw(&self, device: &ash::Device, command_buffer: vk::CommandBuffer, first_instance: u32) {
+        unsafe {
+            match &self.index_buffer {
+                Some(index_buffer) => {
+                    device.cmd_bind_index_buffer(command_buffer, index_buffer.get_buffer(), 0, vk::IndexType::UINT32);
+                    for vertex_buffer in &self.vertex_buffers {
+                        device.cmd_bind_vertex_buffers(command_buffer, 0, &[vertex_buffer.get_buffer()], &[0]);
+                        device.cmd_draw_indexed(command_buffer, index_buffer.get_index_count(), 1, 0, 0, first_instance);
+                    }
+                },
+                None => {
+                    for vertex_buffer in &self.vertex_buffers {
+                        device.cmd_bind_vertex_buffers(command_buffer, 0, &[vertex_buffer.get_buffer()], &[0]);
+                        device.cmd_draw(command_buffer, vertex_buffer.get_vertex_count(), 1, 0, first_instance);
+                    }
+                }
             }
         }
     }
 
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    // Triangles submitted by one `record_draw`
+    pub fn triangle_count(&self) -> u32 {
+        let per_buffer = |vertex_buffer: &VertexBuffer| match &self.index_buffer {
+            Some(index_buffer) => index_buffer.get_index_count() / 3,
+            None => vertex_buffer.get_vertex_count() / 3
+        };
+        self.vertex_buffers.iter().map(per_buffer).sum()
+    }
+
     pub fn destroy(&mut self, device: &ash::Device, allocator: &mut Allocator) {
         for vertex_buffer in &mut self.vertex_buffers {
             vertex_buffer.destroy(device, allocator);
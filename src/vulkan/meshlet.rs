@@ -0,0 +1,86 @@
+use std::collections::HashSet;
+
+use super::mesh::BoundingSphere;
+use super::vertex::Vertex;
+
+// Limits of a single cluster, small enough that culling them one by one pays off on dense meshes
+pub const MAX_MESHLET_VERTICES: usize = 64;
+pub const MAX_MESHLET_TRIANGLES: usize = 124;
+// Meshes with fewer triangles are culled as a whole, their clusters would barely save anything
+pub const MESHLET_MIN_TRIANGLES: usize = 1024;
+
+// A run of triangles in its mesh's index buffer, with the bounds the culling pass tests it by. All in the mesh's own space.
+#[derive(Clone, Copy, Debug)]
+pub struct Meshlet {
+    pub first_index: u32,
+    pub index_count: u32,
+    pub bounds: BoundingSphere,
+    // Average normal of the triangles and the cosine of the cone around it that holds all of them, a cutoff of 1
+    // means they face too many ways to ever be back facing together
+    pub cone_axis: uv::Vec3,
+    pub cone_cutoff: f32,
+}
+
+// Splits the triangles into clusters of consecutive ones, starting a new cluster whenever the next triangle would take
+// it over either limit. Importers keep neighbouring faces close together in the index buffer, so consecutive runs are
+// compact enough and the index buffer needs no reordering. Empty for meshes too small to bother.
+pub fn build_meshlets(vertices: &[Vertex], indices: &[u32]) -> Vec<Meshlet> {
+    if indices.len() / 3 < MESHLET_MIN_TRIANGLES {
+        return vec![];
+    }
+
+    let mut meshlets = vec![];
+    let mut first_triangle = 0;
+    let mut unique: HashSet<u32> = HashSet::with_capacity(MAX_MESHLET_VERTICES);
+    for (triangle_index, triangle) in indices.chunks_exact(3).enumerate() {
+        let new_vertices = triangle.iter().filter(|index| !unique.contains(index)).count();
+        if unique.len() + new_vertices > MAX_MESHLET_VERTICES || triangle_index - first_triangle == MAX_MESHLET_TRIANGLES {
+            meshlets.push(meshlet(vertices, indices, first_triangle, triangle_index));
+            first_triangle = triangle_index;
+            unique.clear();
+        }
+        unique.extend(triangle);
+    }
+    if first_triangle < indices.len() / 3 {
+        meshlets.push(meshlet(vertices, indices, first_triangle, indices.len() / 3));
+    }
+
+    meshlets
+}
+
+fn meshlet(vertices: &[Vertex], indices: &[u32], first_triangle: usize, end_triangle: usize) -> Meshlet {
+    let cluster = &indices[first_triangle * 3..end_triangle * 3];
+    let positions = cluster.iter().map(|&index| vertices[index as usize].pos);
+    let bounds = BoundingSphere::from_points(positions).unwrap_or(BoundingSphere { center: uv::Vec3::zero(), radius: 0.0 });
+
+    let normals: Vec<uv::Vec3> = cluster
+        .chunks_exact(3)
+        .map(|triangle| {
+            let [a, b, c] = [triangle[0], triangle[1], triangle[2]].map(|index| vertices[index as usize].pos);
+            (b - a).cross(c - a)
+        })
+        .filter(|normal| normal.mag_sq() > 0.0)
+        .map(|normal| normal.normalized())
+        .collect();
+    let sum = normals.iter().fold(uv::Vec3::zero(), |sum, normal| sum + *normal);
+    let (cone_axis, cone_cutoff) = match sum.mag_sq() > 0.0 {
+        true => {
+            let axis = sum.normalized();
+            let min_dot = normals.iter().map(|normal| normal.dot(axis)).fold(1.0, f32::min);
+            // Normals spread over close to a hemisphere leave no view from which all of them face away
+            match min_dot <= 0.1 {
+                true => (axis, 1.0),
+                false => (axis, (1.0 - min_dot * min_dot).sqrt())
+            }
+        },
+        false => (uv::Vec3::unit_z(), 1.0)
+    };
+
+    Meshlet {
+        first_index: (first_triangle * 3) as u32,
+        index_count: cluster.len() as u32,
+        bounds,
+        cone_axis,
+        cone_cutoff
+    }
+}
\ No newline at end of file
@@ -13,4 +13,63 @@ pub mod vertex_buffer;
 pub mod index_buffer;
 pub mod mesh;
 pub mod surface;
-pub mod game_object;
\ No newline at end of file
+pub mod game_object;
+pub mod material;
+pub mod camera;
+pub mod uniform_buffer;
+pub mod descriptors;
+pub mod image;
+pub mod render_target;
+pub mod reflection;
+pub mod post;
+pub mod storage_buffer;
+pub mod compute_pipeline;
+pub mod lights;
+pub mod clustered_lighting;
+pub mod staging_buffer;
+pub mod texture;
+pub mod shadows;
+pub mod object_buffer;
+pub mod render_hooks;
+pub mod outline;
+pub mod gizmo;
+pub mod camera_controller;
+pub mod ui;
+pub mod viewport;
+pub mod split_screen;
+pub mod shader_reflection;
+pub mod descriptor_allocator;
+pub mod cubemap;
+pub mod reflection_probes;
+pub mod light_probes;
+pub mod lightmap;
+pub mod gpu_culling;
+pub mod meshlet;
+pub mod shading_rate;
+pub mod upload_ring;
+pub mod parallax;
+pub mod transparency;
+pub mod cutout;
+pub mod water;
+pub mod scatter;
+pub mod wind;
+pub mod atmosphere;
+pub mod day_night;
+pub mod clouds;
+pub mod screenshot;
+pub mod crash_diagnostics;
+pub mod portability;
+pub mod hal;
+pub mod multiview;
+pub mod full_screen_exclusive;
+pub mod adapter_info;
+pub mod thumbnail;
+pub mod texture_streaming;
+pub mod virtual_texture;
+pub mod occlusion;
+pub mod conditional_rendering;
+#[cfg(feature = "tracy")]
+pub mod gpu_profiler;
+pub mod sdf_font;
+pub mod sdf_text;
+pub mod rich_text;
\ No newline at end of file
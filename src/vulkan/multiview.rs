@@ -0,0 +1,39 @@
+use ash::vk;
+
+// Views a single multiview pass draws at most: the six faces of a point light's shadow cube
+pub const CUBE_VIEW_MASK: u32 = 0b111111;
+
+// What VK_KHR_multiview (core since Vulkan 1.1) offers on a device. A multiview render pass runs every draw once per
+// view, each into its own framebuffer layer, so a cube or a pair of eyes costs one pass instead of one per view.
+#[derive(Clone, Copy, Debug)]
+pub struct MultiviewSupport {
+    pub max_view_count: u32,
+}
+
+impl MultiviewSupport {
+    // `None` on devices below Vulkan 1.1 or without the feature
+    pub fn query(instance: &ash::Instance, physical_device: vk::PhysicalDevice) -> Option<Self> {
+        let props = unsafe { instance.get_physical_device_properties(physical_device) };
+        if props.api_version < vk::API_VERSION_1_1 {
+            return None;
+        }
+
+        let mut vulkan11_features = vk::PhysicalDeviceVulkan11Features::default();
+        let mut features = vk::PhysicalDeviceFeatures2::builder().push_next(&mut vulkan11_features);
+        unsafe { instance.get_physical_device_features2(physical_device, &mut features) };
+        if vulkan11_features.multiview != vk::TRUE {
+            return None;
+        }
+
+        let mut multiview_properties = vk::PhysicalDeviceMultiviewProperties::default();
+        let mut properties = vk::PhysicalDeviceProperties2::builder().push_next(&mut multiview_properties);
+        unsafe { instance.get_physical_device_properties2(physical_device, &mut properties) };
+
+        Some(Self { max_view_count: multiview_properties.max_multiview_view_count })
+    }
+
+    // Whether `view_count` views fit into one pass
+    pub fn supports(&self, view_count: u32) -> bool {
+        view_count <= self.max_view_count
+    }
+}
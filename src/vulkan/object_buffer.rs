@@ -0,0 +1,111 @@
+use std::collections::HashMap;
+
+use ash::vk;
+
+use super::descriptors::Descriptors;
+use super::game_object::{GameObject, world_matrices};
+use super::material::MaterialSets;
+use super::upload_ring::{RingSlice, UploadRing};
+
+pub const MAX_OBJECTS: usize = 4096;
+
+// Mirrors the std430 `ObjectData` struct in basic.vert
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct ObjectData {
+    pub model: uv::Mat4,
+    pub previous_model: uv::Mat4,
+    // Slot of the object's parameters in the material buffer
+    pub material: u32,
+    pub _padding: [u32; 3],
+}
+
+// Per object data, indexed by the object's position in the renderer's object list which scene draws pass as their
+// first instance. Bound at set 2 by every scene pipeline. Streamed through the upload ring, the set is bound with the
+// dynamic offset of the frame's slice, see `dynamic_offset`. Binding 1 holds the merged scene vertices for pipelines
+// pulling their vertices, see `PipelineConfig::with_vertex_pulling`.
+pub struct ObjectBuffers {
+    pub set_layout: vk::DescriptorSetLayout,
+    pub set: vk::DescriptorSet,
+    slice: Option<RingSlice>,
+    // Transforms of the last update, keyed by object id so reordering the list doesn't smear
+    previous_models: HashMap<usize, uv::Mat4>,
+}
+
+impl ObjectBuffers {
+    pub fn new(device: &ash::Device, descriptor_pool: vk::DescriptorPool, upload_ring: &UploadRing) -> Result<Self, vk::Result> {
+        let set_layout = Descriptors::create_layout(device, &[
+            (vk::DescriptorType::STORAGE_BUFFER_DYNAMIC, vk::ShaderStageFlags::VERTEX),
+            (vk::DescriptorType::STORAGE_BUFFER, vk::ShaderStageFlags::VERTEX),
+        ])?;
+
+        let set = Descriptors::allocate(device, descriptor_pool, set_layout, 1)?[0];
+        Descriptors::write_buffer(device, set, 0, vk::DescriptorType::STORAGE_BUFFER_DYNAMIC, upload_ring.descriptor_info(Self::binding_range()));
+        // Placeholder until the merged geometry exists, nothing pulls vertices before then
+        Descriptors::write_buffer(device, set, 1, vk::DescriptorType::STORAGE_BUFFER, upload_ring.descriptor_info(vk::WHOLE_SIZE));
+
+        Ok(Self {
+            set_layout,
+            set,
+            slice: None,
+            previous_models: HashMap::new()
+        })
+    }
+
+    // Bytes the set reads from its dynamic offset
+    pub fn binding_range() -> u64 {
+        (MAX_OBJECTS * std::mem::size_of::<ObjectData>()) as u64
+    }
+
+    // Only while no command buffer using the set is pending
+    pub fn write_vertices(&self, device: &ash::Device, vertices: vk::DescriptorBufferInfo) {
+        Descriptors::write_buffer(device, self.set, 1, vk::DescriptorType::STORAGE_BUFFER, vertices);
+    }
+
+    // Makes room for the frame's objects while the frame's upload layout is planned, before any command buffer is recorded
+    pub fn reserve(&mut self, upload_ring: &mut UploadRing, object_count: usize) {
+        self.slice = upload_ring.reserve((object_count.min(MAX_OBJECTS) * std::mem::size_of::<ObjectData>()) as u64);
+        if self.slice.is_none() {
+            tracing::error!("No room left in the upload ring for the per object data");
+        }
+    }
+
+    // Where the objects of swapchain image `index` start in the upload ring
+    pub fn dynamic_offset(&self, upload_ring: &UploadRing, index: usize) -> u32 {
+        self.slice.map_or(0, |slice| upload_ring.offset(index, slice) as u32)
+    }
+
+    // Called once per frame, objects without a previous transform (new this frame) don't move
+    pub fn update(&mut self, index: usize, game_objects: &[GameObject], materials: &MaterialSets, upload_ring: &mut UploadRing) {
+        if game_objects.len() > MAX_OBJECTS {
+            tracing::warn!("{} objects in the scene, only the first {} get per object data", game_objects.len(), MAX_OBJECTS);
+        }
+
+        let models: Vec<(usize, uv::Mat4)> = game_objects
+            .iter()
+            .zip(world_matrices(game_objects))
+            .map(|(game_object, model)| (game_object.get_id(), model))
+            .collect();
+
+        let data: Vec<ObjectData> = models
+            .iter()
+            .zip(game_objects)
+            .take(MAX_OBJECTS)
+            .map(|((id, model), game_object)| ObjectData {
+                model: *model,
+                previous_model: *self.previous_models.get(id).unwrap_or(model),
+                material: materials.index(game_object),
+                _padding: [0; 3]
+            })
+            .collect();
+        if let Some(slice) = self.slice {
+            upload_ring.write(index, slice, 0, &data);
+        }
+
+        self.previous_models = models.into_iter().collect();
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device) {
+        unsafe { device.destroy_descriptor_set_layout(self.set_layout, None) };
+    }
+}
\ No newline at end of file
@@ -0,0 +1,250 @@
+use ash::vk;
+
+use super::game_object::GameObject;
+use super::pipeline::{Pipeline, PipelineConfig};
+use super::swapchain::VulkanSwapchain;
+
+use crate::utils::any_as_u8_slice;
+
+pub const OCCLUSION_VERT: &[u32] = vk_shader_macros::include_glsl!("./shaders/occlusion.vert", kind: vert);
+pub const OCCLUSION_BOUNDS_VERT: &[u32] = vk_shader_macros::include_glsl!("./shaders/occlusion.vert", kind: vert, define: BOUNDS);
+pub const OCCLUSION_FRAG: &[u32] = vk_shader_macros::include_glsl!("./shaders/occlusion.frag", kind: frag);
+
+pub const MAX_OCCLUSION_QUERIES: u32 = 256;
+
+// What a query draws to count its visible samples
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OcclusionTarget {
+    // The mesh of the game object with this id, where it currently is
+    Object(usize),
+    // A box in world space, e.g. around a light for its flare or around a sound source
+    Bounds { center: uv::Vec3, half_extent: uv::Vec3 },
+    // Drawn by the caller between `OcclusionQueries::begin` and `end`, e.g. from an `AfterOpaque` hook
+    Custom,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct OcclusionQuery(usize);
+
+impl OcclusionQuery {
+    // Index of the query in the pools and of its predicate, see `ConditionalRendering`
+    pub fn slot(&self) -> usize {
+        self.0
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OcclusionResult {
+    // Samples of the target that passed the depth test. Without precise queries only zero and not zero mean anything.
+    pub samples: u64,
+    // The frame they were counted in
+    pub frame_index: u64,
+}
+
+impl OcclusionResult {
+    pub fn visible(&self) -> bool {
+        self.samples > 0
+    }
+}
+
+// Hardware occlusion queries against the depth of the opaque scene. Every swapchain image has its own query pool, its
+// results are read once the image's frame is done, so they trail the screen by the frames in flight. Only the main
+// camera (the first split view) is tested.
+pub struct OcclusionQueries {
+    // Without occlusionQueryPrecise the sample counts only tell visible from hidden
+    pub precise: bool,
+    pools: Vec<vk::QueryPool>,
+    targets: Vec<Option<OcclusionTarget>>,
+    results: Vec<Option<OcclusionResult>>,
+    // Frame index and queried slots of every image's last submission
+    pending: Vec<(u64, Vec<usize>)>,
+    mesh_pipeline: Pipeline,
+    bounds_pipeline: Pipeline,
+}
+
+impl OcclusionQueries {
+    pub fn new(device: &ash::Device, swapchain: &VulkanSwapchain, scene_renderpass: &vk::RenderPass, camera_set_layout: vk::DescriptorSetLayout,
+        precise: bool
+    ) -> Result<Self, vk::Result> {
+        let pool_info = vk::QueryPoolCreateInfo::builder()
+            .query_type(vk::QueryType::OCCLUSION)
+            .query_count(MAX_OCCLUSION_QUERIES);
+        let pools = (0..swapchain.image_count)
+            .map(|_| unsafe { device.create_query_pool(&pool_info, None) })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let set_layouts = [camera_set_layout];
+        // Tests against the depth the opaque passes left, the object's own surface included, so it is pulled a little towards
+        // the camera to not fight with itself
+        let mesh_config = PipelineConfig {
+            vertex_shader: OCCLUSION_VERT,
+            fragment_shader: OCCLUSION_FRAG,
+            push_constant_size: std::mem::size_of::<uv::Mat4>() as u32,
+            depth_write: false,
+            depth_compare: vk::CompareOp::LESS_OR_EQUAL,
+            depth_bias: Some((-1.0, -1.0)),
+            color_write: false,
+            ..PipelineConfig::basic(&set_layouts)
+        };
+        let mesh_pipeline = Pipeline::new(device, swapchain, scene_renderpass, &mesh_config)?;
+
+        // Both sides, so a box the camera is in still counts
+        let bounds_config = PipelineConfig {
+            vertex_shader: OCCLUSION_BOUNDS_VERT,
+            vertex_input: false,
+            cull_mode: vk::CullModeFlags::NONE,
+            depth_bias: None,
+            ..mesh_config
+        };
+        let bounds_pipeline = Pipeline::new(device, swapchain, scene_renderpass, &bounds_config)?;
+
+        Ok(Self {
+            precise,
+            pools,
+            targets: vec![],
+            results: vec![],
+            pending: vec![(0, vec![]); swapchain.image_count],
+            mesh_pipeline,
+            bounds_pipeline
+        })
+    }
+
+    // `None` once all MAX_OCCLUSION_QUERIES are taken
+    pub fn add(&mut self, target: OcclusionTarget) -> Option<OcclusionQuery> {
+        let slot = match self.targets.iter().position(Option::is_none) {
+            Some(slot) => slot,
+            None if self.targets.len() < MAX_OCCLUSION_QUERIES as usize => {
+                self.targets.push(None);
+                self.results.push(None);
+                self.targets.len() - 1
+            }
+            None => return None
+        };
+        self.targets[slot] = Some(target);
+
+        Some(OcclusionQuery(slot))
+    }
+
+    // Keeps the results so far, e.g. for bounds following a moving sound source
+    pub fn set_target(&mut self, query: OcclusionQuery, target: OcclusionTarget) {
+        self.targets[query.0] = Some(target);
+    }
+
+    pub fn remove(&mut self, query: OcclusionQuery) {
+        self.targets[query.0] = None;
+        self.results[query.0] = None;
+        // Results still in flight would otherwise go to the next query in the slot
+        for (_, slots) in &mut self.pending {
+            slots.retain(|&slot| slot != query.0);
+        }
+    }
+
+    // The latest result, `None` until the first frame testing the query is done
+    pub fn result(&self, query: OcclusionQuery) -> Option<OcclusionResult> {
+        self.results[query.0]
+    }
+
+    // Called once the frame that last used image `index` is done, before its command buffer is submitted again
+    pub fn collect(&mut self, device: &ash::Device, index: usize, frame_index: u64) -> Result<(), vk::Result> {
+        let slots = self.targets.iter().enumerate().filter(|(_, target)| target.is_some()).map(|(slot, _)| slot).collect();
+        let (queried_frame, queried) = std::mem::replace(&mut self.pending[index], (frame_index, slots));
+        if queried.is_empty() {
+            return Ok(());
+        }
+
+        // The sample count followed by whether it was written at all, queries that weren't begun are left unavailable
+        let mut data = vec![[0u64; 2]; MAX_OCCLUSION_QUERIES as usize];
+        let flags = vk::QueryResultFlags::TYPE_64 | vk::QueryResultFlags::WITH_AVAILABILITY;
+        match unsafe { device.get_query_pool_results(self.pools[index], 0, MAX_OCCLUSION_QUERIES, &mut data, flags) } {
+            Ok(()) | Err(vk::Result::NOT_READY) => {}
+            Err(error) => return Err(error)
+        }
+
+        for slot in queried {
+            let [samples, available] = data[slot];
+            if available != 0 {
+                self.results[slot] = Some(OcclusionResult { samples, frame_index: queried_frame });
+            }
+        }
+        Ok(())
+    }
+
+    pub fn pool(&self, index: usize) -> vk::QueryPool {
+        self.pools[index]
+    }
+
+    // The slots `record` begins a query for, in order
+    pub fn recorded_slots(&self, game_objects: &[GameObject]) -> Vec<usize> {
+        self.targets
+            .iter()
+            .enumerate()
+            .filter(|(_, target)| match target {
+                Some(OcclusionTarget::Object(id)) => game_objects.iter().any(|game_object| game_object.get_id() == *id),
+                Some(OcclusionTarget::Bounds { .. }) => true,
+                _ => false
+            })
+            .map(|(slot, _)| slot)
+            .collect()
+    }
+
+    // Recorded outside of any render pass before the queries of image `index` are begun
+    pub fn record_reset(&self, device: &ash::Device, command_buffer: vk::CommandBuffer, index: usize) {
+        unsafe { device.cmd_reset_query_pool(command_buffer, self.pools[index], 0, MAX_OCCLUSION_QUERIES) };
+    }
+
+    // At most once per query and command buffer, inside the scene render pass
+    pub fn begin(&self, device: &ash::Device, command_buffer: vk::CommandBuffer, index: usize, query: OcclusionQuery) {
+        let flags = match self.precise {
+            true => vk::QueryControlFlags::PRECISE,
+            false => vk::QueryControlFlags::empty()
+        };
+        unsafe { device.cmd_begin_query(command_buffer, self.pools[index], query.0 as u32, flags) };
+    }
+
+    pub fn end(&self, device: &ash::Device, command_buffer: vk::CommandBuffer, index: usize, query: OcclusionQuery) {
+        unsafe { device.cmd_end_query(command_buffer, self.pools[index], query.0 as u32) };
+    }
+
+    // Recorded inside the scene render pass once the opaque surfaces are drawn, `Custom` targets are left to their owner
+    pub fn record(&self, device: &ash::Device, command_buffer: vk::CommandBuffer, index: usize, camera_set: vk::DescriptorSet,
+        game_objects: &[GameObject], models: &[uv::Mat4]
+    ) {
+        let mut bound = vk::Pipeline::null();
+        for (slot, target) in self.targets.iter().enumerate() {
+            let (pipeline, model, object) = match target {
+                Some(OcclusionTarget::Object(id)) => match game_objects.iter().position(|game_object| game_object.get_id() == *id) {
+                    Some(object) => (&self.mesh_pipeline, models[object], Some(&game_objects[object])),
+                    None => continue
+                },
+                Some(OcclusionTarget::Bounds { center, half_extent }) => (&self.bounds_pipeline,
+                    uv::Mat4::from_translation(*center) * uv::Mat4::from_nonuniform_scale(*half_extent), None),
+                _ => continue
+            };
+
+            unsafe {
+                if pipeline.pipeline != bound {
+                    device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, pipeline.pipeline);
+                    device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::GRAPHICS, pipeline.layout, 0, &[camera_set], &[]);
+                    bound = pipeline.pipeline;
+                }
+                device.cmd_push_constants(command_buffer, pipeline.layout, vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT, 0,
+                    any_as_u8_slice(&model));
+            }
+
+            self.begin(device, command_buffer, index, OcclusionQuery(slot));
+            match object {
+                Some(game_object) => game_object.mesh.record_draw(device, command_buffer, 0),
+                None => unsafe { device.cmd_draw(command_buffer, 36, 1, 0, 0) }
+            }
+            self.end(device, command_buffer, index, OcclusionQuery(slot));
+        }
+    }
+
+    pub fn destroy(&self, device: &ash::Device) {
+        self.mesh_pipeline.cleanup(device);
+        self.bounds_pipeline.cleanup(device);
+        for pool in &self.pools {
+            unsafe { device.destroy_query_pool(*pool, None) };
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,108 @@
+use ash::vk;
+
+use super::game_object::GameObject;
+use super::pipeline::{Pipeline, PipelineConfig};
+use super::swapchain::VulkanSwapchain;
+
+use crate::utils::any_as_u8_slice;
+
+pub const OUTLINE_VERT: &[u32] = vk_shader_macros::include_glsl!("./shaders/outline.vert", kind: vert);
+pub const OUTLINE_FRAG: &[u32] = vk_shader_macros::include_glsl!("./shaders/outline.frag", kind: frag);
+
+const SELECTED_STENCIL: u32 = 1;
+
+// Mirrors the push constant block of outline.vert and outline.frag
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct OutlinePushConstants {
+    model: uv::Mat4,
+    color: uv::Vec4,
+    width: f32,
+}
+
+// Selection outline drawn at the end of the scene pass: the selected object first marks its silhouette in the stencil buffer,
+// then it is redrawn enlarged in screen space and only the pixels outside of the mark are colored. Neither pass tests depth,
+// so the outline stays visible through other objects.
+pub struct Outline {
+    pub color: uv::Vec4,
+    // In pixels
+    pub width: f32,
+    mark_pipeline: Pipeline,
+    outline_pipeline: Pipeline,
+}
+
+impl Outline {
+    pub fn new(device: &ash::Device, swapchain: &VulkanSwapchain, scene_renderpass: &vk::RenderPass, camera_set_layout: vk::DescriptorSetLayout) -> Result<Self, vk::Result> {
+        let set_layouts = [camera_set_layout];
+        let base_config = PipelineConfig {
+            vertex_shader: OUTLINE_VERT,
+            fragment_shader: OUTLINE_FRAG,
+            push_constant_size: std::mem::size_of::<OutlinePushConstants>() as u32,
+            depth_test: false,
+            cull_mode: vk::CullModeFlags::NONE,
+            ..PipelineConfig::basic(&set_layouts)
+        };
+
+        let mark_config = PipelineConfig {
+            stencil: Some(vk::StencilOpState {
+                fail_op: vk::StencilOp::REPLACE,
+                pass_op: vk::StencilOp::REPLACE,
+                depth_fail_op: vk::StencilOp::REPLACE,
+                compare_op: vk::CompareOp::ALWAYS,
+                compare_mask: 0xff,
+                write_mask: 0xff,
+                reference: SELECTED_STENCIL
+            }),
+            color_write: false,
+            ..base_config
+        };
+        let mark_pipeline = Pipeline::new(device, swapchain, scene_renderpass, &mark_config)?;
+
+        let outline_config = PipelineConfig {
+            stencil: Some(vk::StencilOpState {
+                fail_op: vk::StencilOp::KEEP,
+                pass_op: vk::StencilOp::KEEP,
+                depth_fail_op: vk::StencilOp::KEEP,
+                compare_op: vk::CompareOp::NOT_EQUAL,
+                compare_mask: 0xff,
+                write_mask: 0,
+                reference: SELECTED_STENCIL
+            }),
+            ..base_config
+        };
+        let outline_pipeline = Pipeline::new(device, swapchain, scene_renderpass, &outline_config)?;
+
+        Ok(Self {
+            color: uv::Vec4::new(4.0, 1.6, 0.2, 1.0),
+            width: 3.0,
+            mark_pipeline,
+            outline_pipeline
+        })
+    }
+
+    // Recorded inside the scene render pass, after everything else was drawn
+    pub fn record(&self, device: &ash::Device, command_buffer: vk::CommandBuffer, camera_set: vk::DescriptorSet, game_object: &GameObject,
+        model: uv::Mat4
+    ) {
+        for (pipeline, width) in [(&self.mark_pipeline, 0.0), (&self.outline_pipeline, self.width)] {
+            let push = OutlinePushConstants {
+                model,
+                color: self.color,
+                width
+            };
+
+            unsafe {
+                device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, pipeline.pipeline);
+                device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::GRAPHICS, pipeline.layout, 0, &[camera_set], &[]);
+                device.cmd_push_constants(command_buffer, pipeline.layout, vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT, 0,
+                    any_as_u8_slice(&push));
+            }
+            game_object.mesh.record_draw(device, command_buffer, 0);
+        }
+    }
+
+    pub fn destroy(&self, device: &ash::Device) {
+        self.mark_pipeline.cleanup(device);
+        self.outline_pipeline.cleanup(device);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,48 @@
+use super::clustered_lighting::CLUSTER_SPECIALIZATION;
+use super::pipeline::SpecializationConstant;
+
+// Must match the constant ids in shaders/include/parallax.glsl, after the ones of `CLUSTER_SPECIALIZATION`
+const PARALLAX_MODE_ID: u32 = 5;
+const PARALLAX_MIN_STEPS_ID: u32 = 6;
+const PARALLAX_MAX_STEPS_ID: u32 = 7;
+
+// How the basic scene pipelines trace the height in the alpha of a normal map, for objects with a
+// `GameObject::parallax_depth`. Baked into the pipelines, so changing it rebuilds them. Reflections and probe
+// captures always use offset mapping.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParallaxQuality {
+    Off,
+    // Shifts the texture coordinates once by the height under them, cheap but flattens out at grazing angles
+    Offset,
+    // Steps through the height field until the view ray goes below it, from `min_steps` looking straight at the
+    // surface to `max_steps` at grazing angles
+    Occlusion { min_steps: u32, max_steps: u32 },
+}
+
+impl Default for ParallaxQuality {
+    fn default() -> Self {
+        ParallaxQuality::Occlusion { min_steps: 8, max_steps: 32 }
+    }
+}
+
+impl ParallaxQuality {
+    // Constants of the basic scene pipelines, the light clusters' and this quality's
+    pub fn scene_specialization(self) -> Vec<SpecializationConstant> {
+        let (mode, min_steps, max_steps) = match self {
+            ParallaxQuality::Off => (0, 0, 0),
+            ParallaxQuality::Offset => (1, 0, 0),
+            // At least one step, and never fewer at grazing angles than head on
+            ParallaxQuality::Occlusion { min_steps, max_steps } => (2, min_steps.max(1), max_steps.max(min_steps).max(1))
+        };
+
+        CLUSTER_SPECIALIZATION
+            .iter()
+            .copied()
+            .chain([
+                SpecializationConstant::uint(PARALLAX_MODE_ID, mode),
+                SpecializationConstant::uint(PARALLAX_MIN_STEPS_ID, min_steps),
+                SpecializationConstant::uint(PARALLAX_MAX_STEPS_ID, max_steps),
+            ])
+            .collect()
+    }
+}
\ No newline at end of file
@@ -3,7 +3,10 @@ use ash::vk;
 pub struct PhysicalDevice {}
 
 impl PhysicalDevice {
-    pub fn pick_physical_device(instance: &ash::Instance) -> Option<(vk::PhysicalDevice, vk::PhysicalDeviceProperties, vk::PhysicalDeviceFeatures)> {
+    // `preferred` is an index into the enumerated devices or a case insensitive part of a device name. It wins over the best
+    // rated device as long as it's supported at all.
+    pub fn pick_physical_device(instance: &ash::Instance, preferred: Option<&str>
+    ) -> Option<(vk::PhysicalDevice, vk::PhysicalDeviceProperties, vk::PhysicalDeviceFeatures)> {
         let physical_devices = unsafe { instance.enumerate_physical_devices().expect("Could not enumerate physical devices!") };
 
         let mut physical_device: vk::PhysicalDevice = vk::PhysicalDevice::null();
@@ -17,8 +20,40 @@ impl PhysicalDevice {
             }
         }
 
+        if let Some(preferred) = preferred {
+            let matches = |(index, pd): &(usize, &vk::PhysicalDevice)| {
+                let props = unsafe { instance.get_physical_device_properties(**pd) };
+                let name = unsafe { std::ffi::CStr::from_ptr(props.device_name.as_ptr()) }.to_string_lossy().to_lowercase();
+                preferred.parse() == Ok(*index) || name.contains(&preferred.to_lowercase())
+            };
+            match physical_devices.iter().enumerate().find(matches) {
+                Some((_, pd)) => match Self::rate_physical_device(instance, pd) {
+                    score if score > 0.0 => {
+                        current_score = score;
+                        physical_device = *pd;
+                    }
+                    _ => tracing::warn!("GPU \"{}\" is not supported, using the best rated one instead", preferred)
+                },
+                None => tracing::warn!("No GPU matches \"{}\", using the best rated one instead", preferred)
+            }
+        }
+
         if physical_device == vk::PhysicalDevice::null() { return None; }
-        
+
+        Some(Self::describe(instance, physical_device, current_score))
+    }
+
+    // For a device chosen elsewhere, e.g. the one an OpenXR runtime renders with. None when it isn't supported.
+    pub fn use_physical_device(instance: &ash::Instance, physical_device: vk::PhysicalDevice
+    ) -> Option<(vk::PhysicalDevice, vk::PhysicalDeviceProperties, vk::PhysicalDeviceFeatures)> {
+        match Self::rate_physical_device(instance, &physical_device) {
+            score if score > 0.0 => Some(Self::describe(instance, physical_device, score)),
+            _ => None
+        }
+    }
+
+    fn describe(instance: &ash::Instance, physical_device: vk::PhysicalDevice, score: f32
+    ) -> (vk::PhysicalDevice, vk::PhysicalDeviceProperties, vk::PhysicalDeviceFeatures) {
         let props = unsafe { instance.get_physical_device_properties(physical_device) };
         let features = unsafe { instance.get_physical_device_features(physical_device) };
         let device_name = String::from(
@@ -36,12 +71,46 @@ impl PhysicalDevice {
         let api_patch = vk::api_version_patch(props.api_version);
         let api_variant = vk::api_version_variant(props.api_version);
 
-        println!("[Reverie][info] Using {:?} device {} (driver v{}.{}.{} with score {})", 
-            props.device_type, device_name, driver_major, driver_minor, driver_patch, current_score);
-        println!("[Reverie][info] Device supports Vulkan v{}.{}.{} (variant {}).",
+        tracing::info!("Using {:?} device {} (driver v{}.{}.{} with score {})", 
+            props.device_type, device_name, driver_major, driver_minor, driver_patch, score);
+        tracing::info!("Device supports Vulkan v{}.{}.{} (variant {})",
             api_major, api_minor, api_patch, api_variant);
         
-        Some((physical_device, props, features))
+        (physical_device, props, features)
+    }
+
+    // Multi draw indirect with first instance, what GPU driven drawing needs. Optional, the renderer draws every
+    // object itself without it.
+    pub fn supports_multi_draw_indirect(instance: &ash::Instance, physical_device: vk::PhysicalDevice) -> bool {
+        let features = unsafe { instance.get_physical_device_features(physical_device) };
+        features.multi_draw_indirect == vk::TRUE && features.draw_indirect_first_instance == vk::TRUE
+    }
+
+    // Storage buffer writes from fragment shaders, what the virtual texture feedback pass needs
+    pub fn supports_fragment_stores(instance: &ash::Instance, physical_device: vk::PhysicalDevice) -> bool {
+        let features = unsafe { instance.get_physical_device_features(physical_device) };
+        features.fragment_stores_and_atomics == vk::TRUE
+    }
+
+    // Occlusion queries counting the exact number of visible samples rather than only whether there are any
+    pub fn supports_precise_occlusion(instance: &ash::Instance, physical_device: vk::PhysicalDevice) -> bool {
+        let features = unsafe { instance.get_physical_device_features(physical_device) };
+        features.occlusion_query_precise == vk::TRUE
+    }
+
+    // BC1 to BC7, see `assets::compress`
+    pub fn supports_texture_compression(instance: &ash::Instance, physical_device: vk::PhysicalDevice) -> bool {
+        let features = unsafe { instance.get_physical_device_features(physical_device) };
+        features.texture_compression_bc == vk::TRUE
+    }
+
+    // A GPU written draw count on top of multi draw indirect, lets culling compact the draws instead of zeroing them
+    pub fn supports_indirect_count(instance: &ash::Instance, physical_device: vk::PhysicalDevice) -> bool {
+        let mut vulkan12_features = vk::PhysicalDeviceVulkan12Features::default();
+        let mut features = vk::PhysicalDeviceFeatures2::builder().push_next(&mut vulkan12_features);
+        unsafe { instance.get_physical_device_features2(physical_device, &mut features) };
+
+        Self::supports_multi_draw_indirect(instance, physical_device) && vulkan12_features.draw_indirect_count == vk::TRUE
     }
 
     pub fn rate_physical_device(instance: &ash::Instance, device: &vk::PhysicalDevice) -> f32 {
@@ -58,8 +127,14 @@ impl PhysicalDevice {
         // Maximum possible size of textures affects graphics quality
         score += props.limits.max_image_dimension2_d as f32;
 
-        if features.geometry_shader < 1 { // Features are either 0 (not supported) or 1 (supported)
-            println!("Device missing geometry shader support, thus your system is not supported!");
+        // Nothing is drawn with geometry shaders, which MoltenVK doesn't have
+        if features.shader_clip_distance < 1 { // Features are either 0 (not supported) or 1 (supported)
+            tracing::warn!("Device missing shader clip distance support, thus your system is not supported!");
+            return 0.0;
+        }
+
+        if features.image_cube_array < 1 {
+            tracing::warn!("Device missing cube array support, thus your system is not supported!");
             return 0.0;
         }
 
@@ -71,7 +146,7 @@ impl PhysicalDevice {
         }
 
         if !found_graphics_queue || !found_transfer_queue {
-            println!("Physical device missing queues.");
+            tracing::warn!("Physical device missing queues");
             return 0.0;
         }
 
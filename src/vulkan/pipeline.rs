@@ -1,9 +1,182 @@
 use ash::vk;
+use serde::{Deserialize, Serialize};
 
 use super::swapchain::VulkanSwapchain;
 use super::vertex::Vertex;
+use super::post::SCENE_FORMATS;
+use super::clustered_lighting::CLUSTER_SPECIALIZATION;
+use super::shader_reflection;
 
-use crate::PushConstantData;
+// Common GLSL (camera block, lights, shadows, ...) lives in shaders/include and is pulled in with `#include`,
+// resolved relative to the including file by the shader compiler and tracked so edits rebuild every user
+pub const BASIC_VERT: &[u32] = vk_shader_macros::include_glsl!("./shaders/basic.vert", kind: vert);
+pub const BASIC_PULLED_VERT: &[u32] = vk_shader_macros::include_glsl!("./shaders/basic.vert", kind: vert, define: VERTEX_PULLING);
+pub const BASIC_FRAG: &[u32] = vk_shader_macros::include_glsl!("./shaders/basic.frag", kind: frag);
+pub const FULLSCREEN_VERT: &[u32] = vk_shader_macros::include_glsl!("./shaders/fullscreen.vert", kind: vert);
+
+// Sets of the scene pipelines, ordered by how often they change: per view, per material and per draw list.
+// Lighting comes last so pipelines that don't shade (unlit materials) still share the layout of the first three.
+pub const FRAME_SET: u32 = 0;
+pub const MATERIAL_SET: u32 = 1;
+pub const OBJECT_SET: u32 = 2;
+pub const LIGHTING_SET: u32 = 3;
+
+// Value of a `layout(constant_id = id) const` in a shader, fixed when the pipeline is created so the driver can fold it
+// like a literal. Only 32 bit scalars (bool, int, uint, float) are supported, stored as their bits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SpecializationConstant {
+    pub id: u32,
+    pub bits: u32,
+}
+
+impl SpecializationConstant {
+    pub const fn uint(id: u32, value: u32) -> Self {
+        Self { id, bits: value }
+    }
+
+    pub const fn int(id: u32, value: i32) -> Self {
+        Self { id, bits: value as u32 }
+    }
+
+    pub fn float(id: u32, value: f32) -> Self {
+        Self { id, bits: value.to_bits() }
+    }
+
+    // Shaders read it as a 32 bit VkBool32
+    pub const fn bool(id: u32, value: bool) -> Self {
+        Self { id, bits: value as u32 }
+    }
+}
+
+// Map entries and data of a `vk::SpecializationInfo`, which only points into them.
+// Ids a shader stage doesn't declare are ignored, so every stage can be given the same constants.
+pub fn specialization_data(constants: &[SpecializationConstant]) -> (Vec<vk::SpecializationMapEntry>, Vec<u8>) {
+    let map_entries = constants
+        .iter()
+        .enumerate()
+        .map(|(index, constant)| vk::SpecializationMapEntry {
+            constant_id: constant.id,
+            offset: (index * std::mem::size_of::<u32>()) as u32,
+            size: std::mem::size_of::<u32>()
+        })
+        .collect();
+    let data = constants.iter().flat_map(|constant| constant.bits.to_ne_bytes()).collect();
+
+    (map_entries, data)
+}
+
+// How the first color attachment combines with what's already there, the alpha being the shader's output alpha
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum BlendMode {
+    // color * alpha + background * (1 - alpha)
+    Alpha,
+    // color * alpha + background, for glows and fire
+    Additive,
+    // color + background * (1 - alpha), the color already carries its coverage (egui output)
+    Premultiplied,
+}
+
+#[derive(Clone, Copy)]
+pub struct PipelineConfig<'a> {
+    pub vertex_shader: &'a [u32],
+    pub fragment_shader: &'a [u32],
+    pub set_layouts: &'a [vk::DescriptorSetLayout],
+    pub push_constant_size: u32,
+    // Fullscreen passes generate their triangle from gl_VertexIndex and bind no vertex buffers
+    pub vertex_input: bool,
+    pub depth_test: bool,
+    // Only with `depth_test`, blended surfaces test against the depth without hiding what's behind them
+    pub depth_write: bool,
+    // LESS for surfaces, LESS_OR_EQUAL for passes filling in what's still at the far plane
+    pub depth_compare: vk::CompareOp,
+    pub color_attachment_count: u32,
+    pub front_face: vk::FrontFace,
+    pub cull_mode: vk::CullModeFlags,
+    // Constant and slope scaled depth bias, used by shadow passes to avoid acne
+    pub depth_bias: Option<(f32, f32)>,
+    // Index of the subpass within the render pass the pipeline is used in
+    pub subpass: u32,
+    // Used for both faces, `None` disables the stencil test
+    pub stencil: Option<vk::StencilOpState>,
+    // Pipelines only touching the depth/stencil attachment leave every color attachment alone
+    pub color_write: bool,
+    // Pipelines blending over the scene leave the attachments after the first (normals, motion, ids) to the surfaces
+    // behind them
+    pub data_write: bool,
+    pub blend_mode: BlendMode,
+    // Applied to both shader stages, e.g. to build variants of one shader without branching on uniforms
+    pub specialization: &'a [SpecializationConstant],
+    // Takes its fragment shading rate from dynamic state, see `VariableRateShading::record_draw_rate`
+    pub dynamic_shading_rate: bool,
+    // Must match the samples of the render pass' attachments
+    pub samples: vk::SampleCountFlags,
+    // Only with more than one sample, the first attachment's alpha masks the covered samples instead of blending
+    pub alpha_to_coverage: bool,
+}
+
+impl<'a> PipelineConfig<'a> {
+    pub fn basic(set_layouts: &'a [vk::DescriptorSetLayout]) -> Self {
+        Self {
+            vertex_shader: BASIC_VERT,
+            fragment_shader: BASIC_FRAG,
+            set_layouts,
+            // Model and object index come from the object buffer, see `ObjectBuffers`
+            push_constant_size: 0,
+            vertex_input: true,
+            depth_test: true,
+            depth_write: true,
+            depth_compare: vk::CompareOp::LESS,
+            color_attachment_count: SCENE_FORMATS.len() as u32,
+            front_face: vk::FrontFace::CLOCKWISE,
+            cull_mode: vk::CullModeFlags::BACK,
+            depth_bias: None,
+            subpass: 0,
+            stencil: None,
+            color_write: true,
+            data_write: true,
+            blend_mode: BlendMode::Alpha,
+            specialization: &CLUSTER_SPECIALIZATION,
+            dynamic_shading_rate: false,
+            samples: vk::SampleCountFlags::TYPE_1,
+            alpha_to_coverage: false,
+        }
+    }
+
+    // Scene pipelines fetching their vertices from the object set by gl_VertexIndex instead of vertex input, only valid
+    // for draws of the merged geometry, see `ObjectBuffers::write_vertices`
+    pub fn with_vertex_pulling(self, enabled: bool) -> Self {
+        match enabled {
+            true => Self { vertex_shader: BASIC_PULLED_VERT, vertex_input: false, ..self },
+            false => self
+        }
+    }
+
+    pub fn fullscreen(fragment_shader: &'a [u32], set_layouts: &'a [vk::DescriptorSetLayout], push_constant_size: u32) -> Self {
+        Self {
+            vertex_shader: FULLSCREEN_VERT,
+            fragment_shader,
+            set_layouts,
+            push_constant_size,
+            vertex_input: false,
+            depth_test: false,
+            depth_write: false,
+            depth_compare: vk::CompareOp::LESS,
+            color_attachment_count: 1,
+            front_face: vk::FrontFace::CLOCKWISE,
+            cull_mode: vk::CullModeFlags::NONE,
+            depth_bias: None,
+            subpass: 0,
+            stencil: None,
+            color_write: true,
+            data_write: true,
+            blend_mode: BlendMode::Alpha,
+            specialization: &[],
+            dynamic_shading_rate: false,
+            samples: vk::SampleCountFlags::TYPE_1,
+            alpha_to_coverage: false,
+        }
+    }
+}
 
 pub struct Pipeline {
     pub pipeline: vk::Pipeline,
@@ -11,34 +184,52 @@ pub struct Pipeline {
 }
 
 impl Pipeline {
-    pub fn new(logical_device: &ash::Device, swapchain: &VulkanSwapchain, renderpass: &vk::RenderPass) -> Result<Self, vk::Result> {
+    pub fn new(logical_device: &ash::Device, swapchain: &VulkanSwapchain, renderpass: &vk::RenderPass, config: &PipelineConfig) -> Result<Self, vk::Result> {
+        let vertex_attribute_descscriptions = Vertex::get_attribute_descriptions();
+        let vertex_binding_descriptions = Vertex::get_binding_description();
+
+        // Catches shaders expecting sets, push constants or vertex attributes the pipeline doesn't have at creation
+        // instead of as validation errors mid frame
+        let vertex_attributes = match config.vertex_input {
+            true => &vertex_attribute_descscriptions[..],
+            false => &[]
+        };
+        shader_reflection::validate(&[config.vertex_shader, config.fragment_shader], config.set_layouts, config.push_constant_size, vertex_attributes)?;
+
         let main_function_name = std::ffi::CString::new("main").unwrap();
 
         let vertexshader_createinfo = vk::ShaderModuleCreateInfo::builder()
-            .code(vk_shader_macros::include_glsl!("./shaders/basic.vert", kind: vert));
+            .code(config.vertex_shader);
         let vertexshader_module = unsafe { logical_device.create_shader_module(&vertexshader_createinfo, None)? };
 
         let fragmentshader_createinfo = vk::ShaderModuleCreateInfo::builder()
-            .code(vk_shader_macros::include_glsl!("./shaders/basic.frag", kind: frag));
+            .code(config.fragment_shader);
         let fragmentshader_module = unsafe { logical_device.create_shader_module(&fragmentshader_createinfo, None)? };
         
+        let (map_entries, specialization_bytes) = specialization_data(config.specialization);
+        let specialization_info = vk::SpecializationInfo::builder()
+            .map_entries(&map_entries)
+            .data(&specialization_bytes);
+
         let vertexshader_stage = vk::PipelineShaderStageCreateInfo::builder()
             .stage(vk::ShaderStageFlags::VERTEX)
             .module(vertexshader_module)
-            .name(&main_function_name);
+            .name(&main_function_name)
+            .specialization_info(&specialization_info);
         let fragmentshader_stage = vk::PipelineShaderStageCreateInfo::builder()
             .stage(vk::ShaderStageFlags::FRAGMENT)
             .module(fragmentshader_module)
-            .name(&main_function_name);
+            .name(&main_function_name)
+            .specialization_info(&specialization_info);
         
         let shader_stages = [vertexshader_stage.build(), fragmentshader_stage.build()];
 
-        let vertex_attribute_descscriptions = Vertex::get_attribute_descriptions();
-        let vertex_binding_descriptions = Vertex::get_binding_description();
-
-        let vertex_input_info = vk::PipelineVertexInputStateCreateInfo::builder()
-            .vertex_attribute_descriptions(&vertex_attribute_descscriptions)
-            .vertex_binding_descriptions(&vertex_binding_descriptions);
+        let mut vertex_input_info = vk::PipelineVertexInputStateCreateInfo::builder();
+        if config.vertex_input {
+            vertex_input_info = vertex_input_info
+                .vertex_attribute_descriptions(&vertex_attribute_descscriptions)
+                .vertex_binding_descriptions(&vertex_binding_descriptions);
+        }
 
         let input_assembly_info = vk::PipelineInputAssemblyStateCreateInfo::builder()
             .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
@@ -61,23 +252,39 @@ impl Pipeline {
             .viewports(&viewports)
             .scissors(&scissors);
 
-        let rasterizer_info = vk::PipelineRasterizationStateCreateInfo::builder()
+        let mut rasterizer_info = vk::PipelineRasterizationStateCreateInfo::builder()
             .line_width(1.0)
             .depth_clamp_enable(false)
-            .front_face(vk::FrontFace::CLOCKWISE)
-            .cull_mode(vk::CullModeFlags::BACK)
+            .front_face(config.front_face)
+            .cull_mode(config.cull_mode)
             .polygon_mode(vk::PolygonMode::FILL);
+        if let Some((constant_factor, slope_factor)) = config.depth_bias {
+            rasterizer_info = rasterizer_info
+                .depth_bias_enable(true)
+                .depth_bias_constant_factor(constant_factor)
+                .depth_bias_slope_factor(slope_factor);
+        }
 
         let multisampler_info = vk::PipelineMultisampleStateCreateInfo::builder()
-            .rasterization_samples(vk::SampleCountFlags::TYPE_1);
+            .rasterization_samples(config.samples)
+            .alpha_to_coverage_enable(config.alpha_to_coverage);
 
-        let colorblend_attachments = [vk::PipelineColorBlendAttachmentState::builder()
-            .blend_enable(true)
-            .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
-            .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+        // Additive blending keeps the alpha of the background
+        let (src_blend_factor, dst_blend_factor, src_alpha_factor, dst_alpha_factor) = match config.blend_mode {
+            BlendMode::Alpha => (vk::BlendFactor::SRC_ALPHA, vk::BlendFactor::ONE_MINUS_SRC_ALPHA, vk::BlendFactor::SRC_ALPHA,
+                vk::BlendFactor::ONE_MINUS_SRC_ALPHA),
+            BlendMode::Additive => (vk::BlendFactor::SRC_ALPHA, vk::BlendFactor::ONE, vk::BlendFactor::ZERO, vk::BlendFactor::ONE),
+            BlendMode::Premultiplied => (vk::BlendFactor::ONE, vk::BlendFactor::ONE_MINUS_SRC_ALPHA, vk::BlendFactor::ONE,
+                vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+        };
+        // With alpha to coverage the alpha already thins out the edges, blending with it as well would fade them twice
+        let colorblend_attachment = vk::PipelineColorBlendAttachmentState::builder()
+            .blend_enable(!config.alpha_to_coverage)
+            .src_color_blend_factor(src_blend_factor)
+            .dst_color_blend_factor(dst_blend_factor)
             .color_blend_op(vk::BlendOp::ADD)
-            .src_alpha_blend_factor(vk::BlendFactor::SRC_ALPHA)
-            .dst_alpha_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+            .src_alpha_blend_factor(src_alpha_factor)
+            .dst_alpha_blend_factor(dst_alpha_factor)
             .alpha_blend_op(vk::BlendOp::ADD)
             .color_write_mask(
                 vk::ColorComponentFlags::R
@@ -85,30 +292,58 @@ impl Pipeline {
                     | vk::ColorComponentFlags::B
                     | vk::ColorComponentFlags::A
             )
-            .build()
-        ];
+            .build();
+        // Only the first attachment holds color, the rest are data (normals, ids, ...) and are written as is
+        let mut colorblend_attachments = vec![colorblend_attachment; config.color_attachment_count as usize];
+        for attachment in colorblend_attachments.iter_mut().skip(1) {
+            attachment.blend_enable = vk::FALSE;
+            if !config.data_write {
+                attachment.color_write_mask = vk::ColorComponentFlags::empty();
+            }
+        }
+        if !config.color_write {
+            for attachment in colorblend_attachments.iter_mut() {
+                attachment.color_write_mask = vk::ColorComponentFlags::empty();
+            }
+        }
         
         let colorblend_info = vk::PipelineColorBlendStateCreateInfo::builder().attachments(&colorblend_attachments);
 
-        let depthstencil_info = vk::PipelineDepthStencilStateCreateInfo::builder()
-            .depth_test_enable(true)
-            .depth_write_enable(true)
-            .depth_compare_op(vk::CompareOp::LESS)
+        let mut depthstencil_info = vk::PipelineDepthStencilStateCreateInfo::builder()
+            .depth_test_enable(config.depth_test)
+            .depth_write_enable(config.depth_test && config.depth_write)
+            .depth_compare_op(config.depth_compare)
             .depth_bounds_test_enable(false)
             .stencil_test_enable(false);
+        if let Some(stencil) = config.stencil {
+            depthstencil_info = depthstencil_info
+                .stencil_test_enable(true)
+                .front(stencil)
+                .back(stencil);
+        }
         
+        let mut dynamic_states = vec![vk::DynamicState::SCISSOR, vk::DynamicState::VIEWPORT];
+        if config.dynamic_shading_rate {
+            dynamic_states.push(vk::DynamicState::FRAGMENT_SHADING_RATE_KHR);
+        }
         let dynamic_state_info = vk::PipelineDynamicStateCreateInfo::builder()
-            .dynamic_states(&[vk::DynamicState::SCISSOR, vk::DynamicState::VIEWPORT]);
+            .dynamic_states(&dynamic_states);
 
         let push_constant_range = [vk::PushConstantRange::builder()
             .stage_flags(vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT)
             .offset(0)
-            .size(std::mem::size_of::<PushConstantData>() as u32)
+            .size(config.push_constant_size)
             .build()
         ];
 
+        let push_constant_ranges = match config.push_constant_size {
+            0 => &push_constant_range[..0],
+            _ => &push_constant_range[..]
+        };
+
         let pipelinelayout_info = vk::PipelineLayoutCreateInfo::builder()
-            .push_constant_ranges(&push_constant_range);
+            .set_layouts(config.set_layouts)
+            .push_constant_ranges(push_constant_ranges);
         let pipeline_layout = unsafe { logical_device.create_pipeline_layout(&pipelinelayout_info, None)? };
 
         let pipeline_info = vk::GraphicsPipelineCreateInfo::builder()
@@ -123,7 +358,7 @@ impl Pipeline {
             .dynamic_state(&dynamic_state_info)
             .layout(pipeline_layout)
             .render_pass(*renderpass)
-            .subpass(0);
+            .subpass(config.subpass);
 
         let graphics_pipeline = unsafe {
             logical_device.create_graphics_pipelines(vk::PipelineCache::null(), &[pipeline_info.build()], None)
@@ -0,0 +1,44 @@
+use std::ffi::CStr;
+
+use ash::vk;
+
+// Instance extensions and flags that make layered implementations like MoltenVK show up in the device list. Without
+// VK_KHR_portability_enumeration the loader hides them, so there's nothing to render with on macOS.
+pub fn instance_extensions(entry: &ash::Entry) -> (Vec<&'static CStr>, vk::InstanceCreateFlags) {
+    let extensions = entry.enumerate_instance_extension_properties(None).unwrap_or_default();
+    let name = vk::KhrPortabilityEnumerationFn::name();
+    match extensions.iter().any(|extension| unsafe { CStr::from_ptr(extension.extension_name.as_ptr()) } == name) {
+        true => (vec![name], vk::InstanceCreateFlags::ENUMERATE_PORTABILITY_KHR),
+        false => (vec![], vk::InstanceCreateFlags::empty())
+    }
+}
+
+// What a device implementing VK_KHR_portability_subset leaves out of full Vulkan. The extension has to be enabled
+// whenever the device lists it.
+#[derive(Clone, Copy, Debug)]
+pub struct PortabilitySubset {
+    pub features: vk::PhysicalDevicePortabilitySubsetFeaturesKHR,
+}
+
+impl PortabilitySubset {
+    // `None` on devices that implement all of Vulkan
+    pub fn query(instance: &ash::Instance, physical_device: vk::PhysicalDevice) -> Option<Self> {
+        let extensions = unsafe { instance.enumerate_device_extension_properties(physical_device).ok()? };
+        let name = vk::KhrPortabilitySubsetFn::name();
+        if !extensions.iter().any(|extension| unsafe { CStr::from_ptr(extension.extension_name.as_ptr()) } == name) {
+            return None;
+        }
+
+        let mut subset_features = vk::PhysicalDevicePortabilitySubsetFeaturesKHR::default();
+        let mut features = vk::PhysicalDeviceFeatures2::builder().push_next(&mut subset_features);
+        unsafe { instance.get_physical_device_features2(physical_device, &mut features) };
+        subset_features.p_next = std::ptr::null_mut();
+
+        // Shadow maps are sampled through comparison samplers written into descriptor sets like any other
+        if subset_features.mutable_comparison_samplers != vk::TRUE {
+            tracing::warn!("Device can't write comparison samplers into descriptor sets, shadows won't work");
+        }
+
+        Some(Self { features: subset_features })
+    }
+}
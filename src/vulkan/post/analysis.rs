@@ -0,0 +1,214 @@
+use ash::vk;
+use gpu_allocator::vulkan::Allocator;
+use gpu_allocator::MemoryLocation;
+
+use crate::vulkan::compute_pipeline::ComputePipeline;
+use crate::vulkan::descriptors::Descriptors;
+use crate::vulkan::storage_buffer::StorageBuffer;
+use crate::utils::any_as_u8_slice;
+
+const IMAGE_ANALYSIS_COMP: &[u32] = vk_shader_macros::include_glsl!("./shaders/image_analysis.comp", kind: comp);
+const IMAGE_RESOLVE_COMP: &[u32] = vk_shader_macros::include_glsl!("./shaders/image_analysis.comp", kind: comp, define: RESOLVE);
+
+// Must match the constants in image_analysis.comp
+pub const HISTOGRAM_BINS: usize = 256;
+const ANALYSIS_GROUPS: u32 = 64;
+const BLACK_LUMINANCE: f32 = 0.0001;
+
+// The `Analysis` block of image_analysis.comp: the histogram, a 32 byte partial per group, then the results
+const HISTOGRAM_SIZE: u64 = (HISTOGRAM_BINS * std::mem::size_of::<u32>()) as u64;
+const RESULTS_OFFSET: u64 = HISTOGRAM_SIZE + ANALYSIS_GROUPS as u64 * 32;
+const BUFFER_SIZE: u64 = RESULTS_OFFSET + 32;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct AnalysisPushConstants {
+    min_log_luminance: f32,
+    log_luminance_range: f32,
+    extent: [u32; 2],
+}
+
+// What the reduction of one frame found
+#[derive(Clone, Debug)]
+pub struct ImageStatistics {
+    // Pixels counted by log2 luminance, bins 1 to 255 evenly cover `min_log_luminance..max_log_luminance` and bin 0
+    // holds the black ones
+    pub histogram: Vec<u32>,
+    pub min_log_luminance: f32,
+    pub max_log_luminance: f32,
+    pub min_luminance: f32,
+    pub max_luminance: f32,
+    pub average_luminance: f32,
+    // Geometric mean, which a few very bright pixels don't throw off
+    pub log_average_luminance: f32,
+    pub average_color: uv::Vec3,
+    pub pixel_count: u32,
+}
+
+impl ImageStatistics {
+    // Share of the pixels darker than `luminance`, as fine as the histogram's bins
+    pub fn fraction_below(&self, luminance: f32) -> f32 {
+        let bin = match luminance < BLACK_LUMINANCE {
+            true => 0,
+            false => {
+                let t = (luminance.log2() - self.min_log_luminance) / (self.max_log_luminance - self.min_log_luminance);
+                (t.clamp(0.0, 1.0) * (HISTOGRAM_BINS - 2) as f32 + 1.0) as usize
+            }
+        };
+        let below: u32 = self.histogram[..bin].iter().sum();
+        below as f32 / self.pixel_count.max(1) as f32
+    }
+}
+
+// Reduces an image on the GPU into a luminance histogram, its min, max and average luminance and its average color.
+// Every swapchain image has its own buffer, read back once that image's frame is done, so `statistics` lags the
+// screen by the frames in flight. Auto exposure adapts to the histogram of the scene's analysis.
+pub struct ImageAnalysis {
+    pub enabled: bool,
+    // Luminance range covered by the histogram, in EV (log2)
+    pub min_log_luminance: f32,
+    pub max_log_luminance: f32,
+    pub set_layout: vk::DescriptorSetLayout,
+    // One per swapchain image, the image in binding 0 and its buffer in binding 1
+    pub sets: Vec<vk::DescriptorSet>,
+    reduce_pipeline: ComputePipeline,
+    resolve_pipeline: ComputePipeline,
+    buffers: Vec<StorageBuffer>,
+    // The histogram range of the reduction each buffer holds, `None` when there's nothing new to read
+    pending: Vec<Option<(f32, f32)>>,
+    statistics: Option<ImageStatistics>,
+}
+
+impl ImageAnalysis {
+    pub fn new(device: &ash::Device, allocator: &mut Allocator, descriptor_pool: vk::DescriptorPool, image_count: usize,
+        image: vk::DescriptorImageInfo
+    ) -> Result<Self, vk::Result> {
+        let set_layout = Descriptors::create_layout(device, &[
+            (vk::DescriptorType::COMBINED_IMAGE_SAMPLER, vk::ShaderStageFlags::COMPUTE),
+            (vk::DescriptorType::STORAGE_BUFFER, vk::ShaderStageFlags::COMPUTE),
+        ])?;
+        let sets = Descriptors::allocate(device, descriptor_pool, set_layout, image_count)?;
+
+        let push_constant_size = std::mem::size_of::<AnalysisPushConstants>() as u32;
+        let reduce_pipeline = ComputePipeline::new(device, IMAGE_ANALYSIS_COMP, &[set_layout], push_constant_size, &[])?;
+        let resolve_pipeline = ComputePipeline::new(device, IMAGE_RESOLVE_COMP, &[set_layout], push_constant_size, &[])?;
+
+        // Read back by the host, the histogram is cleared with a fill before every reduction
+        let buffers: Vec<StorageBuffer> = (0..image_count)
+            .map(|_| StorageBuffer::with_usage(device, allocator, BUFFER_SIZE, vk::BufferUsageFlags::TRANSFER_DST, MemoryLocation::GpuToCpu,
+                "Image Analysis"))
+            .collect();
+        for (set, buffer) in sets.iter().zip(&buffers) {
+            Descriptors::write_buffer(device, *set, 1, vk::DescriptorType::STORAGE_BUFFER, buffer.descriptor_info());
+        }
+
+        let analysis = Self {
+            enabled: false,
+            min_log_luminance: -8.0,
+            max_log_luminance: 4.0,
+            set_layout,
+            sets,
+            reduce_pipeline,
+            resolve_pipeline,
+            buffers,
+            pending: vec![None; image_count],
+            statistics: None
+        };
+        analysis.write_image(device, image);
+
+        Ok(analysis)
+    }
+
+    pub fn write_image(&self, device: &ash::Device, image: vk::DescriptorImageInfo) {
+        for set in &self.sets {
+            Descriptors::write_image(device, *set, 0, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, image);
+        }
+    }
+
+    // The most recent results, `None` until the first analyzed frame is done
+    pub fn statistics(&self) -> Option<&ImageStatistics> {
+        self.statistics.as_ref()
+    }
+
+    // Called once the frame that last used image `index` is done, `recorded` tells whether the next one analyzes
+    pub fn collect(&mut self, index: usize, recorded: bool) {
+        if let Some((min_log_luminance, max_log_luminance)) = self.pending[index] {
+            let buffer = &self.buffers[index];
+            let mut histogram = vec![0u32; HISTOGRAM_BINS];
+            buffer.read_buffer(0, &mut histogram);
+            let mut results = [0.0f32; 8];
+            buffer.read_buffer(RESULTS_OFFSET, &mut results);
+
+            self.statistics = Some(ImageStatistics {
+                histogram,
+                min_log_luminance,
+                max_log_luminance,
+                min_luminance: results[4],
+                max_luminance: results[5],
+                average_luminance: results[3],
+                log_average_luminance: results[6],
+                average_color: uv::Vec3::new(results[0], results[1], results[2]),
+                pixel_count: results[7] as u32
+            });
+        }
+        self.pending[index] = recorded.then_some((self.min_log_luminance, self.max_log_luminance));
+    }
+
+    // Analyzes the top left `extent` of the image, which must have been written as a color attachment. Recorded outside of
+    // any render pass, the results can be read by compute shaders right after.
+    pub fn record(&self, device: &ash::Device, command_buffer: vk::CommandBuffer, index: usize, extent: vk::Extent2D) {
+        let push = AnalysisPushConstants {
+            min_log_luminance: self.min_log_luminance,
+            log_luminance_range: self.max_log_luminance - self.min_log_luminance,
+            extent: [extent.width, extent.height]
+        };
+        let push = unsafe { any_as_u8_slice(&push) };
+
+        let input_barrier = [vk::MemoryBarrier::builder()
+            .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE | vk::AccessFlags::TRANSFER_WRITE)
+            .dst_access_mask(vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE)
+            .build()
+        ];
+        let partial_barrier = [vk::MemoryBarrier::builder()
+            .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+            .dst_access_mask(vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE)
+            .build()
+        ];
+        let result_barrier = [vk::MemoryBarrier::builder()
+            .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+            .dst_access_mask(vk::AccessFlags::SHADER_READ | vk::AccessFlags::HOST_READ)
+            .build()
+        ];
+
+        unsafe {
+            device.cmd_fill_buffer(command_buffer, self.buffers[index].get_buffer(), 0, HISTOGRAM_SIZE, 0);
+            device.cmd_pipeline_barrier(command_buffer, vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::COMPUTE_SHADER, vk::DependencyFlags::empty(), &input_barrier, &[], &[]);
+
+            device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::COMPUTE, self.reduce_pipeline.pipeline);
+            device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::COMPUTE, self.reduce_pipeline.layout, 0, &[self.sets[index]], &[]);
+            device.cmd_push_constants(command_buffer, self.reduce_pipeline.layout, vk::ShaderStageFlags::COMPUTE, 0, push);
+            device.cmd_dispatch(command_buffer, ANALYSIS_GROUPS, 1, 1);
+
+            device.cmd_pipeline_barrier(command_buffer, vk::PipelineStageFlags::COMPUTE_SHADER, vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::DependencyFlags::empty(), &partial_barrier, &[], &[]);
+
+            device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::COMPUTE, self.resolve_pipeline.pipeline);
+            device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::COMPUTE, self.resolve_pipeline.layout, 0, &[self.sets[index]], &[]);
+            device.cmd_push_constants(command_buffer, self.resolve_pipeline.layout, vk::ShaderStageFlags::COMPUTE, 0, push);
+            device.cmd_dispatch(command_buffer, 1, 1, 1);
+
+            device.cmd_pipeline_barrier(command_buffer, vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::PipelineStageFlags::COMPUTE_SHADER | vk::PipelineStageFlags::HOST, vk::DependencyFlags::empty(), &result_barrier, &[], &[]);
+        }
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device, allocator: &mut Allocator) {
+        self.reduce_pipeline.cleanup(device);
+        self.resolve_pipeline.cleanup(device);
+        for buffer in &mut self.buffers {
+            buffer.destroy(device, allocator);
+        }
+        unsafe { device.destroy_descriptor_set_layout(self.set_layout, None) };
+    }
+}
\ No newline at end of file
@@ -0,0 +1,23 @@
+pub const DOF_FRAG: &[u32] = vk_shader_macros::include_glsl!("./shaders/dof.frag", kind: frag);
+
+// Mirrors the push constant block of dof.frag
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct DofSettings {
+    // Upper bound for the circle of confusion radius, in pixels
+    pub max_radius: f32,
+    pub samples: i32,
+}
+
+impl DofSettings {
+    pub const NAME: &'static str = "dof";
+}
+
+impl Default for DofSettings {
+    fn default() -> Self {
+        Self {
+            max_radius: 16.0,
+            samples: 32,
+        }
+    }
+}
\ No newline at end of file
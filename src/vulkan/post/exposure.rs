@@ -0,0 +1,179 @@
+use std::time::Instant;
+
+use ash::vk;
+use gpu_allocator::vulkan::Allocator;
+use gpu_allocator::MemoryLocation;
+
+use crate::vulkan::compute_pipeline::ComputePipeline;
+use crate::vulkan::descriptors::Descriptors;
+use crate::vulkan::storage_buffer::StorageBuffer;
+use crate::utils::any_as_u8_slice;
+
+use super::analysis::ImageAnalysis;
+
+pub const EXPOSURE_AVERAGE_COMP: &[u32] = vk_shader_macros::include_glsl!("./shaders/exposure_average.comp", kind: comp);
+
+#[derive(Clone, Copy, Debug)]
+pub struct AutoExposureSettings {
+    // Luminance range covered by the histogram, in EV (log2)
+    pub min_log_luminance: f32,
+    pub max_log_luminance: f32,
+    // Adaptation rates when the scene gets brighter and darker respectively, higher is faster
+    pub speed_up: f32,
+    pub speed_down: f32,
+    // Middle grey the average luminance is mapped to
+    pub key: f32,
+}
+
+impl Default for AutoExposureSettings {
+    fn default() -> Self {
+        Self {
+            min_log_luminance: -8.0,
+            max_log_luminance: 4.0,
+            speed_up: 3.0,
+            speed_down: 1.0,
+            key: 0.18,
+        }
+    }
+}
+
+// Mirrors the push constant block of exposure_average.comp
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct ExposurePushConstants {
+    min_log_luminance: f32,
+    log_luminance_range: f32,
+    delta_time: f32,
+    speed_up: f32,
+    speed_down: f32,
+    key: f32,
+    pixel_count: u32,
+    reset: u32,
+}
+
+// Layout of the exposure buffer, read by composite.frag
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct ExposureState {
+    exposure: f32,
+    luminance: f32,
+}
+
+// Eye adaptation: averages the luminance histogram of the scene's `ImageAnalysis` and eases the exposure towards the
+// result. The exposure stays on the GPU, the composite pass multiplies it in before tonemapping.
+pub struct AutoExposure {
+    pub enabled: bool,
+    pub settings: AutoExposureSettings,
+    pub set_layout: vk::DescriptorSetLayout,
+    pub set: vk::DescriptorSet,
+    average_pipeline: ComputePipeline,
+    exposure_buffer: StorageBuffer,
+    last_update: Instant,
+    delta_time: f32,
+    reset_frame: u64,
+}
+
+impl AutoExposure {
+    // The average pass reads the histogram through the analysis' set 0, the exposure is in set 1
+    pub fn new(device: &ash::Device, allocator: &mut Allocator, descriptor_pool: vk::DescriptorPool, analysis: &ImageAnalysis) -> Result<Self, vk::Result> {
+        let set_layout = Descriptors::create_layout(device, &[
+            (vk::DescriptorType::STORAGE_BUFFER, vk::ShaderStageFlags::COMPUTE),
+        ])?;
+        let set = Descriptors::allocate(device, descriptor_pool, set_layout, 1)?[0];
+
+        let push_constant_size = std::mem::size_of::<ExposurePushConstants>() as u32;
+        let average_pipeline = ComputePipeline::new(device, EXPOSURE_AVERAGE_COMP, &[analysis.set_layout, set_layout], push_constant_size, &[])?;
+
+        // Host visible so it can be initialized without a transfer
+        let mut exposure_buffer = StorageBuffer::new(device, allocator, std::mem::size_of::<ExposureState>() as u64,
+            MemoryLocation::CpuToGpu, "Exposure Buffer");
+        exposure_buffer.update_buffer(0, &[ExposureState { exposure: 1.0, luminance: 1.0 }]);
+
+        Descriptors::write_buffer(device, set, 0, vk::DescriptorType::STORAGE_BUFFER, exposure_buffer.descriptor_info());
+
+        Ok(Self {
+            enabled: false,
+            settings: AutoExposureSettings::default(),
+            set_layout,
+            set,
+            average_pipeline,
+            exposure_buffer,
+            last_update: Instant::now(),
+            delta_time: 0.0,
+            reset_frame: 0
+        })
+    }
+
+    pub fn exposure_descriptor_info(&self) -> vk::DescriptorBufferInfo {
+        self.exposure_buffer.descriptor_info()
+    }
+
+    // The first adaptation after enabling jumps straight to the measured exposure
+    pub fn enable(&mut self, settings: AutoExposureSettings, frame_index: u64) {
+        if !self.enabled {
+            self.reset_frame = frame_index;
+        }
+        self.enabled = true;
+        self.settings = settings;
+    }
+
+    // Waits for the device since the exposure buffer is reset from the host
+    pub fn disable(&mut self, device: &ash::Device) -> Result<(), vk::Result> {
+        unsafe { device.device_wait_idle()? };
+        self.enabled = false;
+        self.exposure_buffer.update_buffer(0, &[ExposureState { exposure: 1.0, luminance: 1.0 }]);
+
+        Ok(())
+    }
+
+    // Called once per frame, the adaptation speed is independent of the frame rate
+    pub fn tick(&mut self) {
+        let now = Instant::now();
+        self.delta_time = (now - self.last_update).as_secs_f32();
+        self.last_update = now;
+    }
+
+    // Recorded right after `analysis` analyzed the scene for image `index`, with the same histogram range
+    pub fn record(&self, device: &ash::Device, command_buffer: vk::CommandBuffer, analysis: &ImageAnalysis, index: usize, extent: vk::Extent2D,
+        frame_index: u64
+    ) {
+        if !self.enabled {
+            return;
+        }
+
+        let push = ExposurePushConstants {
+            min_log_luminance: self.settings.min_log_luminance,
+            log_luminance_range: self.settings.max_log_luminance - self.settings.min_log_luminance,
+            delta_time: self.delta_time,
+            speed_up: self.settings.speed_up,
+            speed_down: self.settings.speed_down,
+            key: self.settings.key,
+            pixel_count: extent.width * extent.height,
+            reset: (frame_index == self.reset_frame) as u32
+        };
+        let push = unsafe { any_as_u8_slice(&push) };
+
+        let exposure_barrier = [vk::MemoryBarrier::builder()
+            .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+            .dst_access_mask(vk::AccessFlags::SHADER_READ)
+            .build()
+        ];
+
+        unsafe {
+            device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::COMPUTE, self.average_pipeline.pipeline);
+            device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::COMPUTE, self.average_pipeline.layout, 0,
+                &[analysis.sets[index], self.set], &[]);
+            device.cmd_push_constants(command_buffer, self.average_pipeline.layout, vk::ShaderStageFlags::COMPUTE, 0, push);
+            device.cmd_dispatch(command_buffer, 1, 1, 1);
+
+            device.cmd_pipeline_barrier(command_buffer, vk::PipelineStageFlags::COMPUTE_SHADER, vk::PipelineStageFlags::FRAGMENT_SHADER,
+                vk::DependencyFlags::empty(), &exposure_barrier, &[], &[]);
+        }
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device, allocator: &mut Allocator) {
+        self.average_pipeline.cleanup(device);
+        self.exposure_buffer.destroy(device, allocator);
+        unsafe { device.destroy_descriptor_set_layout(self.set_layout, None) };
+    }
+}
\ No newline at end of file
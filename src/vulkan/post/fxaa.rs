@@ -0,0 +1,26 @@
+pub const FXAA_FRAG: &[u32] = vk_shader_macros::include_glsl!("./shaders/fxaa.frag", kind: frag);
+
+// Mirrors the push constant block of fxaa.frag
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct FxaaSettings {
+    // Minimum local contrast for an edge, relative to the brightest neighbor
+    pub edge_threshold: f32,
+    // Contrast below this absolute luma is never treated as an edge (dark areas)
+    pub edge_threshold_min: f32,
+    pub subpixel_blend: f32,
+}
+
+impl FxaaSettings {
+    pub const NAME: &'static str = "fxaa";
+}
+
+impl Default for FxaaSettings {
+    fn default() -> Self {
+        Self {
+            edge_threshold: 0.125,
+            edge_threshold_min: 0.0312,
+            subpixel_blend: 0.75,
+        }
+    }
+}
\ No newline at end of file
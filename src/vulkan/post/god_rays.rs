@@ -0,0 +1,43 @@
+pub const GOD_RAYS_FRAG: &[u32] = vk_shader_macros::include_glsl!("./shaders/god_rays.frag", kind: frag);
+
+// Mirrors the push constant block of god_rays.frag
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct GodRaySettings {
+    // World space direction pointing towards the sun
+    pub sun_direction: uv::Vec4,
+    // Tint of the shafts, alpha is unused
+    pub color: uv::Vec4,
+    pub samples: i32,
+    // How far towards the sun the blur reaches, 1.0 goes all the way
+    pub density: f32,
+    // Per sample attenuation along the ray
+    pub decay: f32,
+    pub weight: f32,
+    pub exposure: f32,
+}
+
+impl GodRaySettings {
+    pub const NAME: &'static str = "god_rays";
+
+    pub fn with_sun_direction(direction: uv::Vec3) -> Self {
+        Self {
+            sun_direction: direction.normalized().into_homogeneous_vector(),
+            ..Self::default()
+        }
+    }
+}
+
+impl Default for GodRaySettings {
+    fn default() -> Self {
+        Self {
+            sun_direction: uv::Vec3::new(0.3, 0.6, -1.0).normalized().into_homogeneous_vector(),
+            color: uv::Vec4::new(1.0, 0.9, 0.7, 1.0),
+            samples: 64,
+            density: 0.9,
+            decay: 0.96,
+            weight: 0.4,
+            exposure: 0.3,
+        }
+    }
+}
\ No newline at end of file
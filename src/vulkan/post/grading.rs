@@ -0,0 +1,119 @@
+// 3D color lookup table, RGBA texels with red varying fastest, then green, then blue
+#[derive(Clone, Debug)]
+pub struct Lut {
+    pub size: u32,
+    pub data: Vec<f32>,
+}
+
+impl Lut {
+    // Maps every color to itself, two entries per axis are enough with linear filtering
+    pub fn identity(size: u32) -> Self {
+        let mut data = Vec::with_capacity((size * size * size * 4) as usize);
+        let scale = 1.0 / (size - 1) as f32;
+        for b in 0..size {
+            for g in 0..size {
+                for r in 0..size {
+                    data.extend_from_slice(&[r as f32 * scale, g as f32 * scale, b as f32 * scale, 1.0]);
+                }
+            }
+        }
+
+        Self {
+            size,
+            data
+        }
+    }
+
+    pub fn from_cube_file(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let source = std::fs::read_to_string(path)?;
+        Self::from_cube(&source)
+    }
+
+    // Adobe/Resolve .cube format, only 3D tables over the default [0, 1] domain are supported
+    pub fn from_cube(source: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut size = 0;
+        let mut data = vec![];
+
+        for line in source.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut words = line.split_whitespace();
+            let first = words.next().unwrap_or_default();
+            match first {
+                "LUT_3D_SIZE" => size = words.next().ok_or("LUT_3D_SIZE without a value")?.parse()?,
+                "LUT_1D_SIZE" => return Err("1D .cube tables are not supported".into()),
+                "TITLE" | "DOMAIN_MIN" | "DOMAIN_MAX" => {},
+                _ => {
+                    let r: f32 = first.parse()?;
+                    let g: f32 = words.next().ok_or("LUT entry with less than 3 values")?.parse()?;
+                    let b: f32 = words.next().ok_or("LUT entry with less than 3 values")?.parse()?;
+                    data.extend_from_slice(&[r, g, b, 1.0]);
+                }
+            }
+        }
+
+        if size < 2 {
+            return Err("Missing or invalid LUT_3D_SIZE".into());
+        }
+        if data.len() != (size * size * size * 4) as usize {
+            return Err(format!("Expected {} LUT entries, found {}", size * size * size, data.len() / 4).into());
+        }
+
+        Ok(Self {
+            size,
+            data
+        })
+    }
+
+    // Horizontal strip of `size` square slices (blue increases per slice), tightly packed RGBA8
+    pub fn from_strip(size: u32, rgba: &[u8]) -> Result<Self, Box<dyn std::error::Error>> {
+        let width = (size * size) as usize;
+        if rgba.len() != width * size as usize * 4 {
+            return Err(format!("LUT strip of size {} needs {}x{} RGBA8 pixels", size, width, size).into());
+        }
+
+        let mut data = Vec::with_capacity(rgba.len());
+        for b in 0..size as usize {
+            for g in 0..size as usize {
+                for r in 0..size as usize {
+                    let pixel = (g * width + b * size as usize + r) * 4;
+                    data.extend(rgba[pixel..pixel + 3].iter().map(|&channel| channel as f32 / 255.0));
+                    data.push(1.0);
+                }
+            }
+        }
+
+        Ok(Self {
+            size,
+            data
+        })
+    }
+}
+
+// Mirrors the push constant block of composite.frag
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct CompositeSettings {
+    // Lift, gamma and gain act on shadows, mid-tones and highlights after tonemapping
+    pub lift: uv::Vec4,
+    pub gamma: uv::Vec4,
+    pub gain: uv::Vec4,
+    // Multiplier on the HDR color before tonemapping, on top of the auto exposure
+    pub exposure: f32,
+    // Blend between the ungraded (0.0) and the LUT graded (1.0) color
+    pub lut_strength: f32,
+}
+
+impl Default for CompositeSettings {
+    fn default() -> Self {
+        Self {
+            lift: uv::Vec4::zero(),
+            gamma: uv::Vec4::one(),
+            gain: uv::Vec4::one(),
+            exposure: 1.0,
+            lut_strength: 1.0,
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,118 @@
+use ash::vk;
+
+use crate::utils::any_as_u8_slice;
+use crate::vulkan::pipeline::{BlendMode, Pipeline, PipelineConfig};
+use crate::vulkan::swapchain::VulkanSwapchain;
+
+pub const COPY_FRAG: &[u32] = vk_shader_macros::include_glsl!("./shaders/copy.frag", kind: frag);
+pub const LENS_FLARE_VERT: &[u32] = vk_shader_macros::include_glsl!("./shaders/lens_flare.vert", kind: vert);
+pub const LENS_FLARE_FRAG: &[u32] = vk_shader_macros::include_glsl!("./shaders/lens_flare.frag", kind: frag);
+
+// Must match FLARE_ELEMENTS in lens_flare.vert
+const FLARE_ELEMENTS: u32 = 7;
+
+#[derive(Clone, Copy, Debug)]
+pub struct LensFlareSettings {
+    // Multiplies the light's color and intensity
+    pub intensity: f32,
+    // Of the sun's flare, point lights get half of it
+    pub size: f32,
+    // Point lights below this intensity get no flare
+    pub threshold: f32,
+    pub max_sources: usize,
+    // Radius around the light the occlusion test samples the depth in, in uv units. Larger fades in and out more
+    // smoothly as geometry passes in front.
+    pub occlusion_radius: f32,
+}
+
+impl Default for LensFlareSettings {
+    fn default() -> Self {
+        Self {
+            intensity: 0.05,
+            size: 1.0,
+            threshold: 2.0,
+            max_sources: 8,
+            occlusion_radius: 0.01,
+        }
+    }
+}
+
+// Light a flare is drawn for, see `VulkanRenderer::lens_flare_sources`
+#[derive(Clone, Copy, Debug)]
+pub struct FlareSource {
+    // World position, or the direction towards a directional light with w = 0.0
+    pub position: uv::Vec4,
+    // Color times intensity
+    pub color: uv::Vec3,
+    // Relative to `LensFlareSettings::size`
+    pub size: f32,
+}
+
+// Mirrors the push constant block of lens_flare.vert
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct FlarePushConstants {
+    position: uv::Vec4,
+    color: uv::Vec4,
+    params: uv::Vec4,
+}
+
+// Sprites of glows, ghosts and a halo along the line from each bright light through the center of the screen, added
+// over the image after the post effects. Each light's vertices test the scene depth around it, so the flare fades as
+// geometry moves in front of the light instead of popping.
+pub struct LensFlare {
+    pub settings: LensFlareSettings,
+    // Carries the image over into the target the flares are added to
+    copy_pipeline: Pipeline,
+    pipeline: Pipeline,
+}
+
+impl LensFlare {
+    pub fn new(device: &ash::Device, swapchain: &VulkanSwapchain, renderpass: &vk::RenderPass, set_layouts: &[vk::DescriptorSetLayout],
+        settings: LensFlareSettings
+    ) -> Result<Self, vk::Result> {
+        let copy_pipeline = Pipeline::new(device, swapchain, renderpass, &PipelineConfig::fullscreen(COPY_FRAG, set_layouts, 0))?;
+        let config = PipelineConfig {
+            vertex_shader: LENS_FLARE_VERT,
+            blend_mode: BlendMode::Additive,
+            ..PipelineConfig::fullscreen(LENS_FLARE_FRAG, set_layouts, std::mem::size_of::<FlarePushConstants>() as u32)
+        };
+        let pipeline = Pipeline::new(device, swapchain, renderpass, &config)?;
+
+        Ok(Self {
+            settings,
+            copy_pipeline,
+            pipeline
+        })
+    }
+
+    pub fn copy_pipeline(&self) -> &Pipeline {
+        &self.copy_pipeline
+    }
+
+    // Inside the pass `copy_pipeline` drew into, with the same sets bound
+    pub fn record(&self, device: &ash::Device, command_buffer: vk::CommandBuffer, sources: &[FlareSource]) {
+        unsafe {
+            device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, self.pipeline.pipeline);
+        }
+
+        for source in sources.iter().take(self.settings.max_sources) {
+            let color = source.color * self.settings.intensity;
+            let push = FlarePushConstants {
+                position: source.position,
+                color: uv::Vec4::new(color.x, color.y, color.z, source.size * self.settings.size),
+                params: uv::Vec4::new(self.settings.occlusion_radius, 0.0, 0.0, 0.0)
+            };
+            unsafe {
+                device.cmd_push_constants(command_buffer, self.pipeline.layout, vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT, 0,
+                    any_as_u8_slice(&push));
+                device.cmd_draw(command_buffer, 6, FLARE_ELEMENTS, 0, 0);
+            }
+        }
+    }
+
+    pub fn destroy(&self, device: &ash::Device) {
+        self.copy_pipeline.cleanup(device);
+        self.pipeline.cleanup(device);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,446 @@
+pub mod ssr;
+pub mod god_rays;
+pub mod motion_blur;
+pub mod dof;
+pub mod fxaa;
+pub mod taa;
+pub mod grading;
+pub mod exposure;
+pub mod analysis;
+pub mod lens_flare;
+
+use ash::vk;
+use gpu_allocator::vulkan::Allocator;
+
+use super::command_pools::Pools;
+use super::descriptors::Descriptors;
+use super::pipeline::{Pipeline, PipelineConfig};
+use super::render_target::RenderTarget;
+use super::renderer::VulkanRenderer;
+use super::swapchain::{VulkanSwapchain, WindowAlpha};
+use super::texture::Texture;
+use super::viewport::ViewportLayout;
+
+use taa::TemporalAa;
+use grading::{CompositeSettings, Lut};
+use exposure::AutoExposure;
+use analysis::ImageAnalysis;
+use lens_flare::{FlareSource, LensFlare, LensFlareSettings};
+
+use crate::utils::any_as_u8_slice;
+
+pub const HDR_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+// Color, view space normal + roughness, motion vectors and object ids, written by every scene pipeline
+pub const SCENE_FORMATS: [vk::Format; 4] = [HDR_FORMAT, vk::Format::R16G16B16A16_SFLOAT, vk::Format::R16G16_SFLOAT, vk::Format::R32_UINT];
+// Holds the object index + 1 of every pixel, 0 where nothing was drawn
+pub const OBJECT_ID_ATTACHMENT: usize = 3;
+
+pub const COMPOSITE_FRAG: &[u32] = vk_shader_macros::include_glsl!("./shaders/composite.frag", kind: frag);
+
+// Mirrors the push constant block of composite.frag, the settings followed by how the frame's alpha is written
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct CompositePush {
+    settings: CompositeSettings,
+    // 0 opaque, 1 premultiplied, 2 straight
+    alpha_mode: u32,
+}
+
+impl CompositePush {
+    fn new(settings: CompositeSettings, alpha: WindowAlpha) -> Self {
+        let alpha_mode = match alpha {
+            WindowAlpha::Opaque => 0,
+            WindowAlpha::Premultiplied => 1,
+            WindowAlpha::Straight => 2
+        };
+        Self { settings, alpha_mode }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AntiAliasing {
+    None,
+    Fxaa,
+    // Jitters the projection and resolves against a history buffer before any other effect
+    Taa,
+}
+
+pub struct PostEffect {
+    pub name: &'static str,
+    pub enabled: bool,
+    pub pipeline: Pipeline,
+    pub push_constants: Vec<u8>,
+}
+
+impl PostEffect {
+    pub fn set_push_constants<T>(&mut self, data: &T) {
+        self.push_constants = unsafe { any_as_u8_slice(data) }.to_vec();
+    }
+}
+
+// The scene is rendered into `scene_target`, optionally resolved by TAA, then every enabled effect runs in order, ping-ponging
+// between two HDR targets, and the composite pass finally writes the result into the swapchain.
+// Every effect reads set 0 (previous color, scene normal + roughness, scene depth, motion) and set 1 (camera).
+// The composite pass additionally tonemaps and grades through the 3D LUT in set 2, which also holds the (auto) exposure.
+pub struct PostProcess {
+    pub scene_target: RenderTarget,
+    // Texel size of the scene target's shading rate image, `None` without one
+    pub shading_rate_texel: Option<vk::Extent2D>,
+    pub targets: [RenderTarget; 2],
+    pub input_set_layout: vk::DescriptorSetLayout,
+    // Reading the scene color, targets[0] and targets[1] respectively
+    pub input_sets: [vk::DescriptorSet; 3],
+    pub camera_set_layout: vk::DescriptorSetLayout,
+    pub composite_pipeline: Pipeline,
+    pub composite_settings: CompositeSettings,
+    pub grading_set_layout: vk::DescriptorSetLayout,
+    pub grading_set: vk::DescriptorSet,
+    pub lut: Texture,
+    // Of the scene color, before any effect ran
+    pub analysis: ImageAnalysis,
+    pub auto_exposure: AutoExposure,
+    pub effects: Vec<PostEffect>,
+    pub taa: Option<TemporalAa>,
+    // Added after the effects, right before compositing
+    pub lens_flare: Option<LensFlare>,
+}
+
+impl PostProcess {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        device: &ash::Device,
+        allocator: &mut Allocator,
+        swapchain: &VulkanSwapchain,
+        present_renderpass: &vk::RenderPass,
+        pools: &Pools,
+        queue: vk::Queue,
+        descriptor_pool: vk::DescriptorPool,
+        camera_set_layout: vk::DescriptorSetLayout,
+        shading_rate_texel: Option<vk::Extent2D>,
+    ) -> Result<Self, vk::Result> {
+        let (scene_target, targets) = Self::create_targets(device, allocator, swapchain.extent, shading_rate_texel)?;
+
+        let input_set_layout = Descriptors::create_layout(device, &[
+            (vk::DescriptorType::COMBINED_IMAGE_SAMPLER, vk::ShaderStageFlags::FRAGMENT),
+            (vk::DescriptorType::COMBINED_IMAGE_SAMPLER, vk::ShaderStageFlags::FRAGMENT),
+            (vk::DescriptorType::COMBINED_IMAGE_SAMPLER, vk::ShaderStageFlags::FRAGMENT),
+            (vk::DescriptorType::COMBINED_IMAGE_SAMPLER, vk::ShaderStageFlags::FRAGMENT),
+        ])?;
+        let input_sets = Descriptors::allocate(device, descriptor_pool, input_set_layout, 3)?;
+        let input_sets = [input_sets[0], input_sets[1], input_sets[2]];
+
+        // The LUT, the exposure and the untouched scene color, whose alpha tells where anything was drawn
+        let grading_set_layout = Descriptors::create_layout(device, &[
+            (vk::DescriptorType::COMBINED_IMAGE_SAMPLER, vk::ShaderStageFlags::FRAGMENT),
+            (vk::DescriptorType::STORAGE_BUFFER, vk::ShaderStageFlags::FRAGMENT),
+            (vk::DescriptorType::COMBINED_IMAGE_SAMPLER, vk::ShaderStageFlags::FRAGMENT),
+        ])?;
+        let grading_set = Descriptors::allocate(device, descriptor_pool, grading_set_layout, 1)?[0];
+        let identity = Lut::identity(2);
+        let lut = Texture::from_rgba32f_3d(device, allocator, pools, queue, identity.size, &identity.data, "Identity LUT")?;
+        Descriptors::write_image(device, grading_set, 0, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, lut.descriptor_info());
+
+        let analysis = ImageAnalysis::new(device, allocator, descriptor_pool, swapchain.image_count, scene_target.descriptor_info(0))?;
+        let auto_exposure = AutoExposure::new(device, allocator, descriptor_pool, &analysis)?;
+        Descriptors::write_buffer(device, grading_set, 1, vk::DescriptorType::STORAGE_BUFFER, auto_exposure.exposure_descriptor_info());
+
+        let set_layouts = [input_set_layout, camera_set_layout, grading_set_layout];
+        let composite_pipeline = Pipeline::new(device, swapchain, present_renderpass,
+            &PipelineConfig::fullscreen(COMPOSITE_FRAG, &set_layouts, std::mem::size_of::<CompositePush>() as u32))?;
+
+        let post_process = Self {
+            scene_target,
+            shading_rate_texel,
+            targets,
+            input_set_layout,
+            input_sets,
+            camera_set_layout,
+            composite_pipeline,
+            composite_settings: CompositeSettings::default(),
+            grading_set_layout,
+            grading_set,
+            lut,
+            analysis,
+            auto_exposure,
+            effects: vec![],
+            taa: None,
+            lens_flare: None
+        };
+        post_process.write_input_sets(device);
+
+        Ok(post_process)
+    }
+
+    fn create_targets(device: &ash::Device, allocator: &mut Allocator, extent: vk::Extent2D, shading_rate_texel: Option<vk::Extent2D>
+    ) -> Result<(RenderTarget, [RenderTarget; 2]), vk::Result> {
+        let scene_target = RenderTarget::with_shading_rate(device, allocator, extent, &SCENE_FORMATS, true, shading_rate_texel, "Scene Target")?;
+        let targets = [
+            RenderTarget::new(device, allocator, extent, &[HDR_FORMAT], false, "Post Target 0")?,
+            RenderTarget::new(device, allocator, extent, &[HDR_FORMAT], false, "Post Target 1")?,
+        ];
+
+        Ok((scene_target, targets))
+    }
+
+    fn write_input_sets(&self, device: &ash::Device) {
+        let colors = [
+            self.scene_target.descriptor_info(0),
+            self.targets[0].descriptor_info(0),
+            self.targets[1].descriptor_info(0),
+        ];
+
+        for (set, color) in self.input_sets.iter().zip(colors) {
+            self.write_input_set(device, *set, color);
+        }
+        self.analysis.write_image(device, self.scene_target.descriptor_info(0));
+        Descriptors::write_image(device, self.grading_set, 2, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, self.scene_target.descriptor_info(0));
+
+        if let Some(taa) = &self.taa {
+            for (set, target) in taa.input_sets.iter().zip(&taa.history) {
+                self.write_input_set(device, *set, target.descriptor_info(0));
+            }
+        }
+    }
+
+    fn write_input_set(&self, device: &ash::Device, set: vk::DescriptorSet, color: vk::DescriptorImageInfo) {
+        Descriptors::write_image(device, set, 0, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, color);
+        Descriptors::write_image(device, set, 1, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, self.scene_target.descriptor_info(1));
+        Descriptors::write_image(device, set, 2, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, self.scene_target.depth_descriptor_info());
+        Descriptors::write_image(device, set, 3, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, self.scene_target.descriptor_info(2));
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn enable_taa(&mut self, device: &ash::Device, allocator: &mut Allocator, swapchain: &VulkanSwapchain, pools: &Pools, queue: vk::Queue,
+        descriptor_pool: vk::DescriptorPool, frame_index: u64
+    ) -> Result<(), vk::Result> {
+        if self.taa.is_some() {
+            return Ok(());
+        }
+
+        self.taa = Some(TemporalAa::new(device, allocator, swapchain, self.scene_target.extent, pools, queue, descriptor_pool,
+            self.input_set_layout, self.camera_set_layout, frame_index)?);
+        self.write_input_sets(device);
+
+        Ok(())
+    }
+
+    // The TAA descriptor sets go back to the pool with it
+    pub fn disable_taa(&mut self, device: &ash::Device, allocator: &mut Allocator, descriptor_pool: vk::DescriptorPool) -> Result<(), vk::Result> {
+        if let Some(mut taa) = self.taa.take() {
+            unsafe {
+                device.device_wait_idle()?;
+                device.free_descriptor_sets(descriptor_pool, &taa.history_sets)?;
+                device.free_descriptor_sets(descriptor_pool, &taa.input_sets)?;
+            }
+            taa.destroy(device, allocator);
+        }
+
+        Ok(())
+    }
+
+    pub fn enable_lens_flare(&mut self, device: &ash::Device, swapchain: &VulkanSwapchain, settings: LensFlareSettings) -> Result<(), vk::Result> {
+        match &mut self.lens_flare {
+            Some(lens_flare) => lens_flare.settings = settings,
+            None => {
+                let set_layouts = [self.input_set_layout, self.camera_set_layout];
+                self.lens_flare = Some(LensFlare::new(device, swapchain, &self.targets[0].renderpass, &set_layouts, settings)?);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn disable_lens_flare(&mut self, device: &ash::Device) -> Result<(), vk::Result> {
+        if let Some(lens_flare) = self.lens_flare.take() {
+            unsafe { device.device_wait_idle()? };
+            lens_flare.destroy(device);
+        }
+        Ok(())
+    }
+
+    // Waits for the device since the previous LUT may still be sampled by frames in flight
+    pub fn set_lut(&mut self, device: &ash::Device, allocator: &mut Allocator, pools: &Pools, queue: vk::Queue, lut: &Lut) -> Result<(), vk::Result> {
+        let texture = Texture::from_rgba32f_3d(device, allocator, pools, queue, lut.size, &lut.data, "Grading LUT")?;
+
+        unsafe { device.device_wait_idle()? };
+        let mut previous = std::mem::replace(&mut self.lut, texture);
+        previous.destroy(device, allocator);
+        Descriptors::write_image(device, self.grading_set, 0, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, self.lut.descriptor_info());
+
+        Ok(())
+    }
+
+    pub fn add_effect(&mut self, device: &ash::Device, swapchain: &VulkanSwapchain, name: &'static str, fragment_shader: &[u32], push_constant_size: u32) -> Result<&mut PostEffect, vk::Result> {
+        let set_layouts = [self.input_set_layout, self.camera_set_layout];
+        let pipeline = Pipeline::new(device, swapchain, &self.targets[0].renderpass,
+            &PipelineConfig::fullscreen(fragment_shader, &set_layouts, push_constant_size))?;
+
+        self.effects.push(PostEffect {
+            name,
+            enabled: true,
+            pipeline,
+            push_constants: vec![]
+        });
+
+        Ok(self.effects.last_mut().unwrap())
+    }
+
+    pub fn effect_mut(&mut self, name: &str) -> Option<&mut PostEffect> {
+        self.effects.iter_mut().find(|effect| effect.name == name)
+    }
+
+    // Auto exposure needs the analysis too. Screenshot tiles keep the exposure the view adapted to, each adapting to its
+    // own part would show their seams.
+    fn analyzes_scene(&self, capturing: bool) -> bool {
+        (self.analysis.enabled || self.auto_exposure.enabled) && !capturing
+    }
+
+    // Called once the frame that last used image `index` is done
+    pub fn update(&mut self, index: usize, capturing: bool) {
+        let recorded = self.analyzes_scene(capturing);
+        self.analysis.collect(index, recorded);
+    }
+
+    // Leaves the present render pass open so overlays can still be drawn into the swapchain image, the caller ends it.
+    // A screenshot `capture` target gets the composited frame too, without the bars and overlays and always opaque.
+    // With a blending `alpha` the swapchain image takes the scene's alpha, see `WindowStyle::transparent`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn record(&self, device: &ash::Device, command_buffer: vk::CommandBuffer, index: usize, camera_set: vk::DescriptorSet,
+        present_renderpass: vk::RenderPass, framebuffer: vk::Framebuffer, viewport: &ViewportLayout, frame_index: u64,
+        flare_sources: &[FlareSource], capture: Option<&RenderTarget>, alpha: WindowAlpha
+    ) {
+        let extent = viewport.render_extent;
+        if self.analyzes_scene(capture.is_some()) {
+            self.analysis.record(device, command_buffer, index, extent);
+            self.auto_exposure.record(device, command_buffer, &self.analysis, index, extent, frame_index);
+        }
+
+        let mut input = self.input_sets[0];
+        let mut output = 0;
+
+        if let Some(taa) = &self.taa {
+            let write = TemporalAa::write_index(frame_index);
+            let target = &taa.history[write];
+            let push = taa.push_constants(frame_index);
+            Self::draw_fullscreen(device, command_buffer, target.renderpass, target.framebuffer, extent,
+                &taa.pipeline, &[input, camera_set, taa.history_sets[1 - write]], unsafe { any_as_u8_slice(&push) });
+
+            input = taa.input_sets[write];
+        }
+
+        for effect in self.effects.iter().filter(|effect| effect.enabled) {
+            let target = &self.targets[output];
+            Self::draw_fullscreen(device, command_buffer, target.renderpass, target.framebuffer, extent,
+                &effect.pipeline, &[input, camera_set], &effect.push_constants);
+
+            input = self.input_sets[output + 1];
+            output ^= 1;
+        }
+
+        if let Some(lens_flare) = self.lens_flare.as_ref().filter(|_| !flare_sources.is_empty()) {
+            let target = &self.targets[output];
+            let rect = vk::Rect2D { offset: vk::Offset2D { x: 0, y: 0 }, extent };
+            Self::begin_fullscreen(device, command_buffer, target.renderpass, target.framebuffer, extent, rect, lens_flare.copy_pipeline(),
+                &[input, camera_set], &[]);
+            lens_flare.record(device, command_buffer, flare_sources);
+            unsafe { device.cmd_end_render_pass(command_buffer) };
+
+            input = self.input_sets[output + 1];
+        }
+
+        if let Some(target) = capture {
+            let push = CompositePush::new(self.composite_settings, WindowAlpha::Opaque);
+            Self::draw_fullscreen(device, command_buffer, target.renderpass, target.framebuffer, extent,
+                &self.composite_pipeline, &[input, camera_set, self.grading_set], unsafe { any_as_u8_slice(&push) });
+        }
+
+        // The render area is the whole swapchain image so the bars around the viewport are cleared
+        let push = CompositePush::new(self.composite_settings, alpha);
+        Self::begin_fullscreen(device, command_buffer, present_renderpass, framebuffer, viewport.window_extent, viewport.rect,
+            &self.composite_pipeline, &[input, camera_set, self.grading_set], unsafe { any_as_u8_slice(&push) });
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn draw_fullscreen(device: &ash::Device, command_buffer: vk::CommandBuffer, renderpass: vk::RenderPass, framebuffer: vk::Framebuffer,
+        extent: vk::Extent2D, pipeline: &Pipeline, sets: &[vk::DescriptorSet], push_constants: &[u8]
+    ) {
+        let rect = vk::Rect2D { offset: vk::Offset2D { x: 0, y: 0 }, extent };
+        Self::begin_fullscreen(device, command_buffer, renderpass, framebuffer, extent, rect, pipeline, sets, push_constants);
+        unsafe { device.cmd_end_render_pass(command_buffer) };
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn begin_fullscreen(device: &ash::Device, command_buffer: vk::CommandBuffer, renderpass: vk::RenderPass, framebuffer: vk::Framebuffer,
+        extent: vk::Extent2D, viewport: vk::Rect2D, pipeline: &Pipeline, sets: &[vk::DescriptorSet], push_constants: &[u8]
+    ) {
+        // Transparent, the bars of a transparent window show what's behind it. Opaque swapchains ignore the alpha.
+        let clear_values = [vk::ClearValue {
+            color: vk::ClearColorValue {
+                float32: [0.0, 0.0, 0.0, 0.0]
+            }
+        }];
+
+        let renderpass_begininfo = vk::RenderPassBeginInfo::builder()
+            .render_pass(renderpass)
+            .framebuffer(framebuffer)
+            .render_area(vk::Rect2D {
+                offset: vk::Offset2D { x: 0, y: 0 },
+                extent
+            })
+            .clear_values(&clear_values);
+
+        unsafe {
+            device.cmd_begin_render_pass(command_buffer, &renderpass_begininfo, vk::SubpassContents::INLINE);
+            VulkanRenderer::set_viewport_rect(device, command_buffer, viewport);
+
+            device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, pipeline.pipeline);
+            device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::GRAPHICS, pipeline.layout, 0, sets, &[]);
+            if !push_constants.is_empty() {
+                device.cmd_push_constants(command_buffer, pipeline.layout, vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT, 0, push_constants);
+            }
+            device.cmd_draw(command_buffer, 3, 1, 0, 0);
+        }
+    }
+
+    // Pipelines only need a compatible render pass, so they survive the targets being recreated
+    pub fn recreate(&mut self, device: &ash::Device, allocator: &mut Allocator, extent: vk::Extent2D, pools: &Pools, queue: vk::Queue, frame_index: u64) -> Result<(), vk::Result> {
+        self.destroy_targets(device, allocator);
+
+        let (scene_target, targets) = Self::create_targets(device, allocator, extent, self.shading_rate_texel)?;
+        self.scene_target = scene_target;
+        self.targets = targets;
+        if let Some(taa) = &mut self.taa {
+            taa.recreate(device, allocator, extent, pools, queue, frame_index)?;
+        }
+        self.write_input_sets(device);
+
+        Ok(())
+    }
+
+    fn destroy_targets(&mut self, device: &ash::Device, allocator: &mut Allocator) {
+        self.scene_target.destroy(device, allocator);
+        for target in &mut self.targets {
+            target.destroy(device, allocator);
+        }
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device, allocator: &mut Allocator) {
+        for effect in &self.effects {
+            effect.pipeline.cleanup(device);
+        }
+        self.composite_pipeline.cleanup(device);
+        if let Some(taa) = &mut self.taa {
+            taa.destroy(device, allocator);
+        }
+        if let Some(lens_flare) = &self.lens_flare {
+            lens_flare.destroy(device);
+        }
+        self.lut.destroy(device, allocator);
+        self.auto_exposure.destroy(device, allocator);
+        self.analysis.destroy(device, allocator);
+        unsafe {
+            device.destroy_descriptor_set_layout(self.input_set_layout, None);
+            device.destroy_descriptor_set_layout(self.grading_set_layout, None);
+        }
+        self.destroy_targets(device, allocator);
+    }
+}
\ No newline at end of file
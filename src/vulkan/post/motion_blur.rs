@@ -0,0 +1,26 @@
+pub const MOTION_BLUR_FRAG: &[u32] = vk_shader_macros::include_glsl!("./shaders/motion_blur.frag", kind: frag);
+
+// Mirrors the push constant block of motion_blur.frag
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct MotionBlurSettings {
+    // Fraction of the frame the virtual shutter stays open, scales the blur length
+    pub shutter: f32,
+    pub samples: i32,
+    // Longest blur in uv units, keeps fast objects from smearing across the screen
+    pub max_length: f32,
+}
+
+impl MotionBlurSettings {
+    pub const NAME: &'static str = "motion_blur";
+}
+
+impl Default for MotionBlurSettings {
+    fn default() -> Self {
+        Self {
+            shutter: 0.5,
+            samples: 12,
+            max_length: 0.05,
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,35 @@
+pub const SSR_FRAG: &[u32] = vk_shader_macros::include_glsl!("./shaders/ssr.frag", kind: frag);
+
+// Mirrors the push constant block of ssr.frag
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct SsrSettings {
+    // Sky gradient reflected wherever the ray misses the depth buffer
+    pub environment_top: uv::Vec4,
+    pub environment_bottom: uv::Vec4,
+    pub max_distance: f32,
+    pub thickness: f32,
+    pub max_steps: i32,
+    // Blur radius in pixels at roughness 1.0
+    pub blur_radius: f32,
+    // Surfaces at or above this roughness don't get screen space reflections
+    pub max_roughness: f32,
+}
+
+impl SsrSettings {
+    pub const NAME: &'static str = "ssr";
+}
+
+impl Default for SsrSettings {
+    fn default() -> Self {
+        Self {
+            environment_top: uv::Vec4::new(0.35, 0.55, 0.85, 1.0),
+            environment_bottom: uv::Vec4::new(0.15, 0.15, 0.15, 1.0),
+            max_distance: 8.0,
+            thickness: 0.2,
+            max_steps: 64,
+            blur_radius: 12.0,
+            max_roughness: 0.8,
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,145 @@
+use ash::vk;
+use gpu_allocator::vulkan::Allocator;
+
+use crate::vulkan::command_pools::Pools;
+use crate::vulkan::descriptors::Descriptors;
+use crate::vulkan::pipeline::{Pipeline, PipelineConfig};
+use crate::vulkan::render_target::RenderTarget;
+use crate::vulkan::swapchain::VulkanSwapchain;
+use super::HDR_FORMAT;
+
+pub const TAA_FRAG: &[u32] = vk_shader_macros::include_glsl!("./shaders/taa.frag", kind: frag);
+
+// Sub-pixel offsets cycled through by the projection, Halton (2, 3) centered on the pixel
+pub const JITTER_SEQUENCE: [(f32, f32); 8] = [
+    (0.0, -0.166_666_67),
+    (-0.25, 0.166_666_67),
+    (0.25, -0.388_888_9),
+    (-0.375, -0.055_555_556),
+    (0.125, 0.277_777_8),
+    (-0.125, -0.277_777_8),
+    (0.375, 0.055_555_556),
+    (-0.4375, 0.388_888_9),
+];
+
+// Mirrors the push constant block of taa.frag
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct TaaPushConstants {
+    pub blend: f32,
+    pub reset: u32,
+}
+
+// Resolves the jittered scene against the reprojected, neighborhood clamped history. The output is
+// written into one history target and read back through the other one next frame.
+pub struct TemporalAa {
+    pub history: [RenderTarget; 2],
+    pub pipeline: Pipeline,
+    pub history_set_layout: vk::DescriptorSetLayout,
+    // Samples history[i], bound at set 2 of the resolve pass
+    pub history_sets: [vk::DescriptorSet; 2],
+    // Post input sets with history[i] as color, read by the effects after the resolve
+    pub input_sets: [vk::DescriptorSet; 2],
+    // Weight of the current frame, lower is smoother but ghosts more
+    pub blend: f32,
+    reset_frame: u64,
+}
+
+impl TemporalAa {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        device: &ash::Device,
+        allocator: &mut Allocator,
+        swapchain: &VulkanSwapchain,
+        extent: vk::Extent2D,
+        pools: &Pools,
+        queue: vk::Queue,
+        descriptor_pool: vk::DescriptorPool,
+        input_set_layout: vk::DescriptorSetLayout,
+        camera_set_layout: vk::DescriptorSetLayout,
+        frame_index: u64,
+    ) -> Result<Self, vk::Result> {
+        let history = Self::create_history(device, allocator, extent, pools, queue)?;
+
+        let history_set_layout = Descriptors::create_layout(device, &[
+            (vk::DescriptorType::COMBINED_IMAGE_SAMPLER, vk::ShaderStageFlags::FRAGMENT)
+        ])?;
+        let history_sets = Descriptors::allocate(device, descriptor_pool, history_set_layout, 2)?;
+        let input_sets = Descriptors::allocate(device, descriptor_pool, input_set_layout, 2)?;
+
+        let set_layouts = [input_set_layout, camera_set_layout, history_set_layout];
+        let pipeline = Pipeline::new(device, swapchain, &history[0].renderpass,
+            &PipelineConfig::fullscreen(TAA_FRAG, &set_layouts, std::mem::size_of::<TaaPushConstants>() as u32))?;
+
+        let taa = Self {
+            history,
+            pipeline,
+            history_set_layout,
+            history_sets: [history_sets[0], history_sets[1]],
+            input_sets: [input_sets[0], input_sets[1]],
+            blend: 0.1,
+            reset_frame: frame_index
+        };
+        taa.write_history_sets(device);
+
+        Ok(taa)
+    }
+
+    // History targets start out ready for sampling, the first resolve ignores their contents
+    fn create_history(device: &ash::Device, allocator: &mut Allocator, extent: vk::Extent2D, pools: &Pools, queue: vk::Queue) -> Result<[RenderTarget; 2], vk::Result> {
+        let history = [
+            RenderTarget::new(device, allocator, extent, &[HDR_FORMAT], false, "TAA History 0")?,
+            RenderTarget::new(device, allocator, extent, &[HDR_FORMAT], false, "TAA History 1")?,
+        ];
+
+        pools.one_time_submit(device, queue, |command_buffer| {
+            for target in &history {
+                target.colors[0].transition_layout(device, command_buffer, vk::ImageLayout::UNDEFINED, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+            }
+        })?;
+
+        Ok(history)
+    }
+
+    fn write_history_sets(&self, device: &ash::Device) {
+        for (set, target) in self.history_sets.iter().zip(&self.history) {
+            Descriptors::write_image(device, *set, 0, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, target.descriptor_info(0));
+        }
+    }
+
+    // History written this frame, the other one holds last frame's result
+    pub fn write_index(frame_index: u64) -> usize {
+        (frame_index % 2) as usize
+    }
+
+    pub fn push_constants(&self, frame_index: u64) -> TaaPushConstants {
+        TaaPushConstants {
+            blend: self.blend,
+            reset: (frame_index == self.reset_frame) as u32
+        }
+    }
+
+    // Starts over from the frame drawn at `frame_index`, for when the last frames show something else
+    pub fn reset_history(&mut self, frame_index: u64) {
+        self.reset_frame = frame_index;
+    }
+
+    pub fn recreate(&mut self, device: &ash::Device, allocator: &mut Allocator, extent: vk::Extent2D, pools: &Pools, queue: vk::Queue, frame_index: u64) -> Result<(), vk::Result> {
+        for target in &mut self.history {
+            target.destroy(device, allocator);
+        }
+        self.history = Self::create_history(device, allocator, extent, pools, queue)?;
+        self.write_history_sets(device);
+        self.reset_frame = frame_index;
+
+        Ok(())
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device, allocator: &mut Allocator) {
+        self.pipeline.cleanup(device);
+        unsafe { device.destroy_descriptor_set_layout(self.history_set_layout, None) };
+        for target in &mut self.history {
+            target.destroy(device, allocator);
+        }
+    }
+}
\ No newline at end of file
@@ -7,7 +7,8 @@ pub struct QueueFamilies {
 }
 
 impl QueueFamilies {
-    pub fn new(instance: &ash::Instance, physical_device: vk::PhysicalDevice, surface: &VulkanSurface) -> Result<QueueFamilies, vk::Result> {
+    // Without a surface any graphics queue will do, nothing gets presented
+    pub fn new(instance: &ash::Instance, physical_device: vk::PhysicalDevice, surface: Option<&VulkanSurface>) -> Result<QueueFamilies, vk::Result> {
         let mut queue_families = QueueFamilies {
             graphics: None,
             transfer: None,
@@ -19,7 +20,7 @@ impl QueueFamilies {
         
         for (index, queue_family) in queue_family_properties.iter().enumerate() {
             if queue_family.queue_count > 0 && queue_family.queue_flags.contains(vk::QueueFlags::GRAPHICS) &&
-                unsafe { surface.surface_loader.get_physical_device_surface_support(physical_device, index as u32, surface.surface).unwrap() } {
+                surface.map_or(Ok(true), |surface| surface.get_physical_device_surface_support(physical_device, index))? {
                     found_graphics_queue_index = Some(index as u32);
                 }
             if queue_family.queue_count > 0 && queue_family.queue_flags.contains(vk::QueueFlags::TRANSFER) {
@@ -0,0 +1,164 @@
+use ash::vk;
+use gpu_allocator::vulkan::Allocator;
+
+use super::camera::{Camera, CameraUniform};
+use super::descriptors::Descriptors;
+use super::pipeline::{Pipeline, PipelineConfig, BASIC_VERT};
+use super::post::SCENE_FORMATS;
+use super::render_target::RenderTarget;
+use super::swapchain::VulkanSwapchain;
+use super::uniform_buffer::UniformBuffer;
+
+pub const REFLECTIVE_FRAG: &[u32] = vk_shader_macros::include_glsl!("./shaders/reflective.frag", kind: frag);
+
+// Plane in the form dot(normal, p) + distance = 0, with the normal facing the reflected side
+#[derive(Clone, Copy, Debug)]
+pub struct ReflectionPlane {
+    pub normal: uv::Vec3,
+    pub distance: f32,
+}
+
+impl ReflectionPlane {
+    pub fn new(normal: uv::Vec3, point: uv::Vec3) -> Self {
+        let normal = normal.normalized();
+        Self {
+            normal,
+            distance: -normal.dot(point)
+        }
+    }
+
+    pub fn reflection_matrix(&self) -> uv::Mat4 {
+        let n = self.normal;
+        let d = self.distance;
+
+        uv::Mat4::new(
+            uv::Vec4::new(1.0 - 2.0 * n.x * n.x, -2.0 * n.x * n.y, -2.0 * n.x * n.z, 0.0),
+            uv::Vec4::new(-2.0 * n.y * n.x, 1.0 - 2.0 * n.y * n.y, -2.0 * n.y * n.z, 0.0),
+            uv::Vec4::new(-2.0 * n.z * n.x, -2.0 * n.z * n.y, 1.0 - 2.0 * n.z * n.z, 0.0),
+            uv::Vec4::new(-2.0 * d * n.x, -2.0 * d * n.y, -2.0 * d * n.z, 1.0),
+        )
+    }
+
+    pub fn as_vec4(&self) -> uv::Vec4 {
+        uv::Vec4::new(self.normal.x, self.normal.y, self.normal.z, self.distance)
+    }
+}
+
+pub struct PlanarReflection {
+    pub plane: ReflectionPlane,
+    // Small offset pushing the clip plane below the surface to hide seams where geometry touches it
+    pub clip_offset: f32,
+    pub target: RenderTarget,
+    pub scene_pipeline: Pipeline,
+    pub surface_pipeline: Pipeline,
+    pub camera_buffers: Vec<UniformBuffer<CameraUniform>>,
+    pub camera_sets: Vec<vk::DescriptorSet>,
+}
+
+impl PlanarReflection {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        device: &ash::Device,
+        allocator: &mut Allocator,
+        swapchain: &VulkanSwapchain,
+        extent: vk::Extent2D,
+        renderpass: &vk::RenderPass,
+        descriptor_pool: vk::DescriptorPool,
+        scene_set_layouts: &[vk::DescriptorSetLayout],
+        plane: ReflectionPlane,
+    ) -> Result<Self, vk::Result> {
+        let target = RenderTarget::new(device, allocator, extent, &SCENE_FORMATS, true, "Planar Reflection")?;
+
+        let (scene_pipeline, surface_pipeline) = Self::create_pipelines(device, swapchain, renderpass, &target, scene_set_layouts)?;
+
+        let camera_sets = Descriptors::allocate(device, descriptor_pool, scene_set_layouts[0], swapchain.image_count)?;
+        let mut camera_buffers = Vec::with_capacity(swapchain.image_count);
+        for set in &camera_sets {
+            let camera_buffer = UniformBuffer::<CameraUniform>::new(device, allocator);
+            Descriptors::write_buffer(device, *set, 0, vk::DescriptorType::UNIFORM_BUFFER, camera_buffer.descriptor_info());
+            camera_buffers.push(camera_buffer);
+        }
+
+        Ok(Self {
+            plane,
+            clip_offset: 0.01,
+            target,
+            scene_pipeline,
+            surface_pipeline,
+            camera_buffers,
+            camera_sets
+        })
+    }
+
+    fn create_pipelines(
+        device: &ash::Device,
+        swapchain: &VulkanSwapchain,
+        renderpass: &vk::RenderPass,
+        target: &RenderTarget,
+        scene_set_layouts: &[vk::DescriptorSetLayout],
+    ) -> Result<(Pipeline, Pipeline), vk::Result> {
+        // Mirroring the view flips triangle winding, so the reflected scene is drawn with the opposite front face
+        let mut scene_config = PipelineConfig::basic(scene_set_layouts);
+        scene_config.front_face = vk::FrontFace::COUNTER_CLOCKWISE;
+        let scene_pipeline = Pipeline::new(device, swapchain, &target.renderpass, &scene_config)?;
+
+        // The surface samples the reflection through the material texture, the renderer points reflective materials at it
+        let surface_config = PipelineConfig {
+            vertex_shader: BASIC_VERT,
+            fragment_shader: REFLECTIVE_FRAG,
+            ..PipelineConfig::basic(scene_set_layouts)
+        };
+        let surface_pipeline = Pipeline::new(device, swapchain, renderpass, &surface_config)?;
+
+        Ok((scene_pipeline, surface_pipeline))
+    }
+
+    pub fn recreate(
+        &mut self,
+        device: &ash::Device,
+        allocator: &mut Allocator,
+        swapchain: &VulkanSwapchain,
+        extent: vk::Extent2D,
+        renderpass: &vk::RenderPass,
+        scene_set_layouts: &[vk::DescriptorSetLayout],
+    ) -> Result<(), vk::Result> {
+        self.scene_pipeline.cleanup(device);
+        self.surface_pipeline.cleanup(device);
+        self.target.destroy(device, allocator);
+
+        self.target = RenderTarget::new(device, allocator, extent, &SCENE_FORMATS, true, "Planar Reflection")?;
+        let (scene_pipeline, surface_pipeline) = Self::create_pipelines(device, swapchain, renderpass, &self.target, scene_set_layouts)?;
+        self.scene_pipeline = scene_pipeline;
+        self.surface_pipeline = surface_pipeline;
+
+        Ok(())
+    }
+
+    pub fn update(&mut self, index: usize, camera: &Camera) {
+        let uniform = self.uniform(camera, self.target.extent);
+        self.camera_buffers[index].update_buffer(&uniform);
+    }
+
+    // Camera mirrored at the plane, `extent` is the size of the view it renders
+    pub fn uniform(&self, camera: &Camera, extent: vk::Extent2D) -> CameraUniform {
+        let reflection = self.plane.reflection_matrix();
+        let position = reflection.transform_point3(camera.position);
+
+        let mut uniform = camera.uniform(extent);
+        uniform.view = camera.view_matrix() * reflection;
+        uniform.position = position.into_homogeneous_point();
+        uniform.clip_plane = self.plane.as_vec4() + uv::Vec4::new(0.0, 0.0, 0.0, self.clip_offset);
+        // Lights are only clustered for the unmirrored camera
+        uniform.near_far.z = 1.0;
+        uniform
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device, allocator: &mut Allocator) {
+        for camera_buffer in &mut self.camera_buffers {
+            camera_buffer.destroy(device, allocator);
+        }
+        self.scene_pipeline.cleanup(device);
+        self.surface_pipeline.cleanup(device);
+        self.target.destroy(device, allocator);
+    }
+}
\ No newline at end of file
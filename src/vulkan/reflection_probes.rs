@@ -0,0 +1,338 @@
+use ash::vk;
+use gpu_allocator::vulkan::Allocator;
+use gpu_allocator::MemoryLocation;
+
+use super::command_pools::Pools;
+use super::compute_pipeline::ComputePipeline;
+use super::cubemap::{CaptureMode, CubemapCapture};
+use super::descriptor_allocator::DescriptorAllocator;
+use super::descriptors::Descriptors;
+use super::image::{Image, create_sampler};
+use super::post::HDR_FORMAT;
+use super::storage_buffer::StorageBuffer;
+use super::swapchain::VulkanSwapchain;
+
+use crate::utils::any_as_u8_slice;
+
+pub const PROBE_PREFILTER_COMP: &[u32] = vk_shader_macros::include_glsl!("./shaders/probe_prefilter.comp", kind: comp);
+
+// Must match PROBE_ROUGHNESS_LEVELS in shaders/include/reflection_probes.glsl
+pub const PROBE_ROUGHNESS_LEVELS: u32 = 5;
+pub const MAX_REFLECTION_PROBES: usize = 8;
+// Face size of the prefiltered cubes, the scene is captured at twice that
+pub const PROBE_SIZE: u32 = 64;
+const CAPTURE_SIZE: u32 = 128;
+const PREFILTER_SAMPLES: u32 = 64;
+const PREFILTER_GROUP_SIZE: u32 = 8;
+
+// Must match the PROBE_SHAPE_* constants in shaders/include/reflection_probes.glsl
+const PROBE_SHAPE_SPHERE: u32 = 0;
+const PROBE_SHAPE_BOX: u32 = 1;
+
+#[derive(Clone, Copy, Debug)]
+pub enum ProbeShape {
+    Sphere { radius: f32 },
+    // Axis aligned, reflections are projected onto the box so they line up with the walls of a room
+    Box { half_extents: uv::Vec3 },
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ReflectionProbe {
+    pub position: uv::Vec3,
+    pub shape: ProbeShape,
+    // Distance inside the volume over which the probe fades out towards its border, overlapping probes blend there
+    pub blend_distance: f32,
+}
+
+impl ReflectionProbe {
+    pub fn sphere(position: uv::Vec3, radius: f32) -> Self {
+        Self {
+            position,
+            shape: ProbeShape::Sphere { radius },
+            blend_distance: radius * 0.25
+        }
+    }
+
+    pub fn room(position: uv::Vec3, half_extents: uv::Vec3) -> Self {
+        Self {
+            position,
+            shape: ProbeShape::Box { half_extents },
+            blend_distance: half_extents.component_min() * 0.25
+        }
+    }
+
+    fn as_gpu_probe(&self, layer: u32) -> GpuReflectionProbe {
+        let (extents, shape) = match self.shape {
+            ProbeShape::Sphere { radius } => (uv::Vec3::broadcast(radius), PROBE_SHAPE_SPHERE),
+            ProbeShape::Box { half_extents } => (half_extents, PROBE_SHAPE_BOX),
+        };
+
+        GpuReflectionProbe {
+            position_blend: uv::Vec4::new(self.position.x, self.position.y, self.position.z, self.blend_distance),
+            extents: extents.into_homogeneous_vector(),
+            params: [layer, shape, 0, 0]
+        }
+    }
+}
+
+// Must match ReflectionProbe in shaders/include/reflection_probes.glsl
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct GpuReflectionProbe {
+    position_blend: uv::Vec4,
+    extents: uv::Vec4,
+    // probe slot in the cube array, shape
+    params: [u32; 4],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct ProbeBufferHeader {
+    count: u32,
+    _padding: [u32; 3],
+}
+
+#[repr(C)]
+struct PrefilterPushConstants {
+    _first_layer: u32,
+    _size: u32,
+    _roughness: f32,
+    _sample_count: u32,
+}
+
+struct ProbeEntry {
+    probe: ReflectionProbe,
+    // Slot in the cube array, kept for the probe's lifetime so removing another probe doesn't move it
+    slot: u32,
+    capture: CubemapCapture,
+    prefilter_set: vk::DescriptorSet,
+    baked: bool,
+}
+
+// Local reflections: every probe captures the scene around it once (and again on `bake`) and convolves it into
+// PROBE_ROUGHNESS_LEVELS cubes of increasing roughness. The scene shader blends the probes whose volume it's in.
+pub struct ReflectionProbes {
+    entries: Vec<ProbeEntry>,
+    // MAX_REFLECTION_PROBES * PROBE_ROUGHNESS_LEVELS cubes, the levels of a probe follow each other
+    image: Image,
+    // Same layers as a 2D array for the prefilter writes
+    storage_view: vk::ImageView,
+    sampler: vk::Sampler,
+    prefilter_set_layout: vk::DescriptorSetLayout,
+    prefilter_pipeline: ComputePipeline,
+    buffers: Vec<StorageBuffer>,
+}
+
+impl ReflectionProbes {
+    pub fn new(device: &ash::Device, allocator: &mut Allocator, pools: &Pools, queue: vk::Queue, image_count: usize) -> Result<Self, vk::Result> {
+        let extent = vk::Extent2D { width: PROBE_SIZE, height: PROBE_SIZE };
+        let layers = MAX_REFLECTION_PROBES as u32 * PROBE_ROUGHNESS_LEVELS * 6;
+        let image = Image::new_layered(device, allocator, extent, HDR_FORMAT,
+            vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::TRANSFER_DST, vk::ImageAspectFlags::COLOR,
+            layers, vk::ImageViewType::CUBE_ARRAY, "Reflection Probes")?;
+        let storage_view = image.create_layers_view(device, vk::ImageViewType::TYPE_2D_ARRAY, 0, layers)?;
+
+        // Unbaked slots are black, so they add nothing even before a probe is baked into them
+        pools.one_time_submit(device, queue, |command_buffer| {
+            image.transition_layout(device, command_buffer, vk::ImageLayout::UNDEFINED, vk::ImageLayout::TRANSFER_DST_OPTIMAL);
+            let clear_color = vk::ClearColorValue { float32: [0.0; 4] };
+            unsafe {
+                device.cmd_clear_color_image(command_buffer, image.image, vk::ImageLayout::TRANSFER_DST_OPTIMAL, &clear_color, &[image.subresource_range()]);
+            }
+            image.transition_layout(device, command_buffer, vk::ImageLayout::TRANSFER_DST_OPTIMAL, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+        })?;
+
+        let prefilter_set_layout = Descriptors::create_layout(device, &[
+            (vk::DescriptorType::COMBINED_IMAGE_SAMPLER, vk::ShaderStageFlags::COMPUTE),
+            (vk::DescriptorType::STORAGE_IMAGE, vk::ShaderStageFlags::COMPUTE),
+        ])?;
+        let prefilter_pipeline = ComputePipeline::new(device, PROBE_PREFILTER_COMP, &[prefilter_set_layout],
+            std::mem::size_of::<PrefilterPushConstants>() as u32, &[])?;
+
+        let buffer_size = (std::mem::size_of::<ProbeBufferHeader>() + MAX_REFLECTION_PROBES * std::mem::size_of::<GpuReflectionProbe>()) as u64;
+        let buffers = (0..image_count)
+            .map(|_| StorageBuffer::new(device, allocator, buffer_size, MemoryLocation::CpuToGpu, "Reflection Probe Buffer"))
+            .collect();
+
+        let sampler = create_sampler(device, vk::Filter::LINEAR, vk::SamplerAddressMode::CLAMP_TO_EDGE)?;
+
+        Ok(Self {
+            entries: vec![],
+            image,
+            storage_view,
+            sampler,
+            prefilter_set_layout,
+            prefilter_pipeline,
+            buffers
+        })
+    }
+
+    // Fails with ERROR_TOO_MANY_OBJECTS once every slot is taken
+    pub fn add(&mut self, device: &ash::Device, allocator: &mut Allocator, descriptors: &mut DescriptorAllocator, swapchain: &VulkanSwapchain,
+        scene_set_layouts: &[vk::DescriptorSetLayout], probe: ReflectionProbe
+    ) -> Result<usize, vk::Result> {
+        let slot = match (0..MAX_REFLECTION_PROBES as u32).find(|slot| self.entries.iter().all(|entry| entry.slot != *slot)) {
+            Some(slot) => slot,
+            None => {
+                tracing::warn!("All {} reflection probe slots are in use", MAX_REFLECTION_PROBES);
+                return Err(vk::Result::ERROR_TOO_MANY_OBJECTS);
+            }
+        };
+
+        let capture = CubemapCapture::new(device, allocator, descriptors, swapchain, scene_set_layouts, probe.position, CAPTURE_SIZE,
+            CaptureMode::OnDemand)?;
+
+        let prefilter_set = descriptors.allocate(device, self.prefilter_set_layout)?;
+        Descriptors::write_image(device, prefilter_set, 0, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, capture.descriptor_info());
+        Descriptors::write_image(device, prefilter_set, 1, vk::DescriptorType::STORAGE_IMAGE, vk::DescriptorImageInfo {
+            sampler: vk::Sampler::null(),
+            image_view: self.storage_view,
+            image_layout: vk::ImageLayout::GENERAL
+        });
+
+        self.entries.push(ProbeEntry { probe, slot, capture, prefilter_set, baked: false });
+
+        Ok(self.entries.len() - 1)
+    }
+
+    // The device must not use the probe anymore
+    pub fn remove(&mut self, device: &ash::Device, allocator: &mut Allocator, descriptors: &mut DescriptorAllocator, index: usize) -> Result<(), vk::Result> {
+        let mut entry = self.entries.remove(index);
+        descriptors.free(device, entry.prefilter_set)?;
+        entry.capture.destroy(device, allocator, descriptors)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn probe(&self, index: usize) -> &ReflectionProbe {
+        &self.entries[index].probe
+    }
+
+    // Moving the probe captures it again from the new position
+    pub fn set_probe(&mut self, index: usize, probe: ReflectionProbe) {
+        let entry = &mut self.entries[index];
+        if entry.probe.position != probe.position {
+            entry.capture.position = probe.position;
+            entry.capture.request_capture();
+        }
+        entry.probe = probe;
+    }
+
+    // Captures the probe again in the next frame, after the scene around it changed
+    pub fn bake(&mut self, index: usize) {
+        self.entries[index].capture.request_capture();
+    }
+
+    pub fn bake_all(&mut self) {
+        for entry in &mut self.entries {
+            entry.capture.request_capture();
+        }
+    }
+
+    // Probes whose capture and prefiltering get recorded this frame
+    pub fn pending(&self) -> Vec<usize> {
+        (0..self.entries.len()).filter(|&index| self.entries[index].capture.needs_capture()).collect()
+    }
+
+    pub fn capture(&self, index: usize) -> &CubemapCapture {
+        &self.entries[index].capture
+    }
+
+    // Called after the next frame is recorded, the probes it captures count as baked from then on
+    pub fn captured(&mut self) {
+        for entry in &mut self.entries {
+            if entry.capture.needs_capture() {
+                entry.baked = true;
+            }
+            entry.capture.captured();
+        }
+    }
+
+    pub fn update(&mut self, index: usize) {
+        let gpu_probes: Vec<GpuReflectionProbe> = self.entries
+            .iter()
+            .filter(|entry| entry.baked)
+            .map(|entry| entry.probe.as_gpu_probe(entry.slot))
+            .collect();
+        let header = ProbeBufferHeader {
+            count: gpu_probes.len() as u32,
+            _padding: [0; 3]
+        };
+
+        let buffer = &mut self.buffers[index];
+        buffer.update_buffer(0, &[header]);
+        buffer.update_buffer(std::mem::size_of::<ProbeBufferHeader>() as u64, &gpu_probes);
+
+        for entry in &mut self.entries {
+            entry.capture.update(index);
+        }
+    }
+
+    // Convolves the probe's fresh capture into its roughness levels, after the capture's passes in the same command buffer
+    pub fn record_prefilter(&self, device: &ash::Device, command_buffer: vk::CommandBuffer, index: usize) {
+        let entry = &self.entries[index];
+        let first_layer = entry.slot * PROBE_ROUGHNESS_LEVELS * 6;
+        let layer_count = PROBE_ROUGHNESS_LEVELS * 6;
+        let group_count = PROBE_SIZE.div_ceil(PREFILTER_GROUP_SIZE);
+
+        self.image.transition_layers(device, command_buffer, first_layer, layer_count, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            vk::ImageLayout::GENERAL);
+
+        unsafe {
+            device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::COMPUTE, self.prefilter_pipeline.pipeline);
+            device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::COMPUTE, self.prefilter_pipeline.layout, 0,
+                &[entry.prefilter_set], &[]);
+
+            for level in 0..PROBE_ROUGHNESS_LEVELS {
+                let push = PrefilterPushConstants {
+                    _first_layer: first_layer + level * 6,
+                    _size: PROBE_SIZE,
+                    _roughness: level as f32 / (PROBE_ROUGHNESS_LEVELS - 1) as f32,
+                    _sample_count: PREFILTER_SAMPLES
+                };
+                device.cmd_push_constants(command_buffer, self.prefilter_pipeline.layout, vk::ShaderStageFlags::COMPUTE, 0, any_as_u8_slice(&push));
+                device.cmd_dispatch(command_buffer, group_count, group_count, 6);
+            }
+        }
+
+        self.image.transition_layers(device, command_buffer, first_layer, layer_count, vk::ImageLayout::GENERAL,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+    }
+
+    pub fn descriptor_info(&self) -> vk::DescriptorImageInfo {
+        vk::DescriptorImageInfo {
+            sampler: self.sampler,
+            image_view: self.image.view,
+            image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
+        }
+    }
+
+    pub fn buffer_info(&self, index: usize) -> vk::DescriptorBufferInfo {
+        self.buffers[index].descriptor_info()
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device, allocator: &mut Allocator, descriptors: &mut DescriptorAllocator) -> Result<(), vk::Result> {
+        for mut entry in self.entries.drain(..) {
+            descriptors.free(device, entry.prefilter_set)?;
+            entry.capture.destroy(device, allocator, descriptors)?;
+        }
+        for buffer in &mut self.buffers {
+            buffer.destroy(device, allocator);
+        }
+        unsafe {
+            device.destroy_sampler(self.sampler, None);
+            device.destroy_image_view(self.storage_view, None);
+            device.destroy_descriptor_set_layout(self.prefilter_set_layout, None);
+        }
+        self.prefilter_pipeline.cleanup(device);
+        self.image.destroy(device, allocator);
+
+        Ok(())
+    }
+}
\ No newline at end of file
@@ -0,0 +1,81 @@
+use ash::vk;
+
+use super::conditional_rendering::ConditionalRendering;
+use super::occlusion::OcclusionQueries;
+use super::render_target::RenderTarget;
+
+// Points in the frame where application callbacks are recorded, in frame order
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HookPoint {
+    // After shadows and reflections, outside of any render pass (compute, custom render passes, ...)
+    BeforeScene,
+    // Inside the scene render pass after every opaque built-in scene draw, before the transparent ones. Pipelines have to
+    // be compatible with `scene_target.renderpass`
+    AfterOpaque,
+    // After the scene render pass, before any post effect. The scene target is ready for sampling
+    BeforePostProcess,
+    // Inside the swapchain render pass after the composite, for overlays drawn on top of the final image
+    Overlay,
+}
+
+// Everything a hook may need to record its commands, only valid while the hook runs
+pub struct FrameContext<'a> {
+    pub device: &'a ash::Device,
+    pub command_buffer: vk::CommandBuffer,
+    // Swapchain image the command buffer belongs to, also indexes the per image descriptor sets
+    pub image_index: usize,
+    pub frame_index: u64,
+    // Size of the scene target, the scene is shown in `viewport` of the swapchain image
+    pub extent: vk::Extent2D,
+    pub viewport: vk::Rect2D,
+    // Render pass active at the hook point, null for the points outside of one
+    pub renderpass: vk::RenderPass,
+    pub camera_set: vk::DescriptorSet,
+    pub lighting_set: vk::DescriptorSet,
+    // Bound with `objects_offset` as its dynamic offset
+    pub objects_set: vk::DescriptorSet,
+    pub objects_offset: u32,
+    pub scene_target: &'a RenderTarget,
+    // Queries with a `Custom` target are begun and ended by their owner's hook
+    pub occlusion_queries: &'a OcclusionQueries,
+    // Predicates draws on the queries' results, `None` without VK_EXT_conditional_rendering
+    pub conditional_rendering: Option<&'a ConditionalRendering>,
+}
+
+pub type RenderHook = Box<dyn Fn(&FrameContext) + Send + Sync>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct HookId(u64);
+
+// Hooks run once every frame, into the command buffers of the swapchain image drawn next. `BeforeScene` and `AfterOpaque`
+// are recorded on one job thread while another records the other two, see `VulkanRenderer::record_frame`.
+// Vulkan objects created by a hook's owner have to be destroyed by it before the renderer is dropped.
+#[derive(Default)]
+pub struct RenderHooks {
+    hooks: Vec<(HookId, HookPoint, RenderHook)>,
+    next_id: u64,
+}
+
+impl RenderHooks {
+    pub fn add(&mut self, point: HookPoint, hook: RenderHook) -> HookId {
+        let id = HookId(self.next_id);
+        self.next_id += 1;
+        self.hooks.push((id, point, hook));
+
+        id
+    }
+
+    pub fn remove(&mut self, id: HookId) -> bool {
+        let count = self.hooks.len();
+        self.hooks.retain(|(hook_id, _, _)| *hook_id != id);
+
+        self.hooks.len() != count
+    }
+
+    // Hooks at the same point run in registration order
+    pub fn record(&self, point: HookPoint, context: &FrameContext) {
+        for (_, _, hook) in self.hooks.iter().filter(|(_, hook_point, _)| *hook_point == point) {
+            hook(context);
+        }
+    }
+}
\ No newline at end of file
@@ -1,53 +1,349 @@
 use ash::vk;
 
+pub struct AttachmentInfo {
+    pub format: vk::Format,
+    pub final_layout: vk::ImageLayout,
+    // Attachments only consumed within the pass (as input attachments) don't have to be written back
+    pub store: bool,
+    // LOAD keeps the previous contents, which have to be in `final_layout` when the pass begins
+    pub load_op: vk::AttachmentLoadOp,
+}
+
+impl AttachmentInfo {
+    pub fn new(format: vk::Format, final_layout: vk::ImageLayout) -> Self {
+        Self {
+            format,
+            final_layout,
+            store: true,
+            load_op: vk::AttachmentLoadOp::CLEAR
+        }
+    }
+
+    pub fn transient(format: vk::Format) -> Self {
+        Self {
+            format,
+            final_layout: match is_depth_format(format) {
+                true => vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+                false => vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL
+            },
+            store: false,
+            load_op: vk::AttachmentLoadOp::CLEAR
+        }
+    }
+}
+
+// Indices into the attachments passed to `RenderPass::init_subpasses`
+pub struct SubpassInfo<'a> {
+    pub colors: &'a [u32],
+    pub inputs: &'a [u32],
+    pub depth: Option<u32>,
+}
+
+pub fn is_depth_format(format: vk::Format) -> bool {
+    matches!(format,
+        vk::Format::D16_UNORM | vk::Format::D32_SFLOAT | vk::Format::X8_D24_UNORM_PACK32
+        | vk::Format::D16_UNORM_S8_UINT | vk::Format::D24_UNORM_S8_UINT | vk::Format::D32_SFLOAT_S8_UINT)
+}
+
+pub fn has_stencil(format: vk::Format) -> bool {
+    matches!(format, vk::Format::D16_UNORM_S8_UINT | vk::Format::D24_UNORM_S8_UINT | vk::Format::D32_SFLOAT_S8_UINT)
+}
+
 pub struct RenderPass {}
 
 impl RenderPass {
+    // Final pass writing into the swapchain image
     pub fn init(logical_device: &ash::Device, format: vk::Format) -> Result<vk::RenderPass, vk::Result> {
-        let attachments = [vk::AttachmentDescription::builder()
-            .format(format)
-            .load_op(vk::AttachmentLoadOp::CLEAR)
-            .store_op(vk::AttachmentStoreOp::STORE)
+        Self::create(logical_device, &[format], None, vk::ImageLayout::PRESENT_SRC_KHR, vk::AttachmentLoadOp::CLEAR, 0)
+    }
+
+    // Final pass of a renderer without a surface, the image is left ready to be copied out
+    pub fn init_readback(logical_device: &ash::Device, format: vk::Format) -> Result<vk::RenderPass, vk::Result> {
+        Self::create(logical_device, &[format], None, vk::ImageLayout::TRANSFER_SRC_OPTIMAL, vk::AttachmentLoadOp::CLEAR, 0)
+    }
+
+    // Attachments are left ready for sampling so the result can be fed into a later pass
+    // (reflections, post processing)
+    pub fn init_offscreen(logical_device: &ash::Device, formats: &[vk::Format], depth_format: Option<vk::Format>) -> Result<vk::RenderPass, vk::Result> {
+        Self::create(logical_device, formats, depth_format, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL, vk::AttachmentLoadOp::CLEAR, 0)
+    }
+
+    // Compatible with `init_offscreen`, LOAD draws on top of what an earlier pass left and DONT_CARE skips the clear
+    pub fn init_offscreen_load_op(logical_device: &ash::Device, formats: &[vk::Format], depth_format: Option<vk::Format>,
+        load_op: vk::AttachmentLoadOp
+    ) -> Result<vk::RenderPass, vk::Result> {
+        Self::create(logical_device, formats, depth_format, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL, load_op, 0)
+    }
+
+    // Like `init_offscreen`, but every draw goes to each layer of the framebuffer picked by `view_mask`, with
+    // gl_ViewIndex telling the shaders which one they're drawing. Needs the multiview feature, see `MultiviewSupport`.
+    pub fn init_offscreen_multiview(logical_device: &ash::Device, formats: &[vk::Format], depth_format: Option<vk::Format>,
+        view_mask: u32
+    ) -> Result<vk::RenderPass, vk::Result> {
+        Self::create(logical_device, formats, depth_format, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL, vk::AttachmentLoadOp::CLEAR, view_mask)
+    }
+
+    // Compatible with pipelines made for itself only, the last attachment is a shading rate image whose texels each
+    // cover `texel_size` pixels. Needs VK_KHR_fragment_shading_rate, which only extends the render pass 2 structures.
+    pub fn init_offscreen_shading_rate(logical_device: &ash::Device, formats: &[vk::Format], depth_format: Option<vk::Format>,
+        load_op: vk::AttachmentLoadOp, texel_size: vk::Extent2D
+    ) -> Result<vk::RenderPass, vk::Result> {
+        let mut attachments: Vec<vk::AttachmentDescription2> = formats
+            .iter()
+            .map(|&format| (format, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL))
+            .chain(depth_format.map(|format| (format, vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL)))
+            .map(|(format, final_layout)| vk::AttachmentDescription2::builder()
+                .format(format)
+                .load_op(load_op)
+                .store_op(vk::AttachmentStoreOp::STORE)
+                .stencil_load_op(match has_stencil(format) {
+                    true => load_op,
+                    false => vk::AttachmentLoadOp::DONT_CARE
+                })
+                .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+                .initial_layout(match load_op {
+                    vk::AttachmentLoadOp::LOAD => final_layout,
+                    _ => vk::ImageLayout::UNDEFINED
+                })
+                .final_layout(final_layout)
+                .samples(vk::SampleCountFlags::TYPE_1)
+                .build())
+            .collect();
+        let rate_layout = vk::ImageLayout::FRAGMENT_SHADING_RATE_ATTACHMENT_OPTIMAL_KHR;
+        attachments.push(vk::AttachmentDescription2::builder()
+            .format(vk::Format::R8_UINT)
+            .load_op(vk::AttachmentLoadOp::LOAD)
+            .store_op(vk::AttachmentStoreOp::DONT_CARE)
             .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
             .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
-            .initial_layout(vk::ImageLayout::UNDEFINED)
-            .final_layout(vk::ImageLayout::PRESENT_SRC_KHR)
-            .samples(vk::SampleCountFlags::TYPE_1) //No AA
-            .build()
-        ];
+            .initial_layout(rate_layout)
+            .final_layout(rate_layout)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .build());
 
-        let color_attachment_references = [vk::AttachmentReference {
-            attachment: 0,
-            layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
-        }];
+        let colors: Vec<vk::AttachmentReference2> = (0..formats.len() as u32)
+            .map(|attachment| vk::AttachmentReference2::builder()
+                .attachment(attachment)
+                .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+                .aspect_mask(vk::ImageAspectFlags::COLOR)
+                .build())
+            .collect();
+        let depth = vk::AttachmentReference2::builder()
+            .attachment(formats.len() as u32)
+            .layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+            .aspect_mask(vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL);
+        let rate = vk::AttachmentReference2::builder()
+            .attachment(attachments.len() as u32 - 1)
+            .layout(rate_layout);
+        let mut rate_info = vk::FragmentShadingRateAttachmentInfoKHR::builder()
+            .fragment_shading_rate_attachment(&rate)
+            .shading_rate_attachment_texel_size(texel_size);
 
-        let subpasses = [vk::SubpassDescription::builder()
-            .color_attachments(&color_attachment_references)
+        let mut subpass = vk::SubpassDescription2::builder()
             .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+            .color_attachments(&colors)
+            .push_next(&mut rate_info);
+        if depth_format.is_some() {
+            subpass = subpass.depth_stencil_attachment(&depth);
+        }
+        let subpasses = [subpass.build()];
+
+        // Same as `create`
+        let dependencies = [vk::SubpassDependency2::builder()
+            .src_subpass(vk::SUBPASS_EXTERNAL)
+            .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS | vk::PipelineStageFlags::FRAGMENT_SHADER)
+            .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE)
+            .dst_subpass(0)
+            .dst_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS)
+            .dst_access_mask(
+                vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+                    | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE
+            )
+            .build(),
+            vk::SubpassDependency2::builder()
+            .src_subpass(0)
+            .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS)
+            .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE)
+            .dst_subpass(vk::SUBPASS_EXTERNAL)
+            .dst_stage_mask(vk::PipelineStageFlags::FRAGMENT_SHADER | vk::PipelineStageFlags::COMPUTE_SHADER)
+            .dst_access_mask(vk::AccessFlags::SHADER_READ)
             .build()
         ];
 
+        let renderpass_info = vk::RenderPassCreateInfo2::builder()
+            .attachments(&attachments)
+            .subpasses(&subpasses)
+            .dependencies(&dependencies);
+
+        unsafe { logical_device.create_render_pass2(&renderpass_info, None) }
+    }
+
+    // A `view_mask` of 0 is a plain single view pass
+    fn create(logical_device: &ash::Device, formats: &[vk::Format], depth_format: Option<vk::Format>, final_layout: vk::ImageLayout,
+        load_op: vk::AttachmentLoadOp, view_mask: u32
+    ) -> Result<vk::RenderPass, vk::Result> {
+        let mut attachments: Vec<AttachmentInfo> = formats
+            .iter()
+            .map(|&format| AttachmentInfo::new(format, final_layout))
+            .collect();
+        if let Some(depth_format) = depth_format {
+            attachments.push(AttachmentInfo::new(depth_format, vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL));
+        }
+        for attachment in &mut attachments {
+            attachment.load_op = load_op;
+        }
+
+        let colors: Vec<u32> = (0..formats.len() as u32).collect();
+        let subpasses = [SubpassInfo {
+            colors: &colors,
+            inputs: &[],
+            depth: depth_format.map(|_| formats.len() as u32),
+        }];
+
         let subpass_dependencies = [vk::SubpassDependency::builder()
             .src_subpass(vk::SUBPASS_EXTERNAL)
-            .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+            .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS | vk::PipelineStageFlags::FRAGMENT_SHADER)
+            .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE)
             .dst_subpass(0)
-            .dst_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+            .dst_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS)
             .dst_access_mask(
                 vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+                    | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE
             )
+            .build(),
+            vk::SubpassDependency::builder()
+            .src_subpass(0)
+            .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS)
+            .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE)
+            .dst_subpass(vk::SUBPASS_EXTERNAL)
+            .dst_stage_mask(vk::PipelineStageFlags::FRAGMENT_SHADER | vk::PipelineStageFlags::COMPUTE_SHADER)
+            .dst_access_mask(vk::AccessFlags::SHADER_READ)
             .build()
         ];
 
-        let renderpass_info = vk::RenderPassCreateInfo::builder()
-            .attachments(&attachments)
-            .subpasses(&subpasses)
-            .dependencies(&subpass_dependencies);
-        
+        let view_masks = [view_mask];
+        let view_masks: &[u32] = match view_mask {
+            0 => &[],
+            _ => &view_masks
+        };
+        Self::create_subpasses(logical_device, &attachments, &subpasses, &subpass_dependencies, view_masks)
+    }
+
+    // General form: every subpass references attachments by index. Attachments read as input attachments by a later
+    // subpass stay in tile memory on tilers, so e.g. a G-buffer can be marked transient and never hit main memory.
+    pub fn init_subpasses(
+        logical_device: &ash::Device,
+        attachments: &[AttachmentInfo],
+        subpasses: &[SubpassInfo],
+        dependencies: &[vk::SubpassDependency],
+    ) -> Result<vk::RenderPass, vk::Result> {
+        Self::create_subpasses(logical_device, attachments, subpasses, dependencies, &[])
+    }
+
+    // `view_masks` has one mask per subpass, or is empty when the pass doesn't use multiview
+    fn create_subpasses(
+        logical_device: &ash::Device,
+        attachments: &[AttachmentInfo],
+        subpasses: &[SubpassInfo],
+        dependencies: &[vk::SubpassDependency],
+        view_masks: &[u32],
+    ) -> Result<vk::RenderPass, vk::Result> {
+        let attachment_descriptions: Vec<vk::AttachmentDescription> = attachments
+            .iter()
+            .map(|attachment| vk::AttachmentDescription::builder()
+                .format(attachment.format)
+                .load_op(attachment.load_op)
+                .store_op(match attachment.store {
+                    true => vk::AttachmentStoreOp::STORE,
+                    false => vk::AttachmentStoreOp::DONT_CARE
+                })
+                .stencil_load_op(match has_stencil(attachment.format) {
+                    true => attachment.load_op,
+                    false => vk::AttachmentLoadOp::DONT_CARE
+                })
+                .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+                .initial_layout(match attachment.load_op {
+                    vk::AttachmentLoadOp::LOAD => attachment.final_layout,
+                    _ => vk::ImageLayout::UNDEFINED
+                })
+                .final_layout(attachment.final_layout)
+                .samples(vk::SampleCountFlags::TYPE_1) //No AA
+                .build())
+            .collect();
+
+        // The descriptions only point into these, so they have to outlive the create call
+        let references: Vec<(Vec<vk::AttachmentReference>, Vec<vk::AttachmentReference>, Option<vk::AttachmentReference>)> = subpasses
+            .iter()
+            .map(|subpass| {
+                let colors = subpass.colors
+                    .iter()
+                    .map(|&attachment| vk::AttachmentReference {
+                        attachment,
+                        layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                    })
+                    .collect();
+                let inputs = subpass.inputs
+                    .iter()
+                    .map(|&attachment| vk::AttachmentReference {
+                        attachment,
+                        layout: match is_depth_format(attachments[attachment as usize].format) {
+                            true => vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+                            false => vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
+                        },
+                    })
+                    .collect();
+                let depth = subpass.depth.map(|attachment| vk::AttachmentReference {
+                    attachment,
+                    layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+                });
+
+                (colors, inputs, depth)
+            })
+            .collect();
+
+        let subpass_descriptions: Vec<vk::SubpassDescription> = references
+            .iter()
+            .map(|(colors, inputs, depth)| {
+                let mut subpass = vk::SubpassDescription::builder()
+                    .color_attachments(colors)
+                    .input_attachments(inputs)
+                    .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS);
+                if let Some(depth) = depth {
+                    subpass = subpass.depth_stencil_attachment(depth);
+                }
+
+                subpass.build()
+            })
+            .collect();
+
+        let mut multiview_info = vk::RenderPassMultiviewCreateInfo::builder()
+            .view_masks(view_masks);
+        let mut renderpass_info = vk::RenderPassCreateInfo::builder()
+            .attachments(&attachment_descriptions)
+            .subpasses(&subpass_descriptions)
+            .dependencies(dependencies);
+        if !view_masks.is_empty() {
+            renderpass_info = renderpass_info.push_next(&mut multiview_info);
+        }
+
         let renderpass = unsafe { logical_device.create_render_pass(&renderpass_info, None)? };
-        
+
         Ok(renderpass)
     }
 
+    // Makes the attachments written by `src` readable as input attachments in `dst`, only for the same pixel
+    pub fn input_dependency(src: u32, dst: u32) -> vk::SubpassDependency {
+        vk::SubpassDependency::builder()
+            .src_subpass(src)
+            .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS)
+            .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE)
+            .dst_subpass(dst)
+            .dst_stage_mask(vk::PipelineStageFlags::FRAGMENT_SHADER)
+            .dst_access_mask(vk::AccessFlags::INPUT_ATTACHMENT_READ)
+            .dependency_flags(vk::DependencyFlags::BY_REGION)
+            .build()
+    }
+
     pub fn cleanup(logical_device: &ash::Device, renderpass: vk::RenderPass) {
         unsafe {
             logical_device.destroy_render_pass(renderpass, None);
@@ -0,0 +1,219 @@
+use ash::vk;
+use gpu_allocator::vulkan::Allocator;
+
+use super::image::{Image, create_sampler};
+use super::render_pass::RenderPass;
+use super::renderer::VulkanRenderer;
+use super::camera::{ClearOp, ClearSettings};
+
+pub struct RenderTarget {
+    pub colors: Vec<Image>,
+    pub depth: Option<Image>,
+    // Shading rate attachment of every pass into the target, see `with_shading_rate`
+    pub shading_rate: Option<Image>,
+    pub renderpass: vk::RenderPass,
+    // Keeps the contents, for drawing into part of the target after an earlier pass in the same frame
+    pub load_renderpass: vk::RenderPass,
+    // Leaves the contents undefined, for views that cover everything they draw to
+    pub discard_renderpass: vk::RenderPass,
+    pub framebuffer: vk::Framebuffer,
+    pub sampler: vk::Sampler,
+    pub extent: vk::Extent2D,
+}
+
+impl RenderTarget {
+    pub fn new(device: &ash::Device, allocator: &mut Allocator, extent: vk::Extent2D, formats: &[vk::Format], depth: bool, name: &str) -> Result<Self, vk::Result> {
+        Self::with_shading_rate(device, allocator, extent, formats, depth, None, name)
+    }
+
+    // With a texel size the passes take their shading rate from an R8_UINT image with one texel per `texel_size` pixels,
+    // see `VariableRateShading`. Its render passes are only compatible with targets of the same texel size.
+    pub fn with_shading_rate(device: &ash::Device, allocator: &mut Allocator, extent: vk::Extent2D, formats: &[vk::Format], depth: bool,
+        shading_rate_texel: Option<vk::Extent2D>, name: &str
+    ) -> Result<Self, vk::Result> {
+        let mut colors = Vec::with_capacity(formats.len());
+        for &format in formats {
+            colors.push(Image::new(device, allocator, extent, format,
+                vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_SRC, vk::ImageAspectFlags::COLOR, name)?);
+        }
+        let depth = match depth {
+            true => Some(Image::new_depth_stencil(device, allocator, extent, vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_SRC,
+                name)?),
+            false => None
+        };
+
+        let shading_rate = match shading_rate_texel {
+            Some(texel) => {
+                let rate_extent = vk::Extent2D { width: extent.width.div_ceil(texel.width), height: extent.height.div_ceil(texel.height) };
+                Some(Image::new(device, allocator, rate_extent, vk::Format::R8_UINT,
+                    vk::ImageUsageFlags::FRAGMENT_SHADING_RATE_ATTACHMENT_KHR | vk::ImageUsageFlags::STORAGE, vk::ImageAspectFlags::COLOR, name)?)
+            },
+            None => None
+        };
+
+        let depth_format = depth.as_ref().map(|depth| depth.format);
+        let (renderpass, load_renderpass, discard_renderpass) = match shading_rate_texel {
+            Some(texel) => (
+                RenderPass::init_offscreen_shading_rate(device, formats, depth_format, vk::AttachmentLoadOp::CLEAR, texel)?,
+                RenderPass::init_offscreen_shading_rate(device, formats, depth_format, vk::AttachmentLoadOp::LOAD, texel)?,
+                RenderPass::init_offscreen_shading_rate(device, formats, depth_format, vk::AttachmentLoadOp::DONT_CARE, texel)?
+            ),
+            None => (
+                RenderPass::init_offscreen(device, formats, depth_format)?,
+                RenderPass::init_offscreen_load_op(device, formats, depth_format, vk::AttachmentLoadOp::LOAD)?,
+                RenderPass::init_offscreen_load_op(device, formats, depth_format, vk::AttachmentLoadOp::DONT_CARE)?
+            )
+        };
+
+        let mut attachments: Vec<vk::ImageView> = colors.iter().map(|color| color.view).collect();
+        if let Some(depth) = &depth {
+            attachments.push(depth.view);
+        }
+        if let Some(shading_rate) = &shading_rate {
+            attachments.push(shading_rate.view);
+        }
+        let framebuffer_info = vk::FramebufferCreateInfo::builder()
+            .render_pass(renderpass)
+            .attachments(&attachments)
+            .width(extent.width)
+            .height(extent.height)
+            .layers(1);
+        let framebuffer = unsafe { device.create_framebuffer(&framebuffer_info, None)? };
+
+        let sampler = create_sampler(device, vk::Filter::LINEAR, vk::SamplerAddressMode::CLAMP_TO_EDGE)?;
+
+        Ok(Self {
+            colors,
+            depth,
+            shading_rate,
+            renderpass,
+            load_renderpass,
+            discard_renderpass,
+            framebuffer,
+            sampler,
+            extent
+        })
+    }
+
+    pub fn descriptor_info(&self, index: usize) -> vk::DescriptorImageInfo {
+        vk::DescriptorImageInfo {
+            sampler: self.sampler,
+            image_view: self.colors[index].view,
+            image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
+        }
+    }
+
+    pub fn depth_descriptor_info(&self) -> vk::DescriptorImageInfo {
+        vk::DescriptorImageInfo {
+            sampler: self.sampler,
+            image_view: self.depth.as_ref().and_then(|depth| depth.depth_view).expect("Render target has no depth attachment!"),
+            image_layout: vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL
+        }
+    }
+
+    // Only the first color attachment takes the camera's clear color
+    pub fn clear_values(&self, clear: &ClearSettings) -> Vec<vk::ClearValue> {
+        let mut clear_values = vec![vk::ClearValue {
+            color: vk::ClearColorValue {
+                float32: [0.0, 0.0, 0.0, 1.0]
+            }
+        }; self.colors.len()];
+        if let Some(first) = clear_values.first_mut() {
+            first.color.float32 = clear.color.into();
+        }
+
+        if self.depth.is_some() {
+            clear_values.push(vk::ClearValue {
+                depth_stencil: vk::ClearDepthStencilValue {
+                    depth: clear.depth,
+                    stencil: 0
+                }
+            });
+        }
+
+        clear_values
+    }
+
+    pub fn rect(&self) -> vk::Rect2D {
+        vk::Rect2D {
+            offset: vk::Offset2D { x: 0, y: 0 },
+            extent: self.extent
+        }
+    }
+
+    // Begins a pass drawing into `rect`. The first pass of a frame clears the whole target unless nothing is to be cleared,
+    // later ones (split screen views) only clear their rect as `clear` asks and keep what the earlier passes drew.
+    pub fn begin_pass(&self, device: &ash::Device, command_buffer: vk::CommandBuffer, rect: vk::Rect2D, first: bool, clear: &ClearSettings) {
+        let clear_values = self.clear_values(clear);
+        let discard = clear.color_op == ClearOp::DontCare && clear.depth_op == ClearOp::DontCare;
+        let renderpass_begininfo = vk::RenderPassBeginInfo::builder()
+            .render_pass(match (first, discard) {
+                (true, true) => self.discard_renderpass,
+                (true, false) => self.renderpass,
+                (false, _) => self.load_renderpass
+            })
+            .framebuffer(self.framebuffer)
+            .render_area(match first {
+                true => self.rect(),
+                false => rect
+            })
+            .clear_values(&clear_values);
+
+        unsafe { device.cmd_begin_render_pass(command_buffer, &renderpass_begininfo, vk::SubpassContents::INLINE) };
+        VulkanRenderer::set_viewport_rect(device, command_buffer, rect);
+
+        if !first {
+            let attachments: Vec<vk::ClearAttachment> = clear_values
+                .iter()
+                .enumerate()
+                .filter_map(|(index, &clear_value)| match index < self.colors.len() {
+                    true if clear.color_op == ClearOp::Clear => Some(vk::ClearAttachment {
+                        aspect_mask: vk::ImageAspectFlags::COLOR,
+                        color_attachment: index as u32,
+                        clear_value
+                    }),
+                    false if clear.depth_op == ClearOp::Clear => Some(vk::ClearAttachment {
+                        aspect_mask: vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL,
+                        color_attachment: 0,
+                        clear_value
+                    }),
+                    _ => None
+                })
+                .collect();
+            if !attachments.is_empty() {
+                let rects = [vk::ClearRect { rect, base_array_layer: 0, layer_count: 1 }];
+                unsafe { device.cmd_clear_attachments(command_buffer, &attachments, &rects) };
+            }
+        }
+    }
+
+    // Begins the load pass over `rect` again after the pass was ended partway through a view, nothing is cleared
+    pub fn resume_pass(&self, device: &ash::Device, command_buffer: vk::CommandBuffer, rect: vk::Rect2D) {
+        let renderpass_begininfo = vk::RenderPassBeginInfo::builder()
+            .render_pass(self.load_renderpass)
+            .framebuffer(self.framebuffer)
+            .render_area(rect);
+
+        unsafe { device.cmd_begin_render_pass(command_buffer, &renderpass_begininfo, vk::SubpassContents::INLINE) };
+        VulkanRenderer::set_viewport_rect(device, command_buffer, rect);
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device, allocator: &mut Allocator) {
+        unsafe {
+            device.destroy_sampler(self.sampler, None);
+            device.destroy_framebuffer(self.framebuffer, None);
+        }
+        RenderPass::cleanup(device, self.renderpass);
+        RenderPass::cleanup(device, self.load_renderpass);
+        RenderPass::cleanup(device, self.discard_renderpass);
+        if let Some(depth) = &mut self.depth {
+            depth.destroy(device, allocator);
+        }
+        if let Some(shading_rate) = &mut self.shading_rate {
+            shading_rate.destroy(device, allocator);
+        }
+        for color in &mut self.colors {
+            color.destroy(device, allocator);
+        }
+    }
+}
\ No newline at end of file
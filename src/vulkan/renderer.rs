@@ -1,4 +1,5 @@
 use ash::vk;
+use ash::vk::Handle;
 use gpu_allocator::vulkan::{Allocator, AllocatorCreateDesc};
 
 use super::{window::VulkanWindow};
@@ -6,21 +7,93 @@ use super::surface::VulkanSurface;
 use super::debug::VulkanDebug;
 use super::physical_device::PhysicalDevice;
 use super::queue::*;
-use super::logical_device::LogicalDevice;
-use super::swapchain::VulkanSwapchain;
+use super::logical_device::{DeviceFactory, LogicalDevice};
+use super::swapchain::{VulkanSwapchain, WindowAlpha};
 use super::render_pass::RenderPass;
-use super::pipeline::Pipeline;
+use super::pipeline::{BlendMode, Pipeline, PipelineConfig, SpecializationConstant, FRAME_SET, MATERIAL_SET, OBJECT_SET};
 use super::command_pools::Pools;
-use super::game_object::GameObject;
+use super::game_object::{GameObject, world_matrices};
+use super::mesh::{BoundingSphere, LodView, Mesh};
+use super::material::{Material, MaterialSets, TextureSlot};
+use super::camera::{Camera, CameraUniform, ClearSettings, Ray};
+use super::descriptors::Descriptors;
+use super::uniform_buffer::UniformBuffer;
+use super::reflection::{PlanarReflection, ReflectionPlane};
+use super::cubemap::{CubemapCapture, CaptureMode};
+use super::reflection_probes::{ReflectionProbe, ReflectionProbes};
+use super::light_probes::{GiQuality, LightProbeGrid, LightProbes};
+use super::lightmap::{BakedLightmap, lightmapped_pipeline};
+use super::transparency::TransparentPipelines;
+use super::cutout::cutout_pipeline;
+use super::gpu_culling::GpuCulling;
+use super::shading_rate::{ShadingRateMode, ShadingRateSupport, VariableRateShading};
+use super::parallax::ParallaxQuality;
+use super::post::{AntiAliasing, PostProcess, OBJECT_ID_ATTACHMENT};
+use super::post::fxaa::{FxaaSettings, FXAA_FRAG};
+use super::post::taa::JITTER_SEQUENCE;
+use super::lights::{DirectionalLight, PointLight, SpotLight};
+use super::clustered_lighting::ClusteredLighting;
+use super::post::ssr::{SsrSettings, SSR_FRAG};
+use super::post::god_rays::{GodRaySettings, GOD_RAYS_FRAG};
+use super::post::motion_blur::{MotionBlurSettings, MOTION_BLUR_FRAG};
+use super::post::dof::{DofSettings, DOF_FRAG};
+use super::post::grading::{CompositeSettings, Lut};
+use super::post::exposure::AutoExposureSettings;
+use super::post::analysis::ImageStatistics;
+use super::post::lens_flare::{FlareSource, LensFlareSettings};
+use super::object_buffer::{ObjectBuffers, MAX_OBJECTS};
+use super::upload_ring::{UploadRing, UPLOAD_REGION_SIZE};
+use super::multiview::MultiviewSupport;
+use super::shadows::{ShadowAssignment, ShadowSystem};
+use super::texture::Texture;
+use super::texture_streaming::{MipChain, StreamingSettings, TextureStreamer};
+use super::staging_buffer::StagingBuffer;
+use super::outline::Outline;
+use super::occlusion::{OcclusionQueries, OcclusionQuery, OcclusionResult, OcclusionTarget};
+use super::conditional_rendering::{self, ConditionalRendering};
+use super::gizmo::Gizmo;
+use super::render_hooks::{FrameContext, HookId, HookPoint, RenderHook, RenderHooks};
+use super::ui::Ui;
+use super::sdf_font::SdfFont;
+use super::sdf_text::{SdfFontId, SdfText};
+use super::viewport::{ViewportLayout, ViewportMode};
+use super::split_screen::{SplitView, ViewArea};
+use super::water::Water;
+use super::virtual_texture::{PageSource, VirtualTexture};
+use super::wind::Wind;
+use super::atmosphere::{Atmosphere, Sky};
+use super::clouds::Clouds;
+use super::screenshot::{Screenshot, ScreenshotCapture, ScreenshotSettings};
+use super::crash_diagnostics::{Breadcrumbs, CrashDiagnostics};
+#[cfg(feature = "tracy")]
+use super::gpu_profiler::GpuProfiler;
+use super::portability;
+use super::full_screen_exclusive::{self, FullScreenExclusive};
+use super::adapter_info::AdapterInfo;
+use super::thumbnail::ThumbnailRenderer;
+use super::hal::AshDevice;
+use super::scatter::{scatter_instances, Scatter, ScatterPipelines, ScatterSettings};
+use super::descriptor_allocator::{DescriptorAllocator, DEFAULT_POOL_RATIOS};
 
-use crate::utils::{align, any_as_u8_slice};
+use crate::jobs::TaskGraph;
+use crate::profiling;
 
 pub struct VulkanRenderer {
     pub entry: ash::Entry,
     pub instance: ash::Instance,
     pub is_framebuffer_resized: bool,
+    // Size of the window in pixels, the swapchain only uses it on Wayland where the surface has none
+    window_extent: vk::Extent2D,
+    // `None` where VK_EXT_full_screen_exclusive isn't available (everywhere but Windows) and when rendering offscreen
+    full_screen_exclusive: Option<FullScreenExclusive>,
+    pub vsync: bool,
+    // The window was created transparent, swapchains blend with what's behind it if the surface can
+    transparent: bool,
     pub debug: VulkanDebug,
-    pub surface: VulkanSurface,
+    // None when rendering offscreen, see `new_offscreen`, and while suspended
+    pub surface: Option<VulkanSurface>,
+    // The window's surface is gone and nothing is drawn until `resume`
+    suspended: bool,
     pub physical_device: vk::PhysicalDevice,
     pub physical_device_properties: vk::PhysicalDeviceProperties,
     pub physical_device_features: vk::PhysicalDeviceFeatures,
@@ -28,293 +101,2191 @@ pub struct VulkanRenderer {
     pub queues: Queues,
     pub device: ash::Device,
     pub swapchain: VulkanSwapchain,
+    pub viewport_mode: ViewportMode,
+    // Follows the swapchain size, the scene renders at `viewport.render_extent` into `viewport.rect`
+    pub viewport: ViewportLayout,
     pub renderpass: vk::RenderPass,
     pub pipeline: Pipeline,
+    pub lightmapped_pipeline: Pipeline,
+    pub cutout_pipeline: Pipeline,
+    pub transparent_pipelines: TransparentPipelines,
+    water: Water,
+    // Colors `Material::Virtual` objects, see `set_virtual_texture`
+    virtual_texture: Option<VirtualTexture>,
+    scatter: Scatter,
+    sky: Sky,
+    clouds: Clouds,
     pub pools: Pools,
     pub command_buffers: Vec<vk::CommandBuffer>,
+    // Fence of the submission that last used each swapchain image's command buffer and upload region, null before the first
+    image_fences: Vec<vk::Fence>,
+    // Swapchain image `fill_commandbuffers` acquired and recorded for the next `draw_frame`
+    acquired_image: Option<usize>,
     pub allocator: std::mem::ManuallyDrop<Allocator>,
+    pub descriptor_pool: vk::DescriptorPool,
+    // Grows on demand, for sets that come and go with the content (UI textures, ...)
+    pub descriptors: DescriptorAllocator,
+    // Transient sets of each frame slot, see `allocate_frame_set`
+    frame_descriptors: Vec<DescriptorAllocator>,
+    pub camera_set_layout: vk::DescriptorSetLayout,
+    pub camera_buffers: Vec<UniformBuffer<CameraUniform>>,
+    pub camera_sets: Vec<vk::DescriptorSet>,
+    pub camera: Camera,
+    // Cameras drawn side by side instead of `camera` for local multiplayer, see `add_split_view`
+    pub split_views: Vec<SplitView>,
+    pub reflection: Option<PlanarReflection>,
+    // Environment maps rendered from points in the scene, see `add_cubemap_capture`
+    pub cubemaps: Vec<CubemapCapture>,
+    // Created by the first `render_thumbnail`
+    thumbnails: Option<ThumbnailRenderer>,
+    pub reflection_probes: ReflectionProbes,
+    pub light_probes: LightProbes,
+    // `None` without driver support for indirect count draws, objects are then drawn one by one
+    pub gpu_culling: Option<GpuCulling>,
+    // Whether the scene pipelines pull their vertices, fixed for the renderer's lifetime
+    vertex_pulling: bool,
+    // Baked into the scene pipelines, see `set_parallax_quality`
+    parallax: ParallaxQuality,
+    // `None` without VK_KHR_fragment_shading_rate, everything is then shaded per pixel
+    pub shading_rate: Option<VariableRateShading>,
+    pub post_process: PostProcess,
+    pub lighting: ClusteredLighting,
+    // Everything written per frame streams through it, see `fill_commandbuffers`
+    pub upload_ring: UploadRing,
+    pub objects: ObjectBuffers,
+    pub materials: MaterialSets,
+    // Mip levels of the textures set with `stream_color_texture` and `stream_normal_map`
+    pub texture_streaming: TextureStreamer,
+    pub previous_view_projection: Option<uv::Mat4>,
+    pub anti_aliasing: AntiAliasing,
+    // Frames submitted so far, drives the TAA jitter and history ping-pong
+    pub frame_index: u64,
+    // Animated materials (water, wind) count their time from here
+    animation_start: std::time::Instant,
+    // Screenshot being rendered, see `capture_screenshot`
+    capture: Option<ScreenshotCapture>,
+    // Passes recorded into every frame slot, dumped with where the GPU got to when the device is lost
+    crash_diagnostics: CrashDiagnostics,
+    // Times the passes for Tracy, `None` when no client is running
+    #[cfg(feature = "tracy")]
+    gpu_profiler: Option<GpuProfiler>,
+    pub wind: Wind,
+    pub lights: Vec<PointLight>,
+    pub spot_lights: Vec<SpotLight>,
+    pub shadows: ShadowSystem,
+    pub light_cookies: Texture,
+    pub ambient_light: uv::Vec3,
+    pub sun: Option<DirectionalLight>,
+    // Drawn as the sky behind the scene, its sun takes the place of `sun` while it's above the horizon and its sky
+    // light adds to `ambient_light`
+    pub atmosphere: Option<Atmosphere>,
+    pub hooks: RenderHooks,
+    pub outline: Outline,
+    pub occlusion_queries: OcclusionQueries,
+    // `None` without VK_EXT_conditional_rendering, occlusion culled objects are then always drawn
+    pub conditional_rendering: Option<ConditionalRendering>,
+    pub gizmo: Gizmo,
+    pub ui: Ui,
+    pub sdf_text: SdfText,
+    // Id of the game object outlined as selected
+    pub selected: Option<usize>,
     pub game_objects: Vec<GameObject>
 }
 
+// Choices made once when the renderer is created
+pub struct RendererSettings {
+    pub validation: bool,
+    // Off presents frames as soon as they're rendered, if the surface supports it
+    pub vsync: bool,
+    // Index or part of the name of the GPU to use instead of the best rated one
+    pub gpu: Option<String>,
+    // Starting tier of the probe grid's global illumination, see `set_gi_quality`
+    pub gi: GiQuality,
+    // Starting mode of variable rate shading, see `VariableRateShading::mode`
+    pub shading_rate: ShadingRateMode,
+    // Scene pipelines fetch vertices from a storage buffer instead of vertex input, needs GPU culling
+    pub vertex_pulling: bool,
+    // Starting quality of height mapped materials, see `set_parallax_quality`
+    pub parallax: ParallaxQuality,
+    pub texture_streaming: StreamingSettings,
+}
+
+impl Default for RendererSettings {
+    fn default() -> Self {
+        Self {
+            validation: true,
+            vsync: true,
+            gpu: None,
+            gi: GiQuality::Baked,
+            shading_rate: ShadingRateMode::Off,
+            vertex_pulling: false,
+            parallax: ParallaxQuality::default(),
+            texture_streaming: StreamingSettings::default()
+        }
+    }
+}
+
+// Work recorded for one frame, counted from the scene rather than queried from the GPU
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RenderStats {
+    pub game_objects: usize,
+    // Draw calls per pass, the UI and post processing aren't included
+    pub scene_draws: usize,
+    pub reflection_draws: usize,
+    pub shadow_draws: usize,
+    // Triangles of the main scene pass
+    pub triangles: u64,
+    pub point_lights: usize,
+    pub spot_lights: usize,
+    // Shadow map layers rendered, every shadowed point light takes six
+    pub shadow_views: usize,
+}
+
+// Images an offscreen renderer cycles through, like the images of a swapchain
+const OFFSCREEN_IMAGE_COUNT: usize = 2;
+
+// Command buffers a frame is recorded into in parallel, see `record_frame`
+const FRAME_PARTS: usize = 4;
+
 impl VulkanRenderer {
-    pub fn new(window: &VulkanWindow) -> Result<Self, Box<dyn std::error::Error>> {
-        let layer_names = vec!["VK_LAYER_KHRONOS_validation"]; 
+    pub fn new(window: &VulkanWindow, settings: &RendererSettings) -> Result<Self, Box<dyn std::error::Error>> {
+        let extent = vk::Extent2D { width: window.width, height: window.height };
+        Self::create(Some(window), extent, settings, None)
+    }
+
+    // Renders into plain images of the given size without a window or surface, e.g. for tests. Frames are drawn with
+    // `draw_frame` as usual and `read_pixels` returns the last one.
+    pub fn new_offscreen(extent: vk::Extent2D, settings: &RendererSettings) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::create(None, extent, settings, None)
+    }
+
+    // Like `new_offscreen` with the instance, GPU and device coming from `factory`
+    pub fn new_offscreen_with(extent: vk::Extent2D, settings: &RendererSettings, factory: &dyn DeviceFactory) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::create(None, extent, settings, Some(factory))
+    }
+
+    // The swapchain takes its size from the surface, `offscreen_extent` only applies without a window
+    fn create(window: Option<&VulkanWindow>, offscreen_extent: vk::Extent2D, settings: &RendererSettings, factory: Option<&dyn DeviceFactory>
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let layer_names = match settings.validation {
+            true => vec!["VK_LAYER_KHRONOS_validation"],
+            false => vec![]
+        };
+        profiling::start();
         let entry = ash::Entry::linked();
-        let instance = Self::create_instance(&entry, &layer_names, &window)
+        let instance = Self::create_instance(&entry, &layer_names, window, factory)
             .expect("Failed to initialize instance!");
         
         let debug = VulkanDebug::new(&entry, &instance)?;
 
-        let surface = VulkanSurface::new(&window, &entry, &instance)?;
+        let surface = match window {
+            Some(window) => Some(VulkanSurface::new(window, &entry, &instance)?),
+            None => None
+        };
 
-        let (physical_device, physical_device_properties, physical_device_features) = PhysicalDevice::pick_physical_device(&instance)
+        let picked = match factory {
+            Some(factory) => PhysicalDevice::use_physical_device(&instance, factory.physical_device(&instance)?),
+            None => PhysicalDevice::pick_physical_device(&instance, settings.gpu.as_deref())
+        };
+        let (physical_device, physical_device_properties, physical_device_features) = picked
             .expect("No suitable physical device found!");
 
-        let queue_families = QueueFamilies::new(&instance, physical_device, &surface)?;
+        let queue_families = QueueFamilies::new(&instance, physical_device, surface.as_ref())?;
+
+        let exclusive_supported = surface.is_some() && full_screen_exclusive::supported(&entry, &instance, physical_device);
+        let (logical_device, queues) = LogicalDevice::new(&instance, physical_device, &queue_families, &layer_names, surface.is_some(),
+            exclusive_supported, factory)?;
+
+        let buffer_device_address = false;
+        let mut allocator = Allocator::new(&AllocatorCreateDesc {
+            instance: instance.clone(),
+            device: logical_device.clone(),
+            physical_device,
+            debug_settings: Default::default(),
+            buffer_device_address,
+        }).expect("Failed to create allocator!");
+        allocator.report_memory_leaks(log::Level::Info);
+
+        let window_extent = window.map_or(offscreen_extent, VulkanWindow::extent);
+        let transparent = window.is_some_and(|window| window.style.transparent);
+        let mut full_screen_exclusive = exclusive_supported.then(|| FullScreenExclusive::new(&instance, &logical_device));
+        if let (Some(full_screen_exclusive), Some(window)) = (&mut full_screen_exclusive, window) {
+            full_screen_exclusive.update(window);
+        }
+        let mut swapchain = match &surface {
+            Some(surface) => VulkanSwapchain::new(&instance, physical_device, &logical_device, surface, &queue_families, settings.vsync,
+                transparent, window_extent, full_screen_exclusive.as_ref())?,
+            None => VulkanSwapchain::offscreen(&logical_device, &mut allocator, offscreen_extent, OFFSCREEN_IMAGE_COUNT)?
+        };
+        if transparent && swapchain.alpha == WindowAlpha::Opaque {
+            tracing::warn!("The surface can't blend with what's behind the window, it stays opaque");
+        }
+
+        let crash_diagnostics = CrashDiagnostics::new(&entry, &instance, &logical_device, physical_device, &mut allocator,
+            swapchain.image_count)?;
+
+        let renderpass = Self::create_present_renderpass(&logical_device, &swapchain)?;
+
+        swapchain.create_framebuffers(&logical_device, renderpass)?;
+
+        // Split views take two camera sets per swapchain image each
+        let descriptor_pool = Descriptors::create_pool(&logical_device, 128, &[
+            vk::DescriptorPoolSize { ty: vk::DescriptorType::UNIFORM_BUFFER, descriptor_count: 128 },
+            vk::DescriptorPoolSize { ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER, descriptor_count: 96 },
+            vk::DescriptorPoolSize { ty: vk::DescriptorType::STORAGE_BUFFER, descriptor_count: 88 },
+            vk::DescriptorPoolSize { ty: vk::DescriptorType::STORAGE_BUFFER_DYNAMIC, descriptor_count: 8 },
+            vk::DescriptorPoolSize { ty: vk::DescriptorType::INPUT_ATTACHMENT, descriptor_count: 16 },
+        ])?;
+
+        let camera_set_layout = Descriptors::create_layout(&logical_device, &[
+            (vk::DescriptorType::UNIFORM_BUFFER, vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT | vk::ShaderStageFlags::COMPUTE)
+        ])?;
+
+        let camera_sets = Descriptors::allocate(&logical_device, descriptor_pool, camera_set_layout, swapchain.image_count)?;
+        let mut camera_buffers = Vec::with_capacity(swapchain.image_count);
+        for set in &camera_sets {
+            let camera_buffer = UniformBuffer::<CameraUniform>::new(&logical_device, &mut allocator);
+            Descriptors::write_buffer(&logical_device, *set, 0, vk::DescriptorType::UNIFORM_BUFFER, camera_buffer.descriptor_info());
+            camera_buffers.push(camera_buffer);
+        }
+
+        let viewport = ViewportLayout::new(ViewportMode::Fill, swapchain.extent);
+        let camera = Camera::new(uv::Vec3::new(0.0, 0.0, 2.0), uv::Vec3::zero(), viewport.aspect_ratio());
+
+        let mut pools = Pools::new(&logical_device, &queue_families)?;
+
+        #[cfg(feature = "tracy")]
+        let gpu_profiler = GpuProfiler::new(&entry, &instance, &logical_device, physical_device, &physical_device_properties,
+            queue_families.graphics.unwrap(), &pools, queues.graphics_queue, swapchain.image_count)?;
+
+        // The scene target gets a shading rate image whenever the device can use one, so the mode can change at runtime
+        let shading_rate_support = ShadingRateSupport::query(&instance, physical_device);
+        let post_process = PostProcess::new(&logical_device, &mut allocator, &swapchain, &renderpass, &pools, queues.graphics_queue,
+            descriptor_pool, camera_set_layout, shading_rate_support.and_then(|support| support.attachment_texel_size))?;
+
+        let shadows = ShadowSystem::new(&logical_device, &mut allocator, &swapchain, MultiviewSupport::query(&instance, physical_device))?;
+
+        // Plain white until the application provides cookies, so spot lights without one stay unmasked
+        let light_cookies = Texture::from_rgba8_layers(&logical_device, &mut allocator, &pools, queues.graphics_queue,
+            vk::Extent2D { width: 1, height: 1 }, &[&[255; 4]], vk::ImageViewType::TYPE_2D_ARRAY, "Light Cookies")?;
+
+        let reflection_probes = ReflectionProbes::new(&logical_device, &mut allocator, &pools, queues.graphics_queue, swapchain.image_count)?;
+
+        let lighting = ClusteredLighting::new(&logical_device, &mut allocator, descriptor_pool, camera_set_layout, swapchain.image_count,
+            &shadows, light_cookies.descriptor_info(), &reflection_probes)?;
+
+        let upload_ring = UploadRing::new(&logical_device, &mut allocator, &physical_device_properties, UPLOAD_REGION_SIZE, swapchain.image_count,
+            ObjectBuffers::binding_range())?;
+        let objects = ObjectBuffers::new(&logical_device, descriptor_pool, &upload_ring)?;
+
+        let materials = MaterialSets::new(&logical_device, &mut allocator, &pools, queues.graphics_queue)?;
+        let texture_streaming = TextureStreamer::new(settings.texture_streaming, &queue_families);
+
+        let scene_set_layouts = [camera_set_layout, materials.set_layout, objects.set_layout, lighting.set_layout];
+        let supports_gpu_culling = PhysicalDevice::supports_multi_draw_indirect(&instance, physical_device);
+        // Pulled vertices come from the merged geometry, which only GPU culling draws from
+        let vertex_pulling = settings.vertex_pulling && supports_gpu_culling;
+        if settings.vertex_pulling && !vertex_pulling {
+            tracing::warn!("Vertex pulling needs GPU culling, using vertex input");
+        }
+        let specialization = settings.parallax.scene_specialization();
+        let scene_config = Self::scene_pipeline_config(&scene_set_layouts, &specialization, shading_rate_support.is_some(), vertex_pulling);
+        let pipeline = Pipeline::new(&logical_device, &swapchain, &post_process.scene_target.renderpass, &scene_config)?;
+        let lightmapped_pipeline = lightmapped_pipeline(&logical_device, &swapchain, &post_process.scene_target.renderpass, &scene_config)?;
+        let cutout_pipeline = cutout_pipeline(&logical_device, &swapchain, &post_process.scene_target.renderpass, &scene_config)?;
+        let transparent_pipelines = TransparentPipelines::new(&logical_device, &swapchain, &post_process.scene_target.renderpass, &scene_config)?;
+
+        let scatter = Scatter::new(&logical_device, &swapchain, &post_process.scene_target.renderpass, &scene_config, camera_set_layout)?;
+
+        let outline = Outline::new(&logical_device, &swapchain, &post_process.scene_target.renderpass, camera_set_layout)?;
+        let occlusion_queries = OcclusionQueries::new(&logical_device, &swapchain, &post_process.scene_target.renderpass, camera_set_layout,
+            physical_device_features.occlusion_query_precise == vk::TRUE)?;
+        let conditional_rendering = conditional_rendering::supported(&instance, physical_device)
+            .then(|| ConditionalRendering::new(&instance, &logical_device, &mut allocator));
+        let gizmo = Gizmo::new(&logical_device, &mut allocator, &swapchain, &renderpass, camera_set_layout)?;
+        let pixels_per_point = window.map_or(1.0, |window| window.window.scale_factor() as f32);
+        let ui = Ui::new(&logical_device, &swapchain, &renderpass, descriptor_pool, &upload_ring, pixels_per_point)?;
+        let sdf_text = SdfText::new(&logical_device, &swapchain, &renderpass, descriptor_pool, &upload_ring)?;
+
+        let mut descriptors = DescriptorAllocator::new(64, &DEFAULT_POOL_RATIOS);
+        let water = Water::new(&logical_device, &mut allocator, &mut descriptors, &pools, queues.graphics_queue, &swapchain,
+            &post_process.scene_target, &scene_config)?;
+        let clouds = Clouds::new(&logical_device, &mut allocator, &mut descriptors, &pools, queues.graphics_queue, &swapchain,
+            post_process.scene_target.extent, camera_set_layout)?;
+        let sky = Sky::new(&logical_device, &swapchain, &post_process.scene_target, &scene_config, clouds.set_layout())?;
+        let mut light_probes = LightProbes::new(&logical_device, &mut allocator, &mut descriptors, &swapchain, &scene_set_layouts)?;
+        light_probes.quality = settings.gi;
+        let gpu_culling = match supports_gpu_culling {
+            true => Some(GpuCulling::new(&logical_device, &mut allocator, &mut descriptors, swapchain.image_count, camera_set_layout,
+                PhysicalDevice::supports_indirect_count(&instance, physical_device))?),
+            false => {
+                tracing::warn!("Device can't multi draw indirect, GPU culling is off");
+                None
+            }
+        };
+        lighting.write_light_probes(&logical_device, &light_probes);
+        let shading_rate = match shading_rate_support {
+            Some(support) => {
+                let mut shading_rate = VariableRateShading::new(&instance, &logical_device, &mut descriptors, support, settings.shading_rate)?;
+                shading_rate.write_target(&logical_device, &post_process.scene_target);
+                Some(shading_rate)
+            },
+            None => {
+                if settings.shading_rate != ShadingRateMode::Off {
+                    tracing::warn!("Device has no variable rate shading, everything is shaded per pixel");
+                }
+                None
+            }
+        };
+
+        let frame_descriptors = (0..swapchain.image_count).map(|_| DescriptorAllocator::new(32, &DEFAULT_POOL_RATIOS)).collect();
+
+        let command_buffers = Self::create_commandbuffers(&logical_device, &mut pools, swapchain.image_count)?;
+        let image_fences = vec![vk::Fence::null(); swapchain.image_count];
+
+        
+        Ok(Self {
+            entry,
+            instance,
+            is_framebuffer_resized: false,
+            window_extent,
+            full_screen_exclusive,
+            vsync: settings.vsync,
+            transparent,
+            debug,
+            surface,
+            suspended: false,
+            physical_device,
+            physical_device_properties,
+            physical_device_features,
+            queue_families,
+            queues,
+            device: logical_device,
+            swapchain,
+            viewport_mode: ViewportMode::Fill,
+            viewport,
+            renderpass,
+            pipeline,
+            lightmapped_pipeline,
+            cutout_pipeline,
+            transparent_pipelines,
+            water,
+            virtual_texture: None,
+            sky,
+            clouds,
+            scatter,
+            pools,
+            image_fences,
+            acquired_image: None,
+            command_buffers,
+            allocator: std::mem::ManuallyDrop::new(allocator),
+            descriptor_pool,
+            descriptors,
+            frame_descriptors,
+            camera_set_layout,
+            camera_buffers,
+            camera_sets,
+            camera,
+            split_views: vec![],
+            reflection: None,
+            cubemaps: vec![],
+            thumbnails: None,
+            reflection_probes,
+            light_probes,
+            gpu_culling,
+            vertex_pulling,
+            parallax: settings.parallax,
+            shading_rate,
+            post_process,
+            lighting,
+            upload_ring,
+            objects,
+            materials,
+            texture_streaming,
+            previous_view_projection: None,
+            anti_aliasing: AntiAliasing::None,
+            frame_index: 0,
+            animation_start: std::time::Instant::now(),
+            capture: None,
+            crash_diagnostics,
+            #[cfg(feature = "tracy")]
+            gpu_profiler,
+            wind: Wind::default(),
+            lights: vec![],
+            spot_lights: vec![],
+            shadows,
+            light_cookies,
+            ambient_light: uv::Vec3::broadcast(0.15),
+            sun: None,
+            atmosphere: None,
+            hooks: RenderHooks::default(),
+            outline,
+            occlusion_queries,
+            conditional_rendering,
+            gizmo,
+            ui,
+            sdf_text,
+            selected: None,
+            game_objects: vec![]
+        })
+    }
+
+    pub fn enable_planar_reflection(&mut self, plane: ReflectionPlane) -> Result<(), vk::Result> {
+        if let Some(reflection) = &mut self.reflection {
+            reflection.plane = plane;
+            return Ok(());
+        }
+
+        let scene_set_layouts = self.scene_set_layouts();
+        let reflection = PlanarReflection::new(&self.device, &mut self.allocator, &self.swapchain, self.viewport.render_extent,
+            &self.post_process.scene_target.renderpass, self.descriptor_pool, &scene_set_layouts, plane)?;
+        // Reflective objects aren't drawn without a reflection, so their sets aren't in use. Water only samples it with
+        // planar reflections, which need one anyway.
+        self.materials.set_texture(&self.device, Material::Reflective, reflection.target.descriptor_info(0));
+        self.materials.set_texture(&self.device, Material::Water, reflection.target.descriptor_info(0));
+        self.reflection = Some(reflection);
+
+        Ok(())
+    }
+
+    // Splits the viewport between the cameras, replacing any previous split. Without cameras the main camera fills it again.
+    pub fn set_split_screen(&mut self, cameras: Vec<Camera>) -> Result<(), vk::Result> {
+        self.clear_split_views()?;
+        let areas = ViewArea::split(cameras.len());
+        for (camera, area) in cameras.into_iter().zip(areas) {
+            self.add_split_view(camera, area)?;
+        }
+
+        Ok(())
+    }
+
+    // Index of the new view in `split_views`. Views draw in order, a later one with `ClearSettings::overlay` draws over the
+    // earlier ones. Whatever no view covers keeps the first view's clear color.
+    pub fn add_split_view(&mut self, camera: Camera, area: ViewArea) -> Result<usize, vk::Result> {
+        let view = SplitView::new(&self.device, &mut self.allocator, self.descriptor_pool, self.camera_set_layout, self.swapchain.image_count,
+            camera, area)?;
+        self.split_views.push(view);
+
+        Ok(self.split_views.len() - 1)
+    }
+
+    // Waits for the device, the view's camera is returned
+    pub fn remove_split_view(&mut self, index: usize) -> Result<Camera, vk::Result> {
+        unsafe { self.device.device_wait_idle()? };
+        let mut view = self.split_views.remove(index);
+        view.destroy(&self.device, &mut self.allocator, self.descriptor_pool)?;
+
+        Ok(view.camera)
+    }
+
+    fn clear_split_views(&mut self) -> Result<(), vk::Result> {
+        if self.split_views.is_empty() {
+            return Ok(());
+        }
+
+        unsafe { self.device.device_wait_idle()? };
+        for mut view in self.split_views.drain(..) {
+            view.destroy(&self.device, &mut self.allocator, self.descriptor_pool)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn enable_ssr(&mut self, settings: SsrSettings) -> Result<(), vk::Result> {
+        let effect = match self.post_process.effect_mut(SsrSettings::NAME) {
+            Some(effect) => effect,
+            None => self.post_process.add_effect(&self.device, &self.swapchain, SsrSettings::NAME, SSR_FRAG,
+                std::mem::size_of::<SsrSettings>() as u32)?
+        };
+        effect.enabled = true;
+        effect.set_push_constants(&settings);
+
+        Ok(())
+    }
+
+    // Camera, lights and per object data, the sets every scene pipeline binds
+    // In the order of FRAME_SET, MATERIAL_SET, OBJECT_SET and LIGHTING_SET
+    pub fn scene_set_layouts(&self) -> [vk::DescriptorSetLayout; 4] {
+        [self.camera_set_layout, self.materials.set_layout, self.objects.set_layout, self.lighting.set_layout]
+    }
+
+    // Shared by the basic and lightmapped scene pipelines
+    fn scene_pipeline_config<'a>(scene_set_layouts: &'a [vk::DescriptorSetLayout], specialization: &'a [SpecializationConstant],
+        dynamic_shading_rate: bool, vertex_pulling: bool
+    ) -> PipelineConfig<'a> {
+        PipelineConfig {
+            specialization,
+            dynamic_shading_rate,
+            ..PipelineConfig::basic(scene_set_layouts)
+        }.with_vertex_pulling(vertex_pulling)
+    }
+
+    fn recreate_scene_pipelines(&mut self) -> Result<(), vk::Result> {
+        let scene_set_layouts = self.scene_set_layouts();
+        let specialization = self.parallax.scene_specialization();
+        let scene_config = Self::scene_pipeline_config(&scene_set_layouts, &specialization, self.shading_rate.is_some(), self.vertex_pulling);
+        let pipeline = Pipeline::new(&self.device, &self.swapchain, &self.post_process.scene_target.renderpass, &scene_config)?;
+        let lightmapped_pipeline = lightmapped_pipeline(&self.device, &self.swapchain, &self.post_process.scene_target.renderpass, &scene_config)?;
+        let cutout_pipeline = cutout_pipeline(&self.device, &self.swapchain, &self.post_process.scene_target.renderpass, &scene_config)?;
+        let transparent_pipelines = TransparentPipelines::new(&self.device, &self.swapchain, &self.post_process.scene_target.renderpass,
+            &scene_config)?;
+        let scatter_pipelines = ScatterPipelines::new(&self.device, &self.swapchain, &self.post_process.scene_target.renderpass, &scene_config,
+            self.scatter.set_layout())?;
+        let water_pipeline = Water::create_pipeline(&self.device, &self.swapchain, &self.post_process.scene_target, &scene_config,
+            self.water.set_layout())?;
+        let sky_pipeline = Sky::create_pipeline(&self.device, &self.swapchain, &self.post_process.scene_target, &scene_config,
+            self.clouds.set_layout())?;
+        self.pipeline = pipeline;
+        self.lightmapped_pipeline = lightmapped_pipeline;
+        self.cutout_pipeline = cutout_pipeline;
+        self.transparent_pipelines = transparent_pipelines;
+        self.water.pipeline = water_pipeline;
+        self.sky.pipeline = sky_pipeline;
+        self.scatter.pipelines = scatter_pipelines;
+        if let Some(virtual_texture) = &mut self.virtual_texture {
+            virtual_texture.recreate_pipelines(&self.device, &self.swapchain, &self.post_process.scene_target, &scene_config)?;
+        }
+        Ok(())
+    }
+
+    pub fn parallax_quality(&self) -> ParallaxQuality {
+        self.parallax
+    }
+
+    // Rebuilds the scene pipelines with the new step counts, waiting for the device to stop using the old ones
+    pub fn set_parallax_quality(&mut self, quality: ParallaxQuality) -> Result<(), vk::Result> {
+        if quality == self.parallax {
+            return Ok(());
+        }
+
+        unsafe { self.device.device_wait_idle()? };
+        self.pipeline.cleanup(&self.device);
+        self.lightmapped_pipeline.cleanup(&self.device);
+        self.cutout_pipeline.cleanup(&self.device);
+        self.transparent_pipelines.cleanup(&self.device);
+        self.water.pipeline.cleanup(&self.device);
+        self.scatter.pipelines.cleanup(&self.device);
+        self.sky.pipeline.cleanup(&self.device);
+        if let Some(virtual_texture) = &mut self.virtual_texture {
+            virtual_texture.cleanup_pipelines(&self.device);
+        }
+        self.parallax = quality;
+        self.recreate_scene_pipelines()
+    }
+
+    pub fn set_anti_aliasing(&mut self, anti_aliasing: AntiAliasing) -> Result<(), vk::Result> {
+        if anti_aliasing == AntiAliasing::Taa {
+            self.post_process.enable_taa(&self.device, &mut self.allocator, &self.swapchain, &self.pools, self.queues.graphics_queue,
+                self.descriptor_pool, self.frame_index)?;
+        } else {
+            self.post_process.disable_taa(&self.device, &mut self.allocator, self.descriptor_pool)?;
+        }
+
+        if anti_aliasing == AntiAliasing::Fxaa && self.post_process.effect_mut(FxaaSettings::NAME).is_none() {
+            let effect = self.post_process.add_effect(&self.device, &self.swapchain, FxaaSettings::NAME, FXAA_FRAG,
+                std::mem::size_of::<FxaaSettings>() as u32)?;
+            effect.set_push_constants(&FxaaSettings::default());
+        }
+        if let Some(effect) = self.post_process.effect_mut(FxaaSettings::NAME) {
+            effect.enabled = anti_aliasing == AntiAliasing::Fxaa;
+        }
+
+        self.anti_aliasing = anti_aliasing;
+
+        Ok(())
+    }
+
+    pub fn enable_motion_blur(&mut self, settings: MotionBlurSettings) -> Result<(), vk::Result> {
+        let effect = match self.post_process.effect_mut(MotionBlurSettings::NAME) {
+            Some(effect) => effect,
+            None => self.post_process.add_effect(&self.device, &self.swapchain, MotionBlurSettings::NAME, MOTION_BLUR_FRAG,
+                std::mem::size_of::<MotionBlurSettings>() as u32)?
+        };
+        effect.enabled = true;
+        effect.set_push_constants(&settings);
+
+        Ok(())
+    }
+
+    // Focus and aperture come from the camera, the settings only control quality
+    pub fn enable_dof(&mut self, settings: DofSettings) -> Result<(), vk::Result> {
+        let effect = match self.post_process.effect_mut(DofSettings::NAME) {
+            Some(effect) => effect,
+            None => self.post_process.add_effect(&self.device, &self.swapchain, DofSettings::NAME, DOF_FRAG,
+                std::mem::size_of::<DofSettings>() as u32)?
+        };
+        effect.enabled = true;
+        effect.set_push_constants(&settings);
+
+        Ok(())
+    }
+
+    pub fn enable_god_rays(&mut self, settings: GodRaySettings) -> Result<(), vk::Result> {
+        let effect = match self.post_process.effect_mut(GodRaySettings::NAME) {
+            Some(effect) => effect,
+            None => self.post_process.add_effect(&self.device, &self.swapchain, GodRaySettings::NAME, GOD_RAYS_FRAG,
+                std::mem::size_of::<GodRaySettings>() as u32)?
+        };
+        effect.enabled = true;
+        effect.set_push_constants(&settings);
+
+        Ok(())
+    }
+
+    // Exposure and lift/gamma/gain are applied by the composite pass every frame
+    pub fn set_composite_settings(&mut self, settings: CompositeSettings) {
+        self.post_process.composite_settings = settings;
+    }
+
+    pub fn enable_lens_flare(&mut self, settings: LensFlareSettings) -> Result<(), vk::Result> {
+        self.post_process.enable_lens_flare(&self.device, &self.swapchain, settings)
+    }
+
+    pub fn disable_lens_flare(&mut self) -> Result<(), vk::Result> {
+        self.post_process.disable_lens_flare(&self.device)
+    }
+
+    // The atmosphere's sun while it's above the horizon, `sun` otherwise
+    fn directional_light(&self) -> Option<DirectionalLight> {
+        match &self.atmosphere {
+            Some(atmosphere) if atmosphere.sun_direction.y > 0.0 => Some(atmosphere.sun_light()),
+            _ => self.sun
+        }
+    }
+
+    // The sun (or whichever directional light is up) and the brightest point lights. Post processing only sees the
+    // main camera, so split views get none.
+    fn lens_flare_sources(&self) -> Vec<FlareSource> {
+        let lens_flare = match self.post_process.lens_flare.as_ref().filter(|_| self.split_views.is_empty()) {
+            Some(lens_flare) => lens_flare,
+            None => return Vec::new()
+        };
+
+        let sun = self.directional_light();
+        let mut lights: Vec<&PointLight> = self.lights.iter().filter(|light| light.intensity >= lens_flare.settings.threshold).collect();
+        lights.sort_by(|a, b| b.intensity.total_cmp(&a.intensity));
+
+        sun.filter(|sun| sun.intensity > 0.0)
+            .map(|sun| FlareSource {
+                position: uv::Vec4::new(sun.direction.x, sun.direction.y, sun.direction.z, 0.0),
+                color: sun.color * sun.intensity,
+                size: 1.0
+            })
+            .into_iter()
+            .chain(lights.into_iter().map(|light| FlareSource {
+                position: light.position.into_homogeneous_point(),
+                color: light.color * light.intensity,
+                size: 0.5
+            }))
+            .take(lens_flare.settings.max_sources)
+            .collect()
+    }
+
+    // The scene analysis takes over the settings' histogram range
+    pub fn enable_auto_exposure(&mut self, settings: AutoExposureSettings) {
+        self.post_process.analysis.min_log_luminance = settings.min_log_luminance;
+        self.post_process.analysis.max_log_luminance = settings.max_log_luminance;
+        self.post_process.auto_exposure.enable(settings, self.frame_index);
+    }
+
+    pub fn disable_auto_exposure(&mut self) -> Result<(), vk::Result> {
+        self.post_process.auto_exposure.disable(&self.device)
+    }
+
+    // Counts how much of `target` the main camera sees, `None` once MAX_OCCLUSION_QUERIES are in use.
+    // Results arrive with the frames in flight of delay, see `occlusion_result`.
+    pub fn add_occlusion_query(&mut self, target: OcclusionTarget) -> Option<OcclusionQuery> {
+        let query = self.occlusion_queries.add(target)?;
+        if let Some(conditional_rendering) = &mut self.conditional_rendering {
+            conditional_rendering.reset(query);
+        }
+        Some(query)
+    }
+
+    pub fn set_occlusion_target(&mut self, query: OcclusionQuery, target: OcclusionTarget) {
+        self.occlusion_queries.set_target(query, target);
+    }
+
+    pub fn remove_occlusion_query(&mut self, query: OcclusionQuery) {
+        self.occlusion_queries.remove(query);
+    }
+
+    pub fn occlusion_result(&self, query: OcclusionQuery) -> Option<OcclusionResult> {
+        self.occlusion_queries.result(query)
+    }
+
+    // Tests the object's mesh against the depth of every frame and skips its scene draws in the next one while it's hidden.
+    // Without conditional rendering or with GPU culling the object is still drawn, the query's results are there either way.
+    pub fn enable_occlusion_culling(&mut self, id: usize) -> Option<OcclusionQuery> {
+        let index = self.game_objects.iter().position(|game_object| game_object.get_id() == id)?;
+        if let Some(query) = self.game_objects[index].occlusion_query {
+            return Some(query);
+        }
+
+        let query = self.add_occlusion_query(OcclusionTarget::Object(id))?;
+        self.game_objects[index].occlusion_query = Some(query);
+        Some(query)
+    }
+
+    pub fn disable_occlusion_culling(&mut self, id: usize) {
+        let query = self.game_objects
+            .iter_mut()
+            .find(|game_object| game_object.get_id() == id)
+            .and_then(|game_object| game_object.occlusion_query.take());
+        if let Some(query) = query {
+            self.occlusion_queries.remove(query);
+        }
+    }
+
+    // Histogram, min/max/average luminance and average color of the HDR scene, e.g. to tell how dark the screen is.
+    // Analyzed while this or auto exposure is enabled, the results trail the screen by the frames in flight.
+    pub fn enable_scene_analysis(&mut self) {
+        self.post_process.analysis.enabled = true;
+    }
+
+    pub fn disable_scene_analysis(&mut self) {
+        self.post_process.analysis.enabled = false;
+    }
+
+    pub fn scene_statistics(&self) -> Option<&ImageStatistics> {
+        self.post_process.analysis.statistics()
+    }
+
+    pub fn set_color_grading_lut(&mut self, lut: &Lut) -> Result<(), vk::Result> {
+        self.post_process.set_lut(&self.device, &mut self.allocator, &self.pools, self.queues.graphics_queue, lut)
+    }
+
+    // Renders the scene around `position` into a cube of `size` pixels per face, sampled through `cubemaps[index].descriptor_info()`
+    pub fn add_cubemap_capture(&mut self, position: uv::Vec3, size: u32, mode: CaptureMode) -> Result<usize, vk::Result> {
+        let scene_set_layouts = self.scene_set_layouts();
+        let capture = CubemapCapture::new(&self.device, &mut self.allocator, &mut self.descriptors, &self.swapchain, &scene_set_layouts,
+            position, size, mode)?;
+        self.cubemaps.push(capture);
+
+        Ok(self.cubemaps.len() - 1)
+    }
+
+    pub fn remove_cubemap_capture(&mut self, index: usize) -> Result<(), vk::Result> {
+        unsafe { self.device.device_wait_idle()? };
+        let mut capture = self.cubemaps.remove(index);
+        capture.destroy(&self.device, &mut self.allocator, &mut self.descriptors)
+    }
+
+    // The probe is captured and prefiltered in the next frame, call `bake_reflection_probe` when the scene around it changes
+    pub fn add_reflection_probe(&mut self, probe: ReflectionProbe) -> Result<usize, vk::Result> {
+        let scene_set_layouts = self.scene_set_layouts();
+        self.reflection_probes.add(&self.device, &mut self.allocator, &mut self.descriptors, &self.swapchain, &scene_set_layouts, probe)
+    }
+
+    pub fn remove_reflection_probe(&mut self, index: usize) -> Result<(), vk::Result> {
+        unsafe { self.device.device_wait_idle()? };
+        self.reflection_probes.remove(&self.device, &mut self.allocator, &mut self.descriptors, index)
+    }
+
+    pub fn bake_reflection_probe(&mut self, index: usize) {
+        self.reflection_probes.bake(index);
+    }
+
+    // Bakes the grid over the next frames, one probe per frame. `None` goes back to the flat `ambient_light`.
+    pub fn set_light_probe_grid(&mut self, grid: Option<LightProbeGrid>) {
+        self.light_probes.set_grid(grid);
+    }
+
+    // Dynamic tiers keep recapturing the probe grid for moving lights, at the cost of six scene passes per probe
+    pub fn set_gi_quality(&mut self, quality: GiQuality) {
+        let was_off = self.light_probes.quality == GiQuality::Off;
+        self.light_probes.quality = quality;
+        // Probes went stale while nothing was captured
+        if was_off && quality != GiQuality::Off {
+            self.light_probes.bake();
+        }
+    }
+
+    // Swaps the game object's mesh for the unwrapped one of the bake and draws it with its lightmap from now on.
+    // The lightmap it had before is destroyed.
+    pub fn apply_lightmap(&mut self, index: usize, baked: &BakedLightmap) -> Result<(), vk::Result> {
+        let mut mesh = Mesh::new(&self.device, &mut self.allocator, baked.vertices.len(), baked.indices.len())?;
+        mesh.update_vertex_buffer(&baked.vertices);
+        mesh.update_index_buffer(&baked.indices);
+        let texture = Texture::from_rgba32f(&self.device, &mut self.allocator, &self.pools, self.queues.graphics_queue, baked.extent(),
+            &baked.texels, "Lightmap")?;
+
+        unsafe { self.device.device_wait_idle()? };
+        let game_object = &mut self.game_objects[index];
+        let mut old_mesh = std::mem::replace(&mut game_object.mesh, mesh);
+        old_mesh.destroy(&self.device, &mut self.allocator);
+        if let Some(old_lightmap) = game_object.lightmap.take() {
+            self.materials.remove_lightmap(&self.device, &mut self.allocator, &mut self.descriptors, old_lightmap)?;
+        }
+
+        let game_object = &mut self.game_objects[index];
+        game_object.lightmap = Some(self.materials.add_lightmap(texture));
+        game_object.material = Material::Lightmapped;
+
+        Ok(())
+    }
+
+    // Samples a color texture (tightly packed RGBA8, sRGB color) with the game object's first UV channel from now on,
+    // tinted by its color. The texture it had before is destroyed.
+    pub fn set_color_texture(&mut self, index: usize, extent: vk::Extent2D, rgba: &[u8]) -> Result<(), vk::Result> {
+        let texture = Texture::from_rgba8(&self.device, &mut self.allocator, &self.pools, self.queues.graphics_queue, extent, rgba,
+            "Color Texture")?;
+
+        self.remove_color_texture(index)?;
+        self.game_objects[index].texture = Some(self.materials.add_color_texture(texture));
+
+        Ok(())
+    }
+
+    // Like `set_color_texture`, with only the mip levels the game objects using it are seen at on the device, see
+    // `TextureStreamer`. The texture starts out blurry and sharpens over the next frames.
+    pub fn stream_color_texture(&mut self, index: usize, extent: vk::Extent2D, rgba: &[u8]) -> Result<(), vk::Result> {
+        self.stream_color_mips(index, MipChain::from_rgba8(extent, rgba))
+    }
+
+    // `stream_color_texture` from a chain made elsewhere, e.g. block compressed, see `supports_texture_compression`
+    pub fn stream_color_mips(&mut self, index: usize, chain: MipChain) -> Result<(), vk::Result> {
+        let (texture, streamed) = self.texture_streaming.create(&self.device, &mut self.allocator, &self.pools, self.queues.transfer_queue,
+            chain)?;
+
+        self.remove_color_texture(index)?;
+        let id = self.materials.add_color_texture(texture);
+        self.texture_streaming.insert(TextureSlot::Color(id), streamed);
+        self.game_objects[index].texture = Some(id);
+
+        Ok(())
+    }
+
+    // Whether streamed textures can be BC7 and BC5, which desktop GPUs sample and most mobile ones don't
+    pub fn supports_texture_compression(&self) -> bool {
+        self.physical_device_features.texture_compression_bc == vk::TRUE
+    }
+
+    fn remove_color_texture(&mut self, index: usize) -> Result<(), vk::Result> {
+        unsafe { self.device.device_wait_idle()? };
+        if let Some(old_texture) = self.game_objects[index].texture.take() {
+            self.texture_streaming.remove(&self.device, &mut self.allocator, &self.pools, TextureSlot::Color(old_texture))?;
+            self.materials.remove_color_texture(&self.device, &mut self.allocator, &mut self.descriptors, old_texture)?;
+        }
+        Ok(())
+    }
+
+    // Samples a tangent space normal map (tightly packed RGBA8) with the game object's first UV channel from now on.
+    // Its alpha is the height parallax traces, 255 at the surface. The normal map it had before is destroyed.
+    pub fn set_normal_map(&mut self, index: usize, extent: vk::Extent2D, rgba: &[u8]) -> Result<(), vk::Result> {
+        let texture = Texture::from_rgba8(&self.device, &mut self.allocator, &self.pools, self.queues.graphics_queue, extent, rgba,
+            "Normal Map")?;
+
+        self.remove_normal_map(index)?;
+        self.game_objects[index].normal_map = Some(self.materials.add_normal_map(texture));
+
+        Ok(())
+    }
+
+    // `set_normal_map` streamed like `stream_color_texture`
+    pub fn stream_normal_map(&mut self, index: usize, extent: vk::Extent2D, rgba: &[u8]) -> Result<(), vk::Result> {
+        self.stream_normal_mips(index, MipChain::from_rgba8(extent, rgba))
+    }
+
+    // `stream_color_mips` for normal maps. BC5 ones only have x and y, the shaders rebuild z and their height is flat.
+    pub fn stream_normal_mips(&mut self, index: usize, chain: MipChain) -> Result<(), vk::Result> {
+        let (texture, streamed) = self.texture_streaming.create(&self.device, &mut self.allocator, &self.pools, self.queues.transfer_queue,
+            chain)?;
+
+        self.remove_normal_map(index)?;
+        let id = self.materials.add_normal_map(texture);
+        self.texture_streaming.insert(TextureSlot::NormalMap(id), streamed);
+        self.game_objects[index].normal_map = Some(id);
+
+        Ok(())
+    }
+
+    fn remove_normal_map(&mut self, index: usize) -> Result<(), vk::Result> {
+        unsafe { self.device.device_wait_idle()? };
+        if let Some(old_normal_map) = self.game_objects[index].normal_map.take() {
+            self.texture_streaming.remove(&self.device, &mut self.allocator, &self.pools, TextureSlot::NormalMap(old_normal_map))?;
+            self.materials.remove_normal_map(&self.device, &mut self.allocator, &mut self.descriptors, old_normal_map)?;
+        }
+        Ok(())
+    }
+
+    // Colors the game objects with `Material::Virtual` from `source` from now on, a texture too large to be resident
+    // (terrain megatextures) that is paged in for what the camera sees, see `VirtualTexture`. The one there was is destroyed.
+    // Only the main view asks for pages, and reflections and captures leave virtual objects out.
+    pub fn set_virtual_texture(&mut self, source: Box<dyn PageSource>) -> Result<(), vk::Result> {
+        if !PhysicalDevice::supports_fragment_stores(&self.instance, self.physical_device) {
+            tracing::warn!("Device can't write storage buffers from fragment shaders, virtual textures are off");
+            return Err(vk::Result::ERROR_FEATURE_NOT_PRESENT);
+        }
+
+        self.remove_virtual_texture()?;
+        let scene_set_layouts = self.scene_set_layouts();
+        let specialization = self.parallax.scene_specialization();
+        let scene_config = Self::scene_pipeline_config(&scene_set_layouts, &specialization, self.shading_rate.is_some(), self.vertex_pulling);
+        self.virtual_texture = Some(VirtualTexture::new(&self.device, &mut self.allocator, &mut self.descriptors, &self.pools, &self.queues,
+            &self.queue_families, self.swapchain.image_count, &self.swapchain, &self.post_process.scene_target, &scene_config, source)?);
+
+        Ok(())
+    }
+
+    // Virtual objects aren't drawn without a virtual texture
+    pub fn remove_virtual_texture(&mut self) -> Result<(), vk::Result> {
+        if let Some(mut virtual_texture) = self.virtual_texture.take() {
+            unsafe { self.device.device_wait_idle()? };
+            virtual_texture.destroy(&self.device, &mut self.allocator, &self.pools, &mut self.descriptors)?;
+        }
+        Ok(())
+    }
+
+    // Spreads copies of `prototype` over the mesh of game object `surface` where it is now, see `ScatterLayer`. They're
+    // drawn from the next frame on until `remove_scatter_layer`.
+    pub fn add_scatter_layer(&mut self, surface: usize, prototype: GameObject, settings: &ScatterSettings) -> Result<usize, vk::Result> {
+        let model = world_matrices(&self.game_objects)[surface];
+        let bounds = prototype.mesh.bounds.unwrap_or(BoundingSphere { center: uv::Vec3::zero(), radius: 1.0 });
+        let instances = scatter_instances(&self.game_objects[surface].mesh, model, bounds, settings);
+        self.scatter.add_layer(&self.device, &mut self.allocator, &mut self.descriptors, self.swapchain.image_count, prototype, &instances,
+            settings)
+    }
+
+    // Destroys the layer and its prototype's mesh after waiting for the device
+    pub fn remove_scatter_layer(&mut self, index: usize) -> Result<(), vk::Result> {
+        unsafe { self.device.device_wait_idle()? };
+        self.scatter.remove_layer(&self.device, &mut self.allocator, &mut self.descriptors, index)
+    }
+
+    // Replaces the cookie texture array, `SpotLight::cookie` indexes into these layers (tightly packed RGBA8)
+    pub fn set_light_cookies(&mut self, extent: vk::Extent2D, layers: &[&[u8]]) -> Result<(), vk::Result> {
+        let cookies = Texture::from_rgba8_array(&self.device, &mut self.allocator, &self.pools, self.queues.graphics_queue,
+            extent, layers, "Light Cookies")?;
+
+        unsafe { self.device.device_wait_idle()? };
+        self.light_cookies.destroy(&self.device, &mut self.allocator);
+        self.light_cookies = cookies;
+        self.lighting.write_cookies(&self.device, self.light_cookies.descriptor_info());
+
+        Ok(())
+    }
+
+    // Overwrites cookie layers starting at `first_layer` in place, the extent and layer count stay the same
+    pub fn update_light_cookies(&mut self, first_layer: u32, layers: &[&[u8]]) -> Result<(), vk::Result> {
+        unsafe { self.device.device_wait_idle()? };
+        self.light_cookies.update_layers(&self.device, &mut self.allocator, &self.pools, self.queues.graphics_queue, first_layer, layers)
+    }
+
+    // Surface extensions are only enabled with a window to present to
+    pub fn create_instance(entry: &ash::Entry, layer_names: &[&str], window: Option<&VulkanWindow>, factory: Option<&dyn DeviceFactory>
+    ) -> Result<ash::Instance, vk::Result> {
+        let app_name = std::ffi::CString::new("Reverie Engine").unwrap();
+        let engine_name = std::ffi::CString::new("Reverie").unwrap();
+
+        let app_info = vk::ApplicationInfo::builder()
+            .application_name(&app_name)
+            .engine_name(&engine_name)
+            .application_version(vk::make_api_version(0, 0, 1, 0))
+            .engine_version(vk::make_api_version(0, 0, 1, 0))
+            .api_version(vk::API_VERSION_1_3);
+
+        let layer_names: Vec<std::ffi::CString> = layer_names
+            .iter()
+            .map(|&ln| std::ffi::CString::new(ln).unwrap())
+            .collect();
+        
+        let layer_name_pointers: Vec<*const i8> = layer_names
+            .iter()
+            .map(|layer_name| layer_name.as_ptr())
+            .collect();
+
+        let mut extension_name_pointers: Vec<*const i8> = 
+            vec![
+                ash::extensions::ext::DebugUtils::name().as_ptr(),
+            ];
+        let (portability_extensions, portability_flags) = portability::instance_extensions(entry);
+        extension_name_pointers.extend(portability_extensions.iter().map(|ext| ext.as_ptr()));
+        if window.is_some() {
+            extension_name_pointers.extend(full_screen_exclusive::instance_extensions(entry).iter().map(|ext| ext.as_ptr()));
+        }
+        // The window's handle decides between VK_KHR_wayland_surface and VK_KHR_xlib_surface, see `WindowBackend`
+        if let Some(window) = window {
+            let required_surface_extensions = ash_window::enumerate_required_extensions(&window.window).unwrap();
+            extension_name_pointers.extend(required_surface_extensions.iter().copied());
+        }
+
+        for ext in extension_name_pointers.iter() {
+            tracing::debug!("Using instance extension {}", unsafe { std::ffi::CStr::from_ptr(*ext).to_string_lossy() });
+        }
+
+        let create_flags = vk::InstanceCreateFlags::default() | portability_flags;
+
+        let create_info = vk::InstanceCreateInfo::builder()
+            .application_info(&app_info)
+            .enabled_layer_names(&layer_name_pointers)
+            .enabled_extension_names(&extension_name_pointers)
+            .flags(create_flags);
+
+        match factory {
+            Some(factory) => factory.create_instance(entry, &create_info),
+            None => unsafe { entry.create_instance(&create_info, None) }
+        }
+    }
+
+    pub fn recreate_swapchain(&mut self) {
+        unsafe {
+            self.device 
+                .device_wait_idle()
+                .expect("Failed to wait device idle (recreate swapchain)!")
+        };
+
+        // Its upload's command buffer goes with the transfer pool
+        if let Some(virtual_texture) = &mut self.virtual_texture {
+            virtual_texture.flush(&self.device, &mut self.allocator, &self.pools, self.frame_index)
+                .expect("Failed to finish virtual texture upload!");
+        }
+
+        unsafe {
+            self.pools.free_frame_command_buffers(&self.device, &self.command_buffers);
+            self.pools.cleanup(&self.device);
+            self.pipeline.cleanup(&self.device);
+            self.lightmapped_pipeline.cleanup(&self.device);
+            self.cutout_pipeline.cleanup(&self.device);
+            self.transparent_pipelines.cleanup(&self.device);
+            self.water.pipeline.cleanup(&self.device);
+            self.scatter.pipelines.cleanup(&self.device);
+            self.sky.pipeline.cleanup(&self.device);
+            if let Some(virtual_texture) = &mut self.virtual_texture {
+                virtual_texture.cleanup_pipelines(&self.device);
+            }
+            RenderPass::cleanup(&self.device, self.renderpass);
+            self.swapchain.cleanup(&self.device, &mut self.allocator);
+        }
+
+        // Offscreen images keep their size, there's no window to follow
+        self.swapchain = match &self.surface {
+            Some(surface) => VulkanSwapchain::new(&self.instance, self.physical_device, &self.device, surface, &self.queue_families, self.vsync,
+                self.transparent, self.window_extent, self.full_screen_exclusive.as_ref()),
+            None => VulkanSwapchain::offscreen(&self.device, &mut self.allocator, self.swapchain.extent, self.swapchain.image_count)
+        }.expect("Failed to recreate swapchain.");
+
+        self.renderpass = Self::create_present_renderpass(&self.device, &self.swapchain)
+            .expect("Failed to recreate renderpass.");
+
+        self.swapchain.create_framebuffers(&self.device, self.renderpass)
+            .expect("Failed to recreate framebuffers.");
+
+        self.pools = Pools::new(&self.device, &self.queue_families)
+            .expect("Failed to recreate pipeline.");
+
+        self.viewport = ViewportLayout::new(self.viewport_mode, self.swapchain.extent);
+        self.recreate_render_targets()
+            .expect("Failed to recreate render targets.");
+
+        self.recreate_scene_pipelines()
+            .expect("Failed to recreate pipeline.");
+
+        self.command_buffers = Self::create_commandbuffers(&self.device, &mut self.pools, self.swapchain.image_count)
+            .expect("Failed to recreate command_buffers.");
+        // The device is idle and the image acquired for the old swapchain is gone with it
+        self.image_fences = vec![vk::Fence::null(); self.swapchain.image_count];
+        self.acquired_image = None;
+    }
+
+    // Android takes the window away whenever the app goes to the background, the swapchain and surface have to go with it.
+    // Frames are skipped until `resume` creates them again for the new window. Offscreen renderers have nothing to give up.
+    pub fn suspend(&mut self) -> Result<(), vk::Result> {
+        let mut surface = match self.surface.take() {
+            Some(surface) => surface,
+            None => return Ok(())
+        };
+
+        unsafe {
+            self.device.device_wait_idle()?;
+            self.swapchain.release_surface(&self.device);
+            surface.cleanup();
+        }
+        self.suspended = true;
+
+        Ok(())
+    }
+
+    pub fn resume(&mut self, window: &VulkanWindow) -> Result<(), vk::Result> {
+        if !self.suspended {
+            return Ok(());
+        }
+
+        self.surface = Some(VulkanSurface::new(window, &self.entry, &self.instance)?);
+        self.window_extent = window.extent();
+        self.suspended = false;
+        self.recreate_swapchain();
+
+        Ok(())
+    }
+
+    pub fn is_suspended(&self) -> bool {
+        self.suspended
+    }
+
+    // `mesh` alone with studio lighting in a `size` by `size` image, see `ThumbnailRenderer::render`. Waits for the GPU.
+    pub fn render_thumbnail(&mut self, mesh: &Mesh, color: uv::Vec3, roughness: f32, size: u32) -> Result<Vec<u8>, vk::Result> {
+        if self.thumbnails.as_ref().is_some_and(|thumbnails| thumbnails.size != size) {
+            unsafe { self.device.device_wait_idle()? };
+            if let Some(mut thumbnails) = self.thumbnails.take() {
+                thumbnails.destroy(&self.device, &mut self.allocator);
+            }
+        }
+        let thumbnails = match &mut self.thumbnails {
+            Some(thumbnails) => thumbnails,
+            None => self.thumbnails.insert(ThumbnailRenderer::new(&self.device, &mut self.allocator, &self.swapchain, size)?)
+        };
+
+        thumbnails.render(&self.device, &mut self.allocator, &self.pools, self.queues.graphics_queue, mesh, color, roughness)
+    }
+
+    // Queried from the device on every call, keep the result around instead of calling this per frame
+    pub fn adapter_info(&self) -> AdapterInfo {
+        AdapterInfo::query(&self.entry, &self.instance, self.physical_device)
+    }
+
+    // The renderer's device behind the backend independent `hal` traits, for code that shouldn't depend on ash
+    pub fn hal_device(&mut self) -> AshDevice<'_> {
+        AshDevice::new(&self.device, &mut self.allocator, &self.pools, self.queues.graphics_queue)
+    }
+
+    // X11 and most other platforms report the new size through the surface and the swapchain goes out of date on its own,
+    // Wayland doesn't, so the window's `Resized` events have to end up here
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.window_extent = vk::Extent2D { width, height };
+        self.is_framebuffer_resized = true;
+    }
+
+    // Called after the window went in or out of exclusive fullscreen, the swapchain is recreated to take or give up the
+    // display where VK_EXT_full_screen_exclusive is available
+    pub fn update_fullscreen(&mut self, window: &VulkanWindow) {
+        self.window_extent = window.extent();
+        if let Some(full_screen_exclusive) = &mut self.full_screen_exclusive {
+            if full_screen_exclusive.update(window) {
+                self.is_framebuffer_resized = true;
+            }
+        }
+    }
+
+    // Only the targets the scene renders into are rebuilt when their size changes, the swapchain stays
+    pub fn set_viewport_mode(&mut self, mode: ViewportMode) -> Result<(), vk::Result> {
+        let viewport = ViewportLayout::new(mode, self.swapchain.extent);
+        let resized = viewport.render_extent != self.viewport.render_extent;
+        self.viewport_mode = mode;
+        self.viewport = viewport;
+
+        if resized {
+            unsafe { self.device.device_wait_idle()? };
+            self.recreate_render_targets()?;
+        }
+
+        Ok(())
+    }
+
+    // Scene, post processing and reflection targets at `viewport.render_extent`, the device has to be idle
+    fn recreate_render_targets(&mut self) -> Result<(), vk::Result> {
+        let extent = self.viewport.render_extent;
+        self.post_process.recreate(&self.device, &mut self.allocator, extent, &self.pools, self.queues.graphics_queue, self.frame_index)?;
+        self.water.resize(&self.device, &mut self.allocator, &self.pools, self.queues.graphics_queue, extent)?;
+        if let Some(virtual_texture) = &mut self.virtual_texture {
+            virtual_texture.resize(&self.device, &mut self.allocator, extent)?;
+        }
+        self.clouds.resize(&self.device, &mut self.allocator, &self.pools, self.queues.graphics_queue, extent)?;
+        if let Some(shading_rate) = &mut self.shading_rate {
+            shading_rate.write_target(&self.device, &self.post_process.scene_target);
+        }
+
+        let scene_set_layouts = self.scene_set_layouts();
+        if let Some(reflection) = &mut self.reflection {
+            reflection.recreate(&self.device, &mut self.allocator, &self.swapchain, extent, &self.post_process.scene_target.renderpass, &scene_set_layouts)?;
+            self.materials.set_texture(&self.device, Material::Reflective, reflection.target.descriptor_info(0));
+            self.materials.set_texture(&self.device, Material::Water, reflection.target.descriptor_info(0));
+        }
+
+        self.camera.aspect_ratio = self.viewport.aspect_ratio();
+        // The history no longer lines up with the new pixels
+        self.previous_view_projection = None;
+
+        Ok(())
+    }
+
+    // Ends in the layout the swapchain images are used in next, presenting or copying them out
+    fn create_present_renderpass(device: &ash::Device, swapchain: &VulkanSwapchain) -> Result<vk::RenderPass, vk::Result> {
+        match swapchain.is_offscreen() {
+            true => RenderPass::init_readback(device, swapchain.surface_format.format),
+            false => RenderPass::init(device, swapchain.surface_format.format)
+        }
+    }
+
+    // RGBA8 pixels of the frame last drawn by an offscreen renderer, row by row from the top left. Renderers presenting
+    // to a window have nothing to read back and return ERROR_FEATURE_NOT_PRESENT.
+    pub fn read_pixels(&mut self) -> Result<Vec<u8>, vk::Result> {
+        let image = match self.swapchain.offscreen_images.get(self.swapchain.current_image) {
+            Some(image) => image,
+            None => return Err(vk::Result::ERROR_FEATURE_NOT_PRESENT)
+        };
+
+        unsafe { self.device.device_wait_idle()? };
+
+        let size = image.extent.width as u64 * image.extent.height as u64 * 4;
+        let mut staging_buffer = StagingBuffer::new(&self.device, &mut self.allocator, size);
+        let result = self.pools.one_time_submit(&self.device, self.queues.graphics_queue, |command_buffer| {
+            // The present render pass already left the image in TRANSFER_SRC_OPTIMAL
+            let regions = [vk::BufferImageCopy::builder()
+                .image_subresource(vk::ImageSubresourceLayers {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    mip_level: 0,
+                    base_array_layer: 0,
+                    layer_count: 1
+                })
+                .image_extent(vk::Extent3D { width: image.extent.width, height: image.extent.height, depth: 1 })
+                .build()
+            ];
+            unsafe {
+                self.device.cmd_copy_image_to_buffer(command_buffer, image.image, vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    staging_buffer.get_buffer(), &regions);
+            }
+        });
+
+        let mut pixels = vec![0; size as usize];
+        staging_buffer.read_buffer(0, &mut pixels);
+        staging_buffer.destroy(&self.device, &mut self.allocator);
+        result?;
+
+        // Offscreen images are BGRA
+        for pixel in pixels.chunks_exact_mut(4) {
+            pixel.swap(0, 2);
+        }
+        Ok(pixels)
+    }
+
+    // Copies `source` of the frame last drawn by an offscreen renderer to the top left of `target`, a B8G8R8A8 image that
+    // is in COLOR_ATTACHMENT_OPTIMAL before and after, like the swapchain images of an OpenXR runtime. Waits for the copy.
+    pub fn copy_frame_to(&self, source: vk::Rect2D, target: vk::Image) -> Result<(), vk::Result> {
+        let image = match self.swapchain.offscreen_images.get(self.swapchain.current_image) {
+            Some(image) => image,
+            None => return Err(vk::Result::ERROR_FEATURE_NOT_PRESENT)
+        };
+
+        let subresource_range = vk::ImageSubresourceRange {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            base_mip_level: 0,
+            level_count: 1,
+            base_array_layer: 0,
+            layer_count: 1
+        };
+        let barrier = |old_layout, new_layout, src_access_mask, dst_access_mask| vk::ImageMemoryBarrier::builder()
+            .old_layout(old_layout)
+            .new_layout(new_layout)
+            .src_access_mask(src_access_mask)
+            .dst_access_mask(dst_access_mask)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .image(target)
+            .subresource_range(subresource_range)
+            .build();
+
+        self.pools.one_time_submit(&self.device, self.queues.graphics_queue, |command_buffer| {
+            let subresource = vk::ImageSubresourceLayers {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                mip_level: 0,
+                base_array_layer: 0,
+                layer_count: 1
+            };
+            let regions = [vk::ImageCopy {
+                src_subresource: subresource,
+                src_offset: vk::Offset3D { x: source.offset.x, y: source.offset.y, z: 0 },
+                dst_subresource: subresource,
+                dst_offset: vk::Offset3D::default(),
+                extent: vk::Extent3D { width: source.extent.width, height: source.extent.height, depth: 1 }
+            }];
+            unsafe {
+                // The present render pass already left the frame in TRANSFER_SRC_OPTIMAL
+                self.device.cmd_pipeline_barrier(command_buffer, vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT, vk::PipelineStageFlags::TRANSFER,
+                    vk::DependencyFlags::empty(), &[], &[], &[barrier(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                        vk::ImageLayout::TRANSFER_DST_OPTIMAL, vk::AccessFlags::COLOR_ATTACHMENT_WRITE, vk::AccessFlags::TRANSFER_WRITE)]);
+                self.device.cmd_copy_image(command_buffer, image.image, vk::ImageLayout::TRANSFER_SRC_OPTIMAL, target,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL, &regions);
+                self.device.cmd_pipeline_barrier(command_buffer, vk::PipelineStageFlags::TRANSFER, vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                    vk::DependencyFlags::empty(), &[], &[], &[barrier(vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                        vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL, vk::AccessFlags::TRANSFER_WRITE, vk::AccessFlags::COLOR_ATTACHMENT_WRITE)]);
+            }
+        })
+    }
+
+    // The main camera's view at `settings.scale` times the render extent, rendered as tiles of the render extent one
+    // after the other and averaged down from `settings.supersample` samples per pixel along each axis. The scene stays
+    // at the current animation time meanwhile and the window shows the tiles as they're drawn. Screen space effects only
+    // see their own tile, so they can show the seams between them.
+    pub fn capture_screenshot(&mut self, settings: ScreenshotSettings) -> Result<Screenshot, vk::Result> {
+        // Split views have cameras of their own
+        if !self.split_views.is_empty() {
+            return Err(vk::Result::ERROR_FEATURE_NOT_PRESENT);
+        }
+
+        unsafe { self.device.device_wait_idle()? };
+        let time = self.animation_time();
+        self.capture = Some(ScreenshotCapture::new(&self.device, &mut self.allocator, self.viewport.render_extent,
+            self.swapchain.surface_format.format, settings, time)?);
+        let result = self.capture_tiles();
+        let mut capture = self.capture.take().expect("Screenshot capture went missing!");
+        // Frames after the screenshot have nothing to blend with either
+        self.previous_view_projection = None;
+
+        match result {
+            Ok(()) => Ok(capture.finish(&self.device, &mut self.allocator)),
+            Err(error) => {
+                unsafe { self.device.device_wait_idle()? };
+                capture.destroy(&self.device, &mut self.allocator);
+                Err(error)
+            }
+        }
+    }
+
+    fn capture_tiles(&mut self) -> Result<(), vk::Result> {
+        loop {
+            // Every tile starts over, the history shows the last one
+            self.previous_view_projection = None;
+            self.clouds.reset_history();
+            if let Some(taa) = &mut self.post_process.taa {
+                taa.reset_history(self.frame_index);
+            }
+
+            let capture = self.capture.as_ref().expect("Screenshot capture went missing!");
+            let (frames, extent) = (capture.settings.frames_per_tile, capture.target.extent);
+            for _ in 0..frames {
+                let frame_index = self.frame_index;
+                self.fill_commandbuffers()?;
+                self.draw_frame();
+                // Nothing was drawn or the swapchain was recreated, the tiles no longer fit together
+                if self.frame_index == frame_index || self.viewport.render_extent != extent {
+                    return Err(vk::Result::ERROR_OUT_OF_DATE_KHR);
+                }
+            }
+
+            unsafe { self.device.device_wait_idle()? };
+            let capture = self.capture.as_mut().expect("Screenshot capture went missing!");
+            if capture.read_tile(&self.device, &mut self.allocator, &self.pools, self.queues.graphics_queue)? {
+                return Ok(());
+            }
+        }
+    }
+
+    fn frame_context(&self, index: usize, command_buffer: vk::CommandBuffer, renderpass: vk::RenderPass) -> FrameContext<'_> {
+        FrameContext {
+            device: &self.device,
+            command_buffer,
+            image_index: index,
+            frame_index: self.frame_index,
+            extent: self.viewport.render_extent,
+            viewport: self.viewport.rect,
+            renderpass,
+            camera_set: self.camera_sets[index],
+            lighting_set: self.lighting.sets[index],
+            objects_set: self.objects.set,
+            objects_offset: self.objects.dynamic_offset(&self.upload_ring, index),
+            scene_target: &self.post_process.scene_target,
+            occlusion_queries: &self.occlusion_queries,
+            conditional_rendering: self.conditional_rendering.as_ref()
+        }
+    }
+
+    // Id of the game object covering the pixel at (x, y) in the last rendered frame, in physical window pixels.
+    // Reads back a single texel of the object id attachment, so it waits for the device to go idle.
+    pub fn pick(&mut self, x: u32, y: u32) -> Result<Option<usize>, vk::Result> {
+        let (x, y) = match self.viewport.to_render(x as f32, y as f32) {
+            Some((x, y)) => (x as u32, y as u32),
+            None => return Ok(None)
+        };
+        let target = &self.post_process.scene_target;
+        if x >= target.extent.width || y >= target.extent.height {
+            return Ok(None);
+        }
+
+        unsafe { self.device.device_wait_idle()? };
+
+        let image = &target.colors[OBJECT_ID_ATTACHMENT];
+        let mut staging_buffer = StagingBuffer::new(&self.device, &mut self.allocator, std::mem::size_of::<u32>() as u64);
+        let result = self.pools.one_time_submit(&self.device, self.queues.graphics_queue, |command_buffer| {
+            image.transition_layout(&self.device, command_buffer, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL, vk::ImageLayout::TRANSFER_SRC_OPTIMAL);
+
+            let regions = [vk::BufferImageCopy::builder()
+                .image_subresource(vk::ImageSubresourceLayers {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    mip_level: 0,
+                    base_array_layer: 0,
+                    layer_count: 1
+                })
+                .image_offset(vk::Offset3D { x: x as i32, y: y as i32, z: 0 })
+                .image_extent(vk::Extent3D { width: 1, height: 1, depth: 1 })
+                .build()
+            ];
+            unsafe {
+                self.device.cmd_copy_image_to_buffer(command_buffer, image.image, vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    staging_buffer.get_buffer(), &regions);
+            }
+
+            image.transition_layout(&self.device, command_buffer, vk::ImageLayout::TRANSFER_SRC_OPTIMAL, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+        });
+
+        let mut bytes = [0; 4];
+        staging_buffer.read_buffer(0, &mut bytes);
+        staging_buffer.destroy(&self.device, &mut self.allocator);
+        result?;
+
+        // Ids are written as object index + 1, so 0 (cleared) is empty space
+        let index = u32::from_ne_bytes(bytes) as usize;
+        Ok(index.checked_sub(1)
+            .and_then(|index| self.game_objects.get(index))
+            .map(|game_object| game_object.get_id()))
+    }
+
+    // World space ray through the window position (x, y) in physical pixels, None over the letterbox bars.
+    // With split screen the ray comes from the camera of the view under the position.
+    pub fn screen_ray(&self, x: f32, y: f32) -> Option<Ray> {
+        let (x, y) = self.viewport.to_render(x, y)?;
+        if self.split_views.is_empty() {
+            return Some(self.camera.screen_ray(x, y, self.viewport.render_extent));
+        }
+
+        self.split_views.iter().find_map(|view| {
+            let rect = view.area.rect(self.viewport.render_extent);
+            let (x, y) = (x - rect.offset.x as f32, y - rect.offset.y as f32);
+            match x >= 0.0 && y >= 0.0 && x < rect.extent.width as f32 && y < rect.extent.height as f32 {
+                true => Some(view.camera.screen_ray(x, y, rect.extent)),
+                false => None
+            }
+        })
+    }
+
+    pub fn selected_index(&self) -> Option<usize> {
+        self.selected.and_then(|id| self.game_objects.iter().position(|game_object| game_object.get_id() == id))
+    }
+
+    // Drags the selected object while a gizmo handle is held, otherwise highlights the handle under the cursor
+    pub fn gizmo_cursor_moved(&mut self, x: f32, y: f32) {
+        let index = match self.selected_index() {
+            Some(index) => index,
+            None => {
+                self.gizmo.hovered = None;
+                return;
+            }
+        };
+
+        let inverse_space = self.parent_matrix(index).inversed();
+        let ray = match self.screen_ray(x, y) {
+            Some(ray) => ray.transformed(inverse_space),
+            None => {
+                self.gizmo.hovered = None;
+                return;
+            }
+        };
+        let camera_position = inverse_space.transform_point3(self.camera.position);
+        let transform = &mut self.game_objects[index].transform3d;
+        if self.gizmo.is_dragging() {
+            self.gizmo.drag(&ray, transform);
+        } else {
+            let scale = self.gizmo.scale(transform.translation, camera_position);
+            self.gizmo.hovered = self.gizmo.hit_test(&ray, transform.translation, scale);
+        }
+    }
+
+    // True if a handle was grabbed, the click should then not change the selection
+    pub fn gizmo_pressed(&mut self, x: f32, y: f32) -> bool {
+        // Not drawn over split views, so it can't be grabbed there either
+        let index = match self.selected_index() {
+            Some(index) if self.split_views.is_empty() => index,
+            _ => return false
+        };
+
+        let inverse_space = self.parent_matrix(index).inversed();
+        let ray = match self.screen_ray(x, y) {
+            Some(ray) => ray.transformed(inverse_space),
+            None => return false
+        };
+        self.gizmo.begin_drag(&ray, &self.game_objects[index].transform3d, inverse_space.transform_point3(self.camera.position))
+    }
+
+    // World matrix of the object's parent, the space its transform (and the gizmo editing it) is in
+    pub fn parent_matrix(&self, index: usize) -> uv::Mat4 {
+        self.game_objects[index].parent
+            .and_then(|id| self.game_objects.iter().position(|game_object| game_object.get_id() == id))
+            .map_or_else(uv::Mat4::identity, |parent| world_matrices(&self.game_objects)[parent])
+    }
 
-        let (logical_device, queues) = LogicalDevice::new(&instance, physical_device, &queue_families, &layer_names)?;
+    pub fn gizmo_released(&mut self) {
+        self.gizmo.end_drag();
+    }
 
-        let mut swapchain = VulkanSwapchain::new(&instance, physical_device, &logical_device, &surface, &queue_families)?;
+    // Destroys the object's mesh after waiting for the device, its children move up to its parent
+    pub fn remove_game_object(&mut self, id: usize) -> Result<(), vk::Result> {
+        let index = match self.game_objects.iter().position(|game_object| game_object.get_id() == id) {
+            Some(index) => index,
+            None => return Ok(())
+        };
 
-        let renderpass = RenderPass::init(&logical_device, swapchain.surface_format.format)?;
+        unsafe { self.device.device_wait_idle()? };
+        let mut game_object = self.game_objects.remove(index);
+        for child in self.game_objects.iter_mut().filter(|child| child.parent == Some(id)) {
+            child.parent = game_object.parent;
+        }
+        game_object.mesh.destroy(&self.device, &mut self.allocator);
+        if let Some(query) = game_object.occlusion_query {
+            self.occlusion_queries.remove(query);
+        }
 
-        swapchain.create_framebuffers(&logical_device, renderpass)?;
+        if self.selected == Some(id) {
+            self.selected = None;
+            self.gizmo.end_drag();
+        }
 
-        let pipeline = Pipeline::new(&logical_device, &swapchain, &renderpass)?;
+        Ok(())
+    }
 
-        let pools = Pools::new(&logical_device, &queue_families)?;
+    // Finishes the UI frame started with `ui.begin_frame`, drawn with the next `fill_commandbuffers`
+    pub fn end_ui_frame(&mut self) -> Result<(), vk::Result> {
+        self.sdf_text.end_frame();
+        self.ui.end_frame(&self.device, &mut self.allocator, &self.pools, self.queues.graphics_queue, &mut self.descriptors)
+    }
 
-        let buffer_device_address = false;
-        let allocator = Allocator::new(&AllocatorCreateDesc {
-            instance: instance.clone(),
-            device: logical_device.clone(),
-            physical_device,
-            debug_settings: Default::default(),
-            buffer_device_address,
-        }).expect("Failed to create allocator!");
-        allocator.report_memory_leaks(log::Level::Info);
+    // Uploads the font's atlas for `sdf_text.draw`
+    pub fn add_sdf_font(&mut self, font: SdfFont) -> Result<SdfFontId, vk::Result> {
+        self.sdf_text.add_font(&self.device, &mut self.allocator, &self.pools, self.queues.graphics_queue, &mut self.descriptors, font)
+    }
 
-        let command_buffers = Self::create_commandbuffers(&logical_device, &pools, swapchain.image_count)?;
+    // Tightly packed RGBA8, shown in rich text with [icon=name]
+    pub fn add_text_icon(&mut self, name: &str, extent: vk::Extent2D, pixels: &[u8]) -> Result<(), vk::Result> {
+        self.sdf_text.add_icon(&self.device, &mut self.allocator, &self.pools, self.queues.graphics_queue, &mut self.descriptors, name, extent,
+            pixels)
+    }
 
-        
-        Ok(Self {
-            entry,
-            instance,
-            is_framebuffer_resized: false,
-            debug,
-            surface,
-            physical_device,
-            physical_device_properties,
-            physical_device_features,
-            queue_families,
-            queues,
-            device: logical_device,
-            swapchain,
-            renderpass,
-            pipeline,
-            pools,
-            command_buffers,
-            allocator: std::mem::ManuallyDrop::new(allocator),
-            game_objects: vec![]
-        })
+    // Set for the frame being prepared, e.g. for a hook binding data that changes every frame. It's recycled once the
+    // GPU is done with this frame slot, so it must not be kept past the next `draw_frame`.
+    pub fn allocate_frame_set(&mut self, layout: vk::DescriptorSetLayout) -> Result<vk::DescriptorSet, vk::Result> {
+        let slot = (self.swapchain.current_image + 1) % self.frame_descriptors.len();
+        self.frame_descriptors[slot].allocate(&self.device, layout)
     }
 
-    pub fn create_instance(entry: &ash::Entry, layer_names: &[&str], window: &VulkanWindow) -> Result<ash::Instance, vk::Result> {
-        let app_name = std::ffi::CString::new("Reverie Engine").unwrap();
-        let engine_name = std::ffi::CString::new("Reverie").unwrap();
+    // Lets the application record its own commands at `point` every frame, see `HookPoint`
+    pub fn add_render_hook(&mut self, point: HookPoint, hook: RenderHook) -> HookId {
+        self.hooks.add(point, hook)
+    }
 
-        let app_info = vk::ApplicationInfo::builder()
-            .application_name(&app_name)
-            .engine_name(&engine_name)
-            .application_version(vk::make_api_version(0, 0, 1, 0))
-            .engine_version(vk::make_api_version(0, 0, 1, 0))
-            .api_version(vk::API_VERSION_1_3);
+    pub fn remove_render_hook(&mut self, id: HookId) -> bool {
+        self.hooks.remove(id)
+    }
 
-        let layer_names: Vec<std::ffi::CString> = layer_names
-            .iter()
-            .map(|&ln| std::ffi::CString::new(ln).unwrap())
-            .collect();
-        
-        let layer_name_pointers: Vec<*const i8> = layer_names
-            .iter()
-            .map(|layer_name| layer_name.as_ptr())
-            .collect();
+    // `FRAME_PARTS` after another for every swapchain image, each from its own pool
+    pub fn create_commandbuffers(logical_device: &ash::Device, pools: &mut Pools, amount: usize) -> Result<Vec<vk::CommandBuffer>, vk::Result> {
+        pools.allocate_frame_command_buffers(logical_device, amount * FRAME_PARTS)
+    }
 
-        let mut extension_name_pointers: Vec<*const i8> = 
-            vec![
-                ash::extensions::ext::DebugUtils::name().as_ptr(),
-            ];
-        let required_surface_extensions = ash_window::enumerate_required_extensions(&window.window)
-            .unwrap()
-            .iter()
-            .map(|ext| *ext)
-            .collect::<Vec<*const i8>>();
-        extension_name_pointers.extend(required_surface_extensions.iter());
+    // The command buffers of swapchain image `i`, submitted in this order
+    fn frame_command_buffers(&self, i: usize) -> &[vk::CommandBuffer] {
+        &self.command_buffers[i * FRAME_PARTS..(i + 1) * FRAME_PARTS]
+    }
 
-        println!("Extensions in use: ");
-        for ext in extension_name_pointers.iter() {
-            println!("\t{}", unsafe { std::ffi::CStr::from_ptr(*ext).to_str().unwrap() });
-        }
+    // Moves on to the next frame slot and acquires the image it draws to, once the GPU is done with both the slot and the
+    // image's last frame. `None` when the swapchain had to be recreated instead.
+    fn acquire_image(&mut self) -> Option<usize> {
+        self.swapchain.current_image = (self.swapchain.current_image + 1) % self.swapchain.image_count;
+        let slot = self.swapchain.current_image;
 
-        let create_flags = vk::InstanceCreateFlags::default();
+        let result = unsafe { self.device.wait_for_fences(&[self.swapchain.may_begin_drawing[slot]], true, u64::MAX) };
+        self.check_device(result, "Fence wait failed!");
 
-        let create_info = vk::InstanceCreateInfo::builder()
-            .application_info(&app_info)
-            .enabled_layer_names(&layer_name_pointers)
-            .enabled_extension_names(&extension_name_pointers)
-            .flags(create_flags);
+        let result = unsafe {
+            // Offscreen images are simply used in turn
+            match &self.swapchain.swapchain_loader {
+                Some(swapchain_loader) => swapchain_loader.acquire_next_image(self.swapchain.swapchain, u64::MAX, self.swapchain.image_available[slot],
+                    vk::Fence::null()),
+                None => Ok((slot as u32, false))
+            }
+        };
+        let image_index = match result {
+            Ok((image_index, _is_sub_optimal)) => image_index as usize,
+            Err(vk_result) => match vk_result {
+                // The new swapchain acquires exclusive fullscreen again, e.g. after alt-tabbing back
+                vk::Result::ERROR_OUT_OF_DATE_KHR | vk::Result::ERROR_FULL_SCREEN_EXCLUSIVE_MODE_LOST_EXT => {
+                    self.recreate_swapchain();
+                    return None;
+                }
+                vk::Result::ERROR_DEVICE_LOST => self.device_lost(),
+                _ => panic!("Failed to acquire swapchain image!")
+            }
+        };
 
-        unsafe { entry.create_instance(&create_info, None) }
+        // Images can come back in any order, the frame that last drew to this one may be in another slot
+        let fence = self.image_fences[image_index];
+        if fence != vk::Fence::null() && fence != self.swapchain.may_begin_drawing[slot] {
+            let result = unsafe { self.device.wait_for_fences(&[fence], true, u64::MAX) };
+            self.check_device(result, "Fence wait failed!");
+        }
+        Some(image_index)
     }
 
-    pub fn recreate_swapchain(&mut self) {
-        unsafe {
-            self.device 
-                .device_wait_idle()
-                .expect("Failed to wait device idle (recreate swapchain)!")
+    pub fn fill_commandbuffers(&mut self) -> Result<(), vk::Result> {
+        crate::profile_scope!("Fill Command Buffers");
+        // There are no swapchain images to record for
+        if self.suspended {
+            return Ok(());
+        }
+
+        // Only the image this frame draws to is recorded, the others may still be in flight
+        let image_index = match self.acquire_image() {
+            Some(image_index) => image_index,
+            None => return Ok(())
         };
 
-        unsafe {
-            self.device.free_command_buffers(self.pools.graphics_command_pool, &self.command_buffers);
-            self.pools.cleanup(&self.device);
-            self.pipeline.cleanup(&self.device);
-            RenderPass::cleanup(&self.device, self.renderpass);
-            self.swapchain.cleanup(&self.device);
+        let models = world_matrices(&self.game_objects);
+        let lod_view = LodView::new(&self.camera, self.viewport.render_extent);
+        self.texture_streaming.update(&self.device, &mut self.allocator, &self.pools, self.queues.transfer_queue, &mut self.materials,
+            &mut self.descriptors, &self.game_objects, &models, self.scatter.prototypes(), &lod_view, self.frame_index,
+            self.swapchain.image_count as u64)?;
+        if let Some(virtual_texture) = &mut self.virtual_texture {
+            virtual_texture.stream(&self.device, &mut self.allocator, &self.pools, self.queues.transfer_queue, self.frame_index,
+                self.swapchain.image_count as u64)?;
         }
+        let objects = self.game_objects.iter().chain(self.scatter.prototypes());
+        self.materials.update(&self.device, &mut self.descriptors, objects, self.frame_index, self.swapchain.image_count as u64)?;
+        self.clouds.update(self.frame_index, self.atmosphere.as_ref().is_some_and(|atmosphere| atmosphere.clouds.is_some()));
 
-        self.swapchain = VulkanSwapchain::new(&self.instance, self.physical_device, &self.device, &self.surface, &self.queue_families)
-            .expect("Failed to recreate swapchain.");
+        // The image's command buffer is recorded with this layout and its region is filled by `update_uniforms`
+        self.upload_ring.begin_layout(image_index);
+        self.objects.reserve(&mut self.upload_ring, self.game_objects.len());
+        self.sdf_text.reserve(&mut self.upload_ring);
+        self.ui.reserve(&mut self.upload_ring);
 
-        self.renderpass = RenderPass::init(&self.device, self.swapchain.surface_format.format)
-            .expect("Failed to recreate renderpass.");
+        let shadow_assignment = self.shadow_assignment();
+        self.light_probes.begin_frame();
+        if let Some(gpu_culling) = &mut self.gpu_culling {
+            let rebuilt = gpu_culling.prepare(&self.device, &mut self.allocator, &self.game_objects, &models, &self.materials, &lod_view)?;
+            // The device is idle after a rebuild, so the set can be written before the frames are recorded again
+            if let Some(vertices) = gpu_culling.vertices_info().filter(|_| rebuilt) {
+                self.objects.write_vertices(&self.device, vertices);
+            }
+        }
 
-        self.swapchain.create_framebuffers(&self.device, self.renderpass)
-            .expect("Failed to recreate framebuffers.");
+        let passes = self.record_frame(image_index, &shadow_assignment, &models)?;
+        self.crash_diagnostics.recorded(image_index, passes);
+        self.acquired_image = Some(image_index);
 
-        self.pipeline = Pipeline::new(&self.device, &self.swapchain, &self.renderpass)
-            .expect("Failed to recreate pipeline.");
+        for capture in &mut self.cubemaps {
+            capture.captured();
+        }
+        self.reflection_probes.captured();
+        self.light_probes.captured();
+        if let Some(shading_rate) = &mut self.shading_rate {
+            shading_rate.drawn();
+        }
+        Ok(())
+    }
 
-        self.pools = Pools::new(&self.device, &self.queue_families)
-            .expect("Failed to recreate pipeline.");
+    // Records everything drawn into swapchain image `i`. Shadows, captures, the scene and post processing each go into a
+    // command buffer of their own, recorded by a job of their own, and the four are submitted together in that order.
+    // Returns the names of the recorded passes.
+    fn record_frame(&self, i: usize, shadow_assignment: &ShadowAssignment, models: &[uv::Mat4]) -> Result<Vec<&'static str>, vk::Result> {
+        // Every view culls the lights for its own camera and draws into its part of the targets,
+        // without split views the main camera covers all of them
+        let scene_rect = self.post_process.scene_target.rect();
+        let views: Vec<(vk::DescriptorSet, vk::DescriptorSet, vk::Rect2D, &ClearSettings, uv::Vec3)> = match self.split_views.is_empty() {
+            true => vec![(self.camera_sets[i], self.reflection.as_ref().map_or(vk::DescriptorSet::null(), |reflection| reflection.camera_sets[i]),
+                scene_rect, &self.camera.clear, self.camera.position)],
+            false => self.split_views
+                .iter()
+                .map(|view| (view.camera_sets[i], view.reflection_sets[i], view.area.rect(scene_rect.extent), &view.camera.clear,
+                    view.camera.position))
+                .collect()
+        };
+        // Passes are numbered across the command buffers, so post processing has to know how many the scene records: one per
+        // view and the virtual texture feedback splitting the first view's in two
+        let scene_passes = views.len() + match self.virtual_texture {
+            Some(_) => 2,
+            None => 0
+        };
 
-        self.command_buffers = Self::create_commandbuffers(&self.device, &self.pools, self.swapchain.image_count)
-            .expect("Failed to recreate command_buffers.");
+        let mut results: [Result<Vec<&'static str>, vk::Result>; FRAME_PARTS] = std::array::from_fn(|_| Ok(vec![]));
+        {
+            let [shadows, captures, scene, post] = &mut results;
+            let views = &views;
+            let logical_device = &self.device;
+            let mut graph = TaskGraph::new();
+            graph.add("record shadows", &[], move || *shadows = self.record_part(i, 0, 0, |breadcrumbs, command_buffer| {
+                self.occlusion_queries.record_reset(logical_device, command_buffer, i);
+                breadcrumbs.pass("Shadows");
+                self.shadows.record(logical_device, command_buffer, shadow_assignment, &self.spot_lights, &self.lights, &self.game_objects,
+                    models);
+            }));
+            graph.add("record captures", &[], move || *captures = self.record_part(i, 1, 1, |breadcrumbs, command_buffer| {
+                breadcrumbs.pass("Captures");
+                for capture in self.cubemaps.iter().filter(|capture| capture.needs_capture()) {
+                    self.record_cubemap_capture(command_buffer, i, capture);
+                }
+                for probe in self.reflection_probes.pending() {
+                    self.record_cubemap_capture(command_buffer, i, self.reflection_probes.capture(probe));
+                    self.reflection_probes.record_prefilter(logical_device, command_buffer, probe);
+                }
+                for (slot, capture) in self.light_probes.pending_captures() {
+                    self.record_cubemap_capture(command_buffer, i, capture);
+                    self.light_probes.record_projection(logical_device, command_buffer, slot);
+                }
+            }));
+            graph.add("record scene", &[], move || *scene = self.record_part(i, 2, 2, |breadcrumbs, command_buffer| {
+                self.record_scene(i, command_buffer, breadcrumbs, views, models);
+            }));
+            graph.add("record post process", &[], move || *post = self.record_part(i, 3, 2 + scene_passes, |breadcrumbs, command_buffer| {
+                self.record_post_process(i, command_buffer, breadcrumbs);
+            }));
+            graph.run();
+        }
 
-        Self::fill_commandbuffers(&self.command_buffers, &self.device, &self.renderpass, &self.swapchain, &self.pipeline, &self.game_objects)
-            .expect("Failed to fill commmandbuffers");
+        let mut passes = vec![];
+        for result in results {
+            passes.extend(result?);
+        }
+        debug_assert_eq!(passes.len(), scene_passes + 4, "The scene recorded a different number of passes than counted!");
+        Ok(passes)
     }
 
-    pub fn create_commandbuffers(logical_device: &ash::Device, pools: &Pools, amount: usize) -> Result<Vec<vk::CommandBuffer>, vk::Result> {
-        let commandbuffer_allocate_info = vk::CommandBufferAllocateInfo::builder()
-            .level(vk::CommandBufferLevel::PRIMARY)
-            .command_pool(pools.graphics_command_pool)
-            .command_buffer_count(amount as u32);
-            
-        
-        unsafe { logical_device.allocate_command_buffers(&commandbuffer_allocate_info) }
+    // Begins command buffer `part` of swapchain image `i`, has `record` fill it with passes counted from `first` and ends it
+    fn record_part(&self, i: usize, part: usize, first: usize, record: impl FnOnce(&mut Breadcrumbs, vk::CommandBuffer)
+    ) -> Result<Vec<&'static str>, vk::Result> {
+        let command_buffer = self.frame_command_buffers(i)[part];
+        let commandbuffer_begininfo = vk::CommandBufferBeginInfo::builder();
+        unsafe { self.device.begin_command_buffer(command_buffer, &commandbuffer_begininfo)?; }
+
+        let mut breadcrumbs = self.crash_diagnostics.breadcrumbs(i, command_buffer, first);
+        #[cfg(feature = "tracy")]
+        breadcrumbs.profile(self.gpu_profiler.as_ref(), part + 1 == FRAME_PARTS);
+        record(&mut breadcrumbs, command_buffer);
+
+        let passes = breadcrumbs.finish();
+        unsafe { self.device.end_command_buffer(command_buffer)?; }
+        Ok(passes)
     }
 
-    pub fn fill_commandbuffers(command_buffers: &[vk::CommandBuffer], logical_device: &ash::Device, renderpass: &vk::RenderPass, swapchain: &VulkanSwapchain, pipeline: &Pipeline, game_objects: &Vec<GameObject>
-    ) -> Result<(), vk::Result> {
-        unsafe {
-            logical_device
-                .wait_for_fences(&[swapchain.may_begin_drawing[swapchain.current_image]], true, std::u64::MAX)
-                .expect("Fence wait failed!");
-        }
+    fn record_scene(&self, i: usize, command_buffer: vk::CommandBuffer, breadcrumbs: &mut Breadcrumbs,
+        views: &[(vk::DescriptorSet, vk::DescriptorSet, vk::Rect2D, &ClearSettings, uv::Vec3)], models: &[uv::Mat4]
+    ) {
+        let logical_device = &self.device;
+        for (view_index, &(camera_set, reflection_camera_set, rect, clear, eye)) in views.iter().enumerate() {
+            breadcrumbs.pass("Scene");
+            self.lighting.record_culling(logical_device, command_buffer, i, camera_set);
 
-        for (i, &command_buffer) in command_buffers.iter().enumerate() {
-            let commandbuffer_begininfo = vk::CommandBufferBeginInfo::builder();
-            unsafe { logical_device.begin_command_buffer(command_buffer, &commandbuffer_begininfo)?; }
-        
+            if let Some(reflection) = &self.reflection {
+                self.cull_scene(command_buffer, i, reflection_camera_set);
+                reflection.target.begin_pass(logical_device, command_buffer, rect, view_index == 0, clear);
+
+                unsafe {
+                    logical_device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, reflection.scene_pipeline.pipeline);
+                    self.bind_scene_sets(command_buffer, reflection.scene_pipeline.layout, reflection_camera_set, i);
+                    // Lightmaps only cover the surfaces the object was unwrapped for, reflections light it dynamically
+                    for material in [Material::Basic, Material::Lightmapped] {
+                        self.draw_material(command_buffer, i, &reflection.scene_pipeline, material, None);
+                    }
+                    if let Some(atmosphere) = &self.atmosphere {
+                        self.sky.record(logical_device, command_buffer, reflection_camera_set, atmosphere, self.clouds.sky_set(self.frame_index),
+                            false);
+                    }
 
-            let clear_values = [vk::ClearValue {
-                color: vk::ClearColorValue {
-                    float32: [0.0, 0.0, 0.0, 1.0]
-                }}, 
-                vk::ClearValue {
-                depth_stencil: vk::ClearDepthStencilValue {
-                    depth: 1.0,
-                    stencil: 0
+                    logical_device.cmd_end_render_pass(command_buffer);
                 }
-            }];
+            }
 
-            let renderpass_begininfo = vk::RenderPassBeginInfo::builder()
-                .render_pass(*renderpass)
-                .framebuffer(swapchain.framebuffers[i])
-                .render_area(vk::Rect2D {
-                    offset: vk::Offset2D { x:0, y:0 },
-                    extent: swapchain.extent
-                })
-                .clear_values(&clear_values);
+            if view_index == 0 {
+                if let Some(shading_rate) = &self.shading_rate {
+                    shading_rate.record_rate_image(logical_device, command_buffer, &self.post_process.scene_target);
+                }
+                self.hooks.record(HookPoint::BeforeScene, &self.frame_context(i, command_buffer, vk::RenderPass::null()));
+            }
+
+            self.cull_scene(command_buffer, i, camera_set);
+            self.scatter.record_culling(logical_device, command_buffer, i, camera_set);
+            if let Some(virtual_texture) = self.virtual_texture.as_ref().filter(|_| view_index == 0) {
+                breadcrumbs.pass("Virtual Texture Feedback");
+                virtual_texture.record_feedback(logical_device, command_buffer, i, rect, clear, |pipeline| {
+                    self.bind_scene_sets(command_buffer, pipeline.layout, camera_set, i);
+                    self.draw_material(command_buffer, i, pipeline, Material::Virtual, self.predicates(view_index));
+                });
+                breadcrumbs.pass("Scene");
+            }
+            if let Some(atmosphere) = &self.atmosphere {
+                if let Some(clouds) = &atmosphere.clouds {
+                    self.clouds.record(logical_device, command_buffer, camera_set, rect, view_index == 0, atmosphere, clouds, self.frame_index);
+                }
+            }
+            let scene_target = &self.post_process.scene_target;
+            scene_target.begin_pass(logical_device, command_buffer, rect, view_index == 0, clear);
 
             unsafe {
-                logical_device.cmd_begin_render_pass(command_buffer, &renderpass_begininfo, vk::SubpassContents::INLINE);
-
-                let viewports = [vk::Viewport {
-                    x: 0.0,
-                    y: 0.0,
-                    width: swapchain.extent.width as f32,
-                    height: swapchain.extent.height as f32,
-                    min_depth: 0.0,
-                    max_depth: 1.0,
-                }];
-
-                let scissors = [vk::Rect2D {
-                    offset: vk::Offset2D { x: 0, y: 0 },
-                    extent: swapchain.extent
-                }];
-                
-                logical_device.cmd_set_viewport(command_buffer, 0, &viewports);
-                logical_device.cmd_set_scissor(command_buffer, 0, &scissors);
-
-                for (_i, game_object) in game_objects.iter().enumerate() {
+                // Cutouts and transparent objects are left out of reflections and captures
+                let mut passes = vec![(&self.pipeline, Material::Basic), (&self.cutout_pipeline, Material::Cutout),
+                    (&self.lightmapped_pipeline, Material::Lightmapped)];
+                if let Some(reflection) = &self.reflection {
+                    passes.push((&reflection.surface_pipeline, Material::Reflective));
+                }
+                for (pipeline, material) in passes {
                     logical_device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, pipeline.pipeline);
-                    match &game_object.mesh.index_buffer {
-                        Some(index_buffer) => {
-                            logical_device.cmd_bind_index_buffer(command_buffer, index_buffer.get_buffer(), 0, vk::IndexType::UINT32);
-                            for vertex_buffer in &game_object.mesh.vertex_buffers {
-                                logical_device.cmd_bind_vertex_buffers(command_buffer, 0, &[vertex_buffer.get_buffer()], &[0]);
-
-                                    let push = PushConstantData {
-                                        _transform: game_object.transform2d.mat2(),
-                                        _offset: game_object.transform2d.translation,
-                                        _color: align::Align16(game_object.color)
-                                    };
-                                    let bytes = push.as_bytes();
-
-                                    logical_device.cmd_push_constants(command_buffer, pipeline.layout, vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT, 0, &bytes);
-                                    logical_device.cmd_draw_indexed(command_buffer, index_buffer.get_index_count(), 1, 0, 0, 0);
-                                
-                            }
-                        },
-                        None => {
-                            for vertex_buffer in &game_object.mesh.vertex_buffers {
-                                logical_device.cmd_bind_vertex_buffers(command_buffer, 0, &[vertex_buffer.get_buffer()], &[0]);
-                                logical_device.cmd_draw(command_buffer, vertex_buffer.get_vertex_count(), 1, 0, 0);
-                            }
-                        }
+                    self.bind_scene_sets(command_buffer, pipeline.layout, camera_set, i);
+                    // The reflective surface pipeline has no dynamic shading rate and is always shaded per pixel
+                    if let Some(shading_rate) = self.shading_rate.as_ref().filter(|_| material != Material::Reflective) {
+                        shading_rate.record_draw_rate(command_buffer);
                     }
+                    self.draw_material(command_buffer, i, pipeline, material, self.predicates(view_index));
+                }
+                if let Some(virtual_texture) = &self.virtual_texture {
+                    logical_device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, virtual_texture.pipeline.pipeline);
+                    self.bind_scene_sets(command_buffer, virtual_texture.pipeline.layout, camera_set, i);
+                    virtual_texture.bind(logical_device, command_buffer, i);
+                    if let Some(shading_rate) = &self.shading_rate {
+                        shading_rate.record_draw_rate(command_buffer);
+                    }
+                    self.draw_material(command_buffer, i, &virtual_texture.pipeline, Material::Virtual, self.predicates(view_index));
+                }
+                if !self.scatter.layers.is_empty() {
+                    self.bind_scene_sets(command_buffer, self.scatter.pipelines.layout(), camera_set, i);
+                    self.scatter.record_draws(logical_device, command_buffer, i, &self.materials);
+                }
+                // Fills in what the opaque passes left empty, before water and transparent objects show it through them
+                if let Some(atmosphere) = &self.atmosphere {
+                    self.sky.record(logical_device, command_buffer, camera_set, atmosphere, self.clouds.sky_set(self.frame_index), true);
+                }
+
+                let context = FrameContext {
+                    camera_set,
+                    viewport: rect,
+                    ..self.frame_context(i, command_buffer, scene_target.renderpass)
+                };
+                self.hooks.record(HookPoint::AfterOpaque, &context);
+                if view_index == 0 {
+                    self.occlusion_queries.record(logical_device, command_buffer, i, camera_set, &self.game_objects, models);
+                }
+
+                // Water sees the opaque scene through copies taken outside of the pass, which then goes on where it stopped
+                if self.game_objects.iter().any(|game_object| game_object.material == Material::Water) {
+                    logical_device.cmd_end_render_pass(command_buffer);
+                    self.water.record_copy(logical_device, command_buffer, scene_target);
+                    scene_target.resume_pass(logical_device, command_buffer, rect);
+
+                    logical_device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, self.water.pipeline.pipeline);
+                    self.bind_scene_sets(command_buffer, self.water.pipeline.layout, camera_set, i);
+                    self.water.bind(logical_device, command_buffer);
+                    Self::draw_game_objects(logical_device, command_buffer, &self.water.pipeline, &self.game_objects, &self.materials,
+                        Material::Water, self.predicates(view_index));
+                }
+
+                // Blended over everything opaque, hooks included, farthest first
+                self.bind_scene_sets(command_buffer, self.transparent_pipelines.get(BlendMode::Alpha).layout, camera_set, i);
+                self.transparent_pipelines.record(logical_device, command_buffer, &self.game_objects, models, &self.materials, eye);
+
+                if let Some(index) = self.selected_index() {
+                    self.outline.record(logical_device, command_buffer, camera_set, &self.game_objects[index], models[index]);
                 }
 
                 logical_device.cmd_end_render_pass(command_buffer);
-                logical_device.end_command_buffer(command_buffer)?;
             }
         }
-        Ok(())
+
+        if let Some(conditional_rendering) = &self.conditional_rendering {
+            let slots = self.occlusion_queries.recorded_slots(&self.game_objects);
+            conditional_rendering.record_update(logical_device, command_buffer, self.occlusion_queries.pool(i), &slots);
+        }
     }
 
-    pub fn draw_frame(&mut self) {
-        self.swapchain.current_image = {self.swapchain.current_image + 1} % self.swapchain.image_count as usize;
-
-        let (image_index, _is_sub_optimal) = unsafe {
-            let result = self.swapchain.swapchain_loader.acquire_next_image(
-                self.swapchain.swapchain, std::u64::MAX, self.swapchain.image_available[self.swapchain.current_image], vk::Fence::null());
-
-            match result {
-                Ok(image_index) => image_index,
-                Err(vk_result) => match vk_result {
-                    vk::Result::ERROR_OUT_OF_DATE_KHR => {
-                        self.recreate_swapchain();
-                        return;
-                    }
-                    _ => panic!("Failed to acquire swapchain image!")
+    // Post processing into the swapchain image and everything drawn over it, ends the swapchain render pass
+    fn record_post_process(&self, i: usize, command_buffer: vk::CommandBuffer, breadcrumbs: &mut Breadcrumbs) {
+        let logical_device = &self.device;
+        breadcrumbs.pass("Post Process");
+        self.hooks.record(HookPoint::BeforePostProcess, &self.frame_context(i, command_buffer, vk::RenderPass::null()));
+
+        // Flares follow the screen, they'd be placed in every tile of a screenshot on their own
+        let flare_sources = match &self.capture {
+            Some(_) => Vec::new(),
+            None => self.lens_flare_sources()
+        };
+        self.post_process.record(logical_device, command_buffer, i, self.camera_sets[i], self.renderpass, self.swapchain.framebuffers[i], &self.viewport,
+            self.frame_index, &flare_sources, self.capture.as_ref().map(|capture| &capture.target), self.swapchain.alpha);
+
+        breadcrumbs.pass("Overlay");
+        // The gizmo edits through the main camera, split views are for playing
+        if let (Some(index), true) = (self.selected_index(), self.split_views.is_empty()) {
+            let space = self.parent_matrix(index);
+            self.gizmo.record(logical_device, command_buffer, self.camera_sets[i], space, self.game_objects[index].transform3d.translation,
+                space.inversed().transform_point3(self.camera.position));
+        }
+
+        self.hooks.record(HookPoint::Overlay, &self.frame_context(i, command_buffer, self.renderpass));
+        // The UI covers the whole window, bars included
+        Self::set_viewport(logical_device, command_buffer, self.swapchain.extent);
+        self.sdf_text.record(logical_device, command_buffer, i, self.swapchain.extent, &self.upload_ring);
+        self.ui.record(logical_device, command_buffer, i, self.swapchain.extent, &self.upload_ring);
+        unsafe { logical_device.cmd_end_render_pass(command_buffer); }
+    }
+
+    pub fn set_viewport(logical_device: &ash::Device, command_buffer: vk::CommandBuffer, extent: vk::Extent2D) {
+        Self::set_viewport_rect(logical_device, command_buffer, vk::Rect2D {
+            offset: vk::Offset2D { x: 0, y: 0 },
+            extent
+        });
+    }
+
+    // Viewport and scissor both cover `rect`
+    pub fn set_viewport_rect(logical_device: &ash::Device, command_buffer: vk::CommandBuffer, rect: vk::Rect2D) {
+        let viewports = [vk::Viewport {
+            x: rect.offset.x as f32,
+            y: rect.offset.y as f32,
+            width: rect.extent.width as f32,
+            height: rect.extent.height as f32,
+            min_depth: 0.0,
+            max_depth: 1.0,
+        }];
+
+        let scissors = [rect];
+        
+        unsafe {
+            logical_device.cmd_set_viewport(command_buffer, 0, &viewports);
+            logical_device.cmd_set_scissor(command_buffer, 0, &scissors);
+        }
+    }
+
+    // Draws the opaque scene into the six faces of `capture`
+    fn record_cubemap_capture(&self, command_buffer: vk::CommandBuffer, index: usize, capture: &CubemapCapture) {
+        for face in 0..6 {
+            let camera_set = capture.camera_set(index, face);
+            self.cull_scene(command_buffer, index, camera_set);
+            capture.begin_face(&self.device, command_buffer, face);
+            unsafe {
+                self.device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, capture.pipeline.pipeline);
+                self.bind_scene_sets(command_buffer, capture.pipeline.layout, camera_set, index);
+                for material in [Material::Basic, Material::Lightmapped] {
+                    self.draw_material(command_buffer, index, &capture.pipeline, material, None);
                 }
+
+                self.device.cmd_end_render_pass(command_buffer);
             }
-        };
+        }
+    }
+
+    // Frame, object and lighting sets of a scene pipeline, the material set is bound per texture by `draw_material`
+    fn bind_scene_sets(&self, command_buffer: vk::CommandBuffer, layout: vk::PipelineLayout, camera_set: vk::DescriptorSet, index: usize) {
+        unsafe {
+            self.device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::GRAPHICS, layout, FRAME_SET, &[camera_set], &[]);
+            self.device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::GRAPHICS, layout, OBJECT_SET,
+                &[self.objects.set, self.lighting.sets[index]], &[self.objects.dynamic_offset(&self.upload_ring, index)]);
+        }
+    }
+
+    // Culls the scene for the camera the next scene pass draws with, outside of any render pass
+    fn cull_scene(&self, command_buffer: vk::CommandBuffer, index: usize, camera_set: vk::DescriptorSet) {
+        if let Some(gpu_culling) = &self.gpu_culling {
+            gpu_culling.record_culling(&self.device, command_buffer, index, camera_set);
+        }
+    }
 
+    // Draws the objects of `material` with `pipeline`, bound along with the scene sets. With GPU culling these are
+    // the indirect draws `cull_scene` left for the pass's camera, otherwise one draw per object.
+    // GPU culled draws are indirect, so they aren't predicated
+    fn draw_material(&self, command_buffer: vk::CommandBuffer, index: usize, pipeline: &Pipeline, material: Material,
+        predicates: Option<&ConditionalRendering>
+    ) {
+        match &self.gpu_culling {
+            Some(gpu_culling) => gpu_culling.record_draws(&self.device, command_buffer, index, pipeline, material),
+            None => Self::draw_game_objects(&self.device, command_buffer, pipeline, &self.game_objects, &self.materials, material, predicates)
+        }
+    }
+
+    // Occlusion queries are only tested from the main camera, other views and captures draw everything
+    fn predicates(&self, view_index: usize) -> Option<&ConditionalRendering> {
+        self.conditional_rendering.as_ref().filter(|_| view_index == 0)
+    }
+
+    // Objects past MAX_OBJECTS have no per object data and are skipped. With `predicates`, objects with an occlusion query
+    // are only drawn if it found them visible.
+    pub fn draw_game_objects(logical_device: &ash::Device, command_buffer: vk::CommandBuffer, pipeline: &Pipeline, game_objects: &[GameObject],
+        materials: &MaterialSets, material: Material, predicates: Option<&ConditionalRendering>
+    ) {
+        // The index into the object buffer is the position in the full list, so filter after enumerating.
+        // Objects sampling the same texture are drawn together so its set is only bound once.
+        let mut draws: Vec<(usize, vk::DescriptorSet)> = game_objects
+            .iter()
+            .enumerate()
+            .take(MAX_OBJECTS)
+            .filter(|(_, game_object)| game_object.material == material)
+            .filter_map(|(index, game_object)| materials.set(game_object).map(|set| (index, set)))
+            .collect();
+        draws.sort_by_key(|&(_, set)| set.as_raw());
+
+        let mut bound_set = vk::DescriptorSet::null();
         unsafe {
-            self.device.wait_for_fences(&[self.swapchain.may_begin_drawing[self.swapchain.current_image]], true, std::u64::MAX)
-                .expect("Fence wait failed!");
+            for (index, set) in draws {
+                if set != bound_set {
+                    logical_device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::GRAPHICS, pipeline.layout, MATERIAL_SET, &[set], &[]);
+                    bound_set = set;
+                }
+
+                let predicate = predicates.zip(game_objects[index].occlusion_query);
+                if let Some((predicates, query)) = predicate {
+                    predicates.begin(command_buffer, query);
+                }
+                game_objects[index].mesh.record_draw(logical_device, command_buffer, index as u32);
+                if let Some((predicates, _)) = predicate {
+                    predicates.end(command_buffer);
+                }
+            }
+        }
+    }
+
+    // What `fill_commandbuffers` records for the current scene
+    pub fn render_stats(&self) -> RenderStats {
+        let assignment = self.shadow_assignment();
+        let shadow_views = assignment.spot.iter().flatten().count() + assignment.point.iter().flatten().count() * 6;
+        // Reflective objects are only drawn when there's a planar reflection to show
+        let in_scene = |game_object: &&GameObject| game_object.material != Material::Reflective || self.reflection.is_some();
+        let draws = |game_object: &GameObject| game_object.mesh.vertex_buffers.len();
+        let all_draws: usize = self.game_objects.iter().map(draws).sum();
+
+        RenderStats {
+            game_objects: self.game_objects.len(),
+            scene_draws: self.game_objects.iter().filter(in_scene).map(draws).sum(),
+            reflection_draws: match self.reflection {
+                Some(_) => self.game_objects.iter().filter(|game_object| game_object.material != Material::Reflective).map(draws).sum(),
+                None => 0
+            },
+            shadow_draws: shadow_views * all_draws,
+            triangles: self.game_objects.iter().filter(in_scene).map(|game_object| game_object.mesh.triangle_count() as u64).sum(),
+            point_lights: self.lights.len(),
+            spot_lights: self.spot_lights.len(),
+            shadow_views
+        }
+    }
+
+    pub fn shadow_assignment(&self) -> ShadowAssignment {
+        ShadowSystem::assign(&self.spot_lights, &self.lights, self.camera.position)
+    }
+
+    // Seconds since the renderer started, wrapped every hour so waves don't lose their float precision
+    pub fn animation_time(&self) -> f32 {
+        match &self.capture {
+            Some(capture) => capture.time,
+            None => (self.animation_start.elapsed().as_secs_f64() % 3600.0) as f32
+        }
+    }
+
+    pub fn update_uniforms(&mut self, index: usize) {
+        crate::profile_scope!("Update Uniforms");
+        let extent = self.viewport.render_extent;
+        let time = self.animation_time();
+        let mut uniform = self.camera.uniform(extent);
+        if let Some(capture) = &self.capture {
+            uniform.projection = capture.projection() * uniform.projection;
+            uniform.inverse_projection = uniform.projection.inversed();
+        }
+        uniform.animate(time, &self.wind);
+        let view_projection = uniform.projection * uniform.view;
+        uniform.previous_view_projection = self.previous_view_projection.unwrap_or(view_projection);
+        self.previous_view_projection = Some(view_projection);
+
+        // Motion vectors and the previous matrices stay unjittered, only what gets rasterized moves
+        if self.anti_aliasing == AntiAliasing::Taa {
+            let (x, y) = JITTER_SEQUENCE[(self.frame_index % JITTER_SEQUENCE.len() as u64) as usize];
+            let jitter = uv::Vec3::new(x * 2.0 / extent.width as f32, y * 2.0 / extent.height as f32, 0.0);
+            uniform.projection = uv::Mat4::from_translation(jitter) * uniform.projection;
+            uniform.inverse_projection = uniform.projection.inversed();
+            uniform.jitter = jitter.into_homogeneous_vector();
+        }
+        self.camera_buffers[index].update_buffer(&uniform);
+        if let Some(virtual_texture) = &mut self.virtual_texture {
+            virtual_texture.update(index, self.frame_index);
+        }
+
+        self.objects.update(index, &self.game_objects, &self.materials, &mut self.upload_ring);
+
+        let shadow_assignment = self.shadow_assignment();
+        let sun = self.directional_light();
+        let ambient = match &self.atmosphere {
+            Some(atmosphere) => atmosphere.ambient() + self.ambient_light,
+            None => self.ambient_light
+        };
+        self.lighting.update(index, &self.lights, &self.spot_lights, sun.as_ref(), &shadow_assignment, &self.shadows.settings, ambient);
+
+        if let Some(reflection) = &mut self.reflection {
+            reflection.update(index, &self.camera);
+        }
+
+        for capture in &mut self.cubemaps {
+            capture.update(index);
+        }
+        self.reflection_probes.update(index);
+        self.light_probes.update(index);
+        if let Some(gpu_culling) = &mut self.gpu_culling {
+            gpu_culling.update(index);
+        }
+
+        for view in &mut self.split_views {
+            let rect = view.area.rect(self.viewport.render_extent);
+            view.update(index, rect, self.reflection.as_ref(), time, &self.wind);
+        }
+
+        self.post_process.auto_exposure.tick();
+        self.post_process.update(index, self.capture.is_some());
+        let result = self.occlusion_queries.collect(&self.device, index, self.frame_index);
+        self.check_device(result, "Failed to read the occlusion queries!");
+        self.sdf_text.update(index, &mut self.upload_ring);
+        self.ui.update(index, &mut self.upload_ring);
+    }
+
+    // Unwraps the result of waiting on or submitting to the GPU, a lost device is reported before panicking
+    fn check_device<T>(&self, result: Result<T, vk::Result>, message: &str) -> T {
+        match result {
+            Ok(value) => value,
+            Err(vk::Result::ERROR_DEVICE_LOST) => self.device_lost(),
+            Err(error) => panic!("{}: {:?}", message, error)
+        }
+    }
+
+    // Logs the crash report and writes it into the working directory, so hangs that only happen on
+    // someone else's machine can still be narrowed down
+    fn device_lost(&self) -> ! {
+        let device_name = unsafe { std::ffi::CStr::from_ptr(self.physical_device_properties.device_name.as_ptr()) }.to_string_lossy();
+        let report = format!("Vulkan device lost on frame {} ({})\n{}", self.frame_index, device_name,
+            self.crash_diagnostics.report(&self.device, self.queues.graphics_queue, &self.allocator));
+        tracing::error!("{}", report);
+
+        let path = format!("reverie-device-lost-{}.txt", std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |time| time.as_secs()));
+        match std::fs::write(&path, &report) {
+            Ok(()) => tracing::error!("Wrote the device lost report to {}", path),
+            Err(error) => tracing::error!("Failed to write the device lost report to {}: {}", path, error)
+        }
+
+        panic!("Vulkan device lost!");
+    }
+
+    pub fn draw_frame(&mut self) {
+        if self.suspended {
+            return;
         }
+        crate::profile_scope!("Draw Frame");
+
+        // Nothing was acquired when the swapchain had to be recreated
+        let image_index = match self.acquired_image.take() {
+            Some(image_index) => image_index as u32,
+            None => return
+        };
+
+        self.update_uniforms(image_index as usize);
 
-        let semaphores_available = [self.swapchain.image_available[self.swapchain.current_image]];
+        // Nothing signals or waits for the semaphores of offscreen images, there's no acquire and no present
+        let (semaphores_available, semaphores_finished) = match self.swapchain.is_offscreen() {
+            true => (vec![], vec![]),
+            false => (vec![self.swapchain.image_available[self.swapchain.current_image]], vec![self.swapchain.rendering_finished[self.swapchain.current_image]])
+        };
         let waiting_stages = [vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT];
-        let semaphores_finished = [self.swapchain.rendering_finished[self.swapchain.current_image]];
-        let command_buffers = [self.command_buffers[image_index as usize]];
         let submit_info = [vk::SubmitInfo::builder()
             .wait_semaphores(&semaphores_available)
-            .wait_dst_stage_mask(&waiting_stages)
-            .command_buffers(&command_buffers)
+            .wait_dst_stage_mask(&waiting_stages[..semaphores_available.len()])
+            .command_buffers(self.frame_command_buffers(image_index as usize))
             .signal_semaphores(&semaphores_finished)
             .build()    
         ];
@@ -322,10 +2293,30 @@ impl VulkanRenderer {
         unsafe {
             self.device.reset_fences(&[self.swapchain.may_begin_drawing[self.swapchain.current_image]])
                 .expect("Fence reset failed!");
-
-            self.device.queue_submit(self.queues.graphics_queue, &submit_info, self.swapchain.may_begin_drawing[self.swapchain.current_image])
-                .expect("Failed to submit command buffer!");
         }
+        let fence = self.swapchain.may_begin_drawing[self.swapchain.current_image];
+        self.image_fences[image_index as usize] = fence;
+        self.crash_diagnostics.submitting(self.queues.graphics_queue, image_index as usize, fence, self.frame_index);
+        #[cfg(feature = "tracy")]
+        if let Some(gpu_profiler) = &mut self.gpu_profiler {
+            gpu_profiler.submitting(image_index as usize, self.crash_diagnostics.passes(image_index as usize));
+        }
+        let result = unsafe { self.device.queue_submit(self.queues.graphics_queue, &submit_info, fence) };
+        self.check_device(result, "Failed to submit command buffer!");
+        self.frame_index += 1;
+        profiling::end_frame();
+
+        // The next frame slot's transient sets are free again once the frame that last used the slot has finished
+        let next = (self.swapchain.current_image + 1) % self.swapchain.image_count;
+        let result = unsafe { self.device.wait_for_fences(&[self.swapchain.may_begin_drawing[next]], true, u64::MAX) };
+        self.check_device(result, "Fence wait failed!");
+        self.frame_descriptors[next].reset(&self.device)
+            .expect("Failed to reset frame descriptor pools!");
+
+        let swapchain_loader = match &self.swapchain.swapchain_loader {
+            Some(swapchain_loader) => swapchain_loader,
+            None => return
+        };
 
         let swapchains = [self.swapchain.swapchain];
         let indices = [image_index];
@@ -334,12 +2325,13 @@ impl VulkanRenderer {
             .swapchains(&swapchains)
             .image_indices(&indices);
         
-        let result = unsafe { self.swapchain.swapchain_loader.queue_present(self.queues.graphics_queue, &present_info) };
+        let result = unsafe { swapchain_loader.queue_present(self.queues.graphics_queue, &present_info) };
 
         let is_resized = match result {
             Ok(_) => self.is_framebuffer_resized,
             Err(vk_result) => match vk_result {
-                vk::Result::ERROR_OUT_OF_DATE_KHR | vk::Result::SUBOPTIMAL_KHR => true,
+                vk::Result::ERROR_OUT_OF_DATE_KHR | vk::Result::SUBOPTIMAL_KHR | vk::Result::ERROR_FULL_SCREEN_EXCLUSIVE_MODE_LOST_EXT => true,
+                vk::Result::ERROR_DEVICE_LOST => self.device_lost(),
                 _ => panic!("Failed to present swapchain image")
             }
         };
@@ -360,30 +2352,93 @@ impl Drop for VulkanRenderer {
                 game_object.mesh.destroy(&self.device, &mut self.allocator);
             }
 
-            self.device.free_command_buffers(self.pools.graphics_command_pool, &self.command_buffers);
+            if let Some(reflection) = &mut self.reflection {
+                reflection.destroy(&self.device, &mut self.allocator);
+            }
+            if let Some(thumbnails) = &mut self.thumbnails {
+                thumbnails.destroy(&self.device, &mut self.allocator);
+            }
+            for capture in &mut self.cubemaps {
+                capture.destroy(&self.device, &mut self.allocator, &mut self.descriptors)
+                    .expect("Failed to free cubemap descriptor sets!");
+            }
+            self.reflection_probes.destroy(&self.device, &mut self.allocator, &mut self.descriptors)
+                .expect("Failed to free reflection probe descriptor sets!");
+            self.light_probes.destroy(&self.device, &mut self.allocator, &mut self.descriptors)
+                .expect("Failed to free light probe descriptor sets!");
+            self.water.destroy(&self.device, &mut self.allocator, &mut self.descriptors)
+                .expect("Failed to free water descriptor sets!");
+            if let Some(virtual_texture) = &mut self.virtual_texture {
+                virtual_texture.destroy(&self.device, &mut self.allocator, &self.pools, &mut self.descriptors)
+                    .expect("Failed to free virtual texture descriptor sets!");
+            }
+            self.scatter.destroy(&self.device, &mut self.allocator, &mut self.descriptors)
+                .expect("Failed to free scatter descriptor sets!");
+            self.clouds.destroy(&self.device, &mut self.allocator, &mut self.descriptors)
+                .expect("Failed to free cloud descriptor sets!");
+            if let Some(gpu_culling) = &mut self.gpu_culling {
+                gpu_culling.destroy(&self.device, &mut self.allocator, &mut self.descriptors)
+                    .expect("Failed to free GPU culling descriptor sets!");
+            }
+            if let Some(shading_rate) = &mut self.shading_rate {
+                shading_rate.destroy(&self.device, &mut self.descriptors)
+                    .expect("Failed to free shading rate descriptor sets!");
+            }
+
+            for camera_buffer in &mut self.camera_buffers {
+                camera_buffer.destroy(&self.device, &mut self.allocator);
+            }
+            for view in &mut self.split_views {
+                view.destroy(&self.device, &mut self.allocator, self.descriptor_pool)
+                    .expect("Failed to free split view descriptor sets!");
+            }
+
+            self.post_process.destroy(&self.device, &mut self.allocator);
+            self.lighting.destroy(&self.device, &mut self.allocator);
+            self.objects.destroy(&self.device);
+            self.upload_ring.destroy(&self.device, &mut self.allocator);
+            self.texture_streaming.destroy(&self.device, &mut self.allocator, &self.pools);
+            self.materials.destroy(&self.device, &mut self.allocator);
+            self.shadows.destroy(&self.device, &mut self.allocator);
+            self.light_cookies.destroy(&self.device, &mut self.allocator);
+            self.crash_diagnostics.destroy(&self.device, &mut self.allocator);
+            #[cfg(feature = "tracy")]
+            if let Some(gpu_profiler) = &self.gpu_profiler {
+                gpu_profiler.destroy();
+            }
+
+            self.device.destroy_descriptor_set_layout(self.camera_set_layout, None);
+            self.device.destroy_descriptor_pool(self.descriptor_pool, None);
+            self.descriptors.destroy(&self.device);
+            for frame_descriptors in &mut self.frame_descriptors {
+                frame_descriptors.destroy(&self.device);
+            }
+
+            self.pools.free_frame_command_buffers(&self.device, &self.command_buffers);
 
             self.pools.cleanup(&self.device);
             self.pipeline.cleanup(&self.device);
+            self.lightmapped_pipeline.cleanup(&self.device);
+            self.cutout_pipeline.cleanup(&self.device);
+            self.transparent_pipelines.cleanup(&self.device);
+            self.sky.pipeline.cleanup(&self.device);
+            self.outline.destroy(&self.device);
+            self.occlusion_queries.destroy(&self.device);
+            if let Some(conditional_rendering) = &mut self.conditional_rendering {
+                conditional_rendering.destroy(&self.device, &mut self.allocator);
+            }
+            self.gizmo.destroy(&self.device, &mut self.allocator);
+            self.sdf_text.destroy(&self.device, &mut self.allocator);
+            self.ui.destroy(&self.device, &mut self.allocator);
             self.device.destroy_render_pass(self.renderpass, None);
-            self.swapchain.cleanup(&self.device);
+            self.swapchain.cleanup(&self.device, &mut self.allocator);
             std::mem::ManuallyDrop::drop(&mut self.allocator);
             self.device.destroy_device(None);
-            self.surface.cleanup();
+            if let Some(surface) = &mut self.surface {
+                surface.cleanup();
+            }
             self.debug.cleanup();
             self.instance.destroy_instance(None)
         };
     }
 }
-
-#[repr(C)]
-pub struct PushConstantData {
-    _transform: uv::Mat2,
-    _offset: uv::Vec2,
-    _color: align::Align16<uv::Vec3>
-}
-
-impl PushConstantData {
-    pub unsafe fn as_bytes(&self) -> &[u8] {
-        any_as_u8_slice(self)
-    }
-}
\ No newline at end of file
@@ -0,0 +1,583 @@
+use super::sdf_font::{GlyphQuad, SdfFont};
+use super::sdf_text::SdfFontId;
+
+// Text with inline markup for dialogue boxes, chat and the like, laid out into lines for `SdfText`. Tags are in square
+// brackets and nest:
+//
+//     [b]bold[/b] [i]italic[/i] [color=#ff8800]orange[/color] [color=#ffffff80]half transparent[/color] [icon=coin]
+//
+// "[[" is a literal bracket, tags that aren't known are kept as text. Text from players should go through `escape` so
+// it can't use markup.
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SpanContent {
+    Text(String),
+    // By the name it was added to `SdfText` with
+    Icon(String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Span {
+    pub content: SpanContent,
+    pub bold: bool,
+    pub italic: bool,
+    // `None` keeps the color of the style the text is drawn with
+    pub color: Option<uv::Vec4>,
+}
+
+pub fn parse(markup: &str) -> Vec<Span> {
+    let mut spans = vec![];
+    let mut text = String::new();
+    let (mut bold, mut italic) = (0usize, 0usize);
+    let mut colors: Vec<uv::Vec4> = vec![];
+
+    let mut rest = markup;
+    while let Some(start) = rest.find('[') {
+        text.push_str(&rest[..start]);
+        rest = &rest[start..];
+        if let Some(after) = rest.strip_prefix("[[") {
+            text.push('[');
+            rest = after;
+            continue;
+        }
+
+        let tag = match rest.find(']') {
+            Some(end) => &rest[1..end],
+            None => break
+        };
+        let known = match tag {
+            "b" | "/b" | "i" | "/i" | "/color" => true,
+            _ => tag.strip_prefix("color=").is_some_and(|color| parse_color(color).is_some()) || tag.starts_with("icon=")
+        };
+        if !known {
+            text.push('[');
+            rest = &rest[1..];
+            continue;
+        }
+
+        if !text.is_empty() {
+            spans.push(Span {
+                content: SpanContent::Text(std::mem::take(&mut text)),
+                bold: bold > 0,
+                italic: italic > 0,
+                color: colors.last().copied()
+            });
+        }
+        match tag {
+            "b" => bold += 1,
+            "/b" => bold = bold.saturating_sub(1),
+            "i" => italic += 1,
+            "/i" => italic = italic.saturating_sub(1),
+            "/color" => {
+                colors.pop();
+            }
+            _ => match (tag.strip_prefix("color="), tag.strip_prefix("icon=")) {
+                (Some(color), _) => colors.extend(parse_color(color)),
+                (_, Some(name)) => spans.push(Span {
+                    content: SpanContent::Icon(name.to_string()),
+                    bold: bold > 0,
+                    italic: italic > 0,
+                    color: colors.last().copied()
+                }),
+                _ => {}
+            }
+        }
+        rest = &rest[tag.len() + 2..];
+    }
+    text.push_str(rest);
+
+    if !text.is_empty() {
+        spans.push(Span {
+            content: SpanContent::Text(text),
+            bold: bold > 0,
+            italic: italic > 0,
+            color: colors.last().copied()
+        });
+    }
+    spans
+}
+
+// Shows `text` as it is when it's part of markup
+pub fn escape(text: &str) -> String {
+    text.replace('[', "[[")
+}
+
+// #rrggbb or #rrggbbaa
+fn parse_color(color: &str) -> Option<uv::Vec4> {
+    let hex = color.strip_prefix('#').filter(|hex| (hex.len() == 6 || hex.len() == 8) && hex.is_ascii())?;
+    let channel = |index: usize| u8::from_str_radix(&hex[index * 2..index * 2 + 2], 16).ok().map(|value| value as f32 / 255.0);
+    let alpha = match hex.len() {
+        8 => channel(3)?,
+        _ => 1.0
+    };
+    Some(uv::Vec4::new(channel(0)?, channel(1)?, channel(2)?, alpha))
+}
+
+// Fonts of the markup's variants, missing ones fall back to the closest there is and finally the regular font
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FontFamily {
+    pub regular: SdfFontId,
+    pub bold: Option<SdfFontId>,
+    pub italic: Option<SdfFontId>,
+    pub bold_italic: Option<SdfFontId>,
+}
+
+impl FontFamily {
+    pub fn new(regular: SdfFontId) -> Self {
+        Self {
+            regular,
+            bold: None,
+            italic: None,
+            bold_italic: None
+        }
+    }
+
+    pub fn variant(&self, bold: bool, italic: bool) -> SdfFontId {
+        let variant = match (bold, italic) {
+            (true, true) => self.bold_italic.or(self.bold).or(self.italic),
+            (true, false) => self.bold,
+            (false, true) => self.italic,
+            (false, false) => None
+        };
+        variant.unwrap_or(self.regular)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextAlign {
+    Left,
+    Center,
+    Right,
+}
+
+// Where text is laid out. Without a width lines only break at '\n'.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TextBox {
+    // In pixels, lines wrap between words and inside words too long for a line of their own
+    pub max_width: Option<f32>,
+    pub align: TextAlign,
+    // Lines past this are cut, ending the last one with an ellipsis if `ellipsis` is set. `Some(0)` lays out nothing.
+    pub max_lines: Option<usize>,
+    pub ellipsis: bool,
+    // Multiplies the font's line height
+    pub line_spacing: f32,
+}
+
+impl Default for TextBox {
+    fn default() -> Self {
+        Self {
+            max_width: None,
+            align: TextAlign::Left,
+            max_lines: None,
+            ellipsis: true,
+            line_spacing: 1.0
+        }
+    }
+}
+
+// Fonts and icons text is laid out with, see `SdfText`
+pub trait GlyphSource {
+    fn font(&self, id: SdfFontId) -> &SdfFont;
+    // Width over height, `None` if there's no icon by that name
+    fn icon_aspect(&self, name: &str) -> Option<f32>;
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum LaidOut {
+    Glyph { font: SdfFontId, color: uv::Vec4, quad: GlyphQuad },
+    // As tall as the line's text, from the top of the line
+    Icon { name: String, min: uv::Vec2, max: uv::Vec2 },
+}
+
+// Pixels from the top left of the box
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RichLayout {
+    pub items: Vec<LaidOut>,
+    pub size: uv::Vec2,
+    pub lines: usize,
+    // Lines were cut by `max_lines`
+    pub truncated: bool,
+}
+
+#[derive(Clone, Copy)]
+enum Atom<'a> {
+    Char { character: char, font: SdfFontId, color: uv::Vec4 },
+    Icon { name: &'a str, width: f32 },
+    Newline,
+}
+
+impl Atom<'_> {
+    fn is_space(&self) -> bool {
+        matches!(self, Atom::Char { character, .. } if character.is_whitespace())
+    }
+}
+
+// Atoms with their pen position and advance, `width` ends at the last one that isn't a space
+#[derive(Default)]
+struct Line {
+    items: Vec<(usize, f32, f32)>,
+    width: f32,
+}
+
+impl Line {
+    fn has_content(&self, atoms: &[Atom]) -> bool {
+        self.items.iter().any(|&(index, _, _)| !atoms[index].is_space())
+    }
+
+    fn place(&mut self, atoms: &[Atom], index: usize, x: f32, advance: f32) {
+        self.items.push((index, x, advance));
+        if !atoms[index].is_space() {
+            self.width = x + advance;
+        }
+    }
+
+    // Where the next atom goes
+    fn pen(&self) -> f32 {
+        self.items.last().map_or(0.0, |&(_, x, advance)| x + advance)
+    }
+}
+
+// Kerning against the atom before it on the line and the advance of atom `index`
+fn metrics(source: &impl GlyphSource, atoms: &[Atom], previous: Option<usize>, index: usize, size: f32) -> (f32, f32) {
+    match atoms[index] {
+        Atom::Char { character, font, .. } => {
+            let sdf_font = source.font(font);
+            let kern = match previous.map(|previous| atoms[previous]) {
+                Some(Atom::Char { character: before, font: before_font, .. }) if before_font == font => sdf_font.kern(before, character, size),
+                _ => 0.0
+            };
+            (kern, sdf_font.advance(character, size))
+        }
+        Atom::Icon { width, .. } => (0.0, width),
+        Atom::Newline => (0.0, 0.0)
+    }
+}
+
+// Lays out `spans` at `size` pixels per em, text without a color of its own in `color`
+pub fn layout(source: &impl GlyphSource, family: &FontFamily, spans: &[Span], size: f32, color: uv::Vec4, text_box: &TextBox) -> RichLayout {
+    let regular = source.font(family.regular);
+    let (ascent, descent) = (regular.ascent(size), regular.descent(size));
+    let line_height = regular.line_height(size) * text_box.line_spacing;
+
+    let mut atoms = vec![];
+    for span in spans {
+        let color = span.color.unwrap_or(color);
+        match &span.content {
+            SpanContent::Text(text) => {
+                let font = family.variant(span.bold, span.italic);
+                atoms.extend(text.chars().map(|character| match character {
+                    '\n' => Atom::Newline,
+                    _ => Atom::Char { character, font, color }
+                }));
+            }
+            // Unknown icons are left out
+            SpanContent::Icon(name) => atoms.extend(source.icon_aspect(name).map(|aspect| Atom::Icon { name, width: (ascent - descent) * aspect }))
+        }
+    }
+
+    let fits = |x: f32| text_box.max_width.is_none_or(|max_width| x <= max_width);
+    let mut lines = vec![Line::default()];
+    let mut index = 0;
+    while index < atoms.len() {
+        if let Atom::Newline = atoms[index] {
+            lines.push(Line::default());
+            index += 1;
+            continue;
+        }
+        // Spaces stay on the line they follow, past its end when they don't fit
+        if atoms[index].is_space() {
+            let line = lines.last_mut().unwrap();
+            let (kern, advance) = metrics(source, &atoms, line.items.last().map(|item| item.0), index, size);
+            let x = line.pen() + kern;
+            line.place(&atoms, index, x, advance);
+            index += 1;
+            continue;
+        }
+
+        // The word up to the next space or line break goes on a new line if it doesn't fit on this one
+        let end = (index..atoms.len()).find(|&end| atoms[end].is_space() || matches!(atoms[end], Atom::Newline)).unwrap_or(atoms.len());
+        let line = lines.last().unwrap();
+        let (mut x, mut previous) = (line.pen(), line.items.last().map(|item| item.0));
+        for word in index..end {
+            let (kern, advance) = metrics(source, &atoms, previous, word, size);
+            x += kern + advance;
+            previous = Some(word);
+        }
+        if !fits(x) && line.has_content(&atoms) {
+            lines.push(Line::default());
+        }
+
+        // Words longer than a line break where they reach its end
+        for word in index..end {
+            let line = lines.last().unwrap();
+            let (mut kern, advance) = metrics(source, &atoms, line.items.last().map(|item| item.0), word, size);
+            if !fits(line.pen() + kern + advance) && line.has_content(&atoms) {
+                lines.push(Line::default());
+                kern = 0.0;
+            }
+            let line = lines.last_mut().unwrap();
+            let x = line.pen() + kern;
+            line.place(&atoms, word, x, advance);
+        }
+        index = end;
+    }
+
+    let truncated = text_box.max_lines.is_some_and(|max_lines| lines.len() > max_lines);
+    if let (true, Some(max_lines)) = (truncated, text_box.max_lines) {
+        lines.truncate(max_lines);
+        // No lines leave nowhere for the ellipsis
+        if let (true, Some(line)) = (text_box.ellipsis, lines.last_mut()) {
+            // In the font and color of the last character
+            let (font, color) = line.items.iter().rev().find_map(|&(index, _, _)| match atoms[index] {
+                Atom::Char { font, color, .. } => Some((font, color)),
+                _ => None
+            }).unwrap_or((family.regular, color));
+            let dots = match source.font(font).has_glyph('…') {
+                true => "…",
+                false => "..."
+            };
+            let first_dot = atoms.len();
+            atoms.extend(dots.chars().map(|character| Atom::Char { character, font, color }));
+            let width: f32 = dots.chars().map(|character| source.font(font).advance(character, size)).sum();
+
+            // Drops what's in the way of the ellipsis, and spaces it would follow
+            while let Some(&(index, x, advance)) = line.items.last() {
+                if !atoms[index].is_space() && fits(x + advance + width) {
+                    break;
+                }
+                line.items.pop();
+            }
+            line.width = line.pen();
+            for dot in first_dot..atoms.len() {
+                let (kern, advance) = metrics(source, &atoms, line.items.last().map(|item| item.0), dot, size);
+                let x = line.pen() + kern;
+                line.place(&atoms, dot, x, advance);
+            }
+        }
+    }
+
+    let box_width = text_box.max_width.unwrap_or_else(|| lines.iter().map(|line| line.width).fold(0.0, f32::max));
+    let mut items = vec![];
+    for (row, line) in lines.iter().enumerate() {
+        let shift = match text_box.align {
+            TextAlign::Left => 0.0,
+            TextAlign::Center => (box_width - line.width) / 2.0,
+            TextAlign::Right => box_width - line.width
+        };
+        let baseline = ascent + row as f32 * line_height;
+        for &(index, x, _) in &line.items {
+            match atoms[index] {
+                Atom::Char { character, font, color } => {
+                    if let Some(quad) = source.font(font).glyph_quad(character, uv::Vec2::new(x + shift, baseline), size) {
+                        items.push(LaidOut::Glyph { font, color, quad });
+                    }
+                }
+                Atom::Icon { name, width } => {
+                    let min = uv::Vec2::new(x + shift, baseline - ascent);
+                    items.push(LaidOut::Icon { name: name.to_string(), min, max: min + uv::Vec2::new(width, ascent - descent) });
+                }
+                Atom::Newline => {}
+            }
+        }
+    }
+
+    RichLayout {
+        items,
+        size: uv::Vec2::new(box_width, match lines.len() {
+            0 => 0.0,
+            count => ascent - descent + (count - 1) as f32 * line_height
+        }),
+        lines: lines.len(),
+        truncated
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vulkan::sdf_font::SdfFontSettings;
+
+    // A TrueType font of 1000 units per em where every printable character and the ellipsis is a 400 by 700 box with
+    // an advance of 500, and space advances 250. At 10 pixels: characters are 5 wide, spaces 2.5, lines 10 apart.
+    fn box_font() -> Vec<u8> {
+        let be16 = |values: &[i16]| values.iter().flat_map(|value| value.to_be_bytes()).collect::<Vec<u8>>();
+        let be32 = |values: &[u32]| values.iter().flat_map(|value| value.to_be_bytes()).collect::<Vec<u8>>();
+
+        let glyph = [be16(&[1, 50, 0, 450, 700, 3, 0]), vec![1; 4], be16(&[50, 0, 400, 0, 0, 700, 0, -700])].concat();
+        let mut head = [be32(&[0x10000, 0, 0, 0x5f0f3cf5]), be16(&[0, 1000]), vec![0; 16], be16(&[50, 0, 450, 700, 0, 0, 0, 1, 0])].concat();
+        head.resize(54, 0);
+        let mut hhea = [be32(&[0x10000]), be16(&[800, -200, 0])].concat();
+        hhea.resize(34, 0);
+        hhea.extend(be16(&[3]));
+        let cmap = [be16(&[0, 1, 0, 4]), be32(&[12]), be16(&[13, 0]), be32(&[52, 0, 3, 0x20, 0x20, 1, 0x21, 0x7e, 2, 0x2026, 0x2026, 2])].concat();
+        let tables: [(&[u8; 4], Vec<u8>); 7] = [
+            (b"cmap", cmap),
+            (b"glyf", glyph.clone()),
+            (b"head", head),
+            (b"hhea", hhea),
+            (b"hmtx", be16(&[500, 0, 250, 0, 500, 50])),
+            (b"loca", be32(&[0, 0, 0, glyph.len() as u32])),
+            (b"maxp", [be32(&[0x5000]), be16(&[3])].concat())
+        ];
+
+        let mut font = [be32(&[0x10000]), be16(&[tables.len() as i16, 0, 0, 0])].concat();
+        let mut offset = font.len() + tables.len() * 16;
+        for (tag, data) in &tables {
+            font.extend_from_slice(*tag);
+            font.extend(be32(&[0, offset as u32, data.len() as u32]));
+            offset += data.len().next_multiple_of(4);
+        }
+        for (_, data) in &tables {
+            font.extend(data);
+            font.resize(font.len().next_multiple_of(4), 0);
+        }
+        font
+    }
+
+    struct Fonts(Vec<SdfFont>);
+
+    impl GlyphSource for Fonts {
+        fn font(&self, id: SdfFontId) -> &SdfFont {
+            &self.0[id.0]
+        }
+
+        fn icon_aspect(&self, name: &str) -> Option<f32> {
+            (name == "coin").then_some(2.0)
+        }
+    }
+
+    fn fonts() -> Fonts {
+        let settings = SdfFontSettings {
+            base_size: 16.0,
+            spread: 2.0,
+            characters: vec![' ', 'a', 'b', 'c', '?', '…']
+        };
+        Fonts((0..2).map(|_| SdfFont::new(box_font(), settings.clone()).unwrap()).collect())
+    }
+
+    fn text(text: &str, bold: bool, italic: bool, color: Option<uv::Vec4>) -> Span {
+        Span {
+            content: SpanContent::Text(text.to_string()),
+            bold,
+            italic,
+            color
+        }
+    }
+
+    fn lay_out(fonts: &Fonts, markup: &str, text_box: TextBox) -> RichLayout {
+        layout(fonts, &FontFamily::new(SdfFontId(0)), &parse(markup), 10.0, uv::Vec4::one(), &text_box)
+    }
+
+    fn glyph_x(layout: &RichLayout) -> Vec<f32> {
+        layout.items.iter().filter_map(|item| match item {
+            LaidOut::Glyph { quad, .. } => Some(quad.min.x),
+            _ => None
+        }).collect()
+    }
+
+    #[test]
+    fn parse_nests_styles() {
+        let spans = parse("[b]bold [i]both[/i][/b] [color=#ff000080]red [icon=coin][/color]plain");
+        let red = Some(uv::Vec4::new(1.0, 0.0, 0.0, 128.0 / 255.0));
+        assert_eq!(spans, vec![
+            text("bold ", true, false, None),
+            text("both", true, true, None),
+            text(" ", false, false, None),
+            text("red ", false, false, red),
+            Span { content: SpanContent::Icon("coin".to_string()), bold: false, italic: false, color: red },
+            text("plain", false, false, None)
+        ]);
+    }
+
+    #[test]
+    fn parse_keeps_unknown_tags_as_text() {
+        let markup = "[u]x[/u] [color=red]y [color=#12345]z [[b] [icon";
+        assert_eq!(parse(markup), vec![text("[u]x[/u] [color=red]y [color=#12345]z [b] [icon", false, false, None)]);
+        assert_eq!(parse(&escape("[b]x[/b]")), vec![text("[b]x[/b]", false, false, None)]);
+    }
+
+    #[test]
+    fn parse_tolerates_unbalanced_tags() {
+        // Closing what isn't open does nothing, what's left open lasts to the end
+        assert_eq!(parse("[/b][/color]a[b]b[/i]c"), vec![text("a", false, false, None), text("b", true, false, None), text("c", true, false, None)]);
+        assert_eq!(parse("[b][b]a[/b]b[/b]c"), vec![text("a", true, false, None), text("b", true, false, None), text("c", false, false, None)]);
+    }
+
+    #[test]
+    fn layout_wraps_between_words() {
+        let fonts = fonts();
+        let layout = lay_out(&fonts, "aa bb cc", TextBox { max_width: Some(12.0), ..Default::default() });
+        assert_eq!(layout.lines, 3);
+        assert_eq!(layout.size, uv::Vec2::new(12.0, 30.0));
+        assert!(!layout.truncated);
+        let x = glyph_x(&layout);
+        assert_eq!(x.len(), 6);
+        assert_eq!((x[0], x[2], x[4]), (x[0], x[0], x[0]));
+        assert_eq!(x[1] - x[0], 5.0);
+
+        // Without a width only line breaks start lines
+        let layout = lay_out(&fonts, "aa bb\ncc", TextBox::default());
+        assert_eq!(layout.lines, 2);
+        assert_eq!(layout.size, uv::Vec2::new(22.5, 20.0));
+    }
+
+    #[test]
+    fn layout_breaks_long_words() {
+        let fonts = fonts();
+        let layout = lay_out(&fonts, "aaaaa", TextBox { max_width: Some(12.0), ..Default::default() });
+        assert_eq!(layout.lines, 3);
+        assert_eq!(glyph_x(&layout).len(), 5);
+    }
+
+    #[test]
+    fn layout_cuts_lines_with_an_ellipsis() {
+        let fonts = fonts();
+        let text_box = TextBox { max_width: Some(12.0), max_lines: Some(2), ..Default::default() };
+        let layout = lay_out(&fonts, "aa bb cc", text_box);
+        assert_eq!((layout.lines, layout.truncated), (2, true));
+        // The second b makes way for the ellipsis
+        let x = glyph_x(&layout);
+        assert_eq!(x.len(), 4);
+        assert_eq!(x[3] - x[2], 5.0);
+
+        let layout = lay_out(&fonts, "aa bb cc", TextBox { ellipsis: false, ..text_box });
+        assert_eq!(glyph_x(&layout).len(), 4);
+        let layout = lay_out(&fonts, "aa bb cc", TextBox { max_lines: Some(3), ..text_box });
+        assert_eq!((layout.lines, layout.truncated), (3, false));
+    }
+
+    #[test]
+    fn layout_without_lines_is_empty() {
+        let fonts = fonts();
+        let layout = lay_out(&fonts, "aa bb", TextBox { max_lines: Some(0), ..Default::default() });
+        assert_eq!(layout, RichLayout { items: vec![], size: uv::Vec2::zero(), lines: 0, truncated: true });
+    }
+
+    #[test]
+    fn layout_places_icons_and_aligns() {
+        let fonts = fonts();
+        // Icons are as tall as the text, unknown ones are left out
+        let layout = lay_out(&fonts, "a[icon=coin][icon=missing]b", TextBox::default());
+        assert_eq!(layout.size, uv::Vec2::new(30.0, 10.0));
+        assert!(layout.items.contains(&LaidOut::Icon { name: "coin".to_string(), min: uv::Vec2::new(5.0, 0.0), max: uv::Vec2::new(25.0, 10.0) }));
+        assert_eq!(layout.items.len(), 3);
+
+        let left = glyph_x(&lay_out(&fonts, "aa", TextBox { max_width: Some(20.0), ..Default::default() }));
+        let center = glyph_x(&lay_out(&fonts, "aa", TextBox { max_width: Some(20.0), align: TextAlign::Center, ..Default::default() }));
+        let right = glyph_x(&lay_out(&fonts, "aa", TextBox { max_width: Some(20.0), align: TextAlign::Right, ..Default::default() }));
+        assert_eq!((center[0] - left[0], right[0] - left[0]), (5.0, 10.0));
+    }
+
+    #[test]
+    fn layout_uses_the_variant_fonts() {
+        let fonts = fonts();
+        let family = FontFamily { bold: Some(SdfFontId(1)), ..FontFamily::new(SdfFontId(0)) };
+        let layout = layout(&fonts, &family, &parse("a[b]b[i]c"), 10.0, uv::Vec4::one(), &TextBox::default());
+        let font_ids: Vec<SdfFontId> = layout.items.iter().filter_map(|item| match item {
+            LaidOut::Glyph { font, .. } => Some(*font),
+            _ => None
+        }).collect();
+        // Bold italic falls back to bold
+        assert_eq!(font_ids, vec![SdfFontId(0), SdfFontId(1), SdfFontId(1)]);
+    }
+}
\ No newline at end of file
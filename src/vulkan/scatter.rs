@@ -0,0 +1,462 @@
+use ash::vk;
+use gpu_allocator::vulkan::Allocator;
+use gpu_allocator::MemoryLocation;
+
+use super::compute_pipeline::ComputePipeline;
+use super::cutout::cutout_pipeline;
+use super::descriptor_allocator::DescriptorAllocator;
+use super::descriptors::Descriptors;
+use super::game_object::GameObject;
+use super::material::{Material, MaterialSets};
+use super::mesh::{BoundingSphere, Mesh};
+use super::pipeline::{Pipeline, PipelineConfig, MATERIAL_SET};
+use super::storage_buffer::StorageBuffer;
+use super::swapchain::VulkanSwapchain;
+
+use crate::utils::any_as_u8_slice;
+
+pub const SCATTER_VERT: &[u32] = vk_shader_macros::include_glsl!("./shaders/basic.vert", kind: vert, define: SCATTER);
+pub const SCATTER_CULL_COMP: &[u32] = vk_shader_macros::include_glsl!("./shaders/scatter_cull.comp", kind: comp);
+
+// Set of a layer's instances, after the scene sets
+pub const SCATTER_SET: u32 = 4;
+pub const MAX_SCATTER_INSTANCES: usize = 1 << 18;
+// Must match the local size in scatter_cull.comp
+const CULL_GROUP_SIZE: u32 = 64;
+
+// Must match ScatterInstance in basic.vert and scatter_cull.comp
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct ScatterInstance {
+    pub model: uv::Mat4,
+    // World space center and radius
+    pub sphere: uv::Vec4,
+}
+
+#[repr(C)]
+struct ScatterPushConstants {
+    _material: u32,
+    _fade_start: f32,
+    _fade_end: f32,
+}
+
+#[repr(C)]
+struct ScatterCullPushConstants {
+    _instance_count: u32,
+    _fade_end: f32,
+}
+
+// Where instances grow, from 0.0 (nowhere) to 1.0 (at the layer's full density), e.g. painted over a terrain's texture
+#[derive(Clone, Debug)]
+pub struct DensityMap {
+    width: u32,
+    height: u32,
+    values: Vec<f32>,
+}
+
+impl DensityMap {
+    // Rows from the top, like image data
+    pub fn new(width: u32, height: u32, values: Vec<f32>) -> Self {
+        assert_eq!(values.len(), (width * height) as usize, "Density map needs one value per texel!");
+        Self { width, height, values }
+    }
+
+    // One channel of 8 bit RGBA pixels
+    pub fn from_rgba8(width: u32, height: u32, rgba: &[u8], channel: usize) -> Self {
+        let values = rgba.chunks_exact(4).map(|pixel| pixel[channel] as f32 / 255.0).collect();
+        Self::new(width, height, values)
+    }
+
+    // Bilinear, texture coordinates repeat like the surface's textures do
+    pub fn sample(&self, tex_coord: uv::Vec2) -> f32 {
+        if self.values.is_empty() {
+            return 0.0;
+        }
+        let x = tex_coord.x.rem_euclid(1.0) * self.width as f32 - 0.5;
+        let y = tex_coord.y.rem_euclid(1.0) * self.height as f32 - 0.5;
+        let texel = |x: f32, y: f32| {
+            let x = (x as i32).rem_euclid(self.width as i32) as u32;
+            let y = (y as i32).rem_euclid(self.height as i32) as u32;
+            self.values[(y * self.width + x) as usize]
+        };
+
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let top = texel(x0, y0) * (1.0 - fx) + texel(x0 + 1.0, y0) * fx;
+        let bottom = texel(x0, y0 + 1.0) * (1.0 - fx) + texel(x0 + 1.0, y0 + 1.0) * fx;
+        top * (1.0 - fy) + bottom * fy
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ScatterSettings {
+    // Instances per square unit of surface where the density map is 1.0
+    pub density: f32,
+    // Sampled with the surface's texture coordinates, `None` is 1.0 everywhere
+    pub density_map: Option<DensityMap>,
+    pub min_scale: f32,
+    pub max_scale: f32,
+    // Steepest slope instances grow on, in radians
+    pub max_slope: f32,
+    // 0.0 grows straight up, 1.0 along the surface normal
+    pub align_to_normal: f32,
+    // Distance from the camera at which instances start shrinking away, they're gone and culled at `fade_end`
+    pub fade_start: f32,
+    pub fade_end: f32,
+    // The same seed spreads the same instances over the same surface
+    pub seed: u32,
+}
+
+impl Default for ScatterSettings {
+    fn default() -> Self {
+        Self {
+            density: 4.0,
+            density_map: None,
+            min_scale: 0.8,
+            max_scale: 1.2,
+            max_slope: 0.6,
+            align_to_normal: 0.3,
+            fade_start: 40.0,
+            fade_end: 60.0,
+            seed: 1
+        }
+    }
+}
+
+// Instances spread over the triangles of `surface` in world space, every triangle getting a share of the density
+// proportional to its area. Each one stands on the surface with a random turn around its up axis and a random scale.
+pub fn scatter_instances(surface: &Mesh, model: uv::Mat4, bounds: BoundingSphere, settings: &ScatterSettings) -> Vec<ScatterInstance> {
+    let mut seed = settings.seed.max(1);
+    let min_up = settings.max_slope.cos();
+    let normal_matrix = model.truncate().inversed().transposed();
+    let mut instances = Vec::new();
+
+    for vertex_buffer in &surface.vertex_buffers {
+        let vertices = vertex_buffer.read();
+        let indices: Vec<u32> = match &surface.index_buffer {
+            Some(index_buffer) => index_buffer.read().to_vec(),
+            None => (0..vertices.len() as u32).collect()
+        };
+
+        for triangle in indices.chunks_exact(3) {
+            let [a, b, c] = [triangle[0], triangle[1], triangle[2]].map(|index| vertices[index as usize]);
+            let positions = [a.pos, b.pos, c.pos].map(|position| model.transform_point3(position));
+            let area = (positions[1] - positions[0]).cross(positions[2] - positions[0]).mag() * 0.5;
+
+            // The fraction of an instance left over is placed with that chance, so small triangles still get theirs
+            let expected = area * settings.density;
+            let count = expected as u32 + (random(&mut seed) < expected.fract()) as u32;
+            for _ in 0..count {
+                // Uniform over the triangle
+                let (r1, r2) = (random(&mut seed).sqrt(), random(&mut seed));
+                let weights = [1.0 - r1, r1 * (1.0 - r2), r1 * r2];
+                let position = positions[0] * weights[0] + positions[1] * weights[1] + positions[2] * weights[2];
+                let normal = (normal_matrix * (a.normal * weights[0] + b.normal * weights[1] + c.normal * weights[2])).normalized();
+                let tex_coord = a.tex_coord * weights[0] + b.tex_coord * weights[1] + c.tex_coord * weights[2];
+
+                let density = settings.density_map.as_ref().map_or(1.0, |map| map.sample(tex_coord));
+                let (keep, turn, scale) = (random(&mut seed), random(&mut seed), random(&mut seed));
+                if normal.y < min_up || keep >= density {
+                    continue;
+                }
+
+                let align = settings.align_to_normal.clamp(0.0, 1.0);
+                let up = (uv::Vec3::unit_y() * (1.0 - align) + normal * align).normalized();
+                let scale = settings.min_scale + (settings.max_scale - settings.min_scale) * scale;
+                let rotation = uv::Rotor3::from_rotation_between(uv::Vec3::unit_y(), up) * uv::Rotor3::from_rotation_xz(turn * std::f32::consts::TAU);
+                let instance_model = uv::Mat4::from_translation(position) * rotation.into_matrix().into_homogeneous() * uv::Mat4::from_scale(scale);
+                let sphere = bounds.transformed(instance_model);
+                instances.push(ScatterInstance {
+                    model: instance_model,
+                    sphere: uv::Vec4::new(sphere.center.x, sphere.center.y, sphere.center.z, sphere.radius)
+                });
+
+                if instances.len() == MAX_SCATTER_INSTANCES {
+                    tracing::warn!("Scatter layer reached {} instances, the rest of the surface stays empty", MAX_SCATTER_INSTANCES);
+                    return instances;
+                }
+            }
+        }
+    }
+
+    instances
+}
+
+// xorshift, uniform in [0, 1)
+fn random(seed: &mut u32) -> f32 {
+    *seed ^= *seed << 13;
+    *seed ^= *seed >> 17;
+    *seed ^= *seed << 5;
+    (*seed >> 8) as f32 / (1 << 24) as f32
+}
+
+// Copies of one prototype object (grass, rocks, trees) spread over a surface. The instances are placed once, in world
+// space, so moving the surface leaves them behind. Every view culls them on the GPU into a visible list that one
+// instanced indirect draw goes through, drawing the first vertex buffer of the prototype's mesh.
+pub struct ScatterLayer {
+    // Mesh and material of every instance, its transform and hierarchy are ignored
+    pub prototype: GameObject,
+    pub fade_start: f32,
+    pub fade_end: f32,
+    instance_count: u32,
+    instances: StorageBuffer,
+    // Per swapchain image
+    visible: Vec<StorageBuffer>,
+    commands: Vec<StorageBuffer>,
+    sets: Vec<vk::DescriptorSet>,
+}
+
+impl ScatterLayer {
+    #[allow(clippy::too_many_arguments)]
+    fn new(device: &ash::Device, allocator: &mut Allocator, descriptors: &mut DescriptorAllocator, set_layout: vk::DescriptorSetLayout,
+        image_count: usize, prototype: GameObject, instances: &[ScatterInstance], settings: &ScatterSettings
+    ) -> Result<Self, vk::Result> {
+        // Buffers can't be empty
+        let instances_size = (instances.len().max(1) * std::mem::size_of::<ScatterInstance>()) as u64;
+        let mut instance_buffer = StorageBuffer::new(device, allocator, instances_size, MemoryLocation::CpuToGpu, "Scatter Instances");
+        instance_buffer.update_buffer(0, instances);
+
+        // The culling only ever writes the instance count
+        let command: Vec<u32> = match (&prototype.mesh.index_buffer, prototype.mesh.vertex_buffers.first()) {
+            (Some(index_buffer), _) => vec![index_buffer.get_index_count(), 0, 0, 0, 0],
+            (None, Some(vertex_buffer)) => vec![vertex_buffer.get_vertex_count(), 0, 0, 0],
+            (None, None) => vec![0, 0, 0, 0]
+        };
+
+        let visible_size = (instances.len().max(1) * std::mem::size_of::<u32>()) as u64;
+        let sets = descriptors.allocate_many(device, set_layout, image_count)?;
+        let mut visible = Vec::with_capacity(image_count);
+        let mut commands = Vec::with_capacity(image_count);
+        for &set in &sets {
+            let visible_buffer = StorageBuffer::new(device, allocator, visible_size, MemoryLocation::GpuOnly, "Scatter Visible Instances");
+            let mut command_buffer = StorageBuffer::with_usage(device, allocator, std::mem::size_of_val(command.as_slice()) as u64,
+                vk::BufferUsageFlags::INDIRECT_BUFFER | vk::BufferUsageFlags::TRANSFER_DST, MemoryLocation::CpuToGpu, "Scatter Draw Command");
+            command_buffer.update_buffer(0, &command);
+            Descriptors::write_buffer(device, set, 0, vk::DescriptorType::STORAGE_BUFFER, instance_buffer.descriptor_info());
+            Descriptors::write_buffer(device, set, 1, vk::DescriptorType::STORAGE_BUFFER, visible_buffer.descriptor_info());
+            Descriptors::write_buffer(device, set, 2, vk::DescriptorType::STORAGE_BUFFER, command_buffer.descriptor_info());
+            visible.push(visible_buffer);
+            commands.push(command_buffer);
+        }
+
+        Ok(Self {
+            prototype,
+            fade_start: settings.fade_start,
+            fade_end: settings.fade_end,
+            instance_count: instances.len() as u32,
+            instances: instance_buffer,
+            visible,
+            commands,
+            sets
+        })
+    }
+
+    pub fn instance_count(&self) -> u32 {
+        self.instance_count
+    }
+
+    fn destroy(&mut self, device: &ash::Device, allocator: &mut Allocator, descriptors: &mut DescriptorAllocator) -> Result<(), vk::Result> {
+        for &set in &self.sets {
+            descriptors.free(device, set)?;
+        }
+        for buffer in self.visible.iter_mut().chain(self.commands.iter_mut()) {
+            buffer.destroy(device, allocator);
+        }
+        self.instances.destroy(device, allocator);
+        self.prototype.mesh.destroy(device, allocator);
+        Ok(())
+    }
+}
+
+// Scene pipelines drawing the instances of scatter layers, cutout prototypes (grass, leaves) are alpha tested and
+// everything else is shaded like `Material::Basic`. Vertices come from the prototype's own buffers.
+pub struct ScatterPipelines {
+    basic: Pipeline,
+    cutout: Pipeline,
+}
+
+impl ScatterPipelines {
+    // Everything else comes from the basic scene pipeline's `scene_config`
+    pub fn new(device: &ash::Device, swapchain: &VulkanSwapchain, renderpass: &vk::RenderPass, scene_config: &PipelineConfig,
+        set_layout: vk::DescriptorSetLayout
+    ) -> Result<Self, vk::Result> {
+        let set_layouts: Vec<vk::DescriptorSetLayout> = scene_config.set_layouts.iter().copied().chain([set_layout]).collect();
+        let config = PipelineConfig {
+            vertex_shader: SCATTER_VERT,
+            set_layouts: &set_layouts,
+            push_constant_size: std::mem::size_of::<ScatterPushConstants>() as u32,
+            vertex_input: true,
+            dynamic_shading_rate: false,
+            ..*scene_config
+        };
+
+        Ok(Self {
+            basic: Pipeline::new(device, swapchain, renderpass, &config)?,
+            cutout: cutout_pipeline(device, swapchain, renderpass, &config)?
+        })
+    }
+
+    // Both pipelines have the same layout
+    pub fn layout(&self) -> vk::PipelineLayout {
+        self.basic.layout
+    }
+
+    fn get(&self, material: Material) -> &Pipeline {
+        match material {
+            Material::Cutout => &self.cutout,
+            _ => &self.basic
+        }
+    }
+
+    pub fn cleanup(&self, device: &ash::Device) {
+        self.basic.cleanup(device);
+        self.cutout.cleanup(device);
+    }
+}
+
+// Every scatter layer of the scene, see `ScatterLayer`
+pub struct Scatter {
+    set_layout: vk::DescriptorSetLayout,
+    cull_pipeline: ComputePipeline,
+    pub pipelines: ScatterPipelines,
+    pub layers: Vec<ScatterLayer>,
+}
+
+impl Scatter {
+    pub fn new(device: &ash::Device, swapchain: &VulkanSwapchain, renderpass: &vk::RenderPass, scene_config: &PipelineConfig,
+        camera_set_layout: vk::DescriptorSetLayout
+    ) -> Result<Self, vk::Result> {
+        // Bound at SCATTER_SET when drawing and after the camera set when culling
+        let set_layout = Descriptors::create_layout(device, &[
+            (vk::DescriptorType::STORAGE_BUFFER, vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::COMPUTE),
+            (vk::DescriptorType::STORAGE_BUFFER, vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::COMPUTE),
+            (vk::DescriptorType::STORAGE_BUFFER, vk::ShaderStageFlags::COMPUTE),
+        ])?;
+        let cull_pipeline = ComputePipeline::new(device, SCATTER_CULL_COMP, &[camera_set_layout, set_layout],
+            std::mem::size_of::<ScatterCullPushConstants>() as u32, &[])?;
+        let pipelines = ScatterPipelines::new(device, swapchain, renderpass, scene_config, set_layout)?;
+
+        Ok(Self {
+            set_layout,
+            cull_pipeline,
+            pipelines,
+            layers: Vec::new()
+        })
+    }
+
+    pub fn set_layout(&self) -> vk::DescriptorSetLayout {
+        self.set_layout
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn add_layer(&mut self, device: &ash::Device, allocator: &mut Allocator, descriptors: &mut DescriptorAllocator, image_count: usize,
+        prototype: GameObject, instances: &[ScatterInstance], settings: &ScatterSettings
+    ) -> Result<usize, vk::Result> {
+        let layer = ScatterLayer::new(device, allocator, descriptors, self.set_layout, image_count, prototype, instances, settings)?;
+        self.layers.push(layer);
+        Ok(self.layers.len() - 1)
+    }
+
+    // Only once the device stopped using the layer
+    pub fn remove_layer(&mut self, device: &ash::Device, allocator: &mut Allocator, descriptors: &mut DescriptorAllocator, index: usize
+    ) -> Result<(), vk::Result> {
+        let mut layer = self.layers.remove(index);
+        layer.destroy(device, allocator, descriptors)
+    }
+
+    pub fn prototypes(&self) -> impl Iterator<Item = &GameObject> {
+        self.layers.iter().map(|layer| &layer.prototype)
+    }
+
+    // Culls every layer against the view of `camera_set`, outside of any render pass. Views drawn one after another
+    // in the same command buffer each cull again, like `GpuCulling::record_culling`.
+    pub fn record_culling(&self, device: &ash::Device, command_buffer: vk::CommandBuffer, index: usize, camera_set: vk::DescriptorSet) {
+        if self.layers.is_empty() {
+            return;
+        }
+
+        let clear_barriers = [vk::MemoryBarrier::builder()
+            .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+            .dst_access_mask(vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE)
+            .build()
+        ];
+        let draw_barriers = [vk::MemoryBarrier::builder()
+            .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+            .dst_access_mask(vk::AccessFlags::INDIRECT_COMMAND_READ | vk::AccessFlags::SHADER_READ)
+            .build()
+        ];
+
+        unsafe {
+            device.cmd_pipeline_barrier(command_buffer, vk::PipelineStageFlags::DRAW_INDIRECT | vk::PipelineStageFlags::VERTEX_SHADER,
+                vk::PipelineStageFlags::TRANSFER | vk::PipelineStageFlags::COMPUTE_SHADER, vk::DependencyFlags::empty(), &[], &[], &[]);
+            for layer in &self.layers {
+                // The instance count, second in both kinds of commands
+                device.cmd_fill_buffer(command_buffer, layer.commands[index].get_buffer(), std::mem::size_of::<u32>() as u64,
+                    std::mem::size_of::<u32>() as u64, 0);
+            }
+            device.cmd_pipeline_barrier(command_buffer, vk::PipelineStageFlags::TRANSFER, vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::DependencyFlags::empty(), &clear_barriers, &[], &[]);
+
+            device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::COMPUTE, self.cull_pipeline.pipeline);
+            for layer in &self.layers {
+                let push = ScatterCullPushConstants {
+                    _instance_count: layer.instance_count,
+                    _fade_end: layer.fade_end
+                };
+                device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::COMPUTE, self.cull_pipeline.layout, 0,
+                    &[camera_set, layer.sets[index]], &[]);
+                device.cmd_push_constants(command_buffer, self.cull_pipeline.layout, vk::ShaderStageFlags::COMPUTE, 0, any_as_u8_slice(&push));
+                device.cmd_dispatch(command_buffer, layer.instance_count.div_ceil(CULL_GROUP_SIZE).max(1), 1, 1);
+            }
+
+            device.cmd_pipeline_barrier(command_buffer, vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::PipelineStageFlags::DRAW_INDIRECT | vk::PipelineStageFlags::VERTEX_SHADER, vk::DependencyFlags::empty(), &draw_barriers,
+                &[], &[]);
+        }
+    }
+
+    // Draws what `record_culling` left of every layer. The frame, object and lighting sets must be bound already,
+    // they're the same for every scene pipeline.
+    pub fn record_draws(&self, device: &ash::Device, command_buffer: vk::CommandBuffer, index: usize, materials: &MaterialSets) {
+        for layer in &self.layers {
+            let (set, vertex_buffer) = match (materials.set(&layer.prototype), layer.prototype.mesh.vertex_buffers.first()) {
+                (Some(set), Some(vertex_buffer)) => (set, vertex_buffer),
+                _ => continue
+            };
+            let pipeline = self.pipelines.get(layer.prototype.material);
+            let push = ScatterPushConstants {
+                _material: materials.index(&layer.prototype),
+                _fade_start: layer.fade_start,
+                _fade_end: layer.fade_end
+            };
+            let command = layer.commands[index].get_buffer();
+
+            unsafe {
+                device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, pipeline.pipeline);
+                device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::GRAPHICS, pipeline.layout, MATERIAL_SET, &[set], &[]);
+                device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::GRAPHICS, pipeline.layout, SCATTER_SET,
+                    &[layer.sets[index]], &[]);
+                device.cmd_push_constants(command_buffer, pipeline.layout, vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT, 0,
+                    any_as_u8_slice(&push));
+                device.cmd_bind_vertex_buffers(command_buffer, 0, &[vertex_buffer.get_buffer()], &[0]);
+                match &layer.prototype.mesh.index_buffer {
+                    Some(index_buffer) => {
+                        device.cmd_bind_index_buffer(command_buffer, index_buffer.get_buffer(), 0, vk::IndexType::UINT32);
+                        device.cmd_draw_indexed_indirect(command_buffer, command, 0, 1, 0);
+                    },
+                    None => device.cmd_draw_indirect(command_buffer, command, 0, 1, 0)
+                }
+            }
+        }
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device, allocator: &mut Allocator, descriptors: &mut DescriptorAllocator) -> Result<(), vk::Result> {
+        for layer in &mut self.layers {
+            layer.destroy(device, allocator, descriptors)?;
+        }
+        self.pipelines.cleanup(device);
+        self.cull_pipeline.cleanup(device);
+        unsafe { device.destroy_descriptor_set_layout(self.set_layout, None) };
+        Ok(())
+    }
+}
\ No newline at end of file
@@ -0,0 +1,203 @@
+use std::error::Error;
+use std::path::Path;
+
+use ash::vk;
+use gpu_allocator::vulkan::Allocator;
+
+use super::command_pools::Pools;
+use super::render_target::RenderTarget;
+use super::staging_buffer::StagingBuffer;
+
+#[derive(Clone, Copy, Debug)]
+pub struct ScreenshotSettings {
+    // Size of the screenshot relative to the render extent
+    pub scale: u32,
+    // Samples per screenshot pixel along each axis, averaged down into it
+    pub supersample: u32,
+    // Frames drawn of every tile before it's read back, temporal effects (TAA, clouds) settle on the frozen scene meanwhile
+    pub frames_per_tile: u32,
+}
+
+impl Default for ScreenshotSettings {
+    fn default() -> Self {
+        Self {
+            scale: 2,
+            supersample: 2,
+            frames_per_tile: 4
+        }
+    }
+}
+
+// RGBA8 pixels in sRGB, row by row from the top left
+pub struct Screenshot {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl Screenshot {
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Box<dyn Error>> {
+        let file = std::io::BufWriter::new(std::fs::File::create(path)?);
+        let mut encoder = png::Encoder::new(file, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_source_srgb(png::SrgbRenderingIntent::Perceptual);
+        encoder.write_header()?.write_image_data(&self.pixels)?;
+        Ok(())
+    }
+}
+
+// A screenshot in progress. The frame is split into `tiles` x `tiles` tiles of the render extent, each rendered on its
+// own through a projection zoomed in on it, so the GPU never holds more than a regular frame. Every tile is composited
+// into `target` next to the swapchain image, read back and averaged into the screenshot's pixels as it arrives.
+pub struct ScreenshotCapture {
+    pub target: RenderTarget,
+    pub settings: ScreenshotSettings,
+    // Animation time the scene is frozen at, tiles taken at different times wouldn't line up
+    pub time: f32,
+    // Column and row of the tile being rendered
+    pub tile: (u32, u32),
+    tiles: u32,
+    bgra: bool,
+    // Sums of the linear samples landing in every screenshot pixel
+    sums: Vec<[f32; 3]>,
+    width: u32,
+    height: u32,
+}
+
+impl ScreenshotCapture {
+    // `format` is the swapchain's, so the composite pipeline can draw into the target
+    pub fn new(device: &ash::Device, allocator: &mut Allocator, extent: vk::Extent2D, format: vk::Format, settings: ScreenshotSettings, time: f32
+    ) -> Result<Self, vk::Result> {
+        let settings = ScreenshotSettings {
+            scale: settings.scale.max(1),
+            supersample: settings.supersample.max(1),
+            frames_per_tile: settings.frames_per_tile.max(1)
+        };
+        let tiles = settings.scale * settings.supersample;
+        let width = extent.width * settings.scale;
+        let height = extent.height * settings.scale;
+        let target = RenderTarget::new(device, allocator, extent, &[format], false, "Screenshot Target")?;
+
+        Ok(Self {
+            target,
+            settings,
+            time,
+            tile: (0, 0),
+            tiles,
+            bgra: matches!(format, vk::Format::B8G8R8A8_SRGB | vk::Format::B8G8R8A8_UNORM),
+            sums: vec![[0.0; 3]; width as usize * height as usize],
+            width,
+            height
+        })
+    }
+
+    pub fn tile_count(&self) -> u32 {
+        self.tiles * self.tiles
+    }
+
+    // Applied after the camera's projection, scales the current tile's part of clip space up to the whole of it
+    pub fn projection(&self) -> uv::Mat4 {
+        let tiles = self.tiles as f32;
+        let center = |tile: u32| -1.0 + (2 * tile + 1) as f32 / tiles;
+        uv::Mat4::from_nonuniform_scale(uv::Vec3::new(tiles, tiles, 1.0))
+            * uv::Mat4::from_translation(uv::Vec3::new(-center(self.tile.0), -center(self.tile.1), 0.0))
+    }
+
+    // Reads the tile last drawn into the target and moves on to the next one, the device has to be idle.
+    // Returns whether every tile has been read.
+    pub fn read_tile(&mut self, device: &ash::Device, allocator: &mut Allocator, pools: &Pools, queue: vk::Queue) -> Result<bool, vk::Result> {
+        let image = &self.target.colors[0];
+        let extent = self.target.extent;
+        let size = extent.width as u64 * extent.height as u64 * 4;
+        let mut staging_buffer = StagingBuffer::new(device, allocator, size);
+        let result = pools.one_time_submit(device, queue, |command_buffer| {
+            let regions = [vk::BufferImageCopy::builder()
+                .image_subresource(vk::ImageSubresourceLayers {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    mip_level: 0,
+                    base_array_layer: 0,
+                    layer_count: 1
+                })
+                .image_extent(vk::Extent3D { width: extent.width, height: extent.height, depth: 1 })
+                .build()
+            ];
+            image.transition_layout(device, command_buffer, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL, vk::ImageLayout::TRANSFER_SRC_OPTIMAL);
+            unsafe {
+                device.cmd_copy_image_to_buffer(command_buffer, image.image, vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    staging_buffer.get_buffer(), &regions);
+            }
+            image.transition_layout(device, command_buffer, vk::ImageLayout::TRANSFER_SRC_OPTIMAL, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+        });
+
+        let mut pixels = vec![0; size as usize];
+        staging_buffer.read_buffer(0, &mut pixels);
+        staging_buffer.destroy(device, allocator);
+        result?;
+
+        self.accumulate(&pixels);
+
+        self.tile.0 += 1;
+        if self.tile.0 == self.tiles {
+            self.tile = (0, self.tile.1 + 1);
+        }
+        Ok(self.tile.1 == self.tiles)
+    }
+
+    // Box filters the tile down, averaging in linear space
+    fn accumulate(&mut self, pixels: &[u8]) {
+        let extent = self.target.extent;
+        let supersample = self.settings.supersample;
+        let (red, blue) = match self.bgra {
+            true => (2, 0),
+            false => (0, 2)
+        };
+
+        for y in 0..extent.height {
+            let row = (self.tile.1 * extent.height + y) / supersample;
+            for x in 0..extent.width {
+                let column = (self.tile.0 * extent.width + x) / supersample;
+                let pixel = &pixels[(y * extent.width + x) as usize * 4..][..4];
+                let sum = &mut self.sums[(row * self.width + column) as usize];
+                sum[0] += srgb_to_linear(pixel[red]);
+                sum[1] += srgb_to_linear(pixel[1]);
+                sum[2] += srgb_to_linear(pixel[blue]);
+            }
+        }
+    }
+
+    pub fn finish(mut self, device: &ash::Device, allocator: &mut Allocator) -> Screenshot {
+        self.target.destroy(device, allocator);
+        let samples = (self.settings.supersample * self.settings.supersample) as f32;
+        let pixels = self.sums
+            .iter()
+            .flat_map(|sum| [linear_to_srgb(sum[0] / samples), linear_to_srgb(sum[1] / samples), linear_to_srgb(sum[2] / samples), 255])
+            .collect();
+
+        Screenshot {
+            width: self.width,
+            height: self.height,
+            pixels
+        }
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device, allocator: &mut Allocator) {
+        self.target.destroy(device, allocator);
+    }
+}
+
+fn srgb_to_linear(value: u8) -> f32 {
+    let value = value as f32 / 255.0;
+    match value <= 0.04045 {
+        true => value / 12.92,
+        false => ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(value: f32) -> u8 {
+    let value = match value <= 0.0031308 {
+        true => value * 12.92,
+        false => 1.055 * value.powf(1.0 / 2.4) - 0.055
+    };
+    (value.clamp(0.0, 1.0) * 255.0).round() as u8
+}
\ No newline at end of file
@@ -0,0 +1,358 @@
+// Signed distance field fonts: every glyph is rendered once at `base_size` into an atlas of distances to its outline
+// instead of coverage, so text stays sharp when drawn much larger or smaller and the shader can draw outlines and
+// shadows from the same texels, see `SdfText`. The distance in an atlas texel is 0.5 on the outline, rising to 1.0
+// `spread` texels inside and falling to 0.0 as far outside.
+//
+// Glyphs are rasterized at `SUPERSAMPLE` times the base size, the exact Euclidean distance transform of that is then
+// averaged down, which keeps corners and thin strokes accurate without the artifacts of working at atlas resolution.
+
+use std::collections::HashMap;
+
+use ab_glyph::{Font, FontVec, GlyphId, PxScale, ScaleFont};
+use rayon::prelude::*;
+
+use crate::jobs;
+
+const SUPERSAMPLE: u32 = 4;
+// Atlases are this wide and grow in height as glyphs are added
+const ATLAS_WIDTH: u32 = 512;
+// Stands in for characters the atlas doesn't have
+const REPLACEMENT: char = '?';
+
+#[derive(Clone, Debug)]
+pub struct SdfFontSettings {
+    // Pixels per em the glyphs are rendered at, larger keeps finer detail
+    pub base_size: f32,
+    // Texels of distance around each glyph at the base size, the widest outline or shadow offset is this much
+    pub spread: f32,
+    pub characters: Vec<char>,
+}
+
+impl Default for SdfFontSettings {
+    // Printable ASCII and Latin-1, enough for English and most western European languages
+    fn default() -> Self {
+        Self {
+            base_size: 48.0,
+            spread: 8.0,
+            characters: (' '..='~').chain('\u{a0}'..='\u{ff}').collect()
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct SdfGlyph {
+    // Atlas texels, the glyph with its spread around it
+    pub atlas_min: [u32; 2],
+    pub atlas_size: [u32; 2],
+    // From the pen position on the baseline to the top left of the atlas cell, in pixels at the base size
+    pub offset: uv::Vec2,
+    pub advance: f32,
+    id: GlyphId,
+}
+
+// Where a glyph ends up in laid out text, in pixels from its top left, and its part of the atlas
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GlyphQuad {
+    pub min: uv::Vec2,
+    pub max: uv::Vec2,
+    pub uv_min: uv::Vec2,
+    pub uv_max: uv::Vec2,
+}
+
+pub struct SdfFont {
+    font: FontVec,
+    pub settings: SdfFontSettings,
+    glyphs: HashMap<char, SdfGlyph>,
+    // One distance per texel
+    pub atlas: Vec<u8>,
+    pub atlas_size: [u32; 2],
+}
+
+impl SdfFont {
+    // TrueType or OpenType data, rendering the atlas takes a moment for large character sets
+    pub fn new(data: Vec<u8>, settings: SdfFontSettings) -> Result<Self, ab_glyph::InvalidFont> {
+        let font = FontVec::try_from_vec(data)?;
+
+        let rendered: Vec<(char, GlyphId, f32, Option<Field>)> = jobs::pool().install(|| {
+            settings.characters
+                .par_iter()
+                .filter_map(|&character| {
+                    let id = font.glyph_id(character);
+                    // The font doesn't have it, it becomes the replacement
+                    if id.0 == 0 && !character.is_whitespace() {
+                        return None;
+                    }
+                    let advance = font.as_scaled(PxScale::from(settings.base_size)).h_advance(id);
+                    Some((character, id, advance, render_field(&font, id, &settings)))
+                })
+                .collect()
+        });
+
+        // Shelves of glyphs from the tallest down, starting a new shelf when one's full
+        let mut order: Vec<usize> = (0..rendered.len()).collect();
+        order.sort_by_key(|&index| std::cmp::Reverse(rendered[index].3.as_ref().map_or(0, |field| field.size[1])));
+        let (mut x, mut y, mut shelf) = (0, 0, 0);
+        let mut placed = vec![[0u32; 2]; rendered.len()];
+        for &index in &order {
+            let size = match &rendered[index].3 {
+                Some(field) => field.size,
+                None => continue
+            };
+            if x + size[0] > ATLAS_WIDTH {
+                (x, y, shelf) = (0, y + shelf, 0);
+            }
+            placed[index] = [x, y];
+            x += size[0];
+            shelf = shelf.max(size[1]);
+        }
+        let atlas_size = [ATLAS_WIDTH, (y + shelf).max(1).next_power_of_two()];
+
+        let mut atlas = vec![0u8; (atlas_size[0] * atlas_size[1]) as usize];
+        let mut glyphs = HashMap::new();
+        for ((character, id, advance, field), min) in rendered.into_iter().zip(placed) {
+            let (size, offset) = match field {
+                Some(field) => {
+                    for row in 0..field.size[1] {
+                        let start = ((min[1] + row) * atlas_size[0] + min[0]) as usize;
+                        let source = (row * field.size[0]) as usize;
+                        atlas[start..start + field.size[0] as usize].copy_from_slice(&field.texels[source..source + field.size[0] as usize]);
+                    }
+                    (field.size, field.offset)
+                }
+                None => ([0, 0], uv::Vec2::zero())
+            };
+            glyphs.insert(character, SdfGlyph { atlas_min: min, atlas_size: size, offset, advance, id });
+        }
+
+        Ok(Self {
+            font,
+            settings,
+            glyphs,
+            atlas,
+            atlas_size
+        })
+    }
+
+    pub fn glyph(&self, character: char) -> Option<&SdfGlyph> {
+        self.glyphs.get(&character).or_else(|| self.glyphs.get(&REPLACEMENT))
+    }
+
+    // Whether it's in the atlas itself rather than drawn as the replacement
+    pub fn has_glyph(&self, character: char) -> bool {
+        self.glyphs.contains_key(&character)
+    }
+
+    // Above and below the baseline, in pixels at `size`. The descent is negative.
+    pub fn ascent(&self, size: f32) -> f32 {
+        self.font.as_scaled(PxScale::from(size)).ascent()
+    }
+
+    pub fn descent(&self, size: f32) -> f32 {
+        self.font.as_scaled(PxScale::from(size)).descent()
+    }
+
+    // From one baseline to the next, in pixels at `size`
+    pub fn line_height(&self, size: f32) -> f32 {
+        let scaled = self.font.as_scaled(PxScale::from(size));
+        scaled.ascent() - scaled.descent() + scaled.line_gap()
+    }
+
+    // How far the pen moves past `character`, in pixels at `size`
+    pub fn advance(&self, character: char, size: f32) -> f32 {
+        self.glyph(character).map_or(0.0, |glyph| glyph.advance * size / self.settings.base_size)
+    }
+
+    // Adjustment of the space between the two, in pixels at `size`
+    pub fn kern(&self, first: char, second: char, size: f32) -> f32 {
+        match (self.glyph(first), self.glyph(second)) {
+            (Some(first), Some(second)) => self.font.as_scaled(PxScale::from(size)).kern(first.id, second.id),
+            _ => 0.0
+        }
+    }
+
+    // Quad of `character` with the pen at `pen` on the baseline, `None` for characters with nothing to draw
+    pub fn glyph_quad(&self, character: char, pen: uv::Vec2, size: f32) -> Option<GlyphQuad> {
+        let glyph = self.glyph(character).filter(|glyph| glyph.atlas_size[0] > 0)?;
+        let scale = size / self.settings.base_size;
+        let atlas_size = uv::Vec2::new(self.atlas_size[0] as f32, self.atlas_size[1] as f32);
+        let min = pen + glyph.offset * scale;
+        let cell = uv::Vec2::new(glyph.atlas_size[0] as f32, glyph.atlas_size[1] as f32);
+        let uv_min = uv::Vec2::new(glyph.atlas_min[0] as f32, glyph.atlas_min[1] as f32);
+        Some(GlyphQuad {
+            min,
+            max: min + cell * scale,
+            uv_min: uv_min / atlas_size,
+            uv_max: (uv_min + cell) / atlas_size
+        })
+    }
+
+    // Quads of the glyphs of `text` at `size` pixels per em, from the top left of its first line. Lines break at '\n'.
+    pub fn layout(&self, text: &str, size: f32) -> Vec<GlyphQuad> {
+        let mut quads = Vec::with_capacity(text.len());
+        let mut pen = uv::Vec2::new(0.0, self.ascent(size));
+        let mut previous = None;
+        for character in text.chars() {
+            if character == '\n' {
+                pen = uv::Vec2::new(0.0, pen.y + self.line_height(size));
+                previous = None;
+                continue;
+            }
+            if let Some(previous) = previous {
+                pen.x += self.kern(previous, character, size);
+            }
+            previous = Some(character);
+
+            quads.extend(self.glyph_quad(character, pen, size));
+            pen.x += self.advance(character, size);
+        }
+        quads
+    }
+
+    // Width of the widest line and the height of all of them, in pixels at `size`
+    pub fn measure(&self, text: &str, size: f32) -> uv::Vec2 {
+        let mut width = 0.0f32;
+        for line in text.split('\n') {
+            let mut line_width = 0.0;
+            let mut previous = None;
+            for character in line.chars() {
+                if let Some(previous) = previous {
+                    line_width += self.kern(previous, character, size);
+                }
+                line_width += self.advance(character, size);
+                previous = Some(character);
+            }
+            width = width.max(line_width);
+        }
+        let lines = text.split('\n').count() as f32;
+        uv::Vec2::new(width, self.ascent(size) - self.descent(size) + (lines - 1.0) * self.line_height(size))
+    }
+}
+
+// A glyph's distance texels at the base size
+struct Field {
+    size: [u32; 2],
+    offset: uv::Vec2,
+    texels: Vec<u8>,
+}
+
+// `None` for glyphs without an outline, like spaces
+fn render_field(font: &FontVec, id: GlyphId, settings: &SdfFontSettings) -> Option<Field> {
+    let glyph = id.with_scale_and_position(PxScale::from(settings.base_size * SUPERSAMPLE as f32), ab_glyph::point(0.0, 0.0));
+    let outline = font.outline_glyph(glyph)?;
+    let bounds = outline.px_bounds();
+
+    // Cells are whole texels at the base size with the spread on every side
+    let padding = (settings.spread.ceil() as u32) * SUPERSAMPLE;
+    let size = [
+        ((bounds.width() as u32 + 2 * padding).div_ceil(SUPERSAMPLE)),
+        ((bounds.height() as u32 + 2 * padding).div_ceil(SUPERSAMPLE))
+    ];
+    let (width, height) = (size[0] * SUPERSAMPLE, size[1] * SUPERSAMPLE);
+
+    let mut coverage = vec![0.0f32; (width * height) as usize];
+    outline.draw(|x, y, value| {
+        let (x, y) = (x + padding, y + padding);
+        if x < width && y < height {
+            coverage[(y * width + x) as usize] = value;
+        }
+    });
+
+    // Squared distances to the nearest texel inside from outside, and to the nearest one outside from inside
+    let inside: Vec<bool> = coverage.iter().map(|&value| value >= 0.5).collect();
+    let mut to_inside: Vec<f32> = inside.iter().map(|&inside| if inside { 0.0 } else { f32::INFINITY }).collect();
+    let mut to_outside: Vec<f32> = inside.iter().map(|&inside| if inside { f32::INFINITY } else { 0.0 }).collect();
+    distance_transform(&mut to_inside, width as usize, height as usize);
+    distance_transform(&mut to_outside, width as usize, height as usize);
+
+    let spread = settings.spread * SUPERSAMPLE as f32;
+    let mut texels = vec![0u8; (size[0] * size[1]) as usize];
+    for (index, texel) in texels.iter_mut().enumerate() {
+        let (x, y) = (index as u32 % size[0] * SUPERSAMPLE, index as u32 / size[0] * SUPERSAMPLE);
+        let mut distance = 0.0;
+        for sample_y in y..y + SUPERSAMPLE {
+            for sample_x in x..x + SUPERSAMPLE {
+                let sample = (sample_y * width + sample_x) as usize;
+                // Half a texel between the last one in and the first one out
+                distance += match inside[sample] {
+                    true => -(to_outside[sample].sqrt() - 0.5),
+                    false => to_inside[sample].sqrt() - 0.5
+                };
+            }
+        }
+        let distance = distance / (SUPERSAMPLE * SUPERSAMPLE) as f32;
+        *texel = ((0.5 - distance / (2.0 * spread)).clamp(0.0, 1.0) * 255.0).round() as u8;
+    }
+
+    Some(Field {
+        size,
+        offset: uv::Vec2::new(bounds.min.x - padding as f32, bounds.min.y - padding as f32) / SUPERSAMPLE as f32,
+        texels
+    })
+}
+
+// Exact squared Euclidean distance transform (Felzenszwalb and Huttenlocher), in place: texels start at 0 on the
+// features and infinity elsewhere and end up at their squared distance to the nearest feature
+fn distance_transform(grid: &mut [f32], width: usize, height: usize) {
+    let mut line = vec![0.0; width.max(height)];
+    let mut output = vec![0.0; width.max(height)];
+    for x in 0..width {
+        for y in 0..height {
+            line[y] = grid[y * width + x];
+        }
+        distance_transform_1d(&line[..height], &mut output[..height]);
+        for y in 0..height {
+            grid[y * width + x] = output[y];
+        }
+    }
+    for y in 0..height {
+        let row = &mut grid[y * width..(y + 1) * width];
+        distance_transform_1d(row, &mut output[..width]);
+        row.copy_from_slice(&output[..width]);
+    }
+}
+
+// Lower envelope of the parabolas rooted at every sample
+fn distance_transform_1d(input: &[f32], output: &mut [f32]) {
+    let count = input.len();
+    let mut vertices = vec![0usize; count];
+    let mut bounds = vec![0.0f32; count + 1];
+    let mut k = 0;
+    bounds[0] = f32::NEG_INFINITY;
+    bounds[1] = f32::INFINITY;
+
+    let first = match input.iter().position(|value| value.is_finite()) {
+        Some(first) => first,
+        None => {
+            output.fill(f32::INFINITY);
+            return;
+        }
+    };
+    vertices[0] = first;
+    for q in first + 1..count {
+        if !input[q].is_finite() {
+            continue;
+        }
+        loop {
+            let v = vertices[k];
+            let s = ((input[q] + (q * q) as f32) - (input[v] + (v * v) as f32)) / (2 * q - 2 * v) as f32;
+            if s <= bounds[k] && k > 0 {
+                k -= 1;
+                continue;
+            }
+            k += 1;
+            vertices[k] = q;
+            bounds[k] = s;
+            bounds[k + 1] = f32::INFINITY;
+            break;
+        }
+    }
+
+    k = 0;
+    for (q, value) in output.iter_mut().enumerate() {
+        while bounds[k + 1] < q as f32 {
+            k += 1;
+        }
+        let v = vertices[k];
+        *value = (q as f32 - v as f32).powi(2) + input[v];
+    }
+}
\ No newline at end of file
@@ -0,0 +1,427 @@
+use std::collections::HashMap;
+
+use ash::vk;
+use gpu_allocator::vulkan::Allocator;
+
+use super::command_pools::Pools;
+use super::descriptors::Descriptors;
+use super::descriptor_allocator::DescriptorAllocator;
+use super::pipeline::{BlendMode, Pipeline, PipelineConfig};
+use super::rich_text::{self, FontFamily, GlyphSource, LaidOut, RichLayout, TextBox};
+use super::sdf_font::{GlyphQuad, SdfFont};
+use super::swapchain::VulkanSwapchain;
+use super::texture::Texture;
+use super::upload_ring::{RingSlice, UploadRing};
+
+use crate::utils::any_as_u8_slice;
+
+pub const SDF_TEXT_VERT: &[u32] = vk_shader_macros::include_glsl!("./shaders/sdf_text.vert", kind: vert);
+pub const SDF_TEXT_FRAG: &[u32] = vk_shader_macros::include_glsl!("./shaders/sdf_text.frag", kind: frag);
+
+// Glyphs per frame, text past this is dropped for the frame
+const MAX_GLYPHS: usize = 1 << 14;
+// Matches the layers of sdf_text.frag
+const LAYER_SHADOW: u32 = 0;
+const LAYER_OUTLINE: u32 = 1;
+const LAYER_FILL: u32 = 2;
+const LAYER_ICON: u32 = 3;
+
+// Mirrors the push constant block of sdf_text.vert and sdf_text.frag
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct SdfTextPushConstants {
+    color: [f32; 4],
+    outline_color: [f32; 4],
+    shadow_color: [f32; 4],
+    // In atlas UVs
+    shadow_offset: [f32; 2],
+    screen_size: [f32; 2],
+    // In distance field units, 0.5 is the whole spread
+    outline_width: f32,
+    shadow_softness: f32,
+    quad_base: u32,
+    layer: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TextShadow {
+    // In pixels, down and to the right for positive values
+    pub offset: uv::Vec2,
+    // Pixels the shadow's edge is blurred over
+    pub softness: f32,
+    pub color: uv::Vec4,
+}
+
+// Colors are straight alpha and gamma encoded, like the swapchain image they're drawn to. Outline widths, shadow
+// offsets and softness reach at most the font's spread, scaled to `size`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TextStyle {
+    // Pixels per em
+    pub size: f32,
+    pub color: uv::Vec4,
+    // Width in pixels and color
+    pub outline: Option<(f32, uv::Vec4)>,
+    pub shadow: Option<TextShadow>,
+}
+
+impl Default for TextStyle {
+    fn default() -> Self {
+        Self {
+            size: 32.0,
+            color: uv::Vec4::one(),
+            outline: None,
+            shadow: None
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SdfFontId(pub(super) usize);
+
+struct LoadedFont {
+    font: SdfFont,
+    texture: Texture,
+    set: vk::DescriptorSet,
+}
+
+struct Icon {
+    texture: Texture,
+    set: vk::DescriptorSet,
+    aspect: f32,
+}
+
+// Glyphs drawn with the same font, style and color, or an icon
+struct TextRun {
+    set: vk::DescriptorSet,
+    push: SdfTextPushConstants,
+    first_quad: u32,
+    quad_count: u32,
+    outline: bool,
+    shadow: bool,
+    icon: bool,
+}
+
+// Text drawn from signed distance field fonts over the final image, under the egui UI. Text is queued with `draw` every
+// frame and shown with the next `fill_commandbuffers` once `end_frame` finished the frame, like the UI. Quads are pulled
+// from the upload ring as a storage buffer, the same way the UI's vertices are.
+pub struct SdfText {
+    fonts: Vec<LoadedFont>,
+    icons: HashMap<String, Icon>,
+    pending_runs: Vec<TextRun>,
+    pending_quads: Vec<[f32; 8]>,
+    // The last finished frame
+    runs: Vec<TextRun>,
+    quads: Vec<[f32; 8]>,
+    texture_set_layout: vk::DescriptorSetLayout,
+    geometry_set_layout: vk::DescriptorSetLayout,
+    geometry_set: vk::DescriptorSet,
+    // `None` when the upload ring had no room for the frame's quads
+    quad_slice: Option<RingSlice>,
+    pipeline: Pipeline,
+}
+
+impl SdfText {
+    pub fn new(device: &ash::Device, swapchain: &VulkanSwapchain, present_renderpass: &vk::RenderPass, descriptor_pool: vk::DescriptorPool,
+        upload_ring: &UploadRing
+    ) -> Result<Self, vk::Result> {
+        let texture_set_layout = Descriptors::create_layout(device, &[
+            (vk::DescriptorType::COMBINED_IMAGE_SAMPLER, vk::ShaderStageFlags::FRAGMENT)
+        ])?;
+        let geometry_set_layout = Descriptors::create_layout(device, &[
+            (vk::DescriptorType::STORAGE_BUFFER, vk::ShaderStageFlags::VERTEX)
+        ])?;
+
+        let geometry_set = Descriptors::allocate(device, descriptor_pool, geometry_set_layout, 1)?[0];
+        Descriptors::write_buffer(device, geometry_set, 0, vk::DescriptorType::STORAGE_BUFFER, upload_ring.descriptor_info(vk::WHOLE_SIZE));
+
+        let set_layouts = [texture_set_layout, geometry_set_layout];
+        let config = PipelineConfig {
+            vertex_shader: SDF_TEXT_VERT,
+            blend_mode: BlendMode::Premultiplied,
+            ..PipelineConfig::fullscreen(SDF_TEXT_FRAG, &set_layouts, std::mem::size_of::<SdfTextPushConstants>() as u32)
+        };
+        let pipeline = Pipeline::new(device, swapchain, present_renderpass, &config)?;
+
+        Ok(Self {
+            fonts: vec![],
+            icons: HashMap::new(),
+            pending_runs: vec![],
+            pending_quads: vec![],
+            runs: vec![],
+            quads: vec![],
+            texture_set_layout,
+            geometry_set_layout,
+            geometry_set,
+            quad_slice: None,
+            pipeline
+        })
+    }
+
+    // Uploads the font's atlas, it stays until the renderer is destroyed
+    pub fn add_font(&mut self, device: &ash::Device, allocator: &mut Allocator, pools: &Pools, queue: vk::Queue,
+        descriptors: &mut DescriptorAllocator, font: SdfFont
+    ) -> Result<SdfFontId, vk::Result> {
+        let bytes: Vec<u8> = font.atlas.iter().flat_map(|&distance| [distance; 4]).collect();
+        let extent = vk::Extent2D { width: font.atlas_size[0], height: font.atlas_size[1] };
+        let texture = Texture::from_rgba8(device, allocator, pools, queue, extent, &bytes, "SDF Font Atlas")?;
+
+        let set = descriptors.allocate(device, self.texture_set_layout)?;
+        Descriptors::write_image(device, set, 0, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, texture.descriptor_info());
+
+        self.fonts.push(LoadedFont {
+            font,
+            texture,
+            set
+        });
+        Ok(SdfFontId(self.fonts.len() - 1))
+    }
+
+    // For measuring and laying out text before it's drawn
+    pub fn font(&self, id: SdfFontId) -> &SdfFont {
+        &self.fonts[id.0].font
+    }
+
+    // Tightly packed RGBA8 with straight alpha, shown inline in rich text with [icon=name] as tall as the text. Adding
+    // an icon under a name that's taken replaces it, waiting for the device.
+    #[allow(clippy::too_many_arguments)]
+    pub fn add_icon(&mut self, device: &ash::Device, allocator: &mut Allocator, pools: &Pools, queue: vk::Queue,
+        descriptors: &mut DescriptorAllocator, name: &str, extent: vk::Extent2D, pixels: &[u8]
+    ) -> Result<(), vk::Result> {
+        let texture = Texture::from_rgba8(device, allocator, pools, queue, extent, pixels, "SDF Text Icon")?;
+        let set = match self.icons.remove(name) {
+            Some(mut old) => {
+                unsafe { device.device_wait_idle()? };
+                old.texture.destroy(device, allocator);
+                old.set
+            }
+            None => descriptors.allocate(device, self.texture_set_layout)?
+        };
+        Descriptors::write_image(device, set, 0, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, texture.descriptor_info());
+
+        self.icons.insert(name.to_string(), Icon {
+            texture,
+            set,
+            aspect: extent.width as f32 / extent.height.max(1) as f32
+        });
+        Ok(())
+    }
+
+    // Queues `text` for this frame with its top left at `position`, in pixels from the top left of the window
+    pub fn draw(&mut self, font: SdfFontId, text: &str, position: uv::Vec2, style: &TextStyle) {
+        let quads = self.fonts[font.0].font.layout(text, style.size);
+        self.push_glyphs(font, &quads, position, style, style.color);
+    }
+
+    // Lays out `markup` without drawing it, e.g. to size the box around it
+    pub fn layout_rich(&self, family: &FontFamily, markup: &str, style: &TextStyle, text_box: &TextBox) -> RichLayout {
+        rich_text::layout(self, family, &rich_text::parse(markup), style.size, style.color, text_box)
+    }
+
+    // Queues `markup` for this frame with the top left of its box at `position`, see `rich_text`. The style's color
+    // is for text the markup doesn't color, its outline and shadow are for all of it. Returns the size of the box.
+    pub fn draw_rich(&mut self, family: &FontFamily, markup: &str, position: uv::Vec2, style: &TextStyle, text_box: &TextBox) -> uv::Vec2 {
+        let layout = self.layout_rich(family, markup, style, text_box);
+        self.draw_layout(&layout, position, style);
+        layout.size
+    }
+
+    pub fn draw_layout(&mut self, layout: &RichLayout, position: uv::Vec2, style: &TextStyle) {
+        let mut items = layout.items.iter().peekable();
+        while let Some(item) = items.next() {
+            match item {
+                LaidOut::Glyph { font, color, quad } => {
+                    // Glyphs of the same font and color go in one run
+                    let mut quads = vec![*quad];
+                    while let Some(LaidOut::Glyph { quad, .. }) = items.next_if(|next| {
+                        matches!(next, LaidOut::Glyph { font: next_font, color: next_color, .. } if next_font == font && next_color == color)
+                    }) {
+                        quads.push(*quad);
+                    }
+                    self.push_glyphs(*font, &quads, position, style, *color);
+                }
+                LaidOut::Icon { name, min, max } => self.push_icon(name, *min + position, *max + position, style.color.w)
+            }
+        }
+    }
+
+    fn push_glyphs(&mut self, font: SdfFontId, quads: &[GlyphQuad], position: uv::Vec2, style: &TextStyle, color: uv::Vec4) {
+        if self.pending_quads.len() + quads.len() > MAX_GLYPHS {
+            tracing::warn!("Text exceeds {} glyphs this frame, the rest is skipped", MAX_GLYPHS);
+            return;
+        }
+        let loaded = &self.fonts[font.0];
+
+        // A distance field unit is twice the spread, in texels at the base size
+        let settings = &loaded.font.settings;
+        let pixels_per_unit = 2.0 * settings.spread * style.size / settings.base_size;
+        let outline_width = style.outline.map_or(0.0, |(width, _)| (width / pixels_per_unit).clamp(0.0, 0.5));
+        let (shadow_offset, shadow_softness) = match style.shadow {
+            Some(shadow) => {
+                // Texels at the base size, kept within the spread so the shadow stays inside the glyph's quad
+                let mut offset = shadow.offset * settings.base_size / style.size;
+                if offset.mag() > settings.spread {
+                    offset = offset.normalized() * settings.spread;
+                }
+                let atlas_size = uv::Vec2::new(loaded.font.atlas_size[0] as f32, loaded.font.atlas_size[1] as f32);
+                (offset / atlas_size, (shadow.softness / pixels_per_unit).clamp(0.0, 0.5))
+            }
+            None => (uv::Vec2::zero(), 0.0)
+        };
+
+        let first_quad = self.pending_quads.len() as u32;
+        self.pending_quads.extend(quads.iter().map(|quad| {
+            let (min, max) = (quad.min + position, quad.max + position);
+            [min.x, min.y, max.x, max.y, quad.uv_min.x, quad.uv_min.y, quad.uv_max.x, quad.uv_max.y]
+        }));
+
+        let (outline_color, shadow_color) = (style.outline.map_or(uv::Vec4::zero(), |(_, color)| color),
+            style.shadow.map_or(uv::Vec4::zero(), |shadow| shadow.color));
+        self.pending_runs.push(TextRun {
+            set: loaded.set,
+            push: SdfTextPushConstants {
+                color: *color.as_array(),
+                outline_color: *outline_color.as_array(),
+                shadow_color: *shadow_color.as_array(),
+                shadow_offset: *shadow_offset.as_array(),
+                screen_size: [0.0; 2],
+                outline_width,
+                shadow_softness,
+                quad_base: 0,
+                layer: LAYER_FILL
+            },
+            first_quad,
+            quad_count: quads.len() as u32,
+            outline: style.outline.is_some(),
+            shadow: style.shadow.is_some(),
+            icon: false
+        });
+    }
+
+    fn push_icon(&mut self, name: &str, min: uv::Vec2, max: uv::Vec2, alpha: f32) {
+        let icon = match self.icons.get(name) {
+            Some(icon) => icon,
+            None => return
+        };
+        if self.pending_quads.len() >= MAX_GLYPHS {
+            tracing::warn!("Text exceeds {} glyphs this frame, the rest is skipped", MAX_GLYPHS);
+            return;
+        }
+
+        self.pending_quads.push([min.x, min.y, max.x, max.y, 0.0, 0.0, 1.0, 1.0]);
+        self.pending_runs.push(TextRun {
+            set: icon.set,
+            push: SdfTextPushConstants {
+                color: [1.0, 1.0, 1.0, alpha],
+                outline_color: [0.0; 4],
+                shadow_color: [0.0; 4],
+                shadow_offset: [0.0; 2],
+                screen_size: [0.0; 2],
+                outline_width: 0.0,
+                shadow_softness: 0.0,
+                quad_base: 0,
+                layer: LAYER_ICON
+            },
+            first_quad: self.pending_quads.len() as u32 - 1,
+            quad_count: 1,
+            outline: false,
+            shadow: false,
+            icon: true
+        });
+    }
+
+    // Finishes the frame's text, drawn with the next `fill_commandbuffers`
+    pub fn end_frame(&mut self) {
+        self.runs = std::mem::take(&mut self.pending_runs);
+        self.quads = std::mem::take(&mut self.pending_quads);
+    }
+
+    // Makes room for the last finished frame while the frame's upload layout is planned
+    pub fn reserve(&mut self, upload_ring: &mut UploadRing) {
+        self.quad_slice = upload_ring.reserve(std::mem::size_of_val(self.quads.as_slice()) as u64);
+        if self.quad_slice.is_none() {
+            tracing::warn!("No room left in the upload ring for SDF text, it's skipped this frame");
+        }
+    }
+
+    // Copies the last finished frame into the quad slice of swapchain image `index`
+    pub fn update(&mut self, index: usize, upload_ring: &mut UploadRing) {
+        if let Some(slice) = self.quad_slice {
+            upload_ring.write(index, slice, 0, &self.quads);
+        }
+    }
+
+    // Recorded inside the present render pass, before the UI
+    pub fn record(&self, device: &ash::Device, command_buffer: vk::CommandBuffer, index: usize, extent: vk::Extent2D, upload_ring: &UploadRing) {
+        let slice = match self.quad_slice {
+            Some(slice) if !self.runs.is_empty() => slice,
+            _ => return
+        };
+        // In 32 bit words, like the shader reads them
+        let quad_base = (upload_ring.offset(index, slice) / 4) as u32;
+
+        unsafe {
+            device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, self.pipeline.pipeline);
+            device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::GRAPHICS, self.pipeline.layout, 1,
+                &[self.geometry_set], &[]);
+        }
+
+        // Each layer for all text before the next, so shadows and outlines never cover the glyphs next to them
+        for layer in [LAYER_SHADOW, LAYER_OUTLINE, LAYER_FILL] {
+            let mut bound = None;
+            for run in &self.runs {
+                let drawn = match layer {
+                    LAYER_SHADOW => run.shadow,
+                    LAYER_OUTLINE => run.outline,
+                    _ => true
+                };
+                if !drawn || run.quad_count == 0 {
+                    continue;
+                }
+
+                let push = SdfTextPushConstants {
+                    screen_size: [extent.width as f32, extent.height as f32],
+                    quad_base,
+                    layer: match run.icon {
+                        true => LAYER_ICON,
+                        false => layer
+                    },
+                    ..run.push
+                };
+                unsafe {
+                    if bound != Some(run.set) {
+                        device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::GRAPHICS, self.pipeline.layout, 0,
+                            &[run.set], &[]);
+                        bound = Some(run.set);
+                    }
+                    device.cmd_push_constants(command_buffer, self.pipeline.layout, vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT, 0,
+                        any_as_u8_slice(&push));
+                    device.cmd_draw(command_buffer, run.quad_count * 6, 1, run.first_quad * 6, 0);
+                }
+            }
+        }
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device, allocator: &mut Allocator) {
+        for font in &mut self.fonts {
+            font.texture.destroy(device, allocator);
+        }
+        for icon in self.icons.values_mut() {
+            icon.texture.destroy(device, allocator);
+        }
+        self.pipeline.cleanup(device);
+        unsafe {
+            device.destroy_descriptor_set_layout(self.texture_set_layout, None);
+            device.destroy_descriptor_set_layout(self.geometry_set_layout, None);
+        }
+    }
+}
+
+impl GlyphSource for SdfText {
+    fn font(&self, id: SdfFontId) -> &SdfFont {
+        SdfText::font(self, id)
+    }
+
+    fn icon_aspect(&self, name: &str) -> Option<f32> {
+        self.icons.get(name).map(|icon| icon.aspect)
+    }
+}
\ No newline at end of file
@@ -0,0 +1,674 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use ash::vk;
+
+use super::descriptors::{Descriptors, LayoutBindings};
+
+// Just enough of the SPIR-V spec to find what a module expects from its pipeline: descriptor bindings,
+// the push constant block and vertex inputs. Everything else in the module is skipped. Set layouts and push constant
+// ranges stay written by hand, as sets are shared between pipelines, and are checked against this.
+const MAGIC: u32 = 0x0723_0203;
+const HEADER_WORDS: usize = 5;
+
+const OP_NAME: u32 = 5;
+const OP_ENTRY_POINT: u32 = 15;
+const OP_TYPE_BOOL: u32 = 20;
+const OP_TYPE_INT: u32 = 21;
+const OP_TYPE_FLOAT: u32 = 22;
+const OP_TYPE_VECTOR: u32 = 23;
+const OP_TYPE_MATRIX: u32 = 24;
+const OP_TYPE_IMAGE: u32 = 25;
+const OP_TYPE_SAMPLER: u32 = 26;
+const OP_TYPE_SAMPLED_IMAGE: u32 = 27;
+const OP_TYPE_ARRAY: u32 = 28;
+const OP_TYPE_RUNTIME_ARRAY: u32 = 29;
+const OP_TYPE_STRUCT: u32 = 30;
+const OP_TYPE_POINTER: u32 = 32;
+const OP_CONSTANT: u32 = 43;
+const OP_SPEC_CONSTANT: u32 = 50;
+const OP_FUNCTION: u32 = 54;
+const OP_VARIABLE: u32 = 59;
+const OP_DECORATE: u32 = 71;
+const OP_MEMBER_DECORATE: u32 = 72;
+
+const DECORATION_BUFFER_BLOCK: u32 = 3;
+const DECORATION_ARRAY_STRIDE: u32 = 6;
+const DECORATION_MATRIX_STRIDE: u32 = 7;
+const DECORATION_BUILT_IN: u32 = 11;
+const DECORATION_LOCATION: u32 = 30;
+const DECORATION_BINDING: u32 = 33;
+const DECORATION_DESCRIPTOR_SET: u32 = 34;
+const DECORATION_OFFSET: u32 = 35;
+
+const STORAGE_UNIFORM_CONSTANT: u32 = 0;
+const STORAGE_INPUT: u32 = 1;
+const STORAGE_UNIFORM: u32 = 2;
+const STORAGE_PUSH_CONSTANT: u32 = 9;
+const STORAGE_STORAGE_BUFFER: u32 = 12;
+
+const DIM_BUFFER: u32 = 5;
+const DIM_SUBPASS_DATA: u32 = 6;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScalarKind {
+    Bool,
+    Int,
+    Uint,
+    Float,
+}
+
+#[derive(Clone, Debug)]
+pub struct DescriptorBinding {
+    pub set: u32,
+    pub binding: u32,
+    pub descriptor_type: vk::DescriptorType,
+    // 0 for runtime sized arrays
+    pub count: u32,
+    pub stages: vk::ShaderStageFlags,
+    pub name: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct VertexInput {
+    pub location: u32,
+    pub kind: ScalarKind,
+    pub components: u32,
+    pub name: String,
+}
+
+#[derive(Clone, Debug)]
+enum Type {
+    Scalar(ScalarKind, u32),
+    Vector(u32, u32),
+    Matrix(u32, u32),
+    Image { dim: u32, sampled: u32 },
+    Sampler,
+    SampledImage,
+    Array(u32, u32),
+    RuntimeArray(u32),
+    Struct(Vec<u32>),
+    Pointer(u32),
+}
+
+// What one shader module declares
+#[derive(Clone, Debug)]
+pub struct ShaderReflection {
+    pub stage: vk::ShaderStageFlags,
+    pub bindings: Vec<DescriptorBinding>,
+    // Bytes up to the end of the last push constant member, 0 without a block
+    pub push_constant_size: u32,
+    pub vertex_inputs: Vec<VertexInput>,
+}
+
+impl ShaderReflection {
+    pub fn new(code: &[u32]) -> Result<Self, String> {
+        if code.len() < HEADER_WORDS || code[0] != MAGIC {
+            return Err("not a SPIR-V module".to_string());
+        }
+
+        let mut stage = vk::ShaderStageFlags::empty();
+        let mut names: HashMap<u32, String> = HashMap::new();
+        let mut types: HashMap<u32, Type> = HashMap::new();
+        let mut constants: HashMap<u32, u32> = HashMap::new();
+        let mut decorations: HashMap<(u32, u32), u32> = HashMap::new();
+        let mut flags: HashMap<u32, Vec<u32>> = HashMap::new();
+        let mut member_decorations: HashMap<(u32, u32, u32), u32> = HashMap::new();
+        // (id, pointer type, storage class)
+        let mut variables: Vec<(u32, u32, u32)> = vec![];
+        // Everything the function bodies refer to, literals included. Only descriptors among them have to match the
+        // layout, shared includes declare blocks in stages that never read them.
+        let mut referenced: HashSet<u32> = HashSet::new();
+        let mut in_functions = false;
+
+        let mut offset = HEADER_WORDS;
+        while offset < code.len() {
+            let word_count = (code[offset] >> 16) as usize;
+            let opcode = code[offset] & 0xffff;
+            if word_count == 0 || offset + word_count > code.len() {
+                return Err(format!("truncated instruction at word {}", offset));
+            }
+            let operands = &code[offset + 1..offset + word_count];
+            offset += word_count;
+
+            in_functions |= opcode == OP_FUNCTION;
+            if in_functions {
+                referenced.extend(operands);
+            }
+
+            match (opcode, operands) {
+                (OP_NAME, [id, string @ ..]) => {
+                    names.insert(*id, read_string(string));
+                }
+                (OP_ENTRY_POINT, [model, ..]) => {
+                    stage = match model {
+                        0 => vk::ShaderStageFlags::VERTEX,
+                        1 => vk::ShaderStageFlags::TESSELLATION_CONTROL,
+                        2 => vk::ShaderStageFlags::TESSELLATION_EVALUATION,
+                        3 => vk::ShaderStageFlags::GEOMETRY,
+                        4 => vk::ShaderStageFlags::FRAGMENT,
+                        5 => vk::ShaderStageFlags::COMPUTE,
+                        _ => return Err(format!("unsupported execution model {}", model))
+                    };
+                }
+                (OP_TYPE_BOOL, [id]) => {
+                    types.insert(*id, Type::Scalar(ScalarKind::Bool, 4));
+                }
+                (OP_TYPE_INT, [id, width, signed]) => {
+                    let kind = match signed {
+                        0 => ScalarKind::Uint,
+                        _ => ScalarKind::Int
+                    };
+                    types.insert(*id, Type::Scalar(kind, width / 8));
+                }
+                (OP_TYPE_FLOAT, [id, width, ..]) => {
+                    types.insert(*id, Type::Scalar(ScalarKind::Float, width / 8));
+                }
+                (OP_TYPE_VECTOR, [id, component, count]) => {
+                    types.insert(*id, Type::Vector(*component, *count));
+                }
+                (OP_TYPE_MATRIX, [id, column, count]) => {
+                    types.insert(*id, Type::Matrix(*column, *count));
+                }
+                (OP_TYPE_IMAGE, [id, _, dim, _, _, _, sampled, ..]) => {
+                    types.insert(*id, Type::Image { dim: *dim, sampled: *sampled });
+                }
+                (OP_TYPE_SAMPLER, [id]) => {
+                    types.insert(*id, Type::Sampler);
+                }
+                (OP_TYPE_SAMPLED_IMAGE, [id, _]) => {
+                    types.insert(*id, Type::SampledImage);
+                }
+                (OP_TYPE_ARRAY, [id, element, length]) => {
+                    types.insert(*id, Type::Array(*element, *length));
+                }
+                (OP_TYPE_RUNTIME_ARRAY, [id, element]) => {
+                    types.insert(*id, Type::RuntimeArray(*element));
+                }
+                (OP_TYPE_STRUCT, [id, members @ ..]) => {
+                    types.insert(*id, Type::Struct(members.to_vec()));
+                }
+                (OP_TYPE_POINTER, [id, _, pointee]) => {
+                    types.insert(*id, Type::Pointer(*pointee));
+                }
+                // Array lengths, 64 bit constants only keep their low word which is plenty for a length
+                (OP_CONSTANT | OP_SPEC_CONSTANT, [_, id, value, ..]) => {
+                    constants.insert(*id, *value);
+                }
+                (OP_VARIABLE, [pointer_type, id, storage_class, ..]) => {
+                    variables.push((*id, *pointer_type, *storage_class));
+                }
+                (OP_DECORATE, [target, decoration, value, ..]) => {
+                    decorations.insert((*target, *decoration), *value);
+                }
+                (OP_DECORATE, [target, decoration]) => {
+                    flags.entry(*target).or_default().push(*decoration);
+                }
+                (OP_MEMBER_DECORATE, [target, member, decoration, value, ..]) => {
+                    member_decorations.insert((*target, *member, *decoration), *value);
+                }
+                _ => {}
+            }
+        }
+
+        if stage.is_empty() {
+            return Err("no entry point".to_string());
+        }
+
+        let module = Module { types, constants, decorations, member_decorations };
+        let has_flag = |id: u32, decoration: u32| flags.get(&id).is_some_and(|flags| flags.contains(&decoration));
+        let name = |id: u32| names.get(&id).cloned().unwrap_or_else(|| format!("%{}", id));
+
+        let mut bindings = vec![];
+        let mut push_constant_size = 0;
+        let mut vertex_inputs = vec![];
+        for &(id, pointer_type, storage_class) in &variables {
+            let pointee = match module.types.get(&pointer_type) {
+                Some(Type::Pointer(pointee)) => *pointee,
+                _ => continue
+            };
+
+            match storage_class {
+                STORAGE_UNIFORM_CONSTANT | STORAGE_UNIFORM | STORAGE_STORAGE_BUFFER if referenced.contains(&id) => {
+                    let (set, binding) = match (module.decorations.get(&(id, DECORATION_DESCRIPTOR_SET)), module.decorations.get(&(id, DECORATION_BINDING))) {
+                        (Some(set), Some(binding)) => (*set, *binding),
+                        _ => continue
+                    };
+                    let (element, count) = module.array_element(pointee);
+                    let descriptor_type = match (storage_class, module.types.get(&element)) {
+                        (STORAGE_STORAGE_BUFFER, _) => vk::DescriptorType::STORAGE_BUFFER,
+                        (STORAGE_UNIFORM, _) if has_flag(element, DECORATION_BUFFER_BLOCK) => vk::DescriptorType::STORAGE_BUFFER,
+                        (STORAGE_UNIFORM, _) => vk::DescriptorType::UNIFORM_BUFFER,
+                        (_, Some(Type::SampledImage)) => vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                        (_, Some(Type::Sampler)) => vk::DescriptorType::SAMPLER,
+                        (_, Some(Type::Image { dim: DIM_SUBPASS_DATA, .. })) => vk::DescriptorType::INPUT_ATTACHMENT,
+                        (_, Some(Type::Image { dim: DIM_BUFFER, sampled: 2 })) => vk::DescriptorType::STORAGE_TEXEL_BUFFER,
+                        (_, Some(Type::Image { dim: DIM_BUFFER, .. })) => vk::DescriptorType::UNIFORM_TEXEL_BUFFER,
+                        (_, Some(Type::Image { sampled: 2, .. })) => vk::DescriptorType::STORAGE_IMAGE,
+                        (_, Some(Type::Image { .. })) => vk::DescriptorType::SAMPLED_IMAGE,
+                        _ => return Err(format!("{} has a type that can't be bound to a descriptor", name(id)))
+                    };
+
+                    bindings.push(DescriptorBinding {
+                        set,
+                        binding,
+                        descriptor_type,
+                        count,
+                        stages: stage,
+                        name: name(id)
+                    });
+                }
+                STORAGE_PUSH_CONSTANT => {
+                    push_constant_size = push_constant_size.max(module.size_of(pointee));
+                }
+                STORAGE_INPUT if stage == vk::ShaderStageFlags::VERTEX => {
+                    // Built-ins like gl_VertexIndex aren't fed by vertex buffers
+                    if has_flag(id, DECORATION_BUILT_IN) || module.decorations.contains_key(&(id, DECORATION_BUILT_IN)) {
+                        continue;
+                    }
+                    let location = match module.decorations.get(&(id, DECORATION_LOCATION)) {
+                        Some(location) => *location,
+                        None => continue
+                    };
+                    let (kind, components) = module.components(pointee)
+                        .ok_or_else(|| format!("vertex input {} isn't a scalar or vector", name(id)))?;
+                    vertex_inputs.push(VertexInput { location, kind, components, name: name(id) });
+                }
+                _ => {}
+            }
+        }
+
+        bindings.sort_by_key(|binding| (binding.set, binding.binding));
+        vertex_inputs.sort_by_key(|input| input.location);
+
+        Ok(Self {
+            stage,
+            bindings,
+            push_constant_size,
+            vertex_inputs
+        })
+    }
+}
+
+struct Module {
+    types: HashMap<u32, Type>,
+    constants: HashMap<u32, u32>,
+    decorations: HashMap<(u32, u32), u32>,
+    member_decorations: HashMap<(u32, u32, u32), u32>,
+}
+
+impl Module {
+    // Arrays of descriptors take one binding with a count
+    fn array_element(&self, id: u32) -> (u32, u32) {
+        match self.types.get(&id) {
+            Some(Type::Array(element, length)) => (*element, self.constants.get(length).copied().unwrap_or(1)),
+            Some(Type::RuntimeArray(element)) => (*element, 0),
+            _ => (id, 1)
+        }
+    }
+
+    fn components(&self, id: u32) -> Option<(ScalarKind, u32)> {
+        match self.types.get(&id)? {
+            Type::Scalar(kind, _) => Some((*kind, 1)),
+            Type::Vector(component, count) => match self.types.get(component)? {
+                Type::Scalar(kind, _) => Some((*kind, *count)),
+                _ => None
+            },
+            _ => None
+        }
+    }
+
+    // Size as laid out by the block's offset and stride decorations, without trailing padding
+    fn size_of(&self, id: u32) -> u32 {
+        match self.types.get(&id) {
+            Some(Type::Scalar(_, size)) => *size,
+            Some(Type::Vector(component, count)) => self.size_of(*component) * count,
+            Some(Type::Matrix(column, count)) => self.size_of(*column) * count,
+            Some(Type::Array(element, length)) => {
+                let length = self.constants.get(length).copied().unwrap_or(1);
+                let stride = self.decorations.get(&(id, DECORATION_ARRAY_STRIDE)).copied().unwrap_or_else(|| self.size_of(*element));
+                stride * length
+            }
+            Some(Type::Struct(members)) => members
+                .iter()
+                .enumerate()
+                .map(|(index, &member)| {
+                    let index = index as u32;
+                    let offset = self.member_decorations.get(&(id, index, DECORATION_OFFSET)).copied().unwrap_or(0);
+                    let size = match (self.types.get(&member), self.member_decorations.get(&(id, index, DECORATION_MATRIX_STRIDE))) {
+                        (Some(Type::Matrix(_, columns)), Some(stride)) => stride * columns,
+                        _ => self.size_of(member)
+                    };
+                    offset + size
+                })
+                .max()
+                .unwrap_or(0),
+            _ => 0
+        }
+    }
+}
+
+// Nul terminated UTF-8 packed into little endian words
+fn read_string(words: &[u32]) -> String {
+    let bytes: Vec<u8> = words
+        .iter()
+        .flat_map(|word| word.to_le_bytes())
+        .take_while(|&byte| byte != 0)
+        .collect();
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+// All stages of a pipeline merged, bindings used by several stages are visible to all of them
+#[derive(Clone, Debug, Default)]
+pub struct PipelineReflection {
+    pub sets: BTreeMap<u32, Vec<DescriptorBinding>>,
+    pub push_constant_size: u32,
+    pub push_constant_stages: vk::ShaderStageFlags,
+    pub vertex_inputs: Vec<VertexInput>,
+}
+
+impl PipelineReflection {
+    pub fn new(stages: &[&[u32]]) -> Result<Self, String> {
+        let mut reflection = Self::default();
+        for code in stages {
+            let shader = ShaderReflection::new(code)?;
+
+            for binding in shader.bindings {
+                let set = reflection.sets.entry(binding.set).or_default();
+                match set.iter_mut().find(|existing| existing.binding == binding.binding) {
+                    Some(existing) if existing.descriptor_type != binding.descriptor_type => {
+                        return Err(format!("set {} binding {} is a {:?} in one stage and a {:?} in another",
+                            binding.set, binding.binding, existing.descriptor_type, binding.descriptor_type));
+                    }
+                    Some(existing) => existing.stages |= binding.stages,
+                    None => set.push(binding)
+                }
+            }
+
+            if shader.push_constant_size > 0 {
+                reflection.push_constant_size = reflection.push_constant_size.max(shader.push_constant_size);
+                reflection.push_constant_stages |= shader.stage;
+            }
+            if shader.stage == vk::ShaderStageFlags::VERTEX {
+                reflection.vertex_inputs = shader.vertex_inputs;
+            }
+        }
+
+        for set in reflection.sets.values_mut() {
+            set.sort_by_key(|binding| binding.binding);
+        }
+
+        Ok(reflection)
+    }
+
+    // Everything the shaders need that the pipeline wouldn't provide, one line per problem. `set_layouts` holds the
+    // bindings of each set of the pipeline layout as `Descriptors::create_layout` takes them, `None` where they aren't
+    // known and only the set itself is checked.
+    pub fn mismatches(&self, set_layouts: &[Option<LayoutBindings>], push_constant_size: u32,
+        vertex_attributes: &[vk::VertexInputAttributeDescription]
+    ) -> Vec<String> {
+        let mut mismatches = vec![];
+
+        for (&set, bindings) in &self.sets {
+            let layout = match set_layouts.get(set as usize) {
+                Some(layout) => layout,
+                None => {
+                    let names: Vec<&str> = bindings.iter().map(|binding| binding.name.as_str()).collect();
+                    mismatches.push(format!("shaders use descriptor set {} ({}) but the pipeline layout only has {} sets",
+                        set, names.join(", "), set_layouts.len()));
+                    continue;
+                }
+            };
+            let layout = match layout {
+                Some(layout) => layout,
+                None => continue
+            };
+
+            for binding in bindings {
+                match layout.get(binding.binding as usize) {
+                    None => mismatches.push(format!("{} uses set {} binding {} but the set layout has {} bindings",
+                        binding.name, set, binding.binding, layout.len())),
+                    Some(&(descriptor_type, _)) if descriptor_type != binding.descriptor_type => {
+                        mismatches.push(format!("{} at set {} binding {} is a {:?} but the set layout has a {:?}",
+                            binding.name, set, binding.binding, binding.descriptor_type, descriptor_type));
+                    }
+                    Some(&(_, stages)) if !stages.contains(binding.stages) => {
+                        mismatches.push(format!("{} at set {} binding {} is used by {:?} but the set layout only shows it to {:?}",
+                            binding.name, set, binding.binding, binding.stages, stages));
+                    }
+                    // Layout bindings hold a single descriptor, runtime sized arrays get by with one
+                    Some(_) if binding.count > 1 => {
+                        mismatches.push(format!("{} at set {} binding {} is an array of {} but the set layout binding holds one descriptor",
+                            binding.name, set, binding.binding, binding.count));
+                    }
+                    Some(_) => {}
+                }
+            }
+        }
+
+        if self.push_constant_size > push_constant_size {
+            mismatches.push(format!("shaders read {} bytes of push constants but the pipeline layout only has {}",
+                self.push_constant_size, push_constant_size));
+        }
+
+        for input in &self.vertex_inputs {
+            match vertex_attributes.iter().find(|attribute| attribute.location == input.location) {
+                // Missing components are filled in with defaults, but the numeric type has to match
+                Some(attribute) if format_kind(attribute.format).is_some_and(|kind| kind != input.kind) => {
+                    mismatches.push(format!("vertex input {} at location {} is {:?} but the attribute is {:?}",
+                        input.name, input.location, input.kind, attribute.format));
+                }
+                Some(_) => {}
+                None => mismatches.push(format!("vertex input {} at location {} has no vertex attribute", input.name, input.location))
+            }
+        }
+
+        mismatches
+    }
+}
+
+// Numeric type shaders read a vertex format as, None for formats this renderer doesn't use
+fn format_kind(format: vk::Format) -> Option<ScalarKind> {
+    match format {
+        vk::Format::R32_SFLOAT | vk::Format::R32G32_SFLOAT | vk::Format::R32G32B32_SFLOAT | vk::Format::R32G32B32A32_SFLOAT
+            | vk::Format::R8G8B8A8_UNORM => Some(ScalarKind::Float),
+        vk::Format::R32_UINT | vk::Format::R32G32_UINT | vk::Format::R32G32B32_UINT | vk::Format::R32G32B32A32_UINT
+            | vk::Format::R8G8B8A8_UINT => Some(ScalarKind::Uint),
+        vk::Format::R32_SINT | vk::Format::R32G32_SINT | vk::Format::R32G32B32_SINT | vk::Format::R32G32B32A32_SINT => Some(ScalarKind::Int),
+        _ => None
+    }
+}
+
+// Checks the shaders of a pipeline against what its layout and vertex input provide before the pipeline is created.
+// Modules that can't be parsed are rejected as well, the shaders are compiled in the build so that's a bug.
+pub fn validate(stages: &[&[u32]], set_layouts: &[vk::DescriptorSetLayout], push_constant_size: u32,
+    vertex_attributes: &[vk::VertexInputAttributeDescription]
+) -> Result<(), vk::Result> {
+    let reflection = match PipelineReflection::new(stages) {
+        Ok(reflection) => reflection,
+        Err(error) => {
+            tracing::error!("Failed to reflect a shader: {}", error);
+            return Err(vk::Result::ERROR_INITIALIZATION_FAILED);
+        }
+    };
+
+    let set_layouts: Vec<_> = set_layouts.iter().map(|&layout| Descriptors::layout_bindings(layout)).collect();
+    let mismatches = reflection.mismatches(&set_layouts, push_constant_size, vertex_attributes);
+    match mismatches.is_empty() {
+        true => Ok(()),
+        false => {
+            for mismatch in &mismatches {
+                tracing::error!("Shader mismatch: {}", mismatch);
+            }
+            Err(vk::Result::ERROR_INITIALIZATION_FAILED)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OP_TYPE_VOID: u32 = 19;
+    const OP_TYPE_FUNCTION: u32 = 33;
+    const OP_LOAD: u32 = 61;
+    const OP_FUNCTION_END: u32 = 56;
+    const DECORATION_BLOCK: u32 = 2;
+    const BUILT_IN_VERTEX_INDEX: u32 = 42;
+
+    fn op(opcode: u32, operands: &[u32]) -> Vec<u32> {
+        let mut words = vec![((operands.len() as u32 + 1) << 16) | opcode];
+        words.extend_from_slice(operands);
+        words
+    }
+
+    fn string(text: &str) -> Vec<u32> {
+        let mut bytes = text.as_bytes().to_vec();
+        bytes.resize(bytes.len() / 4 * 4 + 4, 0);
+        bytes.chunks_exact(4).map(|word| u32::from_le_bytes(word.try_into().unwrap())).collect()
+    }
+
+    fn named(id: u32, name: &str) -> Vec<u32> {
+        op(OP_NAME, &[&[id][..], &string(name)].concat())
+    }
+
+    fn module(execution_model: u32, declarations: &[Vec<u32>], body: &[Vec<u32>]) -> Vec<u32> {
+        let mut words = vec![MAGIC, 0x0001_0000, 0, 100, 0];
+        words.extend(op(OP_ENTRY_POINT, &[&[execution_model, 1][..], &string("main")].concat()));
+        words.extend(declarations.concat());
+        words.extend(op(OP_TYPE_VOID, &[2]));
+        words.extend(op(OP_TYPE_FUNCTION, &[3, 2]));
+        words.extend(op(OP_FUNCTION, &[2, 1, 0, 3]));
+        words.extend(body.concat());
+        words.extend(op(OP_FUNCTION_END, &[]));
+        words
+    }
+
+    // A sampler2D at set 1 binding 2 that's read, one at set 0 binding 0 that isn't, and a { vec4, float } push
+    // constant block
+    fn fragment_shader() -> Vec<u32> {
+        module(4, &[
+            named(10, "albedo"),
+            op(OP_DECORATE, &[10, DECORATION_DESCRIPTOR_SET, 1]),
+            op(OP_DECORATE, &[10, DECORATION_BINDING, 2]),
+            op(OP_DECORATE, &[12, DECORATION_DESCRIPTOR_SET, 0]),
+            op(OP_DECORATE, &[12, DECORATION_BINDING, 0]),
+            op(OP_DECORATE, &[25, DECORATION_BLOCK]),
+            op(OP_MEMBER_DECORATE, &[25, 0, DECORATION_OFFSET, 0]),
+            op(OP_MEMBER_DECORATE, &[25, 1, DECORATION_OFFSET, 16]),
+            op(OP_TYPE_FLOAT, &[20, 32]),
+            op(OP_TYPE_IMAGE, &[21, 20, 1, 0, 0, 0, 1, 0]),
+            op(OP_TYPE_SAMPLED_IMAGE, &[22, 21]),
+            op(OP_TYPE_POINTER, &[23, STORAGE_UNIFORM_CONSTANT, 22]),
+            op(OP_VARIABLE, &[23, 10, STORAGE_UNIFORM_CONSTANT]),
+            op(OP_VARIABLE, &[23, 12, STORAGE_UNIFORM_CONSTANT]),
+            op(OP_TYPE_VECTOR, &[24, 20, 4]),
+            op(OP_TYPE_STRUCT, &[25, 24, 20]),
+            op(OP_TYPE_POINTER, &[26, STORAGE_PUSH_CONSTANT, 25]),
+            op(OP_VARIABLE, &[26, 11, STORAGE_PUSH_CONSTANT])
+        ], &[op(OP_LOAD, &[22, 30, 10])])
+    }
+
+    // A vec3 at location 0 and gl_VertexIndex
+    fn vertex_shader() -> Vec<u32> {
+        module(0, &[
+            named(13, "position"),
+            op(OP_DECORATE, &[13, DECORATION_LOCATION, 0]),
+            op(OP_DECORATE, &[14, DECORATION_BUILT_IN, BUILT_IN_VERTEX_INDEX]),
+            op(OP_TYPE_FLOAT, &[20, 32]),
+            op(OP_TYPE_VECTOR, &[24, 20, 3]),
+            op(OP_TYPE_POINTER, &[27, STORAGE_INPUT, 24]),
+            op(OP_VARIABLE, &[27, 13, STORAGE_INPUT]),
+            op(OP_TYPE_INT, &[28, 32, 1]),
+            op(OP_TYPE_POINTER, &[29, STORAGE_INPUT, 28]),
+            op(OP_VARIABLE, &[29, 14, STORAGE_INPUT])
+        ], &[op(OP_LOAD, &[24, 31, 13]), op(OP_LOAD, &[28, 32, 14])])
+    }
+
+    fn attribute(location: u32, format: vk::Format) -> vk::VertexInputAttributeDescription {
+        vk::VertexInputAttributeDescription { location, binding: 0, format, offset: 0 }
+    }
+
+    #[test]
+    fn reflects_used_bindings_and_push_constants() {
+        let shader = ShaderReflection::new(&fragment_shader()).unwrap();
+        assert_eq!(shader.stage, vk::ShaderStageFlags::FRAGMENT);
+        assert_eq!(shader.bindings.len(), 1);
+        let binding = &shader.bindings[0];
+        assert_eq!((binding.set, binding.binding, binding.count), (1, 2, 1));
+        assert_eq!(binding.descriptor_type, vk::DescriptorType::COMBINED_IMAGE_SAMPLER);
+        assert_eq!(binding.name, "albedo");
+        assert_eq!(shader.push_constant_size, 20);
+    }
+
+    #[test]
+    fn reflects_vertex_inputs_without_built_ins() {
+        let shader = ShaderReflection::new(&vertex_shader()).unwrap();
+        assert_eq!(shader.stage, vk::ShaderStageFlags::VERTEX);
+        assert_eq!(shader.vertex_inputs.len(), 1);
+        let input = &shader.vertex_inputs[0];
+        assert_eq!((input.location, input.kind, input.components, input.name.as_str()), (0, ScalarKind::Float, 3, "position"));
+    }
+
+    #[test]
+    fn rejects_broken_modules() {
+        assert!(ShaderReflection::new(&[1, 2, 3, 4, 5]).is_err());
+        let mut truncated = fragment_shader();
+        truncated.truncate(truncated.len() - 2);
+        assert!(ShaderReflection::new(&truncated).is_err());
+        assert!(ShaderReflection::new(&[MAGIC, 0x0001_0000, 0, 100, 0]).is_err());
+    }
+
+    #[test]
+    fn matching_pipeline_has_no_mismatches() {
+        let (vertex, fragment) = (vertex_shader(), fragment_shader());
+        let reflection = PipelineReflection::new(&[&vertex, &fragment]).unwrap();
+        let sampler = (vk::DescriptorType::COMBINED_IMAGE_SAMPLER, vk::ShaderStageFlags::FRAGMENT);
+        let layouts = [Some(vec![]), Some(vec![sampler; 3])];
+        assert!(reflection.mismatches(&layouts, 20, &[attribute(0, vk::Format::R32G32B32_SFLOAT)]).is_empty());
+        // Layouts that aren't known only count as sets
+        assert!(reflection.mismatches(&[None, None], 20, &[attribute(0, vk::Format::R32G32B32_SFLOAT)]).is_empty());
+    }
+
+    #[test]
+    fn finds_mismatches() {
+        let (vertex, fragment) = (vertex_shader(), fragment_shader());
+        let reflection = PipelineReflection::new(&[&vertex, &fragment]).unwrap();
+        let position = [attribute(0, vk::Format::R32G32B32_SFLOAT)];
+        let sampler = (vk::DescriptorType::COMBINED_IMAGE_SAMPLER, vk::ShaderStageFlags::FRAGMENT);
+
+        let mismatches = |layouts: &[Option<LayoutBindings>], push_constant_size, attributes: &[_]| {
+            reflection.mismatches(layouts, push_constant_size, attributes)
+        };
+        // Missing set, binding, wrong type and stages
+        assert_eq!(mismatches(&[None], 20, &position).len(), 1);
+        assert_eq!(mismatches(&[None, Some(vec![sampler; 2])], 20, &position).len(), 1);
+        let buffer = (vk::DescriptorType::UNIFORM_BUFFER, vk::ShaderStageFlags::FRAGMENT);
+        assert_eq!(mismatches(&[None, Some(vec![sampler, sampler, buffer])], 20, &position).len(), 1);
+        let vertex_only = (vk::DescriptorType::COMBINED_IMAGE_SAMPLER, vk::ShaderStageFlags::VERTEX);
+        assert_eq!(mismatches(&[None, Some(vec![sampler, sampler, vertex_only])], 20, &position).len(), 1);
+        // Push constants and vertex attributes
+        assert_eq!(mismatches(&[None, None], 16, &position).len(), 1);
+        assert_eq!(mismatches(&[None, None], 20, &[]).len(), 1);
+        assert_eq!(mismatches(&[None, None], 20, &[attribute(0, vk::Format::R32G32B32_UINT)]).len(), 1);
+    }
+
+    #[test]
+    fn arrays_take_one_binding() {
+        let shader = module(5, &[
+            op(OP_DECORATE, &[10, DECORATION_DESCRIPTOR_SET, 0]),
+            op(OP_DECORATE, &[10, DECORATION_BINDING, 0]),
+            op(OP_TYPE_FLOAT, &[20, 32]),
+            op(OP_TYPE_IMAGE, &[21, 20, 1, 0, 0, 0, 2, 1]),
+            op(OP_TYPE_INT, &[28, 32, 0]),
+            op(OP_CONSTANT, &[28, 29, 4]),
+            op(OP_TYPE_ARRAY, &[22, 21, 29]),
+            op(OP_TYPE_POINTER, &[23, STORAGE_UNIFORM_CONSTANT, 22]),
+            op(OP_VARIABLE, &[23, 10, STORAGE_UNIFORM_CONSTANT])
+        ], &[op(OP_LOAD, &[22, 30, 10])]);
+        let reflection = PipelineReflection::new(&[&shader]).unwrap();
+        let binding = &reflection.sets[&0][0];
+        assert_eq!((binding.descriptor_type, binding.count), (vk::DescriptorType::STORAGE_IMAGE, 4));
+        let layouts = [Some(vec![(vk::DescriptorType::STORAGE_IMAGE, vk::ShaderStageFlags::COMPUTE)])];
+        assert_eq!(reflection.mismatches(&layouts, 0, &[]).len(), 1);
+    }
+}
@@ -0,0 +1,225 @@
+use ash::vk;
+
+use super::compute_pipeline::ComputePipeline;
+use super::descriptor_allocator::DescriptorAllocator;
+use super::descriptors::Descriptors;
+use super::render_target::RenderTarget;
+
+use crate::utils::any_as_u8_slice;
+
+pub const SHADING_RATE_COMP: &[u32] = vk_shader_macros::include_glsl!("./shaders/shading_rate.comp", kind: comp);
+
+// Must match the local size in shading_rate.comp
+const RATE_GROUP_SIZE: u32 = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShadingRateMode {
+    Off,
+    // One coarser rate for every opaque draw, `VariableRateShading::draw_rate`
+    PerDraw,
+    // A rate per tile of the screen, coarser towards the edges and where the previous frame moved fast
+    Image,
+}
+
+// What VK_KHR_fragment_shading_rate offers on a device
+#[derive(Clone, Copy, Debug)]
+pub struct ShadingRateSupport {
+    // Pixels covered by one texel of a shading rate image, `None` when the device can't use one
+    pub attachment_texel_size: Option<vk::Extent2D>,
+    // Largest fragment a single invocation may shade
+    pub max_fragment_size: vk::Extent2D,
+}
+
+impl ShadingRateSupport {
+    // `None` without the extension or its pipeline rates, the attachment rates are optional on top
+    pub fn query(instance: &ash::Instance, physical_device: vk::PhysicalDevice) -> Option<Self> {
+        let extensions = unsafe { instance.enumerate_device_extension_properties(physical_device).ok()? };
+        let name = vk::KhrFragmentShadingRateFn::name();
+        if !extensions.iter().any(|extension| unsafe { std::ffi::CStr::from_ptr(extension.extension_name.as_ptr()) } == name) {
+            return None;
+        }
+
+        let mut rate_features = vk::PhysicalDeviceFragmentShadingRateFeaturesKHR::default();
+        let mut features = vk::PhysicalDeviceFeatures2::builder().push_next(&mut rate_features);
+        unsafe { instance.get_physical_device_features2(physical_device, &mut features) };
+        // The rates are written by a compute pass into an R8_UINT storage image
+        let storage = features.features.shader_storage_image_extended_formats == vk::TRUE;
+        if rate_features.pipeline_fragment_shading_rate != vk::TRUE {
+            return None;
+        }
+
+        let mut rate_properties = vk::PhysicalDeviceFragmentShadingRatePropertiesKHR::default();
+        let mut properties = vk::PhysicalDeviceProperties2::builder().push_next(&mut rate_properties);
+        unsafe { instance.get_physical_device_properties2(physical_device, &mut properties) };
+
+        Some(Self {
+            attachment_texel_size: match rate_features.attachment_fragment_shading_rate == vk::TRUE && storage {
+                true => Some(rate_properties.min_fragment_shading_rate_attachment_texel_size),
+                false => None
+            },
+            max_fragment_size: rate_properties.max_fragment_size
+        })
+    }
+}
+
+// Must match the push block in shading_rate.comp
+#[repr(C)]
+struct RatePushConstants {
+    _max_rate: [u32; 2],
+    _image_mode: u32,
+    _use_motion: u32,
+    _edge_start: f32,
+    _motion_threshold: f32,
+}
+
+// Variable rate shading of the opaque scene pass. Basic and lightmapped pipelines take their rate from dynamic state,
+// which `record_draw_rate` sets per draw, combined with the scene target's shading rate image when the device has one.
+// Other pipelines in the pass (reflective surfaces, outlines, hooks) always shade every pixel.
+pub struct VariableRateShading {
+    functions: vk::KhrFragmentShadingRateFn,
+    pub support: ShadingRateSupport,
+    pub mode: ShadingRateMode,
+    // Fragment size of `ShadingRateMode::PerDraw`, clamped to what the device supports
+    pub draw_rate: vk::Extent2D,
+    // Distance from the center, 0 to 1 at the screen's edge, from which the image mode starts coarsening
+    pub edge_start: f32,
+    // Motion in pixels per frame from which the image mode starts coarsening
+    pub motion_threshold: f32,
+    set_layout: vk::DescriptorSetLayout,
+    set: vk::DescriptorSet,
+    pipeline: ComputePipeline,
+    // Cleared when the scene target is recreated, its motion is undefined until a frame has been drawn into it
+    motion_valid: bool,
+}
+
+impl VariableRateShading {
+    pub fn new(instance: &ash::Instance, device: &ash::Device, descriptors: &mut DescriptorAllocator, support: ShadingRateSupport,
+        mode: ShadingRateMode
+    ) -> Result<Self, vk::Result> {
+        let functions = vk::KhrFragmentShadingRateFn::load(|name| unsafe {
+            std::mem::transmute(instance.get_device_proc_addr(device.handle(), name.as_ptr()))
+        });
+
+        let set_layout = Descriptors::create_layout(device, &[
+            (vk::DescriptorType::STORAGE_IMAGE, vk::ShaderStageFlags::COMPUTE),
+            (vk::DescriptorType::COMBINED_IMAGE_SAMPLER, vk::ShaderStageFlags::COMPUTE),
+        ])?;
+        let set = descriptors.allocate(device, set_layout)?;
+        let pipeline = ComputePipeline::new(device, SHADING_RATE_COMP, &[set_layout], std::mem::size_of::<RatePushConstants>() as u32, &[])?;
+
+        let mode = match (mode, support.attachment_texel_size) {
+            (ShadingRateMode::Image, None) => {
+                tracing::warn!("Device has no shading rate images, shading rates are per draw instead");
+                ShadingRateMode::PerDraw
+            },
+            (mode, _) => mode
+        };
+
+        Ok(Self {
+            functions,
+            support,
+            mode,
+            draw_rate: vk::Extent2D { width: 2, height: 2 },
+            edge_start: 0.6,
+            motion_threshold: 8.0,
+            set_layout,
+            set,
+            pipeline,
+            motion_valid: false
+        })
+    }
+
+    // Points the compute pass at the scene target's rate image and motion, again after every resize
+    pub fn write_target(&mut self, device: &ash::Device, scene_target: &RenderTarget) {
+        if let Some(rates) = &scene_target.shading_rate {
+            Descriptors::write_image(device, self.set, 0, vk::DescriptorType::STORAGE_IMAGE, vk::DescriptorImageInfo {
+                sampler: vk::Sampler::null(),
+                image_view: rates.view,
+                image_layout: vk::ImageLayout::GENERAL
+            });
+            Descriptors::write_image(device, self.set, 1, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, scene_target.descriptor_info(2));
+        }
+        self.motion_valid = false;
+    }
+
+    // Fills the scene target's rate image, outside of any render pass and before the scene pass. Outside of the image
+    // mode it's filled with full rate since the scene passes use it either way.
+    pub fn record_rate_image(&self, device: &ash::Device, command_buffer: vk::CommandBuffer, scene_target: &RenderTarget) {
+        let rates = match &scene_target.shading_rate {
+            Some(rates) => rates,
+            None => return
+        };
+
+        let push = RatePushConstants {
+            _max_rate: [self.support.max_fragment_size.width, self.support.max_fragment_size.height],
+            _image_mode: (self.mode == ShadingRateMode::Image) as u32,
+            _use_motion: self.motion_valid as u32,
+            _edge_start: self.edge_start,
+            _motion_threshold: self.motion_threshold
+        };
+
+        let barrier = |old_layout, new_layout, src_access_mask, dst_access_mask| vk::ImageMemoryBarrier::builder()
+            .old_layout(old_layout)
+            .new_layout(new_layout)
+            .src_access_mask(src_access_mask)
+            .dst_access_mask(dst_access_mask)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .image(rates.image)
+            .subresource_range(rates.subresource_range())
+            .build();
+
+        unsafe {
+            // Last frame's rates aren't needed anymore
+            device.cmd_pipeline_barrier(command_buffer, vk::PipelineStageFlags::FRAGMENT_SHADING_RATE_ATTACHMENT_KHR,
+                vk::PipelineStageFlags::COMPUTE_SHADER, vk::DependencyFlags::empty(), &[], &[],
+                &[barrier(vk::ImageLayout::UNDEFINED, vk::ImageLayout::GENERAL, vk::AccessFlags::empty(), vk::AccessFlags::SHADER_WRITE)]);
+
+            device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::COMPUTE, self.pipeline.pipeline);
+            device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::COMPUTE, self.pipeline.layout, 0, &[self.set], &[]);
+            device.cmd_push_constants(command_buffer, self.pipeline.layout, vk::ShaderStageFlags::COMPUTE, 0, any_as_u8_slice(&push));
+            device.cmd_dispatch(command_buffer, rates.extent.width.div_ceil(RATE_GROUP_SIZE), rates.extent.height.div_ceil(RATE_GROUP_SIZE), 1);
+
+            // The scene pass overwrites the motion this read, and shades by the rates it wrote
+            device.cmd_pipeline_barrier(command_buffer, vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::PipelineStageFlags::FRAGMENT_SHADING_RATE_ATTACHMENT_KHR | vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                vk::DependencyFlags::empty(), &[], &[],
+                &[barrier(vk::ImageLayout::GENERAL, vk::ImageLayout::FRAGMENT_SHADING_RATE_ATTACHMENT_OPTIMAL_KHR, vk::AccessFlags::SHADER_WRITE,
+                    vk::AccessFlags::FRAGMENT_SHADING_RATE_ATTACHMENT_READ_KHR)]);
+        }
+    }
+
+    // Called after a frame is recorded, the frames after it find that one's motion in the scene target
+    pub fn drawn(&mut self) {
+        self.motion_valid = true;
+    }
+
+    // Sets the rate of the following draws, their pipeline must have `dynamic_shading_rate`. The image's rate replaces
+    // the draw's in the image mode, the other modes ignore it.
+    pub fn record_draw_rate(&self, command_buffer: vk::CommandBuffer) {
+        let fragment_size = match self.mode {
+            ShadingRateMode::PerDraw => vk::Extent2D {
+                width: self.draw_rate.width.min(self.support.max_fragment_size.width),
+                height: self.draw_rate.height.min(self.support.max_fragment_size.height)
+            },
+            ShadingRateMode::Off | ShadingRateMode::Image => vk::Extent2D { width: 1, height: 1 }
+        };
+        let combiner_ops = [
+            vk::FragmentShadingRateCombinerOpKHR::KEEP,
+            match self.mode {
+                ShadingRateMode::Image => vk::FragmentShadingRateCombinerOpKHR::REPLACE,
+                ShadingRateMode::Off | ShadingRateMode::PerDraw => vk::FragmentShadingRateCombinerOpKHR::KEEP
+            }
+        ];
+
+        unsafe { (self.functions.cmd_set_fragment_shading_rate_khr)(command_buffer, &fragment_size, &combiner_ops) };
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device, descriptors: &mut DescriptorAllocator) -> Result<(), vk::Result> {
+        descriptors.free(device, self.set)?;
+        self.pipeline.cleanup(device);
+        unsafe { device.destroy_descriptor_set_layout(self.set_layout, None) };
+
+        Ok(())
+    }
+}
\ No newline at end of file
@@ -0,0 +1,448 @@
+use ash::vk;
+use gpu_allocator::vulkan::Allocator;
+
+use super::cubemap::{cube_face_projection, cube_face_views};
+use super::game_object::GameObject;
+use super::image::{Image, DEPTH_FORMAT, create_sampler, create_shadow_sampler};
+use super::lights::{PointLight, SpotLight};
+use super::multiview::{MultiviewSupport, CUBE_VIEW_MASK};
+use super::pipeline::{Pipeline, PipelineConfig};
+use super::render_pass::RenderPass;
+use super::renderer::VulkanRenderer;
+use super::swapchain::VulkanSwapchain;
+
+use crate::utils::any_as_u8_slice;
+
+pub const SHADOW_VERT: &[u32] = vk_shader_macros::include_glsl!("./shaders/shadow.vert", kind: vert);
+pub const SHADOW_FRAG: &[u32] = vk_shader_macros::include_glsl!("./shaders/shadow.frag", kind: frag);
+pub const SHADOW_CUBE_VERT: &[u32] = vk_shader_macros::include_glsl!("./shaders/shadow_cube.vert", kind: vert);
+
+pub const MAX_SHADOWED_SPOT_LIGHTS: usize = 4;
+pub const MAX_SHADOWED_POINT_LIGHTS: usize = 4;
+pub const SHADOW_MAP_SIZE: u32 = 1024;
+pub const POINT_SHADOW_MAP_SIZE: u32 = 512;
+// Must match POINT_SHADOW_NEAR in shaders/include/lighting.glsl and shaders/shadow_cube.vert
+pub const POINT_SHADOW_NEAR: f32 = 0.05;
+
+#[repr(C)]
+struct ShadowPushConstants {
+    _light_view_projection: uv::Mat4,
+    _model: uv::Mat4,
+}
+
+// Must match the push block in shadow_cube.vert
+#[repr(C)]
+struct CubeShadowPushConstants {
+    _model: uv::Mat4,
+    _light_position_radius: uv::Vec4,
+}
+
+// Must match the SHADOW_FILTER_* constants in shaders/include/lighting.glsl
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShadowFilter {
+    // Single bilinear comparison tap
+    Hardware,
+    // Fixed size Poisson disk
+    Pcf,
+    // Blocker search first, so the penumbra widens away from the contact point
+    Pcss,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ShadowSettings {
+    pub filter: ShadowFilter,
+    // Taps per lookup, at most 16
+    pub samples: u32,
+    pub blocker_samples: u32,
+    // PCF kernel radius in shadow map texels
+    pub filter_radius: f32,
+    // Size of the emitter for PCSS, in shadow map uv units (radians for point lights)
+    pub light_size: f32,
+}
+
+impl ShadowSettings {
+    pub fn low() -> Self {
+        Self {
+            filter: ShadowFilter::Hardware,
+            samples: 1,
+            blocker_samples: 1,
+            filter_radius: 0.0,
+            light_size: 0.0
+        }
+    }
+
+    pub fn medium() -> Self {
+        Self {
+            filter: ShadowFilter::Pcf,
+            samples: 8,
+            blocker_samples: 1,
+            filter_radius: 1.5,
+            light_size: 0.0
+        }
+    }
+
+    pub fn high() -> Self {
+        Self {
+            filter: ShadowFilter::Pcss,
+            samples: 16,
+            blocker_samples: 16,
+            filter_radius: 1.5,
+            light_size: 0.02
+        }
+    }
+
+    pub fn filter_params(&self) -> [u32; 4] {
+        let mode = match self.filter {
+            ShadowFilter::Hardware => 0,
+            ShadowFilter::Pcf => 1,
+            ShadowFilter::Pcss => 2,
+        };
+        [mode, self.samples, self.blocker_samples, 0]
+    }
+
+    pub fn size_params(&self) -> uv::Vec4 {
+        uv::Vec4::new(self.filter_radius, self.light_size, 0.0, 0.0)
+    }
+}
+
+impl Default for ShadowSettings {
+    fn default() -> Self {
+        Self::medium()
+    }
+}
+
+// Shadow layer per light, recomputed every frame from the current lights and camera
+pub struct ShadowAssignment {
+    pub spot: Vec<Option<u32>>,
+    pub point: Vec<Option<u32>>,
+}
+
+// Depth array with a framebuffer for each layer, or for each group of `views` layers drawn by a multiview pass
+struct LayeredDepthTarget {
+    image: Image,
+    layer_views: Vec<vk::ImageView>,
+    framebuffers: Vec<vk::Framebuffer>,
+}
+
+impl LayeredDepthTarget {
+    #[allow(clippy::too_many_arguments)]
+    fn new(device: &ash::Device, allocator: &mut Allocator, renderpass: vk::RenderPass, size: u32, layers: u32, view_type: vk::ImageViewType,
+        views: u32, name: &str
+    ) -> Result<Self, vk::Result> {
+        let extent = vk::Extent2D { width: size, height: size };
+        let image = Image::new_layered(device, allocator, extent, DEPTH_FORMAT,
+            vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | vk::ImageUsageFlags::SAMPLED, vk::ImageAspectFlags::DEPTH,
+            layers, view_type, name)?;
+
+        let mut layer_views = Vec::with_capacity((layers / views) as usize);
+        let mut framebuffers = Vec::with_capacity((layers / views) as usize);
+        for first_layer in (0..layers).step_by(views as usize) {
+            let view = match views {
+                1 => image.create_layer_view(device, first_layer)?,
+                _ => image.create_layers_view(device, vk::ImageViewType::TYPE_2D_ARRAY, first_layer, views)?
+            };
+            let attachments = [view];
+            // Multiview framebuffers still have a single layer, the view mask picks the layers of the attachment
+            let framebuffer_info = vk::FramebufferCreateInfo::builder()
+                .render_pass(renderpass)
+                .attachments(&attachments)
+                .width(extent.width)
+                .height(extent.height)
+                .layers(1);
+            framebuffers.push(unsafe { device.create_framebuffer(&framebuffer_info, None)? });
+            layer_views.push(view);
+        }
+
+        Ok(Self {
+            image,
+            layer_views,
+            framebuffers
+        })
+    }
+
+    fn destroy(&mut self, device: &ash::Device, allocator: &mut Allocator) {
+        unsafe {
+            for &framebuffer in &self.framebuffers {
+                device.destroy_framebuffer(framebuffer, None);
+            }
+            for &view in &self.layer_views {
+                device.destroy_image_view(view, None);
+            }
+        }
+        self.image.destroy(device, allocator);
+    }
+}
+
+// Draws all six faces of a point light's cube at once, with the face matrices built in the vertex shader
+struct CubeShadowPass {
+    renderpass: vk::RenderPass,
+    pipeline: Pipeline,
+}
+
+// Depth maps rendered from the lights' point of view: one array layer per shadow casting spot light
+// and one cube (six layers) per shadow casting point light. Cubes take a single multiview pass where the device
+// has multiview, otherwise a pass per face.
+pub struct ShadowSystem {
+    pub settings: ShadowSettings,
+    pub sampler: vk::Sampler,
+    // Plain sampler reading raw depth for the PCSS blocker search
+    pub depth_sampler: vk::Sampler,
+    pub renderpass: vk::RenderPass,
+    pub pipeline: Pipeline,
+    cube_pass: Option<CubeShadowPass>,
+    spot_maps: LayeredDepthTarget,
+    point_maps: LayeredDepthTarget,
+}
+
+impl ShadowSystem {
+    pub fn new(device: &ash::Device, allocator: &mut Allocator, swapchain: &VulkanSwapchain, multiview: Option<MultiviewSupport>
+    ) -> Result<Self, vk::Result> {
+        let renderpass = RenderPass::init_offscreen(device, &[], Some(DEPTH_FORMAT))?;
+
+        // Both faces are drawn so open meshes (planes, quads) still cast shadows
+        let config = PipelineConfig {
+            vertex_shader: SHADOW_VERT,
+            fragment_shader: SHADOW_FRAG,
+            push_constant_size: std::mem::size_of::<ShadowPushConstants>() as u32,
+            color_attachment_count: 0,
+            cull_mode: vk::CullModeFlags::NONE,
+            depth_bias: Some((1.25, 1.75)),
+            ..PipelineConfig::basic(&[])
+        };
+        let pipeline = Pipeline::new(device, swapchain, &renderpass, &config)?;
+
+        let cube_pass = match multiview.is_some_and(|support| support.supports(6)) {
+            true => {
+                let renderpass = RenderPass::init_offscreen_multiview(device, &[], Some(DEPTH_FORMAT), CUBE_VIEW_MASK)?;
+                let config = PipelineConfig {
+                    vertex_shader: SHADOW_CUBE_VERT,
+                    push_constant_size: std::mem::size_of::<CubeShadowPushConstants>() as u32,
+                    ..config
+                };
+                let pipeline = Pipeline::new(device, swapchain, &renderpass, &config)?;
+                Some(CubeShadowPass { renderpass, pipeline })
+            }
+            false => None
+        };
+
+        let spot_maps = LayeredDepthTarget::new(device, allocator, renderpass, SHADOW_MAP_SIZE,
+            MAX_SHADOWED_SPOT_LIGHTS as u32, vk::ImageViewType::TYPE_2D_ARRAY, 1, "Spot Shadow Maps")?;
+        let point_maps = match &cube_pass {
+            Some(cube_pass) => LayeredDepthTarget::new(device, allocator, cube_pass.renderpass, POINT_SHADOW_MAP_SIZE,
+                MAX_SHADOWED_POINT_LIGHTS as u32 * 6, vk::ImageViewType::CUBE_ARRAY, 6, "Point Shadow Maps")?,
+            None => LayeredDepthTarget::new(device, allocator, renderpass, POINT_SHADOW_MAP_SIZE,
+                MAX_SHADOWED_POINT_LIGHTS as u32 * 6, vk::ImageViewType::CUBE_ARRAY, 1, "Point Shadow Maps")?
+        };
+
+        let sampler = create_shadow_sampler(device)?;
+        let depth_sampler = create_sampler(device, vk::Filter::NEAREST, vk::SamplerAddressMode::CLAMP_TO_EDGE)?;
+
+        Ok(Self {
+            settings: ShadowSettings::default(),
+            sampler,
+            depth_sampler,
+            renderpass,
+            pipeline,
+            cube_pass,
+            spot_maps,
+            point_maps
+        })
+    }
+
+    // Spot lights get layers in order until they run out, point lights closest to the camera win
+    pub fn assign(spot_lights: &[SpotLight], point_lights: &[PointLight], camera_position: uv::Vec3) -> ShadowAssignment {
+        let mut next_layer = 0;
+        let spot = spot_lights
+            .iter()
+            .map(|light| {
+                if !light.cast_shadows || next_layer as usize >= MAX_SHADOWED_SPOT_LIGHTS {
+                    return None;
+                }
+                next_layer += 1;
+                Some(next_layer - 1)
+            })
+            .collect();
+
+        let mut candidates: Vec<usize> = (0..point_lights.len())
+            .filter(|&index| point_lights[index].cast_shadows)
+            .collect();
+        candidates.sort_by(|&a, &b| {
+            let distance_a = (point_lights[a].position - camera_position).mag_sq();
+            let distance_b = (point_lights[b].position - camera_position).mag_sq();
+            distance_a.total_cmp(&distance_b)
+        });
+
+        let mut point = vec![None; point_lights.len()];
+        for (layer, &index) in candidates.iter().take(MAX_SHADOWED_POINT_LIGHTS).enumerate() {
+            point[index] = Some(layer as u32);
+        }
+
+        ShadowAssignment {
+            spot,
+            point
+        }
+    }
+
+    // View projections for the six cube faces, in the +X, -X, +Y, -Y, +Z, -Z order of cube array layers
+    pub fn cube_face_matrices(position: uv::Vec3, radius: f32) -> [uv::Mat4; 6] {
+        let projection = cube_face_projection(POINT_SHADOW_NEAR, radius);
+        cube_face_views(position).map(|view| projection * view)
+    }
+
+    pub fn spot_descriptor_info(&self, compare: bool) -> vk::DescriptorImageInfo {
+        vk::DescriptorImageInfo {
+            sampler: if compare { self.sampler } else { self.depth_sampler },
+            image_view: self.spot_maps.image.view,
+            image_layout: vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL
+        }
+    }
+
+    pub fn point_descriptor_info(&self, compare: bool) -> vk::DescriptorImageInfo {
+        vk::DescriptorImageInfo {
+            sampler: if compare { self.sampler } else { self.depth_sampler },
+            image_view: self.point_maps.image.view,
+            image_layout: vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL
+        }
+    }
+
+    // Every layer goes through the render pass each frame, unused ones are just cleared so the whole
+    // array is always in the layout the lighting shaders expect
+    #[allow(clippy::too_many_arguments)]
+    pub fn record(&self, device: &ash::Device, command_buffer: vk::CommandBuffer, assignment: &ShadowAssignment,
+        spot_lights: &[SpotLight], point_lights: &[PointLight], game_objects: &[GameObject], models: &[uv::Mat4]
+    ) {
+        let mut spot_casters: Vec<Option<uv::Mat4>> = vec![None; MAX_SHADOWED_SPOT_LIGHTS];
+        for (light, layer) in spot_lights.iter().zip(&assignment.spot) {
+            if let Some(layer) = layer {
+                spot_casters[*layer as usize] = Some(light.view_projection());
+            }
+        }
+
+        self.record_layers(device, command_buffer, &self.spot_maps, &spot_casters, game_objects, models);
+
+        if let Some(cube_pass) = &self.cube_pass {
+            let mut cube_casters: Vec<Option<uv::Vec4>> = vec![None; MAX_SHADOWED_POINT_LIGHTS];
+            for (light, layer) in point_lights.iter().zip(&assignment.point) {
+                if let Some(layer) = layer {
+                    cube_casters[*layer as usize] = Some(uv::Vec4::new(light.position.x, light.position.y, light.position.z, light.radius));
+                }
+            }
+
+            self.record_cubes(device, command_buffer, cube_pass, &cube_casters, game_objects, models);
+            return;
+        }
+
+        let mut point_casters: Vec<Option<uv::Mat4>> = vec![None; MAX_SHADOWED_POINT_LIGHTS * 6];
+        for (light, layer) in point_lights.iter().zip(&assignment.point) {
+            if let Some(layer) = layer {
+                for (face, matrix) in Self::cube_face_matrices(light.position, light.radius).into_iter().enumerate() {
+                    point_casters[*layer as usize * 6 + face] = Some(matrix);
+                }
+            }
+        }
+
+        self.record_layers(device, command_buffer, &self.point_maps, &point_casters, game_objects, models);
+    }
+
+    // One pass per light writing all six faces, `casters` holds the position and radius of the light on each cube
+    fn record_cubes(&self, device: &ash::Device, command_buffer: vk::CommandBuffer, cube_pass: &CubeShadowPass,
+        casters: &[Option<uv::Vec4>], game_objects: &[GameObject], models: &[uv::Mat4]
+    ) {
+        let clear_values = [vk::ClearValue {
+            depth_stencil: vk::ClearDepthStencilValue {
+                depth: 1.0,
+                stencil: 0
+            }
+        }];
+
+        for (framebuffer, light_position_radius) in self.point_maps.framebuffers.iter().zip(casters) {
+            let renderpass_begininfo = vk::RenderPassBeginInfo::builder()
+                .render_pass(cube_pass.renderpass)
+                .framebuffer(*framebuffer)
+                .render_area(vk::Rect2D {
+                    offset: vk::Offset2D { x: 0, y: 0 },
+                    extent: self.point_maps.image.extent
+                })
+                .clear_values(&clear_values);
+
+            unsafe {
+                device.cmd_begin_render_pass(command_buffer, &renderpass_begininfo, vk::SubpassContents::INLINE);
+
+                if let Some(light_position_radius) = light_position_radius {
+                    VulkanRenderer::set_viewport(device, command_buffer, self.point_maps.image.extent);
+                    device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, cube_pass.pipeline.pipeline);
+
+                    for (game_object, model) in game_objects.iter().zip(models) {
+                        let push = CubeShadowPushConstants {
+                            _model: *model,
+                            _light_position_radius: *light_position_radius
+                        };
+                        device.cmd_push_constants(command_buffer, cube_pass.pipeline.layout, vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT, 0,
+                            any_as_u8_slice(&push));
+                        game_object.mesh.record_draw(device, command_buffer, 0);
+                    }
+                }
+
+                device.cmd_end_render_pass(command_buffer);
+            }
+        }
+    }
+
+    fn record_layers(&self, device: &ash::Device, command_buffer: vk::CommandBuffer, target: &LayeredDepthTarget,
+        casters: &[Option<uv::Mat4>], game_objects: &[GameObject], models: &[uv::Mat4]
+    ) {
+        let clear_values = [vk::ClearValue {
+            depth_stencil: vk::ClearDepthStencilValue {
+                depth: 1.0,
+                stencil: 0
+            }
+        }];
+
+        for (framebuffer, light_view_projection) in target.framebuffers.iter().zip(casters) {
+            let renderpass_begininfo = vk::RenderPassBeginInfo::builder()
+                .render_pass(self.renderpass)
+                .framebuffer(*framebuffer)
+                .render_area(vk::Rect2D {
+                    offset: vk::Offset2D { x: 0, y: 0 },
+                    extent: target.image.extent
+                })
+                .clear_values(&clear_values);
+
+            unsafe {
+                device.cmd_begin_render_pass(command_buffer, &renderpass_begininfo, vk::SubpassContents::INLINE);
+
+                if let Some(light_view_projection) = light_view_projection {
+                    VulkanRenderer::set_viewport(device, command_buffer, target.image.extent);
+                    device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, self.pipeline.pipeline);
+
+                    for (game_object, model) in game_objects.iter().zip(models) {
+                        let push = ShadowPushConstants {
+                            _light_view_projection: *light_view_projection,
+                            _model: *model
+                        };
+                        device.cmd_push_constants(command_buffer, self.pipeline.layout, vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT, 0,
+                            any_as_u8_slice(&push));
+                        game_object.mesh.record_draw(device, command_buffer, 0);
+                    }
+                }
+
+                device.cmd_end_render_pass(command_buffer);
+            }
+        }
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device, allocator: &mut Allocator) {
+        unsafe {
+            device.destroy_sampler(self.sampler, None);
+            device.destroy_sampler(self.depth_sampler, None);
+        }
+        self.pipeline.cleanup(device);
+        RenderPass::cleanup(device, self.renderpass);
+        if let Some(cube_pass) = &self.cube_pass {
+            cube_pass.pipeline.cleanup(device);
+            RenderPass::cleanup(device, cube_pass.renderpass);
+        }
+        self.spot_maps.destroy(device, allocator);
+        self.point_maps.destroy(device, allocator);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,143 @@
+use ash::vk;
+use gpu_allocator::vulkan::Allocator;
+
+use super::camera::{Camera, CameraUniform};
+use super::descriptors::Descriptors;
+use super::reflection::PlanarReflection;
+use super::uniform_buffer::UniformBuffer;
+use super::wind::Wind;
+
+// Part of the viewport a split view covers, in fractions of its size with the origin top left
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ViewArea {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl ViewArea {
+    pub const FULL: Self = Self { x: 0.0, y: 0.0, width: 1.0, height: 1.0 };
+
+    pub fn new(x: f32, y: f32, width: f32, height: f32) -> Self {
+        Self { x, y, width, height }
+    }
+
+    // Common layouts for local multiplayer: side by side for two players, one wide view above two for three
+    // and a grid for four. Counts past four get a grid with as many rows as needed.
+    pub fn split(count: usize) -> Vec<Self> {
+        match count {
+            0 => vec![],
+            1 => vec![Self::FULL],
+            2 => vec![Self::new(0.0, 0.0, 0.5, 1.0), Self::new(0.5, 0.0, 0.5, 1.0)],
+            3 => vec![Self::new(0.0, 0.0, 1.0, 0.5), Self::new(0.0, 0.5, 0.5, 0.5), Self::new(0.5, 0.5, 0.5, 0.5)],
+            _ => {
+                let columns = (count as f32).sqrt().ceil() as usize;
+                let rows = count.div_ceil(columns);
+                (0..count)
+                    .map(|index| Self::new((index % columns) as f32 / columns as f32, (index / columns) as f32 / rows as f32,
+                        1.0 / columns as f32, 1.0 / rows as f32))
+                    .collect()
+            }
+        }
+    }
+
+    // Pixel rect in a target of `extent`, neighbouring areas share their edges without gaps
+    pub fn rect(&self, extent: vk::Extent2D) -> vk::Rect2D {
+        let edge = |fraction: f32, size: u32| ((fraction.clamp(0.0, 1.0) * size as f32).round() as u32).min(size);
+        let left = edge(self.x, extent.width);
+        let top = edge(self.y, extent.height);
+        let right = edge(self.x + self.width, extent.width).max(left + 1).min(extent.width);
+        let bottom = edge(self.y + self.height, extent.height).max(top + 1).min(extent.height);
+
+        vk::Rect2D {
+            offset: vk::Offset2D { x: left as i32, y: top as i32 },
+            extent: vk::Extent2D { width: right.saturating_sub(left).max(1), height: bottom.saturating_sub(top).max(1) }
+        }
+    }
+}
+
+// One player's camera drawn into its own part of the scene target. Post processing still runs once over the whole
+// image with the renderer's main camera, so TAA and motion blur don't line up with split views.
+pub struct SplitView {
+    pub camera: Camera,
+    pub area: ViewArea,
+    pub camera_buffers: Vec<UniformBuffer<CameraUniform>>,
+    pub camera_sets: Vec<vk::DescriptorSet>,
+    // The view's camera mirrored at the planar reflection, unused while there is none
+    pub reflection_buffers: Vec<UniformBuffer<CameraUniform>>,
+    pub reflection_sets: Vec<vk::DescriptorSet>,
+    previous_view_projection: Option<uv::Mat4>,
+}
+
+impl SplitView {
+    pub fn new(
+        device: &ash::Device,
+        allocator: &mut Allocator,
+        descriptor_pool: vk::DescriptorPool,
+        camera_set_layout: vk::DescriptorSetLayout,
+        image_count: usize,
+        camera: Camera,
+        area: ViewArea,
+    ) -> Result<Self, vk::Result> {
+        let (camera_buffers, camera_sets) = Self::create_camera_sets(device, allocator, descriptor_pool, camera_set_layout, image_count)?;
+        let (reflection_buffers, reflection_sets) = Self::create_camera_sets(device, allocator, descriptor_pool, camera_set_layout, image_count)?;
+
+        Ok(Self {
+            camera,
+            area,
+            camera_buffers,
+            camera_sets,
+            reflection_buffers,
+            reflection_sets,
+            previous_view_projection: None
+        })
+    }
+
+    fn create_camera_sets(device: &ash::Device, allocator: &mut Allocator, descriptor_pool: vk::DescriptorPool,
+        camera_set_layout: vk::DescriptorSetLayout, image_count: usize
+    ) -> Result<(Vec<UniformBuffer<CameraUniform>>, Vec<vk::DescriptorSet>), vk::Result> {
+        let sets = Descriptors::allocate(device, descriptor_pool, camera_set_layout, image_count)?;
+        let mut buffers = Vec::with_capacity(image_count);
+        for set in &sets {
+            let buffer = UniformBuffer::<CameraUniform>::new(device, allocator);
+            Descriptors::write_buffer(device, *set, 0, vk::DescriptorType::UNIFORM_BUFFER, buffer.descriptor_info());
+            buffers.push(buffer);
+        }
+
+        Ok((buffers, sets))
+    }
+
+    // `rect` is the view's part of the scene target, the camera's aspect ratio follows it
+    pub fn update(&mut self, index: usize, rect: vk::Rect2D, reflection: Option<&PlanarReflection>, time: f32, wind: &Wind) {
+        self.camera.aspect_ratio = rect.extent.width as f32 / rect.extent.height as f32;
+        let offset = uv::Vec4::new(rect.offset.x as f32, rect.offset.y as f32, 0.0, 0.0);
+
+        let mut uniform = self.camera.uniform(rect.extent);
+        uniform.viewport_offset = offset;
+        uniform.animate(time, wind);
+        let view_projection = uniform.projection * uniform.view;
+        uniform.previous_view_projection = self.previous_view_projection.unwrap_or(view_projection);
+        self.previous_view_projection = Some(view_projection);
+        self.camera_buffers[index].update_buffer(&uniform);
+
+        if let Some(reflection) = reflection {
+            let mut uniform = reflection.uniform(&self.camera, rect.extent);
+            uniform.viewport_offset = offset;
+            self.reflection_buffers[index].update_buffer(&uniform);
+        }
+    }
+
+    // The sets go back to the pool, the device must not use them anymore
+    pub fn destroy(&mut self, device: &ash::Device, allocator: &mut Allocator, descriptor_pool: vk::DescriptorPool) -> Result<(), vk::Result> {
+        for buffer in self.camera_buffers.iter_mut().chain(self.reflection_buffers.iter_mut()) {
+            buffer.destroy(device, allocator);
+        }
+        unsafe {
+            device.free_descriptor_sets(descriptor_pool, &self.camera_sets)?;
+            device.free_descriptor_sets(descriptor_pool, &self.reflection_sets)?;
+        }
+
+        Ok(())
+    }
+}
\ No newline at end of file
@@ -0,0 +1,68 @@
+use ash::vk;
+use gpu_allocator::vulkan::*;
+use gpu_allocator::MemoryLocation;
+
+pub struct StagingBuffer {
+    buffer: vk::Buffer,
+    allocation: Allocation,
+}
+
+impl StagingBuffer {
+    pub fn new(device: &ash::Device, allocator: &mut Allocator, size: u64) -> StagingBuffer {
+        let staging_buffer_create_info = vk::BufferCreateInfo::builder()
+            .size(size)
+            .usage(vk::BufferUsageFlags::TRANSFER_SRC | vk::BufferUsageFlags::TRANSFER_DST)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+
+        let staging_buffer = unsafe {
+            device
+                .create_buffer(&staging_buffer_create_info, None)
+                .expect("Failed to create staging buffer")
+        };
+
+        let mem_requirements = unsafe { device.get_buffer_memory_requirements(staging_buffer) };
+
+        let allocation = allocator.allocate(&AllocationCreateDesc {
+            requirements: mem_requirements,
+            location: MemoryLocation::CpuToGpu,
+            linear: true,
+            name: "Staging Buffer"
+        }).expect("Failed to allocate memory for staging buffer!");
+
+        unsafe {
+            device
+                .bind_buffer_memory(staging_buffer, allocation.memory(), allocation.offset())
+                .expect("Failed to bind staging buffer");
+        }
+
+        StagingBuffer {
+            buffer: staging_buffer,
+            allocation
+        }
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device, allocator: &mut Allocator) {
+        allocator
+            .free(std::mem::take(&mut self.allocation))
+            .expect("Failed to free staging buffer memory!");
+        unsafe {
+            device.destroy_buffer(self.buffer, None);
+        }
+    }
+
+    pub fn update_buffer(&mut self, offset: usize, data: &[u8]) {
+        let dst = unsafe { self.allocation.mapped_ptr().unwrap().as_ptr().cast::<u8>().add(offset) };
+        unsafe {
+            std::ptr::copy_nonoverlapping(data.as_ptr(), dst, data.len());
+        }
+    }
+
+    pub fn read_buffer(&self, offset: usize, data: &mut [u8]) {
+        let src = unsafe { self.allocation.mapped_ptr().unwrap().as_ptr().cast::<u8>().add(offset) };
+        unsafe {
+            std::ptr::copy_nonoverlapping(src, data.as_mut_ptr(), data.len());
+        }
+    }
+
+    pub fn get_buffer(&self) -> vk::Buffer { self.buffer }
+}
\ No newline at end of file
@@ -0,0 +1,106 @@
+use ash::vk;
+use gpu_allocator::vulkan::*;
+use gpu_allocator::MemoryLocation;
+
+pub struct StorageBuffer {
+    buffer: vk::Buffer,
+    allocation: Allocation,
+    size: u64,
+}
+
+impl StorageBuffer {
+    pub fn new(device: &ash::Device, allocator: &mut Allocator, size: u64, location: MemoryLocation, name: &str) -> StorageBuffer {
+        Self::with_usage(device, allocator, size, vk::BufferUsageFlags::empty(), location, name)
+    }
+
+    // `usage` is added to the storage usage, e.g. for buffers compute shaders fill with indirect draws
+    pub fn with_usage(device: &ash::Device, allocator: &mut Allocator, size: u64, usage: vk::BufferUsageFlags, location: MemoryLocation,
+        name: &str
+    ) -> StorageBuffer {
+        let storage_buffer_create_info = vk::BufferCreateInfo::builder()
+            .size(size)
+            .usage(vk::BufferUsageFlags::STORAGE_BUFFER | usage)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+
+        let storage_buffer = unsafe {
+            device
+                .create_buffer(&storage_buffer_create_info, None)
+                .expect("Failed to create storage buffer")
+        };
+
+        let mem_requirements = unsafe { device.get_buffer_memory_requirements(storage_buffer) };
+
+        let allocation = allocator.allocate(&AllocationCreateDesc {
+            requirements: mem_requirements,
+            location,
+            linear: true,
+            name
+        }).expect("Failed to allocate memory for storage buffer!");
+
+        unsafe {
+            device
+                .bind_buffer_memory(storage_buffer, allocation.memory(), allocation.offset())
+                .expect("Failed to bind storage buffer");
+        }
+
+        StorageBuffer {
+            buffer: storage_buffer,
+            allocation,
+            size
+        }
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device, allocator: &mut Allocator) {
+        allocator
+            .free(std::mem::take(&mut self.allocation))
+            .expect("Failed to free storage buffer memory!");
+        unsafe {
+            device.destroy_buffer(self.buffer, None);
+        }
+    }
+
+    // Only valid for host visible buffers; `offset` is in bytes
+    pub fn update_buffer<T: Copy>(&mut self, offset: u64, data: &[T]) {
+        assert!(offset + (std::mem::size_of_val(data) as u64) <= self.size, "Storage buffer write out of bounds!");
+
+        let dst = unsafe {
+            self.allocation.mapped_ptr()
+                .expect("Storage buffer is not host visible!")
+                .as_ptr()
+                .cast::<u8>()
+                .add(offset as usize)
+                .cast()
+        };
+        unsafe {
+            std::ptr::copy_nonoverlapping(data.as_ptr(), dst, data.len());
+        }
+    }
+
+    // Only valid for host visible buffers; `offset` is in bytes
+    pub fn read_buffer<T: Copy>(&self, offset: u64, data: &mut [T]) {
+        assert!(offset + (std::mem::size_of_val(data) as u64) <= self.size, "Storage buffer read out of bounds!");
+
+        let src = unsafe {
+            self.allocation.mapped_ptr()
+                .expect("Storage buffer is not host visible!")
+                .as_ptr()
+                .cast::<u8>()
+                .add(offset as usize)
+                .cast()
+        };
+        unsafe {
+            std::ptr::copy_nonoverlapping(src, data.as_mut_ptr(), data.len());
+        }
+    }
+
+    pub fn get_buffer(&self) -> vk::Buffer { self.buffer }
+    pub fn get_size(&self) -> u64 { self.size }
+
+    pub fn descriptor_info(&self) -> vk::DescriptorBufferInfo {
+        vk::DescriptorBufferInfo {
+            buffer: self.buffer,
+            offset: 0,
+            range: self.size
+        }
+    }
+}
\ No newline at end of file
@@ -1,15 +1,40 @@
 use ash::vk;
+use gpu_allocator::vulkan::Allocator;
+
 use super::surface::VulkanSurface;
 use super::queue::*;
+use super::image::Image;
+use super::full_screen_exclusive::FullScreenExclusive;
+
+// Format of the offscreen images, the same the swapchain image views use
+pub const OFFSCREEN_FORMAT: vk::Format = vk::Format::B8G8R8A8_UNORM;
+
+// How the window system blends presented frames with what's behind the window
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum WindowAlpha {
+    #[default]
+    Opaque,
+    // Color is expected multiplied by alpha already
+    Premultiplied,
+    // The compositor multiplies color by alpha itself
+    Straight,
+}
 
+// The images frames end up in. Either a real swapchain presenting to a surface, or plain images without one (`offscreen`),
+// which the renderer leaves ready to be copied from instead of presenting them.
 pub struct VulkanSwapchain {
-    pub swapchain_loader: ash::extensions::khr::Swapchain,
+    // Only set for a real swapchain
+    pub swapchain_loader: Option<ash::extensions::khr::Swapchain>,
     pub swapchain: vk::SwapchainKHR,
     pub images: Vec<vk::Image>,
+    // Owns the images of an offscreen swapchain
+    pub offscreen_images: Vec<Image>,
     pub imageviews: Vec<vk::ImageView>,
     pub framebuffers: Vec<vk::Framebuffer>,
     pub surface_format: vk::SurfaceFormatKHR,
     pub extent: vk::Extent2D,
+    // Only anything but opaque when asked for a transparent swapchain the surface can blend
+    pub alpha: WindowAlpha,
     pub image_available: Vec<vk::Semaphore>,
     pub rendering_finished: Vec<vk::Semaphore>,
     pub may_begin_drawing: Vec<vk::Fence>,
@@ -18,18 +43,37 @@ pub struct VulkanSwapchain {
 }
 
 impl VulkanSwapchain {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         instance: &ash::Instance,
         physical_device: vk::PhysicalDevice,
         logical_device: &ash::Device,
         surface: &VulkanSurface,
         queue_families: &QueueFamilies,
+        vsync: bool,
+        transparent: bool,
+        window_extent: vk::Extent2D,
+        full_screen_exclusive: Option<&FullScreenExclusive>,
     ) -> Result<VulkanSwapchain, vk::Result> {
         let surface_capabilities = surface.get_capabilities(physical_device)?;
-        let extent = surface_capabilities.current_extent;
+        // FIFO syncs with the monitor refresh rate and is always available, without vsync frames are presented as soon as they're done
+        let present_modes = surface.get_present_modes(physical_device)?;
+        let present_mode = [vk::PresentModeKHR::IMMEDIATE, vk::PresentModeKHR::MAILBOX]
+            .into_iter()
+            .find(|mode| !vsync && present_modes.contains(mode))
+            .unwrap_or(vk::PresentModeKHR::FIFO);
+        // Wayland leaves the size to the swapchain (0xFFFFFFFF), it's whatever the window was last resized to
+        let extent = match surface_capabilities.current_extent.width {
+            u32::MAX => vk::Extent2D {
+                width: window_extent.width.clamp(surface_capabilities.min_image_extent.width, surface_capabilities.max_image_extent.width),
+                height: window_extent.height.clamp(surface_capabilities.min_image_extent.height, surface_capabilities.max_image_extent.height)
+            },
+            _ => surface_capabilities.current_extent
+        };
+        let (composite_alpha, alpha) = Self::pick_composite_alpha(surface_capabilities.supported_composite_alpha, transparent);
         let surface_format = *surface.get_formats(physical_device)?.first().unwrap();
         let queuefamilies = [queue_families.graphics.unwrap()];
-        let swapchain_create_info = vk::SwapchainCreateInfoKHR::builder()
+        let mut swapchain_create_info = vk::SwapchainCreateInfoKHR::builder()
             .surface(surface.surface)
             .min_image_count(3
                 .max(surface_capabilities.min_image_count)
@@ -43,11 +87,18 @@ impl VulkanSwapchain {
             .image_sharing_mode(vk::SharingMode::EXCLUSIVE)
             .queue_family_indices(&queuefamilies)
             .pre_transform(surface_capabilities.current_transform)
-            .composite_alpha(vk::CompositeAlphaFlagsKHR::OPAQUE)
-            .present_mode(vk::PresentModeKHR::FIFO); //Sync with monitor refresh rate
+            .composite_alpha(composite_alpha)
+            .present_mode(present_mode);
+        let mut exclusive_info = full_screen_exclusive.and_then(FullScreenExclusive::create_info);
+        if let Some((exclusive, win32)) = &mut exclusive_info {
+            swapchain_create_info = swapchain_create_info.push_next(exclusive).push_next(win32);
+        }
         
         let swapchain_loader = ash::extensions::khr::Swapchain::new(instance, logical_device);
         let swapchain = unsafe { swapchain_loader.create_swapchain(&swapchain_create_info, None)? };
+        if let Some(full_screen_exclusive) = full_screen_exclusive {
+            full_screen_exclusive.acquire(swapchain);
+        }
         let swapchain_images = unsafe { swapchain_loader.get_swapchain_images(swapchain)? };
         let image_count = swapchain_images.len();
         let mut swapchain_imageviews = Vec::with_capacity(swapchain_images.len());
@@ -69,28 +120,50 @@ impl VulkanSwapchain {
             swapchain_imageviews.push(imageview);
         }
 
-        let mut image_available = vec![];
-        let mut rendering_finished = vec![];
-        let mut may_begin_drawing = vec![];
-        let semaphore_info = vk::SemaphoreCreateInfo::builder();
-        let fence_info = vk::FenceCreateInfo::builder().flags(vk::FenceCreateFlags::SIGNALED);
-        for _ in 0..image_count {
-            let semaphore_available = unsafe { logical_device.create_semaphore(&semaphore_info, None)? };
-            let semaphore_finished = unsafe { logical_device.create_semaphore(&semaphore_info, None)? };
-            image_available.push(semaphore_available);
-            rendering_finished.push(semaphore_finished);
-            let fence = unsafe { logical_device.create_fence(&fence_info, None)? };
-            may_begin_drawing.push(fence);
-        }
+        let (image_available, rendering_finished, may_begin_drawing) = Self::create_sync_objects(logical_device, image_count)?;
 
         Ok(VulkanSwapchain {
-            swapchain_loader,
+            swapchain_loader: Some(swapchain_loader),
             swapchain,
             images: swapchain_images,
+            offscreen_images: vec![],
             imageviews: swapchain_imageviews,
             framebuffers: vec![],
             surface_format,
             extent,
+            alpha,
+            image_count,
+            current_image: 0,
+            image_available,
+            rendering_finished,
+            may_begin_drawing
+        })
+    }
+
+    pub fn offscreen(logical_device: &ash::Device, allocator: &mut Allocator, extent: vk::Extent2D, image_count: usize
+    ) -> Result<VulkanSwapchain, vk::Result> {
+        let mut offscreen_images = Vec::with_capacity(image_count);
+        for index in 0..image_count {
+            offscreen_images.push(Image::new(logical_device, allocator, extent, OFFSCREEN_FORMAT,
+                vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC, vk::ImageAspectFlags::COLOR,
+                &format!("Offscreen Image {}", index))?);
+        }
+
+        let (image_available, rendering_finished, may_begin_drawing) = Self::create_sync_objects(logical_device, image_count)?;
+
+        Ok(VulkanSwapchain {
+            swapchain_loader: None,
+            swapchain: vk::SwapchainKHR::null(),
+            images: offscreen_images.iter().map(|image| image.image).collect(),
+            imageviews: offscreen_images.iter().map(|image| image.view).collect(),
+            offscreen_images,
+            framebuffers: vec![],
+            surface_format: vk::SurfaceFormatKHR {
+                format: OFFSCREEN_FORMAT,
+                color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR
+            },
+            extent,
+            alpha: WindowAlpha::Opaque,
             image_count,
             current_image: 0,
             image_available,
@@ -99,6 +172,43 @@ impl VulkanSwapchain {
         })
     }
 
+    // Premultiplied is preferred since it's what the UI blends to. Inherit leaves it to the platform, which is
+    // premultiplied on Wayland and Android. Without any of those the swapchain stays opaque.
+    fn pick_composite_alpha(supported: vk::CompositeAlphaFlagsKHR, transparent: bool) -> (vk::CompositeAlphaFlagsKHR, WindowAlpha) {
+        let blending = [
+            (vk::CompositeAlphaFlagsKHR::PRE_MULTIPLIED, WindowAlpha::Premultiplied),
+            (vk::CompositeAlphaFlagsKHR::POST_MULTIPLIED, WindowAlpha::Straight),
+            (vk::CompositeAlphaFlagsKHR::INHERIT, WindowAlpha::Premultiplied)
+        ];
+        blending.into_iter()
+            .find(|(flag, _)| transparent && supported.contains(*flag))
+            .unwrap_or((vk::CompositeAlphaFlagsKHR::OPAQUE, WindowAlpha::Opaque))
+    }
+
+    pub fn is_offscreen(&self) -> bool {
+        self.swapchain_loader.is_none()
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn create_sync_objects(logical_device: &ash::Device, image_count: usize
+    ) -> Result<(Vec<vk::Semaphore>, Vec<vk::Semaphore>, Vec<vk::Fence>), vk::Result> {
+        let mut image_available = vec![];
+        let mut rendering_finished = vec![];
+        let mut may_begin_drawing = vec![];
+        let semaphore_info = vk::SemaphoreCreateInfo::builder();
+        let fence_info = vk::FenceCreateInfo::builder().flags(vk::FenceCreateFlags::SIGNALED);
+        for _ in 0..image_count {
+            let semaphore_available = unsafe { logical_device.create_semaphore(&semaphore_info, None)? };
+            let semaphore_finished = unsafe { logical_device.create_semaphore(&semaphore_info, None)? };
+            image_available.push(semaphore_available);
+            rendering_finished.push(semaphore_finished);
+            let fence = unsafe { logical_device.create_fence(&fence_info, None)? };
+            may_begin_drawing.push(fence);
+        }
+
+        Ok((image_available, rendering_finished, may_begin_drawing))
+    }
+
     pub fn create_framebuffers(&mut self, logical_device: &ash::Device, renderpass: vk::RenderPass) -> Result<(), vk::Result> {
         let width = self.extent.width;
         let height = self.extent.height;
@@ -118,7 +228,31 @@ impl VulkanSwapchain {
         Ok(())
     }
 
-    pub unsafe fn cleanup(&mut self, logical_device: &ash::Device) {
+    /// Destroys what depends on the surface before the surface goes away, the sync objects stay. `cleanup` is still needed
+    /// afterwards, the images are gone until the swapchain is created anew.
+    ///
+    /// # Safety
+    ///
+    /// `logical_device` has to be the device the swapchain was created with, and it must be idle: no submitted work may
+    /// still use the framebuffers, image views or swapchain images.
+    pub unsafe fn release_surface(&mut self, logical_device: &ash::Device) {
+        let swapchain_loader = match &self.swapchain_loader {
+            Some(swapchain_loader) => swapchain_loader,
+            None => return
+        };
+
+        for fb in self.framebuffers.drain(..) {
+            logical_device.destroy_framebuffer(fb, None);
+        }
+        for iv in self.imageviews.drain(..) {
+            logical_device.destroy_image_view(iv, None);
+        }
+        self.images.clear();
+        swapchain_loader.destroy_swapchain(self.swapchain, None);
+        self.swapchain = vk::SwapchainKHR::null();
+    }
+
+    pub unsafe fn cleanup(&mut self, logical_device: &ash::Device, allocator: &mut Allocator) {
         for fence in &self.may_begin_drawing {
             logical_device.destroy_fence(*fence, None);
         }
@@ -131,10 +265,20 @@ impl VulkanSwapchain {
         for fb in &self.framebuffers {
             logical_device.destroy_framebuffer(*fb, None);
         }
-        for iv in &self.imageviews {
-            logical_device.destroy_image_view(*iv, None);
-        }
 
-        self.swapchain_loader.destroy_swapchain(self.swapchain, None);
+        match &self.swapchain_loader {
+            Some(swapchain_loader) => {
+                for iv in &self.imageviews {
+                    logical_device.destroy_image_view(*iv, None);
+                }
+                swapchain_loader.destroy_swapchain(self.swapchain, None);
+            }
+            // The views belong to the images here
+            None => {
+                for image in &mut self.offscreen_images {
+                    image.destroy(logical_device, allocator);
+                }
+            }
+        }
     }
 }
\ No newline at end of file
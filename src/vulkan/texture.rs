@@ -0,0 +1,228 @@
+use ash::vk;
+use gpu_allocator::vulkan::Allocator;
+
+use super::command_pools::Pools;
+use super::image::{Image, create_sampler};
+use super::staging_buffer::StagingBuffer;
+
+pub struct Texture {
+    pub image: Image,
+    pub sampler: vk::Sampler,
+}
+
+impl Texture {
+    pub fn from_rgba8(
+        device: &ash::Device,
+        allocator: &mut Allocator,
+        pools: &Pools,
+        queue: vk::Queue,
+        extent: vk::Extent2D,
+        data: &[u8],
+        name: &str,
+    ) -> Result<Self, vk::Result> {
+        Self::from_rgba8_layers(device, allocator, pools, queue, extent, &[data], vk::ImageViewType::TYPE_2D, name)
+    }
+
+    // Sampled as a sampler2DArray, e.g. terrain splats or decal atlases
+    pub fn from_rgba8_array(
+        device: &ash::Device,
+        allocator: &mut Allocator,
+        pools: &Pools,
+        queue: vk::Queue,
+        extent: vk::Extent2D,
+        layers: &[&[u8]],
+        name: &str,
+    ) -> Result<Self, vk::Result> {
+        Self::from_rgba8_layers(device, allocator, pools, queue, extent, layers, vk::ImageViewType::TYPE_2D_ARRAY, name)
+    }
+
+    // Array of `layer_count` transparent black layers, to be filled in later with `update_layers`
+    pub fn new_rgba8_array(
+        device: &ash::Device,
+        allocator: &mut Allocator,
+        pools: &Pools,
+        queue: vk::Queue,
+        extent: vk::Extent2D,
+        layer_count: u32,
+        name: &str,
+    ) -> Result<Self, vk::Result> {
+        let image = Image::new_layered(device, allocator, extent, vk::Format::R8G8B8A8_UNORM,
+            vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST, vk::ImageAspectFlags::COLOR,
+            layer_count, vk::ImageViewType::TYPE_2D_ARRAY, name)?;
+
+        pools.one_time_submit(device, queue, |command_buffer| {
+            image.transition_layout(device, command_buffer, vk::ImageLayout::UNDEFINED, vk::ImageLayout::TRANSFER_DST_OPTIMAL);
+            let clear_color = vk::ClearColorValue { float32: [0.0; 4] };
+            unsafe {
+                device.cmd_clear_color_image(command_buffer, image.image, vk::ImageLayout::TRANSFER_DST_OPTIMAL, &clear_color, &[image.subresource_range()]);
+            }
+            image.transition_layout(device, command_buffer, vk::ImageLayout::TRANSFER_DST_OPTIMAL, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+        })?;
+
+        let sampler = create_sampler(device, vk::Filter::LINEAR, vk::SamplerAddressMode::CLAMP_TO_EDGE)?;
+
+        Ok(Self {
+            image,
+            sampler
+        })
+    }
+
+    // Every layer is tightly packed RGBA8 of the same extent
+    #[allow(clippy::too_many_arguments)]
+    pub fn from_rgba8_layers(
+        device: &ash::Device,
+        allocator: &mut Allocator,
+        pools: &Pools,
+        queue: vk::Queue,
+        extent: vk::Extent2D,
+        layers: &[&[u8]],
+        view_type: vk::ImageViewType,
+        name: &str,
+    ) -> Result<Self, vk::Result> {
+        let layer_size = (extent.width * extent.height * 4) as usize;
+        for layer in layers {
+            assert_eq!(layer.len(), layer_size, "Texture layer has the wrong size for its extent!");
+        }
+
+        let image = Image::new_layered(device, allocator, extent, vk::Format::R8G8B8A8_UNORM,
+            vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST, vk::ImageAspectFlags::COLOR,
+            layers.len() as u32, view_type, name)?;
+
+        let data: Vec<u8> = layers.concat();
+        Self::upload(device, allocator, pools, queue, &image, &data)?;
+
+        let sampler = create_sampler(device, vk::Filter::LINEAR, vk::SamplerAddressMode::CLAMP_TO_EDGE)?;
+
+        Ok(Self {
+            image,
+            sampler
+        })
+    }
+
+    // Tightly packed RGBA float texels, e.g. baked lighting that doesn't fit into 8 bits
+    pub fn from_rgba32f(
+        device: &ash::Device,
+        allocator: &mut Allocator,
+        pools: &Pools,
+        queue: vk::Queue,
+        extent: vk::Extent2D,
+        data: &[f32],
+        name: &str,
+    ) -> Result<Self, vk::Result> {
+        assert_eq!(data.len(), (extent.width * extent.height * 4) as usize, "Texture data has the wrong size for its extent!");
+
+        let image = Image::new(device, allocator, extent, vk::Format::R32G32B32A32_SFLOAT,
+            vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST, vk::ImageAspectFlags::COLOR, name)?;
+
+        let bytes: Vec<u8> = data.iter().flat_map(|value| value.to_ne_bytes()).collect();
+        Self::upload(device, allocator, pools, queue, &image, &bytes)?;
+
+        let sampler = create_sampler(device, vk::Filter::LINEAR, vk::SamplerAddressMode::CLAMP_TO_EDGE)?;
+
+        Ok(Self {
+            image,
+            sampler
+        })
+    }
+
+    // `data` holds size³ RGBA texels, red varying fastest
+    pub fn from_rgba32f_3d(
+        device: &ash::Device,
+        allocator: &mut Allocator,
+        pools: &Pools,
+        queue: vk::Queue,
+        size: u32,
+        data: &[f32],
+        name: &str,
+    ) -> Result<Self, vk::Result> {
+        assert_eq!(data.len(), (size * size * size * 4) as usize, "3D texture data has the wrong size!");
+
+        let image = Image::new_3d(device, allocator, size, vk::Format::R32G32B32A32_SFLOAT,
+            vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST, name)?;
+
+        let bytes: Vec<u8> = data.iter().flat_map(|value| value.to_ne_bytes()).collect();
+        Self::upload(device, allocator, pools, queue, &image, &bytes)?;
+
+        let sampler = create_sampler(device, vk::Filter::LINEAR, vk::SamplerAddressMode::CLAMP_TO_EDGE)?;
+
+        Ok(Self {
+            image,
+            sampler
+        })
+    }
+
+    // Replaces layers `first_layer..` of an RGBA8 array with tightly packed texels, the other layers stay as they are.
+    // The device must not be sampling the texture while it's updated.
+    #[allow(clippy::too_many_arguments)]
+    pub fn update_layers(&self, device: &ash::Device, allocator: &mut Allocator, pools: &Pools, queue: vk::Queue, first_layer: u32,
+        layers: &[&[u8]]
+    ) -> Result<(), vk::Result> {
+        assert_eq!(self.image.format, vk::Format::R8G8B8A8_UNORM, "Only RGBA8 textures can be updated by layer!");
+        assert!(first_layer as usize + layers.len() <= self.image.layers as usize, "Texture layer update is out of range!");
+        let layer_size = (self.image.extent.width * self.image.extent.height * 4) as usize;
+        for layer in layers {
+            assert_eq!(layer.len(), layer_size, "Texture layer has the wrong size for its extent!");
+        }
+
+        let data: Vec<u8> = layers.concat();
+        Self::upload_layers(device, allocator, pools, queue, &self.image, first_layer, layers.len() as u32,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL, &data)
+    }
+
+    // Copies tightly packed texels covering every layer (or every slice of a 3D image) and leaves the image ready for sampling
+    fn upload(device: &ash::Device, allocator: &mut Allocator, pools: &Pools, queue: vk::Queue, image: &Image, data: &[u8]) -> Result<(), vk::Result> {
+        Self::upload_layers(device, allocator, pools, queue, image, 0, image.layers, vk::ImageLayout::UNDEFINED, data)
+    }
+
+    // `old_layout` is the layout the layers are in now, UNDEFINED when their contents don't matter
+    #[allow(clippy::too_many_arguments)]
+    fn upload_layers(device: &ash::Device, allocator: &mut Allocator, pools: &Pools, queue: vk::Queue, image: &Image, first_layer: u32,
+        layer_count: u32, old_layout: vk::ImageLayout, data: &[u8]
+    ) -> Result<(), vk::Result> {
+        let mut staging_buffer = StagingBuffer::new(device, allocator, data.len() as u64);
+        staging_buffer.update_buffer(0, data);
+
+        let result = pools.one_time_submit(device, queue, |command_buffer| {
+            image.transition_layers(device, command_buffer, first_layer, layer_count, old_layout, vk::ImageLayout::TRANSFER_DST_OPTIMAL);
+
+            let regions = [vk::BufferImageCopy::builder()
+                .buffer_offset(0)
+                .image_subresource(vk::ImageSubresourceLayers {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    mip_level: 0,
+                    base_array_layer: first_layer,
+                    layer_count
+                })
+                .image_extent(vk::Extent3D {
+                    width: image.extent.width,
+                    height: image.extent.height,
+                    depth: image.depth
+                })
+                .build()
+            ];
+            unsafe {
+                device.cmd_copy_buffer_to_image(command_buffer, staging_buffer.get_buffer(), image.image,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL, &regions);
+            }
+
+            image.transition_layers(device, command_buffer, first_layer, layer_count, vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+        });
+
+        staging_buffer.destroy(device, allocator);
+        result
+    }
+
+    pub fn descriptor_info(&self) -> vk::DescriptorImageInfo {
+        vk::DescriptorImageInfo {
+            sampler: self.sampler,
+            image_view: self.image.view,
+            image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
+        }
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device, allocator: &mut Allocator) {
+        unsafe { device.destroy_sampler(self.sampler, None) };
+        self.image.destroy(device, allocator);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,564 @@
+// Streams the mip levels of textures in and out, so a large world doesn't need every texture resident at full resolution.
+// A streamed texture keeps its whole mip chain in system memory. Its image has room for the levels from `allocated` down
+// and is sampled from `clamp` down, the base level of its view, which is what clamps the LOD the shaders can pick.
+// Finer levels go up on the transfer queue one at a time and are sampled once their upload's fence has signaled.
+// How fine a texture should be follows from how large the objects using it are on screen, coarsened where the textures
+// together would exceed the budget.
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::ops::Range;
+
+use ash::vk;
+use gpu_allocator::vulkan::Allocator;
+
+use super::command_pools::Pools;
+use super::descriptor_allocator::DescriptorAllocator;
+use super::game_object::GameObject;
+use super::image::{Image, create_sampler};
+use super::material::{MaterialSets, TextureSlot};
+use super::mesh::LodView;
+use super::queue::QueueFamilies;
+use super::staging_buffer::StagingBuffer;
+use super::texture::Texture;
+
+// Levels this size or smaller are always resident, so there's something to sample from the start
+const MIN_RESIDENT_SIZE: u32 = 64;
+
+#[derive(Clone, Copy, Debug)]
+pub struct StreamingSettings {
+    // Device memory of all streamed textures together, in bytes
+    pub budget: u64,
+    // Bytes uploaded per frame at most, a single level larger than that still goes up on its own
+    pub upload_per_frame: u64,
+    // Added to the level every texture wants, above 0 trades sharpness for memory
+    pub lod_bias: f32,
+}
+
+impl Default for StreamingSettings {
+    fn default() -> Self {
+        Self {
+            budget: 512 << 20,
+            upload_per_frame: 16 << 20,
+            lod_bias: 0.0
+        }
+    }
+}
+
+// A texture and every level below it, box filtered in whatever space the texels are stored in. Levels hold RGBA8 texels
+// or, in a block compressed format, the blocks covering them.
+#[derive(Clone, Debug)]
+pub struct MipChain {
+    pub extent: vk::Extent2D,
+    pub format: vk::Format,
+    pub levels: Vec<Vec<u8>>,
+}
+
+impl MipChain {
+    pub fn from_rgba8(extent: vk::Extent2D, rgba: &[u8]) -> Self {
+        assert_eq!(rgba.len(), (extent.width * extent.height * 4) as usize, "Texture data has the wrong size for its extent!");
+
+        let mut levels = vec![rgba.to_vec()];
+        let mut size = extent;
+        while size.width > 1 || size.height > 1 {
+            let next = mip_extent(size, 1);
+            levels.push(downsample(&levels[levels.len() - 1], size, next));
+            size = next;
+        }
+
+        Self {
+            extent,
+            format: vk::Format::R8G8B8A8_UNORM,
+            levels
+        }
+    }
+
+    // Levels already in `format`, down to 1x1, e.g. compressed by `assets::compress`
+    pub fn from_levels(extent: vk::Extent2D, format: vk::Format, levels: Vec<Vec<u8>>) -> Self {
+        assert_eq!(levels.len() as u32, extent.width.max(extent.height).ilog2() + 1, "Mip chain has the wrong number of levels!");
+
+        Self {
+            extent,
+            format,
+            levels
+        }
+    }
+
+    pub fn level_count(&self) -> u32 {
+        self.levels.len() as u32
+    }
+
+    pub fn level_extent(&self, level: u32) -> vk::Extent2D {
+        mip_extent(self.extent, level)
+    }
+
+    // Of the levels from `level` down
+    fn bytes_from(&self, level: u32) -> u64 {
+        self.levels[level as usize..].iter().map(|texels| texels.len() as u64).sum()
+    }
+
+    // Finest level that's always resident
+    fn resident_level(&self) -> u32 {
+        (0..self.level_count())
+            .find(|&level| {
+                let extent = self.level_extent(level);
+                extent.width.max(extent.height) <= MIN_RESIDENT_SIZE
+            })
+            .unwrap_or(self.level_count() - 1)
+    }
+
+    // Level sampled about once per pixel when the texture covers `pixels` across the screen
+    fn wanted_level(&self, pixels: f32, lod_bias: f32) -> u32 {
+        let size = self.extent.width.max(self.extent.height) as f32;
+        let level = (size / pixels).log2() + lod_bias;
+        (level.max(0.0) as u32).min(self.resident_level())
+    }
+
+    // Screen pixels per texel of `level`, the lower the less coarsening it costs
+    fn density(&self, level: u32, pixels: f32) -> f32 {
+        let extent = self.level_extent(level);
+        pixels / extent.width.max(extent.height) as f32
+    }
+}
+
+fn mip_extent(extent: vk::Extent2D, level: u32) -> vk::Extent2D {
+    vk::Extent2D {
+        width: (extent.width >> level).max(1),
+        height: (extent.height >> level).max(1)
+    }
+}
+
+// Averages 2x2 blocks, sides of odd length repeat their last texel
+fn downsample(texels: &[u8], extent: vk::Extent2D, half: vk::Extent2D) -> Vec<u8> {
+    let mut result = Vec::with_capacity((half.width * half.height * 4) as usize);
+    for y in 0..half.height {
+        for x in 0..half.width {
+            let columns = [(x * 2).min(extent.width - 1), (x * 2 + 1).min(extent.width - 1)];
+            let rows = [(y * 2).min(extent.height - 1), (y * 2 + 1).min(extent.height - 1)];
+            for channel in 0..4 {
+                let sum: u32 = rows
+                    .iter()
+                    .flat_map(|&row| columns.iter().map(move |&column| texels[((row * extent.width + column) * 4 + channel) as usize] as u32))
+                    .sum();
+                result.push(((sum + 2) / 4) as u8);
+            }
+        }
+    }
+    result
+}
+
+pub struct StreamedTexture {
+    chain: MipChain,
+    // Chain level of the image's level 0
+    allocated: u32,
+    // Levels from here down hold their texels
+    uploaded: u32,
+    // Levels from here down are sampled
+    clamp: u32,
+    upload: Option<Upload>,
+}
+
+// Levels of the chain on their way to the device, into a new image with room from chain level `allocated` down when
+// `texture` is there and into the texture's own image otherwise
+struct Upload {
+    level: u32,
+    texture: Option<(Texture, u32)>,
+    staging: StagingBuffer,
+    command_buffer: vk::CommandBuffer,
+    fence: vk::Fence,
+}
+
+impl Upload {
+    fn release(&mut self, device: &ash::Device, allocator: &mut Allocator, pools: &Pools) {
+        self.staging.destroy(device, allocator);
+        unsafe {
+            device.free_command_buffers(pools.transfer_command_pool, &[self.command_buffer]);
+            device.destroy_fence(self.fence, None);
+        }
+    }
+}
+
+// What a frame recorded before the texture changed may still use
+struct Retired {
+    frame: u64,
+    texture: Option<Texture>,
+    view: Option<vk::ImageView>,
+    sets: Vec<vk::DescriptorSet>,
+}
+
+pub struct TextureStreamer {
+    pub settings: StreamingSettings,
+    textures: HashMap<TextureSlot, StreamedTexture>,
+    retired: Vec<Retired>,
+    // The graphics and transfer families when they differ, the levels of an image are uploaded while it's sampled
+    queue_families: Vec<u32>,
+}
+
+impl TextureStreamer {
+    pub fn new(settings: StreamingSettings, queue_families: &QueueFamilies) -> Self {
+        let mut families: Vec<u32> = queue_families.graphics.into_iter().chain(queue_families.transfer).collect();
+        families.dedup();
+
+        Self {
+            settings,
+            textures: HashMap::new(),
+            retired: Vec::new(),
+            queue_families: families
+        }
+    }
+
+    // Uploads the levels that are always resident on `queue`, a transfer queue, and waits for them. The texture goes to
+    // `MaterialSets`, the returned state to `insert` with the slot it got there.
+    pub fn create(&self, device: &ash::Device, allocator: &mut Allocator, pools: &Pools, queue: vk::Queue, chain: MipChain
+    ) -> Result<(Texture, StreamedTexture), vk::Result> {
+        let level = chain.resident_level();
+        let texture = create_texture(device, allocator, &self.queue_families, &chain, level)?;
+        let (staging, command_buffer, fence) = submit_levels(device, allocator, pools, queue, &chain, &texture.image, level,
+            level..chain.level_count())?;
+        let mut upload = Upload { level, texture: None, staging, command_buffer, fence };
+        let result = unsafe { device.wait_for_fences(&[fence], true, u64::MAX) };
+        upload.release(device, allocator, pools);
+        result?;
+
+        Ok((texture, StreamedTexture {
+            chain,
+            allocated: level,
+            uploaded: level,
+            clamp: level,
+            upload: None
+        }))
+    }
+
+    pub fn insert(&mut self, slot: TextureSlot, texture: StreamedTexture) {
+        self.textures.insert(slot, texture);
+    }
+
+    // Stops streaming the slot, before `MaterialSets` destroys its texture. Waits for an upload still going on.
+    pub fn remove(&mut self, device: &ash::Device, allocator: &mut Allocator, pools: &Pools, slot: TextureSlot) -> Result<(), vk::Result> {
+        if let Some(mut upload) = self.textures.remove(&slot).and_then(|streamed| streamed.upload) {
+            let result = unsafe { device.wait_for_fences(&[upload.fence], true, u64::MAX) };
+            upload.release(device, allocator, pools);
+            if let Some((mut texture, _)) = upload.texture.take() {
+                texture.destroy(device, allocator);
+            }
+            result?;
+        }
+        Ok(())
+    }
+
+    // Device memory the streamed textures take right now
+    pub fn resident_bytes(&self) -> u64 {
+        self.textures.values().map(|streamed| streamed.chain.bytes_from(streamed.allocated)).sum()
+    }
+
+    // Swaps in finished uploads and starts the next ones on `queue`, a transfer queue. Sets sampling a changed texture are
+    // detached from `materials`, so run this before their `update`. Textures of scatter `prototypes` stay at full resolution,
+    // their instances are all over the place.
+    #[allow(clippy::too_many_arguments)]
+    pub fn update<'a>(&mut self, device: &ash::Device, allocator: &mut Allocator, pools: &Pools, queue: vk::Queue,
+        materials: &mut MaterialSets, descriptors: &mut DescriptorAllocator, game_objects: &[GameObject], models: &[uv::Mat4],
+        prototypes: impl IntoIterator<Item = &'a GameObject>, view: &LodView, frame_index: u64, frames_in_flight: u64
+    ) -> Result<(), vk::Result> {
+        crate::profile_scope!("Texture Streaming");
+        let (expired, retired): (Vec<Retired>, Vec<Retired>) = std::mem::take(&mut self.retired)
+            .into_iter()
+            .partition(|retired| retired.frame + frames_in_flight < frame_index);
+        self.retired = retired;
+        for mut retired in expired {
+            for set in retired.sets {
+                descriptors.free(device, set)?;
+            }
+            if let Some(view) = retired.view {
+                unsafe { device.destroy_image_view(view, None) };
+            }
+            if let Some(texture) = &mut retired.texture {
+                texture.destroy(device, allocator);
+            }
+        }
+
+        for (&slot, streamed) in &mut self.textures {
+            let finished = match &streamed.upload {
+                Some(upload) => unsafe { device.get_fence_status(upload.fence)? },
+                None => false
+            };
+            if !finished {
+                continue;
+            }
+
+            let mut upload = streamed.upload.take().expect("Finished upload went missing!");
+            upload.release(device, allocator, pools);
+            if let Some((texture, allocated)) = upload.texture.take() {
+                let old = materials.replace_texture(slot, texture);
+                self.retired.push(Retired { frame: frame_index, texture: old, view: None, sets: Vec::new() });
+                streamed.allocated = allocated;
+            }
+            streamed.uploaded = upload.level;
+            self.retired.push(clamp_view(device, materials, slot, streamed, upload.level, frame_index)?);
+        }
+
+        let wanted = self.wanted_levels(game_objects, models, prototypes, view);
+        let over_budget = self.resident_bytes() > self.settings.budget;
+        let mut uploaded = 0;
+        for (slot, level) in wanted {
+            let streamed = self.textures.get_mut(&slot).expect("Wanted level of a texture that isn't streamed!");
+            if streamed.upload.is_some() {
+                continue;
+            }
+
+            if level > streamed.clamp || (level < streamed.clamp && streamed.uploaded < streamed.clamp) {
+                // Coarser, or finer levels that are still in the image from before, only the view changes
+                let clamp = level.max(streamed.uploaded);
+                self.retired.push(clamp_view(device, materials, slot, streamed, clamp, frame_index)?);
+            } else if level < streamed.clamp {
+                let next = streamed.clamp - 1;
+                let grows = next < streamed.allocated;
+                let bytes = match grows {
+                    true => streamed.chain.bytes_from(next),
+                    false => streamed.chain.levels[next as usize].len() as u64
+                };
+                if uploaded > 0 && uploaded + bytes > self.settings.upload_per_frame {
+                    continue;
+                }
+                uploaded += bytes;
+
+                // A new image gets room for every level wanted, so the next ones go straight into it
+                streamed.upload = Some(match grows {
+                    true => start_image_upload(device, allocator, pools, queue, &self.queue_families, &streamed.chain, level, next)?,
+                    false => {
+                        let image = &materials.texture_mut(slot).expect("Streamed texture isn't in the material sets!").image;
+                        let (staging, command_buffer, fence) = submit_levels(device, allocator, pools, queue, &streamed.chain, image,
+                            streamed.allocated, next..streamed.clamp)?;
+                        Upload { level: next, texture: None, staging, command_buffer, fence }
+                    }
+                });
+                continue;
+            }
+
+            // Memory of levels nobody wants is given back once it's more than one level, or right away over budget
+            let unused = level.saturating_sub(streamed.allocated);
+            if streamed.clamp == level && (unused > 1 || (unused > 0 && over_budget)) {
+                let bytes = streamed.chain.bytes_from(level);
+                if uploaded > 0 && uploaded + bytes > self.settings.upload_per_frame {
+                    continue;
+                }
+                uploaded += bytes;
+                streamed.upload = Some(start_image_upload(device, allocator, pools, queue, &self.queue_families, &streamed.chain, level,
+                    level)?);
+            }
+        }
+
+        Ok(())
+    }
+
+    // The level each streamed texture should be sampled from, largest on screen first. Textures nobody draws only keep
+    // what's always resident.
+    fn wanted_levels<'a>(&self, game_objects: &[GameObject], models: &[uv::Mat4], prototypes: impl IntoIterator<Item = &'a GameObject>,
+        view: &LodView
+    ) -> Vec<(TextureSlot, u32)> {
+        let mut pixels: HashMap<TextureSlot, f32> = self.textures.keys().map(|&slot| (slot, 0.0)).collect();
+        let mut cover = |game_object: &GameObject, size: f32| {
+            let slots = game_object.texture.map(TextureSlot::Color).into_iter().chain(game_object.normal_map.map(TextureSlot::NormalMap));
+            for slot in slots {
+                if let Some(pixels) = pixels.get_mut(&slot) {
+                    *pixels = pixels.max(size);
+                }
+            }
+        };
+        for (game_object, &model) in game_objects.iter().zip(models) {
+            cover(game_object, screen_size(game_object, model, view));
+        }
+        for prototype in prototypes {
+            cover(prototype, f32::INFINITY);
+        }
+
+        let mut wanted: Vec<(TextureSlot, u32, f32)> = pixels
+            .into_iter()
+            .map(|(slot, pixels)| (slot, self.textures[&slot].chain.wanted_level(pixels, self.settings.lod_bias), pixels))
+            .collect();
+
+        // Over budget the texture whose texels are smallest on screen gets a level coarser, until everything fits
+        let mut total: u64 = wanted.iter().map(|(slot, level, _)| self.textures[slot].chain.bytes_from(*level)).sum();
+        let mut candidates: BinaryHeap<Reverse<(u32, usize)>> = wanted
+            .iter()
+            .enumerate()
+            .filter(|(_, (slot, level, _))| *level < self.textures[slot].chain.resident_level())
+            .map(|(index, (slot, level, pixels))| Reverse((self.textures[slot].chain.density(*level, *pixels).to_bits(), index)))
+            .collect();
+        while total > self.settings.budget {
+            let index = match candidates.pop() {
+                Some(Reverse((_, index))) => index,
+                None => break
+            };
+            let chain = &self.textures[&wanted[index].0].chain;
+            let (_, level, pixels) = &mut wanted[index];
+            total -= chain.levels[*level as usize].len() as u64;
+            *level += 1;
+            if *level < chain.resident_level() {
+                candidates.push(Reverse((chain.density(*level, *pixels).to_bits(), index)));
+            }
+        }
+
+        wanted.sort_by(|a, b| b.2.total_cmp(&a.2));
+        wanted.into_iter().map(|(slot, level, _)| (slot, level)).collect()
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device, allocator: &mut Allocator, pools: &Pools) {
+        // The device is idle, the sets go away with the allocator's pools
+        for (_, mut streamed) in self.textures.drain() {
+            if let Some(mut upload) = streamed.upload.take() {
+                upload.release(device, allocator, pools);
+                if let Some((mut texture, _)) = upload.texture.take() {
+                    texture.destroy(device, allocator);
+                }
+            }
+        }
+        for mut retired in self.retired.drain(..) {
+            if let Some(view) = retired.view {
+                unsafe { device.destroy_image_view(view, None) };
+            }
+            if let Some(texture) = &mut retired.texture {
+                texture.destroy(device, allocator);
+            }
+        }
+    }
+}
+
+fn create_texture(device: &ash::Device, allocator: &mut Allocator, queue_families: &[u32], chain: &MipChain, allocated: u32
+) -> Result<Texture, vk::Result> {
+    let image = Image::new_mipmapped(device, allocator, chain.level_extent(allocated), chain.format,
+        vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST, chain.level_count() - allocated, queue_families,
+        "Streamed Texture")?;
+    let sampler = create_sampler(device, vk::Filter::LINEAR, vk::SamplerAddressMode::CLAMP_TO_EDGE)?;
+
+    Ok(Texture {
+        image,
+        sampler
+    })
+}
+
+// Levels `level..` into a new image with room from `allocated` down
+#[allow(clippy::too_many_arguments)]
+fn start_image_upload(device: &ash::Device, allocator: &mut Allocator, pools: &Pools, queue: vk::Queue, queue_families: &[u32],
+    chain: &MipChain, allocated: u32, level: u32
+) -> Result<Upload, vk::Result> {
+    let texture = create_texture(device, allocator, queue_families, chain, allocated)?;
+    let (staging, command_buffer, fence) = submit_levels(device, allocator, pools, queue, chain, &texture.image, allocated,
+        level..chain.level_count())?;
+    Ok(Upload { level, texture: Some((texture, allocated)), staging, command_buffer, fence })
+}
+
+// Pixels across the object's bounds on screen, infinite from inside them
+fn screen_size(game_object: &GameObject, model: uv::Mat4, view: &LodView) -> f32 {
+    let bounds = match game_object.mesh.bounds {
+        Some(bounds) => bounds.transformed(model),
+        None => return f32::INFINITY
+    };
+    let distance = (bounds.center - view.position).mag() - bounds.radius;
+    match distance > 0.0 {
+        true => 2.0 * bounds.radius * view.pixels_per_unit / distance,
+        false => f32::INFINITY
+    }
+}
+
+// Points the view of the slot's texture at chain levels `clamp..` and detaches the sets sampling the old view
+fn clamp_view(device: &ash::Device, materials: &mut MaterialSets, slot: TextureSlot, streamed: &mut StreamedTexture, clamp: u32, frame: u64
+) -> Result<Retired, vk::Result> {
+    let texture = materials.texture_mut(slot).expect("Streamed texture isn't in the material sets!");
+    let view = texture.image.create_mips_view(device, vk::ImageViewType::TYPE_2D, clamp - streamed.allocated)?;
+    let old_view = std::mem::replace(&mut texture.image.view, view);
+    streamed.clamp = clamp;
+
+    Ok(Retired {
+        frame,
+        texture: None,
+        view: Some(old_view),
+        sets: materials.detach_sets(slot)
+    })
+}
+
+// Records copying chain `levels` into `image`, whose level 0 is chain level `allocated`, and submits it. The levels are
+// left ready for sampling once the returned fence signals, transfer queues know no shader stages to hand them over to.
+#[allow(clippy::too_many_arguments)]
+fn submit_levels(device: &ash::Device, allocator: &mut Allocator, pools: &Pools, queue: vk::Queue, chain: &MipChain, image: &Image,
+    allocated: u32, levels: Range<u32>
+) -> Result<(StagingBuffer, vk::CommandBuffer, vk::Fence), vk::Result> {
+    let data: Vec<u8> = chain.levels[levels.start as usize..levels.end as usize].concat();
+    let mut staging = StagingBuffer::new(device, allocator, data.len() as u64);
+    staging.update_buffer(0, &data);
+
+    let mut offset = 0;
+    let regions: Vec<vk::BufferImageCopy> = levels
+        .clone()
+        .map(|level| {
+            let extent = chain.level_extent(level);
+            let region = vk::BufferImageCopy::builder()
+                .buffer_offset(offset)
+                .image_subresource(vk::ImageSubresourceLayers {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    mip_level: level - allocated,
+                    base_array_layer: 0,
+                    layer_count: 1
+                })
+                .image_extent(vk::Extent3D { width: extent.width, height: extent.height, depth: 1 })
+                .build();
+            offset += chain.levels[level as usize].len() as u64;
+            region
+        })
+        .collect();
+
+    let range = vk::ImageSubresourceRange {
+        aspect_mask: vk::ImageAspectFlags::COLOR,
+        base_mip_level: levels.start - allocated,
+        level_count: levels.end - levels.start,
+        base_array_layer: 0,
+        layer_count: 1
+    };
+    let to_transfer = [vk::ImageMemoryBarrier::builder()
+        .old_layout(vk::ImageLayout::UNDEFINED)
+        .new_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+        .dst_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+        .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .image(image.image)
+        .subresource_range(range)
+        .build()
+    ];
+    let to_sampled = [vk::ImageMemoryBarrier::builder()
+        .old_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+        .new_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+        .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+        .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .image(image.image)
+        .subresource_range(range)
+        .build()
+    ];
+
+    let allocate_info = vk::CommandBufferAllocateInfo::builder()
+        .level(vk::CommandBufferLevel::PRIMARY)
+        .command_pool(pools.transfer_command_pool)
+        .command_buffer_count(1);
+    let begin_info = vk::CommandBufferBeginInfo::builder()
+        .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+
+    unsafe {
+        let command_buffer = device.allocate_command_buffers(&allocate_info)?[0];
+        device.begin_command_buffer(command_buffer, &begin_info)?;
+        device.cmd_pipeline_barrier(command_buffer, vk::PipelineStageFlags::TOP_OF_PIPE, vk::PipelineStageFlags::TRANSFER,
+            vk::DependencyFlags::empty(), &[], &[], &to_transfer);
+        device.cmd_copy_buffer_to_image(command_buffer, staging.get_buffer(), image.image, vk::ImageLayout::TRANSFER_DST_OPTIMAL, &regions);
+        device.cmd_pipeline_barrier(command_buffer, vk::PipelineStageFlags::TRANSFER, vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+            vk::DependencyFlags::empty(), &[], &[], &to_sampled);
+        device.end_command_buffer(command_buffer)?;
+
+        let fence = device.create_fence(&vk::FenceCreateInfo::default(), None)?;
+        let command_buffers = [command_buffer];
+        let submit_info = [vk::SubmitInfo::builder()
+            .command_buffers(&command_buffers)
+            .build()
+        ];
+        device.queue_submit(queue, &submit_info, fence)?;
+
+        Ok((staging, command_buffer, fence))
+    }
+}
@@ -0,0 +1,194 @@
+use ash::vk;
+use gpu_allocator::vulkan::Allocator;
+
+use super::command_pools::Pools;
+use super::image::{Image, DEPTH_FORMAT};
+use super::mesh::Mesh;
+use super::pipeline::{Pipeline, PipelineConfig};
+use super::render_pass::{AttachmentInfo, RenderPass, SubpassInfo};
+use super::renderer::VulkanRenderer;
+use super::staging_buffer::StagingBuffer;
+use super::swapchain::VulkanSwapchain;
+
+use crate::utils::any_as_u8_slice;
+
+pub const THUMBNAIL_VERT: &[u32] = vk_shader_macros::include_glsl!("./shaders/thumbnail.vert", kind: vert);
+pub const THUMBNAIL_FRAG: &[u32] = vk_shader_macros::include_glsl!("./shaders/thumbnail.frag", kind: frag);
+
+// sRGB encoded on write, which is what egui textures expect
+const THUMBNAIL_FORMAT: vk::Format = vk::Format::R8G8B8A8_SRGB;
+// Vertical field of view of the thumbnail camera, narrow so there's little perspective distortion
+const THUMBNAIL_FOV: f32 = 0.5;
+
+// Must match the push block in thumbnail.vert and thumbnail.frag
+#[repr(C)]
+struct ThumbnailPushConstants {
+    _view_projection: uv::Mat4,
+    _color_roughness: uv::Vec4,
+    _view_direction: uv::Vec4,
+}
+
+// Draws single meshes into a small image of its own with fixed studio lighting and reads it back, for previews like the
+// asset browser's. Nothing of the scene or the swapchain is touched, every render waits for the GPU.
+pub struct ThumbnailRenderer {
+    pub size: u32,
+    color: Image,
+    depth: Image,
+    renderpass: vk::RenderPass,
+    framebuffer: vk::Framebuffer,
+    pipeline: Pipeline,
+}
+
+impl ThumbnailRenderer {
+    pub fn new(device: &ash::Device, allocator: &mut Allocator, swapchain: &VulkanSwapchain, size: u32) -> Result<Self, vk::Result> {
+        let extent = vk::Extent2D { width: size, height: size };
+        let color = Image::new(device, allocator, extent, THUMBNAIL_FORMAT,
+            vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC, vk::ImageAspectFlags::COLOR, "Thumbnail")?;
+        let depth = Image::new_depth(device, allocator, extent, vk::ImageUsageFlags::empty(), "Thumbnail Depth")?;
+
+        // The color is left ready to be copied out in the same submission
+        let attachments = [
+            AttachmentInfo::new(THUMBNAIL_FORMAT, vk::ImageLayout::TRANSFER_SRC_OPTIMAL),
+            AttachmentInfo::transient(DEPTH_FORMAT)
+        ];
+        let subpasses = [SubpassInfo {
+            colors: &[0],
+            inputs: &[],
+            depth: Some(1),
+        }];
+        let dependencies = [vk::SubpassDependency::builder()
+            .src_subpass(vk::SUBPASS_EXTERNAL)
+            .src_stage_mask(vk::PipelineStageFlags::TRANSFER)
+            .src_access_mask(vk::AccessFlags::TRANSFER_READ)
+            .dst_subpass(0)
+            .dst_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS)
+            .dst_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE)
+            .build(),
+            vk::SubpassDependency::builder()
+            .src_subpass(0)
+            .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+            .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+            .dst_subpass(vk::SUBPASS_EXTERNAL)
+            .dst_stage_mask(vk::PipelineStageFlags::TRANSFER)
+            .dst_access_mask(vk::AccessFlags::TRANSFER_READ)
+            .build()
+        ];
+        let renderpass = RenderPass::init_subpasses(device, &attachments, &subpasses, &dependencies)?;
+
+        let framebuffer_attachments = [color.view, depth.view];
+        let framebuffer_info = vk::FramebufferCreateInfo::builder()
+            .render_pass(renderpass)
+            .attachments(&framebuffer_attachments)
+            .width(size)
+            .height(size)
+            .layers(1);
+        let framebuffer = unsafe { device.create_framebuffer(&framebuffer_info, None)? };
+
+        // Both faces, so open meshes don't show holes
+        let config = PipelineConfig {
+            vertex_shader: THUMBNAIL_VERT,
+            fragment_shader: THUMBNAIL_FRAG,
+            push_constant_size: std::mem::size_of::<ThumbnailPushConstants>() as u32,
+            color_attachment_count: 1,
+            cull_mode: vk::CullModeFlags::NONE,
+            specialization: &[],
+            ..PipelineConfig::basic(&[])
+        };
+        let pipeline = Pipeline::new(device, swapchain, &renderpass, &config)?;
+
+        Ok(Self {
+            size,
+            color,
+            depth,
+            renderpass,
+            framebuffer,
+            pipeline
+        })
+    }
+
+    // Tightly packed RGBA8 (sRGB, premultiplied alpha) of `size` by `size` pixels. The mesh is framed by its bounding
+    // sphere and seen from above and to the side, the background stays transparent.
+    #[allow(clippy::too_many_arguments)]
+    pub fn render(&self, device: &ash::Device, allocator: &mut Allocator, pools: &Pools, queue: vk::Queue, mesh: &Mesh, color: uv::Vec3,
+        roughness: f32
+    ) -> Result<Vec<u8>, vk::Result> {
+        let bounds = match mesh.bounds {
+            Some(bounds) => bounds,
+            None => return Ok(vec![0; self.size as usize * self.size as usize * 4])
+        };
+        let radius = bounds.radius.max(f32::EPSILON);
+        let view_direction = uv::Vec3::new(0.6, 0.5, 0.8).normalized();
+        let distance = radius / (THUMBNAIL_FOV * 0.5).sin() * 1.05;
+        let eye = bounds.center + view_direction * distance;
+        let view = uv::Mat4::look_at(eye, bounds.center, uv::Vec3::unit_y());
+        let projection = uv::projection::rh_yup::perspective_vk(THUMBNAIL_FOV, 1.0, (distance - radius * 1.1).max(radius * 0.01),
+            distance + radius * 1.1);
+        let push = ThumbnailPushConstants {
+            _view_projection: projection * view,
+            _color_roughness: uv::Vec4::new(color.x, color.y, color.z, roughness),
+            _view_direction: view_direction.into_homogeneous_vector()
+        };
+
+        let extent = self.color.extent;
+        let size = extent.width as u64 * extent.height as u64 * 4;
+        let mut staging_buffer = StagingBuffer::new(device, allocator, size);
+        let clear_values = [
+            vk::ClearValue {
+                color: vk::ClearColorValue { float32: [0.0, 0.0, 0.0, 0.0] }
+            },
+            vk::ClearValue {
+                depth_stencil: vk::ClearDepthStencilValue { depth: 1.0, stencil: 0 }
+            }
+        ];
+        let result = pools.one_time_submit(device, queue, |command_buffer| {
+            let renderpass_begininfo = vk::RenderPassBeginInfo::builder()
+                .render_pass(self.renderpass)
+                .framebuffer(self.framebuffer)
+                .render_area(vk::Rect2D {
+                    offset: vk::Offset2D { x: 0, y: 0 },
+                    extent
+                })
+                .clear_values(&clear_values);
+            let regions = [vk::BufferImageCopy::builder()
+                .image_subresource(vk::ImageSubresourceLayers {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    mip_level: 0,
+                    base_array_layer: 0,
+                    layer_count: 1
+                })
+                .image_extent(vk::Extent3D { width: extent.width, height: extent.height, depth: 1 })
+                .build()
+            ];
+
+            unsafe {
+                device.cmd_begin_render_pass(command_buffer, &renderpass_begininfo, vk::SubpassContents::INLINE);
+                VulkanRenderer::set_viewport(device, command_buffer, extent);
+                device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, self.pipeline.pipeline);
+                device.cmd_push_constants(command_buffer, self.pipeline.layout, vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT, 0,
+                    any_as_u8_slice(&push));
+                mesh.record_draw(device, command_buffer, 0);
+                device.cmd_end_render_pass(command_buffer);
+
+                device.cmd_copy_image_to_buffer(command_buffer, self.color.image, vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    staging_buffer.get_buffer(), &regions);
+            }
+        });
+
+        let mut pixels = vec![0; size as usize];
+        staging_buffer.read_buffer(0, &mut pixels);
+        staging_buffer.destroy(device, allocator);
+        result?;
+
+        Ok(pixels)
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device, allocator: &mut Allocator) {
+        self.pipeline.cleanup(device);
+        unsafe {
+            device.destroy_framebuffer(self.framebuffer, None);
+        }
+        RenderPass::cleanup(device, self.renderpass);
+        self.color.destroy(device, allocator);
+        self.depth.destroy(device, allocator);
+    }
+}
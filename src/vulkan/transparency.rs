@@ -0,0 +1,105 @@
+use ash::vk;
+
+use super::game_object::GameObject;
+use super::material::{Material, MaterialSets};
+use super::object_buffer::MAX_OBJECTS;
+use super::pipeline::{BlendMode, Pipeline, PipelineConfig, BASIC_VERT, MATERIAL_SET};
+use super::swapchain::VulkanSwapchain;
+
+pub const TRANSPARENT_FRAG: &[u32] = vk_shader_macros::include_glsl!("./shaders/basic.frag", kind: frag, define: TRANSPARENT);
+
+// Scene pipelines of `Material::Transparent`, one per blend mode. They test against the opaque depth without writing
+// it and only blend into the color target, so the normals, motion and ids stay those of the surfaces behind. Objects
+// are drawn one by one in sorted order, never through the GPU culled indirect draws, so the vertices come from the
+// object's own buffers even when the opaque pipelines pull theirs.
+pub struct TransparentPipelines {
+    alpha: Pipeline,
+    additive: Pipeline,
+    premultiplied: Pipeline,
+}
+
+impl TransparentPipelines {
+    // Everything else comes from the basic scene pipeline's `scene_config`
+    pub fn new(device: &ash::Device, swapchain: &VulkanSwapchain, renderpass: &vk::RenderPass, scene_config: &PipelineConfig
+    ) -> Result<Self, vk::Result> {
+        let pipeline = |blend_mode| {
+            // Shaded per pixel like the reflective surfaces
+            let config = PipelineConfig {
+                vertex_shader: BASIC_VERT,
+                fragment_shader: TRANSPARENT_FRAG,
+                vertex_input: true,
+                dynamic_shading_rate: false,
+                depth_write: false,
+                data_write: false,
+                blend_mode,
+                ..*scene_config
+            };
+            Pipeline::new(device, swapchain, renderpass, &config)
+        };
+
+        Ok(Self {
+            alpha: pipeline(BlendMode::Alpha)?,
+            additive: pipeline(BlendMode::Additive)?,
+            premultiplied: pipeline(BlendMode::Premultiplied)?
+        })
+    }
+
+    pub fn get(&self, blend_mode: BlendMode) -> &Pipeline {
+        match blend_mode {
+            BlendMode::Alpha => &self.alpha,
+            BlendMode::Additive => &self.additive,
+            BlendMode::Premultiplied => &self.premultiplied
+        }
+    }
+
+    // Draws the transparent objects farthest from `eye` first, each over everything drawn before it. The frame, object
+    // and lighting sets must be bound already, they're the same for every scene pipeline.
+    pub fn record(&self, device: &ash::Device, command_buffer: vk::CommandBuffer, game_objects: &[GameObject], models: &[uv::Mat4],
+        materials: &MaterialSets, eye: uv::Vec3
+    ) {
+        let mut bound_pipeline = None;
+        unsafe {
+            for (index, blend_mode, set) in back_to_front(game_objects, models, materials, eye) {
+                let pipeline = self.get(blend_mode);
+                if bound_pipeline != Some(blend_mode) {
+                    device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, pipeline.pipeline);
+                    bound_pipeline = Some(blend_mode);
+                }
+                device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::GRAPHICS, pipeline.layout, MATERIAL_SET, &[set], &[]);
+                game_objects[index].mesh.record_draw(device, command_buffer, index as u32);
+            }
+        }
+    }
+
+    pub fn cleanup(&self, device: &ash::Device) {
+        self.alpha.cleanup(device);
+        self.additive.cleanup(device);
+        self.premultiplied.cleanup(device);
+    }
+}
+
+// Transparent objects with their blend mode and material set, sorted by the distance of their bounds' center to
+// `eye`, farthest first. Objects without bounds sort by their origin. Sorting whole objects can't untangle objects
+// that intersect or surround each other, those blend in whichever order their centers give.
+fn back_to_front(game_objects: &[GameObject], models: &[uv::Mat4], materials: &MaterialSets, eye: uv::Vec3
+) -> Vec<(usize, BlendMode, vk::DescriptorSet)> {
+    let mut draws: Vec<(f32, usize, BlendMode, vk::DescriptorSet)> = game_objects
+        .iter()
+        .zip(models)
+        .enumerate()
+        .take(MAX_OBJECTS)
+        .filter_map(|(index, (game_object, &model))| match game_object.material {
+            Material::Transparent(blend_mode) => materials.set(game_object).map(|set| {
+                let center = match game_object.mesh.bounds {
+                    Some(bounds) => bounds.transformed(model).center,
+                    None => model.transform_point3(uv::Vec3::zero())
+                };
+                ((center - eye).mag_sq(), index, blend_mode, set)
+            }),
+            _ => None
+        })
+        .collect();
+    draws.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+    draws.into_iter().map(|(_, index, blend_mode, set)| (index, blend_mode, set)).collect()
+}
\ No newline at end of file
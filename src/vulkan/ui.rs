@@ -0,0 +1,435 @@
+use std::collections::HashMap;
+use std::time::Instant;
+
+use ash::vk;
+use gpu_allocator::vulkan::Allocator;
+use winit::event::{ElementState, KeyboardInput, ModifiersState, MouseButton, MouseScrollDelta, VirtualKeyCode, WindowEvent};
+
+use super::command_pools::Pools;
+use super::descriptors::Descriptors;
+use super::descriptor_allocator::DescriptorAllocator;
+use super::pipeline::{BlendMode, Pipeline, PipelineConfig};
+use super::swapchain::VulkanSwapchain;
+use super::texture::Texture;
+use super::upload_ring::{RingSlice, UploadRing};
+
+use crate::clipboard;
+use crate::utils::any_as_u8_slice;
+
+pub const UI_VERT: &[u32] = vk_shader_macros::include_glsl!("./shaders/ui.vert", kind: vert);
+pub const UI_FRAG: &[u32] = vk_shader_macros::include_glsl!("./shaders/ui.frag", kind: frag);
+
+// Geometry per swapchain image, meshes past these limits are dropped for the frame
+const MAX_VERTICES: usize = 1 << 16;
+const MAX_INDICES: usize = 3 << 16;
+// Points scrolled per wheel notch
+const SCROLL_LINE: f32 = 50.0;
+
+// Mirrors the push constant block of ui.vert
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct UiPushConstants {
+    screen_size: [f32; 2],
+    vertex_offset: u32,
+    // Word offset of the frame's vertices in the upload ring
+    vertex_base: u32,
+}
+
+struct UiTexture {
+    texture: Texture,
+    set: vk::DescriptorSet,
+    // CPU copy, partial updates patch it and upload the whole image again
+    size: [usize; 2],
+    pixels: Vec<egui::Color32>,
+}
+
+// A mesh of the last finished frame and where it lives in the frame's geometry slices
+struct UiDraw {
+    mesh: egui::epaint::Mesh,
+    clip_rect: egui::Rect,
+    vertex_offset: u32,
+    index_offset: u32,
+}
+
+// egui integration: translates winit events into egui input, and draws the tessellated output on top of the final image.
+// Vertices are pulled from the upload ring as storage buffers, so the pipeline doesn't depend on egui's vertex layout.
+pub struct Ui {
+    pub context: egui::Context,
+    input: egui::RawInput,
+    pointer: egui::Pos2,
+    modifiers: egui::Modifiers,
+    pub pixels_per_point: f32,
+    start: Instant,
+    textures: HashMap<egui::TextureId, UiTexture>,
+    draws: Vec<UiDraw>,
+    texture_set_layout: vk::DescriptorSetLayout,
+    geometry_set_layout: vk::DescriptorSetLayout,
+    geometry_set: vk::DescriptorSet,
+    // Slices of the upload ring for all vertices and indices of the frame, `None` when it had no room for them
+    geometry_slices: Option<(RingSlice, RingSlice)>,
+    pipeline: Pipeline,
+}
+
+impl Ui {
+    pub fn new(device: &ash::Device, swapchain: &VulkanSwapchain, present_renderpass: &vk::RenderPass, descriptor_pool: vk::DescriptorPool,
+        upload_ring: &UploadRing, pixels_per_point: f32
+    ) -> Result<Self, vk::Result> {
+        let texture_set_layout = Descriptors::create_layout(device, &[
+            (vk::DescriptorType::COMBINED_IMAGE_SAMPLER, vk::ShaderStageFlags::FRAGMENT)
+        ])?;
+        let geometry_set_layout = Descriptors::create_layout(device, &[
+            (vk::DescriptorType::STORAGE_BUFFER, vk::ShaderStageFlags::VERTEX),
+            (vk::DescriptorType::STORAGE_BUFFER, vk::ShaderStageFlags::VERTEX)
+        ])?;
+
+        // Both read the whole ring, the frame's slices are found through the push constants and the first vertex
+        let geometry_set = Descriptors::allocate(device, descriptor_pool, geometry_set_layout, 1)?[0];
+        for binding in 0..2 {
+            Descriptors::write_buffer(device, geometry_set, binding, vk::DescriptorType::STORAGE_BUFFER, upload_ring.descriptor_info(vk::WHOLE_SIZE));
+        }
+
+        let set_layouts = [texture_set_layout, geometry_set_layout];
+        let config = PipelineConfig {
+            vertex_shader: UI_VERT,
+            blend_mode: BlendMode::Premultiplied,
+            ..PipelineConfig::fullscreen(UI_FRAG, &set_layouts, std::mem::size_of::<UiPushConstants>() as u32)
+        };
+        let pipeline = Pipeline::new(device, swapchain, present_renderpass, &config)?;
+
+        Ok(Self {
+            context: egui::Context::default(),
+            input: egui::RawInput::default(),
+            pointer: egui::Pos2::ZERO,
+            modifiers: egui::Modifiers::default(),
+            pixels_per_point,
+            start: Instant::now(),
+            textures: HashMap::new(),
+            draws: vec![],
+            texture_set_layout,
+            geometry_set_layout,
+            geometry_set,
+            geometry_slices: None,
+            pipeline
+        })
+    }
+
+    // Queues the event for the next frame. True if the UI used it, the scene should then ignore it.
+    // Releases are never claimed, so buttons and keys held by the scene don't get stuck.
+    pub fn handle_event(&mut self, event: &WindowEvent) -> bool {
+        match event {
+            WindowEvent::CursorMoved { position, .. } => {
+                self.pointer = egui::pos2(position.x as f32 / self.pixels_per_point, position.y as f32 / self.pixels_per_point);
+                self.input.events.push(egui::Event::PointerMoved(self.pointer));
+                self.context.is_using_pointer()
+            }
+            WindowEvent::CursorLeft { .. } => {
+                self.input.events.push(egui::Event::PointerGone);
+                false
+            }
+            WindowEvent::MouseInput { state, button, .. } => {
+                let button = match button {
+                    MouseButton::Left => egui::PointerButton::Primary,
+                    MouseButton::Right => egui::PointerButton::Secondary,
+                    MouseButton::Middle => egui::PointerButton::Middle,
+                    MouseButton::Other(_) => return false
+                };
+                self.input.events.push(egui::Event::PointerButton {
+                    pos: self.pointer,
+                    button,
+                    pressed: *state == ElementState::Pressed,
+                    modifiers: self.modifiers
+                });
+                *state == ElementState::Pressed && self.context.wants_pointer_input()
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                let delta = match delta {
+                    MouseScrollDelta::LineDelta(x, y) => egui::vec2(*x, *y) * SCROLL_LINE,
+                    MouseScrollDelta::PixelDelta(position) => egui::vec2(position.x as f32, position.y as f32) / self.pixels_per_point
+                };
+                self.input.events.push(egui::Event::Scroll(delta));
+                self.context.wants_pointer_input()
+            }
+            WindowEvent::ReceivedCharacter(character) => {
+                if !character.is_control() {
+                    self.input.events.push(egui::Event::Text(character.to_string()));
+                }
+                self.context.wants_keyboard_input()
+            }
+            WindowEvent::KeyboardInput { input: KeyboardInput { state, virtual_keycode: Some(key), .. }, .. } => {
+                if *state == ElementState::Pressed {
+                    if let Some(event) = clipboard_event(*key, self.modifiers) {
+                        self.input.events.push(event);
+                    }
+                }
+                if let Some(key) = translate_key(*key) {
+                    self.input.events.push(egui::Event::Key {
+                        key,
+                        pressed: *state == ElementState::Pressed,
+                        modifiers: self.modifiers
+                    });
+                }
+                *state == ElementState::Pressed && self.context.wants_keyboard_input()
+            }
+            WindowEvent::ModifiersChanged(state) => {
+                self.modifiers = translate_modifiers(*state);
+                false
+            }
+            WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+                self.pixels_per_point = *scale_factor as f32;
+                false
+            }
+            _ => false
+        }
+    }
+
+    // Starts a frame with the input gathered since the last one, build windows and panels on the returned context
+    pub fn begin_frame(&mut self, extent: vk::Extent2D) -> egui::Context {
+        let mut input = std::mem::take(&mut self.input);
+        input.screen_rect = Some(egui::Rect::from_min_size(egui::Pos2::ZERO,
+            egui::vec2(extent.width as f32, extent.height as f32) / self.pixels_per_point));
+        input.pixels_per_point = Some(self.pixels_per_point);
+        input.time = Some(self.start.elapsed().as_secs_f64());
+        input.modifiers = self.modifiers;
+
+        self.context.begin_frame(input);
+        self.context.clone()
+    }
+
+    // Tessellates the frame and applies texture changes, waiting for the device whenever a texture is replaced or freed
+    pub fn end_frame(&mut self, device: &ash::Device, allocator: &mut Allocator, pools: &Pools, queue: vk::Queue,
+        descriptors: &mut DescriptorAllocator
+    ) -> Result<(), vk::Result> {
+        let output = self.context.end_frame();
+        if !output.platform_output.copied_text.is_empty() {
+            clipboard::set_text(output.platform_output.copied_text);
+        }
+
+        for (id, delta) in output.textures_delta.set {
+            self.set_texture(device, allocator, pools, queue, descriptors, id, delta)?;
+        }
+
+        let primitives = self.context.tessellate(output.shapes);
+        self.draws.clear();
+        let (mut vertex_count, mut index_count) = (0, 0);
+        for primitive in primitives {
+            let mesh = match primitive.primitive {
+                egui::epaint::Primitive::Mesh(mesh) => mesh,
+                egui::epaint::Primitive::Callback(_) => continue
+            };
+            if vertex_count + mesh.vertices.len() > MAX_VERTICES || index_count + mesh.indices.len() > MAX_INDICES {
+                tracing::warn!("UI geometry exceeds {} vertices or {} indices, the rest is skipped", MAX_VERTICES, MAX_INDICES);
+                break;
+            }
+
+            let (vertex_offset, index_offset) = (vertex_count as u32, index_count as u32);
+            vertex_count += mesh.vertices.len();
+            index_count += mesh.indices.len();
+            self.draws.push(UiDraw {
+                mesh,
+                clip_rect: primitive.clip_rect,
+                vertex_offset,
+                index_offset
+            });
+        }
+
+        if !output.textures_delta.free.is_empty() {
+            unsafe { device.device_wait_idle()? };
+        }
+        for id in output.textures_delta.free {
+            if let Some(mut texture) = self.textures.remove(&id) {
+                texture.texture.destroy(device, allocator);
+                descriptors.free(device, texture.set)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn set_texture(&mut self, device: &ash::Device, allocator: &mut Allocator, pools: &Pools, queue: vk::Queue,
+        descriptors: &mut DescriptorAllocator, id: egui::TextureId, delta: egui::epaint::ImageDelta
+    ) -> Result<(), vk::Result> {
+        let size = delta.image.size();
+        let pixels: Vec<egui::Color32> = match &delta.image {
+            egui::ImageData::Color(image) => image.pixels.clone(),
+            egui::ImageData::Font(image) => image.srgba_pixels(1.0).collect()
+        };
+
+        let (size, pixels) = match (delta.pos, self.textures.get_mut(&id)) {
+            (Some([x, y]), Some(texture)) => {
+                for row in 0..size[1] {
+                    let start = (y + row) * texture.size[0] + x;
+                    texture.pixels[start..start + size[0]].copy_from_slice(&pixels[row * size[0]..(row + 1) * size[0]]);
+                }
+                (texture.size, std::mem::take(&mut texture.pixels))
+            }
+            _ => (size, pixels)
+        };
+
+        let bytes: Vec<u8> = pixels.iter().flat_map(|color| color.to_array()).collect();
+        let texture = Texture::from_rgba8(device, allocator, pools, queue,
+            vk::Extent2D { width: size[0] as u32, height: size[1] as u32 }, &bytes, "UI Texture")?;
+
+        let set = match self.textures.remove(&id) {
+            Some(mut old) => {
+                unsafe { device.device_wait_idle()? };
+                old.texture.destroy(device, allocator);
+                old.set
+            }
+            None => descriptors.allocate(device, self.texture_set_layout)?
+        };
+        Descriptors::write_image(device, set, 0, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, texture.descriptor_info());
+
+        self.textures.insert(id, UiTexture {
+            texture,
+            set,
+            size,
+            pixels
+        });
+
+        Ok(())
+    }
+
+    // Makes room for the last finished frame while the frame's upload layout is planned
+    pub fn reserve(&mut self, upload_ring: &mut UploadRing) {
+        let (vertex_count, index_count) = self.draws.last()
+            .map_or((0, 0), |draw| (draw.vertex_offset as usize + draw.mesh.vertices.len(), draw.index_offset as usize + draw.mesh.indices.len()));
+        let vertices = upload_ring.reserve((vertex_count * std::mem::size_of::<egui::epaint::Vertex>()) as u64);
+        let indices = upload_ring.reserve((index_count * std::mem::size_of::<u32>()) as u64);
+        self.geometry_slices = vertices.zip(indices);
+        if self.geometry_slices.is_none() {
+            tracing::warn!("No room left in the upload ring for the UI, it's skipped this frame");
+        }
+    }
+
+    // Copies the last finished frame into the geometry slices of swapchain image `index`
+    pub fn update(&mut self, index: usize, upload_ring: &mut UploadRing) {
+        let (vertices, indices) = match self.geometry_slices {
+            Some(slices) => slices,
+            None => return
+        };
+        for draw in &self.draws {
+            upload_ring.write(index, vertices, (draw.vertex_offset as usize * std::mem::size_of::<egui::epaint::Vertex>()) as u64,
+                &draw.mesh.vertices);
+            upload_ring.write(index, indices, (draw.index_offset as usize * std::mem::size_of::<u32>()) as u64, &draw.mesh.indices);
+        }
+    }
+
+    // Recorded inside the present render pass, last
+    pub fn record(&self, device: &ash::Device, command_buffer: vk::CommandBuffer, index: usize, extent: vk::Extent2D, upload_ring: &UploadRing) {
+        let screen_size = [extent.width as f32 / self.pixels_per_point, extent.height as f32 / self.pixels_per_point];
+        let (vertices, indices) = match self.geometry_slices {
+            Some(slices) => slices,
+            None => return
+        };
+        // In 32 bit words, like the shader reads them
+        let (vertex_base, index_base) = ((upload_ring.offset(index, vertices) / 4) as u32, (upload_ring.offset(index, indices) / 4) as u32);
+
+        unsafe {
+            device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, self.pipeline.pipeline);
+            device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::GRAPHICS, self.pipeline.layout, 1,
+                &[self.geometry_set], &[]);
+        }
+
+        for draw in &self.draws {
+            let texture = match self.textures.get(&draw.mesh.texture_id) {
+                Some(texture) => texture,
+                None => continue
+            };
+
+            // Clip rects are in points and may reach past the screen
+            let min_x = (draw.clip_rect.min.x * self.pixels_per_point).round().clamp(0.0, extent.width as f32);
+            let min_y = (draw.clip_rect.min.y * self.pixels_per_point).round().clamp(0.0, extent.height as f32);
+            let max_x = (draw.clip_rect.max.x * self.pixels_per_point).round().clamp(min_x, extent.width as f32);
+            let max_y = (draw.clip_rect.max.y * self.pixels_per_point).round().clamp(min_y, extent.height as f32);
+            if max_x <= min_x || max_y <= min_y {
+                continue;
+            }
+            let scissors = [vk::Rect2D {
+                offset: vk::Offset2D { x: min_x as i32, y: min_y as i32 },
+                extent: vk::Extent2D { width: (max_x - min_x) as u32, height: (max_y - min_y) as u32 }
+            }];
+
+            let push = UiPushConstants {
+                screen_size,
+                vertex_offset: draw.vertex_offset,
+                vertex_base
+            };
+
+            unsafe {
+                device.cmd_set_scissor(command_buffer, 0, &scissors);
+                device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::GRAPHICS, self.pipeline.layout, 0, &[texture.set], &[]);
+                device.cmd_push_constants(command_buffer, self.pipeline.layout, vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT, 0,
+                    any_as_u8_slice(&push));
+                device.cmd_draw(command_buffer, draw.mesh.indices.len() as u32, 1, index_base + draw.index_offset, 0);
+            }
+        }
+
+        // Later passes in the same render pass expect the full screen scissor again
+        let scissors = [vk::Rect2D {
+            offset: vk::Offset2D { x: 0, y: 0 },
+            extent
+        }];
+        unsafe { device.cmd_set_scissor(command_buffer, 0, &scissors) };
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device, allocator: &mut Allocator) {
+        for texture in self.textures.values_mut() {
+            texture.texture.destroy(device, allocator);
+        }
+        self.pipeline.cleanup(device);
+        unsafe {
+            device.destroy_descriptor_set_layout(self.texture_set_layout, None);
+            device.destroy_descriptor_set_layout(self.geometry_set_layout, None);
+        }
+    }
+}
+
+fn translate_modifiers(state: ModifiersState) -> egui::Modifiers {
+    egui::Modifiers {
+        alt: state.alt(),
+        ctrl: state.ctrl(),
+        shift: state.shift(),
+        mac_cmd: cfg!(target_os = "macos") && state.logo(),
+        command: match cfg!(target_os = "macos") {
+            true => state.logo(),
+            false => state.ctrl()
+        }
+    }
+}
+
+// Text fields copy and paste through these rather than the keys, pasting reads the clipboard right away
+fn clipboard_event(key: VirtualKeyCode, modifiers: egui::Modifiers) -> Option<egui::Event> {
+    match (key, modifiers.command) {
+        (VirtualKeyCode::C, true) | (VirtualKeyCode::Copy, _) => Some(egui::Event::Copy),
+        (VirtualKeyCode::X, true) | (VirtualKeyCode::Cut, _) => Some(egui::Event::Cut),
+        (VirtualKeyCode::V, true) | (VirtualKeyCode::Paste, _) => clipboard::get_text().map(egui::Event::Paste),
+        _ => None
+    }
+}
+
+fn translate_key(key: VirtualKeyCode) -> Option<egui::Key> {
+    Some(match key {
+        VirtualKeyCode::Down => egui::Key::ArrowDown,
+        VirtualKeyCode::Left => egui::Key::ArrowLeft,
+        VirtualKeyCode::Right => egui::Key::ArrowRight,
+        VirtualKeyCode::Up => egui::Key::ArrowUp,
+        VirtualKeyCode::Escape => egui::Key::Escape,
+        VirtualKeyCode::Tab => egui::Key::Tab,
+        VirtualKeyCode::Back => egui::Key::Backspace,
+        VirtualKeyCode::Return => egui::Key::Enter,
+        VirtualKeyCode::Space => egui::Key::Space,
+        VirtualKeyCode::Insert => egui::Key::Insert,
+        VirtualKeyCode::Delete => egui::Key::Delete,
+        VirtualKeyCode::Home => egui::Key::Home,
+        VirtualKeyCode::End => egui::Key::End,
+        VirtualKeyCode::PageUp => egui::Key::PageUp,
+        VirtualKeyCode::PageDown => egui::Key::PageDown,
+        VirtualKeyCode::A => egui::Key::A,
+        VirtualKeyCode::C => egui::Key::C,
+        VirtualKeyCode::V => egui::Key::V,
+        VirtualKeyCode::X => egui::Key::X,
+        VirtualKeyCode::Z => egui::Key::Z,
+        _ => return None
+    })
+}
\ No newline at end of file
@@ -0,0 +1,76 @@
+use ash::vk;
+use gpu_allocator::vulkan::*;
+use gpu_allocator::MemoryLocation;
+
+pub struct UniformBuffer<T> {
+    buffer: vk::Buffer,
+    allocation: Allocation,
+    _marker: std::marker::PhantomData<T>
+}
+
+impl<T: Copy> UniformBuffer<T> {
+    pub fn new(device: &ash::Device, allocator: &mut Allocator) -> UniformBuffer<T> {
+        let uniform_buffer_create_info = vk::BufferCreateInfo::builder()
+            .size(Self::get_size())
+            .usage(vk::BufferUsageFlags::UNIFORM_BUFFER)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+
+        let uniform_buffer = unsafe {
+            device
+                .create_buffer(&uniform_buffer_create_info, None)
+                .expect("Failed to create uniform buffer")
+        };
+
+        let mem_requirements = unsafe { device.get_buffer_memory_requirements(uniform_buffer) };
+        let location = MemoryLocation::CpuToGpu;
+
+        let allocation = allocator.allocate(&AllocationCreateDesc {
+            requirements: mem_requirements,
+            location,
+            linear: true,
+            name: "Uniform Buffer"
+        }).expect("Failed to allocate memory for uniform buffer!");
+
+        unsafe {
+            device
+                .bind_buffer_memory(uniform_buffer, allocation.memory(), allocation.offset())
+                .expect("Failed to bind uniform buffer");
+        }
+
+        UniformBuffer {
+            buffer: uniform_buffer,
+            allocation,
+            _marker: std::marker::PhantomData
+        }
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device, allocator: &mut Allocator) {
+        allocator
+            .free(std::mem::take(&mut self.allocation))
+            .expect("Failed to free uniform buffer memory!");
+        unsafe {
+            device.destroy_buffer(self.buffer, None);
+        }
+    }
+
+    pub fn get_size() -> u64 {
+        std::mem::size_of::<T>() as u64
+    }
+
+    pub fn update_buffer(&mut self, data: &T) {
+        let dst = self.allocation.mapped_ptr().unwrap().cast().as_ptr();
+        unsafe {
+            std::ptr::copy_nonoverlapping(data as *const T, dst, 1);
+        }
+    }
+
+    pub fn get_buffer(&self) -> vk::Buffer { self.buffer }
+
+    pub fn descriptor_info(&self) -> vk::DescriptorBufferInfo {
+        vk::DescriptorBufferInfo {
+            buffer: self.buffer,
+            offset: 0,
+            range: Self::get_size()
+        }
+    }
+}
\ No newline at end of file
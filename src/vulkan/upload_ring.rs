@@ -0,0 +1,137 @@
+use ash::vk;
+use gpu_allocator::vulkan::*;
+use gpu_allocator::MemoryLocation;
+
+// Bytes each frame slot can stream, shared by everything written per frame
+pub const UPLOAD_REGION_SIZE: u64 = 4 << 20;
+
+// Part of the region a layout was planned for
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RingSlice {
+    pub offset: u64,
+    pub size: u64,
+}
+
+// One persistently mapped buffer for the data written every frame (per object data, UI geometry, dynamic vertices, ...),
+// split into a region per swapchain image. A frame plans the layout of its image's region with `begin_layout` and
+// `reserve`, records the image's command buffer with the `offset`s of the slices and `write`s them. Regions of frames
+// still in flight keep the layout they were recorded with, the next layout only applies to the next region.
+pub struct UploadRing {
+    buffer: vk::Buffer,
+    allocation: Allocation,
+    region_size: u64,
+    regions: usize,
+    alignment: u64,
+    cursor: u64,
+    // Region the slices reserved since `begin_layout` are in
+    region: usize,
+}
+
+impl UploadRing {
+    // `max_binding_range` is the largest range a dynamic descriptor reads from a slice, the buffer ends that far past the
+    // last region so such a range fits from any offset
+    pub fn new(device: &ash::Device, allocator: &mut Allocator, properties: &vk::PhysicalDeviceProperties, region_size: u64, regions: usize,
+        max_binding_range: u64
+    ) -> Result<Self, vk::Result> {
+        let limits = &properties.limits;
+        let alignment = limits.min_storage_buffer_offset_alignment
+            .max(limits.min_uniform_buffer_offset_alignment)
+            .max(limits.non_coherent_atom_size)
+            .max(16);
+        let region_size = region_size.div_ceil(alignment) * alignment;
+
+        let buffer_info = vk::BufferCreateInfo::builder()
+            .size(region_size * regions as u64 + max_binding_range)
+            .usage(vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::UNIFORM_BUFFER | vk::BufferUsageFlags::VERTEX_BUFFER
+                | vk::BufferUsageFlags::INDEX_BUFFER)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+        let buffer = unsafe { device.create_buffer(&buffer_info, None)? };
+
+        let requirements = unsafe { device.get_buffer_memory_requirements(buffer) };
+        let allocation = allocator.allocate(&AllocationCreateDesc {
+            requirements,
+            location: MemoryLocation::CpuToGpu,
+            linear: true,
+            name: "Upload Ring"
+        }).expect("Failed to allocate memory for the upload ring!");
+
+        unsafe { device.bind_buffer_memory(buffer, allocation.memory(), allocation.offset())? };
+
+        Ok(Self {
+            buffer,
+            allocation,
+            region_size,
+            regions,
+            alignment,
+            cursor: 0,
+            region: 0
+        })
+    }
+
+    // Starts planning the slices of swapchain image `region`, once the GPU is done with the frame that last used it. The
+    // slices reserved before are gone.
+    pub fn begin_layout(&mut self, region: usize) {
+        assert!(region < self.regions, "No upload ring region for swapchain image {}!", region);
+        self.cursor = 0;
+        self.region = region;
+    }
+
+    // `None` once the region is full
+    pub fn reserve(&mut self, size: u64) -> Option<RingSlice> {
+        let offset = self.cursor;
+        if offset + size > self.region_size {
+            return None;
+        }
+        self.cursor = (offset + size).div_ceil(self.alignment) * self.alignment;
+        Some(RingSlice { offset, size })
+    }
+
+    // Copies `data` to `offset` bytes into `slice` in the region of swapchain image `region`, the one being planned
+    pub fn write<T: Copy>(&mut self, region: usize, slice: RingSlice, offset: u64, data: &[T]) {
+        assert!(offset + std::mem::size_of_val(data) as u64 <= slice.size, "Upload ring write out of bounds!");
+
+        let dst = unsafe {
+            self.allocation.mapped_ptr()
+                .expect("Upload ring is not host visible!")
+                .as_ptr()
+                .cast::<u8>()
+                .add((self.offset(region, slice) + offset) as usize)
+                .cast()
+        };
+        unsafe {
+            std::ptr::copy_nonoverlapping(data.as_ptr(), dst, data.len());
+        }
+    }
+
+    // Byte offset of `slice` in the buffer, for the region of swapchain image `region`. Slices only exist in the region
+    // they were reserved for, the layout of the others may differ.
+    pub fn offset(&self, region: usize, slice: RingSlice) -> u64 {
+        assert_eq!(region, self.region, "Upload ring slice used outside the region it was reserved for!");
+        region as u64 * self.region_size + slice.offset
+    }
+
+    // Bytes reserved in the region so far this frame
+    pub fn used(&self) -> u64 {
+        self.cursor
+    }
+
+    pub fn get_buffer(&self) -> vk::Buffer { self.buffer }
+
+    // The buffer from its start, for descriptors offset per draw (dynamic ones or by a push constant)
+    pub fn descriptor_info(&self, range: u64) -> vk::DescriptorBufferInfo {
+        vk::DescriptorBufferInfo {
+            buffer: self.buffer,
+            offset: 0,
+            range
+        }
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device, allocator: &mut Allocator) {
+        allocator
+            .free(std::mem::take(&mut self.allocation))
+            .expect("Failed to free upload ring memory!");
+        unsafe {
+            device.destroy_buffer(self.buffer, None);
+        }
+    }
+}
\ No newline at end of file
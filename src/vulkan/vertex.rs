@@ -4,8 +4,15 @@ use memoffset::offset_of;
 #[repr(C)]
 #[derive(Clone, Debug, Copy)]
 pub struct Vertex {
-    pub pos: uv::Vec2,
+    pub pos: uv::Vec3,
     pub color: uv::Vec3,
+    pub normal: uv::Vec3,
+    // Second UV channel into the object's lightmap, zero for meshes that aren't lightmapped
+    pub lightmap_uv: uv::Vec2,
+    // First UV channel, what material textures like normal maps are sampled with
+    pub tex_coord: uv::Vec2,
+    // Direction of increasing u, w is the handedness of the bitangent (`cross(normal, tangent) * w`)
+    pub tangent: uv::Vec4,
 }
 
 impl Vertex {
@@ -17,20 +24,86 @@ impl Vertex {
         }]
     }
 
-    pub fn get_attribute_descriptions() -> [vk::VertexInputAttributeDescription; 2] {
+    pub fn get_attribute_descriptions() -> [vk::VertexInputAttributeDescription; 6] {
         [
             vk::VertexInputAttributeDescription {
                 binding: 0,
                 location: 0,
-                format: vk::Format::R32G32B32A32_SFLOAT,
+                format: vk::Format::R32G32B32_SFLOAT,
                 offset: offset_of!(Vertex, pos) as u32,
             },
             vk::VertexInputAttributeDescription {
                 binding: 0,
                 location: 1,
-                format: vk::Format::R32G32B32A32_SFLOAT,
+                format: vk::Format::R32G32B32_SFLOAT,
                 offset: offset_of!(Vertex, color) as u32
+            },
+            vk::VertexInputAttributeDescription {
+                binding: 0,
+                location: 2,
+                format: vk::Format::R32G32B32_SFLOAT,
+                offset: offset_of!(Vertex, normal) as u32
+            },
+            vk::VertexInputAttributeDescription {
+                binding: 0,
+                location: 3,
+                format: vk::Format::R32G32_SFLOAT,
+                offset: offset_of!(Vertex, lightmap_uv) as u32
+            },
+            vk::VertexInputAttributeDescription {
+                binding: 0,
+                location: 4,
+                format: vk::Format::R32G32_SFLOAT,
+                offset: offset_of!(Vertex, tex_coord) as u32
+            },
+            vk::VertexInputAttributeDescription {
+                binding: 0,
+                location: 5,
+                format: vk::Format::R32G32B32A32_SFLOAT,
+                offset: offset_of!(Vertex, tangent) as u32
             }
         ]
     }
+}
+
+// Per vertex tangents from the texture coordinates of the triangles around each vertex, made orthogonal to its
+// normal. Vertices whose triangles have no usable texture coordinates get any direction orthogonal to the normal.
+pub fn generate_tangents(vertices: &mut [Vertex], indices: &[u32]) {
+    let mut tangents = vec![uv::Vec3::zero(); vertices.len()];
+    let mut bitangents = vec![uv::Vec3::zero(); vertices.len()];
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [triangle[0], triangle[1], triangle[2]].map(|index| vertices[index as usize]);
+        let (edge1, edge2) = (b.pos - a.pos, c.pos - a.pos);
+        let (duv1, duv2) = (b.tex_coord - a.tex_coord, c.tex_coord - a.tex_coord);
+        let determinant = duv1.x * duv2.y - duv2.x * duv1.y;
+        if determinant.abs() < f32::EPSILON {
+            continue;
+        }
+
+        let tangent = (edge1 * duv2.y - edge2 * duv1.y) / determinant;
+        let bitangent = (edge2 * duv1.x - edge1 * duv2.x) / determinant;
+        for &index in triangle {
+            tangents[index as usize] += tangent;
+            bitangents[index as usize] += bitangent;
+        }
+    }
+
+    for ((vertex, tangent), bitangent) in vertices.iter_mut().zip(tangents).zip(bitangents) {
+        let normal = vertex.normal;
+        let mut orthogonal = tangent - normal * normal.dot(tangent);
+        if orthogonal.mag_sq() <= f32::EPSILON {
+            // Whichever axis is least aligned with the normal
+            let axis = match normal.x.abs() < 0.9 {
+                true => uv::Vec3::unit_x(),
+                false => uv::Vec3::unit_y()
+            };
+            orthogonal = axis - normal * normal.dot(axis);
+        }
+        let handedness = match normal.cross(orthogonal).dot(bitangent) < 0.0 {
+            true => -1.0,
+            false => 1.0
+        };
+        let tangent = orthogonal.normalized();
+        vertex.tangent = uv::Vec4::new(tangent.x, tangent.y, tangent.z, handedness);
+    }
 }
\ No newline at end of file
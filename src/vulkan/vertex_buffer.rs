@@ -14,7 +14,8 @@ impl VertexBuffer {
     pub fn new(device: &ash::Device, allocator: &mut Allocator, size: u64) -> VertexBuffer {
         let vertex_buffer_create_info = vk::BufferCreateInfo::builder()
             .size(size)
-            .usage(vk::BufferUsageFlags::VERTEX_BUFFER)
+            // Storage so pipelines pulling their vertices can read it too
+            .usage(vk::BufferUsageFlags::VERTEX_BUFFER | vk::BufferUsageFlags::STORAGE_BUFFER)
             .sharing_mode(vk::SharingMode::EXCLUSIVE);
 
         let vertex_buffer = unsafe {
@@ -69,6 +70,16 @@ impl VertexBuffer {
         self.vertex_count = data.len() as u32;
     }
 
+    // The vertices last written with `update_buffer`, read back from the mapped memory
+    pub fn read(&self) -> &[Vertex] {
+        let src = self.allocation.mapped_ptr().unwrap().cast().as_ptr();
+        unsafe { std::slice::from_raw_parts(src, self.vertex_count as usize) }
+    }
+
+    pub fn descriptor_info(&self) -> vk::DescriptorBufferInfo {
+        vk::DescriptorBufferInfo { buffer: self.buffer, offset: 0, range: vk::WHOLE_SIZE }
+    }
+
     pub fn get_buffer(&self) -> vk::Buffer { self.buffer }
     pub fn get_vertex_count(&self) -> u32 { self.vertex_count }
 }
\ No newline at end of file
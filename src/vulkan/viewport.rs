@@ -0,0 +1,94 @@
+use ash::vk;
+
+// How the scene is fitted into the window
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ViewportMode {
+    // Renders at the window size and fills it
+    #[default]
+    Fill,
+    // Keeps width / height, the rest of the window is covered by letterbox or pillarbox bars
+    FixedAspect(f32),
+    // Renders at a fixed resolution and scales it to the largest rect of the same aspect that fits
+    VirtualResolution(vk::Extent2D),
+}
+
+// Where the scene ends up in the window, recomputed whenever the window is resized
+#[derive(Clone, Copy, Debug)]
+pub struct ViewportLayout {
+    pub window_extent: vk::Extent2D,
+    // Size of the scene and post processing targets
+    pub render_extent: vk::Extent2D,
+    // Part of the window the scene is shown in, in physical pixels
+    pub rect: vk::Rect2D,
+}
+
+impl ViewportLayout {
+    pub fn new(mode: ViewportMode, window_extent: vk::Extent2D) -> Self {
+        let window_extent = vk::Extent2D { width: window_extent.width.max(1), height: window_extent.height.max(1) };
+
+        let (render_extent, rect) = match mode {
+            ViewportMode::Fill => (window_extent, vk::Rect2D { offset: vk::Offset2D { x: 0, y: 0 }, extent: window_extent }),
+            ViewportMode::FixedAspect(aspect) => {
+                let rect = Self::fit(window_extent, aspect);
+                (rect.extent, rect)
+            }
+            ViewportMode::VirtualResolution(resolution) => {
+                let resolution = vk::Extent2D { width: resolution.width.max(1), height: resolution.height.max(1) };
+                (resolution, Self::fit(window_extent, resolution.width as f32 / resolution.height as f32))
+            }
+        };
+
+        Self {
+            window_extent,
+            render_extent,
+            rect
+        }
+    }
+
+    // Largest rect with the aspect ratio centered in the window
+    fn fit(window_extent: vk::Extent2D, aspect: f32) -> vk::Rect2D {
+        let aspect = match aspect.is_finite() && aspect > 0.0 {
+            true => aspect,
+            false => 1.0
+        };
+
+        let window_aspect = window_extent.width as f32 / window_extent.height as f32;
+        let extent = match window_aspect > aspect {
+            // Wider than the target, bars left and right
+            true => vk::Extent2D {
+                width: ((window_extent.height as f32 * aspect).round() as u32).clamp(1, window_extent.width),
+                height: window_extent.height
+            },
+            false => vk::Extent2D {
+                width: window_extent.width,
+                height: ((window_extent.width as f32 / aspect).round() as u32).clamp(1, window_extent.height)
+            }
+        };
+
+        vk::Rect2D {
+            offset: vk::Offset2D {
+                x: ((window_extent.width - extent.width) / 2) as i32,
+                y: ((window_extent.height - extent.height) / 2) as i32
+            },
+            extent
+        }
+    }
+
+    // Window position in physical pixels to the matching position in the render extent, None on the bars
+    pub fn to_render(&self, x: f32, y: f32) -> Option<(f32, f32)> {
+        let x = x - self.rect.offset.x as f32;
+        let y = y - self.rect.offset.y as f32;
+        if x < 0.0 || y < 0.0 || x >= self.rect.extent.width as f32 || y >= self.rect.extent.height as f32 {
+            return None;
+        }
+
+        Some((
+            x * self.render_extent.width as f32 / self.rect.extent.width as f32,
+            y * self.render_extent.height as f32 / self.rect.extent.height as f32
+        ))
+    }
+
+    pub fn aspect_ratio(&self) -> f32 {
+        self.render_extent.width as f32 / self.render_extent.height as f32
+    }
+}
\ No newline at end of file
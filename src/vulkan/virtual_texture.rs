@@ -0,0 +1,583 @@
+// A texture far larger than fits on the device (terrain megatextures), split into pages of PAGE_SIZE texels on every
+// mip level. Only pages something on screen samples are resident, in the slots of a cache image, and a page table per
+// swapchain image points every page at its slot or at the slot of the closest coarser page that is resident. The
+// coarsest level is a single page that always is, so sampling never misses.
+// Which pages are wanted comes from a feedback pass drawing the virtual objects at 1/FEEDBACK_SCALE of the scene's
+// resolution, writing a flag per page into a buffer read back a frame later. Pages are generated by the `PageSource` on
+// the job pool and uploaded on the transfer queue, least recently used ones make room for them.
+// Sparse residency (VK_EXT/sparse binding) would let the cache be the texture itself, the page table here is what runs
+// on every device.
+
+use std::collections::{HashMap, VecDeque};
+
+use ash::vk;
+use gpu_allocator::vulkan::Allocator;
+use gpu_allocator::MemoryLocation;
+use rayon::prelude::*;
+
+use super::camera::ClearSettings;
+use super::command_pools::Pools;
+use super::descriptor_allocator::DescriptorAllocator;
+use super::descriptors::Descriptors;
+use super::image::{Image, create_sampler};
+use super::pipeline::{Pipeline, PipelineConfig};
+use super::queue::{QueueFamilies, Queues};
+use super::render_target::RenderTarget;
+use super::staging_buffer::StagingBuffer;
+use super::storage_buffer::StorageBuffer;
+use super::swapchain::VulkanSwapchain;
+use super::texture_streaming::MipChain;
+
+use crate::jobs;
+
+// Bound after the scene sets, like WATER_SET
+pub const VIRTUAL_TEXTURE_SET: u32 = 4;
+
+// Mirror the constants in shaders/include/virtual_texture.glsl
+pub const PAGE_SIZE: u32 = 128;
+// Texels repeated around every page in its slot, so filtering never reaches into the neighbouring one
+pub const PAGE_BORDER: u32 = 4;
+const SLOT_SIZE: u32 = PAGE_SIZE + 2 * PAGE_BORDER;
+// Slots across the cache, a page table entry has a byte for each coordinate
+const CACHE_SLOTS: u32 = 16;
+const FEEDBACK_SCALE: u32 = 8;
+
+// Pages generated and uploaded per frame at most
+const PAGES_PER_UPLOAD: usize = 8;
+// Frames a page stays wanted after the feedback last asked for it
+const WANT_FRAMES: u64 = 8;
+
+const VIRTUAL_FRAG: &[u32] = vk_shader_macros::include_glsl!("./shaders/basic.frag", kind: frag, define: VIRTUAL);
+const FEEDBACK_FRAG: &[u32] = vk_shader_macros::include_glsl!("./shaders/virtual_feedback.frag");
+
+// Texels of a virtual texture, asked for a page at a time from the job pool
+pub trait PageSource: Send + Sync {
+    // Texels across level 0, a power of two no smaller than PAGE_SIZE. The texture is square.
+    fn size(&self) -> u32;
+    // RGBA8 sRGB color of a texel of mip `level`, already filtered down from the finer levels
+    fn texel(&self, level: u32, x: u32, y: u32) -> [u8; 4];
+}
+
+// Square RGBA8 chains only, e.g. a megatexture loaded whole
+impl PageSource for MipChain {
+    fn size(&self) -> u32 {
+        assert_eq!(self.format, vk::Format::R8G8B8A8_UNORM, "Virtual textures are paged from uncompressed texels!");
+        self.extent.width
+    }
+
+    fn texel(&self, level: u32, x: u32, y: u32) -> [u8; 4] {
+        let offset = ((y * self.level_extent(level).width + x) * 4) as usize;
+        let texels = &self.levels[level as usize];
+        [texels[offset], texels[offset + 1], texels[offset + 2], texels[offset + 3]]
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+struct Page {
+    level: u32,
+    x: u32,
+    y: u32,
+}
+
+struct Resident {
+    slot: u32,
+    last_used: u64,
+}
+
+// Pages on their way into their slots
+struct PageUpload {
+    pages: Vec<(Page, u32)>,
+    staging: StagingBuffer,
+    command_buffer: vk::CommandBuffer,
+    fence: vk::Fence,
+}
+
+impl PageUpload {
+    fn release(&mut self, device: &ash::Device, allocator: &mut Allocator, pools: &Pools) {
+        self.staging.destroy(device, allocator);
+        unsafe {
+            device.free_command_buffers(pools.transfer_command_pool, &[self.command_buffer]);
+            device.destroy_fence(self.fence, None);
+        }
+    }
+}
+
+pub struct VirtualTexture {
+    source: Box<dyn PageSource>,
+    // Pages across level 0, and levels down to the single page
+    pages: u32,
+    levels: u32,
+    resident: HashMap<Page, Resident>,
+    // Pages the feedback asked for that aren't resident yet, with the frame it last did
+    wanted: HashMap<Page, u64>,
+    free_slots: Vec<u32>,
+    // Slots of evicted pages with the frame they were evicted in, free again once no frame in flight samples them
+    cooling: VecDeque<(u64, u32)>,
+    upload: Option<PageUpload>,
+    // Entries of the page table, rebuilt whenever the resident pages change
+    table: Vec<u32>,
+    version: u64,
+    // Version of the table in each image's buffer
+    written: Vec<u64>,
+    cache: Image,
+    sampler: vk::Sampler,
+    page_tables: Vec<StorageBuffer>,
+    requests: Vec<StorageBuffer>,
+    feedback: RenderTarget,
+    set_layout: vk::DescriptorSetLayout,
+    sets: Vec<vk::DescriptorSet>,
+    // Draws `Material::Virtual` in the scene pass, after the scene sets are bound
+    pub pipeline: Pipeline,
+    feedback_pipeline: Pipeline,
+}
+
+impl VirtualTexture {
+    // Uploads the coarsest page on the transfer queue and waits for it
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(device: &ash::Device, allocator: &mut Allocator, descriptors: &mut DescriptorAllocator, pools: &Pools, queues: &Queues,
+        queue_families: &QueueFamilies, image_count: usize, swapchain: &VulkanSwapchain, scene_target: &RenderTarget,
+        scene_config: &PipelineConfig, source: Box<dyn PageSource>
+    ) -> Result<Self, vk::Result> {
+        let size = source.size();
+        assert!(size.is_power_of_two() && size >= PAGE_SIZE, "Virtual texture size has to be a power of two of at least a page!");
+        let pages = size / PAGE_SIZE;
+        let levels = pages.trailing_zeros() + 1;
+
+        let mut families: Vec<u32> = queue_families.graphics.into_iter().chain(queue_families.transfer).collect();
+        families.dedup();
+        // Pages go up while the others are sampled, the cache never leaves the general layout for that
+        let cache_size = CACHE_SLOTS * SLOT_SIZE;
+        let cache = Image::new_mipmapped(device, allocator, vk::Extent2D { width: cache_size, height: cache_size }, vk::Format::R8G8B8A8_UNORM,
+            vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST, 1, &families, "Virtual Texture Cache")?;
+        pools.one_time_submit(device, queues.graphics_queue, |command_buffer| {
+            cache.transition_layout(device, command_buffer, vk::ImageLayout::UNDEFINED, vk::ImageLayout::GENERAL);
+        })?;
+        let sampler = create_sampler(device, vk::Filter::LINEAR, vk::SamplerAddressMode::CLAMP_TO_EDGE)?;
+
+        let entries = entry_count(pages, levels);
+        let page_tables = (0..image_count)
+            .map(|_| StorageBuffer::new(device, allocator, (4 + entries) as u64 * 4, MemoryLocation::CpuToGpu, "Virtual Texture Page Table"))
+            .collect();
+        let requests: Vec<StorageBuffer> = (0..image_count)
+            .map(|_| {
+                let mut buffer = StorageBuffer::with_usage(device, allocator, entries as u64 * 4, vk::BufferUsageFlags::TRANSFER_DST,
+                    MemoryLocation::GpuToCpu, "Virtual Texture Requests");
+                buffer.update_buffer(0, &vec![0u32; entries]);
+                buffer
+            })
+            .collect();
+
+        let feedback = RenderTarget::new(device, allocator, feedback_extent(scene_target.extent), &[], true, "Virtual Texture Feedback")?;
+
+        let set_layout = Descriptors::create_layout(device, &[
+            (vk::DescriptorType::STORAGE_BUFFER, vk::ShaderStageFlags::FRAGMENT),
+            (vk::DescriptorType::COMBINED_IMAGE_SAMPLER, vk::ShaderStageFlags::FRAGMENT),
+            (vk::DescriptorType::STORAGE_BUFFER, vk::ShaderStageFlags::FRAGMENT),
+        ])?;
+        let sets = descriptors.allocate_many(device, set_layout, image_count)?;
+        let (pipeline, feedback_pipeline) = Self::create_pipelines(device, swapchain, scene_target, &feedback, scene_config, set_layout)?;
+
+        let mut virtual_texture = Self {
+            source,
+            pages,
+            levels,
+            resident: HashMap::new(),
+            wanted: HashMap::new(),
+            free_slots: (0..CACHE_SLOTS * CACHE_SLOTS).rev().collect(),
+            cooling: VecDeque::new(),
+            upload: None,
+            table: Vec::new(),
+            version: 0,
+            written: vec![u64::MAX; image_count],
+            cache,
+            sampler,
+            page_tables,
+            requests,
+            feedback,
+            set_layout,
+            sets,
+            pipeline,
+            feedback_pipeline
+        };
+        virtual_texture.write_sets(device);
+
+        let root = Page { level: levels - 1, x: 0, y: 0 };
+        let slot = virtual_texture.free_slots.pop().expect("Virtual texture cache has no slots!");
+        virtual_texture.upload = Some(virtual_texture.start_upload(device, allocator, pools, queues.transfer_queue, vec![(root, slot)])?);
+        virtual_texture.finish_upload(device, allocator, pools, 0, true)?;
+
+        Ok(virtual_texture)
+    }
+
+    // The feedback pipeline draws into the feedback target with the scene's vertex shader, both have set 4 after the scene sets
+    fn create_pipelines(device: &ash::Device, swapchain: &VulkanSwapchain, scene_target: &RenderTarget, feedback: &RenderTarget,
+        scene_config: &PipelineConfig, set_layout: vk::DescriptorSetLayout
+    ) -> Result<(Pipeline, Pipeline), vk::Result> {
+        let set_layouts: Vec<vk::DescriptorSetLayout> = scene_config.set_layouts.iter().copied().chain([set_layout]).collect();
+        let config = PipelineConfig {
+            fragment_shader: VIRTUAL_FRAG,
+            set_layouts: &set_layouts,
+            ..*scene_config
+        };
+        let pipeline = Pipeline::new(device, swapchain, &scene_target.renderpass, &config)?;
+
+        let feedback_config = PipelineConfig {
+            fragment_shader: FEEDBACK_FRAG,
+            set_layouts: &set_layouts,
+            color_attachment_count: 0,
+            dynamic_shading_rate: false,
+            alpha_to_coverage: false,
+            samples: vk::SampleCountFlags::TYPE_1,
+            ..*scene_config
+        };
+        let feedback_pipeline = Pipeline::new(device, swapchain, &feedback.renderpass, &feedback_config)?;
+
+        Ok((pipeline, feedback_pipeline))
+    }
+
+    // With the scene pipelines, after `cleanup_pipelines`
+    pub fn recreate_pipelines(&mut self, device: &ash::Device, swapchain: &VulkanSwapchain, scene_target: &RenderTarget,
+        scene_config: &PipelineConfig
+    ) -> Result<(), vk::Result> {
+        let (pipeline, feedback_pipeline) = Self::create_pipelines(device, swapchain, scene_target, &self.feedback, scene_config,
+            self.set_layout)?;
+        self.pipeline = pipeline;
+        self.feedback_pipeline = feedback_pipeline;
+        Ok(())
+    }
+
+    pub fn cleanup_pipelines(&mut self, device: &ash::Device) {
+        self.pipeline.cleanup(device);
+        self.feedback_pipeline.cleanup(device);
+    }
+
+    // Follows the scene target's size, the device has to be idle
+    pub fn resize(&mut self, device: &ash::Device, allocator: &mut Allocator, extent: vk::Extent2D) -> Result<(), vk::Result> {
+        self.feedback.destroy(device, allocator);
+        self.feedback = RenderTarget::new(device, allocator, feedback_extent(extent), &[], true, "Virtual Texture Feedback")?;
+        Ok(())
+    }
+
+    fn write_sets(&self, device: &ash::Device) {
+        let cache = vk::DescriptorImageInfo {
+            sampler: self.sampler,
+            image_view: self.cache.view,
+            image_layout: vk::ImageLayout::GENERAL
+        };
+        for (index, &set) in self.sets.iter().enumerate() {
+            Descriptors::write_buffer(device, set, 0, vk::DescriptorType::STORAGE_BUFFER, self.page_tables[index].descriptor_info());
+            Descriptors::write_image(device, set, 1, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, cache);
+            Descriptors::write_buffer(device, set, 2, vk::DescriptorType::STORAGE_BUFFER, self.requests[index].descriptor_info());
+        }
+    }
+
+    // Resident pages in the cache right now
+    pub fn resident_pages(&self) -> usize {
+        self.resident.len()
+    }
+
+    // Takes in the pages the feedback of image `index` asked for and writes the current page table into its buffer.
+    // The image's last frame has to be done.
+    pub fn update(&mut self, index: usize, frame_index: u64) {
+        let mut requested = vec![0u32; entry_count(self.pages, self.levels)];
+        self.requests[index].read_buffer(0, &mut requested);
+
+        for (entry, _) in requested.iter().enumerate().filter(|&(_, &flag)| flag != 0) {
+            let page = self.page_at(entry as u32);
+            match self.resident.get_mut(&page) {
+                Some(resident) => resident.last_used = frame_index,
+                None => { self.wanted.insert(page, frame_index); }
+            }
+            // The coarser pages stand in until it's there, and keep standing in for its neighbours
+            for level in page.level + 1..self.levels {
+                let shift = level - page.level;
+                if let Some(resident) = self.resident.get_mut(&Page { level, x: page.x >> shift, y: page.y >> shift }) {
+                    resident.last_used = frame_index;
+                }
+            }
+        }
+
+        if self.written[index] != self.version {
+            self.page_tables[index].update_buffer(0, &[self.pages, self.levels, CACHE_SLOTS, 0]);
+            self.page_tables[index].update_buffer(16, &self.table);
+            self.written[index] = self.version;
+        }
+    }
+
+    // Swaps in a finished upload and starts uploading the next wanted pages on `queue`, a transfer queue. Slots
+    // evicted now are reused once `frames_in_flight` more frames have been submitted.
+    #[allow(clippy::too_many_arguments)]
+    pub fn stream(&mut self, device: &ash::Device, allocator: &mut Allocator, pools: &Pools, queue: vk::Queue, frame_index: u64,
+        frames_in_flight: u64
+    ) -> Result<(), vk::Result> {
+        crate::profile_scope!("Virtual Texture Streaming");
+        self.finish_upload(device, allocator, pools, frame_index, false)?;
+        if self.upload.is_some() {
+            return Ok(());
+        }
+
+        while let Some(&(frame, slot)) = self.cooling.front() {
+            if frame + frames_in_flight >= frame_index {
+                break;
+            }
+            self.free_slots.push(slot);
+            self.cooling.pop_front();
+        }
+        self.wanted.retain(|_, &mut frame| frame + WANT_FRAMES >= frame_index);
+
+        // Coarse pages first, they stand in for the most
+        let mut wanted: Vec<(Page, u64)> = self.wanted.iter().map(|(&page, &frame)| (page, frame)).collect();
+        wanted.sort_by_key(|&(page, frame)| (std::cmp::Reverse(page.level), std::cmp::Reverse(frame)));
+
+        let mut pages = Vec::new();
+        let mut evicted = false;
+        for &(page, _) in wanted.iter().take(PAGES_PER_UPLOAD) {
+            match self.free_slots.pop() {
+                Some(slot) => pages.push((page, slot)),
+                // Make room for the next frames, unless every page is still in use
+                None => match self.least_recently_used(frame_index, frames_in_flight) {
+                    Some(victim) => {
+                        let resident = self.resident.remove(&victim).expect("Evicted page isn't resident!");
+                        self.cooling.push_back((frame_index, resident.slot));
+                        evicted = true;
+                    },
+                    None => break
+                }
+            }
+        }
+        if evicted {
+            self.rebuild_table();
+        }
+
+        if !pages.is_empty() {
+            self.upload = Some(self.start_upload(device, allocator, pools, queue, pages)?);
+        }
+        Ok(())
+    }
+
+    // Waits for pages still going up and makes them resident, before the command pools are recreated
+    pub fn flush(&mut self, device: &ash::Device, allocator: &mut Allocator, pools: &Pools, frame_index: u64) -> Result<(), vk::Result> {
+        self.finish_upload(device, allocator, pools, frame_index, true)
+    }
+
+    // Pinned pages of the coarsest level and ones used by the frames in flight are never evicted
+    fn least_recently_used(&self, frame_index: u64, frames_in_flight: u64) -> Option<Page> {
+        self.resident
+            .iter()
+            .filter(|(page, resident)| page.level + 1 < self.levels && resident.last_used + frames_in_flight < frame_index)
+            .min_by_key(|(_, resident)| resident.last_used)
+            .map(|(&page, _)| page)
+    }
+
+    fn start_upload(&self, device: &ash::Device, allocator: &mut Allocator, pools: &Pools, queue: vk::Queue, pages: Vec<(Page, u32)>
+    ) -> Result<PageUpload, vk::Result> {
+        let source = &*self.source;
+        let texels: Vec<Vec<u8>> = jobs::pool().install(|| pages.par_iter().map(|&(page, _)| page_texels(source, page)).collect());
+
+        let slot_bytes = (SLOT_SIZE * SLOT_SIZE * 4) as u64;
+        let mut staging = StagingBuffer::new(device, allocator, slot_bytes * pages.len() as u64);
+        staging.update_buffer(0, &texels.concat());
+
+        // Slots start at multiples of SLOT_SIZE, which suits the image transfer granularity of transfer queues
+        let regions: Vec<vk::BufferImageCopy> = pages
+            .iter()
+            .enumerate()
+            .map(|(index, &(_, slot))| vk::BufferImageCopy::builder()
+                .buffer_offset(index as u64 * slot_bytes)
+                .image_subresource(vk::ImageSubresourceLayers {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    mip_level: 0,
+                    base_array_layer: 0,
+                    layer_count: 1
+                })
+                .image_offset(vk::Offset3D { x: ((slot % CACHE_SLOTS) * SLOT_SIZE) as i32, y: ((slot / CACHE_SLOTS) * SLOT_SIZE) as i32, z: 0 })
+                .image_extent(vk::Extent3D { width: SLOT_SIZE, height: SLOT_SIZE, depth: 1 })
+                .build())
+            .collect();
+
+        let allocate_info = vk::CommandBufferAllocateInfo::builder()
+            .level(vk::CommandBufferLevel::PRIMARY)
+            .command_pool(pools.transfer_command_pool)
+            .command_buffer_count(1);
+        let begin_info = vk::CommandBufferBeginInfo::builder()
+            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+
+        unsafe {
+            let command_buffer = device.allocate_command_buffers(&allocate_info)?[0];
+            device.begin_command_buffer(command_buffer, &begin_info)?;
+            device.cmd_copy_buffer_to_image(command_buffer, staging.get_buffer(), self.cache.image, vk::ImageLayout::GENERAL, &regions);
+            device.end_command_buffer(command_buffer)?;
+
+            let fence = device.create_fence(&vk::FenceCreateInfo::default(), None)?;
+            let command_buffers = [command_buffer];
+            let submit_info = [vk::SubmitInfo::builder()
+                .command_buffers(&command_buffers)
+                .build()
+            ];
+            device.queue_submit(queue, &submit_info, fence)?;
+
+            Ok(PageUpload { pages, staging, command_buffer, fence })
+        }
+    }
+
+    // Makes the pages of a finished upload resident, waiting for it with `wait`
+    fn finish_upload(&mut self, device: &ash::Device, allocator: &mut Allocator, pools: &Pools, frame_index: u64, wait: bool
+    ) -> Result<(), vk::Result> {
+        let Some(upload) = &mut self.upload else {
+            return Ok(());
+        };
+        let done = match wait {
+            true => unsafe { device.wait_for_fences(&[upload.fence], true, u64::MAX).map(|_| true)? },
+            false => unsafe { device.get_fence_status(upload.fence)? }
+        };
+        if !done {
+            return Ok(());
+        }
+
+        upload.release(device, allocator, pools);
+        for (page, slot) in std::mem::take(&mut upload.pages) {
+            self.wanted.remove(&page);
+            self.resident.insert(page, Resident { slot, last_used: frame_index });
+        }
+        self.upload = None;
+        self.rebuild_table();
+        Ok(())
+    }
+
+    // Every page points at itself when resident and at its closest resident ancestor otherwise. An entry holds the
+    // slot's coordinates in its low two bytes and the level of the page in it in the third.
+    fn rebuild_table(&mut self) {
+        let mut table = Vec::with_capacity(entry_count(self.pages, self.levels));
+        for level in 0..self.levels {
+            let pages = self.pages >> level;
+            for y in 0..pages {
+                for x in 0..pages {
+                    let entry = (level..self.levels)
+                        .find_map(|resident_level| {
+                            let shift = resident_level - level;
+                            self.resident
+                                .get(&Page { level: resident_level, x: x >> shift, y: y >> shift })
+                                .map(|resident| (resident.slot % CACHE_SLOTS) | (resident.slot / CACHE_SLOTS) << 8 | resident_level << 16)
+                        })
+                        .unwrap_or(0);
+                    table.push(entry);
+                }
+            }
+        }
+        self.table = table;
+        self.version += 1;
+    }
+
+    // The page of a page table entry
+    fn page_at(&self, mut entry: u32) -> Page {
+        for level in 0..self.levels {
+            let pages = self.pages >> level;
+            if entry < pages * pages {
+                return Page { level, x: entry % pages, y: entry / pages };
+            }
+            entry -= pages * pages;
+        }
+        panic!("Page table entry out of range!");
+    }
+
+    // Binds the page table and cache of image `index` for the virtual pipeline, after the scene sets
+    pub fn bind(&self, device: &ash::Device, command_buffer: vk::CommandBuffer, index: usize) {
+        unsafe {
+            device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::GRAPHICS, self.pipeline.layout, VIRTUAL_TEXTURE_SET,
+                &[self.sets[index]], &[]);
+        }
+    }
+
+    // Records the feedback pass for the scene view in `rect`, outside of any render pass. `draw` binds the scene sets
+    // for the pipeline it gets and draws the virtual objects with it.
+    pub fn record_feedback<F: FnOnce(&Pipeline)>(&self, device: &ash::Device, command_buffer: vk::CommandBuffer, index: usize,
+        rect: vk::Rect2D, clear: &ClearSettings, draw: F
+    ) {
+        let requests = &self.requests[index];
+        let clear_barriers = [vk::MemoryBarrier::builder()
+            .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+            .dst_access_mask(vk::AccessFlags::SHADER_WRITE)
+            .build()
+        ];
+        let read_barriers = [vk::MemoryBarrier::builder()
+            .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+            .dst_access_mask(vk::AccessFlags::HOST_READ)
+            .build()
+        ];
+        let rect = vk::Rect2D {
+            offset: vk::Offset2D { x: rect.offset.x / FEEDBACK_SCALE as i32, y: rect.offset.y / FEEDBACK_SCALE as i32 },
+            extent: feedback_extent(rect.extent)
+        };
+        // Only the depth is drawn, whatever the camera clears to
+        let clear = ClearSettings {
+            depth: clear.depth,
+            ..Default::default()
+        };
+
+        unsafe {
+            device.cmd_fill_buffer(command_buffer, requests.get_buffer(), 0, requests.get_size(), 0);
+            device.cmd_pipeline_barrier(command_buffer, vk::PipelineStageFlags::TRANSFER, vk::PipelineStageFlags::FRAGMENT_SHADER,
+                vk::DependencyFlags::empty(), &clear_barriers, &[], &[]);
+
+            self.feedback.begin_pass(device, command_buffer, rect, true, &clear);
+            device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, self.feedback_pipeline.pipeline);
+            device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::GRAPHICS, self.feedback_pipeline.layout, VIRTUAL_TEXTURE_SET,
+                &[self.sets[index]], &[]);
+            draw(&self.feedback_pipeline);
+            device.cmd_end_render_pass(command_buffer);
+
+            device.cmd_pipeline_barrier(command_buffer, vk::PipelineStageFlags::FRAGMENT_SHADER, vk::PipelineStageFlags::HOST,
+                vk::DependencyFlags::empty(), &read_barriers, &[], &[]);
+        }
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device, allocator: &mut Allocator, pools: &Pools, descriptors: &mut DescriptorAllocator
+    ) -> Result<(), vk::Result> {
+        if let Some(mut upload) = self.upload.take() {
+            let result = unsafe { device.wait_for_fences(&[upload.fence], true, u64::MAX) };
+            upload.release(device, allocator, pools);
+            result?;
+        }
+        for &set in &self.sets {
+            descriptors.free(device, set)?;
+        }
+        self.cleanup_pipelines(device);
+        self.feedback.destroy(device, allocator);
+        self.cache.destroy(device, allocator);
+        for buffer in self.page_tables.iter_mut().chain(&mut self.requests) {
+            buffer.destroy(device, allocator);
+        }
+        unsafe {
+            device.destroy_sampler(self.sampler, None);
+            device.destroy_descriptor_set_layout(self.set_layout, None);
+        }
+        Ok(())
+    }
+}
+
+// Entries of every level together, finest level first
+fn entry_count(pages: u32, levels: u32) -> usize {
+    (0..levels).map(|level| ((pages >> level) * (pages >> level)) as usize).sum()
+}
+
+fn feedback_extent(extent: vk::Extent2D) -> vk::Extent2D {
+    vk::Extent2D {
+        width: (extent.width / FEEDBACK_SCALE).max(1),
+        height: (extent.height / FEEDBACK_SCALE).max(1)
+    }
+}
+
+// The page's texels with its border, which repeats the edge of the texture where the page is at one
+fn page_texels(source: &dyn PageSource, page: Page) -> Vec<u8> {
+    let last = (source.size() >> page.level) as i64 - 1;
+    let origin = |coordinate: u32| (coordinate * PAGE_SIZE) as i64 - PAGE_BORDER as i64;
+    let mut texels = Vec::with_capacity((SLOT_SIZE * SLOT_SIZE * 4) as usize);
+    for y in 0..SLOT_SIZE {
+        let texel_y = (origin(page.y) + y as i64).clamp(0, last) as u32;
+        for x in 0..SLOT_SIZE {
+            let texel_x = (origin(page.x) + x as i64).clamp(0, last) as u32;
+            texels.extend_from_slice(&source.texel(page.level, texel_x, texel_y));
+        }
+    }
+    texels
+}
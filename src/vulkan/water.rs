@@ -0,0 +1,242 @@
+use ash::vk;
+use gpu_allocator::vulkan::Allocator;
+use serde::{Deserialize, Serialize};
+
+use super::command_pools::Pools;
+use super::descriptor_allocator::DescriptorAllocator;
+use super::descriptors::Descriptors;
+use super::image::{create_sampler, Image};
+use super::pipeline::{Pipeline, PipelineConfig};
+use super::post::HDR_FORMAT;
+use super::render_target::RenderTarget;
+use super::swapchain::VulkanSwapchain;
+
+pub const WATER_VERT: &[u32] = vk_shader_macros::include_glsl!("./shaders/basic.vert", kind: vert, define: WATER);
+pub const WATER_FRAG: &[u32] = vk_shader_macros::include_glsl!("./shaders/water.frag", kind: frag);
+
+// Set of the scene color and depth copies, after the scene sets
+pub const WATER_SET: u32 = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WaterReflections {
+    // Samples the planar reflection through the material texture like `Material::Reflective`, needs a reflection plane
+    // at the water's height
+    Planar,
+    // Left to the screen space reflection effect, which only reflects what's on screen
+    ScreenSpace,
+}
+
+// Component of game objects drawn with `Material::Water`. Their mesh is the calm surface, the waves move its vertices
+// so it needs enough of them to show the waves. A normal map adds ripples scrolling along with the waves. The object's
+// color tints what's seen through the water where it's shallow.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct WaterMaterial {
+    // Color the water fades to with depth
+    pub deep_color: uv::Vec3,
+    // Depth below the surface at which the floor has faded into `deep_color`
+    pub depth_fade: f32,
+    // Direction the waves travel in on the xz plane
+    pub wave_direction: uv::Vec2,
+    pub wave_length: f32,
+    pub wave_height: f32,
+    // In units per second
+    pub wave_speed: f32,
+    // How far the waves bend what's seen through the water, in screen space
+    pub refraction: f32,
+    // Depth up to which the shore has foam, 0.0 for none
+    pub foam_width: f32,
+    pub reflections: WaterReflections,
+}
+
+impl Default for WaterMaterial {
+    fn default() -> Self {
+        Self {
+            deep_color: uv::Vec3::new(0.01, 0.08, 0.12),
+            depth_fade: 4.0,
+            wave_direction: uv::Vec2::new(1.0, 0.3),
+            wave_length: 6.0,
+            wave_height: 0.1,
+            wave_speed: 1.5,
+            refraction: 0.02,
+            foam_width: 0.3,
+            reflections: WaterReflections::ScreenSpace
+        }
+    }
+}
+
+impl WaterMaterial {
+    // Packed as `MaterialData::water` in shaders/include/material.glsl expects it
+    pub fn uniform(&self) -> [uv::Vec4; 3] {
+        let direction = match self.wave_direction.mag_sq() > 0.0 {
+            true => self.wave_direction.normalized(),
+            false => uv::Vec2::unit_x()
+        };
+        let planar = match self.reflections {
+            WaterReflections::Planar => 1.0,
+            WaterReflections::ScreenSpace => 0.0
+        };
+        [
+            uv::Vec4::new(self.deep_color.x, self.deep_color.y, self.deep_color.z, self.depth_fade.max(0.001)),
+            uv::Vec4::new(direction.x, direction.y, self.wave_length.max(0.001), self.wave_height),
+            uv::Vec4::new(self.wave_speed, self.refraction, self.foam_width, planar)
+        ]
+    }
+}
+
+// Pass drawing the water surfaces into the scene after the opaque objects. Water has to see what's below it, so the
+// scene color and depth are copied out first and the surfaces sample the copies: the depth tells how much water the
+// view goes through (fading into the deep color, foam where the floor comes close) and the color, shifted by the
+// waves, is the refracted floor. Transparent objects are drawn after the water, over it.
+pub struct Water {
+    pub pipeline: Pipeline,
+    set_layout: vk::DescriptorSetLayout,
+    set: vk::DescriptorSet,
+    color: Image,
+    depth: Image,
+    sampler: vk::Sampler,
+}
+
+impl Water {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(device: &ash::Device, allocator: &mut Allocator, descriptors: &mut DescriptorAllocator, pools: &Pools, queue: vk::Queue,
+        swapchain: &VulkanSwapchain, scene_target: &RenderTarget, scene_config: &PipelineConfig
+    ) -> Result<Self, vk::Result> {
+        let set_layout = Descriptors::create_layout(device, &[
+            (vk::DescriptorType::COMBINED_IMAGE_SAMPLER, vk::ShaderStageFlags::FRAGMENT),
+            (vk::DescriptorType::COMBINED_IMAGE_SAMPLER, vk::ShaderStageFlags::FRAGMENT),
+        ])?;
+        let set = descriptors.allocate(device, set_layout)?;
+        let sampler = create_sampler(device, vk::Filter::LINEAR, vk::SamplerAddressMode::CLAMP_TO_EDGE)?;
+        let (color, depth) = Self::create_copies(device, allocator, pools, queue, scene_target.extent)?;
+        let pipeline = Self::create_pipeline(device, swapchain, scene_target, scene_config, set_layout)?;
+
+        let water = Self {
+            pipeline,
+            set_layout,
+            set,
+            color,
+            depth,
+            sampler
+        };
+        water.write_set(device);
+
+        Ok(water)
+    }
+
+    // Both copies start out in the layouts `record_copy` leaves them in
+    fn create_copies(device: &ash::Device, allocator: &mut Allocator, pools: &Pools, queue: vk::Queue, extent: vk::Extent2D
+    ) -> Result<(Image, Image), vk::Result> {
+        let usage = vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST;
+        let color = Image::new(device, allocator, extent, HDR_FORMAT, usage, vk::ImageAspectFlags::COLOR, "Water Scene Color")?;
+        let depth = Image::new_depth_stencil(device, allocator, extent, usage, "Water Scene Depth")?;
+        pools.one_time_submit(device, queue, |command_buffer| {
+            color.transition_layout(device, command_buffer, vk::ImageLayout::UNDEFINED, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+            depth.transition_layout(device, command_buffer, vk::ImageLayout::UNDEFINED, vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL);
+        })?;
+
+        Ok((color, depth))
+    }
+
+    // Vertices come from the object's own buffers like the transparent pipelines', and water is shaded per pixel
+    pub fn create_pipeline(device: &ash::Device, swapchain: &VulkanSwapchain, scene_target: &RenderTarget, scene_config: &PipelineConfig,
+        set_layout: vk::DescriptorSetLayout
+    ) -> Result<Pipeline, vk::Result> {
+        let set_layouts: Vec<vk::DescriptorSetLayout> = scene_config.set_layouts.iter().copied().chain([set_layout]).collect();
+        let config = PipelineConfig {
+            vertex_shader: WATER_VERT,
+            fragment_shader: WATER_FRAG,
+            set_layouts: &set_layouts,
+            vertex_input: true,
+            dynamic_shading_rate: false,
+            ..*scene_config
+        };
+        Pipeline::new(device, swapchain, &scene_target.renderpass, &config)
+    }
+
+    pub fn set_layout(&self) -> vk::DescriptorSetLayout {
+        self.set_layout
+    }
+
+    fn write_set(&self, device: &ash::Device) {
+        let color = vk::DescriptorImageInfo {
+            sampler: self.sampler,
+            image_view: self.color.view,
+            image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
+        };
+        let depth = vk::DescriptorImageInfo {
+            sampler: self.sampler,
+            image_view: self.depth.depth_view.expect("Depth copy has no depth view!"),
+            image_layout: vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL
+        };
+        Descriptors::write_image(device, self.set, 0, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, color);
+        Descriptors::write_image(device, self.set, 1, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, depth);
+    }
+
+    // Follows the scene target's size, the device may not be using the copies
+    pub fn resize(&mut self, device: &ash::Device, allocator: &mut Allocator, pools: &Pools, queue: vk::Queue, extent: vk::Extent2D
+    ) -> Result<(), vk::Result> {
+        self.color.destroy(device, allocator);
+        self.depth.destroy(device, allocator);
+        let (color, depth) = Self::create_copies(device, allocator, pools, queue, extent)?;
+        self.color = color;
+        self.depth = depth;
+        self.write_set(device);
+        Ok(())
+    }
+
+    // Copies the scene color and depth between the opaque and the water pass, outside of any render pass. The scene
+    // target is left in the layouts its passes leave it in, so its load pass can continue right after.
+    pub fn record_copy(&self, device: &ash::Device, command_buffer: vk::CommandBuffer, scene_target: &RenderTarget) {
+        let scene_color = &scene_target.colors[0];
+        let scene_depth = scene_target.depth.as_ref().expect("Scene target has no depth attachment!");
+        let copies = [
+            (scene_color, &self.color, vk::ImageAspectFlags::COLOR, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL),
+            (scene_depth, &self.depth, vk::ImageAspectFlags::DEPTH, vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL)
+        ];
+
+        for (source, destination, aspect_mask, layout) in copies {
+            source.transition_layout(device, command_buffer, layout, vk::ImageLayout::TRANSFER_SRC_OPTIMAL);
+            destination.transition_layout(device, command_buffer, layout, vk::ImageLayout::TRANSFER_DST_OPTIMAL);
+
+            let subresource = vk::ImageSubresourceLayers {
+                aspect_mask,
+                mip_level: 0,
+                base_array_layer: 0,
+                layer_count: 1
+            };
+            let region = vk::ImageCopy {
+                src_subresource: subresource,
+                src_offset: vk::Offset3D::default(),
+                dst_subresource: subresource,
+                dst_offset: vk::Offset3D::default(),
+                extent: vk::Extent3D { width: scene_target.extent.width, height: scene_target.extent.height, depth: 1 }
+            };
+            unsafe {
+                device.cmd_copy_image(command_buffer, source.image, vk::ImageLayout::TRANSFER_SRC_OPTIMAL, destination.image,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL, &[region]);
+            }
+
+            source.transition_layout(device, command_buffer, vk::ImageLayout::TRANSFER_SRC_OPTIMAL, layout);
+            destination.transition_layout(device, command_buffer, vk::ImageLayout::TRANSFER_DST_OPTIMAL, layout);
+        }
+    }
+
+    // Binds the copies for `pipeline`, after the scene sets
+    pub fn bind(&self, device: &ash::Device, command_buffer: vk::CommandBuffer) {
+        unsafe {
+            device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::GRAPHICS, self.pipeline.layout, WATER_SET, &[self.set], &[]);
+        }
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device, allocator: &mut Allocator, descriptors: &mut DescriptorAllocator) -> Result<(), vk::Result> {
+        descriptors.free(device, self.set)?;
+        self.pipeline.cleanup(device);
+        self.color.destroy(device, allocator);
+        self.depth.destroy(device, allocator);
+        unsafe {
+            device.destroy_sampler(self.sampler, None);
+            device.destroy_descriptor_set_layout(self.set_layout, None);
+        }
+        Ok(())
+    }
+}
\ No newline at end of file
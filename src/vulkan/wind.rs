@@ -0,0 +1,57 @@
+use serde::{Deserialize, Serialize};
+
+// Wind blowing over the whole scene, read by the scene's vertex shaders through the camera block. Materials sway with
+// it by their `GameObject::wind` response and their vertices by how far they are above the object's origin, scaled
+// down by the stiffness in the vertex color's red channel (0.0 sways freely, 1.0 stays put). Only the color pass
+// sways, shadows keep the rest pose.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Wind {
+    // Direction the wind blows in on the xz plane
+    pub direction: uv::Vec2,
+    // Steady bend, in units per unit of height above the object's origin
+    pub strength: f32,
+    // Extra bend at the peak of a gust
+    pub gust_strength: f32,
+    // Distance between gusts as they roll over the scene
+    pub gust_size: f32,
+    // In units per second
+    pub gust_speed: f32,
+    // Quick sway of every plant on its own, in radians per second
+    pub flutter: f32,
+}
+
+impl Default for Wind {
+    fn default() -> Self {
+        Self {
+            direction: uv::Vec2::new(1.0, 0.0),
+            strength: 0.05,
+            gust_strength: 0.1,
+            gust_size: 20.0,
+            gust_speed: 4.0,
+            flutter: 3.0
+        }
+    }
+}
+
+impl Wind {
+    // No wind at all, vertices stay where the mesh has them
+    pub fn calm() -> Self {
+        Self {
+            strength: 0.0,
+            gust_strength: 0.0,
+            ..Self::default()
+        }
+    }
+
+    // Packed as the camera block's `wind` and `wind_gusts` in shaders/include/camera.glsl
+    pub fn uniform(&self) -> [uv::Vec4; 2] {
+        let direction = match self.direction.mag_sq() > 0.0 {
+            true => self.direction.normalized(),
+            false => uv::Vec2::unit_x()
+        };
+        [
+            uv::Vec4::new(direction.x, direction.y, self.strength, self.gust_strength),
+            uv::Vec4::new(self.gust_size.max(0.001), self.gust_speed, self.flutter, 0.0)
+        ]
+    }
+}
\ No newline at end of file
@@ -1,27 +1,261 @@
-use winit::event_loop::EventLoop;
-use winit::window::Window;
+use winit::event_loop::{EventLoop, EventLoopBuilder};
+use winit::monitor::{MonitorHandle, VideoMode};
+use winit::window::{Fullscreen, Window};
 
 use anyhow::Result;
 
+// Display server the window is created on. Only Linux and the BSDs have a choice, everywhere else it's `Auto`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum WindowBackend {
+    // Wayland when a compositor is running, X11 otherwise (or whatever WINIT_UNIX_BACKEND says)
+    #[default]
+    Auto,
+    Wayland,
+    X11,
+}
+
+// How the window covers a monitor. Monitors and video modes are indices into `VulkanWindow::monitors` and the monitor's
+// `video_modes`, `None` is the monitor the window is on (the primary one before it exists) and its native resolution.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FullscreenMode {
+    #[default]
+    Windowed,
+    // A window the size of the monitor without decorations, switching in and out of it is instant
+    Borderless { monitor: Option<usize> },
+    // Changes the monitor's video mode and gives the window the whole display, which is what lets the swapchain
+    // skip the compositor. On Windows the swapchain also takes VK_EXT_full_screen_exclusive, see `FullScreenExclusive`.
+    Exclusive { monitor: Option<usize>, video_mode: Option<usize> },
+}
+
+// Looks and stacking of the window, for overlay tools and widgets as much as for games
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WindowStyle {
+    // Let what's behind the window show through wherever the frame's alpha is below one. Only takes effect where the
+    // surface can blend, see `WindowAlpha`, and can't be changed once the window exists.
+    pub transparent: bool,
+    // Above every window that isn't, even while another one has focus
+    pub always_on_top: bool,
+    // Title bar and borders
+    pub decorations: bool,
+}
+
+impl Default for WindowStyle {
+    fn default() -> Self {
+        Self {
+            transparent: false,
+            always_on_top: false,
+            decorations: true
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VideoModeInfo {
+    pub width: u32,
+    pub height: u32,
+    pub refresh_rate_millihertz: u32,
+    pub bit_depth: u16,
+}
+
+#[derive(Clone, Debug)]
+pub struct MonitorInfo {
+    pub name: String,
+    // Top left corner on the virtual desktop, in pixels
+    pub position: (i32, i32),
+    pub width: u32,
+    pub height: u32,
+    // What the system currently runs it at, unknown on some platforms
+    pub refresh_rate_millihertz: Option<u32>,
+    pub scale_factor: f64,
+    pub video_modes: Vec<VideoModeInfo>,
+}
+
+impl MonitorInfo {
+    fn new(monitor: &MonitorHandle) -> Self {
+        let size = monitor.size();
+        let position = monitor.position();
+        Self {
+            name: monitor.name().unwrap_or_default(),
+            position: (position.x, position.y),
+            width: size.width,
+            height: size.height,
+            refresh_rate_millihertz: monitor.refresh_rate_millihertz(),
+            scale_factor: monitor.scale_factor(),
+            video_modes: sorted_video_modes(monitor).iter().map(|mode| VideoModeInfo {
+                width: mode.size().width,
+                height: mode.size().height,
+                refresh_rate_millihertz: mode.refresh_rate_millihertz(),
+                bit_depth: mode.bit_depth()
+            }).collect()
+        }
+    }
+}
+
 pub struct VulkanWindow {
     pub window: Window,
     pub width: u32,
-    pub height: u32
+    pub height: u32,
+    // The backend the window ended up on, never `Auto` where there is a choice
+    pub backend: WindowBackend,
+    pub style: WindowStyle,
 }
 
 impl VulkanWindow {
-    pub fn create_window(title: &'static str, width: u32, height: u32) -> Result<(EventLoop<()>, Self)> {
-        let event_loop = EventLoop::new();
+    // An invisible window is for runs nobody watches
+    pub fn create_window(title: &'static str, width: u32, height: u32, fullscreen: FullscreenMode, style: WindowStyle, visible: bool,
+        backend: WindowBackend
+    ) -> Result<(EventLoop<()>, Self)> {
+        let mut builder = EventLoopBuilder::new();
+        select_backend(&mut builder, backend);
+        let event_loop = builder.build();
+        let fullscreen = resolve_fullscreen(fullscreen, event_loop.available_monitors().collect(), event_loop.primary_monitor());
         let window = winit::window::WindowBuilder::new()
             .with_title(title)
             .with_inner_size(winit::dpi::LogicalSize::new(width, height))
+            .with_fullscreen(fullscreen)
+            .with_transparent(style.transparent)
+            .with_always_on_top(style.always_on_top)
+            .with_decorations(style.decorations)
+            .with_visible(visible)
             .build(&event_loop)
             .expect("Failed to create window.");
 
+        let backend = used_backend(&event_loop);
+        tracing::info!("Created the window on the {:?} backend", backend);
+
         Ok((event_loop, Self {
                 window,
                 width,
-                height
+                height,
+                backend,
+                style
         }))
     }
-}
\ No newline at end of file
+
+    // Size of the window's surface in pixels. Wayland has no size of its own for the surface, the swapchain takes this one.
+    pub fn extent(&self) -> ash::vk::Extent2D {
+        let size = self.window.inner_size();
+        ash::vk::Extent2D { width: size.width, height: size.height }
+    }
+
+    // Every connected monitor, in the order `FullscreenMode` indexes them
+    pub fn monitors(&self) -> Vec<MonitorInfo> {
+        self.window.available_monitors().map(|monitor| MonitorInfo::new(&monitor)).collect()
+    }
+
+    // The monitor the window is mostly on, `None` if the platform can't tell
+    pub fn current_monitor(&self) -> Option<MonitorInfo> {
+        self.window.current_monitor().map(|monitor| MonitorInfo::new(&monitor))
+    }
+
+    // The swapchain follows with the next `Resized` event. Renderers using VK_EXT_full_screen_exclusive should be told
+    // through `VulkanRenderer::update_fullscreen` afterwards.
+    pub fn set_fullscreen(&self, mode: FullscreenMode) {
+        let fullscreen = resolve_fullscreen(mode, self.window.available_monitors().collect(), self.window.current_monitor());
+        self.window.set_fullscreen(fullscreen);
+    }
+
+    pub fn set_always_on_top(&mut self, always_on_top: bool) {
+        self.window.set_always_on_top(always_on_top);
+        self.style.always_on_top = always_on_top;
+    }
+
+    pub fn set_decorations(&mut self, decorations: bool) {
+        self.window.set_decorations(decorations);
+        self.style.decorations = decorations;
+    }
+
+    // Of the monitor the window is on in Hz, the video mode's rate in exclusive fullscreen. `None` where the platform
+    // doesn't report it.
+    pub fn refresh_rate(&self) -> Option<f32> {
+        let millihertz = match self.window.fullscreen() {
+            Some(Fullscreen::Exclusive(mode)) => Some(mode.refresh_rate_millihertz()),
+            _ => self.window.current_monitor().and_then(|monitor| monitor.refresh_rate_millihertz())
+        };
+        millihertz.filter(|rate| *rate > 0).map(|rate| rate as f32 / 1000.0)
+    }
+
+    // The monitor the window exclusively owns, if any
+    pub fn exclusive_monitor(&self) -> Option<MonitorHandle> {
+        match self.window.fullscreen() {
+            Some(Fullscreen::Exclusive(mode)) => Some(mode.monitor()),
+            _ => None
+        }
+    }
+}
+
+// Largest and fastest first
+fn sorted_video_modes(monitor: &MonitorHandle) -> Vec<VideoMode> {
+    let mut modes: Vec<VideoMode> = monitor.video_modes().collect();
+    modes.sort_by_key(|mode| std::cmp::Reverse((mode.size().width * mode.size().height, mode.refresh_rate_millihertz(), mode.bit_depth())));
+    modes
+}
+
+fn resolve_fullscreen(mode: FullscreenMode, monitors: Vec<MonitorHandle>, current: Option<MonitorHandle>) -> Option<Fullscreen> {
+    let pick = |index: Option<usize>| match index {
+        Some(index) => monitors.get(index).cloned().or_else(|| {
+            tracing::warn!("There's no monitor {}, using the current one", index);
+            current.clone()
+        }),
+        None => current.clone()
+    }.or_else(|| monitors.first().cloned());
+
+    match mode {
+        FullscreenMode::Windowed => None,
+        FullscreenMode::Borderless { monitor } => Some(Fullscreen::Borderless(pick(monitor))),
+        FullscreenMode::Exclusive { monitor, video_mode } => {
+            let monitor = pick(monitor)?;
+            let modes = sorted_video_modes(&monitor);
+            // The native resolution at the highest refresh rate it has
+            let native = modes.iter().find(|mode| mode.size() == monitor.size()).or(modes.first());
+            let mode = match video_mode {
+                Some(index) => modes.get(index).or_else(|| {
+                    tracing::warn!("Monitor {} has no video mode {}, using its native one", monitor.name().unwrap_or_default(), index);
+                    native
+                }),
+                None => native
+            };
+
+            match mode {
+                Some(mode) => Some(Fullscreen::Exclusive(mode.clone())),
+                None => {
+                    tracing::warn!("Monitor {} lists no video modes, going borderless instead", monitor.name().unwrap_or_default());
+                    Some(Fullscreen::Borderless(Some(monitor)))
+                }
+            }
+        }
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "dragonfly", target_os = "freebsd", target_os = "netbsd", target_os = "openbsd"))]
+fn select_backend(builder: &mut EventLoopBuilder<()>, backend: WindowBackend) {
+    use winit::platform::unix::EventLoopBuilderExtUnix;
+
+    match backend {
+        WindowBackend::Auto => {}
+        WindowBackend::Wayland => { builder.with_wayland(); }
+        WindowBackend::X11 => { builder.with_x11(); }
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "dragonfly", target_os = "freebsd", target_os = "netbsd", target_os = "openbsd")))]
+fn select_backend(_builder: &mut EventLoopBuilder<()>, backend: WindowBackend) {
+    if backend != WindowBackend::Auto {
+        tracing::warn!("There's no {:?} backend on this platform, using the native one", backend);
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "dragonfly", target_os = "freebsd", target_os = "netbsd", target_os = "openbsd"))]
+fn used_backend(event_loop: &EventLoop<()>) -> WindowBackend {
+    use winit::platform::unix::EventLoopWindowTargetExtUnix;
+
+    match event_loop.is_wayland() {
+        true => WindowBackend::Wayland,
+        false => WindowBackend::X11
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "dragonfly", target_os = "freebsd", target_os = "netbsd", target_os = "openbsd")))]
+fn used_backend(_event_loop: &EventLoop<()>) -> WindowBackend {
+    WindowBackend::Auto
+}
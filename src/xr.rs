@@ -0,0 +1,436 @@
+// OpenXR headsets, run with `cargo run --example xr --features xr`. The runtime creates the renderer's Vulkan instance
+// and device, both eyes are drawn side by side as split views into an offscreen frame and copied into a swapchain per eye.
+
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use ash::vk::{self, Handle};
+use openxr as xr;
+
+use crate::assets::{AssetKind, AssetManager};
+use crate::vulkan::camera::{Camera, Fov};
+use crate::vulkan::lights::PointLight;
+use crate::vulkan::logical_device::DeviceFactory;
+use crate::vulkan::renderer::{RendererSettings, VulkanRenderer};
+
+const VIEW_TYPE: xr::ViewConfigurationType = xr::ViewConfigurationType::PRIMARY_STEREO;
+// Offscreen frames are B8G8R8A8_UNORM with gamma already applied, they're copied bit for bit into sRGB images
+const SWAPCHAIN_FORMAT: vk::Format = vk::Format::B8G8R8A8_SRGB;
+// Meters per second and radians per second with the thumbstick all the way out
+const MOVE_SPEED: f32 = 2.0;
+const TURN_SPEED: f32 = 1.5;
+const STICK_DEAD_ZONE: f32 = 0.15;
+// How far along the aim ray selecting looks for objects
+const AIM_DISTANCE: f32 = 100.0;
+
+// The runtime and the headset, created before the renderer since they decide which GPU it renders on
+pub struct XrContext {
+    pub instance: xr::Instance,
+    pub system: xr::SystemId,
+    blend_mode: xr::EnvironmentBlendMode,
+}
+
+impl XrContext {
+    pub fn new() -> Result<Self> {
+        let entry = unsafe { xr::Entry::load() }.map_err(|error| anyhow!("Failed to load the OpenXR loader: {:?}", error))?;
+        if !entry.enumerate_extensions()?.khr_vulkan_enable2 {
+            return Err(anyhow!("The OpenXR runtime can't render with Vulkan"));
+        }
+
+        let mut extensions = xr::ExtensionSet::default();
+        extensions.khr_vulkan_enable2 = true;
+        let instance = entry.create_instance(&xr::ApplicationInfo {
+            application_name: "Reverie",
+            application_version: 1,
+            engine_name: "Reverie",
+            engine_version: 1
+        }, &extensions, &[])?;
+        let properties = instance.properties()?;
+        tracing::info!("Using OpenXR runtime {} v{}", properties.runtime_name, properties.runtime_version);
+
+        let system = instance.system(xr::FormFactor::HEAD_MOUNTED_DISPLAY)?;
+        let blend_mode = *instance.enumerate_environment_blend_modes(system, VIEW_TYPE)?
+            .first()
+            .ok_or_else(|| anyhow!("The headset has no way to show stereo views"))?;
+
+        // The renderer asks for Vulkan 1.3
+        let requirements = instance.graphics_requirements::<xr::Vulkan>(system)?;
+        let minimum = requirements.min_api_version_supported;
+        if (minimum.major(), minimum.minor()) > (1, 3) {
+            return Err(anyhow!("The OpenXR runtime needs at least Vulkan {}.{}", minimum.major(), minimum.minor()));
+        }
+
+        Ok(Self { instance, system, blend_mode })
+    }
+
+    // Size of the image the runtime recommends for each eye
+    pub fn eye_extent(&self) -> Result<vk::Extent2D> {
+        let views = self.instance.enumerate_view_configuration_views(self.system, VIEW_TYPE)?;
+        let view = views.first().ok_or_else(|| anyhow!("The headset has no views"))?;
+        Ok(vk::Extent2D { width: view.recommended_image_rect_width, height: view.recommended_image_rect_height })
+    }
+}
+
+fn xr_result<T>(result: xr::Result<T>) -> Result<T, vk::Result> {
+    result.map_err(|error| {
+        tracing::error!("OpenXR failed to set up Vulkan: {}", error);
+        vk::Result::ERROR_INITIALIZATION_FAILED
+    })
+}
+
+fn get_instance_proc_addr(entry: &ash::Entry) -> xr::sys::platform::VkGetInstanceProcAddr {
+    unsafe { std::mem::transmute::<vk::PFN_vkGetInstanceProcAddr, xr::sys::platform::VkGetInstanceProcAddr>(entry.static_fn().get_instance_proc_addr) }
+}
+
+// The runtime adds the extensions it needs to the renderer's and picks the GPU the headset is connected to
+impl DeviceFactory for XrContext {
+    fn create_instance(&self, entry: &ash::Entry, create_info: &vk::InstanceCreateInfo) -> Result<ash::Instance, vk::Result> {
+        let instance = unsafe {
+            xr_result(self.instance.create_vulkan_instance(self.system, get_instance_proc_addr(entry),
+                (create_info as *const vk::InstanceCreateInfo).cast()))?
+        }.map_err(vk::Result::from_raw)?;
+
+        Ok(unsafe { ash::Instance::load(entry.static_fn(), vk::Instance::from_raw(instance as u64)) })
+    }
+
+    fn physical_device(&self, instance: &ash::Instance) -> Result<vk::PhysicalDevice, vk::Result> {
+        let physical_device = xr_result(unsafe { self.instance.vulkan_graphics_device(self.system, instance.handle().as_raw() as _) })?;
+        Ok(vk::PhysicalDevice::from_raw(physical_device as u64))
+    }
+
+    fn create_device(&self, instance: &ash::Instance, physical_device: vk::PhysicalDevice, create_info: &vk::DeviceCreateInfo
+    ) -> Result<ash::Device, vk::Result> {
+        let entry = ash::Entry::linked();
+        let device = unsafe {
+            xr_result(self.instance.create_vulkan_device(self.system, get_instance_proc_addr(&entry), physical_device.as_raw() as _,
+                (create_info as *const vk::DeviceCreateInfo).cast()))?
+        }.map_err(vk::Result::from_raw)?;
+
+        Ok(unsafe { ash::Device::load(instance.fp_v1_0(), vk::Device::from_raw(device as u64)) })
+    }
+}
+
+// Where the stage (the tracked play area) is in the world. Thumbsticks move it around, the headset and controllers
+// are tracked relative to it.
+#[derive(Clone, Copy, Debug, Default)]
+struct PlayArea {
+    origin: uv::Vec3,
+    yaw: f32,
+}
+
+impl PlayArea {
+    fn direction(&self, direction: uv::Vec3) -> uv::Vec3 {
+        uv::Mat3::from_rotation_y(self.yaw) * direction
+    }
+
+    fn point(&self, point: xr::Vector3f) -> uv::Vec3 {
+        self.origin + self.direction(uv::Vec3::new(point.x, point.y, point.z))
+    }
+
+    // World space position and view direction, up included
+    fn pose(&self, pose: xr::Posef) -> (uv::Vec3, uv::Vec3, uv::Vec3) {
+        let forward = self.direction(rotate(pose.orientation, -uv::Vec3::unit_z()));
+        let up = self.direction(rotate(pose.orientation, uv::Vec3::unit_y()));
+        (self.point(pose.position), forward, up)
+    }
+
+    fn pose_camera(&self, camera: &mut Camera, view: &xr::View) {
+        let (position, forward, up) = self.pose(view.pose);
+        camera.position = position;
+        camera.target = position + forward;
+        camera.up = up;
+        camera.fov = Some(Fov {
+            left: view.fov.angle_left,
+            right: view.fov.angle_right,
+            up: view.fov.angle_up,
+            down: view.fov.angle_down
+        });
+    }
+}
+
+fn rotate(orientation: xr::Quaternionf, vector: uv::Vec3) -> uv::Vec3 {
+    let axis = uv::Vec3::new(orientation.x, orientation.y, orientation.z);
+    let t = 2.0 * axis.cross(vector);
+    vector + orientation.w * t + axis.cross(t)
+}
+
+fn stick(state: xr::ActionState<xr::Vector2f>) -> uv::Vec2 {
+    let value = uv::Vec2::new(state.current_state.x, state.current_state.y);
+    match state.is_active && value.mag() > STICK_DEAD_ZONE {
+        true => value,
+        false => uv::Vec2::zero()
+    }
+}
+
+// Left thumbstick moves, right thumbstick turns, the right trigger (or select button) selects what the right controller
+// points at. Bindings are suggested for the Oculus Touch and the generic simple controller, runtimes remap them for others.
+struct Actions {
+    set: xr::ActionSet,
+    locomotion: xr::Action<xr::Vector2f>,
+    turn: xr::Action<xr::Vector2f>,
+    select: xr::Action<bool>,
+    aim_space: xr::Space,
+}
+
+impl Actions {
+    fn new(xr: &XrContext, session: &xr::Session<xr::Vulkan>) -> Result<Self> {
+        let instance = &xr.instance;
+        let set = instance.create_action_set("reverie", "Reverie", 0)?;
+        let locomotion = set.create_action::<xr::Vector2f>("move", "Move", &[])?;
+        let turn = set.create_action::<xr::Vector2f>("turn", "Turn", &[])?;
+        let select = set.create_action::<bool>("select", "Select", &[])?;
+        let aim = set.create_action::<xr::Posef>("aim", "Aim", &[])?;
+
+        let path = |path: &str| instance.string_to_path(path);
+        instance.suggest_interaction_profile_bindings(path("/interaction_profiles/oculus/touch_controller")?, &[
+            xr::Binding::new(&locomotion, path("/user/hand/left/input/thumbstick")?),
+            xr::Binding::new(&turn, path("/user/hand/right/input/thumbstick")?),
+            xr::Binding::new(&select, path("/user/hand/right/input/trigger/value")?),
+            xr::Binding::new(&aim, path("/user/hand/right/input/aim/pose")?),
+        ])?;
+        instance.suggest_interaction_profile_bindings(path("/interaction_profiles/khr/simple_controller")?, &[
+            xr::Binding::new(&select, path("/user/hand/right/input/select/click")?),
+            xr::Binding::new(&aim, path("/user/hand/right/input/aim/pose")?),
+        ])?;
+        session.attach_action_sets(&[&set])?;
+
+        let aim_space = aim.create_space(session.clone(), xr::Path::NULL, xr::Posef::IDENTITY)?;
+
+        Ok(Self { set, locomotion, turn, select, aim_space })
+    }
+}
+
+// A running session on the headset. Has to be dropped before the renderer it draws with.
+pub struct XrSession {
+    session: xr::Session<xr::Vulkan>,
+    frame_waiter: xr::FrameWaiter,
+    frame_stream: xr::FrameStream<xr::Vulkan>,
+    stage: xr::Space,
+    // One per eye with its images
+    swapchains: Vec<(xr::Swapchain<xr::Vulkan>, Vec<vk::Image>)>,
+    eye_extent: vk::Extent2D,
+    actions: Actions,
+    play_area: PlayArea,
+    running: bool,
+    events: xr::EventDataBuffer,
+}
+
+impl XrSession {
+    // `renderer` has to come from `VulkanRenderer::new_offscreen_with` with `xr` as the factory and room for both eyes
+    // side by side. Its split views are replaced by one for each eye.
+    pub fn new(xr: &XrContext, renderer: &mut VulkanRenderer) -> Result<Self> {
+        let (session, frame_waiter, frame_stream) = unsafe {
+            xr.instance.create_session::<xr::Vulkan>(xr.system, &xr::vulkan::SessionCreateInfo {
+                instance: renderer.instance.handle().as_raw() as _,
+                physical_device: renderer.physical_device.as_raw() as _,
+                device: renderer.device.handle().as_raw() as _,
+                queue_family_index: renderer.queue_families.graphics.unwrap(),
+                queue_index: 0
+            })?
+        };
+
+        // Seated runtimes may not track a stage, the local space is always there
+        let space_type = match session.enumerate_reference_spaces()?.contains(&xr::ReferenceSpaceType::STAGE) {
+            true => xr::ReferenceSpaceType::STAGE,
+            false => xr::ReferenceSpaceType::LOCAL
+        };
+        let stage = session.create_reference_space(space_type, xr::Posef::IDENTITY)?;
+
+        if !session.enumerate_swapchain_formats()?.contains(&(SWAPCHAIN_FORMAT.as_raw() as u32)) {
+            return Err(anyhow!("The OpenXR runtime has no {:?} swapchains", SWAPCHAIN_FORMAT));
+        }
+        let eye_extent = xr.eye_extent()?;
+        let view_count = xr.instance.enumerate_view_configuration_views(xr.system, VIEW_TYPE)?.len();
+        let swapchains = (0..view_count).map(|_| {
+            let swapchain = session.create_swapchain(&xr::SwapchainCreateInfo {
+                create_flags: xr::SwapchainCreateFlags::EMPTY,
+                usage_flags: xr::SwapchainUsageFlags::COLOR_ATTACHMENT | xr::SwapchainUsageFlags::TRANSFER_DST,
+                format: SWAPCHAIN_FORMAT.as_raw() as u32,
+                sample_count: 1,
+                width: eye_extent.width,
+                height: eye_extent.height,
+                face_count: 1,
+                array_size: 1,
+                mip_count: 1
+            })?;
+            let images = swapchain.enumerate_images()?.into_iter().map(vk::Image::from_raw).collect();
+            Ok((swapchain, images))
+        }).collect::<Result<Vec<_>>>()?;
+
+        let actions = Actions::new(xr, &session)?;
+
+        let cameras = (0..view_count).map(|_| Camera::new(uv::Vec3::zero(), -uv::Vec3::unit_z(), 1.0)).collect();
+        renderer.set_split_screen(cameras)?;
+
+        Ok(Self {
+            session,
+            frame_waiter,
+            frame_stream,
+            stage,
+            swapchains,
+            eye_extent,
+            actions,
+            play_area: PlayArea::default(),
+            running: false,
+            events: xr::EventDataBuffer::new()
+        })
+    }
+
+    // Handles the runtime's events, then draws and submits a frame if the headset is showing one. False once the
+    // runtime wants the app to quit.
+    pub fn frame(&mut self, xr: &XrContext, renderer: &mut VulkanRenderer) -> Result<bool> {
+        while let Some(event) = xr.instance.poll_event(&mut self.events)? {
+            match event {
+                xr::Event::SessionStateChanged(change) => match change.state() {
+                    xr::SessionState::READY => {
+                        self.session.begin(VIEW_TYPE)?;
+                        self.running = true;
+                    }
+                    xr::SessionState::STOPPING => {
+                        self.session.end()?;
+                        self.running = false;
+                    }
+                    xr::SessionState::EXITING | xr::SessionState::LOSS_PENDING => return Ok(false),
+                    _ => {}
+                },
+                xr::Event::InstanceLossPending(_) => return Ok(false),
+                _ => {}
+            }
+        }
+
+        // Nothing is shown until the headset is put on
+        if !self.running {
+            std::thread::sleep(Duration::from_millis(100));
+            return Ok(true);
+        }
+
+        // Paces the app to the headset, everything is posed for the time the frame will be on the displays
+        let frame_state = self.frame_waiter.wait()?;
+        self.frame_stream.begin()?;
+        if !frame_state.should_render {
+            self.frame_stream.end(frame_state.predicted_display_time, xr.blend_mode, &[])?;
+            return Ok(true);
+        }
+        let time = frame_state.predicted_display_time;
+        let delta_time = frame_state.predicted_display_period.as_nanos() as f32 / 1e9;
+
+        self.session.sync_actions(&[(&self.actions.set).into()])?;
+        let (_, views) = self.session.locate_views(VIEW_TYPE, time, &self.stage)?;
+        self.move_play_area(&views, delta_time)?;
+        for (view, split_view) in views.iter().zip(&mut renderer.split_views) {
+            self.play_area.pose_camera(&mut split_view.camera, view);
+        }
+        // Shadows and post processing follow the main camera
+        if let Some(view) = views.first() {
+            self.play_area.pose_camera(&mut renderer.camera, view);
+        }
+
+        renderer.fill_commandbuffers()?;
+        renderer.draw_frame();
+
+        let select = self.actions.select.state(&self.session, xr::Path::NULL)?;
+        if select.is_active && select.changed_since_last_sync && select.current_state {
+            self.select_aimed(renderer, time)?;
+        }
+
+        for (eye, (swapchain, images)) in self.swapchains.iter_mut().enumerate() {
+            let index = swapchain.acquire_image()?;
+            swapchain.wait_image(xr::Duration::INFINITE)?;
+            let source = renderer.split_views[eye].area.rect(renderer.swapchain.extent);
+            renderer.copy_frame_to(source, images[index as usize])?;
+            swapchain.release_image()?;
+        }
+
+        let rect = xr::Rect2Di {
+            offset: xr::Offset2Di { x: 0, y: 0 },
+            extent: xr::Extent2Di { width: self.eye_extent.width as i32, height: self.eye_extent.height as i32 }
+        };
+        // The runtime reprojects with the poses the eyes were drawn for, in the stage's space
+        let projection_views: Vec<_> = views.iter().zip(&self.swapchains).map(|(view, (swapchain, _))| {
+            xr::CompositionLayerProjectionView::new()
+                .pose(view.pose)
+                .fov(view.fov)
+                .sub_image(xr::SwapchainSubImage::new()
+                    .swapchain(swapchain)
+                    .image_array_index(0)
+                    .image_rect(rect))
+        }).collect();
+        let layer = xr::CompositionLayerProjection::new()
+            .space(&self.stage)
+            .views(&projection_views);
+        self.frame_stream.end(time, xr.blend_mode, &[&layer])?;
+
+        Ok(true)
+    }
+
+    // Moves along the floor where the head is looking
+    fn move_play_area(&mut self, views: &[xr::View], delta_time: f32) -> Result<()> {
+        let locomotion = stick(self.actions.locomotion.state(&self.session, xr::Path::NULL)?);
+        let turn = stick(self.actions.turn.state(&self.session, xr::Path::NULL)?);
+        self.play_area.yaw -= turn.x * TURN_SPEED * delta_time;
+
+        let (_, forward, _) = match views.first() {
+            Some(view) => self.play_area.pose(view.pose),
+            None => return Ok(())
+        };
+        let forward = uv::Vec3::new(forward.x, 0.0, forward.z);
+        if forward.mag_sq() > 0.0 {
+            let forward = forward.normalized();
+            let right = forward.cross(uv::Vec3::unit_y());
+            self.play_area.origin += (forward * locomotion.y + right * locomotion.x) * MOVE_SPEED * delta_time;
+        }
+
+        Ok(())
+    }
+
+    // Picks through the last eye at the point the right controller aims at
+    fn select_aimed(&self, renderer: &mut VulkanRenderer, time: xr::Time) -> Result<()> {
+        let location = self.actions.aim_space.locate(&self.stage, time)?;
+        let valid = xr::SpaceLocationFlags::POSITION_VALID | xr::SpaceLocationFlags::ORIENTATION_VALID;
+        let view = match renderer.split_views.last() {
+            Some(view) if location.location_flags.contains(valid) => view,
+            _ => return Ok(())
+        };
+
+        let (position, forward, _) = self.play_area.pose(location.pose);
+        let clip = view.camera.projection_matrix() * view.camera.view_matrix() * (position + forward * AIM_DISTANCE).into_homogeneous_point();
+        if clip.w <= 0.0 {
+            return Ok(());
+        }
+        let rect = view.area.rect(renderer.swapchain.extent);
+        let (x, y) = (clip.x / clip.w * 0.5 + 0.5, clip.y / clip.w * 0.5 + 0.5);
+        if !(0.0..1.0).contains(&x) || !(0.0..1.0).contains(&y) {
+            return Ok(());
+        }
+
+        let x = rect.offset.x as u32 + (x * rect.extent.width as f32) as u32;
+        let y = rect.offset.y as u32 + (y * rect.extent.height as f32) as u32;
+        renderer.selected = renderer.pick(x, y)?;
+
+        Ok(())
+    }
+}
+
+// Renders the meshes under assets/ on the headset until the runtime ends the session
+pub fn run(settings: &RendererSettings) -> Result<()> {
+    let xr = XrContext::new()?;
+    let eye_extent = xr.eye_extent()?;
+    let extent = vk::Extent2D { width: eye_extent.width * 2, height: eye_extent.height };
+    let mut renderer = VulkanRenderer::new_offscreen_with(extent, settings, &xr).map_err(|error| anyhow!("{}", error))?;
+    renderer.lights.push(PointLight::new(uv::Vec3::new(0.5, 2.0, 1.0), uv::Vec3::one(), 6.0, 8.0));
+
+    let mut assets = AssetManager::new("assets");
+    for index in 0..assets.assets.len() {
+        if assets.assets[index].kind == AssetKind::Mesh {
+            if let Err(error) = assets.instantiate(&mut renderer, index, uv::Vec3::new(0.0, 0.0, -2.0)) {
+                tracing::error!("Failed to load {}: {}", assets.assets[index].name(), error);
+            }
+        }
+    }
+
+    let mut session = XrSession::new(&xr, &mut renderer)?;
+    while session.frame(&xr, &mut renderer)? {}
+    drop(session);
+
+    Ok(())
+}
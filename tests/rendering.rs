@@ -0,0 +1,183 @@
+// Renders reference scenes offscreen and compares them against the images in tests/golden.
+//
+// They need a Vulkan device, so they're ignored by default. Run them with `cargo test --test rendering -- --ignored`,
+// preferably on a software rasterizer (lavapipe or SwiftShader), so the goldens stay comparable across machines.
+// REVERIE_TEST_GPU picks the device, by index or part of its name, and defaults to lavapipe ("llvmpipe").
+// A missing golden is written from the render on the first run, look at it and commit it. REVERIE_BLESS=1 writes all
+// of them anew after an intended change. Failed comparisons leave the render and a diff image in the target directory.
+
+use std::path::{Path, PathBuf};
+
+use ash::vk;
+
+use reverie::assets::AssetManager;
+use reverie::vulkan::game_object::GameObject;
+use reverie::vulkan::lights::{PointLight, SpotLight};
+use reverie::vulkan::material::Material;
+use reverie::vulkan::mesh::Mesh;
+use reverie::vulkan::reflection::ReflectionPlane;
+use reverie::vulkan::renderer::{RendererSettings, VulkanRenderer};
+
+const EXTENT: vk::Extent2D = vk::Extent2D { width: 256, height: 192 };
+// Frames drawn before reading back, so effects that settle over a few frames (exposure) always end up in the same state
+const FRAMES: u32 = 4;
+// Largest difference of a channel that still counts as equal, rasterizers round slightly differently
+const CHANNEL_TOLERANCE: u8 = 8;
+// Share of pixels allowed past the tolerance, edges can land on neighbouring pixels
+const MAX_DIFFERENT_PIXELS: f32 = 0.002;
+
+fn renderer() -> VulkanRenderer {
+    let settings = RendererSettings {
+        gpu: Some(std::env::var("REVERIE_TEST_GPU").unwrap_or_else(|_| "llvmpipe".to_string())),
+        ..Default::default()
+    };
+    let mut renderer = VulkanRenderer::new_offscreen(EXTENT, &settings).expect("Failed to create an offscreen renderer");
+    renderer.camera.position = uv::Vec3::new(0.0, 1.5, 3.0);
+    renderer.camera.target = uv::Vec3::new(0.0, 0.3, 0.0);
+    renderer
+}
+
+fn add_cube(renderer: &mut VulkanRenderer, translation: uv::Vec3, scale: uv::Vec3, color: uv::Vec3) -> &mut GameObject {
+    let mesh = Mesh::cube(&renderer.device, &mut renderer.allocator, uv::Vec3::one()).expect("Failed to create a cube");
+    let mut game_object = GameObject::new(mesh, color);
+    game_object.transform3d.translation = translation;
+    game_object.transform3d.scale = scale;
+    renderer.game_objects.push(game_object);
+    renderer.game_objects.last_mut().unwrap()
+}
+
+// Thin slab whose top face is the y = 0 plane
+fn add_ground(renderer: &mut VulkanRenderer) -> &mut GameObject {
+    add_cube(renderer, uv::Vec3::new(0.0, -0.05, 0.0), uv::Vec3::new(4.0, 0.1, 4.0), uv::Vec3::broadcast(0.7))
+}
+
+fn render(renderer: &mut VulkanRenderer) -> Vec<u8> {
+    for _ in 0..FRAMES {
+        renderer.fill_commandbuffers().expect("Failed to write commands");
+        renderer.draw_frame();
+    }
+    renderer.read_pixels().expect("Failed to read back the frame")
+}
+
+fn compare_with_golden(name: &str, pixels: &[u8]) {
+    let golden_path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("golden").join(format!("{}.png", name));
+    if std::env::var_os("REVERIE_BLESS").is_some_and(|bless| bless == "1") {
+        write_png(&golden_path, pixels);
+        eprintln!("Wrote golden image {}", golden_path.display());
+        return;
+    }
+    if !golden_path.exists() {
+        write_png(&golden_path, pixels);
+        eprintln!("{} was missing and is written from this render, check it and commit it, or bless it again with \
+            REVERIE_BLESS=1 on a software rasterizer", golden_path.display());
+        return;
+    }
+
+    let (size, golden) = AssetManager::load_texture(&golden_path).expect("Failed to read the golden image");
+    assert_eq!(size, [EXTENT.width, EXTENT.height], "{} doesn't have the size of the render", golden_path.display());
+
+    let (share, diff) = compare(pixels, &golden);
+    if share > MAX_DIFFERENT_PIXELS {
+        let failures = failure_dir();
+        write_png(&failures.join(format!("{}.png", name)), pixels);
+        write_png(&failures.join(format!("{}_diff.png", name)), &diff);
+        panic!("{} differs from {} in {:.2}% of the pixels, see {}", name, golden_path.display(), share * 100.0, failures.display());
+    }
+}
+
+// Share of the RGBA8 pixels that differ by more than the tolerance, and an image of where they are
+fn compare(pixels: &[u8], golden: &[u8]) -> (f32, Vec<u8>) {
+    assert_eq!(pixels.len(), golden.len(), "Images of different sizes");
+    let mut diff = Vec::with_capacity(pixels.len());
+    let mut different = 0;
+    for (pixel, expected) in pixels.chunks_exact(4).zip(golden.chunks_exact(4)) {
+        let error = pixel.iter().zip(expected).map(|(a, b)| a.abs_diff(*b)).max().unwrap_or(0);
+        if error > CHANNEL_TOLERANCE {
+            different += 1;
+            diff.extend_from_slice(&[255, 0, 0, 255]);
+        } else {
+            // The matching parts stay faintly visible for orientation
+            diff.extend(pixel[..3].iter().map(|channel| channel / 4).chain([255]));
+        }
+    }
+    (different as f32 / (pixels.len() / 4).max(1) as f32, diff)
+}
+
+fn failure_dir() -> PathBuf {
+    Path::new(env!("CARGO_TARGET_TMPDIR")).join("golden-failures")
+}
+
+fn write_png(path: &Path, pixels: &[u8]) {
+    std::fs::create_dir_all(path.parent().unwrap()).expect("Failed to create the image directory");
+    let file = std::fs::File::create(path).expect("Failed to create the image");
+    let mut encoder = png::Encoder::new(std::io::BufWriter::new(file), EXTENT.width, EXTENT.height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header()
+        .and_then(|mut writer| writer.write_image_data(pixels))
+        .expect("Failed to write the image");
+}
+
+#[test]
+fn compare_tolerates_rounding() {
+    let golden = [100, 150, 200, 255].repeat(4);
+    let mut pixels = golden.clone();
+    pixels[0] += CHANNEL_TOLERANCE;
+    pixels[6] -= CHANNEL_TOLERANCE;
+    let (share, diff) = compare(&pixels, &golden);
+    assert_eq!(share, 0.0);
+    assert_eq!(&diff[..4], &[(100 + CHANNEL_TOLERANCE) / 4, 37, 50, 255]);
+}
+
+#[test]
+fn compare_counts_different_pixels() {
+    let golden = [0, 0, 0, 255].repeat(8);
+    let mut pixels = golden.clone();
+    pixels[4 * 3 + 1] = CHANNEL_TOLERANCE + 1;
+    pixels[4 * 5 + 3] = 0;
+    let (share, diff) = compare(&pixels, &golden);
+    assert_eq!(share, 2.0 / 8.0);
+    assert_eq!(&diff[4 * 3..4 * 4], &[255, 0, 0, 255]);
+    assert_eq!(&diff[4 * 5..4 * 6], &[255, 0, 0, 255]);
+    assert_eq!(&diff[4 * 4..4 * 5], &[0, 0, 0, 255]);
+}
+
+#[test]
+#[ignore = "needs a Vulkan device"]
+fn point_light() {
+    let mut renderer = renderer();
+    add_ground(&mut renderer);
+    add_cube(&mut renderer, uv::Vec3::new(0.0, 0.5, 0.0), uv::Vec3::one(), uv::Vec3::new(0.9, 0.3, 0.2));
+    renderer.lights.push(PointLight::new(uv::Vec3::new(1.0, 1.5, 1.0), uv::Vec3::one(), 6.0, 6.0));
+
+    let pixels = render(&mut renderer);
+    compare_with_golden("point_light", &pixels);
+}
+
+#[test]
+#[ignore = "needs a Vulkan device"]
+fn spot_light_shadows() {
+    let mut renderer = renderer();
+    add_ground(&mut renderer);
+    add_cube(&mut renderer, uv::Vec3::new(-0.4, 0.5, 0.0), uv::Vec3::one(), uv::Vec3::new(0.2, 0.5, 0.9));
+    add_cube(&mut renderer, uv::Vec3::new(0.8, 0.25, 0.5), uv::Vec3::broadcast(0.5), uv::Vec3::new(0.3, 0.8, 0.3));
+    renderer.spot_lights.push(SpotLight::new(uv::Vec3::new(1.5, 2.5, 1.5), uv::Vec3::new(-0.5, -1.0, -0.5),
+        uv::Vec3::new(1.0, 0.9, 0.7), 10.0, 8.0, 35f32.to_radians()));
+
+    let pixels = render(&mut renderer);
+    compare_with_golden("spot_light_shadows", &pixels);
+}
+
+#[test]
+#[ignore = "needs a Vulkan device"]
+fn planar_reflection() {
+    let mut renderer = renderer();
+    add_ground(&mut renderer).material = Material::Reflective;
+    add_cube(&mut renderer, uv::Vec3::new(0.0, 0.6, 0.0), uv::Vec3::broadcast(0.8), uv::Vec3::new(0.9, 0.8, 0.2));
+    renderer.lights.push(PointLight::new(uv::Vec3::new(-1.0, 2.0, 1.0), uv::Vec3::one(), 6.0, 6.0));
+    renderer.enable_planar_reflection(ReflectionPlane::new(uv::Vec3::unit_y(), uv::Vec3::zero()))
+        .expect("Failed to enable the planar reflection");
+
+    let pixels = render(&mut renderer);
+    compare_with_golden("planar_reflection", &pixels);
+}
\ No newline at end of file